@@ -0,0 +1,5 @@
+#!/bin/bash
+set -euo pipefail
+cd "$(dirname "$0")/.."
+source scripts/demo-lib.sh
+run_demo "batched" "pipe-run" "specs/layout-sales-report.pipe" "specs/input-fixed-80.data" "work/sample-pipe-outputs/layout-sales-report.out"
@@ -0,0 +1,60 @@
+//! Example plugin library providing a `ROT13` stage.
+//!
+//! Build it and load it into `pipe-run`:
+//!
+//! ```text
+//! cargo build --features plugins --example rot13_plugin
+//! cargo run --features plugins --bin pipe-run -- \
+//!     --plugin target/debug/examples/librot13_plugin.so \
+//!     my-pipeline.pipe specs/input-fixed-80.data
+//! ```
+//!
+//! where the pipeline uses `| PLUGIN ROT13` or `| PLUGIN ROT13 pos,len`.
+
+use pipelines_rs::plugin::PluginRegistrar;
+use pipelines_rs::{RECORD_WIDTH, Record, Stage};
+
+/// Rotates letters by 13 places, in the whole record or one field.
+struct Rot13 {
+    pos: usize,
+    len: usize,
+}
+
+impl Stage for Rot13 {
+    fn process_into(&mut self, mut record: Record, output: &mut Vec<Record>) {
+        let rotated: String = record
+            .field(self.pos, self.len)
+            .chars()
+            .map(|c| match c {
+                'A'..='Z' => (((c as u8 - b'A' + 13) % 26) + b'A') as char,
+                'a'..='z' => (((c as u8 - b'a' + 13) % 26) + b'a') as char,
+                _ => c,
+            })
+            .collect();
+        record.set_field(self.pos, self.len, &rotated);
+        output.push(record);
+    }
+}
+
+/// Parses the optional `pos,len` argument.
+fn parse_field(args: &str) -> Result<(usize, usize), String> {
+    if args.is_empty() {
+        return Ok((0, RECORD_WIDTH));
+    }
+    let (pos, len) = args.split_once(',').ok_or("expected pos,len")?;
+    let pos: usize = pos.trim().parse().map_err(|_| "invalid position")?;
+    let len: usize = len.trim().parse().map_err(|_| "invalid length")?;
+    if len == 0 || pos + len > RECORD_WIDTH {
+        return Err(format!("field {args} is outside the record"));
+    }
+    Ok((pos, len))
+}
+
+fn register(registrar: &mut PluginRegistrar) {
+    registrar.register("ROT13", |args| {
+        let (pos, len) = parse_field(args)?;
+        Ok(Box::new(Rot13 { pos, len }))
+    });
+}
+
+pipelines_rs::declare_plugin!(register);
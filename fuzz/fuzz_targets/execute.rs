@@ -0,0 +1,12 @@
+//! Fuzz both executors: the data is pipeline text, then a NUL byte, then
+//! the input records.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use naive_pipe::fuzz::fuzz_entry;
+
+fuzz_target!(|data: &str| {
+    let (pipeline, input) = data.split_once('\0').unwrap_or((data, ""));
+    fuzz_entry(input, pipeline);
+});
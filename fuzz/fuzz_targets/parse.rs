@@ -0,0 +1,10 @@
+//! Fuzz the parser, diagnostics, and formatter with pipeline text.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use naive_pipe::fuzz::fuzz_entry;
+
+fuzz_target!(|pipeline: &str| {
+    fuzz_entry("", pipeline);
+});
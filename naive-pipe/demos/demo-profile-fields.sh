@@ -0,0 +1,5 @@
+#!/bin/bash
+set -euo pipefail
+cd "$(dirname "$0")/../.."
+source scripts/demo-lib.sh
+run_demo "rat" "pipe-run-rat" "specs/profile-fields.pipe" "specs/input-fixed-80.data" "naive-pipe/work/sample-pipe-outputs/profile-fields.out"
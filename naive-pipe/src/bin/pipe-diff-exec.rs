@@ -0,0 +1,153 @@
+//! CLI tool to check that the batch and record-at-a-time executors produce
+//! identical output, including the files written by `>` and `>>` (see
+//! `naive_pipe::differential`).
+//!
+//! Each pipeline runs through both executors against `NAME.data` (or the
+//! `--input` file); the first record where they disagree is reported with
+//! its RAT trace. Exit codes: 0 when every pipeline agrees, 1 when one
+//! diverges, 2 when a file cannot be read or a pipeline fails in both.
+
+use clap::Parser;
+use naive_pipe::differential::compare_executors;
+use pipelines_rs::cli::parse_parm;
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::ebcdic::{self, CodePage};
+use pipelines_rs::{HostVars, PipelinePlan, RECORD_WIDTH, parse_commands, pipeline_lrecl};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Run pipelines through both the batch and RAT executors and report the
+/// first record where their output differs.
+#[derive(Parser)]
+#[command(name = "pipe-diff-exec")]
+struct Cli {
+    /// Pipeline files (.pipe), or directories of them
+    #[arg(default_value = "specs")]
+    paths: Vec<PathBuf>,
+
+    /// Input for pipelines without a NAME.data file of their own
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+
+    /// Set a STAMP parameter, read as PARM.NAME (repeatable)
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_parm)]
+    parms: Vec<(String, String)>,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+}
+
+/// The result of one pipeline.
+enum Outcome {
+    Agree,
+    /// The report of where the executors first disagree.
+    Diverged(String),
+    /// The pipeline has segments, which only the batch executor runs.
+    Skipped,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut pipelines = Vec::new();
+    for path in &cli.paths {
+        if !path.is_dir() {
+            pipelines.push(path.clone());
+            continue;
+        }
+        match fs::read_dir(path) {
+            Ok(entries) => pipelines.extend(
+                entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "pipe")),
+            ),
+            Err(e) => {
+                eprintln!("Error reading directory '{}': {e}", path.display());
+                process::exit(2);
+            }
+        }
+    }
+    pipelines.sort();
+
+    let (mut agreed, mut diverged, mut skipped, mut errors) = (0, 0, 0, 0);
+    for pipeline in &pipelines {
+        let name = pipeline.display();
+        match run(&cli, pipeline) {
+            Ok(Outcome::Diverged(report)) => {
+                diverged += 1;
+                println!("DIVERGED {name}");
+                for line in report.lines() {
+                    println!("  {line}");
+                }
+            }
+            Ok(Outcome::Agree) => {
+                agreed += 1;
+                println!("ok       {name}");
+            }
+            Ok(Outcome::Skipped) => {
+                skipped += 1;
+                println!("skipped  {name}: segments run only in the batch executor");
+            }
+            Err(e) => {
+                errors += 1;
+                println!("ERROR    {name}: {e}");
+            }
+        }
+    }
+
+    println!(
+        "\n{agreed} agree; {diverged} diverge; {skipped} skipped; {errors} errors ({} pipelines)",
+        pipelines.len()
+    );
+    if diverged > 0 {
+        process::exit(1);
+    }
+    if errors > 0 {
+        process::exit(2);
+    }
+}
+
+/// Runs one pipeline through both executors.
+fn run(cli: &Cli, pipeline: &Path) -> Result<Outcome, String> {
+    let pipeline_text =
+        fs::read_to_string(pipeline).map_err(|e| format!("cannot read pipeline: {e}"))?;
+    let pipeline_text = cli.syntax.to_dsl(&pipeline_text)?;
+    let plan = PipelinePlan::parse(&pipeline_text)?;
+    if !plan.segments().is_empty() {
+        return Ok(Outcome::Skipped);
+    }
+    let commands = parse_commands(&pipeline_text).unwrap_or_default();
+    let lrecl = pipeline_lrecl(&pipeline_text).unwrap_or(RECORD_WIDTH);
+
+    let paired = pipeline.with_extension("data");
+    let input = paired
+        .exists()
+        .then_some(paired)
+        .or(cli.input.clone())
+        .ok_or_else(|| {
+            format!(
+                "no input file ({} or --input)",
+                pipeline.with_extension("data").display()
+            )
+        })?;
+    let input_text = read(&input, ebcdic::input_code_page(&commands), lrecl)
+        .map_err(|e| format!("cannot read input: {e}"))?;
+
+    // One HostVars for both runs, so STAMP's date and time agree
+    let vars = HostVars::for_pipeline_file(pipeline, None, &cli.parms, plan.all_commands());
+    Ok(match compare_executors(&plan, &input_text, &vars)? {
+        Some(divergence) => Outcome::Diverged(divergence.to_string()),
+        None => Outcome::Agree,
+    })
+}
+
+/// Reads a data file, decoding EBCDIC if the pipeline converts it.
+fn read(path: &Path, code_page: Option<CodePage>, lrecl: usize) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    match code_page {
+        Some(code_page) => Ok(ebcdic::decode_records(&bytes, code_page, lrecl)),
+        None => String::from_utf8(bytes).map_err(|_| "file is not UTF-8 text".to_string()),
+    }
+}
@@ -1,31 +1,151 @@
 //! CLI tool to run pipeline (.pipe) files using the record-at-a-time executor.
+//!
+//! The exit status is the run's condition code: 0 for a clean run, 4 for
+//! warnings, 8 for error records, and 12 for a run that fails (see
+//! `pipelines_rs::condcode`).
 
-use clap::Parser;
-use naive_pipe::execute_pipeline_rat;
-use std::fs;
-use std::io::{self, Write};
+use clap::{Parser, ValueEnum};
+use naive_pipe::{
+    Checkpoint, CheckpointOptions, SideOutputs, TraceOptions, execute_plan_block_streaming,
+    execute_plan_rat_checkpointed, execute_plan_rat_streaming, execute_plan_rat_traced,
+    execute_plan_threaded_streaming,
+};
+use pipelines_rs::cli::{parse_dd, parse_output, parse_parm};
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::io::{Compression, Encoder, FbReader, FbWriter, decoder, input_paths};
+use pipelines_rs::{
+    Command, ConditionCode, HostVars, OutputFiles, PipelinePlan, RECORD_WIDTH, Record,
+    RecordLayout, ebcdic, input_file, parse_commands, pipeline_layout, pipeline_lrecl,
+};
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::process;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Exit status of a run that cannot start or fails.
+const FATAL: i32 = ConditionCode::Fatal.code();
 
 /// Run a pipeline file against input data (record-at-a-time executor).
 ///
-/// Produces identical output to pipe-run (batched) for all pipelines.
+/// Runs pipelines without segments (`? label:`, `? < name`), which only
+/// pipe-run (batched) runs; `pipe-diff-exec` checks that the two agree.
 #[derive(Parser)]
 #[command(name = "pipe-run-rat")]
 struct Cli {
     /// Pipeline definition file (.pipe)
     pipeline: String,
 
-    /// Input data file (80-byte fixed-width records, or /dev/stdin)
-    input: String,
+    /// Input data files or glob patterns (80-byte fixed-width records),
+    /// read in order as one stream, or - for stdin; omit them when the
+    /// pipeline starts with `< path`
+    input: Vec<String>,
 
-    /// Write output to file instead of stdout
-    #[arg(short, long)]
-    output: Option<String>,
+    /// Write output to file instead of stdout (- for stdout); NAME=PATH
+    /// writes the records of `SPLIT NAME` to PATH (repeatable)
+    #[arg(short, long = "output", value_name = "[NAME=]PATH", value_parser = parse_output)]
+    outputs: Vec<(Option<String>, String)>,
 
     /// Show paths, executor, and record counts on stderr
     #[arg(short, long)]
     verbose: bool,
+
+    /// Executor: one record through all stages at a time, blocks of
+    /// records through each stage, or each stage on its own thread
+    /// connected by bounded channels
+    #[arg(long, value_enum, default_value_t = Executor::Rat)]
+    executor: Executor,
+
+    /// Read `< NAME` from PATH instead of a file called NAME (repeatable)
+    #[arg(long = "in", value_name = "NAME=PATH", value_parser = parse_dd)]
+    inputs: Vec<(String, String)>,
+
+    /// Write `> NAME` or `>> NAME` to PATH instead of a file called NAME
+    /// (repeatable)
+    #[arg(long = "out", value_name = "NAME=PATH", value_parser = parse_dd)]
+    outs: Vec<(String, String)>,
+
+    /// Set a STAMP parameter, read as PARM.NAME (repeatable)
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_parm)]
+    parms: Vec<(String, String)>,
+
+    /// Job step name for STAMP's STEP variable (default: pipeline file name)
+    #[arg(long)]
+    step: Option<String>,
+
+    /// Input format: text lines, fixed-length records with no line ends,
+    /// or JSON Lines objects keyed by the pipeline's LAYOUT field names
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    input_format: Format,
+
+    /// Output format: text lines, fixed-length records with no line ends,
+    /// or JSON Lines objects keyed by the pipeline's LAYOUT field names
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    output_format: Format,
+
+    /// Save the input position and stage state to PATH every
+    /// --checkpoint-every input records, for --restart (rat executor,
+    /// uncompressed -o file)
+    #[arg(long, value_name = "PATH")]
+    checkpoint: Option<String>,
+
+    /// Input records between checkpoints
+    #[arg(long, value_name = "N", default_value_t = 10000)]
+    checkpoint_every: usize,
+
+    /// Resume an interrupted run from its checkpoint file, cutting the
+    /// output file back to where the checkpoint left it; checkpoints go on
+    /// being saved there unless --checkpoint names another file
+    #[arg(long, value_name = "PATH")]
+    restart: Option<String>,
+
+    /// Write the debug trace of the run to PATH as JSON, for the web
+    /// debugger's Import button (rat executor; the input is read into
+    /// memory first)
+    #[arg(long, value_name = "PATH")]
+    trace: Option<String>,
+
+    /// Trace only every Nth input record
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    trace_every: usize,
+
+    /// Most records the trace holds, counting each record at each pipe
+    /// point; capture stops there and the trace is marked truncated
+    #[arg(long, value_name = "N")]
+    trace_max: Option<usize>,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+
+    /// Load PLUGIN stages from a shared library (repeatable)
+    #[cfg(feature = "plugins")]
+    #[arg(long = "plugin", value_name = "LIBRARY")]
+    plugins: Vec<String>,
+}
+
+/// How the stages run.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Executor {
+    /// Record at a time, on one thread
+    Rat,
+    /// Blocks of records through each stage in turn, on one thread
+    Block,
+    /// One thread per stage
+    Threaded,
+}
+
+/// Format of the input or output file.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One record per line
+    Text,
+    /// Records of exactly LRECL bytes laid end to end (RECFM=FB)
+    Fb,
+    /// One JSON object per line
+    Jsonl,
 }
 
 fn main() {
@@ -35,55 +155,650 @@ fn main() {
         Ok(content) => content,
         Err(e) => {
             eprintln!("Error reading pipeline file '{}': {e}", cli.pipeline);
-            process::exit(1);
+            process::exit(FATAL);
+        }
+    };
+    let pipeline_text = match cli.syntax.to_dsl(&pipeline_text) {
+        Ok(text) => text.into_owned(),
+        Err(e) => {
+            eprintln!("Pipeline error: {e}");
+            process::exit(FATAL);
         }
     };
 
-    let input_text = match fs::read_to_string(&cli.input) {
-        Ok(content) => content,
+    // An unparseable pipeline is reported when it runs
+    let commands = parse_commands(&pipeline_text).unwrap_or_default();
+    if let Err(e) = check_dds(&cli.inputs, &cli.outs, &commands) {
+        eprintln!("Error: {e}");
+        process::exit(FATAL);
+    }
+    let input_args = match (input_file(&commands), &cli.input[..]) {
+        (None, [_, ..]) => cli.input.clone(),
+        (Some(path), []) => vec![resolve(&cli.inputs, path).to_string()],
+        (Some(path), [_, ..]) => {
+            eprintln!("Error: the pipeline reads '{path}' (< {path}); omit the input file");
+            process::exit(FATAL);
+        }
+        (None, []) => {
+            eprintln!("Error: an input file is required unless the pipeline starts with < path");
+            process::exit(FATAL);
+        }
+    };
+    let input_paths = input_paths(&input_args).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(FATAL);
+    });
+    let lrecl = pipeline_lrecl(&pipeline_text).unwrap_or(RECORD_WIDTH);
+    let layout = if cli.input_format == Format::Jsonl || cli.output_format == Format::Jsonl {
+        match jsonl_layout(&pipeline_text, &commands) {
+            Ok(layout) => layout,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(FATAL);
+            }
+        }
+    } else {
+        RecordLayout::new()
+    };
+    // Every input must exist before the first is read, as a job step
+    // allocates all of its datasets before it runs
+    for path in input_paths.iter().filter(|path| *path != "-") {
+        if let Err(e) = File::open(path) {
+            input_error(path, e);
+        }
+    }
+    let out_path = match check_outputs(&cli.outputs, &commands) {
+        Ok(path) => path.filter(|path| *path != "-"),
         Err(e) => {
-            eprintln!("Error reading input file '{}': {e}", cli.input);
-            process::exit(1);
+            eprintln!("Error: {e}");
+            process::exit(FATAL);
         }
     };
+    // A pipeline ending in `>` or `>>` writes its output there, not stdout
+    let file_last = matches!(commands.last(), Some(Command::FileOut { .. }));
+    let checkpoint_path = cli.checkpoint.as_deref().or(cli.restart.as_deref());
+    if checkpoint_path.is_some() {
+        checkpoint_usage(&cli, out_path);
+    }
+    if cli.trace.is_some() {
+        trace_usage(&cli, checkpoint_path);
+    }
+    let restart = cli.restart.as_deref().map(|path| {
+        Checkpoint::read(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(FATAL);
+        })
+    });
+
+    #[cfg(feature = "plugins")]
+    for library in &cli.plugins {
+        match pipelines_rs::plugin::load_library(library) {
+            Ok(names) if cli.verbose => eprintln!("Plugin:   {library} ({})", names.join(", ")),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error loading plugin: {e}");
+                process::exit(FATAL);
+            }
+        }
+    }
 
     if cli.verbose {
         eprintln!("Pipeline: {}", cli.pipeline);
-        eprintln!("Input:    {}", cli.input);
-        eprintln!("Output:   {}", cli.output.as_deref().unwrap_or("(stdout)"));
-        eprintln!("Executor: record-at-a-time");
-    }
-
-    match execute_pipeline_rat(&input_text, &pipeline_text) {
-        Ok((output, input_count, output_count)) => {
-            if let Some(out_path) = &cli.output {
-                if let Some(parent) = Path::new(out_path.as_str()).parent()
-                    && !parent.as_os_str().is_empty()
-                    && fs::create_dir_all(parent).is_err()
-                {
-                    eprintln!("Error creating output directory for '{out_path}'");
-                    process::exit(1);
-                }
-                if let Err(e) = fs::write(out_path, &output) {
+        for path in &input_paths {
+            eprintln!("Input:    {}", input_name(path));
+        }
+        match out_path {
+            Some(path) => eprintln!("Output:   {path}"),
+            None if !file_last => eprintln!("Output:   (stdout)"),
+            None => {}
+        }
+        for (name, path) in &cli.outputs {
+            if let Some(name) = name {
+                eprintln!("Output:   {path} (SPLIT {name})");
+            }
+        }
+        for cmd in &commands {
+            if let Command::FileOut { path, .. } = cmd {
+                eprintln!("Output:   {} ({})", resolve(&cli.outs, path), cmd.name());
+            }
+        }
+        match cli.executor {
+            Executor::Rat => eprintln!("Executor: record-at-a-time"),
+            Executor::Block => eprintln!("Executor: block"),
+            Executor::Threaded => eprintln!("Executor: threaded"),
+        }
+        if let Some(path) = checkpoint_path {
+            eprintln!("Checkpt:  {path} every {} records", cli.checkpoint_every);
+        }
+        if let Some(checkpoint) = &restart {
+            eprintln!(
+                "Restart:  after {} in -> {} out",
+                checkpoint.records_in, checkpoint.records_out
+            );
+        }
+        if let Some(path) = &cli.trace {
+            eprintln!("Trace:    {path} every {} records", cli.trace_every);
+        }
+    }
+
+    let plan = match PipelinePlan::parse(&pipeline_text) {
+        Ok(plan) => plan,
+        Err(e) => {
+            eprintln!("Pipeline error: {e}");
+            process::exit(FATAL);
+        }
+    };
+    // SPLIT outputs and `>` files are opened before the run and written
+    // as records pass
+    let splits = cli
+        .outputs
+        .iter()
+        .filter_map(|(name, path)| Some((name.as_deref()?, path.as_str())));
+    let files = match OutputFiles::open(&commands, splits, &cli.outs) {
+        Ok(files) => match ebcdic::output_code_page(&commands) {
+            Some(code_page) => files.with_ebcdic(code_page, lrecl),
+            None => files,
+        },
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(FATAL);
+        }
+    };
+    let files = Arc::new(Mutex::new(files));
+    let output_bytes = Rc::new(Cell::new(restart.as_ref().map_or(0, |c| c.output_bytes)));
+    let writer: Box<dyn Write> = match out_path {
+        Some(out_path) => {
+            if let Some(parent) = Path::new(out_path).parent()
+                && !parent.as_os_str().is_empty()
+                && fs::create_dir_all(parent).is_err()
+            {
+                eprintln!("Error creating output directory for '{out_path}'");
+                process::exit(FATAL);
+            }
+            let file = match &restart {
+                Some(checkpoint) => reopen_output(out_path, checkpoint.output_bytes),
+                None => File::create(out_path),
+            };
+            match file {
+                Ok(file) if checkpoint_path.is_some() => Box::new(CountingWriter {
+                    inner: BufWriter::new(file),
+                    bytes: Rc::clone(&output_bytes),
+                }),
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
                     eprintln!("Error writing output file '{out_path}': {e}");
-                    process::exit(1);
+                    process::exit(FATAL);
                 }
-            } else {
-                if let Err(e) = io::stdout().write_all(output.as_bytes()) {
-                    eprintln!("Error writing output: {e}");
-                    process::exit(1);
-                }
-                if !output.is_empty() && !output.ends_with('\n') {
-                    println!();
+            }
+        }
+        None if file_last => Box::new(io::sink()),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    // A `.gz` or `.zst` output file is compressed as it is written
+    let compression = out_path.map_or(Compression::None, Compression::from_path);
+    let writer = match Encoder::new(writer, compression) {
+        Ok(writer) => writer,
+        Err(e) => {
+            eprintln!(
+                "Error writing output file '{}': {e}",
+                out_path.unwrap_or("-")
+            );
+            process::exit(FATAL);
+        }
+    };
+    let output = match cli.output_format {
+        Format::Text => Output::Text(writer, &commands, lrecl),
+        Format::Fb => {
+            let mut fb = FbWriter::new(writer).with_lrecl(lrecl);
+            if let Some(code_page) = ebcdic::output_code_page(&commands) {
+                fb = fb.with_ebcdic(code_page);
+            }
+            Output::Fb(fb)
+        }
+        Format::Jsonl => Output::Jsonl(writer, &layout),
+    };
+    let output = RefCell::new(output);
+
+    // The inputs are read one after another, counting each one's records
+    let counts = vec![Cell::new(0); input_paths.len()];
+    let lines = input_paths.iter().zip(&counts).flat_map(|(path, count)| {
+        let reader = open_input(path);
+        input_lines(reader, cli.input_format, &commands, lrecl, &layout, path).inspect(
+            move |line| {
+                if !line.is_empty() {
+                    count.set(count.get() + 1);
                 }
+            },
+        )
+    });
+    let vars = HostVars::for_pipeline_file(
+        Path::new(&cli.pipeline),
+        cli.step.as_deref(),
+        &cli.parms,
+        &commands,
+    );
+    let side = SideOutputs::new().with_writer(files.clone());
+    let write_failed = Cell::new(false);
+    let emit = |record: &Record| {
+        output
+            .borrow_mut()
+            .write(record)
+            .inspect_err(|_| write_failed.set(true))
+    };
+    // Output is flushed before each checkpoint, so the file holds every
+    // byte the checkpoint counts
+    let save = |mut checkpoint: Checkpoint| {
+        output
+            .borrow_mut()
+            .flush()
+            .inspect_err(|_| write_failed.set(true))?;
+        checkpoint.output_bytes = output_bytes.get();
+        checkpoint.write(Path::new(checkpoint_path.unwrap_or_default()))
+    };
+    let result = match cli.executor {
+        Executor::Rat if checkpoint_path.is_some() => execute_plan_rat_checkpointed(
+            &plan,
+            lines,
+            &vars,
+            &side,
+            CheckpointOptions {
+                every: cli.checkpoint_every,
+                restart: restart.as_ref(),
+            },
+            emit,
+            save,
+        ),
+        Executor::Rat if cli.trace.is_some() => trace_run(&cli, &plan, lines, &vars, &side, emit),
+        Executor::Rat => execute_plan_rat_streaming(&plan, lines, &vars, &side, emit),
+        Executor::Block => execute_plan_block_streaming(&plan, lines, &vars, &side, emit),
+        Executor::Threaded => execute_plan_threaded_streaming(&plan, lines, &vars, &side, emit),
+    }
+    .and_then(|counts| {
+        output
+            .into_inner()
+            .finish()
+            .inspect_err(|_| write_failed.set(true))?;
+        Ok(counts)
+    });
+    if result.is_ok()
+        && let Err(e) = files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .finish()
+    {
+        eprintln!("Error: {e}");
+        process::exit(FATAL);
+    }
+    match result {
+        Ok((input_count, output_count)) => {
+            // A finished run has nothing to restart
+            if let Some(path) = checkpoint_path {
+                let _ = fs::remove_file(path);
             }
             if cli.verbose {
+                if input_paths.len() > 1 {
+                    for (path, count) in input_paths.iter().zip(&counts) {
+                        eprintln!("Read:     {} from {}", count.get(), input_name(path));
+                    }
+                }
                 eprintln!("Records:  {input_count} in -> {output_count} out");
             }
+            let warnings = side.warnings();
+            for warning in &warnings {
+                eprintln!("Warning:  {warning}");
+            }
+            let error_records = side.error_records();
+            let code = plan
+                .condition_codes()
+                .classify(warnings.len(), error_records);
+            if cli.verbose {
+                eprintln!(
+                    "RC:       {code} ({} warnings, {error_records} error records)",
+                    warnings.len()
+                );
+            }
+            process::exit(code.code());
         }
         Err(e) => {
-            eprintln!("Pipeline error: {e}");
-            process::exit(1);
+            match out_path {
+                Some(out_path) if write_failed.get() => {
+                    eprintln!("Error writing output file '{out_path}': {e}")
+                }
+                None if write_failed.get() => eprintln!("Error writing output: {e}"),
+                _ => eprintln!("Pipeline error: {e}"),
+            }
+            process::exit(FATAL);
+        }
+    }
+}
+
+/// Where output records go, in the chosen output format.
+enum Output<'a> {
+    /// Text lines, or EBCDIC records if the pipeline ends with `CONVERT EBCDIC`
+    Text(Encoder<Box<dyn Write>>, &'a [Command], usize),
+    /// Fixed-length records, in EBCDIC if the pipeline ends with `CONVERT EBCDIC`
+    Fb(FbWriter<Encoder<Box<dyn Write>>>),
+    /// JSON Lines objects keyed by the layout's field names
+    Jsonl(Encoder<Box<dyn Write>>, &'a RecordLayout),
+}
+
+impl Output<'_> {
+    /// Writes one output record.
+    fn write(&mut self, record: &Record) -> Result<(), String> {
+        match self {
+            Output::Text(writer, commands, lrecl) => {
+                ebcdic::write_output(writer, record, commands, *lrecl).map_err(|e| e.to_string())
+            }
+            Output::Fb(writer) => writer.write_record(record).map_err(|e| e.to_string()),
+            Output::Jsonl(writer, layout) => write_jsonl(writer, record, layout),
+        }
+    }
+
+    /// Flushes the records buffered so far to the output file.
+    fn flush(&mut self) -> Result<(), String> {
+        match self {
+            Output::Text(writer, ..) | Output::Jsonl(writer, _) => {
+                writer.flush().map_err(|e| e.to_string())
+            }
+            Output::Fb(writer) => writer.flush().map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Flushes the records still buffered, ending a compressed stream.
+    fn finish(self) -> Result<(), String> {
+        let writer = match self {
+            Output::Text(writer, ..) | Output::Jsonl(writer, _) => writer,
+            Output::Fb(writer) => writer.into_inner(),
+        };
+        writer.finish().map(drop).map_err(|e| e.to_string())
+    }
+}
+
+/// A writer counting the bytes written through it, which a checkpoint
+/// records as the length of the output so far.
+struct CountingWriter<W> {
+    inner: W,
+    bytes: Rc<Cell<u64>>,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes.set(self.bytes.get() + written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Exits unless the run can be checkpointed: by the record-at-a-time
+/// executor, into an uncompressed output file it can cut back on restart.
+fn checkpoint_usage(cli: &Cli, out_path: Option<&str>) {
+    let problem = if cli.executor != Executor::Rat {
+        "--checkpoint and --restart need --executor rat"
+    } else if out_path.is_none() {
+        "--checkpoint and --restart need an output file (-o)"
+    } else if out_path.map(Compression::from_path) != Some(Compression::None) {
+        "--checkpoint and --restart cannot write a compressed output file"
+    } else if cli.checkpoint_every == 0 {
+        "--checkpoint-every must be at least 1"
+    } else {
+        return;
+    };
+    eprintln!("Error: {problem}");
+    process::exit(FATAL);
+}
+
+/// Exits unless the run can be traced: with the json feature, by the
+/// record-at-a-time executor, without checkpoints.
+fn trace_usage(cli: &Cli, checkpoint_path: Option<&str>) {
+    let problem = if !cfg!(feature = "json") {
+        "--trace requires the json feature"
+    } else if cli.executor != Executor::Rat {
+        "--trace needs --executor rat"
+    } else if checkpoint_path.is_some() {
+        "--trace cannot be used with --checkpoint or --restart"
+    } else {
+        return;
+    };
+    eprintln!("Error: {problem}");
+    process::exit(FATAL);
+}
+
+/// Runs the pipeline with tracing and writes the trace to the --trace file.
+fn trace_run<I, S, F>(
+    cli: &Cli,
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    emit: F,
+) -> Result<(usize, usize), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let mut options = TraceOptions::new().every(cli.trace_every);
+    if let Some(max) = cli.trace_max {
+        options = options.max_records(max);
+    }
+    let (input_count, output_count, trace) =
+        execute_plan_rat_traced(plan, lines, vars, side, &options, emit)?;
+    let path = cli.trace.as_deref().unwrap_or_default();
+    fs::write(path, trace_json(&trace)?)
+        .map_err(|e| format!("cannot write trace file '{path}': {e}"))?;
+    if cli.verbose {
+        eprintln!(
+            "Traced:   {} of {input_count} records{}",
+            trace.record_traces.len(),
+            if trace.truncated { " (truncated)" } else { "" }
+        );
+    }
+    Ok((input_count, output_count))
+}
+
+#[cfg(feature = "json")]
+fn trace_json(trace: &naive_pipe::RatDebugTrace) -> Result<String, String> {
+    Ok(trace.to_json())
+}
+
+#[cfg(not(feature = "json"))]
+fn trace_json(_: &naive_pipe::RatDebugTrace) -> Result<String, String> {
+    Err("--trace requires the json feature".to_string())
+}
+
+/// Check that every SPLIT output has a `-o NAME=PATH` and every named
+/// `-o` a SPLIT stage, returning the main output path if one is given.
+fn check_outputs<'a>(
+    outputs: &'a [(Option<String>, String)],
+    commands: &[Command],
+) -> Result<Option<&'a str>, String> {
+    let split_names: Vec<&str> = commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::Split { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut main = None;
+    for (name, path) in outputs {
+        match name {
+            Some(name) if !split_names.contains(&name.as_str()) => {
+                return Err(format!("-o {name}=... names no SPLIT stage"));
+            }
+            Some(_) => {}
+            None if main.is_some() => return Err("more than one unnamed -o".to_string()),
+            None => main = Some(path.as_str()),
+        }
+    }
+    for name in split_names {
+        if !outputs.iter().any(|(n, _)| n.as_deref() == Some(name)) {
+            return Err(format!(
+                "SPLIT {name} needs an output file (-o {name}=PATH)"
+            ));
         }
     }
+    Ok(main)
+}
+
+/// Opens the output file of a restarted run, cut back to the `bytes` its
+/// checkpoint counted and positioned at the end.
+fn reopen_output(path: &str, bytes: u64) -> io::Result<File> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let len = file.metadata()?.len();
+    if len < bytes {
+        return Err(io::Error::other(format!(
+            "it has {len} bytes but the checkpoint counted {bytes}"
+        )));
+    }
+    file.set_len(bytes)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+/// Reads the input file a line at a time in the chosen input format, as
+/// the text lines the pipeline's source reads; exits if it cannot be read.
+fn input_lines<'a>(
+    reader: Box<dyn BufRead>,
+    format: Format,
+    commands: &[Command],
+    lrecl: usize,
+    layout: &RecordLayout,
+    path: &'a str,
+) -> Box<dyn Iterator<Item = String> + 'a> {
+    let or_exit = move |line: Result<String, String>| line.unwrap_or_else(|e| input_error(path, e));
+    match format {
+        Format::Text => Box::new(ebcdic::input_lines(reader, commands, lrecl).map(or_exit)),
+        Format::Fb => {
+            let mut fb = FbReader::new(reader).with_lrecl(lrecl);
+            if let Some(code_page) = ebcdic::input_code_page(commands) {
+                fb = fb.with_ebcdic(code_page);
+            }
+            Box::new(fb.map(move |record| {
+                or_exit(
+                    record
+                        .map(|r| r.as_str().to_string())
+                        .map_err(|e| e.to_string()),
+                )
+            }))
+        }
+        Format::Jsonl => {
+            let lines = ebcdic::input_lines(reader, commands, lrecl).map(or_exit);
+            Box::new(jsonl_lines(lines, layout).map(or_exit))
+        }
+    }
+}
+
+/// Opens the input file, or stdin for `-`, decompressing a `.gz` or
+/// `.zst` file as it is read; exits if it cannot be opened.
+fn open_input(path: &str) -> Box<dyn BufRead> {
+    if path == "-" {
+        return Box::new(io::stdin().lock());
+    }
+    let file = File::open(path).unwrap_or_else(|e| input_error(path, e));
+    decoder(BufReader::new(file), Compression::from_path(path))
+        .unwrap_or_else(|e| input_error(path, e))
+}
+
+/// The input file's name for messages: `(stdin)` for `-`.
+fn input_name(path: &str) -> &str {
+    if path == "-" { "(stdin)" } else { path }
+}
+
+/// Reports an input file that cannot be read, and exits.
+fn input_error(path: &str, e: impl std::fmt::Display) -> ! {
+    match path {
+        "-" => eprintln!("Error reading stdin: {e}"),
+        _ => eprintln!("Error reading input file '{path}': {e}"),
+    }
+    process::exit(FATAL);
+}
+
+/// The layout JSON Lines objects map to: the pipeline's LAYOUT fields.
+fn jsonl_layout(pipeline_text: &str, commands: &[Command]) -> Result<RecordLayout, String> {
+    if !cfg!(feature = "jsonl") {
+        return Err("JSON Lines requires the jsonl feature".to_string());
+    }
+    if ebcdic::input_code_page(commands).is_some() || ebcdic::output_code_page(commands).is_some() {
+        return Err("JSON Lines cannot be combined with CONVERT".to_string());
+    }
+    let layout = pipeline_layout(pipeline_text)?;
+    if layout.is_empty() {
+        return Err("JSON Lines needs a LAYOUT line to name the fields".to_string());
+    }
+    Ok(layout)
+}
+
+/// Converts JSON Lines input into the record lines the pipeline reads.
+#[cfg(feature = "jsonl")]
+fn jsonl_lines<'a>(
+    lines: impl Iterator<Item = String> + 'a,
+    layout: &RecordLayout,
+) -> Box<dyn Iterator<Item = Result<String, String>> + 'a> {
+    Box::new(
+        pipelines_rs::json::JsonlSource::new(lines, layout).map(|record| {
+            record
+                .map(|r| r.as_str().to_string())
+                .map_err(|e| e.to_string())
+        }),
+    )
+}
+
+/// Writes an output record as a JSON Lines object.
+#[cfg(feature = "jsonl")]
+fn write_jsonl(
+    writer: &mut dyn Write,
+    record: &Record,
+    layout: &RecordLayout,
+) -> Result<(), String> {
+    writeln!(writer, "{}", record.to_json(layout)).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "jsonl"))]
+fn jsonl_lines<'a>(
+    _: impl Iterator<Item = String> + 'a,
+    _: &RecordLayout,
+) -> Box<dyn Iterator<Item = Result<String, String>> + 'a> {
+    Box::new(std::iter::once(Err(
+        "JSON Lines requires the jsonl feature".to_string(),
+    )))
+}
+
+#[cfg(not(feature = "jsonl"))]
+fn write_jsonl(_: &mut dyn Write, _: &Record, _: &RecordLayout) -> Result<(), String> {
+    Err("JSON Lines requires the jsonl feature".to_string())
+}
+
+/// Check that every `--in NAME` is read by a `< NAME` stage and every
+/// `--out NAME` written by a `> NAME` or `>> NAME` stage.
+fn check_dds(
+    inputs: &[(String, String)],
+    outs: &[(String, String)],
+    commands: &[Command],
+) -> Result<(), String> {
+    for (name, _) in inputs {
+        if input_file(commands) != Some(name) {
+            return Err(format!("--in {name}=... names no < {name} stage"));
+        }
+    }
+    for (name, _) in outs {
+        let written = commands
+            .iter()
+            .any(|cmd| matches!(cmd, Command::FileOut { path, .. } if path == name));
+        if !written {
+            return Err(format!("--out {name}=... names no > {name} stage"));
+        }
+    }
+    Ok(())
+}
+
+/// The path `--in` or `--out` gives for `name`, or `name` itself.
+fn resolve<'a>(dds: &'a [(String, String)], name: &'a str) -> &'a str {
+    dds.iter()
+        .rev()
+        .find(|(n, _)| n == name)
+        .map_or(name, |(_, path)| path)
 }
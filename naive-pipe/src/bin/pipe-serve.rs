@@ -0,0 +1,163 @@
+//! HTTP service exposing the batch and record-at-a-time executors.
+
+use clap::Parser;
+use naive_pipe::serve::{Limits, Response, Service};
+use pipelines_rs::cms::Syntax;
+use std::io::Read;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use tiny_http::{Header, Request, Server};
+
+/// Serve pipeline execution over HTTP.
+///
+/// See `naive_pipe::serve` for the list of endpoints.
+#[derive(Parser)]
+#[command(name = "pipe-serve")]
+struct Cli {
+    /// Address to listen on
+    #[arg(short, long, default_value = "127.0.0.1:9953")]
+    listen: String,
+
+    /// Directory of saved pipelines (.pipe files)
+    #[arg(short, long, default_value = "specs")]
+    pipelines: String,
+
+    /// Maximum request body size in bytes; larger requests get 413
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    max_body: u64,
+
+    /// Maximum size of a request's input in bytes
+    #[arg(long, default_value_t = Limits::default().max_input)]
+    max_input: usize,
+
+    /// Longest a request's DELAY stages may wait in total, in milliseconds
+    #[arg(long, default_value_t = Limits::default().max_delay_ms)]
+    max_delay_ms: u64,
+
+    /// Most copies a request's DUPLICATE stages may make of one record
+    #[arg(long, default_value_t = Limits::default().max_copies)]
+    max_copies: usize,
+
+    /// Most records a /trace request may hold across its traces
+    #[arg(long, default_value_t = Limits::default().max_trace)]
+    max_trace: usize,
+
+    /// Origin allowed to call the service from a browser (sent as
+    /// Access-Control-Allow-Origin); none if not given
+    #[arg(long, value_name = "ORIGIN")]
+    cors_origin: Option<String>,
+
+    /// Number of requests handled at once
+    #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    workers: u16,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+
+    /// Log each request to stderr
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() {
+    let cli = Arc::new(Cli::parse());
+    if let Some(origin) = &cli.cors_origin
+        && Header::from_bytes("Access-Control-Allow-Origin", origin.as_bytes()).is_err()
+    {
+        eprintln!("Error: '{origin}' is not a valid origin");
+        process::exit(2);
+    }
+
+    let server = match Server::http(&cli.listen) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            eprintln!("Error listening on '{}': {e}", cli.listen);
+            process::exit(1);
+        }
+    };
+    let service = Arc::new(
+        Service::new(&cli.pipelines)
+            .with_limits(Limits {
+                max_input: cli.max_input,
+                max_delay_ms: cli.max_delay_ms,
+                max_copies: cli.max_copies,
+                max_trace: cli.max_trace,
+            })
+            .with_syntax(cli.syntax),
+    );
+
+    eprintln!("pipe-serve listening on http://{}", cli.listen);
+    eprintln!("Pipelines: {}", cli.pipelines);
+    eprintln!("Workers:   {}", cli.workers);
+
+    // Each worker takes the next request, so a slow pipeline holds up only
+    // its own worker
+    let workers: Vec<_> = (0..cli.workers)
+        .map(|_| {
+            let (server, service, cli) = (server.clone(), service.clone(), cli.clone());
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    handle_request(&service, request, &cli);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn handle_request(service: &Service, mut request: Request, cli: &Cli) {
+    let method = request.method().as_str().to_string();
+    let path = request.url().to_string();
+
+    // One byte past the limit is read, to tell a body at the limit from a
+    // longer one
+    let mut body = String::new();
+    let read = request
+        .as_reader()
+        .take(cli.max_body.saturating_add(1))
+        .read_to_string(&mut body);
+
+    let response = match read {
+        Ok(_) if body.len() as u64 > cli.max_body => text_response(
+            413,
+            format!("Request body is larger than {} bytes", cli.max_body),
+        ),
+        Ok(_) => service.handle(&method, &path, &body),
+        Err(e) => text_response(400, format!("Error reading request body: {e}")),
+    };
+
+    if cli.verbose {
+        eprintln!("{method} {path} -> {}", response.status);
+    }
+
+    let mut http_response = tiny_http::Response::from_string(response.body)
+        .with_status_code(response.status)
+        .with_header(header("Content-Type", response.content_type));
+    if let Some(origin) = &cli.cors_origin {
+        http_response = http_response
+            .with_header(header("Access-Control-Allow-Origin", origin))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"))
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST, OPTIONS"))
+            .with_header(header("Vary", "Origin"));
+    }
+
+    if let Err(e) = request.respond(http_response) {
+        eprintln!("Error writing response: {e}");
+    }
+}
+
+fn text_response(status: u16, body: String) -> Response {
+    Response {
+        status,
+        content_type: "text/plain; charset=utf-8",
+        body,
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("header is valid")
+}
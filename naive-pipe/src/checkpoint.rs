@@ -0,0 +1,235 @@
+//! Checkpoints for restarting long record-at-a-time runs.
+//!
+//! A [`Checkpoint`] records how far a run has got: the input records it
+//! has read and processed, the output records and bytes it has written,
+//! and the state of each stage (see [`RecordStage::checkpoint`]). A run
+//! restarted from one skips the input already read, restores each stage,
+//! and carries on writing where the output stopped, as a restarted job
+//! step resumes from its last checkpoint instead of from the first record.
+//!
+//! Checkpoints are small text files:
+//!
+//! ```text
+//! CHECKPOINT 1
+//! INPUT 20000
+//! OUTPUT 8113 657153
+//! STAGE - LOCATE
+//! STAGE 8113 TAKE
+//! ```
+//!
+//! `STAGE` lines hold each stage's state (`-` for none) and name, in
+//! pipeline order.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::record_stage::RecordStage;
+
+/// The first line of a checkpoint file, naming its format version.
+const HEADER: &str = "CHECKPOINT 1";
+
+/// How far a run has got, and the state of its stages at that point.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// Input records read and processed.
+    pub records_in: usize,
+    /// Output records written.
+    pub records_out: usize,
+    /// Output bytes written, where a restarted run cuts off the output
+    /// file and carries on.
+    pub output_bytes: u64,
+    /// Each stage's name and saved state, in pipeline order.
+    pub stages: Vec<(String, String)>,
+}
+
+/// How a checkpointed run saves checkpoints, and where it resumes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckpointOptions<'a> {
+    /// Input records between checkpoints (none are saved for 0).
+    pub every: usize,
+    /// The checkpoint a restarted run resumes from.
+    pub restart: Option<&'a Checkpoint>,
+}
+
+impl Checkpoint {
+    /// Saves the state of `stages` after `records_in` input records and
+    /// `records_out` output records. The output byte count is left at 0
+    /// for the writer of the output to fill in.
+    ///
+    /// Fails, naming the stage, if a stage cannot be checkpointed.
+    pub fn capture(
+        stages: &[Box<dyn RecordStage>],
+        records_in: usize,
+        records_out: usize,
+    ) -> Result<Self, String> {
+        let stages = stages
+            .iter()
+            .map(|stage| match stage.checkpoint() {
+                Some(state) => Ok((stage.name().to_string(), state)),
+                None => Err(format!(
+                    "{} keeps records or state a checkpoint cannot save, so the run cannot be restarted",
+                    stage.name()
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            records_in,
+            records_out,
+            output_bytes: 0,
+            stages,
+        })
+    }
+
+    /// Restores the saved state into newly built `stages`, which must be
+    /// the stages of the same pipeline.
+    pub fn restore(&self, stages: &mut [Box<dyn RecordStage>]) -> Result<(), String> {
+        if stages.len() != self.stages.len() {
+            return Err(format!(
+                "the checkpoint has {} stages but the pipeline has {}",
+                self.stages.len(),
+                stages.len()
+            ));
+        }
+        for (idx, (stage, (name, state))) in stages.iter_mut().zip(&self.stages).enumerate() {
+            if stage.name() != name {
+                return Err(format!(
+                    "stage {} is {} in the checkpoint but {} in the pipeline",
+                    idx + 1,
+                    name,
+                    stage.name()
+                ));
+            }
+            stage.restore(state)?;
+        }
+        Ok(())
+    }
+
+    /// Parses a checkpoint written by its `Display` form.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(format!("not a checkpoint: expected '{HEADER}' first"));
+        }
+        let mut checkpoint = Self::default();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let number = |word: &str| {
+                word.parse()
+                    .map_err(|_| format!("invalid checkpoint line '{line}'"))
+            };
+            match words[..] {
+                ["INPUT", records] => checkpoint.records_in = number(records)?,
+                ["OUTPUT", records, bytes] => {
+                    checkpoint.records_out = number(records)?;
+                    checkpoint.output_bytes = number(bytes)? as u64;
+                }
+                ["STAGE", state, ref name @ ..] if !name.is_empty() => {
+                    let state = if state == "-" { "" } else { state };
+                    checkpoint.stages.push((name.join(" "), state.to_string()));
+                }
+                _ => return Err(format!("invalid checkpoint line '{line}'")),
+            }
+        }
+        Ok(checkpoint)
+    }
+
+    /// Reads a checkpoint file.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read checkpoint '{}': {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("checkpoint '{}': {e}", path.display()))
+    }
+
+    /// Writes the checkpoint to `path`, replacing any earlier one only once
+    /// the new one is complete, so a crash while writing leaves the last
+    /// good checkpoint.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        fs::write(&partial, self.to_string())
+            .and_then(|()| fs::rename(&partial, path))
+            .map_err(|e| format!("cannot write checkpoint '{}': {e}", path.display()))
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "INPUT {}", self.records_in)?;
+        writeln!(f, "OUTPUT {} {}", self.records_out, self.output_bytes)?;
+        for (name, state) in &self.stages {
+            let state = if state.is_empty() { "-" } else { state };
+            writeln!(f, "STAGE {state} {name}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_stage::command_to_record_stage;
+    use pipelines_rs::{Record, parse_commands};
+
+    fn stages(pipeline: &str) -> Vec<Box<dyn RecordStage>> {
+        parse_commands(pipeline).unwrap()[1..]
+            .iter()
+            .map(command_to_record_stage)
+            .collect()
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut running =
+            stages("PIPE CONSOLE | SKIP 1 | REFORMAT DATE 0 YYYYMMDD TO MMDDYY | COUNT");
+        for n in 0..5 {
+            running[0].process(Record::from_str(&n.to_string()));
+            running[2].process(Record::from_str(&n.to_string()));
+        }
+        let mut checkpoint = Checkpoint::capture(&running, 5, 0).unwrap();
+        checkpoint.output_bytes = 12;
+        let text = checkpoint.to_string();
+        assert_eq!(
+            text,
+            "CHECKPOINT 1\nINPUT 5\nOUTPUT 0 12\nSTAGE 1 SKIP\nSTAGE - REFORMAT DATE\nSTAGE 5 COUNT\n"
+        );
+        assert_eq!(Checkpoint::parse(&text).unwrap(), checkpoint);
+
+        let mut restarted =
+            stages("PIPE CONSOLE | SKIP 1 | REFORMAT DATE 0 YYYYMMDD TO MMDDYY | COUNT");
+        checkpoint.restore(&mut restarted).unwrap();
+        assert_eq!(restarted[2].flush(), [Record::from_str("5")]);
+    }
+
+    #[test]
+    fn test_checkpoint_errors() {
+        let holding = stages("PIPE CONSOLE | UPPER | TAKE LAST 2");
+        let err = Checkpoint::capture(&holding, 0, 0).unwrap_err();
+        assert!(err.starts_with("TAKE keeps records"), "{err}");
+
+        let checkpoint = Checkpoint::capture(&stages("PIPE CONSOLE | TAKE 3"), 1, 1).unwrap();
+        let err = checkpoint
+            .restore(&mut stages("PIPE CONSOLE | SKIP 3"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "stage 1 is TAKE in the checkpoint but SKIP in the pipeline"
+        );
+        assert!(
+            checkpoint
+                .restore(&mut stages("PIPE CONSOLE | TAKE 3 | UPPER"))
+                .is_err()
+        );
+
+        assert!(Checkpoint::parse("INPUT 5").is_err());
+        assert!(Checkpoint::parse("CHECKPOINT 1\nINPUT five").is_err());
+        assert!(Checkpoint::parse("CHECKPOINT 1\nSTAGE x COUNT").is_ok());
+        let mut count = stages("PIPE CONSOLE | COUNT");
+        let bad = Checkpoint::parse("CHECKPOINT 1\nSTAGE x COUNT").unwrap();
+        assert_eq!(
+            bad.restore(&mut count).unwrap_err(),
+            "invalid COUNT checkpoint state 'x'"
+        );
+    }
+}
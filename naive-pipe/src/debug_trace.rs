@@ -1,9 +1,21 @@
 //! Debug trace types for the record-at-a-time executor.
 //!
 //! These types capture the journey of each record through the pipeline,
-//! enabling visualization of record-at-a-time execution flow.
+//! enabling visualization of record-at-a-time execution flow. With the
+//! `json` feature, [`RatDebugTrace::to_json`] and
+//! [`RatDebugTrace::from_json`] save a trace to a file and load it back, so
+//! a trace written by `pipe-run-rat --trace` can be stepped through in the
+//! web debugger.
 
-use pipelines_rs::Record;
+use pipelines_rs::{ColumnDiff, Record};
+#[cfg(feature = "json")]
+use pipelines_rs::{MAX_LRECL, RECORD_WIDTH};
+#[cfg(feature = "json")]
+use serde_json::{Value, json};
+
+/// Version of the trace JSON format written by [`RatDebugTrace::to_json`].
+#[cfg(feature = "json")]
+const TRACE_FORMAT_VERSION: u64 = 1;
 
 /// Trace of one input record's journey through the pipeline.
 ///
@@ -11,8 +23,12 @@ use pipelines_rs::Record;
 /// output after stage `i-1`. Length is `num_stages + 1`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct RecordTrace {
+    /// Position of the traced record in the input, counting from 0.
+    pub input_index: usize,
     /// Records present at each pipe point between stages.
     pub pipe_points: Vec<Vec<Record>>,
+    /// Records a stage wrote nothing for, where the stage says why.
+    pub drops: Vec<DroppedRecord>,
 }
 
 /// Trace of one stage's flush output journey through downstream stages.
@@ -26,6 +42,23 @@ pub struct FlushTrace {
     pub stage_index: usize,
     /// Records at each pipe point from flush source through downstream stages.
     pub pipe_points: Vec<Vec<Record>>,
+    /// Records a downstream stage wrote nothing for, where the stage says
+    /// why.
+    pub drops: Vec<DroppedRecord>,
+}
+
+/// A traced record that a stage wrote nothing for, with the stage's
+/// explanation (see [`RecordStage::drop_reason`](crate::RecordStage::drop_reason)).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DroppedRecord {
+    /// Pipe point the record was at: it was read by the stage at this
+    /// index in `stage_names`, and is missing from the next pipe point.
+    pub point: usize,
+    /// Position of the record among the trace's records at that pipe point.
+    pub index: usize,
+    /// The stage as written and the value that failed its test, e.g.
+    /// `FILTER 18,10 = "SALES" (value was "MARKETING")`.
+    pub reason: String,
 }
 
 /// Complete debug trace of a record-at-a-time pipeline execution.
@@ -33,10 +66,368 @@ pub struct FlushTrace {
 pub struct RatDebugTrace {
     /// Names of each stage in the pipeline.
     pub stage_names: Vec<String>,
-    /// One trace per input record, showing its journey through all stages.
+    /// One trace per traced input record, showing its journey through all
+    /// stages.
     pub record_traces: Vec<RecordTrace>,
     /// One trace per stage that produced flush output.
     pub flush_traces: Vec<FlushTrace>,
+    /// Records that passed each pipe point, traced or not: `point_counts[0]`
+    /// is the input count and the last entry the output count.
+    pub point_counts: Vec<usize>,
+    /// Whether the `max_records` cap stopped capturing before the end.
+    pub truncated: bool,
+}
+
+impl RatDebugTrace {
+    /// Returns the number of input records.
+    pub fn input_count(&self) -> usize {
+        self.point_counts.first().copied().unwrap_or(0)
+    }
+
+    /// Returns the number of input records without a record trace.
+    pub fn untraced(&self) -> usize {
+        self.input_count() - self.record_traces.len()
+    }
+
+    /// Returns the trace as a JSON document.
+    ///
+    /// Records are written as text without trailing blanks, and the record
+    /// length once, so [`from_json`](Self::from_json) restores them.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> String {
+        self.json_value().to_string()
+    }
+
+    /// Reads a trace written by [`to_json`](Self::to_json).
+    ///
+    /// Fails if the text is not JSON, a field is missing or of the wrong
+    /// type, `lrecl` is outside 1 to [`MAX_LRECL`], or the pipe points do
+    /// not fit the stages.
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| format!("Invalid trace JSON: {e}"))?;
+        let version = value
+            .get("version")
+            .map_or(Some(TRACE_FORMAT_VERSION), Value::as_u64);
+        if version != Some(TRACE_FORMAT_VERSION) {
+            return Err(format!(
+                "Unsupported trace version {} (expected {TRACE_FORMAT_VERSION})",
+                value["version"]
+            ));
+        }
+        let lrecl = match value.get("lrecl") {
+            Some(lrecl) => lrecl
+                .as_u64()
+                .filter(|n| (1..=MAX_LRECL as u64).contains(n))
+                .ok_or(format!("Trace 'lrecl' must be from 1 to {MAX_LRECL}"))?
+                as usize,
+            None => RECORD_WIDTH,
+        };
+        let stage_names = field(&value, "stage_names")?
+            .iter()
+            .map(|name| {
+                name.as_str()
+                    .map(str::to_string)
+                    .ok_or("Trace 'stage_names' must hold strings".to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_points = stage_names.len() + 1;
+
+        let mut record_traces = Vec::new();
+        for entry in field(&value, "record_traces")? {
+            let pipe_points = pipe_points(entry, lrecl)?;
+            if pipe_points.len() != num_points {
+                return Err(format!(
+                    "A record trace has {} pipe points; {} stages need {num_points}",
+                    pipe_points.len(),
+                    stage_names.len()
+                ));
+            }
+            record_traces.push(RecordTrace {
+                input_index: index(entry, "input_index")?,
+                pipe_points,
+                drops: drops(entry, stage_names.len())?,
+            });
+        }
+        let mut flush_traces = Vec::new();
+        for entry in field(&value, "flush_traces")? {
+            let stage_index = index(entry, "stage_index")?;
+            let pipe_points = pipe_points(entry, lrecl)?;
+            if stage_index >= stage_names.len()
+                || pipe_points.len() != stage_names.len() - stage_index
+            {
+                return Err(format!(
+                    "A flush trace of stage {stage_index} has {} pipe points",
+                    pipe_points.len()
+                ));
+            }
+            flush_traces.push(FlushTrace {
+                stage_index,
+                pipe_points,
+                drops: drops(entry, stage_names.len())?,
+            });
+        }
+        let point_counts = field(&value, "point_counts")?
+            .iter()
+            .map(|count| {
+                count
+                    .as_u64()
+                    .map(|n| n as usize)
+                    .ok_or("Trace 'point_counts' must hold numbers".to_string())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let truncated = value
+            .get("truncated")
+            .map_or(Some(false), Value::as_bool)
+            .ok_or("Trace 'truncated' must be true or false")?;
+
+        Ok(Self {
+            stage_names,
+            record_traces,
+            flush_traces,
+            point_counts,
+            truncated,
+        })
+    }
+
+    /// Returns the trace as the JSON value [`to_json`](Self::to_json)
+    /// writes, which `POST /trace` also returns.
+    #[cfg(feature = "json")]
+    pub(crate) fn json_value(&self) -> Value {
+        let lrecl = self
+            .record_traces
+            .iter()
+            .flat_map(|rt| &rt.pipe_points)
+            .chain(self.flush_traces.iter().flat_map(|ft| &ft.pipe_points))
+            .flatten()
+            .next()
+            .map_or(RECORD_WIDTH, Record::lrecl);
+        json!({
+            "version": TRACE_FORMAT_VERSION,
+            "lrecl": lrecl,
+            "stage_names": self.stage_names,
+            "record_traces": self
+                .record_traces
+                .iter()
+                .map(|rt| json!({
+                    "input_index": rt.input_index,
+                    "pipe_points": pipe_points_json(&rt.pipe_points),
+                    "drops": drops_json(&rt.drops),
+                }))
+                .collect::<Vec<_>>(),
+            "flush_traces": self
+                .flush_traces
+                .iter()
+                .map(|ft| json!({
+                    "stage_index": ft.stage_index,
+                    "pipe_points": pipe_points_json(&ft.pipe_points),
+                    "drops": drops_json(&ft.drops),
+                }))
+                .collect::<Vec<_>>(),
+            "point_counts": self.point_counts,
+            "truncated": self.truncated,
+        })
+    }
+}
+
+/// Returns the records at each pipe point as arrays of trimmed text.
+#[cfg(feature = "json")]
+fn pipe_points_json(pipe_points: &[Vec<Record>]) -> Value {
+    json!(
+        pipe_points
+            .iter()
+            .map(|records| records
+                .iter()
+                .map(|r| r.as_str().trim_end())
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    )
+}
+
+/// Returns the dropped records of a record or flush trace.
+#[cfg(feature = "json")]
+fn drops_json(drops: &[DroppedRecord]) -> Value {
+    json!(
+        drops
+            .iter()
+            .map(|drop| json!({
+                "point": drop.point,
+                "index": drop.index,
+                "reason": drop.reason,
+            }))
+            .collect::<Vec<_>>()
+    )
+}
+
+/// Returns the array in field `name` of a trace document.
+#[cfg(feature = "json")]
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<Value>, String> {
+    value
+        .get(name)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("Trace is missing the '{name}' array"))
+}
+
+/// Returns the number in field `name` of a record or flush trace.
+#[cfg(feature = "json")]
+fn index(entry: &Value, name: &str) -> Result<usize, String> {
+    entry
+        .get(name)
+        .and_then(Value::as_u64)
+        .map(|n| n as usize)
+        .ok_or_else(|| format!("A trace entry is missing '{name}'"))
+}
+
+/// Reads the dropped records of a record or flush trace, which traces
+/// written without them lack.
+#[cfg(feature = "json")]
+fn drops(entry: &Value, num_stages: usize) -> Result<Vec<DroppedRecord>, String> {
+    let Some(drops) = entry.get("drops") else {
+        return Ok(Vec::new());
+    };
+    drops
+        .as_array()
+        .ok_or("Trace 'drops' must be an array")?
+        .iter()
+        .map(|drop| {
+            let point = index(drop, "point")?;
+            if point >= num_stages {
+                return Err(format!(
+                    "A dropped record is at pipe point {point}, after the last stage"
+                ));
+            }
+            Ok(DroppedRecord {
+                point,
+                index: index(drop, "index")?,
+                reason: drop
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .ok_or("A dropped record is missing 'reason'")?
+                    .to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the pipe points of a record or flush trace as `lrecl`-byte
+/// records.
+#[cfg(feature = "json")]
+fn pipe_points(entry: &Value, lrecl: usize) -> Result<Vec<Vec<Record>>, String> {
+    field(entry, "pipe_points")?
+        .iter()
+        .map(|point| {
+            point
+                .as_array()
+                .ok_or("Trace pipe points must be arrays of records")?
+                .iter()
+                .map(|record| {
+                    record
+                        .as_str()
+                        .map(|text| Record::from_str_lrecl(text, lrecl))
+                        .ok_or("Trace records must be strings".to_string())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A test choosing which input records to trace.
+type Predicate = Box<dyn Fn(&Record) -> bool>;
+
+/// Which records [`execute_rat_traced_with`](crate::execute_rat_traced_with)
+/// captures.
+///
+/// Every record still runs through the pipeline and is counted in
+/// [`RatDebugTrace::point_counts`]; the options only choose which input
+/// records keep their pipe points, so large inputs trace in bounded memory.
+/// The default traces everything.
+pub struct TraceOptions {
+    every: usize,
+    predicate: Option<Predicate>,
+    max_records: Option<usize>,
+}
+
+impl TraceOptions {
+    /// Creates options that trace every record.
+    pub fn new() -> Self {
+        Self {
+            every: 1,
+            predicate: None,
+            max_records: None,
+        }
+    }
+
+    /// Traces only every `n`th input record: the first, the `n + 1`th, and
+    /// so on. `0` is treated as `1`.
+    #[must_use]
+    pub fn every(mut self, n: usize) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    /// Traces only input records the predicate accepts.
+    #[must_use]
+    pub fn matching(mut self, predicate: impl Fn(&Record) -> bool + 'static) -> Self {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    /// Caps the records held across all traces, counting each record at
+    /// each pipe point. Capture stops at the first trace that would go over
+    /// the cap, and the trace is marked truncated.
+    #[must_use]
+    pub fn max_records(mut self, max: usize) -> Self {
+        self.max_records = Some(max);
+        self
+    }
+
+    /// Returns the cap on captured records, if any.
+    pub fn limit(&self) -> Option<usize> {
+        self.max_records
+    }
+
+    /// Returns whether the input record at `index` is sampled and matches.
+    pub fn selects(&self, index: usize, record: &Record) -> bool {
+        index.is_multiple_of(self.every) && self.predicate.as_ref().is_none_or(|p| p(record))
+    }
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the columns that the stage before `pipe_points[point]` changed
+/// in each record there, one list per record.
+///
+/// Each record is compared with the record at the same position in the
+/// pipe point before, or with the only record there when one record became
+/// several (DUPLICATE). Records with nothing to compare against, such as
+/// those at the first pipe point, get an empty list.
+pub fn changed_columns(pipe_points: &[Vec<Record>], point: usize) -> Vec<Vec<ColumnDiff>> {
+    let Some(records) = pipe_points.get(point) else {
+        return Vec::new();
+    };
+    let before: &[Record] = match point.checked_sub(1) {
+        Some(prev) => &pipe_points[prev],
+        None => &[],
+    };
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            let source = if before.len() == records.len() {
+                before.get(i)
+            } else if before.len() == 1 {
+                before.first()
+            } else {
+                None
+            };
+            source.map_or_else(Vec::new, |source| source.diff(record))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -46,10 +437,12 @@ mod tests {
     #[test]
     fn test_record_trace_structure() {
         let trace = RecordTrace {
+            input_index: 0,
             pipe_points: vec![
                 vec![Record::from_str("input")],
                 vec![Record::from_str("output")],
             ],
+            drops: vec![],
         };
         assert_eq!(trace.pipe_points.len(), 2);
         assert_eq!(trace.pipe_points[0].len(), 1);
@@ -61,6 +454,7 @@ mod tests {
         let trace = FlushTrace {
             stage_index: 1,
             pipe_points: vec![vec![Record::from_str("3")]],
+            drops: vec![],
         };
         assert_eq!(trace.stage_index, 1);
         assert_eq!(trace.pipe_points.len(), 1);
@@ -72,32 +466,195 @@ mod tests {
             stage_names: vec!["FILTER".to_string(), "COUNT".to_string()],
             record_traces: vec![],
             flush_traces: vec![],
+            point_counts: vec![3, 1, 1],
+            truncated: false,
         };
         assert_eq!(trace.stage_names.len(), 2);
         assert!(trace.record_traces.is_empty());
         assert!(trace.flush_traces.is_empty());
+        assert_eq!(trace.input_count(), 3);
+        assert_eq!(trace.untraced(), 3);
+    }
+
+    #[test]
+    fn test_trace_options_selects() {
+        let all = TraceOptions::default();
+        assert!(all.selects(7, &Record::from_str("A")));
+        assert_eq!(all.limit(), None);
+
+        let options = TraceOptions::new()
+            .every(3)
+            .matching(|r| r.as_str().starts_with('A'))
+            .max_records(10);
+        assert!(options.selects(0, &Record::from_str("A")));
+        assert!(!options.selects(1, &Record::from_str("A")));
+        assert!(options.selects(3, &Record::from_str("AB")));
+        assert!(!options.selects(3, &Record::from_str("B")));
+        assert_eq!(options.limit(), Some(10));
+        assert!(
+            TraceOptions::new()
+                .every(0)
+                .selects(1, &Record::from_str("A"))
+        );
     }
 
     #[test]
     fn test_record_trace_with_expansion() {
         // DUPLICATE produces multiple records at a pipe point
         let trace = RecordTrace {
+            input_index: 0,
             pipe_points: vec![
                 vec![Record::from_str("A")],
                 vec![Record::from_str("A"), Record::from_str("A")],
             ],
+            drops: vec![],
         };
         assert_eq!(trace.pipe_points[0].len(), 1);
         assert_eq!(trace.pipe_points[1].len(), 2);
     }
 
+    #[test]
+    fn test_changed_columns() {
+        let pipe_points = vec![
+            vec![Record::from_str("SMITH   JOHN")],
+            vec![Record::from_str("SMITH   JON")],
+            vec![
+                Record::from_str("SMITH   JON"),
+                Record::from_str("SMYTH   JON"),
+            ],
+            vec![],
+        ];
+        assert_eq!(changed_columns(&pipe_points, 0), vec![vec![]]);
+        assert_eq!(
+            changed_columns(&pipe_points, 1),
+            vec![vec![ColumnDiff { start: 10, len: 2 }]]
+        );
+        assert_eq!(
+            changed_columns(&pipe_points, 2),
+            vec![vec![], vec![ColumnDiff { start: 2, len: 1 }]]
+        );
+        assert!(changed_columns(&pipe_points, 3).is_empty());
+        assert!(changed_columns(&pipe_points, 4).is_empty());
+    }
+
     #[test]
     fn test_record_trace_with_filter() {
         // FILTER can produce zero records at a pipe point
         let trace = RecordTrace {
+            input_index: 4,
             pipe_points: vec![vec![Record::from_str("rejected")], vec![]],
+            drops: vec![],
         };
         assert_eq!(trace.pipe_points[0].len(), 1);
         assert!(trace.pipe_points[1].is_empty());
     }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_round_trip() {
+        let record = |text: &str| Record::from_str_lrecl(text, 40);
+        let trace = RatDebugTrace {
+            stage_names: vec!["LOCATE".to_string(), "COUNT".to_string()],
+            record_traces: vec![
+                RecordTrace {
+                    input_index: 0,
+                    pipe_points: vec![vec![record("SALES  A")], vec![record("SALES  A")], vec![]],
+                    drops: vec![],
+                },
+                RecordTrace {
+                    input_index: 2,
+                    pipe_points: vec![vec![record("ENG")], vec![], vec![]],
+                    drops: vec![DroppedRecord {
+                        point: 0,
+                        index: 0,
+                        reason: "LOCATE /S/ (not found in the record)".to_string(),
+                    }],
+                },
+            ],
+            flush_traces: vec![FlushTrace {
+                stage_index: 1,
+                pipe_points: vec![vec![record("1")]],
+                drops: vec![],
+            }],
+            point_counts: vec![3, 1, 1],
+            truncated: true,
+        };
+        let text = trace.to_json();
+        assert!(text.contains(r#""lrecl":40"#));
+        assert!(text.contains(r#"["SALES  A"]"#));
+        assert_eq!(RatDebugTrace::from_json(&text), Ok(trace));
+
+        let empty = RatDebugTrace {
+            stage_names: vec![],
+            record_traces: vec![],
+            flush_traces: vec![],
+            point_counts: vec![0],
+            truncated: false,
+        };
+        assert_eq!(RatDebugTrace::from_json(&empty.to_json()), Ok(empty));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_from_json_defaults() {
+        let trace = RatDebugTrace::from_json(
+            r#"{"stage_names": ["UPPER"],
+                "record_traces": [{"input_index": 0, "pipe_points": [["a"], ["A"]]}],
+                "flush_traces": [], "point_counts": [1, 1]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            trace.record_traces[0].pipe_points[1][0].lrecl(),
+            RECORD_WIDTH
+        );
+        assert!(!trace.truncated);
+        assert!(trace.record_traces[0].drops.is_empty());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_from_json_errors() {
+        let error = |text: &str| RatDebugTrace::from_json(text).unwrap_err();
+        assert!(error("{").starts_with("Invalid trace JSON"));
+        assert_eq!(
+            error(r#"{"version": 2}"#),
+            "Unsupported trace version 2 (expected 1)"
+        );
+        for lrecl in ["0", "32761", "18446744073709551615", "\"80\""] {
+            assert_eq!(
+                error(&format!(r#"{{"lrecl": {lrecl}}}"#)),
+                "Trace 'lrecl' must be from 1 to 32760"
+            );
+        }
+        assert_eq!(
+            error(r#"{"stage_names": ["A"]}"#),
+            "Trace is missing the 'record_traces' array"
+        );
+        assert_eq!(
+            error(
+                r#"{"stage_names": ["A"], "record_traces": [{"input_index": 0, "pipe_points": [["x"]]}]}"#
+            ),
+            "A record trace has 1 pipe points; 1 stages need 2"
+        );
+        assert_eq!(
+            error(
+                r#"{"stage_names": ["A"], "record_traces": [],
+                    "flush_traces": [{"stage_index": 1, "pipe_points": []}]}"#
+            ),
+            "A flush trace of stage 1 has 0 pipe points"
+        );
+        assert_eq!(
+            error(
+                r#"{"stage_names": ["A"], "record_traces": [{"input_index": 0, "pipe_points": [[1], []]}]}"#
+            ),
+            "Trace records must be strings"
+        );
+        assert_eq!(
+            error(
+                r#"{"stage_names": ["A"], "record_traces": [{"input_index": 0,
+                    "pipe_points": [["x"], []], "drops": [{"point": 1, "index": 0, "reason": "r"}]}]}"#
+            ),
+            "A dropped record is at pipe point 1, after the last stage"
+        );
+    }
 }
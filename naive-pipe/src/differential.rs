@@ -0,0 +1,530 @@
+//! Differential testing of the batch and record-at-a-time executors.
+//!
+//! The RAT executor is meant to produce exactly the batch executor's output
+//! for every pipeline it runs. [`compare_executors`] checks that for one
+//! plan and input: it runs both with tracing and, when their outputs
+//! differ, finds the first pipe point whose records differ, the first
+//! differing record there, and the RAT trace of the input record (or stage
+//! flush) that produced it. When the outputs agree, it compares the
+//! records of SPLIT outputs and of `>` and `>>` files. `pipe-diff-exec` runs it over
+//! pipeline files.
+//!
+//! # Example
+//!
+//! ```
+//! use naive_pipe::differential::compare_executors;
+//! use pipelines_rs::{HostVars, PipelinePlan};
+//!
+//! let plan = PipelinePlan::parse("PIPE CONSOLE | LOCATE /SALES/ | REVERSE | CONSOLE").unwrap();
+//! let input = "SMITH   SALES\nJONES   ENGINEER\nDOE     SALES";
+//! assert_eq!(compare_executors(&plan, input, &HostVars::new()), Ok(None));
+//! ```
+
+use std::fmt;
+
+use pipelines_rs::{DebugCallbacks, HostVars, PipelinePlan, Record, SplitOutputs, execute_plan};
+
+use crate::debug_trace::{RatDebugTrace, TraceOptions};
+use crate::dsl::execute_plan_rat_debug_with;
+use crate::record_stage::SideOutputs;
+
+/// Where the records at a RAT pipe point came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// The input record at this position, counting from 0.
+    Input(usize),
+    /// The flush of the stage at this index, counting from 0 after the
+    /// source.
+    Flush(usize),
+}
+
+/// The records a traced record became, with the pipe point of each.
+pub type Journey = Vec<(usize, Vec<Record>)>;
+
+/// How the two executors disagree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// One executor failed and the other did not, or both failed with
+    /// different errors.
+    Failed {
+        batch: Option<String>,
+        rat: Option<String>,
+    },
+    /// The executors produced different records.
+    Differs {
+        /// Pipe point of the first difference: 0 is the input, `i` the
+        /// output of stage `i`.
+        point: usize,
+        /// Name of the stage that wrote the pipe point.
+        stage: String,
+        /// Position of the first differing record at the pipe point.
+        index: usize,
+        /// The batch executor's record there, if it has one.
+        batch: Option<Record>,
+        /// The RAT executor's record there, if it has one.
+        rat: Option<Record>,
+        /// The RAT trace context of the record: its origin, and the records
+        /// it became at each pipe point up to `point`.
+        context: Option<(Origin, Journey)>,
+    },
+    /// The executors wrote different records to a side output: a SPLIT
+    /// output or the file of a `>` stage.
+    Output {
+        /// The output, e.g. `file 'work/sales.out'`.
+        output: String,
+        /// Position of the first differing record in the output.
+        index: usize,
+        /// The batch executor's record there, if it has one.
+        batch: Option<Record>,
+        /// The RAT executor's record there, if it has one.
+        rat: Option<Record>,
+    },
+}
+
+/// Prints where the executors first disagree, with the RAT trace of the
+/// record there. Record numbers are 1-based.
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Failed { batch, rat } => {
+                let result = |error: &Option<String>| match error {
+                    Some(e) => format!("failed: {e}"),
+                    None => "succeeded".to_string(),
+                };
+                write!(f, "batch {}\nRAT {}", result(batch), result(rat))
+            }
+            Divergence::Differs {
+                point,
+                stage,
+                index,
+                batch,
+                rat,
+                context,
+            } => {
+                write!(
+                    f,
+                    "{}: record {} differs\n  batch: {}\n  RAT:   {}",
+                    point_name(*point, stage),
+                    index + 1,
+                    record_text(batch),
+                    record_text(rat)
+                )?;
+                if let (Some(batch), Some(rat)) = (batch, rat) {
+                    for diff in batch.diff(rat) {
+                        write!(
+                            f,
+                            "\n  {},{}: batch {:?}, RAT {:?}",
+                            diff.start,
+                            diff.len,
+                            batch.field(diff.start, diff.len),
+                            rat.field(diff.start, diff.len)
+                        )?;
+                    }
+                }
+                let Some((origin, journey)) = context else {
+                    return Ok(());
+                };
+                match origin {
+                    Origin::Input(i) => write!(f, "\nRAT trace of input record {}:", i + 1)?,
+                    Origin::Flush(s) => write!(f, "\nRAT trace of stage {} flush:", s + 1)?,
+                }
+                for (point, records) in journey {
+                    let records: Vec<String> = records
+                        .iter()
+                        .map(|r| format!("{:?}", r.as_str().trim_end()))
+                        .collect();
+                    let records = if records.is_empty() {
+                        "(dropped)".to_string()
+                    } else {
+                        records.join(", ")
+                    };
+                    write!(f, "\n  {:<8} {records}", point_label(*point))?;
+                }
+                Ok(())
+            }
+            Divergence::Output {
+                output,
+                index,
+                batch,
+                rat,
+            } => write!(
+                f,
+                "{output}: record {} differs\n  batch: {}\n  RAT:   {}",
+                index + 1,
+                record_text(batch),
+                record_text(rat)
+            ),
+        }
+    }
+}
+
+/// Quotes a record's text for a report.
+fn record_text(record: &Option<Record>) -> String {
+    match record {
+        Some(r) => format!("{:?}", r.as_str().trim_end()),
+        None => "(no record)".to_string(),
+    }
+}
+
+/// Runs a plan through both executors and returns where they first
+/// disagree, or `None` if they produce the same output.
+///
+/// Returns an error if the plan cannot run in the RAT executor (it has
+/// segments) or both executors fail with the same error. Both executors
+/// use the same `vars`, so STAMP's date and time agree.
+pub fn compare_executors(
+    plan: &PipelinePlan,
+    input_text: &str,
+    vars: &HostVars,
+) -> Result<Option<Divergence>, String> {
+    if !plan.segments().is_empty() {
+        return Err(
+            "Pipelines with segments (? label: or ? < name) run only in the batched executor"
+                .to_string(),
+        );
+    }
+    let batch = execute_plan(plan, input_text, &Some(DebugCallbacks::new()), vars);
+    let side = SideOutputs::new();
+    let rat = execute_plan_rat_debug_with(plan, input_text, vars, &side, &TraceOptions::new());
+    let (batch, (rat_output, _, _, trace)) = match (batch, rat) {
+        (Ok(batch), Ok(rat)) => (batch, rat),
+        (Err(batch), Err(rat)) if batch == rat => return Err(batch),
+        (batch, rat) => {
+            return Ok(Some(Divergence::Failed {
+                batch: batch.err(),
+                rat: rat.err(),
+            }));
+        }
+    };
+    if batch.output == rat_output {
+        return Ok(output_divergence(&batch.outputs, &side.outputs(), |name| {
+            format!("SPLIT {name}")
+        })
+        .or_else(|| {
+            output_divergence(&batch.files, &side.files(), |path| format!("file '{path}'"))
+        }));
+    }
+
+    let batch_points: Vec<Vec<Record>> = batch
+        .debug_info
+        .iter()
+        .map(|info| info.output_records.clone().unwrap_or_default())
+        .collect();
+    Ok(Some(divergence(&batch_points, &trace).unwrap_or_else(
+        || {
+            // The stages agree, so the output text itself differs
+            let last = trace.stage_names.len();
+            let lines = |text: &str| -> Vec<Record> {
+                text.lines()
+                    .map(|line| Record::from_str_lrecl(line, plan.lrecl()))
+                    .collect()
+            };
+            let (batch, rat) = (lines(&batch.output), lines(&rat_output));
+            let index = first_difference(&batch, &rat).unwrap_or_default();
+            Divergence::Differs {
+                point: last,
+                stage: "output".to_string(),
+                index,
+                batch: batch.get(index).cloned(),
+                rat: rat.get(index).cloned(),
+                context: None,
+            }
+        },
+    )))
+}
+
+/// Finds the first pipe point where the batch records differ from the
+/// records the RAT trace passed there.
+fn divergence(batch_points: &[Vec<Record>], trace: &RatDebugTrace) -> Option<Divergence> {
+    let rat_points = pipe_points(trace);
+    rat_points.iter().enumerate().find_map(|(point, rat)| {
+        let batch = batch_points.get(point).map_or(&[][..], Vec::as_slice);
+        let index = first_difference(batch, rat)?;
+        Some(Divergence::Differs {
+            point,
+            stage: stage_name(trace, point),
+            index,
+            batch: batch.get(index).cloned(),
+            rat: rat.get(index).cloned(),
+            context: context(trace, point, index),
+        })
+    })
+}
+
+/// Finds the first record that differs between the batch and RAT records
+/// of each side output; `label` describes an output by its name.
+fn output_divergence(
+    batch: &SplitOutputs,
+    rat: &SplitOutputs,
+    label: impl Fn(&str) -> String,
+) -> Option<Divergence> {
+    let mut names: Vec<&str> = batch.names().collect();
+    names.extend(rat.names().filter(|name| batch.get(name).is_none()));
+    names.into_iter().find_map(|name| {
+        let batch = batch.get(name).unwrap_or_default();
+        let rat = rat.get(name).unwrap_or_default();
+        let index = first_difference(batch, rat)?;
+        Some(Divergence::Output {
+            output: label(name),
+            index,
+            batch: batch.get(index).cloned(),
+            rat: rat.get(index).cloned(),
+        })
+    })
+}
+
+/// Returns the position of the first record that differs, or that only one
+/// side has.
+fn first_difference(batch: &[Record], rat: &[Record]) -> Option<usize> {
+    batch
+        .iter()
+        .zip(rat)
+        .position(|(b, r)| b != r)
+        .or_else(|| (batch.len() != rat.len()).then(|| batch.len().min(rat.len())))
+}
+
+/// Collects the records at each RAT pipe point, in the order the executor
+/// passed them: each input record's, then each stage's flush output.
+fn pipe_points(trace: &RatDebugTrace) -> Vec<Vec<Record>> {
+    let mut points = vec![Vec::new(); trace.stage_names.len() + 1];
+    for record_trace in &trace.record_traces {
+        for (point, records) in record_trace.pipe_points.iter().enumerate() {
+            points[point].extend(records.iter().cloned());
+        }
+    }
+    for flush in &trace.flush_traces {
+        for (offset, records) in flush.pipe_points.iter().enumerate() {
+            points[flush.stage_index + 1 + offset].extend(records.iter().cloned());
+        }
+    }
+    points
+}
+
+/// Finds the trace that passed record `index` at pipe point `point`, and
+/// returns its origin and its records up to that point.
+fn context(trace: &RatDebugTrace, point: usize, index: usize) -> Option<(Origin, Journey)> {
+    let mut seen = 0;
+    for record_trace in &trace.record_traces {
+        seen += record_trace.pipe_points.get(point).map_or(0, Vec::len);
+        if index < seen {
+            let journey = record_trace.pipe_points[..=point]
+                .iter()
+                .cloned()
+                .enumerate()
+                .collect();
+            return Some((Origin::Input(record_trace.input_index), journey));
+        }
+    }
+    for flush in &trace.flush_traces {
+        let Some(offset) = point.checked_sub(flush.stage_index + 1) else {
+            continue;
+        };
+        seen += flush.pipe_points.get(offset).map_or(0, Vec::len);
+        if index < seen {
+            let journey = flush.pipe_points[..=offset]
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, records)| (flush.stage_index + 1 + i, records))
+                .collect();
+            return Some((Origin::Flush(flush.stage_index), journey));
+        }
+    }
+    None
+}
+
+/// Returns the name of the stage that writes pipe point `point`.
+fn stage_name(trace: &RatDebugTrace, point: usize) -> String {
+    match point.checked_sub(1) {
+        Some(stage) => trace.stage_names.get(stage).cloned().unwrap_or_default(),
+        None => "input".to_string(),
+    }
+}
+
+/// Describes a pipe point for the first line of a report.
+fn point_name(point: usize, stage: &str) -> String {
+    if point == 0 {
+        "input".to_string()
+    } else {
+        format!("after stage {point} ({stage})")
+    }
+}
+
+/// Labels a pipe point in a trace.
+fn point_label(point: usize) -> String {
+    if point == 0 {
+        "input".to_string()
+    } else {
+        format!("stage {point}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug_trace::{FlushTrace, RecordTrace};
+
+    fn records(lines: &[&str]) -> Vec<Record> {
+        lines.iter().map(|line| Record::from_str(line)).collect()
+    }
+
+    /// A trace of `LOCATE /S/ | COUNT` over "SALES", "ENG", "SUPPORT".
+    fn trace() -> RatDebugTrace {
+        RatDebugTrace {
+            stage_names: vec!["LOCATE".to_string(), "COUNT".to_string()],
+            record_traces: vec![
+                RecordTrace {
+                    input_index: 0,
+                    pipe_points: vec![records(&["SALES"]), records(&["SALES"]), vec![]],
+                    drops: vec![],
+                },
+                RecordTrace {
+                    input_index: 1,
+                    pipe_points: vec![records(&["ENG"]), vec![], vec![]],
+                    drops: vec![],
+                },
+                RecordTrace {
+                    input_index: 2,
+                    pipe_points: vec![records(&["SUPPORT"]), records(&["SUPPORT"]), vec![]],
+                    drops: vec![],
+                },
+            ],
+            flush_traces: vec![FlushTrace {
+                stage_index: 1,
+                pipe_points: vec![records(&["2"])],
+                drops: vec![],
+            }],
+            point_counts: vec![3, 2, 1],
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_compare_executors_agree() {
+        let input = "SMITH   SALES\nJONES   ENGINEER\nDOE     SALES";
+        for pipeline in [
+            "PIPE CONSOLE | LOCATE /SALES/ | CONSOLE",
+            "PIPE CONSOLE | SORT 8,8 D | DUPLICATE 2 | COUNT | CONSOLE",
+            "PIPE CONSOLE | STAMP 40,8 DATE | TAKE LAST 1 | CONSOLE",
+        ] {
+            let plan = PipelinePlan::parse(pipeline).unwrap();
+            assert_eq!(compare_executors(&plan, input, &HostVars::new()), Ok(None));
+        }
+
+        let plan =
+            PipelinePlan::parse("PIPE CONSOLE | a: LOCATE /X/ | CONSOLE ? a: | CONSOLE").unwrap();
+        assert!(compare_executors(&plan, input, &HostVars::new()).is_err());
+    }
+
+    #[test]
+    fn test_divergence_in_record() {
+        let batch = vec![
+            records(&["SALES", "ENG", "SUPPORT"]),
+            records(&["SALES", "SUPPORX"]),
+            records(&["2"]),
+        ];
+        let found = divergence(&batch, &trace()).unwrap();
+        let Divergence::Differs {
+            point,
+            index,
+            context,
+            ..
+        } = &found
+        else {
+            panic!("expected differing records");
+        };
+        assert_eq!((*point, *index), (1, 1));
+        assert_eq!(context.as_ref().unwrap().0, Origin::Input(2));
+        assert_eq!(
+            found.to_string(),
+            "after stage 1 (LOCATE): record 2 differs\n  \
+             batch: \"SUPPORX\"\n  \
+             RAT:   \"SUPPORT\"\n  \
+             6,1: batch \"X\", RAT \"T\"\n\
+             RAT trace of input record 3:\n  \
+             input    \"SUPPORT\"\n  \
+             stage 1  \"SUPPORT\""
+        );
+    }
+
+    #[test]
+    fn test_divergence_in_flush() {
+        let mut batch = vec![
+            records(&["SALES", "ENG", "SUPPORT"]),
+            records(&["SALES", "SUPPORT"]),
+        ];
+        batch.push(records(&["3"]));
+        let found = divergence(&batch, &trace()).unwrap();
+        assert!(matches!(
+            &found,
+            Divergence::Differs {
+                point: 2,
+                index: 0,
+                context: Some((Origin::Flush(1), _)),
+                ..
+            }
+        ));
+        assert!(
+            found
+                .to_string()
+                .ends_with("RAT trace of stage 2 flush:\n  stage 2  \"2\"")
+        );
+
+        batch[2].push(Record::from_str("EXTRA"));
+        batch[2][0] = Record::from_str("2");
+        let found = divergence(&batch, &trace()).unwrap();
+        assert!(matches!(
+            found,
+            Divergence::Differs {
+                point: 2,
+                index: 1,
+                rat: None,
+                context: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_compare_files() {
+        let input = "SMITH   SALES\nJONES   ENGINEER\nDOE     SALES";
+        let plan = PipelinePlan::parse(
+            "PIPE CONSOLE | > work/all.txt | LOCATE /SALES/ | > work/sales.txt",
+        )
+        .unwrap();
+        assert_eq!(compare_executors(&plan, input, &HostVars::new()), Ok(None));
+
+        let mut batch = SplitOutputs::new();
+        batch.append("work/all.txt", records(&["SMITH", "JONES"]));
+        let mut rat = SplitOutputs::new();
+        rat.append("work/all.txt", records(&["SMITH"]));
+        let found = output_divergence(&batch, &rat, |path| format!("file '{path}'")).unwrap();
+        assert_eq!(
+            found.to_string(),
+            "file 'work/all.txt': record 2 differs\n  batch: \"JONES\"\n  RAT:   (no record)"
+        );
+        assert_eq!(output_divergence(&batch, &batch, str::to_string), None);
+    }
+
+    #[test]
+    fn test_compare_split_outputs() {
+        let input = "SMITH   SALES\nJONES   ENGINEER\nDOE     SALES";
+        let plan = PipelinePlan::parse(
+            "PIPE CONSOLE | SPLIT SALES IF 8,5 = \"SALES\" BUILD 0,8,0 | SPLIT NONE IF 0,1 = \"Z\"",
+        )
+        .unwrap();
+        assert_eq!(compare_executors(&plan, input, &HostVars::new()), Ok(None));
+    }
+
+    #[test]
+    fn test_failed_display() {
+        let divergence = Divergence::Failed {
+            batch: None,
+            rat: Some("bad stage".to_string()),
+        };
+        assert_eq!(
+            divergence.to_string(),
+            "batch succeeded\nRAT failed: bad stage"
+        );
+    }
+}
@@ -1,65 +1,264 @@
 //! RAT-specific pipeline execution wrappers.
 //!
 //! Provides `execute_pipeline_rat` and `execute_pipeline_rat_debug` which
-//! parse DSL text and execute using the record-at-a-time executor.
+//! parse DSL text and execute using the record-at-a-time executor. The
+//! `_with_vars` variants take the host variables used by `STAMP`, and the
+//! `execute_plan_rat` variants run an already parsed or built
+//! [`PipelinePlan`], the same plan the batch executor runs.
+//! [`execute_plan_rat_streaming`] reads input lines and writes output
+//! records as it goes, for files too large to hold in memory, and
+//! [`execute_plan_block_streaming`] and [`execute_plan_threaded_streaming`]
+//! do the same a block of records at a time and with each stage on its own
+//! thread.
+//!
+//! The records written by `>` and `>>` stages, and the warnings the
+//! source reports, are kept in a [`SideOutputs`] the caller passes in; the
+//! variants without one discard them. An error a stage reports there, such
+//! as a SORT work file that cannot be written, fails the run when it ends.
+
+use std::cell::{Cell, RefCell};
 
-use pipelines_rs::{Command, Record, parse_commands};
+use pipelines_rs::dsl::{source_records, stream_records, stream_records_pooled};
+use pipelines_rs::{Command, HostVars, PipelinePlan, Record, RecordPool, check_runtime};
 
-use crate::debug_trace::RatDebugTrace;
-use crate::executor::{execute_rat, execute_rat_traced};
-use crate::record_stage::{RecordStage, command_to_record_stage};
+use crate::checkpoint::{Checkpoint, CheckpointOptions};
+use crate::debug_trace::{RatDebugTrace, TraceOptions};
+use crate::executor::{
+    BLOCK_SIZE, execute_block_streaming, execute_rat, execute_rat_checkpointed,
+    execute_rat_streaming, execute_rat_traced_with, execute_threaded_streaming,
+};
+use crate::record_stage::{ConsoleStage, RecordStage, SideOutputs, command_to_record_stage_side};
 
 /// Execute a pipeline in record-at-a-time mode.
 ///
 /// Returns (output_text, input_count, output_count) on success.
-/// Produces identical output to `execute_pipeline` for all pipelines.
 pub fn execute_pipeline_rat(
     input_text: &str,
     pipeline_text: &str,
 ) -> Result<(String, usize, usize), String> {
-    let commands = parse_commands(pipeline_text)?;
-
-    if commands.is_empty() {
-        return Err("Pipeline is empty".to_string());
-    }
-    if commands.len() < 2 {
-        return Err("Pipeline must have at least 2 stages".to_string());
-    }
-
-    let first = commands.first().unwrap();
-    if !first.can_be_first() {
-        return Err(format!(
-            "{} cannot be the first stage (try CONSOLE, LITERAL, or HOLE)",
-            first.name()
-        ));
-    }
+    execute_pipeline_rat_with_vars(input_text, pipeline_text, &HostVars::new())
+}
 
-    let input_records: Vec<Record> = match first {
-        Command::Console => input_text
-            .lines()
-            .filter(|line| !line.is_empty())
-            .map(Record::from_str)
-            .collect(),
-        Command::Literal { text } => vec![Record::from_str(text)],
-        Command::Hole => vec![],
-        _ => return Err(format!("Unhandled source stage: {}", first.name())),
-    };
+/// Execute a pipeline in record-at-a-time mode, resolving `STAMP` host
+/// variables from `vars`.
+///
+/// Returns (output_text, input_count, output_count) on success.
+pub fn execute_pipeline_rat_with_vars(
+    input_text: &str,
+    pipeline_text: &str,
+    vars: &HostVars,
+) -> Result<(String, usize, usize), String> {
+    execute_plan_rat(&PipelinePlan::parse(pipeline_text)?, input_text, vars)
+}
 
+/// Execute a plan in record-at-a-time mode.
+///
+/// Returns (output_text, input_count, output_count) on success.
+pub fn execute_plan_rat(
+    plan: &PipelinePlan,
+    input_text: &str,
+    vars: &HostVars,
+) -> Result<(String, usize, usize), String> {
+    let side = SideOutputs::new();
+    let (input_records, mut stages) = prepare(plan, input_text, vars, &side)?;
     let input_count = input_records.len();
 
-    let mut stages: Vec<Box<dyn RecordStage>> =
-        commands[1..].iter().map(command_to_record_stage).collect();
-
     let output_records = execute_rat(input_records, &mut stages);
     let output_count = output_records.len();
+    side.check()?;
+
+    Ok((output_text(&output_records), input_count, output_count))
+}
+
+/// Execute a plan in record-at-a-time mode, pulling input lines as the
+/// source needs them and passing each output record to `emit` as soon as
+/// the last stage writes it.
+///
+/// Returns (input_count, output_count) on success. `emit` failing stops
+/// the run with its error. Each record is recycled once `emit` has it, and
+/// its buffer holds a later input record, so a run does not allocate and
+/// free a record per input line. The records `>` and `>>` stages write are
+/// kept in `side` until the run ends.
+pub fn execute_plan_rat_streaming<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    mut emit: F,
+) -> Result<(usize, usize), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let mut stages = build_stages(plan, vars, side)?;
+    let pool = RefCell::new(RecordPool::new());
+    let input = stream_records_pooled(plan.source(), lines, plan.lrecl(), &pool, |w| side.warn(w))?;
+    let mut output_count = 0;
+    let input_count = execute_rat_streaming(input, &mut stages, |record| {
+        output_count += 1;
+        emit(&record)?;
+        pool.borrow_mut().recycle(record);
+        Ok(())
+    })?;
+    side.check()?;
+    Ok((input_count, output_count))
+}
+
+/// Execute a plan like [`execute_plan_rat_streaming`], resuming from
+/// `options.restart` if given, and passing a [`Checkpoint`] to `save`
+/// after every `options.every` input records.
+///
+/// A restarted run skips the input records the checkpoint has read,
+/// restores each stage's state, and emits only the records the first run
+/// had not; its counts include those before the checkpoint. Fails before
+/// reading any input if a stage cannot be checkpointed, which includes `>`
+/// and `>>`, so `side` keeps only the source's warnings.
+pub fn execute_plan_rat_checkpointed<I, S, F, C>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    options: CheckpointOptions,
+    mut emit: F,
+    mut save: C,
+) -> Result<(usize, usize), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+    C: FnMut(Checkpoint) -> Result<(), String>,
+{
+    let mut stages = build_stages(plan, vars, side)?;
+    Checkpoint::capture(&stages, 0, 0)?;
+    let (skip, written) = match options.restart {
+        Some(checkpoint) => {
+            checkpoint.restore(&mut stages)?;
+            (checkpoint.records_in, checkpoint.records_out)
+        }
+        None => (0, 0),
+    };
+    let output_count = Cell::new(written);
+    let pool = RefCell::new(RecordPool::new());
+    let input = stream_records_pooled(plan.source(), lines, plan.lrecl(), &pool, |w| side.warn(w))?
+        .skip(skip);
+    let input_count = execute_rat_checkpointed(
+        input,
+        &mut stages,
+        options.every,
+        |record| {
+            output_count.set(output_count.get() + 1);
+            emit(&record)?;
+            pool.borrow_mut().recycle(record);
+            Ok(())
+        },
+        |stages, count| {
+            save(Checkpoint::capture(
+                stages,
+                skip + count,
+                output_count.get(),
+            )?)
+        },
+    )?;
+    side.check()?;
+    Ok((skip + input_count, output_count.get()))
+}
 
-    let output_text = output_records
+/// Execute a plan like [`execute_plan_rat_streaming`], passing records
+/// through the stages [`BLOCK_SIZE`] at a time.
+///
+/// Returns (input_count, output_count) on success; the output is the same
+/// as in record-at-a-time mode.
+pub fn execute_plan_block_streaming<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    mut emit: F,
+) -> Result<(usize, usize), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let mut stages = build_stages(plan, vars, side)?;
+    let pool = RefCell::new(RecordPool::new());
+    let input = stream_records_pooled(plan.source(), lines, plan.lrecl(), &pool, |w| side.warn(w))?;
+    let mut output_count = 0;
+    let input_count = execute_block_streaming(input, &mut stages, BLOCK_SIZE, |record| {
+        output_count += 1;
+        emit(&record)?;
+        pool.borrow_mut().recycle(record);
+        Ok(())
+    })?;
+    side.check()?;
+    Ok((input_count, output_count))
+}
+
+/// Execute a plan like [`execute_plan_rat_streaming`], with each stage on
+/// its own thread connected to the next by a bounded channel.
+///
+/// Returns (input_count, output_count) on success; the output is the same
+/// as in record-at-a-time mode.
+pub fn execute_plan_threaded_streaming<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    mut emit: F,
+) -> Result<(usize, usize), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    check_plan(plan, vars)?;
+    let stages = plan
+        .stages()
         .iter()
-        .map(|r| r.as_str().trim_end())
-        .collect::<Vec<_>>()
-        .join("\n");
+        .enumerate()
+        .map(|(idx, cmd)| move || build_stage(plan, idx, cmd, vars, side))
+        .collect();
+    let input = stream_records(plan.source(), lines, plan.lrecl(), |w| side.warn(w))?;
+    let mut output_count = 0;
+    let input_count = execute_threaded_streaming(input, stages, |record| {
+        output_count += 1;
+        emit(&record)
+    })?;
+    side.check()?;
+    Ok((input_count, output_count))
+}
 
-    Ok((output_text, input_count, output_count))
+/// Execute a plan like [`execute_plan_rat_streaming`], tracing the records
+/// `options` selects.
+///
+/// Returns (input_count, output_count, trace) on success. The traced
+/// executor runs on a vector of records, so every input line is read
+/// before the run and every output record passed to `emit` after it;
+/// `options` bound only the memory the trace holds.
+pub fn execute_plan_rat_traced<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    side: &SideOutputs,
+    options: &TraceOptions,
+    mut emit: F,
+) -> Result<(usize, usize, RatDebugTrace), String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let mut stages = build_stages(plan, vars, side)?;
+    let input = stream_records(plan.source(), lines, plan.lrecl(), |w| side.warn(w))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let input_count = input.len();
+    let (output, trace) = execute_rat_traced_with(input, &mut stages, options);
+    side.check()?;
+    for record in &output {
+        emit(record)?;
+    }
+    Ok((input_count, output.len(), trace))
 }
 
 /// Execute a pipeline in record-at-a-time mode with debug tracing.
@@ -69,47 +268,126 @@ pub fn execute_pipeline_rat_debug(
     input_text: &str,
     pipeline_text: &str,
 ) -> Result<(String, usize, usize, RatDebugTrace), String> {
-    let commands = parse_commands(pipeline_text)?;
-
-    if commands.is_empty() {
-        return Err("Pipeline is empty".to_string());
-    }
-    if commands.len() < 2 {
-        return Err("Pipeline must have at least 2 stages".to_string());
-    }
+    execute_pipeline_rat_debug_with_vars(input_text, pipeline_text, &HostVars::new())
+}
 
-    let first = commands.first().unwrap();
-    if !first.can_be_first() {
-        return Err(format!(
-            "{} cannot be the first stage (try CONSOLE, LITERAL, or HOLE)",
-            first.name()
-        ));
-    }
+/// Execute a pipeline in record-at-a-time mode with debug tracing,
+/// resolving `STAMP` host variables from `vars`.
+///
+/// Returns (output_text, input_count, output_count, trace) on success.
+pub fn execute_pipeline_rat_debug_with_vars(
+    input_text: &str,
+    pipeline_text: &str,
+    vars: &HostVars,
+) -> Result<(String, usize, usize, RatDebugTrace), String> {
+    execute_plan_rat_debug(&PipelinePlan::parse(pipeline_text)?, input_text, vars)
+}
 
-    let input_records: Vec<Record> = match first {
-        Command::Console => input_text
-            .lines()
-            .filter(|line| !line.is_empty())
-            .map(Record::from_str)
-            .collect(),
-        Command::Literal { text } => vec![Record::from_str(text)],
-        Command::Hole => vec![],
-        _ => return Err(format!("Unhandled source stage: {}", first.name())),
-    };
+/// Execute a plan in record-at-a-time mode with debug tracing.
+///
+/// Returns (output_text, input_count, output_count, trace) on success.
+pub fn execute_plan_rat_debug(
+    plan: &PipelinePlan,
+    input_text: &str,
+    vars: &HostVars,
+) -> Result<(String, usize, usize, RatDebugTrace), String> {
+    execute_plan_rat_debug_with(
+        plan,
+        input_text,
+        vars,
+        &SideOutputs::new(),
+        &TraceOptions::new(),
+    )
+}
 
+/// Execute a plan in record-at-a-time mode, tracing the records `options`
+/// selects and keeping the records `>` and `>>` stages write in `side`.
+///
+/// Returns (output_text, input_count, output_count, trace) on success.
+pub fn execute_plan_rat_debug_with(
+    plan: &PipelinePlan,
+    input_text: &str,
+    vars: &HostVars,
+    side: &SideOutputs,
+    options: &TraceOptions,
+) -> Result<(String, usize, usize, RatDebugTrace), String> {
+    let (input_records, mut stages) = prepare(plan, input_text, vars, side)?;
     let input_count = input_records.len();
 
-    let mut stages: Vec<Box<dyn RecordStage>> =
-        commands[1..].iter().map(command_to_record_stage).collect();
-
-    let (output_records, trace) = execute_rat_traced(input_records, &mut stages);
+    let (output_records, trace) = execute_rat_traced_with(input_records, &mut stages, options);
     let output_count = output_records.len();
+    side.check()?;
+
+    Ok((
+        output_text(&output_records),
+        input_count,
+        output_count,
+        trace,
+    ))
+}
 
-    let output_text = output_records
+/// Source records and the stages that process them.
+type Prepared = (Vec<Record>, Vec<Box<dyn RecordStage>>);
+
+/// Validates a plan and builds its source records and stages.
+fn prepare(
+    plan: &PipelinePlan,
+    input_text: &str,
+    vars: &HostVars,
+    side: &SideOutputs,
+) -> Result<Prepared, String> {
+    let stages = build_stages(plan, vars, side)?;
+    let input_records = source_records(plan.source(), input_text, plan.lrecl(), |w| side.warn(w))?;
+    Ok((input_records, stages))
+}
+
+/// Validates a plan and builds the stages after its source.
+fn build_stages(
+    plan: &PipelinePlan,
+    vars: &HostVars,
+    side: &SideOutputs,
+) -> Result<Vec<Box<dyn RecordStage>>, String> {
+    check_plan(plan, vars)?;
+    Ok(plan
+        .stages()
+        .iter()
+        .enumerate()
+        .map(|(idx, cmd)| build_stage(plan, idx, cmd, vars, side))
+        .collect())
+}
+
+/// Checks that a plan can run without the batch executor.
+fn check_plan(plan: &PipelinePlan, vars: &HostVars) -> Result<(), String> {
+    plan.validate()?;
+    if !plan.segments().is_empty() {
+        return Err(
+            "Pipelines with segments (? label: or ? < name) run only in the batched executor"
+                .to_string(),
+        );
+    }
+    check_runtime(plan.commands(), vars)
+}
+
+/// Builds stage `idx` after the plan's source.
+fn build_stage(
+    plan: &PipelinePlan,
+    idx: usize,
+    cmd: &Command,
+    vars: &HostVars,
+    side: &SideOutputs,
+) -> Box<dyn RecordStage> {
+    match cmd {
+        // The last CONSOLE is the output itself, not an echo of it
+        Command::Console if idx + 1 == plan.stages().len() => Box::new(ConsoleStage::output()),
+        _ => command_to_record_stage_side(cmd, vars, plan.lrecl(), side),
+    }
+}
+
+/// Formats output records, one trimmed line each.
+fn output_text(records: &[Record]) -> String {
+    records
         .iter()
         .map(|r| r.as_str().trim_end())
         .collect::<Vec<_>>()
-        .join("\n");
-
-    Ok((output_text, input_count, output_count, trace))
+        .join("\n")
 }
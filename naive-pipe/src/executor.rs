@@ -4,24 +4,70 @@
 //! stage chain before reading the next input record. This contrasts with
 //! the batch executor which processes all records through one stage before
 //! moving to the next.
+//!
+//! The block executor ([`execute_block_streaming`]) moves records through
+//! the stages in blocks of [`BLOCK_SIZE`], with one `process_block` call per
+//! stage and block rather than a `process` call per stage and record; the
+//! output is again the same.
+//!
+//! The threaded executor ([`execute_threaded_streaming`]) runs the same
+//! stages with each on its own thread, connected by bounded channels, as
+//! the stages of a CMS pipeline run concurrently: a CPU-heavy stage works
+//! on one record while the stages after it work on earlier ones. Records
+//! leave in the same order, so its output matches the RAT executor's.
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::thread;
 
 use pipelines_rs::Record;
+use pipelines_rs::instrument::{RecordSpan, RunSpan, StageSpan};
 
-use crate::debug_trace::{FlushTrace, RatDebugTrace, RecordTrace};
+use crate::debug_trace::{DroppedRecord, FlushTrace, RatDebugTrace, RecordTrace, TraceOptions};
 use crate::record_stage::RecordStage;
 
-/// Push records through a slice of stages, processing each record
-/// through each stage in sequence.
-fn push_through_stages(records: Vec<Record>, stages: &mut [Box<dyn RecordStage>]) -> Vec<Record> {
-    let mut current = records;
-    for stage in stages.iter_mut() {
-        let mut next = Vec::new();
-        for r in current {
-            next.extend(stage.process(r));
+/// Creates the span of each stage, numbered from 1 after the source as in
+/// the batch executor.
+fn stage_spans(stages: &[Box<dyn RecordStage>]) -> Vec<StageSpan> {
+    stages
+        .iter()
+        .enumerate()
+        .map(|(idx, stage)| StageSpan::new(idx + 1, stage.name()))
+        .collect()
+}
+
+/// Flushes stage `i`, pulling its records (inside its span) `block` at a
+/// time and passing each block through the stages after it before pulling
+/// the next, so a stage that streams its flush (SORT) is never held whole.
+/// Returns the number of records that left the last stage for `emit`.
+fn flush_through<F, E>(
+    stages: &mut [Box<dyn RecordStage>],
+    spans: &[StageSpan],
+    i: usize,
+    block: usize,
+    emit: &mut F,
+) -> Result<usize, E>
+where
+    F: FnMut(Record) -> Result<(), E>,
+{
+    let (stage, rest) = stages[i..].split_first_mut().expect("stage to flush");
+    let mut flushed = stage.flush_records();
+    let mut output_count = 0;
+    loop {
+        let mut records: Vec<Record> = {
+            let _entered = spans[i].enter();
+            flushed.by_ref().take(block).collect()
+        };
+        if records.is_empty() {
+            return Ok(output_count);
+        }
+        push_block(&mut records, rest, &spans[i + 1..]);
+        output_count += records.len();
+        for record in records {
+            emit(record)?;
         }
-        current = next;
     }
-    current
 }
 
 /// Execute a pipeline in record-at-a-time mode.
@@ -31,21 +77,297 @@ fn push_through_stages(records: Vec<Record>, stages: &mut [Box<dyn RecordStage>]
 /// in order, with flush output propagated through downstream stages.
 pub fn execute_rat(input: Vec<Record>, stages: &mut [Box<dyn RecordStage>]) -> Vec<Record> {
     let mut output = Vec::new();
+    let Ok(_) = execute_rat_streaming(input.into_iter().map(Ok::<_, Infallible>), stages, |r| {
+        output.push(r);
+        Ok(())
+    });
+    output
+}
+
+/// Execute a pipeline in record-at-a-time mode, reading each input record
+/// only when the previous one has left the stage chain and handing each
+/// output record to `emit` as soon as it is produced.
+///
+/// Only records the stages themselves keep (e.g. `TAKE LAST`) are held in
+/// memory. Each record goes through the stages as a block of one, in a
+/// `Vec` reused from record to record, so stages that override
+/// [`RecordStage::process_block`] allocate nothing for it. Returns the
+/// number of input records, or the first error from `input` or `emit`,
+/// which stops the run.
+pub fn execute_rat_streaming<I, F, E>(
+    input: I,
+    stages: &mut [Box<dyn RecordStage>],
+    emit: F,
+) -> Result<usize, E>
+where
+    I: Iterator<Item = Result<Record, E>>,
+    F: FnMut(Record) -> Result<(), E>,
+{
+    execute_rat_checkpointed(input, stages, 0, emit, |_, _| Ok(()))
+}
+
+/// Execute a pipeline like [`execute_rat_streaming`], calling `checkpoint`
+/// with the stages and the number of input records read after every
+/// `every` input records (never, for 0).
+///
+/// By then those records and everything the stages emitted for them have
+/// gone to `emit`, so the stages' state and the count are a consistent
+/// point to restart from. An error from `checkpoint` stops the run.
+pub fn execute_rat_checkpointed<I, F, C, E>(
+    input: I,
+    stages: &mut [Box<dyn RecordStage>],
+    every: usize,
+    mut emit: F,
+    mut checkpoint: C,
+) -> Result<usize, E>
+where
+    I: Iterator<Item = Result<Record, E>>,
+    F: FnMut(Record) -> Result<(), E>,
+    C: FnMut(&[Box<dyn RecordStage>], usize) -> Result<(), E>,
+{
+    let run_span = RunSpan::enter("rat", stages.len());
+    let spans = stage_spans(stages);
+    let mut input_count = 0;
+    let mut output_count = 0;
+    let mut block = Vec::new();
 
     // Process each input record through the entire stage chain
     for record in input {
-        output.extend(push_through_stages(vec![record], stages));
+        let _record_span = RecordSpan::enter(input_count);
+        input_count += 1;
+        block.push(record?);
+        push_block(&mut block, stages, &spans);
+        output_count += block.len();
+        for r in block.drain(..) {
+            emit(r)?;
+        }
+        if every > 0 && input_count.is_multiple_of(every) {
+            checkpoint(stages, input_count)?;
+        }
     }
 
-    // Flush propagation: flush each stage and push output through remaining stages
+    // Flush propagation: flush each stage and push its output, a record
+    // at a time, through the remaining stages
     for i in 0..stages.len() {
-        let flush_output = stages[i].flush();
-        if !flush_output.is_empty() {
-            output.extend(push_through_stages(flush_output, &mut stages[i + 1..]));
+        output_count += flush_through(stages, &spans, i, 1, &mut emit)?;
+    }
+
+    run_span.finish(input_count, output_count);
+    Ok(input_count)
+}
+
+/// Push a block of records through a slice of stages, each stage taking
+/// the whole block before the next.
+fn push_block(records: &mut Vec<Record>, stages: &mut [Box<dyn RecordStage>], spans: &[StageSpan]) {
+    for (stage, span) in stages.iter_mut().zip(spans) {
+        if records.is_empty() {
+            break;
         }
+        let _entered = span.enter();
+        stage.process_block(records);
     }
+}
 
-    output
+/// Records the block executor reads and passes through the stages together.
+pub const BLOCK_SIZE: usize = 256;
+
+/// Execute a pipeline like [`execute_rat_streaming`], but reading up to
+/// `block` input records at a time and passing each block through every
+/// stage's [`RecordStage::process_block`] before reading the next.
+///
+/// Each stage sees the records in the same order, so the output is the
+/// same. Returns the number of input records, or the first error from
+/// `input` or `emit`; records read before an input error are still
+/// processed and emitted.
+pub fn execute_block_streaming<I, F, E>(
+    mut input: I,
+    stages: &mut [Box<dyn RecordStage>],
+    block: usize,
+    mut emit: F,
+) -> Result<usize, E>
+where
+    I: Iterator<Item = Result<Record, E>>,
+    F: FnMut(Record) -> Result<(), E>,
+{
+    let run_span = RunSpan::enter("block", stages.len());
+    let spans = stage_spans(stages);
+    let block = block.max(1);
+    let mut input_count = 0;
+    let mut output_count = 0;
+    let mut records = Vec::with_capacity(block);
+    loop {
+        let mut input_error = None;
+        for record in input.by_ref().take(block) {
+            match record {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    input_error = Some(e);
+                    break;
+                }
+            }
+        }
+        if records.is_empty() && input_error.is_none() {
+            break;
+        }
+        input_count += records.len();
+        push_block(&mut records, stages, &spans);
+        output_count += records.len();
+        for record in records.drain(..) {
+            emit(record)?;
+        }
+        if let Some(e) = input_error {
+            return Err(e);
+        }
+    }
+
+    // Flush propagation, as in record-at-a-time mode
+    for i in 0..stages.len() {
+        output_count += flush_through(stages, &spans, i, block, &mut emit)?;
+    }
+
+    run_span.finish(input_count, output_count);
+    Ok(input_count)
+}
+
+/// Records a channel between two threaded stages holds before the stage
+/// writing to it waits for the stage reading it.
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+/// Execute a pipeline with each stage on its own thread, reading input
+/// and handing output records to `emit` on the calling thread.
+///
+/// Stages are not `Send`, so each is built on its thread by the function
+/// in `stages` that makes it. A stage is flushed when the stage before it
+/// has flushed and finished, as in [`execute_rat_streaming`], and its
+/// output goes downstream in order, so the output is the same. Returns
+/// the number of input records, or the first error from `input` or
+/// `emit`, which stops the run: records already read still reach `emit`,
+/// but no stage is flushed.
+pub fn execute_threaded_streaming<I, B, F, E>(
+    input: I,
+    stages: Vec<B>,
+    mut emit: F,
+) -> Result<usize, E>
+where
+    I: Iterator<Item = Result<Record, E>>,
+    B: FnOnce() -> Box<dyn RecordStage> + Send,
+    F: FnMut(Record) -> Result<(), E>,
+{
+    let run_span = RunSpan::enter("threaded", stages.len());
+    let mut output_count = 0;
+    let mut emit = |record| {
+        output_count += 1;
+        emit(record)
+    };
+    let failed = AtomicBool::new(false);
+    let result = thread::scope(|scope| {
+        let failed = &failed;
+        let (input_tx, mut rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let count = stages.len();
+        for (idx, build) in stages.into_iter().enumerate() {
+            // The last stage never waits for the calling thread, which
+            // also has to send input; it drains output between sends
+            let (tx, next_rx) = if idx + 1 == count {
+                let (tx, rx) = mpsc::channel();
+                (Output::Unbounded(tx), rx)
+            } else {
+                let (tx, rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+                (Output::Bounded(tx), rx)
+            };
+            let stage_rx = std::mem::replace(&mut rx, next_rx);
+            scope.spawn(move || run_stage(build(), idx, stage_rx, tx, failed));
+        }
+        let input_tx = Output::Bounded(input_tx);
+
+        let mut input_count = 0;
+        let mut input_error = None;
+        for record in input {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    failed.store(true, Ordering::SeqCst);
+                    input_error = Some(e);
+                    break;
+                }
+            };
+            input_count += 1;
+            if !input_tx.send(record) {
+                break;
+            }
+            if let Err(e) = rx.try_iter().try_for_each(&mut emit) {
+                failed.store(true, Ordering::SeqCst);
+                return Err(e);
+            }
+        }
+        // Closing the first channel ends each stage in turn
+        drop(input_tx);
+        rx.iter().try_for_each(&mut emit)?;
+        match input_error {
+            Some(e) => Err(e),
+            None => Ok(input_count),
+        }
+    });
+    if let Ok(input_count) = result {
+        run_span.finish(input_count, output_count);
+    }
+    result
+}
+
+/// Where a threaded stage sends its output records.
+enum Output {
+    Bounded(SyncSender<Record>),
+    Unbounded(Sender<Record>),
+}
+
+impl Output {
+    /// Sends a record downstream, returning false if nothing reads it
+    /// any more.
+    fn send(&self, record: Record) -> bool {
+        match self {
+            Output::Bounded(tx) => tx.send(record).is_ok(),
+            Output::Unbounded(tx) => tx.send(record).is_ok(),
+        }
+    }
+}
+
+/// Runs threaded stage `idx` until its input ends, then flushes it unless
+/// the run has failed.
+///
+/// The stage's span is entered only while the stage works, not while it
+/// waits for input or for room downstream.
+fn run_stage(
+    mut stage: Box<dyn RecordStage>,
+    idx: usize,
+    input: Receiver<Record>,
+    output: Output,
+    failed: &AtomicBool,
+) {
+    let span = StageSpan::new(idx + 1, stage.name());
+    for record in input {
+        let records = {
+            let _entered = span.enter();
+            stage.process(record)
+        };
+        for r in records {
+            if !output.send(r) {
+                return;
+            }
+        }
+    }
+    if failed.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut records = stage.flush_records();
+    loop {
+        let record = {
+            let _entered = span.enter();
+            records.next()
+        };
+        let Some(r) = record else { return };
+        if !output.send(r) {
+            return;
+        }
+    }
 }
 
 /// Execute a pipeline in record-at-a-time mode with debug tracing.
@@ -55,54 +377,72 @@ pub fn execute_rat(input: Vec<Record>, stages: &mut [Box<dyn RecordStage>]) -> V
 pub fn execute_rat_traced(
     input: Vec<Record>,
     stages: &mut [Box<dyn RecordStage>],
+) -> (Vec<Record>, RatDebugTrace) {
+    execute_rat_traced_with(input, stages, &TraceOptions::new())
+}
+
+/// Execute a pipeline in record-at-a-time mode, tracing the records
+/// `options` selects.
+///
+/// Every record is processed and counted in `point_counts`; only selected
+/// records, and flush output while under the cap, keep their pipe points.
+pub fn execute_rat_traced_with(
+    input: Vec<Record>,
+    stages: &mut [Box<dyn RecordStage>],
+    options: &TraceOptions,
 ) -> (Vec<Record>, RatDebugTrace) {
     let stage_names: Vec<String> = stages.iter().map(|s| s.name().to_string()).collect();
     let num_stages = stages.len();
+    let mut point_counts = vec![0; num_stages + 1];
+    let mut left = options.limit().unwrap_or(usize::MAX);
+    let mut truncated = false;
     let mut output = Vec::new();
     let mut record_traces = Vec::new();
     let mut flush_traces = Vec::new();
 
     // Process each input record through the entire stage chain with tracing
-    for record in input {
-        let mut pipe_points: Vec<Vec<Record>> = Vec::with_capacity(num_stages + 1);
-        pipe_points.push(vec![record.clone()]);
-
-        let mut current = vec![record];
-        for stage in stages.iter_mut() {
-            let mut next = Vec::new();
-            for r in current {
-                next.extend(stage.process(r));
+    for (input_index, record) in input.into_iter().enumerate() {
+        let wanted = !truncated && options.selects(input_index, &record);
+        let budget = wanted.then_some(left);
+        let (current, captured) = run_traced(vec![record], stages, &mut point_counts, 0, budget);
+        output.extend(current);
+        match captured {
+            Some((pipe_points, drops)) => {
+                left -= pipe_points.iter().map(Vec::len).sum::<usize>();
+                record_traces.push(RecordTrace {
+                    input_index,
+                    pipe_points,
+                    drops,
+                });
             }
-            pipe_points.push(next.clone());
-            current = next;
+            None => truncated |= wanted,
         }
-
-        output.extend(current);
-        record_traces.push(RecordTrace { pipe_points });
     }
 
     // Flush propagation with tracing
     for i in 0..num_stages {
         let flush_output = stages[i].flush();
         if !flush_output.is_empty() {
-            let mut pipe_points: Vec<Vec<Record>> = Vec::new();
-            pipe_points.push(flush_output.clone());
-
-            let mut current = flush_output;
-            for stage in stages[i + 1..].iter_mut() {
-                let mut next = Vec::new();
-                for r in current {
-                    next.extend(stage.process(r));
+            let budget = (!truncated).then_some(left);
+            let (current, captured) = run_traced(
+                flush_output,
+                &mut stages[i + 1..],
+                &mut point_counts[i + 1..],
+                i + 1,
+                budget,
+            );
+            output.extend(current);
+            match captured {
+                Some((pipe_points, drops)) => {
+                    left -= pipe_points.iter().map(Vec::len).sum::<usize>();
+                    flush_traces.push(FlushTrace {
+                        stage_index: i,
+                        pipe_points,
+                        drops,
+                    });
                 }
-                pipe_points.push(next.clone());
-                current = next;
+                None => truncated = true,
             }
-
-            output.extend(current);
-            flush_traces.push(FlushTrace {
-                stage_index: i,
-                pipe_points,
-            });
         }
     }
 
@@ -110,16 +450,81 @@ pub fn execute_rat_traced(
         stage_names,
         record_traces,
         flush_traces,
+        point_counts,
+        truncated,
     };
 
     (output, trace)
 }
 
+/// The pipe points of a traced run, and the records dropped on the way.
+type Captured = (Vec<Vec<Record>>, Vec<DroppedRecord>);
+
+/// Runs records through a chain of stages, adding the records at each pipe
+/// point to `counts`. `first_point` is the pipe point the records start at.
+///
+/// With a budget, also returns the pipe points and the records stages
+/// explained dropping, or `None` when the pipe points hold more records
+/// than the budget allows.
+fn run_traced(
+    records: Vec<Record>,
+    stages: &mut [Box<dyn RecordStage>],
+    counts: &mut [usize],
+    first_point: usize,
+    budget: Option<usize>,
+) -> (Vec<Record>, Option<Captured>) {
+    let mut pipe_points = budget.map(|_| Vec::with_capacity(stages.len() + 1));
+    let mut drops = Vec::new();
+    let mut left = budget.unwrap_or(0);
+    counts[0] += records.len();
+    capture(&mut pipe_points, &mut left, &records);
+
+    let mut current = records;
+    for (offset, (stage, count)) in stages.iter_mut().zip(&mut counts[1..]).enumerate() {
+        let mut next = Vec::new();
+        for (index, r) in current.into_iter().enumerate() {
+            // Only a traced record is kept to ask the stage about
+            let traced = pipe_points.is_some().then(|| r.clone());
+            let output = stage.process(r);
+            if output.is_empty()
+                && let Some(record) = traced
+                && let Some(reason) = stage.drop_reason(&record)
+            {
+                drops.push(DroppedRecord {
+                    point: first_point + offset,
+                    index,
+                    reason,
+                });
+            }
+            next.extend(output);
+        }
+        *count += next.len();
+        capture(&mut pipe_points, &mut left, &next);
+        current = next;
+    }
+    (current, pipe_points.map(|pipe_points| (pipe_points, drops)))
+}
+
+/// Adds a pipe point's records to a capture, dropping the capture when they
+/// do not fit in the records `left`.
+fn capture(pipe_points: &mut Option<Vec<Vec<Record>>>, left: &mut usize, records: &[Record]) {
+    match pipe_points {
+        Some(points) if records.len() <= *left => {
+            *left -= records.len();
+            points.push(records.to_vec());
+        }
+        _ => *pipe_points = None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::record_stage::command_to_record_stage;
-    use pipelines_rs::{Command, execute_pipeline, parse_commands};
+    use crate::checkpoint::CheckpointOptions;
+    use crate::record_stage::{
+        SideOutputs, command_to_record_stage, command_to_record_stage_lrecl,
+    };
+    use pipelines_rs::{Command, HostVars, execute_pipeline, parse_commands, pipeline_lrecl};
     use std::fs;
     use std::path::Path;
 
@@ -132,6 +537,7 @@ mod tests {
     /// Helper: run RAT executor on a spec file and return trimmed output.
     fn run_rat(input: &str, pipeline: &str) -> String {
         let commands = parse_commands(pipeline).unwrap();
+        let lrecl = pipeline_lrecl(pipeline).unwrap();
         assert!(commands.len() >= 2);
 
         let first = &commands[0];
@@ -139,15 +545,17 @@ mod tests {
             Command::Console => input
                 .lines()
                 .filter(|line| !line.is_empty())
-                .map(Record::from_str)
+                .map(|line| Record::from_str_lrecl(line, lrecl))
                 .collect(),
-            Command::Literal { text } => vec![Record::from_str(text)],
+            Command::Literal { text } => vec![Record::from_str_lrecl(text, lrecl)],
             Command::Hole => vec![],
             _ => panic!("Unhandled source stage: {}", first.name()),
         };
 
-        let mut stages: Vec<Box<dyn RecordStage>> =
-            commands[1..].iter().map(command_to_record_stage).collect();
+        let mut stages: Vec<Box<dyn RecordStage>> = commands[1..]
+            .iter()
+            .map(|cmd| command_to_record_stage_lrecl(cmd, &HostVars::new(), lrecl))
+            .collect();
 
         let output_records = execute_rat(input_records, &mut stages);
         output_records
@@ -157,6 +565,44 @@ mod tests {
             .join("\n")
     }
 
+    /// Helper: run the threaded executor on a spec file and return trimmed
+    /// output.
+    fn run_threaded(input: &str, pipeline: &str) -> String {
+        let plan = pipelines_rs::PipelinePlan::parse(pipeline).unwrap();
+        let mut output = Vec::new();
+        crate::dsl::execute_plan_threaded_streaming(
+            &plan,
+            input.lines(),
+            &HostVars::new(),
+            &SideOutputs::new(),
+            |r| {
+                output.push(r.as_str().trim_end().to_string());
+                Ok(())
+            },
+        )
+        .unwrap();
+        output.join("\n")
+    }
+
+    /// Helper: run the block executor on a spec file and return trimmed
+    /// output.
+    fn run_block(input: &str, pipeline: &str) -> String {
+        let plan = pipelines_rs::PipelinePlan::parse(pipeline).unwrap();
+        let mut output = Vec::new();
+        crate::dsl::execute_plan_block_streaming(
+            &plan,
+            input.lines(),
+            &HostVars::new(),
+            &SideOutputs::new(),
+            |r| {
+                output.push(r.as_str().trim_end().to_string());
+                Ok(())
+            },
+        )
+        .unwrap();
+        output.join("\n")
+    }
+
     /// Assert RAT and batch executors produce identical output for a spec file.
     fn assert_equivalence(spec_name: &str) {
         let spec_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -172,6 +618,16 @@ mod tests {
             batch_output, rat_output,
             "RAT output differs from batch for {spec_name}"
         );
+        assert_eq!(
+            rat_output,
+            run_block(&input, &pipeline),
+            "block output differs from RAT for {spec_name}"
+        );
+        assert_eq!(
+            rat_output,
+            run_threaded(&input, &pipeline),
+            "threaded output differs from RAT for {spec_name}"
+        );
     }
 
     // --- Unit tests ---
@@ -206,6 +662,181 @@ mod tests {
         assert_eq!(output[0].as_str().trim(), "2");
     }
 
+    #[test]
+    fn test_streaming_emits_as_records_arrive() {
+        let input = ["A", "B", "C"].map(|t| Ok(Record::from_str(t)));
+        let mut stages: Vec<Box<dyn RecordStage>> = vec![
+            command_to_record_stage(&Command::Upper),
+            command_to_record_stage(&Command::Count),
+        ];
+        let mut output = Vec::new();
+        let count = execute_rat_streaming(input.into_iter(), &mut stages, |r| {
+            output.push(r);
+            Ok::<_, String>(())
+        });
+        assert_eq!(count, Ok(3));
+        assert_eq!(output[0].as_str().trim(), "3");
+
+        // An input error stops the run before later records are read
+        let input = vec![
+            Ok(Record::from_str("A")),
+            Err("bad line".to_string()),
+            Ok(Record::from_str("B")),
+        ];
+        let mut stages: Vec<Box<dyn RecordStage>> =
+            vec![command_to_record_stage(&Command::Console)];
+        let mut output = Vec::new();
+        let result = execute_rat_streaming(input.into_iter(), &mut stages, |r| {
+            output.push(r);
+            Ok(())
+        });
+        assert_eq!(result, Err("bad line".to_string()));
+        assert_eq!(output.len(), 1);
+    }
+
+    /// Subscriber counting the spans created, by name.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct SpanCounter(std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for SpanCounter {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut names = self.0.lock().unwrap();
+            names.push(span.metadata().name());
+            tracing::span::Id::from_u64(names.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_rat_spans() {
+        let counter = SpanCounter::default();
+        let input: Vec<Record> = (0..3).map(|n| Record::from_str(&n.to_string())).collect();
+        let mut stages: Vec<Box<dyn RecordStage>> = vec![
+            command_to_record_stage(&Command::Upper),
+            command_to_record_stage(&Command::Count),
+        ];
+        let output =
+            tracing::subscriber::with_default(counter.clone(), || execute_rat(input, &mut stages));
+        assert_eq!(output[0].as_str().trim(), "3");
+        let names = counter.0.lock().unwrap();
+        assert_eq!(names[..3], ["pipeline", "stage", "stage"]);
+        assert_eq!(names.iter().filter(|n| **n == "record").count(), 3);
+    }
+
+    #[test]
+    fn test_block_streaming() {
+        let records = || (0..10).map(|n| Record::from_str(&format!("{n:02}")));
+        let stages = || -> Vec<Box<dyn RecordStage>> {
+            vec![
+                command_to_record_stage(&Command::Locate {
+                    pattern: "1".to_string(),
+                    field: None,
+                }),
+                command_to_record_stage(&Command::Duplicate { n: 1 }),
+                command_to_record_stage(&Command::Literal {
+                    text: "END".to_string(),
+                }),
+            ]
+        };
+        let expected = execute_rat(records().collect(), &mut stages());
+        for block in [1, 3, 256] {
+            let mut output = Vec::new();
+            let count = execute_block_streaming(records().map(Ok), &mut stages(), block, |r| {
+                output.push(r);
+                Ok::<_, String>(())
+            });
+            assert_eq!(count, Ok(10));
+            assert_eq!(output, expected, "block of {block}");
+        }
+
+        // Records read before an input error still come out, unflushed
+        let input = vec![
+            Ok(Record::from_str("A")),
+            Err("bad line".to_string()),
+            Ok(Record::from_str("B")),
+        ];
+        let mut stages: Vec<Box<dyn RecordStage>> = vec![command_to_record_stage(&Command::Count)];
+        let mut output = Vec::new();
+        let result = execute_block_streaming(input.into_iter(), &mut stages, 8, |r| {
+            output.push(r);
+            Ok(())
+        });
+        assert_eq!(result, Err("bad line".to_string()));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_threaded_streaming() {
+        let input = (0..5000).map(|n| Ok(Record::from_str(&format!("{n:05}"))));
+        let stages = vec![
+            || command_to_record_stage(&Command::Upper),
+            || command_to_record_stage(&Command::Duplicate { n: 1 }),
+            || command_to_record_stage(&Command::TakeLast { n: 3 }),
+        ];
+        let mut output = Vec::new();
+        let count = execute_threaded_streaming(input, stages, |r| {
+            output.push(r);
+            Ok::<_, String>(())
+        });
+        assert_eq!(count, Ok(5000));
+        let output: Vec<&str> = output.iter().map(|r| r.as_str().trim()).collect();
+        assert_eq!(output, ["04998", "04999", "04999"]);
+
+        // An input error ends the run: records already read come out, but
+        // no stage is flushed
+        let input = vec![
+            Ok(Record::from_str("A")),
+            Err("bad line".to_string()),
+            Ok(Record::from_str("B")),
+        ];
+        let stages = vec![
+            Box::new(|| command_to_record_stage(&Command::Upper)) as Box<dyn FnOnce() -> _ + Send>,
+            Box::new(|| {
+                command_to_record_stage(&Command::Literal {
+                    text: "END".to_string(),
+                })
+            }),
+        ];
+        let mut output = Vec::new();
+        let result = execute_threaded_streaming(input.into_iter(), stages, |r| {
+            output.push(r);
+            Ok(())
+        });
+        assert_eq!(result, Err("bad line".to_string()));
+        assert_eq!(output.len(), 2);
+
+        // An emit error stops the stages
+        let input = (0..100_000).map(|_| Ok(Record::from_str("X")));
+        let stages = vec![|| command_to_record_stage(&Command::Console)];
+        let mut emitted = 0;
+        let result = execute_threaded_streaming(input, stages, |_| {
+            emitted += 1;
+            if emitted == 10 {
+                Err("disk full")
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err("disk full"));
+        assert_eq!(emitted, 10);
+    }
+
     #[test]
     fn test_literal_prepends() {
         let input = vec![Record::from_str("A"), Record::from_str("B")];
@@ -236,7 +867,7 @@ mod tests {
     fn test_duplicate_expansion() {
         let input = vec![Record::from_str("X")];
         let mut stages: Vec<Box<dyn RecordStage>> =
-            vec![command_to_record_stage(&Command::Duplicate { n: 3 })];
+            vec![command_to_record_stage(&Command::Duplicate { n: 2 })];
         let output = execute_rat(input, &mut stages);
         assert_eq!(output.len(), 3);
     }
@@ -271,6 +902,44 @@ mod tests {
         assert_eq!(trace.flush_traces[0].pipe_points[0].len(), 1);
     }
 
+    #[test]
+    fn test_traced_records_drop_reasons() {
+        let input = vec![
+            Record::from_str("SMITH   JOHN      SALES     00050000"),
+            Record::from_str("JONES   MARY      ENGINEER  00075000"),
+        ];
+        let mut stages: Vec<Box<dyn RecordStage>> = vec![
+            command_to_record_stage(&Command::Duplicate { n: 2 }),
+            command_to_record_stage(&Command::FilterEq {
+                pos: 18,
+                len: 10,
+                value: "SALES".to_string(),
+            }),
+            command_to_record_stage(&Command::Count),
+            command_to_record_stage(&Command::Skip { n: 1 }),
+        ];
+        let (output, trace) = execute_rat_traced(input, &mut stages);
+        assert!(output.is_empty());
+        assert!(trace.record_traces[0].drops.is_empty());
+        assert_eq!(
+            trace.record_traces[1].drops,
+            [0, 1, 2].map(|index| DroppedRecord {
+                point: 1,
+                index,
+                reason: r#"FILTER 18,10 = "SALES" (value was "ENGINEER")"#.to_string(),
+            })
+        );
+        // The COUNT record is dropped by SKIP during the flush
+        assert_eq!(
+            trace.flush_traces[0].drops,
+            [DroppedRecord {
+                point: 3,
+                index: 0,
+                reason: "SKIP 1 (skipped 1 of 1)".to_string(),
+            }]
+        );
+    }
+
     #[test]
     fn test_traced_equivalence() {
         let input = vec![
@@ -299,6 +968,43 @@ mod tests {
         assert_eq!(plain, traced);
     }
 
+    #[test]
+    fn test_traced_sampling_keeps_counts() {
+        let input: Vec<Record> = (0..10)
+            .map(|n| Record::from_str(&format!("{}{n}", if n < 5 { "A" } else { "B" })))
+            .collect();
+        let stages = || -> Vec<Box<dyn RecordStage>> {
+            vec![
+                command_to_record_stage(&Command::Locate {
+                    pattern: "A".to_string(),
+                    field: None,
+                }),
+                command_to_record_stage(&Command::Count),
+            ]
+        };
+
+        let options = TraceOptions::new()
+            .every(2)
+            .matching(|r| r.as_str().starts_with('A'));
+        let (output, trace) = execute_rat_traced_with(input.clone(), &mut stages(), &options);
+        assert_eq!(output[0].as_str().trim(), "5");
+        let traced: Vec<usize> = trace.record_traces.iter().map(|t| t.input_index).collect();
+        assert_eq!(traced, [0, 2, 4]);
+        assert_eq!(trace.point_counts, [10, 5, 1]);
+        assert_eq!(trace.untraced(), 7);
+        assert_eq!(trace.flush_traces.len(), 1);
+        assert!(!trace.truncated);
+
+        // Each record trace holds 2 records (input, after LOCATE)
+        let options = TraceOptions::new().max_records(5);
+        let (capped, trace) = execute_rat_traced_with(input, &mut stages(), &options);
+        assert_eq!(capped, output);
+        assert_eq!(trace.record_traces.len(), 2);
+        assert!(trace.flush_traces.is_empty());
+        assert!(trace.truncated);
+        assert_eq!(trace.point_counts, [10, 5, 1]);
+    }
+
     // --- Equivalence tests for all spec files ---
 
     macro_rules! equiv_test {
@@ -320,6 +1026,7 @@ mod tests {
     equiv_test!(equiv_filter_sales, "filter-sales.pipe");
     equiv_test!(equiv_literal_footer, "literal-footer.pipe");
     equiv_test!(equiv_literal_header_footer, "literal-header-footer.pipe");
+    equiv_test!(equiv_layout_sales_report, "layout-sales-report.pipe");
     equiv_test!(equiv_locate_errors, "locate-errors.pipe");
     equiv_test!(equiv_locate_field, "locate-field.pipe");
     equiv_test!(equiv_lower_case, "lower-case.pipe");
@@ -328,9 +1035,335 @@ mod tests {
     equiv_test!(equiv_multi_transform, "multi-transform.pipe");
     equiv_test!(equiv_nlocate_exclude, "nlocate-exclude.pipe");
     equiv_test!(equiv_non_marketing, "non-marketing.pipe");
+    equiv_test!(equiv_print_line_132, "print-line-132.pipe");
+    equiv_test!(equiv_profile_fields, "profile-fields.pipe");
     equiv_test!(equiv_reverse_text, "reverse-text.pipe");
     equiv_test!(equiv_sales_report, "sales-report.pipe");
     equiv_test!(equiv_skip_take_window, "skip-take-window.pipe");
+    equiv_test!(equiv_stamp_header, "stamp-header.pipe");
+    equiv_test!(equiv_sum_sales, "sum-sales.pipe");
     equiv_test!(equiv_top_five, "top-five.pipe");
     equiv_test!(equiv_upper_case, "upper-case.pipe");
+
+    #[test]
+    fn test_built_plan_runs_the_same_in_both_executors() {
+        let plan = pipelines_rs::PipelinePlan::new(Command::Console)
+            .stage(Command::Locate {
+                pattern: "SALES".to_string(),
+                field: None,
+            })
+            .stage(Command::Duplicate { n: 1 })
+            .stage(Command::Console);
+        let input = "SMITH   SALES\nJONES   ENGINEER";
+        let vars = HostVars::new();
+
+        let batch = pipelines_rs::execute_plan(&plan, input, &None, &vars).unwrap();
+        let rat = crate::dsl::execute_plan_rat(&plan, input, &vars).unwrap();
+        assert_eq!(rat, (batch.output, batch.input_count, batch.output_count));
+        assert_eq!(rat.2, 2);
+    }
+
+    #[test]
+    fn test_source_warnings_kept_in_side_outputs() {
+        let plan =
+            pipelines_rs::PipelinePlan::parse("LAYOUT A 0,2\nPIPE FROM CSV | CONSOLE").unwrap();
+        let vars = HostVars::new();
+        let batch = pipelines_rs::execute_plan(&plan, "ABCD\nAB", &None, &vars).unwrap();
+        let side = SideOutputs::new();
+        let counts =
+            crate::dsl::execute_plan_rat_streaming(&plan, "ABCD\nAB".lines(), &vars, &side, |_| {
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(counts, (2, 2));
+        assert_eq!(side.warnings(), batch.warnings);
+        assert_eq!(side.warnings().len(), 1);
+    }
+
+    #[test]
+    fn test_filter_error_records_kept_in_side_outputs() {
+        let input = "00012\n12X45\n     \n00030";
+        let vars = HostVars::new();
+        for pipeline in [
+            "PIPE CONSOLE | FILTER 0,5 NUM > 20 | CONSOLE",
+            "PIPE CONSOLE | FILTER 0,5 ZD > 0 | CONSOLE",
+            r#"PIPE CONSOLE | FILTER 0,1 = "0" OR 0,5 NUM > 20 | CONSOLE"#,
+            "PIPE CONSOLE | SPLIT A IF 0,5 NUM > 20 | CONSOLE",
+        ] {
+            let plan = pipelines_rs::PipelinePlan::parse(pipeline).unwrap();
+            let batch = pipelines_rs::execute_plan(&plan, input, &None, &vars).unwrap();
+            let rat = SideOutputs::new();
+            crate::dsl::execute_plan_rat_streaming(&plan, input.lines(), &vars, &rat, |_| Ok(()))
+                .unwrap();
+            assert_eq!(rat.error_records(), batch.error_records, "{pipeline}");
+            let block = SideOutputs::new();
+            crate::dsl::execute_plan_block_streaming(&plan, input.lines(), &vars, &block, |_| {
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(block.error_records(), batch.error_records, "{pipeline}");
+        }
+    }
+
+    /// Flushes `n` numbered records, counting in `pulled` how many the
+    /// executor has pulled.
+    struct LazyFlush {
+        n: usize,
+        pulled: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl RecordStage for LazyFlush {
+        fn process(&mut self, _record: Record) -> Vec<Record> {
+            vec![]
+        }
+
+        fn flush_records(&mut self) -> Box<dyn Iterator<Item = Record> + '_> {
+            let pulled = &self.pulled;
+            Box::new((0..self.n).map(move |n| {
+                pulled.set(n + 1);
+                Record::from_str(&n.to_string())
+            }))
+        }
+
+        fn name(&self) -> &str {
+            "LAZY"
+        }
+    }
+
+    #[test]
+    fn test_flush_streams_through_later_stages() {
+        let pulled = std::rc::Rc::new(std::cell::Cell::new(0));
+        let stages = || -> Vec<Box<dyn RecordStage>> {
+            vec![
+                Box::new(LazyFlush {
+                    n: 100,
+                    pulled: pulled.clone(),
+                }),
+                command_to_record_stage(&Command::Upper),
+            ]
+        };
+        // Each record leaves the last stage before the next is pulled
+        let mut emitted = 0;
+        execute_rat_streaming(
+            [Ok::<_, Infallible>(Record::from_str("A"))].into_iter(),
+            &mut stages(),
+            |_| {
+                emitted += 1;
+                assert_eq!(pulled.get(), emitted);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(emitted, 100);
+
+        // Or each block of them
+        let mut emitted: usize = 0;
+        execute_block_streaming(
+            std::iter::empty::<Result<Record, Infallible>>(),
+            &mut stages(),
+            10,
+            |_| {
+                emitted += 1;
+                assert_eq!(pulled.get(), emitted.div_ceil(10) * 10);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(emitted, 100);
+    }
+
+    #[test]
+    fn test_side_output_failure_fails_run() {
+        let plan = pipelines_rs::PipelinePlan::parse("PIPE CONSOLE | SORT | CONSOLE").unwrap();
+        let side = SideOutputs::new();
+        side.fail("SORT work file: disk full".to_string());
+        side.fail("later error".to_string());
+        let result = crate::dsl::execute_plan_rat_streaming(
+            &plan,
+            "B\nA".lines(),
+            &HostVars::new(),
+            &side,
+            |_| Ok(()),
+        );
+        assert_eq!(result.unwrap_err(), "SORT work file: disk full");
+
+        let side = SideOutputs::new();
+        let mut output = Vec::new();
+        crate::dsl::execute_plan_rat_streaming(
+            &plan,
+            "B\nA".lines(),
+            &HostVars::new(),
+            &side,
+            |r| {
+                output.push(r.as_str().trim_end().to_string());
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(output, ["A", "B"]);
+        assert_eq!(side.check(), Ok(()));
+    }
+
+    /// Logs each side record with the number of input lines read so far.
+    struct SideLog {
+        read: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        log: Vec<String>,
+    }
+
+    impl SideLog {
+        fn push(&mut self, path: &str, record: &Record) -> Result<(), String> {
+            if record.as_str().starts_with("BAD") {
+                return Err(format!("cannot write {path}"));
+            }
+            let read = self.read.load(std::sync::atomic::Ordering::SeqCst);
+            let text = record.as_str().trim_end();
+            self.log.push(format!("{path} {text} {read}"));
+            Ok(())
+        }
+    }
+
+    impl pipelines_rs::SideWriter for SideLog {
+        fn write_output(&mut self, name: &str, record: &Record) -> Result<(), String> {
+            self.push(&format!("SPLIT {name}"), record)
+        }
+
+        fn write_file(&mut self, path: &str, record: &Record) -> Result<(), String> {
+            self.push(path, record)
+        }
+    }
+
+    #[test]
+    fn test_side_writer_gets_records_as_read() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+
+        let plan = pipelines_rs::PipelinePlan::parse(
+            r#"PIPE CONSOLE | SPLIT A IF 0,2 = "ab" | > all.txt | CONSOLE"#,
+        )
+        .unwrap();
+        let read = Arc::new(AtomicUsize::new(0));
+        let writer = Arc::new(Mutex::new(SideLog {
+            read: Arc::clone(&read),
+            log: Vec::new(),
+        }));
+        let side = SideOutputs::new().with_writer(writer.clone());
+        let lines = ["b", "ab", "c"].into_iter().inspect(|_| {
+            read.fetch_add(1, Ordering::SeqCst);
+        });
+        crate::dsl::execute_plan_rat_streaming(&plan, lines, &HostVars::new(), &side, |_| Ok(()))
+            .unwrap();
+        assert_eq!(
+            writer.lock().unwrap().log,
+            ["all.txt b 1", "SPLIT A ab 2", "all.txt ab 2", "all.txt c 3"]
+        );
+        assert!(side.outputs().get("A").is_none_or(<[Record]>::is_empty));
+        assert!(side.files().get("all.txt").is_none_or(<[Record]>::is_empty));
+
+        // A failed write fails the run
+        let side = SideOutputs::new().with_writer(writer);
+        let result = crate::dsl::execute_plan_rat_streaming(
+            &plan,
+            ["ok", "BAD", "never"].into_iter(),
+            &HostVars::new(),
+            &side,
+            |_| Ok(()),
+        );
+        assert_eq!(result.unwrap_err(), "cannot write all.txt");
+    }
+
+    #[test]
+    fn test_cms_syntax_runs_the_same_in_both_executors() {
+        let text = "PIPE CONSOLE | SPECS 1-8 1 /lit/ NEXT 29-36 STRIP NW | CONSOLE";
+        let dsl = pipelines_rs::cms::Syntax::Auto.to_dsl(text).unwrap();
+        let input = "SMITH   JOHN      SALES     00050000\nJONES   MARY      ENGINEER  00075000";
+        let batch = pipelines_rs::execute_pipeline(input, &dsl).unwrap();
+        let rat = crate::dsl::execute_pipeline_rat(input, &dsl).unwrap();
+        assert_eq!(rat, batch);
+        assert_eq!(rat.0, "SMITH   lit 00050000\nJONES   lit 00075000");
+    }
+
+    #[test]
+    fn test_segments_run_only_in_batch_executor() {
+        let pipeline = "PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: | CONSOLE";
+        assert!(execute_pipeline("A\nB", pipeline).is_ok());
+        let error = crate::dsl::execute_pipeline_rat("A\nB", pipeline).unwrap_err();
+        assert!(error.contains("batched executor"));
+    }
+
+    #[test]
+    fn test_restart_from_checkpoint_matches_full_run() {
+        let plan = pipelines_rs::PipelinePlan::parse(
+            "PIPE CONSOLE | LITERAL HDR | SKIP 2 | TAKE 6 | STAMP 10,4 STEP | COUNT",
+        )
+        .unwrap();
+        let input: Vec<String> = (1..=10).map(|n| format!("R{n}")).collect();
+        let vars = HostVars::new();
+
+        let mut full = Vec::new();
+        let mut checkpoints = Vec::new();
+        let counts = crate::dsl::execute_plan_rat_checkpointed(
+            &plan,
+            input.iter(),
+            &vars,
+            &SideOutputs::new(),
+            CheckpointOptions {
+                every: 3,
+                restart: None,
+            },
+            |r| {
+                full.push(r.as_str().trim_end().to_string());
+                Ok(())
+            },
+            |checkpoint| {
+                checkpoints.push(checkpoint);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(counts, (10, full.len()));
+        assert_eq!(checkpoints.len(), 3);
+        assert_eq!(
+            checkpoints.iter().map(|c| c.records_in).collect::<Vec<_>>(),
+            [3, 6, 9]
+        );
+
+        for checkpoint in &checkpoints {
+            let mut rest = Vec::new();
+            let counts = crate::dsl::execute_plan_rat_checkpointed(
+                &plan,
+                input.iter(),
+                &vars,
+                &SideOutputs::new(),
+                CheckpointOptions {
+                    every: 0,
+                    restart: Some(checkpoint),
+                },
+                |r| {
+                    rest.push(r.as_str().trim_end().to_string());
+                    Ok(())
+                },
+                |_| Ok(()),
+            )
+            .unwrap();
+            assert_eq!(counts, (10, full.len()));
+            assert_eq!(rest, full[checkpoint.records_out..]);
+        }
+
+        let holding =
+            pipelines_rs::PipelinePlan::parse("PIPE CONSOLE | TAKE LAST 2 | CONSOLE").unwrap();
+        let err = crate::dsl::execute_plan_rat_checkpointed(
+            &holding,
+            input.iter(),
+            &vars,
+            &SideOutputs::new(),
+            CheckpointOptions {
+                every: 3,
+                restart: None,
+            },
+            |_| Ok(()),
+            |_| Ok(()),
+        )
+        .unwrap_err();
+        assert!(err.contains("cannot be restarted"), "{err}");
+    }
 }
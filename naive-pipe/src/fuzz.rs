@@ -0,0 +1,230 @@
+//! Fuzzing entry point for the DSL parser and the executors.
+//!
+//! [`fuzz_entry`] takes any pipeline text and input, and checks what must
+//! hold for all of them: parsing, diagnostics, and formatting never panic,
+//! formatting a formatted pipeline changes nothing, and a pipeline that
+//! parses gives the same result from the batch executor as from the
+//! record-at-a-time executor. The cargo-fuzz targets in `fuzz/` call it
+//! with the fuzzer's data:
+//!
+//! ```text
+//! cargo +nightly fuzz run execute
+//! ```
+//!
+//! A failed check panics, which the fuzzer reports as a crash.
+//!
+//! # Example
+//!
+//! ```
+//! use naive_pipe::fuzz::fuzz_entry;
+//!
+//! fuzz_entry("SMITH   SALES\nJONES   ENGINEER", "PIPE CONSOLE | LOCATE /SALES/ | CONSOLE");
+//! fuzz_entry("", "PIPE CONSOLE | FILTER");
+//! ```
+
+use std::time::UNIX_EPOCH;
+
+use pipelines_rs::diagnostic::diagnose;
+use pipelines_rs::format::format_pipeline;
+use pipelines_rs::{Command, HostVars, PipelinePlan, execute_plan};
+
+use crate::dsl::execute_plan_rat;
+
+/// Largest DUPLICATE count run, so that a fuzzed count cannot exhaust
+/// memory.
+const MAX_DUPLICATES: usize = 16;
+
+/// Checks the parser, diagnostics, formatter, and both executors on
+/// `pipeline` and `input`, panicking if any check fails.
+pub fn fuzz_entry(input: &str, pipeline: &str) {
+    diagnose(pipeline);
+    if let Ok(formatted) = format_pipeline(pipeline) {
+        assert_eq!(
+            format_pipeline(&formatted).as_ref(),
+            Ok(&formatted),
+            "formatting is not idempotent for {pipeline:?}"
+        );
+    }
+
+    let Ok(plan) = PipelinePlan::parse(pipeline) else {
+        return;
+    };
+    if !runnable(&plan) {
+        return;
+    }
+    let vars = HostVars::new().with_time(UNIX_EPOCH);
+    let batch = execute_plan(&plan, input, &None, &vars).map(|run| run.output);
+    let rat = execute_plan_rat(&plan, input, &vars).map(|(output, _, _)| output);
+    assert_eq!(
+        batch, rat,
+        "batch and RAT executors disagree on {pipeline:?} with input {input:?}"
+    );
+}
+
+/// Whether both executors can run the plan quickly: RAT has no segments,
+/// and DELAY and PLUGIN stages are slow or load code.
+fn runnable(plan: &PipelinePlan) -> bool {
+    plan.segments().is_empty()
+        && plan.commands().iter().all(|command| match command {
+            Command::Delay { .. } | Command::Plugin { .. } => false,
+            Command::Duplicate { n } => *n <= MAX_DUPLICATES,
+            _ => true,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipelines_rs::testing::Rng;
+
+    /// Stages to build fuzzed pipelines from, with their arguments.
+    const STAGES: &[&str] = &[
+        "FILTER 18,10 = \"SALES\"",
+        "FILTER 28,8 NUM >= \"50000\" AND 0,1 != \"X\"",
+        "LOCATE /SALES/",
+        "NLOCATE 0,8 /ON/",
+        "SELECT 0,8,0; 28,8,8",
+        "OVERLAY 0,4,10",
+        "CHANGE /A/b/ 2",
+        "XLATE 0,8 UPPER",
+        "TAKE 3",
+        "TAKE LAST 2",
+        "SKIP 1",
+        "DROP LAST 1",
+        "UNIQUE 18,10",
+        "SORT 18,10 D; 0,8",
+        "SUM 28,8",
+        "COUNT",
+        "REVERSE",
+        "DUPLICATE 2",
+        "PAD 40 *",
+        "TRUNC 12",
+        "SQUISH",
+        "RECNO 70-79",
+        "LITERAL  header  line",
+        "STAMP 60,8 DATE",
+        "SPECS 1-8 1 29-36 NW",
+        "BETWEEN /JONES/ /DOE/",
+        "SAMPLE 0.5 SEED 3",
+        "PROFILE 28,8 NUM",
+        "DUMP HEX",
+        "UPPER",
+        "LOWER",
+        "TO CSV HEADER",
+        "FROM CSV",
+        "REFORMAT DATE 0 YYYYMMDD TO MMDDYY 40",
+        "SPLIT X IF 0,1 = \"S\" BUILD 0,8,0",
+        "FANIN",
+        "f: FANINANY",
+        "a: TAKE 1",
+        "> out",
+        "SELECT A,0; B,10",
+        "FILTER B NUM > \"1\"",
+        "SORT A; B D SUM B",
+        "STAMP HEADER 0,9 /RUN STEP:/; 10,12 STEP",
+        "CHANGE 0,8 \"S\" \"ZZ\"",
+        "XLATE 0,80 a-z A-Z",
+        "MAX 28,8",
+        "AVG 28,8",
+    ];
+
+    /// Bytes that mutations insert: separators, quotes, digits, and text
+    /// outside ASCII.
+    const NOISE: &[&str] = &[
+        "|",
+        "?",
+        " ",
+        ",",
+        ";",
+        "\"",
+        "/",
+        "0",
+        "9",
+        "-",
+        ":",
+        "a:",
+        "#",
+        "\u{fb01}",
+        "\u{df}",
+        "\u{e9}",
+        "\t",
+        "99999999999999999999",
+    ];
+
+    fn input() -> String {
+        [
+            "SMITH   JOHN      SALES     00050000",
+            "JONES   MARY      ENGINEER  00075000",
+            "DOE     JANE      SALES     00060000",
+            "BROWN   BOB       MARKETING 0004X000",
+        ]
+        .join("\n")
+    }
+
+    /// Builds a pipeline from random stages, after an optional LRECL or
+    /// LAYOUT line, then mutates its text.
+    fn pipeline(rng: &mut Rng) -> String {
+        let mut text = String::from(
+            [
+                "",
+                "LRECL 40\n",
+                "LAYOUT A 0,8; B 28,8 NUMERIC\n",
+                "LRECL 132\nRC WARN 1\n",
+            ][rng.below(4) as usize],
+        );
+        text.push_str("PIPE CONSOLE");
+        for _ in 0..1 + rng.below(4) {
+            text.push_str("\n| ");
+            text.push_str(STAGES[rng.below(STAGES.len() as u64) as usize]);
+        }
+        text.push_str("\n| CONSOLE\n?");
+
+        let mut chars: Vec<String> = text.chars().map(String::from).collect();
+        for _ in 0..rng.below(9) {
+            if chars.is_empty() {
+                break;
+            }
+            let at = rng.below(chars.len() as u64) as usize;
+            match rng.below(3) {
+                0 => {
+                    chars.remove(at);
+                }
+                1 => chars.insert(
+                    at,
+                    NOISE[rng.below(NOISE.len() as u64) as usize].to_string(),
+                ),
+                _ => chars.truncate(at),
+            }
+        }
+        chars.concat()
+    }
+
+    #[test]
+    fn test_fuzz_entry_on_generated_pipelines() {
+        let mut rng = Rng::new(4089);
+        let input = input();
+        for _ in 0..3000 {
+            fuzz_entry(&input, &pipeline(&mut rng));
+        }
+    }
+
+    #[test]
+    fn test_fuzz_entry_edge_cases() {
+        for pipeline in [
+            "",
+            "?",
+            "|",
+            "PIPE",
+            "PIPE CONSOLE | FILTER",
+            "PIPE CONSOLE | FILTER 0,1",
+            "PIPE CONSOLE | \u{fb01}lter 0,1 = \"a\" | CONSOLE",
+            "PIPE CONSOLE | a: | CONSOLE",
+            "PIPE CONSOLE | SPLIT X IF\u{df} 0,1 | CONSOLE",
+            "LRECL 0",
+            "PIPE CONSOLE | DUPLICATE 999999999 | CONSOLE",
+        ] {
+            fuzz_entry(&input(), pipeline);
+            fuzz_entry("", pipeline);
+        }
+    }
+}
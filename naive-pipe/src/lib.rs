@@ -3,14 +3,34 @@
 //! This crate provides the record-at-a-time execution model for pipelines-rs.
 //! Each input record flows through the entire stage chain before the next
 //! record is read, contrasting with the batch executor which processes all
-//! records through one stage before moving to the next.
+//! records through one stage before moving to the next. The threaded
+//! executor runs the same stages with one thread each, and the block
+//! executor passes records through them in blocks.
 
+pub mod checkpoint;
 pub mod debug_trace;
+pub mod differential;
 pub mod dsl;
 pub mod executor;
+pub mod fuzz;
 pub mod record_stage;
+#[cfg(feature = "serve")]
+pub mod serve;
 
-pub use debug_trace::{FlushTrace, RatDebugTrace, RecordTrace};
-pub use dsl::{execute_pipeline_rat, execute_pipeline_rat_debug};
-pub use executor::{execute_rat, execute_rat_traced};
-pub use record_stage::{RecordStage, command_to_record_stage};
+pub use checkpoint::{Checkpoint, CheckpointOptions};
+pub use debug_trace::{
+    DroppedRecord, FlushTrace, RatDebugTrace, RecordTrace, TraceOptions, changed_columns,
+};
+pub use dsl::{
+    execute_pipeline_rat, execute_pipeline_rat_debug, execute_pipeline_rat_debug_with_vars,
+    execute_pipeline_rat_with_vars, execute_plan_block_streaming, execute_plan_rat,
+    execute_plan_rat_checkpointed, execute_plan_rat_debug, execute_plan_rat_debug_with,
+    execute_plan_rat_streaming, execute_plan_rat_traced, execute_plan_threaded_streaming,
+};
+pub use executor::{
+    BLOCK_SIZE, CHANNEL_CAPACITY, execute_block_streaming, execute_rat, execute_rat_checkpointed,
+    execute_rat_streaming, execute_rat_traced, execute_rat_traced_with, execute_threaded_streaming,
+};
+pub use record_stage::{
+    RecordStage, SideOutputs, command_to_record_stage, command_to_record_stage_with_vars,
+};
@@ -4,8 +4,17 @@
 //! output records. This enables the record-at-a-time (RAT) executor to show
 //! individual record flow through the pipeline.
 
-use pipelines_rs::Command;
-use pipelines_rs::Record;
+use pipelines_rs::CompareOp;
+use pipelines_rs::{
+    Between, Change, Command, Condition, Console, DropLast, Dump, Duplicate, Literal, Overlay, Pad,
+    Recno, Sample, Squish, TakeLast, Trunc,
+};
+use pipelines_rs::{ErrorPolicy, Stage};
+use pipelines_rs::{HostVars, StampValue};
+use pipelines_rs::{RECORD_WIDTH, Record, SideWriter, SplitOutputs};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
 /// A pipeline stage that processes records one at a time.
 ///
@@ -24,20 +33,102 @@ pub trait RecordStage {
         vec![]
     }
 
+    /// Flushes the stage like `flush`, returning its records as they are
+    /// made rather than all at once.
+    ///
+    /// The default returns `flush`'s records. SORT overrides it to merge
+    /// its sorted runs as the records are pulled.
+    fn flush_records(&mut self) -> Box<dyn Iterator<Item = Record> + '_> {
+        Box::new(self.flush().into_iter())
+    }
+
+    /// Process a block of input records in place, replacing them with the
+    /// stage's output for them, in order.
+    ///
+    /// The default calls `process` for each record. Filters and other
+    /// simple stages override it to work through the block in one call,
+    /// saving a dynamic call and an output `Vec` per record.
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        let input = std::mem::replace(records, Vec::with_capacity(records.len()));
+        for record in input {
+            records.extend(self.process(record));
+        }
+    }
+
+    /// Returns the state a checkpoint saves for this stage, a single word
+    /// (no whitespace), or `None` if it holds state that cannot be saved,
+    /// so a run through it cannot be restarted.
+    ///
+    /// The default, for stages that keep nothing from one record to the
+    /// next, is empty state.
+    fn checkpoint(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    /// Restores the state `checkpoint` saved, on a newly built stage.
+    ///
+    /// The default accepts the empty state of a stage that keeps none.
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("{} has no checkpoint state", self.name()))
+        }
+    }
+
+    /// Explains why the stage wrote nothing for `record`, which `process`
+    /// has just dropped: the stage as written and what failed its test,
+    /// such as `FILTER 18,10 = "SALES" (value was "MARKETING")`. The traced
+    /// executor keeps the explanation with the record's trace.
+    ///
+    /// The default, for stages that hold records or drop them for no
+    /// reason beyond their name, is `None`.
+    fn drop_reason(&self, _record: &Record) -> Option<String> {
+        None
+    }
+
     /// The display name of this stage.
     fn name(&self) -> &str;
 }
 
+/// Parses the checkpoint state of stage `name`.
+fn parse_state<T: std::str::FromStr>(name: &str, state: &str) -> Result<T, String> {
+    state
+        .parse()
+        .map_err(|_| format!("invalid {name} checkpoint state '{state}'"))
+}
+
 // ---------------------------------------------------------------------------
 // Stage implementations
 // ---------------------------------------------------------------------------
 
-/// CONSOLE - passes records through unchanged.
-pub struct ConsoleStage;
+/// CONSOLE - passes records through unchanged, echoing them to stderr in
+/// the middle of a pipeline.
+pub struct ConsoleStage {
+    echo: bool,
+}
+
+impl ConsoleStage {
+    /// The last CONSOLE of a pipeline, which is the output and echoes
+    /// nothing.
+    pub fn output() -> Self {
+        Self { echo: false }
+    }
+}
 
 impl RecordStage for ConsoleStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        vec![record]
+        if self.echo {
+            Console::new().process(record).into_iter().collect()
+        } else {
+            vec![record]
+        }
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        if self.echo {
+            Console::new().process_block(records);
+        }
     }
 
     fn name(&self) -> &str {
@@ -61,6 +152,20 @@ impl RecordStage for FilterEqStage {
         }
     }
 
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| record.field_eq(self.pos, self.len, &self.value));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::FilterEq {
+            pos: self.pos,
+            len: self.len,
+            value: self.value.clone(),
+        };
+        let condition = Condition::text(self.pos, self.len, CompareOp::Eq, &self.value);
+        filter_drop_reason(&command, &condition, record)
+    }
+
     fn name(&self) -> &str {
         "FILTER"
     }
@@ -82,23 +187,152 @@ impl RecordStage for FilterNeStage {
         }
     }
 
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| !record.field_eq(self.pos, self.len, &self.value));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::FilterNe {
+            pos: self.pos,
+            len: self.len,
+            value: self.value.clone(),
+        };
+        let condition = Condition::text(self.pos, self.len, CompareOp::Ne, &self.value);
+        filter_drop_reason(&command, &condition, record)
+    }
+
     fn name(&self) -> &str {
         "FILTER"
     }
 }
 
+/// FILTER pos,len ZD op n - keeps records whose zoned decimal field compares true.
+///
+/// Records whose field is not a number are error records, counted in `side`.
+pub struct FilterZonedStage {
+    pos: usize,
+    len: usize,
+    op: CompareOp,
+    value: i64,
+    side: SideOutputs,
+}
+
+impl FilterZonedStage {
+    fn keeps(&self, record: &Record) -> bool {
+        match record.field_zoned(self.pos, self.len) {
+            Some(field) => self.op.compare(field, self.value),
+            None => {
+                self.side.add_error_record();
+                false
+            }
+        }
+    }
+}
+
+impl RecordStage for FilterZonedStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        if self.keeps(&record) {
+            vec![record]
+        } else {
+            vec![]
+        }
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| self.keeps(record));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::FilterZoned {
+            pos: self.pos,
+            len: self.len,
+            op: self.op,
+            value: self.value,
+        };
+        let condition = Condition::zoned(self.pos, self.len, self.op, self.value);
+        filter_drop_reason(&command, &condition, record)
+    }
+
+    fn name(&self) -> &str {
+        "FILTER"
+    }
+}
+
+/// FILTER condition - keeps records meeting a compound or ordered condition.
+///
+/// Records with a field it compares as a number that is not one are error
+/// records, counted in `side`.
+pub struct FilterExprStage {
+    condition: Condition,
+    side: SideOutputs,
+}
+
+impl FilterExprStage {
+    fn keeps(&self, record: &Record) -> bool {
+        if self.condition.has_bad_number(record) {
+            self.side.add_error_record();
+        }
+        self.condition.matches(record)
+    }
+}
+
+impl RecordStage for FilterExprStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        if self.keeps(&record) {
+            vec![record]
+        } else {
+            vec![]
+        }
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| self.keeps(record));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::FilterExpr {
+            condition: self.condition.clone(),
+        };
+        filter_drop_reason(&command, &self.condition, record)
+    }
+
+    fn name(&self) -> &str {
+        "FILTER"
+    }
+}
+
+/// Explains a FILTER drop: the stage as written and the field values that
+/// failed its condition.
+fn filter_drop_reason(command: &Command, condition: &Condition, record: &Record) -> Option<String> {
+    condition
+        .explain_failure(record)
+        .map(|why| format!("{command} ({why})"))
+}
+
 /// SELECT - extracts and repositions fields.
 pub struct SelectStage {
     fields: Vec<(usize, usize, usize)>,
 }
 
-impl RecordStage for SelectStage {
-    fn process(&mut self, record: Record) -> Vec<Record> {
-        let mut output = Record::new();
+impl SelectStage {
+    fn select(&self, record: &Record) -> Record {
+        let mut output = Record::blank(record.lrecl());
         for &(src, len, dest) in &self.fields {
             output.set_field(dest, len, record.field(src, len));
         }
-        vec![output]
+        output
+    }
+}
+
+impl RecordStage for SelectStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        vec![self.select(&record)]
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        for record in records.iter_mut() {
+            *record = self.select(record);
+        }
     }
 
     fn name(&self) -> &str {
@@ -122,6 +356,19 @@ impl RecordStage for TakeStage {
         }
     }
 
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.seen.to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.seen = parse_state("TAKE", state)?;
+        Ok(())
+    }
+
+    fn drop_reason(&self, _record: &Record) -> Option<String> {
+        Some(format!("TAKE {} (already took {})", self.n, self.seen))
+    }
+
     fn name(&self) -> &str {
         "TAKE"
     }
@@ -143,11 +390,175 @@ impl RecordStage for SkipStage {
         }
     }
 
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.seen.to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.seen = parse_state("SKIP", state)?;
+        Ok(())
+    }
+
+    fn drop_reason(&self, _record: &Record) -> Option<String> {
+        Some(format!(
+            "SKIP {} (skipped {} of {})",
+            self.n, self.seen, self.n
+        ))
+    }
+
     fn name(&self) -> &str {
         "SKIP"
     }
 }
 
+/// BETWEEN - keeps sections from a start marker to an end marker.
+pub struct BetweenStage {
+    between: Between,
+}
+
+impl RecordStage for BetweenStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.between.process(record).into_iter().collect()
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.between.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "BETWEEN"
+    }
+}
+
+/// OVERLAY, PAD, TRUNC, SQUISH, XLATE, REFORMAT DATE, DUMP, TO CSV - reshape
+/// (or just show) each record with a library stage.
+pub struct ShapeStage<S: Stage> {
+    stage: S,
+    name: &'static str,
+}
+
+impl<S: Stage> RecordStage for ShapeStage<S> {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let mut output = Vec::new();
+        self.stage.process_into(record, &mut output);
+        output
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        self.stage.process_block(records);
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.stage.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        // DUMP numbers its records and TO CSV writes its header once
+        match self.name {
+            "DUMP" | "TO CSV" => None,
+            _ => Some(String::new()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+/// RECNO - keeps records by their position in the input.
+pub struct RecnoStage {
+    recno: Recno,
+}
+
+impl RecordStage for RecnoStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.recno.process(record).into_iter().collect()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.recno.seen().to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.recno.set_seen(parse_state("RECNO", state)?);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "RECNO"
+    }
+}
+
+/// SAMPLE - keeps a reproducible random fraction of the records.
+pub struct SampleStage {
+    sample: Sample,
+}
+
+impl RecordStage for SampleStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.sample.process(record).into_iter().collect()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "SAMPLE"
+    }
+}
+
+/// TAKE LAST n - holds the last n records, emitting them at end of input.
+pub struct TakeLastStage {
+    take: TakeLast,
+}
+
+impl RecordStage for TakeLastStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.take.process(record).into_iter().collect()
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.take.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "TAKE"
+    }
+}
+
+/// DROP LAST n - passes each record once n more have arrived, dropping
+/// the last n.
+pub struct DropLastStage {
+    drop: DropLast,
+}
+
+impl RecordStage for DropLastStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.drop.process(record).into_iter().collect()
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.drop.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "DROP"
+    }
+}
+
 /// LOCATE - keeps records containing a pattern.
 pub struct LocateStage {
     pattern: String,
@@ -156,11 +567,28 @@ pub struct LocateStage {
 
 impl RecordStage for LocateStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        let matches = match self.field {
-            Some((pos, len)) => record.field_contains(pos, len, &self.pattern),
-            None => record.as_str().contains(self.pattern.as_str()),
+        if locates(&record, &self.pattern, self.field) {
+            vec![record]
+        } else {
+            vec![]
+        }
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| locates(record, &self.pattern, self.field));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::Locate {
+            pattern: self.pattern.clone(),
+            field: self.field,
         };
-        if matches { vec![record] } else { vec![] }
+        Some(locate_drop_reason(
+            &command,
+            record,
+            self.field,
+            "not found",
+        ))
     }
 
     fn name(&self) -> &str {
@@ -176,11 +604,23 @@ pub struct NlocateStage {
 
 impl RecordStage for NlocateStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        let matches = match self.field {
-            Some((pos, len)) => record.field_contains(pos, len, &self.pattern),
-            None => record.as_str().contains(self.pattern.as_str()),
+        if locates(&record, &self.pattern, self.field) {
+            vec![]
+        } else {
+            vec![record]
+        }
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        records.retain(|record| !locates(record, &self.pattern, self.field));
+    }
+
+    fn drop_reason(&self, record: &Record) -> Option<String> {
+        let command = Command::Nlocate {
+            pattern: self.pattern.clone(),
+            field: self.field,
         };
-        if matches { vec![] } else { vec![record] }
+        Some(locate_drop_reason(&command, record, self.field, "found"))
     }
 
     fn name(&self) -> &str {
@@ -188,9 +628,35 @@ impl RecordStage for NlocateStage {
     }
 }
 
+/// True if the record, or the field if given, contains `pattern`.
+fn locates(record: &Record, pattern: &str, field: Option<(usize, usize)>) -> bool {
+    match field {
+        Some((pos, len)) => record.field_contains(pos, len, pattern),
+        None => record.as_str().contains(pattern),
+    }
+}
+
+/// Explains a LOCATE or NLOCATE drop: the stage as written and the field
+/// searched, or `outcome` when it searches the whole record.
+fn locate_drop_reason(
+    command: &Command,
+    record: &Record,
+    field: Option<(usize, usize)>,
+    outcome: &str,
+) -> String {
+    match field {
+        Some((pos, len)) => format!(
+            "{command} (value was \"{}\")",
+            record.field(pos, len).trim()
+        ),
+        None => format!("{command} ({outcome} in the record)"),
+    }
+}
+
 /// COUNT - counts records and emits summary on flush.
 pub struct CountStage {
     count: usize,
+    lrecl: usize,
 }
 
 impl RecordStage for CountStage {
@@ -199,8 +665,22 @@ impl RecordStage for CountStage {
         vec![]
     }
 
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        self.count += records.len();
+        records.clear();
+    }
+
     fn flush(&mut self) -> Vec<Record> {
-        vec![Record::from_str(&self.count.to_string())]
+        vec![Record::from_str_lrecl(&self.count.to_string(), self.lrecl)]
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.count.to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.count = parse_state("COUNT", state)?;
+        Ok(())
     }
 
     fn name(&self) -> &str {
@@ -208,16 +688,37 @@ impl RecordStage for CountStage {
     }
 }
 
-/// CHANGE "old" "new" - replaces occurrences in each record.
+/// SUM|MIN|MAX|AVG pos,len - emits one summary record at end of input.
+pub struct StatisticStage {
+    total: pipelines_rs::Total,
+}
+
+impl RecordStage for StatisticStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.total.process(record).into_iter().collect()
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.total.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        self.total.aggregate().kind().name()
+    }
+}
+
+/// CHANGE [pos,len] /old/new/ [n] - replaces occurrences in each record.
 pub struct ChangeStage {
-    old: String,
-    new: String,
+    change: Change,
 }
 
 impl RecordStage for ChangeStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        let content = record.as_str().replace(&self.old, &self.new);
-        vec![Record::from_str(&content)]
+        vec![self.change.apply(&record)]
     }
 
     fn name(&self) -> &str {
@@ -225,32 +726,57 @@ impl RecordStage for ChangeStage {
     }
 }
 
+/// SPECS input [conversion] output ... - builds each record from fields,
+/// literals, and the record number.
+pub struct SpecsStage {
+    specs: pipelines_rs::Specs,
+}
+
+impl RecordStage for SpecsStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.specs.process(record).into_iter().collect()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.specs.records().to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.specs.set_records(parse_state("SPECS", state)?);
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "SPECS"
+    }
+}
+
 /// LITERAL "text" - emits a literal record before the first input record.
 ///
 /// On `flush()`, emits the literal if no input records were received
 /// (matching batch behavior where LITERAL prepends to an empty stream).
 pub struct LiteralStage {
-    text: String,
-    emitted: bool,
+    literal: Literal,
 }
 
 impl RecordStage for LiteralStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        if !self.emitted {
-            self.emitted = true;
-            vec![Record::from_str(&self.text), record]
-        } else {
-            vec![record]
-        }
+        let mut output = Vec::new();
+        self.literal.process_into(record, &mut output);
+        output
     }
 
     fn flush(&mut self) -> Vec<Record> {
-        if !self.emitted {
-            self.emitted = true;
-            vec![Record::from_str(&self.text)]
-        } else {
-            vec![]
-        }
+        self.literal.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.literal.is_written().to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.literal.set_written(parse_state("LITERAL", state)?);
+        Ok(())
     }
 
     fn name(&self) -> &str {
@@ -263,7 +789,10 @@ pub struct UpperStage;
 
 impl RecordStage for UpperStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        vec![Record::from_str(&record.as_str().to_uppercase())]
+        vec![Record::from_str_lrecl(
+            &record.as_str().to_uppercase(),
+            record.lrecl(),
+        )]
     }
 
     fn name(&self) -> &str {
@@ -276,7 +805,10 @@ pub struct LowerStage;
 
 impl RecordStage for LowerStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        vec![Record::from_str(&record.as_str().to_lowercase())]
+        vec![Record::from_str_lrecl(
+            &record.as_str().to_lowercase(),
+            record.lrecl(),
+        )]
     }
 
     fn name(&self) -> &str {
@@ -284,45 +816,493 @@ impl RecordStage for LowerStage {
     }
 }
 
-/// REVERSE - reverses characters in each record.
-pub struct ReverseStage;
+/// REVERSE - reverses characters in each record.
+pub struct ReverseStage;
+
+impl RecordStage for ReverseStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let reversed: String = record.as_str().trim_end().chars().rev().collect();
+        vec![Record::from_str_lrecl(&reversed, record.lrecl())]
+    }
+
+    fn name(&self) -> &str {
+        "REVERSE"
+    }
+}
+
+/// DUPLICATE [n] - writes each record and n more copies.
+pub struct DuplicateStage {
+    duplicate: Duplicate,
+}
+
+impl RecordStage for DuplicateStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let mut output = Vec::new();
+        self.duplicate.process_into(record, &mut output);
+        output
+    }
+
+    fn name(&self) -> &str {
+        "DUPLICATE"
+    }
+}
+
+/// UNIQUE [pos,len] - keeps the first record with each key.
+pub struct UniqueStage {
+    dedup: pipelines_rs::Dedup,
+}
+
+impl RecordStage for UniqueStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.dedup.process(record).into_iter().collect()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "UNIQUE"
+    }
+}
+
+/// SORT - takes every record, emitting them sorted (and summed) on flush.
+///
+/// Past [`SORT_MEMORY`](pipelines_rs::SORT_MEMORY) the records spill to
+/// sorted runs in temporary files, merged as the flushed records are
+/// pulled. A work file that cannot be written or read fails the run
+/// through the run's [`SideOutputs`].
+pub struct SortStage {
+    sort: pipelines_rs::Sort,
+    sum: Option<Vec<pipelines_rs::SumField>>,
+    side: SideOutputs,
+}
+
+impl RecordStage for SortStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        if let Err(e) = self.sort.push(record) {
+            self.side.fail(format!("SORT work file: {e}"));
+        }
+        vec![]
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.flush_records().collect()
+    }
+
+    fn flush_records(&mut self) -> Box<dyn Iterator<Item = Record> + '_> {
+        let side = &self.side;
+        pipelines_rs::dsl::sorted_records(&mut self.sort, self.sum.as_deref(), |e| side.fail(e))
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "SORT"
+    }
+}
+
+/// HOLE - discards all input, outputs nothing.
+pub struct HoleStage;
+
+impl RecordStage for HoleStage {
+    fn process(&mut self, _record: Record) -> Vec<Record> {
+        vec![]
+    }
+
+    fn name(&self) -> &str {
+        "HOLE"
+    }
+}
+
+/// DELAY n ms - pauses before passing each record.
+///
+/// The wasm debugger cannot sleep; it reads the delay from the command
+/// instead and uses it to pace its replay animation.
+pub struct DelayStage {
+    inner: pipelines_rs::ThrottleStage,
+}
+
+impl RecordStage for DelayStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.inner.process(record).into_iter().collect()
+    }
+
+    fn name(&self) -> &str {
+        "DELAY"
+    }
+}
+
+/// PROFILE - accumulates per-field statistics and emits a report on flush.
+pub struct ProfileStage {
+    inner: pipelines_rs::ProfileStage,
+}
+
+impl RecordStage for ProfileStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        self.inner.process(record);
+        vec![]
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.inner.flush()
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "PROFILE"
+    }
+}
+
+/// STAMP - writes host variables into each record, or (with HEADER) emits
+/// one header record before the first input record.
+///
+/// Like LITERAL, a header is emitted on `flush()` if no input was received.
+pub struct StampStage {
+    vars: HostVars,
+    header: bool,
+    fields: Vec<(usize, usize, StampValue)>,
+    lrecl: usize,
+    emitted: bool,
+}
+
+impl RecordStage for StampStage {
+    fn process(&mut self, mut record: Record) -> Vec<Record> {
+        if !self.header {
+            self.vars.stamp(&mut record, &self.fields);
+            vec![record]
+        } else if !self.emitted {
+            self.emitted = true;
+            vec![self.vars.header(&self.fields, self.lrecl), record]
+        } else {
+            vec![record]
+        }
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        if self.header && !self.emitted {
+            self.emitted = true;
+            vec![self.vars.header(&self.fields, self.lrecl)]
+        } else {
+            vec![]
+        }
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        Some(self.emitted.to_string())
+    }
+
+    fn restore(&mut self, state: &str) -> Result<(), String> {
+        self.emitted = parse_state("STAMP", state)?;
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "STAMP"
+    }
+}
+
+/// PLUGIN - a stage provided at runtime through `pipelines_rs::plugin`.
+pub struct PluginStage {
+    inner: pipelines_rs::plugin::PluginStage,
+}
+
+impl RecordStage for PluginStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let mut output = Vec::new();
+        self.inner.process_into(record, &mut output);
+        output
+    }
+
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "PLUGIN"
+    }
+}
+
+/// ENCRYPT / DECRYPT - seals or opens a field (see `pipelines_rs::crypto`).
+pub struct CryptStage {
+    inner: pipelines_rs::crypto::CryptStage,
+    name: &'static str,
+}
+
+impl RecordStage for CryptStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let mut output = Vec::new();
+        self.inner.process_into(record, &mut output);
+        output
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+}
+
+/// CONVERT - passes records through; the input or output file is
+/// converted from or to EBCDIC where it is read or written.
+pub struct ConvertStage;
+
+impl RecordStage for ConvertStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        vec![record]
+    }
+
+    fn name(&self) -> &str {
+        "CONVERT"
+    }
+}
+
+/// SPLIT - passes records through, copying those meeting the condition,
+/// rebuilt by BUILD, to the named output in the run's [`SideOutputs`].
+pub struct SplitStage {
+    name: String,
+    condition: Option<Box<dyn RecordStage>>,
+    build: Option<SelectStage>,
+    side: SideOutputs,
+}
+
+impl RecordStage for SplitStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        let mut copies = match &mut self.condition {
+            Some(condition) => condition.process(record.clone()),
+            None => vec![record.clone()],
+        };
+        if let Some(build) = &self.build {
+            copies = copies.iter().map(|copy| build.select(copy)).collect();
+        }
+        self.side.append_output(&self.name, copies);
+        vec![record]
+    }
+
+    /// A restarted run would lose the copies made before the checkpoint.
+    fn checkpoint(&self) -> Option<String> {
+        None
+    }
+
+    fn name(&self) -> &str {
+        "SPLIT"
+    }
+}
+
+/// The records a run writes besides its output: the named outputs of SPLIT
+/// stages and the files of `>` and `>>` stages, with the warnings its
+/// source reports and the first error that fails the run part way. The
+/// stages that write them share one handle, so any clone sees every record
+/// written.
+///
+/// With a [`SideWriter`] (see [`with_writer`](Self::with_writer)) each
+/// record goes to the writer as the stage passes it on; otherwise the
+/// records are kept for the host to read once the run ends.
+#[derive(Clone, Default)]
+pub struct SideOutputs {
+    outputs: Arc<Mutex<SplitOutputs>>,
+    files: Arc<Mutex<SplitOutputs>>,
+    warnings: Arc<Mutex<Vec<String>>>,
+    error_records: Arc<AtomicUsize>,
+    failure: Arc<Mutex<Option<String>>>,
+    writer: Option<Arc<Mutex<dyn SideWriter + Send>>>,
+}
+
+impl fmt::Debug for SideOutputs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SideOutputs")
+            .field("outputs", &self.outputs)
+            .field("files", &self.files)
+            .field("warnings", &self.warnings)
+            .field("error_records", &self.error_records)
+            .field("failure", &self.failure)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SideOutputs {
+    /// Creates empty side outputs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes SPLIT outputs and `>` and `>>` files through `writer` as
+    /// records pass, instead of keeping them; the first write that fails
+    /// fails the run.
+    #[must_use]
+    pub fn with_writer(mut self, writer: Arc<Mutex<dyn SideWriter + Send>>) -> Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Records copied by SPLIT stages, by output name, in order of the
+    /// stages.
+    pub fn outputs(&self) -> SplitOutputs {
+        self.outputs
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Records written by `>` and `>>` stages, by path, in order of the
+    /// stages.
+    pub fn files(&self) -> SplitOutputs {
+        self.files
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Warnings from the run's source, such as `FROM CSV` values cut to
+    /// fit their fields.
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Adds a warning, for [`warnings`](Self::warnings).
+    pub fn warn(&self, warning: String) {
+        self.warnings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(warning);
+    }
+
+    /// Records the stages rejected as errors, such as FILTER records whose
+    /// field is not a number.
+    pub fn error_records(&self) -> usize {
+        self.error_records.load(Ordering::Relaxed)
+    }
+
+    /// Counts an error record, for [`error_records`](Self::error_records).
+    pub fn add_error_record(&self) {
+        self.error_records.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Fails the run with `error` unless it has already failed: a stage
+    /// cannot return an error, so the executor reports it through
+    /// [`check`](Self::check) when the run ends.
+    pub fn fail(&self, error: String) {
+        self.failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_or_insert(error);
+    }
+
+    /// Returns the first error passed to [`fail`](Self::fail), if any.
+    pub fn check(&self) -> Result<(), String> {
+        match self
+            .failure
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn append_output(&self, name: &str, records: impl IntoIterator<Item = Record>) {
+        match &self.writer {
+            Some(writer) => self.write(writer, records, |w, r| w.write_output(name, r)),
+            None => self
+                .outputs
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .append(name, records),
+        }
+    }
+
+    fn append_file(&self, path: &str, records: impl IntoIterator<Item = Record>) {
+        match &self.writer {
+            Some(writer) => self.write(writer, records, |w, r| w.write_file(path, r)),
+            None => self
+                .files
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .append(path, records),
+        }
+    }
+
+    /// Writes `records` through `writer`, failing the run at the first
+    /// error; nothing more is written once the run has failed.
+    fn write(
+        &self,
+        writer: &Mutex<dyn SideWriter + Send>,
+        records: impl IntoIterator<Item = Record>,
+        mut write: impl FnMut(&mut dyn SideWriter, &Record) -> Result<(), String>,
+    ) {
+        let mut writer = writer.lock().unwrap_or_else(PoisonError::into_inner);
+        for record in records {
+            if self.check().is_err() {
+                return;
+            }
+            if let Err(e) = write(&mut *writer, &record) {
+                self.fail(e);
+            }
+        }
+    }
+}
+
+/// `>`, `>>` - pass records through, copying them to the file's records in
+/// the run's [`SideOutputs`].
+pub struct FileOutStage {
+    name: &'static str,
+    path: String,
+    side: SideOutputs,
+}
 
-impl RecordStage for ReverseStage {
+impl RecordStage for FileOutStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        let reversed: String = record.as_str().trim_end().chars().rev().collect();
-        vec![Record::from_str(&reversed)]
+        self.side.append_file(&self.path, [record.clone()]);
+        vec![record]
+    }
+
+    /// A restarted run would lose the records written before the
+    /// checkpoint.
+    fn checkpoint(&self) -> Option<String> {
+        None
     }
 
     fn name(&self) -> &str {
-        "REVERSE"
+        self.name
     }
 }
 
-/// DUPLICATE n - repeats each record n times.
-pub struct DuplicateStage {
-    n: usize,
+/// `<`, FROM CSV - pass records through; the host reads the file (they
+/// only ever appear as the source).
+pub struct FileStage {
+    name: &'static str,
 }
 
-impl RecordStage for DuplicateStage {
+impl RecordStage for FileStage {
     fn process(&mut self, record: Record) -> Vec<Record> {
-        std::iter::repeat_n(record, self.n).collect()
+        vec![record]
     }
 
     fn name(&self) -> &str {
-        "DUPLICATE"
+        self.name
     }
 }
 
-/// HOLE - discards all input, outputs nothing.
-pub struct HoleStage;
+/// FANIN, FANINANY - pass records through; with no segments (which only
+/// the batched executor runs) the primary input is the only stream.
+pub struct FaninStage {
+    name: &'static str,
+}
 
-impl RecordStage for HoleStage {
-    fn process(&mut self, _record: Record) -> Vec<Record> {
-        vec![]
+impl RecordStage for FaninStage {
+    fn process(&mut self, record: Record) -> Vec<Record> {
+        vec![record]
     }
 
     fn name(&self) -> &str {
-        "HOLE"
+        self.name
     }
 }
 
@@ -331,9 +1311,44 @@ impl RecordStage for HoleStage {
 // ---------------------------------------------------------------------------
 
 /// Create a `RecordStage` from a parsed `Command`.
+///
+/// The command must pass `pipelines_rs::check_runtime` (registered
+/// `PLUGIN` stages, loadable `ENCRYPT`/`DECRYPT` keys); otherwise this panics.
 pub fn command_to_record_stage(cmd: &Command) -> Box<dyn RecordStage> {
+    command_to_record_stage_with_vars(cmd, &HostVars::new())
+}
+
+/// Create a `RecordStage`, resolving `STAMP` host variables from `vars`.
+///
+/// Panics on commands that fail `pipelines_rs::check_runtime`, as
+/// `command_to_record_stage` does.
+pub fn command_to_record_stage_with_vars(cmd: &Command, vars: &HostVars) -> Box<dyn RecordStage> {
+    command_to_record_stage_lrecl(cmd, vars, RECORD_WIDTH)
+}
+
+/// Create a `RecordStage` for a pipeline of `lrecl`-byte records, which is
+/// the length of the records COUNT, LITERAL, and STAMP HEADER emit.
+///
+/// Records written by SPLIT, `>`, and `>>` are discarded; use
+/// [`command_to_record_stage_side`] to keep them.
+pub fn command_to_record_stage_lrecl(
+    cmd: &Command,
+    vars: &HostVars,
+    lrecl: usize,
+) -> Box<dyn RecordStage> {
+    command_to_record_stage_side(cmd, vars, lrecl, &SideOutputs::new())
+}
+
+/// Create a `RecordStage` like [`command_to_record_stage_lrecl`], keeping
+/// the records written by SPLIT, `>`, and `>>` in `side`.
+pub fn command_to_record_stage_side(
+    cmd: &Command,
+    vars: &HostVars,
+    lrecl: usize,
+    side: &SideOutputs,
+) -> Box<dyn RecordStage> {
     match cmd {
-        Command::Console => Box::new(ConsoleStage),
+        Command::Console => Box::new(ConsoleStage { echo: true }),
         Command::FilterEq { pos, len, value } => Box::new(FilterEqStage {
             pos: *pos,
             len: *len,
@@ -344,11 +1359,88 @@ pub fn command_to_record_stage(cmd: &Command) -> Box<dyn RecordStage> {
             len: *len,
             value: value.clone(),
         }),
+        Command::FilterZoned {
+            pos,
+            len,
+            op,
+            value,
+        } => Box::new(FilterZonedStage {
+            pos: *pos,
+            len: *len,
+            op: *op,
+            value: *value,
+            side: side.clone(),
+        }),
+        Command::FilterExpr { condition } => Box::new(FilterExprStage {
+            condition: condition.clone(),
+            side: side.clone(),
+        }),
         Command::Select { fields } => Box::new(SelectStage {
             fields: fields.clone(),
         }),
         Command::Take { n } => Box::new(TakeStage { n: *n, seen: 0 }),
         Command::Skip { n } => Box::new(SkipStage { n: *n, seen: 0 }),
+        Command::Between {
+            start,
+            end,
+            field,
+            inclusive,
+        } => {
+            let mut between = Between::new(start, end);
+            if let Some((pos, len)) = *field {
+                between = between.within(pos, len);
+            }
+            if !inclusive {
+                between = between.exclusive();
+            }
+            Box::new(BetweenStage { between })
+        }
+        Command::Overlay { fields } => Box::new(ShapeStage {
+            stage: Overlay::new(fields.clone()),
+            name: cmd.name(),
+        }),
+        Command::Pad { len, fill } => Box::new(ShapeStage {
+            stage: Pad::new(*len, *fill),
+            name: cmd.name(),
+        }),
+        Command::Trunc { len } => Box::new(ShapeStage {
+            stage: Trunc::new(*len),
+            name: cmd.name(),
+        }),
+        Command::Squish => Box::new(ShapeStage {
+            stage: Squish::new(),
+            name: cmd.name(),
+        }),
+        Command::Xlate { spec } => Box::new(ShapeStage {
+            stage: spec
+                .stage()
+                .expect("XLATE tables are validated before building"),
+            name: cmd.name(),
+        }),
+        Command::ReformatDate { spec } => Box::new(ShapeStage {
+            stage: spec.stage().on_error(ErrorPolicy::Reject),
+            name: cmd.name(),
+        }),
+        Command::ToCsv { spec } => Box::new(ShapeStage {
+            stage: spec.stage(),
+            name: cmd.name(),
+        }),
+        Command::Dump { format } => Box::new(ShapeStage {
+            stage: Dump::new(*format),
+            name: cmd.name(),
+        }),
+        Command::TakeLast { n } => Box::new(TakeLastStage {
+            take: TakeLast::new(*n),
+        }),
+        Command::DropLast { n } => Box::new(DropLastStage {
+            drop: DropLast::new(*n),
+        }),
+        Command::Recno { ranges } => Box::new(RecnoStage {
+            recno: Recno::new(ranges.clone()),
+        }),
+        Command::Sample { fraction, seed } => Box::new(SampleStage {
+            sample: Sample::new(*fraction, *seed),
+        }),
         Command::Locate { pattern, field } => Box::new(LocateStage {
             pattern: pattern.clone(),
             field: *field,
@@ -357,20 +1449,111 @@ pub fn command_to_record_stage(cmd: &Command) -> Box<dyn RecordStage> {
             pattern: pattern.clone(),
             field: *field,
         }),
-        Command::Count => Box::new(CountStage { count: 0 }),
-        Command::Change { old, new } => Box::new(ChangeStage {
-            old: old.clone(),
-            new: new.clone(),
+        Command::Count => Box::new(CountStage { count: 0, lrecl }),
+        Command::Statistic { kind, pos, len } => Box::new(StatisticStage {
+            // Blank fields and values that are not numbers are left out,
+            // as the batch executor does
+            total: pipelines_rs::Total::new(pipelines_rs::Aggregate::new(*kind, *pos, *len), lrecl)
+                .on_error(pipelines_rs::ErrorPolicy::Reject),
         }),
+        Command::Change {
+            old,
+            new,
+            field,
+            count,
+        } => {
+            let mut change = Change::new(old, new);
+            if let Some((pos, len)) = *field {
+                change = change.within(pos, len);
+            }
+            if let Some(n) = *count {
+                change = change.count(n);
+            }
+            Box::new(ChangeStage { change })
+        }
         Command::Literal { text } => Box::new(LiteralStage {
-            text: text.clone(),
-            emitted: false,
+            literal: Literal::new(Record::from_str_lrecl(text, lrecl)),
         }),
         Command::Upper => Box::new(UpperStage),
         Command::Lower => Box::new(LowerStage),
         Command::Reverse => Box::new(ReverseStage),
-        Command::Duplicate { n } => Box::new(DuplicateStage { n: *n }),
+        Command::Duplicate { n } => Box::new(DuplicateStage {
+            duplicate: Duplicate::new(*n),
+        }),
+        Command::Unique { field } => Box::new(UniqueStage {
+            dedup: pipelines_rs::Dedup::new(vec![field.unwrap_or((0, lrecl))]),
+        }),
+        Command::Sort { keys, sum } => Box::new(SortStage {
+            sort: pipelines_rs::dsl::sort_for(keys, lrecl),
+            sum: sum.clone(),
+            side: side.clone(),
+        }),
         Command::Hole => Box::new(HoleStage),
+        Command::Delay { ms } => Box::new(DelayStage {
+            inner: pipelines_rs::ThrottleStage::new(std::time::Duration::from_millis(*ms)),
+        }),
+        Command::Profile { fields } => Box::new(ProfileStage {
+            inner: pipelines_rs::ProfileStage::from_fields(fields),
+        }),
+        Command::Stamp { header, fields } => Box::new(StampStage {
+            vars: vars.clone(),
+            header: *header,
+            fields: fields.clone(),
+            lrecl,
+            emitted: false,
+        }),
+        Command::Plugin { name, args } => Box::new(PluginStage {
+            inner: pipelines_rs::plugin::create(name, args)
+                .expect("PLUGIN stages are validated before building"),
+        }),
+        Command::Encrypt { spec } => Box::new(CryptStage {
+            inner: pipelines_rs::crypto::encrypt_stage(spec)
+                .expect("ENCRYPT keys are validated before building"),
+            name: "ENCRYPT",
+        }),
+        Command::Decrypt { spec } => Box::new(CryptStage {
+            inner: pipelines_rs::crypto::decrypt_stage(spec)
+                .expect("DECRYPT keys are validated before building"),
+            name: "DECRYPT",
+        }),
+        Command::Convert { .. } => Box::new(ConvertStage),
+        Command::Specs { items } => Box::new(SpecsStage {
+            specs: pipelines_rs::Specs::new(items.clone()),
+        }),
+        Command::Split {
+            name,
+            condition,
+            build,
+        } => {
+            // Register the output, so one no record reaches is still written
+            side.append_output(name, []);
+            // The condition only picks the copies: every record passes on,
+            // so none is an error record
+            let test_side = SideOutputs::new();
+            Box::new(SplitStage {
+                name: name.clone(),
+                condition: condition.as_ref().map(|condition| {
+                    command_to_record_stage_side(condition, vars, lrecl, &test_side)
+                }),
+                build: build.as_ref().map(|fields| SelectStage {
+                    fields: fields.clone(),
+                }),
+                side: side.clone(),
+            })
+        }
+        Command::FileOut { path, .. } => {
+            // Register the file, so one no record reaches is still written
+            side.append_file(path, []);
+            Box::new(FileOutStage {
+                name: cmd.name(),
+                path: path.clone(),
+                side: side.clone(),
+            })
+        }
+        Command::FileIn { .. } | Command::FromCsv { .. } => {
+            Box::new(FileStage { name: cmd.name() })
+        }
+        Command::Fanin | Command::Faninany => Box::new(FaninStage { name: cmd.name() }),
     }
 }
 
@@ -380,10 +1563,64 @@ mod tests {
 
     #[test]
     fn test_console_passthrough() {
-        let mut stage = ConsoleStage;
         let r = Record::from_str("hello");
-        let out = stage.process(r.clone());
-        assert_eq!(out, vec![r]);
+        for mut stage in [ConsoleStage { echo: true }, ConsoleStage::output()] {
+            assert_eq!(stage.process(r.clone()), vec![r.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_process_block_matches_process() {
+        let records: Vec<Record> = ["SALES 00050", "ENG   00075", "SALES 0001J", "MKT   00020"]
+            .map(Record::from_str)
+            .to_vec();
+        let commands = [
+            Command::FilterEq {
+                pos: 0,
+                len: 6,
+                value: "SALES".to_string(),
+            },
+            Command::FilterNe {
+                pos: 0,
+                len: 6,
+                value: "SALES".to_string(),
+            },
+            Command::FilterZoned {
+                pos: 6,
+                len: 5,
+                op: CompareOp::Gt,
+                value: 30,
+            },
+            Command::Locate {
+                pattern: "00".to_string(),
+                field: Some((6, 3)),
+            },
+            Command::Nlocate {
+                pattern: "SALES".to_string(),
+                field: None,
+            },
+            Command::Select {
+                fields: vec![(6, 5, 0)],
+            },
+            Command::Count,
+            Command::Duplicate { n: 1 },
+            Command::Upper,
+            Command::Trunc { len: 8 },
+        ];
+        for cmd in &commands {
+            let mut one = command_to_record_stage(cmd);
+            let mut expected: Vec<Record> = records
+                .iter()
+                .flat_map(|r| one.process(r.clone()))
+                .collect();
+            expected.extend(one.flush());
+
+            let mut block = command_to_record_stage(cmd);
+            let mut output = records.clone();
+            block.process_block(&mut output);
+            output.extend(block.flush());
+            assert_eq!(output, expected, "{}", cmd.name());
+        }
     }
 
     #[test]
@@ -430,6 +1667,56 @@ mod tests {
         assert!(stage.process(r).is_empty());
     }
 
+    #[test]
+    fn test_drop_reasons() {
+        let record = Record::from_str("SMITH   JOHN      MARKETING 0004500{");
+        let filter = FilterEqStage {
+            pos: 18,
+            len: 10,
+            value: "SALES".to_string(),
+        };
+        assert_eq!(
+            filter.drop_reason(&record).as_deref(),
+            Some(r#"FILTER 18,10 = "SALES" (value was "MARKETING")"#)
+        );
+        let zoned = FilterZonedStage {
+            pos: 28,
+            len: 8,
+            op: CompareOp::Gt,
+            value: 50000,
+            side: SideOutputs::new(),
+        };
+        assert_eq!(
+            zoned.drop_reason(&record).as_deref(),
+            Some(r#"FILTER 28,8 ZD > 50000 (value was "0004500{")"#)
+        );
+        let locate = LocateStage {
+            pattern: "SALES".to_string(),
+            field: None,
+        };
+        assert_eq!(
+            locate.drop_reason(&record).as_deref(),
+            Some("LOCATE /SALES/ (not found in the record)")
+        );
+        let nlocate = NlocateStage {
+            pattern: "MARK".to_string(),
+            field: Some((18, 10)),
+        };
+        assert_eq!(
+            nlocate.drop_reason(&record).as_deref(),
+            Some(r#"NLOCATE 18,10 /MARK/ (value was "MARKETING")"#)
+        );
+
+        let mut take = TakeStage { n: 1, seen: 0 };
+        take.process(Record::from_str("A"));
+        assert!(take.process(record.clone()).is_empty());
+        assert_eq!(
+            take.drop_reason(&record).as_deref(),
+            Some("TAKE 1 (already took 1)")
+        );
+        assert_eq!(UpperStage.drop_reason(&record), None);
+    }
+
     #[test]
     fn test_select_stage() {
         let mut stage = SelectStage {
@@ -455,6 +1742,23 @@ mod tests {
         assert_eq!(stage.process(Record::from_str("C")).len(), 1);
     }
 
+    #[test]
+    fn test_take_last_and_drop_last_stages() {
+        let mut take = TakeLastStage {
+            take: TakeLast::new(1),
+        };
+        let mut drop = DropLastStage {
+            drop: DropLast::new(1),
+        };
+        for line in ["A", "B"] {
+            assert!(take.process(Record::from_str(line)).is_empty());
+        }
+        assert!(drop.process(Record::from_str("A")).is_empty());
+        assert_eq!(drop.process(Record::from_str("B")).len(), 1);
+        assert_eq!(take.flush()[0].as_str().trim(), "B");
+        assert!(drop.flush().is_empty());
+    }
+
     #[test]
     fn test_locate_whole_record() {
         let mut stage = LocateStage {
@@ -514,7 +1818,10 @@ mod tests {
 
     #[test]
     fn test_count_stage() {
-        let mut stage = CountStage { count: 0 };
+        let mut stage = CountStage {
+            count: 0,
+            lrecl: RECORD_WIDTH,
+        };
         assert!(stage.process(Record::from_str("A")).is_empty());
         assert!(stage.process(Record::from_str("B")).is_empty());
         assert!(stage.process(Record::from_str("C")).is_empty());
@@ -525,7 +1832,10 @@ mod tests {
 
     #[test]
     fn test_count_stage_zero() {
-        let mut stage = CountStage { count: 0 };
+        let mut stage = CountStage {
+            count: 0,
+            lrecl: RECORD_WIDTH,
+        };
         let flushed = stage.flush();
         assert_eq!(flushed.len(), 1);
         assert_eq!(flushed[0].as_str().trim(), "0");
@@ -534,8 +1844,7 @@ mod tests {
     #[test]
     fn test_change_stage() {
         let mut stage = ChangeStage {
-            old: "HELLO".to_string(),
-            new: "WORLD".to_string(),
+            change: Change::new("HELLO", "WORLD"),
         };
         let out = stage.process(Record::from_str("HELLO THERE"));
         assert!(out[0].as_str().starts_with("WORLD THERE"));
@@ -544,8 +1853,7 @@ mod tests {
     #[test]
     fn test_literal_with_input() {
         let mut stage = LiteralStage {
-            text: "HEADER".to_string(),
-            emitted: false,
+            literal: Literal::new(Record::from_str("HEADER")),
         };
         let out1 = stage.process(Record::from_str("A"));
         assert_eq!(out1.len(), 2);
@@ -562,8 +1870,7 @@ mod tests {
     #[test]
     fn test_literal_no_input() {
         let mut stage = LiteralStage {
-            text: "HEADER".to_string(),
-            emitted: false,
+            literal: Literal::new(Record::from_str("HEADER")),
         };
         let flushed = stage.flush();
         assert_eq!(flushed.len(), 1);
@@ -593,7 +1900,9 @@ mod tests {
 
     #[test]
     fn test_duplicate_stage() {
-        let mut stage = DuplicateStage { n: 3 };
+        let mut stage = DuplicateStage {
+            duplicate: Duplicate::new(2),
+        };
         let out = stage.process(Record::from_str("A"));
         assert_eq!(out.len(), 3);
         for r in &out {
@@ -628,12 +1937,414 @@ mod tests {
         assert_eq!(flushed[0].as_str().trim(), "2");
     }
 
+    #[test]
+    fn test_factory_delay() {
+        let cmd = Command::Delay { ms: 1 };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "DELAY");
+        let out = stage.process(Record::from_str("A"));
+        assert_eq!(out[0].as_str().trim(), "A");
+    }
+
+    #[test]
+    fn test_factory_profile() {
+        let cmd = Command::Profile { fields: vec![] };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "PROFILE");
+        assert!(stage.process(Record::from_str("A 1")).is_empty());
+        assert!(stage.process(Record::from_str("B 2")).is_empty());
+        let flushed = stage.flush();
+        assert_eq!(flushed[0].as_str().trim(), "PROFILE 2 RECORDS");
+        assert_eq!(flushed.len(), 4);
+    }
+
+    #[test]
+    fn test_factory_stamp_header() {
+        let vars = HostVars::new().with_step("STEP010");
+        let cmd = Command::Stamp {
+            header: true,
+            fields: vec![(0, 8, StampValue::Var("STEP".to_string()))],
+        };
+        let mut stage = command_to_record_stage_with_vars(&cmd, &vars);
+        assert_eq!(stage.name(), "STAMP");
+        let out = stage.process(Record::from_str("A"));
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[0].as_str().trim(), "STEP010");
+        assert_eq!(stage.process(Record::from_str("B")).len(), 1);
+        assert!(stage.flush().is_empty());
+    }
+
+    #[test]
+    fn test_factory_plugin() {
+        pipelines_rs::plugin::register("RAT-TEST-UPPER", |_| {
+            Ok(Box::new(pipelines_rs::Reformat::new(|r: &Record| {
+                Record::from_str(&r.as_str().to_uppercase())
+            })))
+        });
+        let cmd = Command::Plugin {
+            name: "RAT-TEST-UPPER".to_string(),
+            args: String::new(),
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "PLUGIN");
+        let out = stage.process(Record::from_str("abc"));
+        assert_eq!(out[0].as_str().trim(), "ABC");
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_factory_encrypt() {
+        use pipelines_rs::crypto::{CryptSpec, Encoding};
+        let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let cmd = Command::Encrypt {
+            spec: CryptSpec::new((0, 4), (0, 64), Encoding::Hex).with_key_hex(key),
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "ENCRYPT");
+        let out = stage.process(Record::from_str("1234"));
+        assert_eq!(out[0].as_str().trim_end().len(), 64);
+    }
+
     #[test]
     fn test_factory_duplicate() {
         let cmd = Command::Duplicate { n: 2 };
         let mut stage = command_to_record_stage(&cmd);
         assert_eq!(stage.name(), "DUPLICATE");
         let out = stage.process(Record::from_str("X"));
-        assert_eq!(out.len(), 2);
+        assert_eq!(out.len(), 3);
+    }
+
+    #[test]
+    fn test_factory_unique() {
+        let cmd = Command::Unique {
+            field: Some((0, 1)),
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "UNIQUE");
+        assert_eq!(stage.process(Record::from_str("A1")).len(), 1);
+        assert_eq!(stage.process(Record::from_str("B1")).len(), 1);
+        assert!(stage.process(Record::from_str("A2")).is_empty());
+    }
+
+    #[test]
+    fn test_factory_convert() {
+        let cmd = Command::Convert {
+            to: pipelines_rs::ebcdic::Charset::Ebcdic,
+            code_page: pipelines_rs::ebcdic::CodePage::Cp1047,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "CONVERT");
+        let out = stage.process(Record::from_str("ABC"));
+        assert_eq!(out[0].as_str().trim(), "ABC");
+    }
+
+    #[test]
+    fn test_factory_filter_zoned() {
+        let cmd = Command::FilterZoned {
+            pos: 0,
+            len: 4,
+            op: CompareOp::Lt,
+            value: 0,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "FILTER");
+        assert_eq!(stage.process(Record::from_str("012J")).len(), 1);
+        assert!(stage.process(Record::from_str("012A")).is_empty());
+        assert!(stage.process(Record::from_str("ABCD")).is_empty());
+    }
+
+    #[test]
+    fn test_factory_split() {
+        let cmd = Command::Split {
+            name: "ALL".to_string(),
+            condition: None,
+            build: None,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "SPLIT");
+        assert_eq!(stage.process(Record::from_str("ABC")).len(), 1);
+    }
+
+    #[test]
+    fn test_factory_split_outputs() {
+        let side = SideOutputs::new();
+        let cmd = pipelines_rs::parse_commands("SPLIT ab IF 0,1 = \"A\" BUILD 1,2,0").unwrap();
+        let mut stage = command_to_record_stage_side(&cmd[0], &HostVars::new(), 80, &side);
+        let none = pipelines_rs::parse_commands("SPLIT NONE IF 0,1 = \"Z\"").unwrap();
+        let mut none = command_to_record_stage_side(&none[0], &HostVars::new(), 80, &side);
+        for text in ["ABC", "BCD", "AXY"] {
+            let record = Record::from_str(text);
+            assert_eq!(stage.process(record.clone()), vec![record.clone()]);
+            none.process(record);
+        }
+        let outputs = side.outputs();
+        let ab: Vec<_> = outputs
+            .get("AB")
+            .unwrap()
+            .iter()
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect();
+        assert_eq!(ab, ["BC", "XY"]);
+        assert_eq!(outputs.get("NONE"), Some(&[][..]));
+        assert!(stage.checkpoint().is_none());
+    }
+
+    #[test]
+    fn test_factory_file() {
+        let cmd = Command::FileOut {
+            path: "out.txt".to_string(),
+            append: true,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), ">>");
+        assert_eq!(stage.process(Record::from_str("ABC")).len(), 1);
+    }
+
+    #[test]
+    fn test_factory_fanin() {
+        for cmd in [Command::Fanin, Command::Faninany] {
+            let mut stage = command_to_record_stage(&cmd);
+            assert_eq!(stage.name(), cmd.name());
+            assert_eq!(stage.process(Record::from_str("ABC")).len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_factory_between() {
+        let cmd = Command::Between {
+            start: "B".to_string(),
+            end: "E".to_string(),
+            field: Some((0, 1)),
+            inclusive: false,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "BETWEEN");
+        let out: Vec<_> = ["A", "BEGIN", "X", "END", "Y"]
+            .into_iter()
+            .flat_map(|line| stage.process(Record::from_str(line)))
+            .collect();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].as_str().trim(), "X");
+    }
+
+    #[test]
+    fn test_factory_pad_trunc_squish() {
+        let mut stage = command_to_record_stage(&Command::Pad { len: 4, fill: '-' });
+        assert_eq!(stage.name(), "PAD");
+        assert_eq!(
+            stage.process(Record::from_str("A"))[0].as_str().trim(),
+            "A---"
+        );
+        let mut stage = command_to_record_stage(&Command::Trunc { len: 1 });
+        assert_eq!(
+            stage.process(Record::from_str("AB"))[0].as_str().trim(),
+            "A"
+        );
+        let mut stage = command_to_record_stage(&Command::Squish);
+        assert_eq!(
+            stage.process(Record::from_str("A  B"))[0].as_str().trim(),
+            "A B"
+        );
+    }
+
+    #[test]
+    fn test_factory_overlay() {
+        let cmd = Command::Overlay {
+            fields: vec![(0, 2, 4)],
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "OVERLAY");
+        assert_eq!(
+            stage.process(Record::from_str("AB"))[0].as_str().trim(),
+            "AB  AB"
+        );
+    }
+
+    #[test]
+    fn test_factory_xlate() {
+        let spec = pipelines_rs::xlate::XlateSpec {
+            field: Some((0, 2)),
+            ..Default::default()
+        };
+        let mut stage = command_to_record_stage(&Command::Xlate { spec });
+        assert_eq!(stage.name(), "XLATE");
+        assert_eq!(
+            stage.process(Record::from_str("abc"))[0].as_str().trim(),
+            "ABc"
+        );
+    }
+
+    #[test]
+    fn test_factory_recno() {
+        let cmd = Command::Recno {
+            ranges: vec![pipelines_rs::RecnoRange::starting(2).by(2)],
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "RECNO");
+        let kept: Vec<usize> = (0..5)
+            .map(|_| stage.process(Record::from_str("R")).len())
+            .collect();
+        assert_eq!(kept, [0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_factory_sample() {
+        let cmd = Command::Sample {
+            fraction: 0.5,
+            seed: 3,
+        };
+        let run = |cmd: &Command| -> Vec<usize> {
+            let mut stage = command_to_record_stage(cmd);
+            (0..50)
+                .map(|_| stage.process(Record::from_str("R")).len())
+                .collect()
+        };
+        assert_eq!(command_to_record_stage(&cmd).name(), "SAMPLE");
+        assert_eq!(run(&cmd), run(&cmd));
+        assert!(run(&cmd).contains(&0) && run(&cmd).contains(&1));
+    }
+
+    #[test]
+    fn test_factory_reformat_date() {
+        use pipelines_rs::dates::{DateFormat, DateSpec};
+
+        let cmd = Command::ReformatDate {
+            spec: DateSpec {
+                pos: 0,
+                from: DateFormat::Mmddyy,
+                to: DateFormat::Yyyymmdd,
+                dest: None,
+            },
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "REFORMAT DATE");
+        assert_eq!(
+            stage.process(Record::from_str("031585"))[0].as_str().trim(),
+            "19850315"
+        );
+        assert!(stage.process(Record::from_str("023185")).is_empty());
+    }
+
+    #[test]
+    fn test_factory_dump() {
+        let cmd = Command::Dump {
+            format: pipelines_rs::DumpFormat::Ruler,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "DUMP");
+        let out = stage.process(Record::from_str("ABC"));
+        assert_eq!(out[0].as_str().trim(), "ABC");
+    }
+
+    #[test]
+    fn test_factory_to_csv() {
+        use pipelines_rs::csv::CsvSpec;
+
+        let cmd = Command::ToCsv {
+            spec: CsvSpec {
+                layout: "LAYOUT NAME 0,8; PAY 8,8 NUMERIC".parse().unwrap(),
+                header: true,
+            },
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "TO CSV");
+        let out = stage.process(Record::from_str("SMITH   00050000"));
+        let lines: Vec<&str> = out.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(lines, ["NAME,PAY", "SMITH,50000"]);
+        assert!(stage.flush().is_empty());
+    }
+
+    #[test]
+    fn test_factory_drop_last() {
+        let mut stage = command_to_record_stage(&Command::DropLast { n: 0 });
+        assert_eq!(stage.name(), "DROP");
+        assert_eq!(stage.process(Record::from_str("A")).len(), 1);
+    }
+
+    #[test]
+    fn test_factory_change() {
+        let cmd = Command::Change {
+            old: "A".to_string(),
+            new: "B".to_string(),
+            field: Some((2, 3)),
+            count: Some(1),
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "CHANGE");
+        let out = stage.process(Record::from_str("A-A-A"));
+        assert_eq!(out[0].as_str().trim_end(), "A-B-A");
+    }
+
+    #[test]
+    fn test_factory_filter_expr() {
+        let cmd = Command::FilterExpr {
+            condition: Condition::number(0, 3, CompareOp::Gt, 10).or(Condition::text(
+                4,
+                1,
+                CompareOp::Lt,
+                "M",
+            )),
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "FILTER");
+        assert_eq!(stage.process(Record::from_str("011 Z")).len(), 1);
+        assert_eq!(stage.process(Record::from_str("009 A")).len(), 1);
+        assert!(stage.process(Record::from_str("009 Z")).is_empty());
+    }
+
+    #[test]
+    fn test_factory_statistic() {
+        let cmd = Command::Statistic {
+            kind: pipelines_rs::AggregateKind::Max,
+            pos: 0,
+            len: 3,
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "MAX");
+        assert!(stage.process(Record::from_str("007")).is_empty());
+        assert!(stage.process(Record::from_str("012")).is_empty());
+        let out = stage.flush();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].as_str().trim_end(), "12");
+    }
+
+    #[test]
+    fn test_factory_specs() {
+        let cmd = Command::Specs {
+            items: vec![
+                pipelines_rs::SpecItem::recno().at(0).width(3),
+                pipelines_rs::SpecItem::range(0, 3).next_word(),
+            ],
+        };
+        let mut stage = command_to_record_stage(&cmd);
+        assert_eq!(stage.name(), "SPECS");
+        stage.process(Record::from_str("ABC"));
+        let out = stage.process(Record::from_str("DEF"));
+        assert_eq!(out[0].as_str().trim_end(), "  2 DEF");
+    }
+
+    #[test]
+    fn test_factory_file_out() {
+        let side = SideOutputs::new();
+        let vars = HostVars::new();
+        let write = |path: &str, append| Command::FileOut {
+            path: path.to_string(),
+            append,
+        };
+        let mut all = command_to_record_stage_side(&write("all.txt", false), &vars, 80, &side);
+        let mut none = command_to_record_stage_side(&write("none.txt", true), &vars, 80, &side);
+        assert_eq!((all.name(), none.name()), (">", ">>"));
+        for line in ["A", "B"] {
+            let out = all.process(Record::from_str(line));
+            assert_eq!(out[0].as_str().trim_end(), line);
+        }
+        assert!(all.checkpoint().is_none());
+
+        let files = side.files();
+        assert_eq!(files.names().collect::<Vec<_>>(), ["all.txt", "none.txt"]);
+        assert_eq!(files.get("all.txt").unwrap().len(), 2);
+        assert!(files.get("none.txt").unwrap().is_empty());
+        none.process(Record::from_str("C"));
+        assert_eq!(side.files().get("none.txt").unwrap().len(), 1);
     }
 }
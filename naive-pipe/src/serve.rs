@@ -0,0 +1,699 @@
+//! Request handling for the `pipe-serve` HTTP service.
+//!
+//! The routing logic lives here, independent of the HTTP transport, so it
+//! can be unit tested without opening sockets. The `pipe-serve` binary
+//! adapts incoming HTTP requests to [`Service::handle`].
+//!
+//! Endpoints:
+//! - `GET /health` - liveness check
+//! - `GET /pipelines` - list saved `.pipe` files in the pipeline directory
+//! - `GET /pipelines/{name}` - fetch the text of a saved pipeline
+//! - `POST /run` - execute a pipeline, returning output and record counts
+//! - `POST /stats` - execute a pipeline, returning per-stage record counts
+//! - `POST /trace` - execute with the RAT executor, returning the debug trace
+//! - `GET /metrics` - Prometheus metrics for all jobs run by this service
+//!
+//! `POST` bodies are JSON objects with `input` and either `pipeline` (DSL
+//! text) or `name` (a saved pipeline). `/run` also accepts `executor`
+//! (`"batch"` or `"rat"`, default `"batch"`). Pipelines may also be written
+//! in CMS Pipelines syntax, as the service's [`Syntax`] allows
+//! ([`Service::with_syntax`]; by default either is accepted).
+//!
+//! Any client can post a pipeline, so an inline pipeline may not use stages
+//! that reach the server's files, keys, or code: `<`, `>`, `>>`, `XLATE
+//! FILE`, `PLUGIN`, and `ENCRYPT`/`DECRYPT` with a key from a file or the
+//! environment (`KEYHEX` is allowed). Saved pipelines were written by the
+//! operator and may use them. Every request is held to the service's
+//! [`Limits`] on input size, DELAY time, DUPLICATE copies, and records
+//! traced, and a pipeline that does not parse is rejected before it runs.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::crypto::KeySource;
+use pipelines_rs::xlate::TableSource;
+use pipelines_rs::{
+    Command, HostVars, JobMetrics, MetricsRegistry, PipelinePlan, execute_pipeline_debug_with_vars,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::debug_trace::TraceOptions;
+use crate::dsl::{execute_pipeline_rat_with_vars, execute_plan_rat_debug_with};
+use crate::record_stage::SideOutputs;
+
+/// A transport-independent HTTP response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// HTTP status code.
+    pub status: u16,
+    /// Value for the `Content-Type` header.
+    pub content_type: &'static str,
+    /// Response body.
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: &Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string(),
+        }
+    }
+
+    fn text(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(status, &json!({ "error": message }))
+    }
+}
+
+/// Body of a `POST` execution request.
+#[derive(Debug, Deserialize)]
+struct RunRequest {
+    /// Inline pipeline DSL text.
+    pipeline: Option<String>,
+    /// Name of a saved pipeline (without the `.pipe` extension).
+    name: Option<String>,
+    /// Input records, one per line.
+    #[serde(default)]
+    input: String,
+    /// `"batch"` (default) or `"rat"`.
+    executor: Option<String>,
+    /// `/trace` only: trace every Nth input record.
+    trace_every: Option<usize>,
+    /// `/trace` only: cap on the records held across all traces, at most
+    /// the service's [`Limits::max_trace`].
+    trace_max: Option<usize>,
+}
+
+/// Limits on the work one request may ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Largest `input`, in bytes.
+    pub max_input: usize,
+    /// Longest the DELAY stages may wait over the whole input, in
+    /// milliseconds: each stage's delay times the records reaching it.
+    pub max_delay_ms: u64,
+    /// Most copies the DUPLICATE stages may make of one input record.
+    pub max_copies: usize,
+    /// Most records a `/trace` holds across all its traces, whether or not
+    /// the request sets a smaller `trace_max`.
+    pub max_trace: usize,
+}
+
+impl Default for Limits {
+    /// 16 MiB of input, 10 seconds of DELAY, 1000 copies, and 10,000
+    /// traced records.
+    fn default() -> Self {
+        Self {
+            max_input: 16 * 1024 * 1024,
+            max_delay_ms: 10_000,
+            max_copies: 1000,
+            max_trace: 10_000,
+        }
+    }
+}
+
+/// The pipeline execution service.
+pub struct Service {
+    pipeline_dir: PathBuf,
+    limits: Limits,
+    syntax: Syntax,
+    metrics: Mutex<MetricsRegistry>,
+}
+
+impl Service {
+    /// Creates a service that lists and loads saved pipelines from
+    /// `pipeline_dir`, with the default [`Limits`].
+    pub fn new(pipeline_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            pipeline_dir: pipeline_dir.into(),
+            limits: Limits::default(),
+            syntax: Syntax::Auto,
+            metrics: Mutex::new(MetricsRegistry::new()),
+        }
+    }
+
+    /// Uses `limits` instead of the defaults.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reads pipelines in `syntax` instead of [`Syntax::Auto`].
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Route a request to its handler.
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> Response {
+        let path = path.split('?').next().unwrap_or(path);
+        match (method, path) {
+            ("GET", "/health") => Response::text(200, "ok".to_string()),
+            ("GET", "/pipelines") => self.list_pipelines(),
+            ("GET", "/metrics") => self.render_metrics(),
+            ("GET", p) if p.starts_with("/pipelines/") => {
+                self.get_pipeline(&p["/pipelines/".len()..])
+            }
+            ("POST", "/run") => self.with_request(body, run),
+            ("POST", "/stats") => self.with_request(body, stats),
+            ("POST", "/trace") => self.with_request(body, |req, pipeline, job| {
+                trace(req, pipeline, job, self.limits.max_trace)
+            }),
+            ("OPTIONS", _) => Response::text(204, String::new()),
+            _ => Response::error(404, &format!("no route for {method} {path}")),
+        }
+    }
+
+    fn render_metrics(&self) -> Response {
+        let text = match self.metrics.lock() {
+            Ok(registry) => registry.render(),
+            Err(poisoned) => poisoned.into_inner().render(),
+        };
+        Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: text,
+        }
+    }
+
+    fn list_pipelines(&self) -> Response {
+        let entries = match fs::read_dir(&self.pipeline_dir) {
+            Ok(entries) => entries,
+            Err(e) => return Response::error(500, &format!("cannot read pipeline dir: {e}")),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().to_string_lossy().to_string();
+                name.strip_suffix(".pipe").map(str::to_string)
+            })
+            .collect();
+        names.sort();
+        Response::json(200, &json!(names))
+    }
+
+    fn get_pipeline(&self, name: &str) -> Response {
+        match self.load_pipeline(name) {
+            Ok(text) => Response::text(200, text),
+            Err(resp) => resp,
+        }
+    }
+
+    /// Load a saved pipeline, rejecting names that could escape the directory.
+    fn load_pipeline(&self, name: &str) -> Result<String, Response> {
+        let name = name.strip_suffix(".pipe").unwrap_or(name);
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+        {
+            return Err(Response::error(
+                400,
+                &format!("invalid pipeline name '{name}'"),
+            ));
+        }
+        let path = self.pipeline_dir.join(format!("{name}.pipe"));
+        fs::read_to_string(path)
+            .map_err(|_| Response::error(404, &format!("pipeline '{name}' not found")))
+    }
+
+    /// Parse a JSON request body, resolve its pipeline text, and run `f`.
+    fn with_request<F>(&self, body: &str, f: F) -> Response
+    where
+        F: FnOnce(&RunRequest, &str, &mut JobMetrics) -> Response,
+    {
+        let req: RunRequest = match serde_json::from_str(body) {
+            Ok(req) => req,
+            Err(e) => return Response::error(400, &format!("invalid request body: {e}")),
+        };
+        let pipeline = match (&req.pipeline, &req.name) {
+            (Some(text), _) => text.clone(),
+            (None, Some(name)) => match self.load_pipeline(name) {
+                Ok(text) => text,
+                Err(resp) => return resp,
+            },
+            (None, None) => return Response::error(400, "request needs 'pipeline' or 'name'"),
+        };
+        let pipeline = match self.syntax.to_dsl(&pipeline) {
+            Ok(text) => text.into_owned(),
+            Err(e) => return Response::error(400, &e),
+        };
+        if let Err(resp) = self.check(&req, &pipeline) {
+            return resp;
+        }
+        let label = req.name.as_deref().unwrap_or("inline");
+        let mut job = JobMetrics::new(label, req.executor.as_deref().unwrap_or("batch"));
+        let started = Instant::now();
+        let response = f(&req, &pipeline, &mut job);
+        job.duration = started.elapsed();
+        job.success = response.status == 200;
+        match self.metrics.lock() {
+            Ok(mut registry) => registry.record(&job),
+            Err(poisoned) => poisoned.into_inner().record(&job),
+        }
+        response
+    }
+}
+
+impl Service {
+    /// Checks that a request's pipeline parses and keeps to the limits, and
+    /// an inline pipeline for stages that reach the server.
+    fn check(&self, req: &RunRequest, pipeline: &str) -> Result<(), Response> {
+        let limits = &self.limits;
+        if req.input.len() > limits.max_input {
+            return Err(Response::error(
+                413,
+                &format!(
+                    "input is {} bytes; the limit is {}",
+                    req.input.len(),
+                    limits.max_input
+                ),
+            ));
+        }
+        let plan = PipelinePlan::parse(pipeline).map_err(|e| Response::error(422, &e))?;
+        if req.pipeline.is_some()
+            && let Some(cmd) = plan.all_commands().find(|cmd| reaches_server(cmd))
+        {
+            return Err(Response::error(
+                403,
+                &format!("{} is not allowed in a posted pipeline", cmd.name()),
+            ));
+        }
+
+        // Each DELAY waits once per record reaching it, and DUPLICATE
+        // multiplies the records after it
+        let records = req.input.lines().count().max(1) as u64;
+        let (mut copies, mut delay_ms) = (1u64, 0u64);
+        for cmd in plan.all_commands() {
+            match cmd {
+                Command::Duplicate { n } => {
+                    copies = copies.saturating_mul(*n as u64 + 1);
+                    if copies > limits.max_copies as u64 {
+                        return Err(Response::error(
+                            422,
+                            &format!(
+                                "DUPLICATE makes more than {} copies of a record",
+                                limits.max_copies
+                            ),
+                        ));
+                    }
+                }
+                Command::Delay { ms } => {
+                    delay_ms = delay_ms.saturating_add(ms.saturating_mul(copies * records));
+                    if delay_ms > limits.max_delay_ms {
+                        return Err(Response::error(
+                            422,
+                            &format!(
+                                "DELAY would wait more than {} ms over the input",
+                                limits.max_delay_ms
+                            ),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a stage reads or writes the server's files, loads code, or
+/// reads a key held by the server.
+fn reaches_server(cmd: &Command) -> bool {
+    match cmd {
+        Command::FileIn { .. } | Command::FileOut { .. } | Command::Plugin { .. } => true,
+        Command::Xlate { spec } => matches!(spec.source, TableSource::File(_)),
+        Command::Encrypt { spec } | Command::Decrypt { spec } => {
+            !matches!(spec.key, KeySource::Hex(_))
+        }
+        _ => false,
+    }
+}
+
+/// Host variables for a posted pipeline. No environment variable is
+/// allowed, so `STAMP ENV.name` cannot read the server's environment.
+fn host_vars() -> HostVars {
+    HostVars::new()
+}
+
+/// `POST /run` - execute and return the output text.
+fn run(req: &RunRequest, pipeline: &str, job: &mut JobMetrics) -> Response {
+    let executor = req.executor.as_deref().unwrap_or("batch");
+    let result = match executor {
+        "batch" => execute_pipeline_debug_with_vars(&req.input, pipeline, &None, &host_vars()).map(
+            |(output, input_count, output_count, debug_info)| {
+                job.set_stages(&debug_info);
+                (output, input_count, output_count)
+            },
+        ),
+        "rat" => execute_pipeline_rat_with_vars(&req.input, pipeline, &host_vars()),
+        other => return Response::error(400, &format!("unknown executor '{other}'")),
+    };
+    match result {
+        Ok((output, input_count, output_count)) => {
+            job.records_in = input_count;
+            job.records_out = output_count;
+            Response::json(
+                200,
+                &json!({
+                    "executor": executor,
+                    "output": output,
+                    "input_count": input_count,
+                    "output_count": output_count,
+                }),
+            )
+        }
+        Err(e) => Response::error(422, &e),
+    }
+}
+
+/// `POST /stats` - execute and return per-stage record counts.
+fn stats(req: &RunRequest, pipeline: &str, job: &mut JobMetrics) -> Response {
+    match execute_pipeline_debug_with_vars(&req.input, pipeline, &None, &host_vars()) {
+        Ok((_, input_count, output_count, debug_info)) => {
+            job.records_in = input_count;
+            job.records_out = output_count;
+            job.set_stages(&debug_info);
+            let stages: Vec<Value> = debug_info
+                .iter()
+                .map(|info| {
+                    json!({
+                        "stage": info.stage_name,
+                        "input_count": info.input_count,
+                        "output_count": info.output_count,
+                    })
+                })
+                .collect();
+            Response::json(
+                200,
+                &json!({
+                    "input_count": input_count,
+                    "output_count": output_count,
+                    "stages": stages,
+                }),
+            )
+        }
+        Err(e) => Response::error(422, &e),
+    }
+}
+
+/// `POST /trace` - execute with the RAT executor and return its trace,
+/// holding at most `max_trace` records.
+fn trace(req: &RunRequest, pipeline: &str, job: &mut JobMetrics, max_trace: usize) -> Response {
+    job.executor = "rat".to_string();
+    let options = TraceOptions::new()
+        .every(req.trace_every.unwrap_or(1))
+        .max_records(req.trace_max.map_or(max_trace, |max| max.min(max_trace)));
+    let result = PipelinePlan::parse(pipeline).and_then(|plan| {
+        execute_plan_rat_debug_with(
+            &plan,
+            &req.input,
+            &host_vars(),
+            &SideOutputs::new(),
+            &options,
+        )
+    });
+    match result {
+        Ok((output, input_count, output_count, trace)) => {
+            job.records_in = input_count;
+            job.records_out = output_count;
+            Response::json(
+                200,
+                &json!({
+                    "output": output,
+                    "input_count": input_count,
+                    "output_count": output_count,
+                    "trace": trace.json_value(),
+                }),
+            )
+        }
+        Err(e) => Response::error(422, &e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn service() -> Service {
+        Service::new(
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("..")
+                .join("specs"),
+        )
+    }
+
+    fn body(resp: &Response) -> Value {
+        serde_json::from_str(&resp.body).unwrap()
+    }
+
+    const INPUT: &str =
+        "SMITH   JOHN      SALES     00050000\nJONES   MARY      ENGINEER  00075000";
+
+    #[test]
+    fn test_health() {
+        let resp = service().handle("GET", "/health", "");
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.body, "ok");
+    }
+
+    #[test]
+    fn test_list_pipelines() {
+        let resp = service().handle("GET", "/pipelines", "");
+        assert_eq!(resp.status, 200);
+        let names = body(&resp);
+        assert!(names.as_array().unwrap().contains(&json!("filter-sales")));
+    }
+
+    #[test]
+    fn test_get_pipeline_rejects_traversal() {
+        let resp = service().handle("GET", "/pipelines/..%2Fsecret", "");
+        assert_eq!(resp.status, 400);
+        let resp = service().handle("GET", "/pipelines/no-such-pipe", "");
+        assert_eq!(resp.status, 404);
+    }
+
+    #[test]
+    fn test_run_inline_both_executors() {
+        for executor in ["batch", "rat"] {
+            let req = json!({
+                "pipeline": "PIPE CONSOLE | LOCATE /SALES/ | CONSOLE",
+                "input": INPUT,
+                "executor": executor,
+            });
+            let resp = service().handle("POST", "/run", &req.to_string());
+            assert_eq!(resp.status, 200, "{}", resp.body);
+            let out = body(&resp);
+            assert_eq!(out["input_count"], 2);
+            assert_eq!(out["output_count"], 1);
+            assert!(out["output"].as_str().unwrap().starts_with("SMITH"));
+        }
+    }
+
+    #[test]
+    fn test_run_cms_syntax() {
+        for executor in ["batch", "rat"] {
+            let req = json!({
+                "pipeline": "PIPE CONSOLE | SPECS 1-8 1 /lit/ NEXT 29-36 STRIP NW | CONSOLE",
+                "input": INPUT,
+                "executor": executor,
+            });
+            let resp = service().handle("POST", "/run", &req.to_string());
+            assert_eq!(resp.status, 200, "{}", resp.body);
+            assert!(
+                body(&resp)["output"]
+                    .as_str()
+                    .unwrap()
+                    .starts_with("SMITH   lit 00050000")
+            );
+        }
+
+        let req = json!({
+            "pipeline": "PIPE CONSOLE | SPECS 1-8 1 /lit/ | CONSOLE",
+            "input": INPUT,
+        });
+        let resp = service()
+            .with_syntax(Syntax::Dsl)
+            .handle("POST", "/run", &req.to_string());
+        assert_eq!(resp.status, 422, "{}", resp.body);
+        let req = json!({ "pipeline": "PIPE CONSOLE | SPECS 1-8", "input": INPUT });
+        let resp = service()
+            .with_syntax(Syntax::Cms)
+            .handle("POST", "/run", &req.to_string());
+        assert_eq!(resp.status, 400, "{}", resp.body);
+    }
+
+    #[test]
+    fn test_run_saved_pipeline() {
+        let req = json!({ "name": "count-records", "input": INPUT });
+        let resp = service().handle("POST", "/run", &req.to_string());
+        assert_eq!(resp.status, 200, "{}", resp.body);
+        assert_eq!(body(&resp)["output"], "2");
+    }
+
+    #[test]
+    fn test_stats_per_stage() {
+        let req = json!({ "pipeline": "PIPE CONSOLE | LOCATE /SALES/ | COUNT", "input": INPUT });
+        let resp = service().handle("POST", "/stats", &req.to_string());
+        let out = body(&resp);
+        assert_eq!(out["stages"][1]["stage"], "LOCATE");
+        assert_eq!(out["stages"][1]["input_count"], 2);
+        assert_eq!(out["stages"][1]["output_count"], 1);
+    }
+
+    #[test]
+    fn test_trace() {
+        let req = json!({ "pipeline": "PIPE CONSOLE | UPPER | CONSOLE", "input": "a\nb" });
+        let resp = service().handle("POST", "/trace", &req.to_string());
+        let out = body(&resp);
+        assert_eq!(out["trace"]["stage_names"], json!(["UPPER", "CONSOLE"]));
+        assert_eq!(out["trace"]["record_traces"][0]["pipe_points"][1][0], "A");
+    }
+
+    #[test]
+    fn test_trace_sampled() {
+        let req = json!({
+            "pipeline": "PIPE CONSOLE | UPPER | CONSOLE",
+            "input": "a\nb\nc\nd",
+            "trace_every": 2,
+            "trace_max": 3,
+        });
+        let resp = service().handle("POST", "/trace", &req.to_string());
+        let out = body(&resp);
+        assert_eq!(out["output_count"], 4);
+        assert_eq!(out["trace"]["record_traces"][0]["input_index"], 0);
+        assert_eq!(out["trace"]["record_traces"].as_array().unwrap().len(), 1);
+        assert_eq!(out["trace"]["point_counts"], json!([4, 4, 4]));
+        assert_eq!(out["trace"]["truncated"], true);
+    }
+
+    #[test]
+    fn test_metrics_endpoint() {
+        let svc = service();
+        let req = json!({ "name": "filter-sales", "input": INPUT });
+        svc.handle("POST", "/run", &req.to_string());
+        svc.handle(
+            "POST",
+            "/run",
+            &json!({ "pipeline": "PIPE CONSOLE | STAMP 0,20 ENV.PATH | CONSOLE" }).to_string(),
+        );
+        let resp = svc.handle("GET", "/metrics", "");
+        assert_eq!(resp.status, 200);
+        assert!(
+            resp.body
+                .contains("pipelines_records_in_total{pipeline=\"filter-sales\"} 2")
+        );
+        assert!(
+            resp.body
+                .contains("pipelines_jobs_total{pipeline=\"inline\",status=\"error\"} 1")
+        );
+        assert!(resp.body.contains("stage=\"FILTER\"} 1"));
+    }
+
+    #[test]
+    fn test_env_not_readable() {
+        let req =
+            json!({ "pipeline": "PIPE CONSOLE | STAMP 0,20 ENV.PATH | CONSOLE", "input": "a" });
+        for endpoint in ["/run", "/stats", "/trace"] {
+            let resp = service().handle("POST", endpoint, &req.to_string());
+            assert_eq!(resp.status, 422);
+            assert!(resp.body.contains("not available"), "{}", resp.body);
+        }
+    }
+
+    #[test]
+    fn test_rejects_server_stages() {
+        for pipeline in [
+            "PIPE < /etc/passwd | CONSOLE",
+            "PIPE CONSOLE | > /tmp/out.txt",
+            "PIPE CONSOLE | XLATE FILE /etc/passwd | CONSOLE",
+            "PIPE CONSOLE | PLUGIN rot13 | CONSOLE",
+            "PIPE CONSOLE | DECRYPT 0,44 KEYFILE /etc/key | CONSOLE",
+        ] {
+            let req = json!({ "pipeline": pipeline, "input": INPUT });
+            let resp = service().handle("POST", "/run", &req.to_string());
+            assert_eq!(resp.status, 403, "{pipeline}: {}", resp.body);
+            assert!(
+                body(&resp)["error"]
+                    .as_str()
+                    .unwrap()
+                    .contains("not allowed")
+            );
+        }
+        let req = json!({ "pipeline": "PIPE CONSOLE | XLATE UPPER | CONSOLE", "input": INPUT });
+        assert_eq!(
+            service().handle("POST", "/run", &req.to_string()).status,
+            200
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let svc = service().with_limits(Limits {
+            max_input: 100,
+            max_delay_ms: 50,
+            max_copies: 10,
+            max_trace: 2,
+        });
+        let status = |pipeline: &str, input: &str| {
+            let req = json!({ "pipeline": pipeline, "input": input });
+            svc.handle("POST", "/run", &req.to_string()).status
+        };
+        assert_eq!(status("PIPE CONSOLE | CONSOLE", INPUT), 200);
+        assert_eq!(status("PIPE CONSOLE | CONSOLE", &"A\n".repeat(60)), 413);
+        assert_eq!(status("PIPE CONSOLE | DUPLICATE 9 | CONSOLE", INPUT), 200);
+        assert_eq!(
+            status("PIPE CONSOLE | DUPLICATE 3 | DUPLICATE 2 | CONSOLE", INPUT),
+            422
+        );
+        assert_eq!(status("PIPE CONSOLE | DELAY 20 MS | CONSOLE", INPUT), 200);
+        assert_eq!(
+            status("PIPE CONSOLE | DUPLICATE 1 | DELAY 20 MS | CONSOLE", INPUT),
+            422
+        );
+
+        // A trace holds at most `max_trace` records, even if asked for more
+        for trace_max in [None, Some(100)] {
+            let req = json!({
+                "pipeline": "PIPE CONSOLE | UPPER | CONSOLE",
+                "input": "a\nb\nc",
+                "trace_max": trace_max,
+            });
+            let out = body(&svc.handle("POST", "/trace", &req.to_string()));
+            assert_eq!(out["output_count"], 3);
+            assert!(out["trace"]["record_traces"].as_array().unwrap().is_empty());
+            assert_eq!(out["trace"]["truncated"], true);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let svc = service();
+        assert_eq!(svc.handle("POST", "/run", "not json").status, 400);
+        assert_eq!(svc.handle("POST", "/run", "{}").status, 400);
+        let req = json!({ "pipeline": "PIPE BOGUS | CONSOLE" });
+        for endpoint in ["/run", "/stats", "/trace"] {
+            let resp = svc.handle("POST", endpoint, &req.to_string());
+            assert_eq!(resp.status, 422, "{endpoint}");
+            assert!(resp.body.contains("BOGUS"), "{}", resp.body);
+        }
+        // A pipeline that does not parse is rejected before it is run, so
+        // it is not counted as a job
+        assert!(!svc.render_metrics().body.contains("status=\"error\""));
+        assert_eq!(svc.handle("DELETE", "/run", "").status, 404);
+    }
+}
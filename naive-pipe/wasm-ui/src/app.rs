@@ -1,6 +1,7 @@
 //! Main application component.
 
 use gloo::timers::callback::Timeout;
+use naive_pipe::RatDebugTrace;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::JsCast;
@@ -9,8 +10,11 @@ use web_sys::{Blob, HtmlAnchorElement, HtmlInputElement, HtmlSelectElement, Url}
 use yew::prelude::*;
 
 use crate::components::{InputPanel, OutputPanel, PipelinePanel};
-use crate::debugger::{DebuggerPanel, DebuggerState};
-use crate::dsl::{execute_pipeline, execute_pipeline_debug, parse_pipeline_lines};
+use crate::debugger::{DebuggerPanel, DebuggerState, stage_delays, trace_pipeline_lines};
+use crate::dsl::{
+    PipelineLine, execute_pipeline, execute_pipeline_debug, explain_pipeline, parse_pipeline_lines,
+    pipeline_layout,
+};
 
 /// Render CSS-animated countdown with cycling dots.
 fn countdown_html(countdown: u32, prefix: &str, suffix: &str) -> Html {
@@ -178,10 +182,19 @@ pub const TUTORIALS: &[TutorialStep] = &[
     TutorialStep {
         name: "DUPLICATE",
         description: "DUPLICATE outputs each record multiple times.\n\n\
-            Syntax: DUPLICATE n\n\n\
-            Each input record is output n times.\n\
-            Useful for testing or data generation.",
-        example_pipeline: "# Duplicate: triple each record\nPIPE CONSOLE\n| TAKE 2\n| DUPLICATE 3\n| CONSOLE\n?",
+            Syntax: DUPLICATE [n]\n\n\
+            Each input record is output, followed by n more copies\n\
+            (1 if n is omitted). Useful for testing or data generation.",
+        example_pipeline: "# Duplicate: triple each record\nPIPE CONSOLE\n| TAKE 2\n| DUPLICATE 2\n| CONSOLE\n?",
+    },
+    TutorialStep {
+        name: "DELAY",
+        description: "DELAY paces records through the pipeline.\n\n\
+            Syntax: DELAY n ms\n\n\
+            Each record pauses n milliseconds before passing on.\n\
+            Load this example in the Debug tab and press Replay\n\
+            to watch records flow one at a time, lingering at DELAY.",
+        example_pipeline: "# Delay: watch records trickle through (use Debug > Replay)\nPIPE CONSOLE\n| LOCATE /SALES/\n| DELAY 1000 ms\n| UPPER\n| CONSOLE\n?",
     },
 ];
 
@@ -253,30 +266,11 @@ fn initialize_debugger(state: &mut AppState) {
     let lines = parse_pipeline_lines(&state.pipeline_text);
     match execute_pipeline_debug(&state.input_text, &state.pipeline_text) {
         Ok((output, input_count, output_count, trace)) => {
-            let stage_count = trace.stage_names.len();
-            state.debugger_state.active = true;
-            state.debugger_state.trace = Some(trace);
-            state.debugger_state.current_step = 0;
-            state.debugger_state.trace_idx = 0;
-            state.debugger_state.visible_pp = 0;
-            state.debugger_state.in_flush_phase = false;
-            state.debugger_state.accumulated_output = String::new();
-            state.debugger_state.stage_count = stage_count;
             state.debugger_state.output_text = output;
             state.debugger_state.input_count = input_count;
             state.debugger_state.output_count = output_count;
-            state.debugger_state.pipeline_lines = lines;
-            state.debugger_state.error = None;
-            state.debugger_state.hit_breakpoint = None;
-            state.debugger_state.total_steps = state.debugger_state.compute_total_steps();
-            state
-                .debugger_state
-                .watches
-                .retain(|w| w.stage_index < stage_count);
-            state
-                .debugger_state
-                .breakpoints
-                .retain(|b| b.stage_index < stage_count);
+            state.debugger_state.stage_delays = stage_delays(&state.pipeline_text);
+            load_trace(state, trace, lines);
         }
         Err(e) => {
             state.debugger_state.active = true;
@@ -299,6 +293,99 @@ fn initialize_debugger(state: &mut AppState) {
     state.error = None;
 }
 
+/// Put the debugger at step 0 of `trace`, keeping the watches and
+/// breakpoints on stages it has. The caller sets the output and counts.
+fn load_trace(state: &mut AppState, trace: RatDebugTrace, lines: Vec<PipelineLine>) {
+    let stage_count = trace.stage_names.len();
+    state.debugger_state.active = true;
+    state.debugger_state.trace = Some(trace);
+    state.debugger_state.current_step = 0;
+    state.debugger_state.trace_idx = 0;
+    state.debugger_state.visible_pp = 0;
+    state.debugger_state.in_flush_phase = false;
+    state.debugger_state.accumulated_output = String::new();
+    state.debugger_state.stage_count = stage_count;
+    state.debugger_state.pipeline_lines = lines;
+    state.debugger_state.error = None;
+    state.debugger_state.hit_breakpoint = None;
+    state.debugger_state.replaying = false;
+    state.debugger_state.clear_watch_values();
+    state.debugger_state.total_steps = state.debugger_state.compute_total_steps();
+    state
+        .debugger_state
+        .watches
+        .retain(|w| w.stage_index < stage_count);
+    state
+        .debugger_state
+        .breakpoints
+        .retain(|b| b.stage_index < stage_count);
+}
+
+/// Load a trace saved by Export or `pipe-run-rat --trace` into the
+/// debugger. Its output is what the traced records produced.
+fn import_trace(state: &mut AppState, json: &str) {
+    let trace = match RatDebugTrace::from_json(json) {
+        Ok(trace) => trace,
+        Err(e) => {
+            state.debugger_state.error = Some(e);
+            return;
+        }
+    };
+    let lines = trace_pipeline_lines(&trace, &state.pipeline_text);
+    let same_pipeline = lines == parse_pipeline_lines(&state.pipeline_text);
+    let output: Vec<&str> = trace
+        .record_traces
+        .iter()
+        .map(|rt| &rt.pipe_points)
+        .chain(trace.flush_traces.iter().map(|ft| &ft.pipe_points))
+        .filter_map(|pipe_points| pipe_points.last())
+        .flatten()
+        .map(|r| r.as_str().trim_end())
+        .collect();
+    state.debugger_state.output_text = output.join("\n");
+    state.debugger_state.input_count = trace.input_count();
+    state.debugger_state.output_count = trace.point_counts.last().copied().unwrap_or(0);
+    state.debugger_state.stage_delays = if same_pipeline {
+        stage_delays(&state.pipeline_text)
+    } else {
+        Vec::new()
+    };
+    load_trace(state, trace, lines);
+    state.output_text.clear();
+    state.stats.clear();
+    state.error = None;
+}
+
+/// Offer `text` to the browser as a download named `file_name`.
+fn download(text: &str, file_name: &str) {
+    let array = js_sys::Array::new();
+    array.push(&JsValue::from_str(text));
+
+    let blob = Blob::new_with_str_sequence(&array).unwrap();
+    let url = Url::create_object_url_with_blob(&blob).unwrap();
+
+    let window = web_sys::window().unwrap();
+    let document = window.document().unwrap();
+    let anchor: HtmlAnchorElement = document.create_element("a").unwrap().dyn_into().unwrap();
+
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    let _ = Url::revoke_object_url(&url);
+}
+
+/// Show the debugger's progressively accumulated output in the output panel.
+fn sync_debug_output(state: &mut AppState) {
+    state.output_text = state.debugger_state.accumulated_output.clone();
+    let out_lines = state.output_text.lines().count();
+    state.stats = format!(
+        "Input: {} records | Output: {} records",
+        state.debugger_state.input_count, out_lines,
+    );
+    state.error = None;
+}
+
 /// Main application component.
 #[function_component(App)]
 pub fn app() -> Html {
@@ -388,25 +475,7 @@ pub fn app() -> Html {
 
     let on_save = {
         let state = state.clone();
-        Callback::from(move |_| {
-            let text = state.pipeline_text.clone();
-            let array = js_sys::Array::new();
-            array.push(&JsValue::from_str(&text));
-
-            let blob = Blob::new_with_str_sequence(&array).unwrap();
-            let url = Url::create_object_url_with_blob(&blob).unwrap();
-
-            let window = web_sys::window().unwrap();
-            let document = window.document().unwrap();
-            let anchor: HtmlAnchorElement =
-                document.create_element("a").unwrap().dyn_into().unwrap();
-
-            anchor.set_href(&url);
-            anchor.set_download("pipeline.pipe");
-            anchor.click();
-
-            let _ = Url::revoke_object_url(&url);
-        })
+        Callback::from(move |_| download(&state.pipeline_text, "pipeline.pipe"))
     };
 
     // Tutorial dropdown change handler
@@ -521,6 +590,27 @@ pub fn app() -> Html {
         })
     };
 
+    // Plan button: explain the pipeline in the output panel
+    let on_plan = {
+        let state = state.clone();
+        Callback::from(move |_: ()| {
+            let mut new_state = (*state).clone();
+            match explain_pipeline(&new_state.pipeline_text) {
+                Ok((explanation, warnings)) => {
+                    new_state.output_text = explanation;
+                    new_state.error = None;
+                    new_state.stats = format!("Plan: {} warnings", warnings);
+                }
+                Err(e) => {
+                    new_state.output_text.clear();
+                    new_state.error = Some(e);
+                    new_state.stats.clear();
+                }
+            }
+            state.set(new_state);
+        })
+    };
+
     // Clear output button
     let on_clear = {
         let state = state.clone();
@@ -581,6 +671,47 @@ pub fn app() -> Html {
         })
     };
 
+    // Debugger: import a trace saved as JSON
+    let on_debug_import = {
+        let state = state.clone();
+        Callback::from(move |e: web_sys::Event| {
+            let state = state.clone();
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some(files) = input.files()
+                && let Some(file) = files.get(0)
+            {
+                let reader = web_sys::FileReader::new().unwrap();
+                let reader_clone = reader.clone();
+
+                let onload = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                    if let Ok(result) = reader_clone.result()
+                        && let Some(text) = result.as_string()
+                    {
+                        let mut new_state = (*state).clone();
+                        import_trace(&mut new_state, &text);
+                        state.set(new_state);
+                    }
+                }) as Box<dyn FnMut(_)>);
+
+                reader.set_onload(Some(onload.as_ref().unchecked_ref()));
+                onload.forget();
+
+                let _ = reader.read_as_text(&file);
+            }
+            input.set_value("");
+        })
+    };
+
+    // Debugger: save the trace as JSON
+    let on_debug_export = {
+        let state = state.clone();
+        Callback::from(move |_: ()| {
+            if let Some(trace) = &state.debugger_state.trace {
+                download(&trace.to_json(), "trace.json");
+            }
+        })
+    };
+
     // Debugger: run pipeline with debug trace
     let on_debug_run = {
         let state = state.clone();
@@ -603,13 +734,8 @@ pub fn app() -> Html {
                         break;
                     }
                 }
-                new_state.output_text = new_state.debugger_state.accumulated_output.clone();
-                let out_lines = new_state.output_text.lines().count();
-                new_state.stats = format!(
-                    "Input: {} records | Output: {} records",
-                    new_state.debugger_state.input_count, out_lines,
-                );
-                new_state.error = None;
+                new_state.debugger_state.replaying = false;
+                sync_debug_output(&mut new_state);
                 state.set(new_state);
                 return;
             }
@@ -625,32 +751,47 @@ pub fn app() -> Html {
         Callback::from(move |_: ()| {
             let mut new_state = (*state).clone();
             new_state.debugger_state.hit_breakpoint = None;
+            new_state.debugger_state.replaying = false;
             new_state.debugger_state.advance();
 
             // Update output panel progressively
-            new_state.output_text = new_state.debugger_state.accumulated_output.clone();
-            let out_lines = new_state.output_text.lines().count();
-            new_state.stats = format!(
-                "Input: {} records | Output: {} records",
-                new_state.debugger_state.input_count, out_lines,
-            );
-            new_state.error = None;
+            sync_debug_output(&mut new_state);
 
             state.set(new_state);
         })
     };
 
+    // Debugger: step back one pipe point
+    let on_debug_step_back = {
+        let state = state.clone();
+        Callback::from(move |_: ()| {
+            let mut new_state = (*state).clone();
+            new_state.debugger_state.step_back();
+            sync_debug_output(&mut new_state);
+            state.set(new_state);
+        })
+    };
+
+    // Debugger: jump to a step
+    let on_debug_jump = {
+        let state = state.clone();
+        Callback::from(move |step: usize| {
+            let mut new_state = (*state).clone();
+            if new_state.debugger_state.current_step == step {
+                return;
+            }
+            new_state.debugger_state.jump_to(step);
+            sync_debug_output(&mut new_state);
+            state.set(new_state);
+        })
+    };
+
     // Debugger: reset to step 0
     let on_debug_reset = {
         let state = state.clone();
         Callback::from(move |_: ()| {
             let mut new_state = (*state).clone();
-            new_state.debugger_state.current_step = 0;
-            new_state.debugger_state.trace_idx = 0;
-            new_state.debugger_state.visible_pp = 0;
-            new_state.debugger_state.in_flush_phase = false;
-            new_state.debugger_state.accumulated_output = String::new();
-            new_state.debugger_state.hit_breakpoint = None;
+            new_state.debugger_state.rewind();
             // Clear output panel on reset
             new_state.output_text.clear();
             new_state.stats.clear();
@@ -659,6 +800,38 @@ pub fn app() -> Html {
         })
     };
 
+    // Debugger: start or pause replay (restarts from step 0 when finished)
+    let on_debug_replay = {
+        let state = state.clone();
+        Callback::from(move |_: ()| {
+            let mut new_state = (*state).clone();
+            if new_state.debugger_state.replaying {
+                new_state.debugger_state.replaying = false;
+                state.set(new_state);
+                return;
+            }
+            if !new_state.debugger_state.active {
+                initialize_debugger(&mut new_state);
+            } else if new_state.debugger_state.is_finished() {
+                new_state.debugger_state.rewind();
+                sync_debug_output(&mut new_state);
+            }
+            new_state.debugger_state.hit_breakpoint = None;
+            new_state.debugger_state.replaying = new_state.debugger_state.trace.is_some();
+            state.set(new_state);
+        })
+    };
+
+    // Debugger: change replay speed
+    let on_debug_replay_speed = {
+        let state = state.clone();
+        Callback::from(move |ms: u32| {
+            let mut new_state = (*state).clone();
+            new_state.debugger_state.replay_interval_ms = ms;
+            state.set(new_state);
+        })
+    };
+
     // Debugger: toggle watch at pipe point
     let on_toggle_watch = {
         let state = state.clone();
@@ -679,6 +852,32 @@ pub fn app() -> Html {
         })
     };
 
+    // Debugger: set or clear a breakpoint's condition
+    let on_set_breakpoint_condition = {
+        let state = state.clone();
+        Callback::from(move |(stage_index, text): (usize, String)| {
+            let mut new_state = (*state).clone();
+            let layout = pipeline_layout(&new_state.pipeline_text);
+            new_state
+                .debugger_state
+                .set_breakpoint_condition(stage_index, &text, &layout);
+            state.set(new_state);
+        })
+    };
+
+    // Debugger: set or clear the field a watch shows
+    let on_set_watch_field = {
+        let state = state.clone();
+        Callback::from(move |(label, text): (String, String)| {
+            let mut new_state = (*state).clone();
+            let layout = pipeline_layout(&new_state.pipeline_text);
+            new_state
+                .debugger_state
+                .set_watch_field(&label, &text, &layout);
+            state.set(new_state);
+        })
+    };
+
     // Debugger: remove watch by label
     let on_remove_watch = {
         let state = state.clone();
@@ -787,6 +986,35 @@ pub fn app() -> Html {
         );
     }
 
+    // Replay timer: each revealed step schedules the next one, pausing
+    // longer at DELAY stages. Stops at a breakpoint or the end.
+    {
+        let state = state.clone();
+        let replaying = state.debugger_state.replaying;
+        let current_step = state.debugger_state.current_step;
+
+        use_effect_with((replaying, current_step), move |(replaying, _)| {
+            let mut handle = None;
+            if *replaying {
+                let delay = state.debugger_state.replay_delay_ms();
+                handle = Some(Timeout::new(delay, move || {
+                    let mut new_state = (*state).clone();
+                    let hit = new_state.debugger_state.advance();
+                    if hit || new_state.debugger_state.is_finished() {
+                        new_state.debugger_state.replaying = false;
+                    }
+                    sync_debug_output(&mut new_state);
+                    state.set(new_state);
+                }));
+            }
+            move || {
+                if let Some(handle) = handle {
+                    handle.cancel();
+                }
+            }
+        });
+    }
+
     // Get current tutorial info for rendering
     let current_tutorial = state.tutorial_step.and_then(|idx| TUTORIALS.get(idx));
     let next_tutorial_name = state
@@ -874,6 +1102,7 @@ pub fn app() -> Html {
                                 on_run={on_run.clone()}
                                 on_load={on_load}
                                 on_save={on_save}
+                                on_plan={on_plan}
                                 show_run_tooltip={state.tutorial_phase == TutorialPhase::ShowingRunTooltip}
                                 on_tooltip_dismiss={on_tutorial_cancel.clone()}
                                 auto_mode={state.auto_mode}
@@ -886,12 +1115,20 @@ pub fn app() -> Html {
                                 state={state.debugger_state.clone()}
                                 on_run={on_debug_run}
                                 on_step={on_debug_step}
+                                on_step_back={on_debug_step_back}
+                                on_jump={on_debug_jump}
                                 on_reset={on_debug_reset}
+                                on_replay={on_debug_replay}
+                                on_replay_speed={on_debug_replay_speed}
                                 on_toggle_watch={on_toggle_watch}
                                 on_toggle_breakpoint={on_toggle_breakpoint}
+                                on_set_breakpoint_condition={on_set_breakpoint_condition}
                                 on_remove_watch={on_remove_watch}
+                                on_set_watch_field={on_set_watch_field}
                                 on_load_example={on_debug_load_example}
                                 on_load_file={on_debug_load_file}
+                                on_import={on_debug_import}
+                                on_export={on_debug_export}
                             />
                         }
                     }}
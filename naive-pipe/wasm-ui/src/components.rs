@@ -2,6 +2,8 @@
 
 use yew::prelude::*;
 
+use crate::dsl::lint_pipeline;
+
 /// Input panel for entering records.
 #[derive(Properties, PartialEq)]
 pub struct InputPanelProps {
@@ -50,6 +52,8 @@ pub struct PipelinePanelProps {
     pub on_run: Callback<()>,
     pub on_load: Callback<web_sys::Event>,
     pub on_save: Callback<()>,
+    #[prop_or_default]
+    pub on_plan: Callback<()>,
     #[prop_or(false)]
     pub show_run_tooltip: bool,
     #[prop_or_default]
@@ -91,6 +95,13 @@ pub fn pipeline_panel(props: &PipelinePanelProps) -> Html {
         })
     };
 
+    let on_plan_click = {
+        let on_plan = props.on_plan.clone();
+        Callback::from(move |_| {
+            on_plan.emit(());
+        })
+    };
+
     let on_dismiss = {
         let on_tooltip_dismiss = props.on_tooltip_dismiss.clone();
         Callback::from(move |_| {
@@ -98,6 +109,8 @@ pub fn pipeline_panel(props: &PipelinePanelProps) -> Html {
         })
     };
 
+    let diagnostics = lint_pipeline(&props.value);
+
     html! {
         <div class="panel pipeline-panel">
             <div class="panel-header">
@@ -110,6 +123,9 @@ pub fn pipeline_panel(props: &PipelinePanelProps) -> Html {
                     <button class="save-button" onclick={on_save_click}>
                         { "Save" }
                     </button>
+                    <button class="save-button" onclick={on_plan_click} title="Show stages, fields, and warnings without running">
+                        { "Plan" }
+                    </button>
                     <div class="run-button-container">
                         <button class="run-button" onclick={on_run_click}>
                             { "Run" }
@@ -139,6 +155,15 @@ pub fn pipeline_panel(props: &PipelinePanelProps) -> Html {
                     rows="8"
                     placeholder="Enter pipeline commands..."
                 />
+                if !diagnostics.is_empty() {
+                    <div class="lint-markers">
+                        { for diagnostics.into_iter().map(|(text, error)| html! {
+                            <pre class={classes!("lint-marker", if error { "lint-error" } else { "lint-warning" })}>
+                                { text }
+                            </pre>
+                        }) }
+                    </div>
+                }
                 <div class="dsl-help">
                     <details>
                         <summary>{ "DSL Reference" }</summary>
@@ -148,23 +173,40 @@ pub fn pipeline_panel(props: &PipelinePanelProps) -> Html {
 ?                          - End of pipeline
 # comment                  - Comments ignored
 
+< file                     - Start: read a file (Input Records here)
+> file, >> file            - Write/append a file (passes through here)
+BETWEEN /start/ /end/      - Keep sections from start to end
 CHANGE /old/new/           - Replace text (any delimiter)
 CONSOLE                    - Pass through (middle), debug output
 COUNT                      - Output record count
-DUPLICATE n                - Repeat each record n times
+DROP LAST n                - Drop last n records (e.g. trailer)
+DUMP [HEX|RULER]           - Show records as hex or under a ruler
+DUPLICATE [n]              - Write each record and n more copies
 FILTER pos,len = "v"       - Keep matching records
 FILTER pos,len != "v"      - Omit matching records
+FROM CSV [HEADER]          - Start: read CSV into LAYOUT fields
 HOLE                       - Discard all input (like /dev/null)
 LITERAL text               - Prefix literal record
 LOCATE /pattern/           - Keep records containing pattern
 LOCATE pos,len /pattern/   - Keep if field contains pattern
 LOWER                      - Convert to lowercase
 NLOCATE /pattern/          - Keep records NOT containing pattern
+OVERLAY p,l,d; p,l,d       - Copy fields, keeping the rest of the record
+PAD n [c]                  - Fill records with c out to n columns
+RECNO 1-10, 50 BY 5        - Keep records by position (from 1)
+REFORMAT DATE p f TO f     - Rewrite a date (YYYYMMDD, MMDDYY, YYDDD)
 REVERSE                    - Reverse characters in record
+SAMPLE f [SEED n]          - Keep a random fraction f of records
 SELECT p,l,d; p,l,d        - Select fields (src,len,dest)
 SKIP n                     - Skip first n records
+SQUISH                     - Squeeze runs of blanks to one
 TAKE n                     - Keep first n records
-UPPER                      - Convert to uppercase"#}</pre>
+TAKE LAST n                - Keep last n records
+TO CSV [HEADER]            - Write LAYOUT fields as CSV lines
+TRUNC n                    - Keep first n columns
+UNIQUE [pos,len]           - Keep first record of each key
+UPPER                      - Convert to uppercase
+XLATE [p,l] [LOWER] [a b]  - Translate characters (a to b)"#}</pre>
                     </details>
                 </div>
             </div>
@@ -2,11 +2,29 @@
 //!
 //! Stepping is per-pipe-point: each step reveals the next pipe point
 //! in a record's journey through the pipeline. When a record is filtered
-//! (empty pipe point), the next step moves to the next record. After all
-//! records, flush traces are stepped similarly.
+//! (empty pipe point), the next step moves to the next record, and the
+//! empty pipe point says which stage dropped it and why, as the trace
+//! recorded it: `dropped by FILTER 18,10 = "SALES" (value was
+//! "MARKETING")`. After all records, flush traces are stepped similarly.
 //!
 //! As records reach the final stage, their output appears progressively
-//! in the output panel (not buffered until the end).
+//! in the output panel (not buffered until the end). Watched records mark
+//! the columns the stage before the watch changed. A watch on a field
+//! (`18,10`, or a `LAYOUT` name) shows just that field of each record, and
+//! marks the values that changed since the watch last showed data.
+//!
+//! Every step is captured up front, so the debugger can also step back or
+//! jump to any step: it rewinds and replays the steps before it, which
+//! rebuilds the output and watch values exactly as stepping forward did.
+//!
+//! A breakpoint can carry a condition, so Run stops only at the steps
+//! where it holds: a FILTER condition on a record at the pipe point
+//! (`18,10 = "SALES"`), or a test of the input record number
+//! (`record == 57`).
+//!
+//! Export saves the trace as JSON, and Import loads one saved there or by
+//! `pipe-run-rat --trace`, to step through without running the pipeline
+//! in the browser.
 //!
 //! **Indexing note:** `execute_pipeline_rat_debug` handles the source
 //! stage separately. `trace.stage_names` excludes the source;
@@ -14,12 +32,16 @@
 //! `pipeline_lines` includes ALL stages (source at index 0). The pipe
 //! point between pipeline stage `i` and `i+1` maps to `pipe_points[i]`.
 
-use naive_pipe::RatDebugTrace;
-use web_sys::HtmlSelectElement;
+use naive_pipe::{DroppedRecord, RatDebugTrace, changed_columns};
+use pipelines_rs::{
+    ColumnDiff, Command, CompareOp, Condition, Record, RecordLayout, parse_commands,
+    parse_condition,
+};
+use web_sys::{HtmlInputElement, HtmlSelectElement};
 use yew::prelude::*;
 
 use crate::app::TUTORIALS;
-use crate::dsl::PipelineLine;
+use crate::dsl::{PipelineLine, parse_pipeline_lines, to_dsl};
 
 const DOTS: &str = "\u{00B7}\u{00B7}\u{00B7}";
 
@@ -30,12 +52,113 @@ pub struct Watch {
     pub label: String,
     /// Pipeline stage index of the stage above this pipe point.
     pub stage_index: usize,
+    /// Field spec as entered; empty to watch whole records.
+    pub field_text: String,
+    /// The watched field, or why `field_text` does not parse.
+    pub field: Result<Option<WatchField>, String>,
+    /// Field values the watch shows: those at the latest step that reached
+    /// its pipe point with records.
+    pub values: Vec<String>,
+    /// Field values it showed before they last changed.
+    pub previous: Vec<String>,
+}
+
+/// A field range a watch shows instead of whole records.
+#[derive(Clone, PartialEq)]
+pub struct WatchField {
+    /// The `LAYOUT` field name, if the field was given by name.
+    pub name: Option<String>,
+    pub pos: usize,
+    pub len: usize,
+}
+
+impl WatchField {
+    /// Parses `pos,len`, or a field name declared by `layout`.
+    pub fn parse(text: &str, layout: &RecordLayout) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some((pos, len)) = text.split_once(',') {
+            let number = |s: &str| {
+                s.trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Expected pos,len, got '{text}'"))
+            };
+            let (pos, len) = (number(pos)?, number(len)?);
+            if len == 0 {
+                return Err("Field length must be at least 1".to_string());
+            }
+            return Ok(WatchField {
+                name: None,
+                pos,
+                len,
+            });
+        }
+        layout
+            .fields()
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(text))
+            .map(|f| WatchField {
+                name: Some(f.name.clone()),
+                pos: f.start,
+                len: f.len,
+            })
+            .ok_or_else(|| format!("Unknown field '{text}' (use pos,len or a LAYOUT name)"))
+    }
+
+    /// "DEPT 18,10" or "18,10".
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{name} {},{}", self.pos, self.len),
+            None => format!("{},{}", self.pos, self.len),
+        }
+    }
 }
 
 /// A breakpoint at a pipe point between stages.
 #[derive(Clone, PartialEq)]
 pub struct Breakpoint {
     pub stage_index: usize,
+    /// Condition as entered; empty to stop at every step.
+    pub condition_text: String,
+    /// The parsed condition, or why `condition_text` does not parse.
+    pub condition: Result<Option<BreakCondition>, String>,
+}
+
+/// What a conditional breakpoint tests when a step reveals its pipe point.
+#[derive(Clone, PartialEq)]
+pub enum BreakCondition {
+    /// The current input record's number, counting from 1, compares with
+    /// this number: `record == 57`. Never holds for flush output.
+    Record(CompareOp, usize),
+    /// A record at the pipe point meets a FILTER condition.
+    Field(Condition),
+}
+
+impl BreakCondition {
+    /// Parses `record OP N`, or else a FILTER condition whose fields may be
+    /// named by `layout`.
+    pub fn parse(text: &str, layout: &RecordLayout) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if let [keyword, op, n] = words[..]
+            && keyword.eq_ignore_ascii_case("record")
+        {
+            let op = CompareOp::from_symbol(op)
+                .ok_or_else(|| format!("Unknown operator '{op}' in record condition"))?;
+            let n = n
+                .parse()
+                .map_err(|_| format!("Expected a record number, got '{n}'"))?;
+            return Ok(BreakCondition::Record(op, n));
+        }
+        parse_condition(text, layout).map(BreakCondition::Field)
+    }
+
+    /// Returns true if the condition holds for the records at the pipe
+    /// point of the input record numbered `record` (`None` in a flush).
+    fn holds(&self, records: &[Record], record: Option<usize>) -> bool {
+        match self {
+            BreakCondition::Record(op, n) => record.is_some_and(|r| op.compare(r, *n)),
+            BreakCondition::Field(condition) => records.iter().any(|r| condition.matches(r)),
+        }
+    }
 }
 
 /// Debugger state (stored in AppState).
@@ -66,6 +189,12 @@ pub struct DebuggerState {
     pub output_count: usize,
     pub pipeline_lines: Vec<PipelineLine>,
     pub error: Option<String>,
+    /// True while replay mode is auto-stepping.
+    pub replaying: bool,
+    /// Base time between replay steps, in milliseconds.
+    pub replay_interval_ms: u32,
+    /// Extra pause per pipeline stage (from DELAY commands), in milliseconds.
+    pub stage_delays: Vec<u64>,
 }
 
 impl Default for DebuggerState {
@@ -89,10 +218,57 @@ impl Default for DebuggerState {
             output_count: 0,
             pipeline_lines: Vec::new(),
             error: None,
+            replaying: false,
+            replay_interval_ms: 500,
+            stage_delays: Vec::new(),
         }
     }
 }
 
+/// The pipeline lines to show for an imported trace: the editor's pipeline
+/// if its stages are the traced ones, otherwise the traced stage names
+/// after a line for the source.
+pub fn trace_pipeline_lines(trace: &RatDebugTrace, pipeline_text: &str) -> Vec<PipelineLine> {
+    let lines = parse_pipeline_lines(pipeline_text);
+    let same_stages = lines.len() == trace.stage_names.len() + 1
+        && lines[1..]
+            .iter()
+            .zip(&trace.stage_names)
+            .all(|(line, name)| {
+                line.text
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|verb| verb.eq_ignore_ascii_case(name))
+            });
+    if same_stages {
+        return lines;
+    }
+    std::iter::once("(input)")
+        .chain(trace.stage_names.iter().map(String::as_str))
+        .enumerate()
+        .map(|(stage_index, text)| PipelineLine {
+            text: text.to_string(),
+            stage_index,
+        })
+        .collect()
+}
+
+/// Extra replay pause for each pipeline stage: the DELAY time for DELAY
+/// stages, zero otherwise. Empty if the pipeline does not parse.
+pub fn stage_delays(pipeline_text: &str) -> Vec<u64> {
+    parse_commands(&to_dsl(pipeline_text))
+        .map(|commands| {
+            commands
+                .iter()
+                .map(|cmd| match cmd {
+                    Command::Delay { ms } => *ms,
+                    _ => 0,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Max UI pipe points to reveal for a record trace.
 /// Stops at the first empty pipe point (filter) + 1.
 fn max_pp_for_record(rt: &naive_pipe::RecordTrace) -> usize {
@@ -133,10 +309,105 @@ impl DebuggerState {
         } else {
             let label = format!("w{}", self.next_watch_id);
             self.next_watch_id += 1;
-            self.watches.push(Watch { label, stage_index });
+            self.watches.push(Watch {
+                label,
+                stage_index,
+                field_text: String::new(),
+                field: Ok(None),
+                values: Vec::new(),
+                previous: Vec::new(),
+            });
         }
     }
 
+    /// Sets the field the watch labeled `label` shows; empty text watches
+    /// whole records again.
+    pub fn set_watch_field(&mut self, label: &str, text: &str, layout: &RecordLayout) {
+        if let Some(watch) = self.watches.iter_mut().find(|w| w.label == label) {
+            let text = text.trim();
+            watch.field_text = text.to_string();
+            watch.field = if text.is_empty() {
+                Ok(None)
+            } else {
+                WatchField::parse(text, layout).map(Some)
+            };
+            watch.values.clear();
+            watch.previous.clear();
+        }
+        self.update_field_watches();
+    }
+
+    /// Forgets the field values watches have shown.
+    pub fn clear_watch_values(&mut self) {
+        for watch in &mut self.watches {
+            watch.values.clear();
+            watch.previous.clear();
+        }
+    }
+
+    /// Reads each field watch's field from the records just revealed at
+    /// its pipe point, keeping the values it showed before if they change.
+    fn update_field_watches(&mut self) {
+        let updates: Vec<(usize, Vec<String>)> = self
+            .watches
+            .iter()
+            .enumerate()
+            .filter_map(|(i, watch)| {
+                let Ok(Some(field)) = &watch.field else {
+                    return None;
+                };
+                let (pipe_points, index) = self.revealed_at(watch.stage_index)?;
+                let values: Vec<String> = pipe_points[index]
+                    .iter()
+                    .map(|r| r.field(field.pos, field.len).to_string())
+                    .collect();
+                (!values.is_empty() && values != watch.values).then_some((i, values))
+            })
+            .collect();
+        for (i, values) in updates {
+            let watch = &mut self.watches[i];
+            watch.previous = std::mem::replace(&mut watch.values, values);
+        }
+    }
+
+    /// The pipe points of the current trace entry and the index of the one
+    /// at `stage_index`, if the current step has revealed it.
+    fn revealed_at(&self, stage_index: usize) -> Option<(&[Vec<Record>], usize)> {
+        let trace = self.trace.as_ref()?;
+        if self.current_step == 0 {
+            return None;
+        }
+        if !self.in_flush_phase {
+            let rt = trace.record_traces.get(self.trace_idx)?;
+            (stage_index < self.visible_pp && stage_index < rt.pipe_points.len())
+                .then_some((rt.pipe_points.as_slice(), stage_index))
+        } else {
+            let ft = trace.flush_traces.get(self.trace_idx)?;
+            let offset = stage_index.checked_sub(ft.stage_index + 1)?;
+            (offset < self.visible_pp && offset < ft.pipe_points.len())
+                .then_some((ft.pipe_points.as_slice(), offset))
+        }
+    }
+
+    /// The records the stage before pipe point `stage_index` dropped from
+    /// the current trace, with its reasons, once that pipe point is
+    /// revealed.
+    fn drops_at(&self, stage_index: usize) -> Vec<&DroppedRecord> {
+        let (Some(trace), Some(_)) = (&self.trace, self.revealed_at(stage_index)) else {
+            return Vec::new();
+        };
+        let drops = if self.in_flush_phase {
+            trace.flush_traces.get(self.trace_idx).map(|ft| &ft.drops)
+        } else {
+            trace.record_traces.get(self.trace_idx).map(|rt| &rt.drops)
+        };
+        drops
+            .into_iter()
+            .flatten()
+            .filter(|drop| drop.point + 1 == stage_index)
+            .collect()
+    }
+
     pub fn remove_watch(&mut self, label: &str) {
         self.watches.retain(|w| w.label != label);
     }
@@ -156,7 +427,35 @@ impl DebuggerState {
         {
             self.breakpoints.remove(pos);
         } else {
-            self.breakpoints.push(Breakpoint { stage_index });
+            self.breakpoints.push(Breakpoint {
+                stage_index,
+                condition_text: String::new(),
+                condition: Ok(None),
+            });
+        }
+    }
+
+    /// Sets the condition of the breakpoint at `stage_index`; empty text
+    /// makes it unconditional. A condition that does not parse keeps the
+    /// breakpoint from stopping until it is corrected.
+    pub fn set_breakpoint_condition(
+        &mut self,
+        stage_index: usize,
+        text: &str,
+        layout: &RecordLayout,
+    ) {
+        if let Some(bp) = self
+            .breakpoints
+            .iter_mut()
+            .find(|b| b.stage_index == stage_index)
+        {
+            let text = text.trim();
+            bp.condition_text = text.to_string();
+            bp.condition = if text.is_empty() {
+                Ok(None)
+            } else {
+                BreakCondition::parse(text, layout).map(Some)
+            };
         }
     }
 
@@ -166,6 +465,51 @@ impl DebuggerState {
             .any(|b| b.stage_index == stage_index)
     }
 
+    /// Returns true if a breakpoint at `stage_index` stops the current
+    /// step: it has no condition, or its condition holds for the records
+    /// just revealed there.
+    fn breakpoint_hit(&self, stage_index: usize) -> bool {
+        let Some(bp) = self
+            .breakpoints
+            .iter()
+            .find(|b| b.stage_index == stage_index)
+        else {
+            return false;
+        };
+        match &bp.condition {
+            Ok(None) => true,
+            Ok(Some(condition)) => {
+                let (records, record) = self.revealed_records();
+                condition.holds(records, record)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The records at the pipe point most recently revealed, and the
+    /// number of the input record they came from (`None` in a flush).
+    fn revealed_records(&self) -> (&[Record], Option<usize>) {
+        let Some(trace) = &self.trace else {
+            return (&[], None);
+        };
+        let offset = self.visible_pp.saturating_sub(1);
+        if !self.in_flush_phase {
+            trace
+                .record_traces
+                .get(self.trace_idx)
+                .map_or((&[], None), |rt| {
+                    let records = rt.pipe_points.get(offset).map_or(&[][..], Vec::as_slice);
+                    (records, Some(rt.input_index + 1))
+                })
+        } else {
+            let records = trace
+                .flush_traces
+                .get(self.trace_idx)
+                .and_then(|ft| ft.pipe_points.get(offset));
+            (records.map_or(&[][..], Vec::as_slice), None)
+        }
+    }
+
     fn record_count(&self) -> usize {
         self.trace
             .as_ref()
@@ -234,6 +578,47 @@ impl DebuggerState {
         }
     }
 
+    /// True when every step has been revealed.
+    pub fn is_finished(&self) -> bool {
+        self.active && self.current_step >= self.total_steps
+    }
+
+    /// Return to step 0, keeping the trace, watches, and breakpoints.
+    pub fn rewind(&mut self) {
+        self.current_step = 0;
+        self.trace_idx = 0;
+        self.visible_pp = 0;
+        self.in_flush_phase = false;
+        self.accumulated_output = String::new();
+        self.hit_breakpoint = None;
+        self.replaying = false;
+        self.clear_watch_values();
+    }
+
+    /// Moves to `step` (clamped to the last step) by replaying from step
+    /// 0, ignoring breakpoints. Stops any replay.
+    pub fn jump_to(&mut self, step: usize) {
+        self.rewind();
+        while self.current_step < step.min(self.total_steps) {
+            self.advance();
+        }
+        self.hit_breakpoint = None;
+    }
+
+    /// Goes back one step.
+    pub fn step_back(&mut self) {
+        self.jump_to(self.current_step.saturating_sub(1));
+    }
+
+    /// Time to wait before the next replay step: the base interval plus
+    /// any DELAY on the stage the current record enters next.
+    pub fn replay_delay_ms(&self) -> u32 {
+        let next_stage = self.currently_revealed_pipe_point().map_or(1, |pp| pp + 1);
+        let extra = self.stage_delays.get(next_stage).copied().unwrap_or(0);
+        self.replay_interval_ms
+            .saturating_add(u32::try_from(extra).unwrap_or(u32::MAX))
+    }
+
     /// Advance one granular step. Collects output when a trace completes.
     /// Returns `true` if a breakpoint was hit.
     pub fn advance(&mut self) -> bool {
@@ -260,11 +645,12 @@ impl DebuggerState {
             self.visible_pp = 1;
         }
         self.current_step += 1;
-        if let Some(pp) = self.currently_revealed_pipe_point() {
-            if self.has_breakpoint(pp) {
-                self.hit_breakpoint = Some(pp);
-                return true;
-            }
+        self.update_field_watches();
+        if let Some(pp) = self.currently_revealed_pipe_point()
+            && self.breakpoint_hit(pp)
+        {
+            self.hit_breakpoint = Some(pp);
+            return true;
         }
         false
     }
@@ -341,22 +727,32 @@ pub struct DebuggerProps {
     pub state: DebuggerState,
     pub on_run: Callback<()>,
     pub on_step: Callback<()>,
+    pub on_step_back: Callback<()>,
+    pub on_jump: Callback<usize>,
     pub on_reset: Callback<()>,
+    pub on_replay: Callback<()>,
+    pub on_replay_speed: Callback<u32>,
     pub on_toggle_watch: Callback<usize>,
     pub on_toggle_breakpoint: Callback<usize>,
+    pub on_set_breakpoint_condition: Callback<(usize, String)>,
     pub on_remove_watch: Callback<String>,
+    pub on_set_watch_field: Callback<(String, String)>,
     pub on_load_example: Callback<usize>,
     pub on_load_file: Callback<web_sys::Event>,
+    pub on_import: Callback<web_sys::Event>,
+    pub on_export: Callback<()>,
 }
 
 #[function_component(DebuggerPanel)]
 pub fn debugger_panel(props: &DebuggerProps) -> Html {
     let state = &props.state;
     let file_input_ref = use_node_ref();
+    let trace_input_ref = use_node_ref();
 
     let on_load_select = {
         let cb_example = props.on_load_example.clone();
         let file_ref = file_input_ref.clone();
+        let trace_ref = trace_input_ref.clone();
         Callback::from(move |e: Event| {
             let target: HtmlSelectElement = e.target_unchecked_into();
             let value = target.value();
@@ -364,6 +760,10 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
                 if let Some(input) = file_ref.cast::<web_sys::HtmlInputElement>() {
                     input.click();
                 }
+            } else if value == "import" {
+                if let Some(input) = trace_ref.cast::<web_sys::HtmlInputElement>() {
+                    input.click();
+                }
             } else if let Ok(idx) = value.parse::<usize>() {
                 cb_example.emit(idx);
             }
@@ -376,6 +776,14 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
         let cb = props.on_load_file.clone();
         Callback::from(move |e: Event| cb.emit(e))
     };
+    let on_trace_change = {
+        let cb = props.on_import.clone();
+        Callback::from(move |e: Event| cb.emit(e))
+    };
+    let on_export = {
+        let cb = props.on_export.clone();
+        Callback::from(move |_: MouseEvent| cb.emit(()))
+    };
 
     let on_run = {
         let cb = props.on_run.clone();
@@ -385,15 +793,53 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
         let cb = props.on_step.clone();
         Callback::from(move |_: MouseEvent| cb.emit(()))
     };
+    let on_step_back = {
+        let cb = props.on_step_back.clone();
+        Callback::from(move |_: MouseEvent| cb.emit(()))
+    };
+    let on_jump = {
+        let cb = props.on_jump.clone();
+        Callback::from(move |e: InputEvent| {
+            let target: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(step) = target.value().trim().parse::<usize>() {
+                cb.emit(step);
+            }
+        })
+    };
+    let on_jump_box = {
+        let cb = props.on_jump.clone();
+        Callback::from(move |e: Event| {
+            let target: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(step) = target.value().trim().parse::<usize>() {
+                cb.emit(step);
+            }
+        })
+    };
     let on_reset = {
         let cb = props.on_reset.clone();
         Callback::from(move |_: MouseEvent| cb.emit(()))
     };
+    let on_replay = {
+        let cb = props.on_replay.clone();
+        Callback::from(move |_: MouseEvent| cb.emit(()))
+    };
+    let on_replay_speed = {
+        let cb = props.on_replay_speed.clone();
+        Callback::from(move |e: Event| {
+            let target: HtmlSelectElement = e.target_unchecked_into();
+            if let Ok(ms) = target.value().parse::<u32>() {
+                cb.emit(ms);
+            }
+        })
+    };
 
     let step_label = state.step_label();
     let run_disabled = state.active && state.current_step >= state.total_steps;
     let step_disabled = !state.active || state.current_step >= state.total_steps;
     let reset_disabled = !state.active || state.current_step == 0;
+    let back_disabled = reset_disabled;
+    let jump_disabled = !state.active || state.total_steps == 0;
+    let export_disabled = state.trace.is_none();
 
     html! {
         <div class="panel debugger-panel">
@@ -401,7 +847,7 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
                 <h2>{"Debug"}</h2>
                 <div class="debug-controls">
                     <select class="debug-load-select" onchange={on_load_select}
-                        title="Load an example or upload a .pipe file">
+                        title="Load an example, upload a .pipe file, or import a saved trace">
                         <option value="" disabled=true selected=true>{"Load example..."}</option>
                         <optgroup label="Examples">
                             { for TUTORIALS.iter().enumerate().map(|(idx, t)| {
@@ -411,14 +857,24 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
                             })}
                         </optgroup>
                         <option value="upload">{"Upload .pipe file..."}</option>
+                        <option value="import">{"Import trace (.json)..."}</option>
                     </select>
                     <input type="file" accept=".pipe" ref={file_input_ref}
                         style="display:none" onchange={on_file_change} />
+                    <input type="file" accept=".json,application/json" ref={trace_input_ref}
+                        style="display:none" onchange={on_trace_change} />
                     <button class="debug-btn debug-btn-run" onclick={on_run}
                         disabled={run_disabled}
                         title="Run pipeline">
                         {"Run"}
                     </button>
+                    <button class="debug-btn debug-btn-back"
+                        onclick={on_step_back}
+                        disabled={back_disabled}
+                        title="Step back to the previous pipe point"
+                    >
+                        {"\u{25C0} Back"}
+                    </button>
                     <button class="debug-btn debug-btn-step"
                         onclick={on_step}
                         disabled={step_disabled}
@@ -426,6 +882,24 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
                     >
                         {"Step \u{25B6}"}
                     </button>
+                    <button class="debug-btn debug-btn-replay"
+                        onclick={on_replay}
+                        title="Animate records flowing through the stages"
+                    >
+                        {if state.replaying {"Pause \u{23F8}"} else {"Replay \u{23F5}"}}
+                    </button>
+                    <select class="debug-load-select debug-speed-select" onchange={on_replay_speed}
+                        title="Replay speed">
+                        { for [(1000, "Slow"), (500, "Normal"), (200, "Fast"), (50, "Very fast")]
+                            .iter()
+                            .map(|(ms, label)| html! {
+                                <option value={ms.to_string()}
+                                    selected={state.replay_interval_ms == *ms}>
+                                    {*label}
+                                </option>
+                            })
+                        }
+                    </select>
                     <button class="debug-btn debug-btn-reset"
                         onclick={on_reset}
                         disabled={reset_disabled}
@@ -433,13 +907,38 @@ pub fn debugger_panel(props: &DebuggerProps) -> Html {
                     >
                         {"Reset"}
                     </button>
+                    <button class="debug-btn debug-btn-export"
+                        onclick={on_export}
+                        disabled={export_disabled}
+                        title="Save the trace as JSON, to import later"
+                    >
+                        {"Export"}
+                    </button>
                     <span class="step-counter">{step_label}</span>
                 </div>
             </div>
+            if state.active && state.total_steps > 0 {
+                <div class="debug-timeline">
+                    <input class="debug-step-slider" type="range"
+                        min="0" max={state.total_steps.to_string()}
+                        value={state.current_step.to_string()}
+                        disabled={jump_disabled}
+                        title="Drag to move to any step"
+                        oninput={on_jump} />
+                    <input class="debug-jump-box" type="number"
+                        min="0" max={state.total_steps.to_string()}
+                        value={state.current_step.to_string()}
+                        disabled={jump_disabled}
+                        title="Jump to step"
+                        onchange={on_jump_box} />
+                    <span class="debug-step-total">{format!("/ {}", state.total_steps)}</span>
+                </div>
+            }
             <div class="panel-content debugger-content">
                 { render_error(state) }
                 { render_stage_list(state, &props.on_toggle_watch, &props.on_toggle_breakpoint) }
-                { render_watch_list(state, &props.on_remove_watch) }
+                { render_breakpoint_list(state, &props.on_toggle_breakpoint, &props.on_set_breakpoint_condition) }
+                { render_watch_list(state, &props.on_remove_watch, &props.on_set_watch_field) }
             </div>
         </div>
     }
@@ -529,7 +1028,17 @@ fn render_pipe_point(
     on_toggle_breakpoint: &Callback<usize>,
 ) -> Html {
     let watches = state.watches_at(stage_index);
-    let record_info = pipe_point_info(state, stage_index);
+    let drops = state.drops_at(stage_index);
+    let mut record_info = pipe_point_info(state, stage_index);
+    let dropped = record_info == DOTS && !drops.is_empty();
+    if dropped {
+        record_info = if drops.len() == 1 {
+            format!("dropped by {}", drops[0].reason)
+        } else {
+            format!("{} dropped, first by {}", drops.len(), drops[0].reason)
+        };
+    }
+    let drop_reasons: Vec<&str> = drops.iter().map(|drop| drop.reason.as_str()).collect();
     let has_watch = !watches.is_empty();
     let has_bp = state.has_breakpoint(stage_index);
     let is_bp_hit = state.hit_breakpoint == Some(stage_index);
@@ -563,14 +1072,18 @@ fn render_pipe_point(
     } else {
         "pipe-watch-icon"
     };
-    let bp_class = if has_bp {
-        "pipe-bp-icon active"
-    } else {
-        "pipe-bp-icon"
+    let conditional = state
+        .breakpoints
+        .iter()
+        .any(|b| b.stage_index == stage_index && !b.condition_text.is_empty());
+    let bp_class = match (has_bp, conditional) {
+        (true, true) => "pipe-bp-icon active conditional",
+        (true, false) => "pipe-bp-icon active",
+        _ => "pipe-bp-icon",
     };
 
     html! {
-        <div class={classes!(base_class, is_bp_hit.then_some("pipe-bp-hit"))}>
+        <div class={classes!(base_class, is_bp_hit.then_some("pipe-bp-hit"), dropped.then_some("pipe-dropped"))}>
             <span class={watch_class} onclick={on_watch_click} title="Toggle watch">
                 {"\u{24E6}"}
             </span>
@@ -580,7 +1093,7 @@ fn render_pipe_point(
             { for watches.iter().map(|w| {
                 html! { <span class="watch-label">{&w.label}</span> }
             })}
-            <span class="pipe-info">{record_info}</span>
+            <span class="pipe-info" title={drop_reasons.join("\n")}>{record_info}</span>
         </div>
     }
 }
@@ -643,7 +1156,11 @@ fn format_pipe_point_records(records: &[pipelines_rs::Record]) -> String {
     }
 }
 
-fn render_watch_list(state: &DebuggerState, on_remove_watch: &Callback<String>) -> Html {
+fn render_watch_list(
+    state: &DebuggerState,
+    on_remove_watch: &Callback<String>,
+    on_set_watch_field: &Callback<(String, String)>,
+) -> Html {
     if !state.active {
         return html! {};
     }
@@ -654,33 +1171,110 @@ fn render_watch_list(state: &DebuggerState, on_remove_watch: &Callback<String>)
                 <p class="watch-hint">{"Click \u{24E6} to toggle a watch"}</p>
             } else {
                 { for state.watches.iter().map(|watch| {
-                    render_watch_item(state, watch, on_remove_watch)
+                    render_watch_item(state, watch, on_remove_watch, on_set_watch_field)
                 })}
             }
         </div>
     }
 }
 
-fn render_watch_item(
+fn render_breakpoint_list(
     state: &DebuggerState,
-    watch: &Watch,
-    on_remove_watch: &Callback<String>,
+    on_toggle_breakpoint: &Callback<usize>,
+    on_set_condition: &Callback<(usize, String)>,
+) -> Html {
+    if !state.active || state.breakpoints.is_empty() {
+        return html! {};
+    }
+    html! {
+        <div class="watch-list breakpoint-list">
+            <h3 class="watch-list-header">{"Breakpoints"}</h3>
+            { for state.breakpoints.iter().map(|bp| {
+                render_breakpoint_item(state, bp, on_toggle_breakpoint, on_set_condition)
+            })}
+        </div>
+    }
+}
+
+fn render_breakpoint_item(
+    state: &DebuggerState,
+    bp: &Breakpoint,
+    on_toggle_breakpoint: &Callback<usize>,
+    on_set_condition: &Callback<(usize, String)>,
 ) -> Html {
+    let stage_index = bp.stage_index;
+    let on_delete = {
+        let cb = on_toggle_breakpoint.clone();
+        Callback::from(move |e: MouseEvent| {
+            e.stop_propagation();
+            cb.emit(stage_index);
+        })
+    };
+    let on_change = {
+        let cb = on_set_condition.clone();
+        Callback::from(move |e: Event| {
+            let target: HtmlInputElement = e.target_unchecked_into();
+            cb.emit((stage_index, target.value()));
+        })
+    };
+
+    html! {
+        <div class="watch-item">
+            <div class="watch-item-header">
+                <span class="breakpoint-item-label">{"\u{24B7}"}</span>
+                <span class="watch-item-desc">{pipe_point_description(state, stage_index)}</span>
+                <button class="watch-delete" onclick={on_delete} title="Remove breakpoint">
+                    {"\u{1F5D1}"}
+                </button>
+            </div>
+            <div class="watch-records">
+                <input class="breakpoint-condition" type="text"
+                    value={bp.condition_text.clone()}
+                    placeholder={"always, or 18,10 = \"SALES\" or record == 57"}
+                    title="Stop only when a record here meets this FILTER condition, or at this input record number"
+                    onchange={on_change} />
+                if let Err(e) = &bp.condition {
+                    <div class="debug-input-error">{e}</div>
+                }
+            </div>
+        </div>
+    }
+}
+
+/// Describes the pipe point below stage `stage_index`: "after LOCATE → CHANGE".
+fn pipe_point_description(state: &DebuggerState, stage_index: usize) -> String {
     let stage_name = state
         .pipeline_lines
         .iter()
-        .find(|l| l.stage_index == watch.stage_index)
+        .find(|l| l.stage_index == stage_index)
         .map(|l| l.text.split_whitespace().next().unwrap_or("").to_string())
         .unwrap_or_default();
 
     let next_stage_name = state
         .pipeline_lines
         .iter()
-        .find(|l| l.stage_index == watch.stage_index + 1)
+        .find(|l| l.stage_index == stage_index + 1)
         .map(|l| l.text.split_whitespace().next().unwrap_or("").to_string())
         .unwrap_or_else(|| "END".to_string());
 
-    let description = format!("after {stage_name} \u{2192} {next_stage_name}");
+    format!("after {stage_name} \u{2192} {next_stage_name}")
+}
+
+fn render_watch_item(
+    state: &DebuggerState,
+    watch: &Watch,
+    on_remove_watch: &Callback<String>,
+    on_set_watch_field: &Callback<(String, String)>,
+) -> Html {
+    let description = pipe_point_description(state, watch.stage_index);
+    let on_field_change = {
+        let cb = on_set_watch_field.clone();
+        let label = watch.label.clone();
+        Callback::from(move |e: Event| {
+            let target: HtmlInputElement = e.target_unchecked_into();
+            cb.emit((label.clone(), target.value()));
+        })
+    };
 
     let on_delete = {
         let cb = on_remove_watch.clone();
@@ -696,66 +1290,87 @@ fn render_watch_item(
             <div class="watch-item-header">
                 <span class="watch-item-label">{&watch.label}</span>
                 <span class="watch-item-desc">{description}</span>
+                <input class="watch-field" type="text"
+                    value={watch.field_text.clone()}
+                    placeholder="field: 18,10 or NAME"
+                    title="Show only this field (pos,len or a LAYOUT field name); empty for whole records"
+                    onchange={on_field_change} />
                 <button class="watch-delete" onclick={on_delete} title="Remove watch">
                     {"\u{1F5D1}"}
                 </button>
             </div>
             <div class="watch-records">
-                { render_watch_records(state, watch.stage_index) }
+                { match &watch.field {
+                    Ok(Some(field)) => render_field_values(state, watch, field),
+                    Ok(None) => render_watch_records(state, watch.stage_index),
+                    Err(e) => html! { <div class="debug-input-error">{e}</div> },
+                }}
             </div>
         </div>
     }
 }
 
+/// Renders a field watch's values, marking those that changed since the
+/// watch last showed data.
+fn render_field_values(state: &DebuggerState, watch: &Watch, field: &WatchField) -> Html {
+    if watch.values.is_empty() {
+        let hint = if state.current_step == 0 {
+            "step to see data"
+        } else {
+            "not reached yet"
+        };
+        return html! { <span class="watch-not-reached">{hint}</span> };
+    }
+    let count = watch.values.len();
+    html! {
+        <>
+            <div class="watch-field-label">{field.label()}</div>
+            { for watch.values.iter().enumerate().take(20).map(|(i, value)| {
+                let changed = !watch.previous.is_empty() && watch.previous.get(i) != Some(value);
+                html! {
+                    <div class="watch-record">
+                        if changed {
+                            <mark class="watch-changed"
+                                title={format!("was {:?}", watch.previous.get(i).map_or("", |p| p.as_str()))}>
+                                {value}
+                            </mark>
+                        } else {
+                            {value}
+                        }
+                    </div>
+                }
+            })}
+            if count > 20 {
+                <div class="watch-record-more">
+                    {format!("... ({count} total)")}
+                </div>
+            }
+        </>
+    }
+}
+
 fn render_watch_records(state: &DebuggerState, stage_index: usize) -> Html {
     if state.current_step == 0 {
         return html! {
             <span class="watch-not-reached">{"step to see data"}</span>
         };
     }
-    let trace = match &state.trace {
-        Some(t) => t,
-        None => {
-            return html! {
-                <span class="watch-empty">{"no data"}</span>
-            };
-        }
-    };
-
-    let records = if !state.in_flush_phase {
-        trace.record_traces.get(state.trace_idx).and_then(|rt| {
-            if stage_index < state.visible_pp {
-                rt.pipe_points.get(stage_index)
-            } else {
-                None
-            }
-        })
-    } else {
-        trace.flush_traces.get(state.trace_idx).and_then(|ft| {
-            let flush_start = ft.stage_index + 1;
-            if stage_index >= flush_start {
-                let offset = stage_index - flush_start;
-                if offset < state.visible_pp {
-                    ft.pipe_points.get(offset)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
-    };
+    if state.trace.is_none() {
+        return html! {
+            <span class="watch-empty">{"no data"}</span>
+        };
+    }
 
-    match records {
-        Some(recs) if recs.is_empty() => html! {},
-        Some(recs) => {
+    match state.revealed_at(stage_index) {
+        Some((pipe_points, index)) if pipe_points[index].is_empty() => html! {},
+        Some((pipe_points, index)) => {
+            let recs = &pipe_points[index];
+            let changes = changed_columns(pipe_points, index);
             let count = recs.len();
             html! {
                 <>
-                    { for recs.iter().take(20).map(|r| {
-                        html! {
-                            <div class="watch-record">{r.as_str().trim_end()}</div>
-                        }
+                    { for recs.iter().zip(&changes).take(20).map(|(r, diffs)| {
+                        render_changed_record(r, diffs)
                     })}
                     if count > 20 {
                         <div class="watch-record-more">
@@ -768,3 +1383,39 @@ fn render_watch_records(state: &DebuggerState, stage_index: usize) -> Html {
         None => html! {},
     }
 }
+
+/// Renders a watched record with the columns its last stage changed marked.
+fn render_changed_record(record: &Record, diffs: &[ColumnDiff]) -> Html {
+    // Keep trailing blanks that a stage wrote, so blanked fields show
+    let text = record.as_str();
+    let end = diffs
+        .iter()
+        .map(|d| d.start + d.len)
+        .max()
+        .unwrap_or(0)
+        .max(text.trim_end().len())
+        .min(text.len());
+    let mut parts = Vec::new();
+    let mut col = 0;
+    for diff in diffs.iter().filter(|d| d.start < end) {
+        let stop = (diff.start + diff.len).min(end);
+        if col < diff.start {
+            parts.push(html! { {&text[col..diff.start]} });
+        }
+        parts.push(html! {
+            <mark class="watch-changed" title={format!("columns {}-{}", diff.start, stop - 1)}>
+                {&text[diff.start..stop]}
+            </mark>
+        });
+        col = stop;
+    }
+    if col < end {
+        parts.push(html! { {&text[col..end]} });
+    }
+    html! {
+        <div class="watch-record">{ for parts }</div>
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,359 @@
+//! Tests for the debugger's stepping, breakpoints, and watches, run on the
+//! host by `cargo test` and in a browser by `wasm-pack test`.
+
+use super::*;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const INPUT: &str = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+
+/// Three stages after the source; the second input record stops at the
+/// FILTER, so the records take 3, 2, and 3 steps.
+const PIPELINE: &str = "PIPE CONSOLE
+| FILTER 18,10 = \"SALES\"
+| CHANGE /SALES/REVENUE/
+| CONSOLE";
+
+/// A debugger at step 0 of `pipeline` run on `input`, as the app sets it
+/// up.
+fn debugger(input: &str, pipeline: &str) -> DebuggerState {
+    let (output, input_count, output_count, trace) =
+        naive_pipe::execute_pipeline_rat_debug(input, pipeline).unwrap();
+    let mut state = DebuggerState {
+        active: true,
+        stage_count: trace.stage_names.len(),
+        trace: Some(trace),
+        pipeline_lines: parse_pipeline_lines(pipeline),
+        output_text: output,
+        input_count,
+        output_count,
+        ..DebuggerState::new()
+    };
+    state.total_steps = state.compute_total_steps();
+    state
+}
+
+/// Advances until a breakpoint stops a step, returning that step, or
+/// `None` if the run finishes first.
+fn run_to_breakpoint(state: &mut DebuggerState) -> Option<usize> {
+    while !state.is_finished() {
+        if state.advance() {
+            return Some(state.current_step);
+        }
+    }
+    None
+}
+
+fn layout() -> RecordLayout {
+    RecordLayout::new()
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_break_condition_parse_errors() {
+    let error = |text: &str| BreakCondition::parse(text, &layout()).err().unwrap();
+    for empty in ["", "   "] {
+        assert_eq!(error(empty), "FILTER requires pos,len before operator");
+    }
+    assert_eq!(
+        error("record =~ 3"),
+        "Unknown operator '=~' in record condition"
+    );
+    assert_eq!(
+        error("record == three"),
+        "Expected a record number, got 'three'"
+    );
+    assert_eq!(
+        error("18 = \"SALES\""),
+        "FILTER requires pos,len before operator"
+    );
+    assert_eq!(error("18,x = \"SALES\""), "Invalid length number");
+    assert_eq!(
+        error("DEPT = \"SALES\""),
+        "Unknown field 'DEPT' (no LAYOUT declared)"
+    );
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_break_condition_operators() {
+    let holds = |text: &str, record: Option<usize>| {
+        let condition = BreakCondition::parse(text, &layout()).unwrap();
+        condition.holds(&[], record)
+    };
+    for (op, below, at, above) in [
+        ("==", false, true, false),
+        ("=", false, true, false),
+        ("!=", true, false, true),
+        ("<>", true, false, true),
+        ("<", true, false, false),
+        ("<=", true, true, false),
+        (">", false, false, true),
+        (">=", false, true, true),
+    ] {
+        let text = format!("record {op} 2");
+        assert_eq!(holds(&text, Some(1)), below, "{text} at 1");
+        assert_eq!(holds(&text, Some(2)), at, "{text} at 2");
+        assert_eq!(holds(&text, Some(3)), above, "{text} at 3");
+        assert!(!holds(&text, None), "{text} in a flush");
+    }
+
+    let sales = BreakCondition::parse("18,10 = \"SALES\"", &layout()).unwrap();
+    let records: Vec<Record> = INPUT.lines().map(Record::from_str).collect();
+    assert!(sales.holds(&records, None));
+    assert!(!sales.holds(&records[1..2], Some(2)));
+    assert!(!sales.holds(&[], Some(1)));
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_unconditional_breakpoint_stops_at_each_visit() {
+    let mut state = debugger(INPUT, PIPELINE);
+    assert_eq!(state.total_steps, 8);
+    state.toggle_breakpoint(1);
+    assert!(state.has_breakpoint(1));
+
+    // Pipe point 1 is revealed by the second step of each record
+    assert_eq!(run_to_breakpoint(&mut state), Some(2));
+    assert_eq!(state.hit_breakpoint, Some(1));
+    assert_eq!(run_to_breakpoint(&mut state), Some(5));
+    assert_eq!(run_to_breakpoint(&mut state), Some(7));
+    assert_eq!(run_to_breakpoint(&mut state), None);
+
+    state.toggle_breakpoint(1);
+    assert!(!state.has_breakpoint(1));
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_conditional_breakpoints() {
+    let mut state = debugger(INPUT, PIPELINE);
+    state.toggle_breakpoint(0);
+    state.set_breakpoint_condition(0, "record == 3", &layout());
+    assert_eq!(run_to_breakpoint(&mut state), Some(6));
+    assert_eq!(state.step_label(), "[BP] Record 3 of 3 (1/3)");
+    assert_eq!(run_to_breakpoint(&mut state), None);
+
+    // A FILTER condition tests the records at the pipe point
+    state.rewind();
+    state.set_breakpoint_condition(0, "18,10 = \"ENGINEER\"", &layout());
+    assert_eq!(run_to_breakpoint(&mut state), Some(4));
+    assert_eq!(run_to_breakpoint(&mut state), None);
+
+    // A condition that does not parse never stops; empty text clears it
+    state.rewind();
+    state.set_breakpoint_condition(0, "record ~ 1", &layout());
+    assert!(state.breakpoints[0].condition.is_err());
+    assert_eq!(run_to_breakpoint(&mut state), None);
+    state.rewind();
+    state.set_breakpoint_condition(0, "  ", &layout());
+    assert!(matches!(state.breakpoints[0].condition, Ok(None)));
+    assert_eq!(run_to_breakpoint(&mut state), Some(1));
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_breakpoint_on_stage_that_sees_no_record() {
+    let pipeline = "PIPE CONSOLE
+| LOCATE /NOBODY/
+| UPPER
+| CONSOLE";
+    let mut state = debugger(INPUT, pipeline);
+    state.toggle_breakpoint(2);
+    state.toggle_breakpoint(3);
+    assert_eq!(run_to_breakpoint(&mut state), None);
+    assert!(state.is_finished());
+    assert_eq!(state.hit_breakpoint, None);
+    assert!(state.accumulated_output.is_empty());
+
+    // Nothing has reached the pipe point, so a condition has no records
+    state.rewind();
+    state.advance();
+    state.advance();
+    assert_eq!(state.revealed_records(), (&[][..], Some(1)));
+    let empty = BreakCondition::parse("0,5 = \"SMITH\"", &layout()).unwrap();
+    assert!(!empty.holds(state.revealed_records().0, Some(1)));
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_watch_field_parse() {
+    let layout: RecordLayout = "LAYOUT DEPT 18,10; PAY 28,8".parse().unwrap();
+    let dept = WatchField::parse(" dept ", &layout).unwrap();
+    assert_eq!(
+        (dept.name.as_deref(), dept.pos, dept.len),
+        (Some("DEPT"), 18, 10)
+    );
+    assert_eq!(dept.label(), "DEPT 18,10");
+    let field = WatchField::parse("28, 8", &layout).unwrap();
+    assert_eq!((field.name, field.pos, field.len), (None, 28, 8));
+    assert_eq!(
+        WatchField::parse("18,0", &layout).err().unwrap(),
+        "Field length must be at least 1"
+    );
+    assert_eq!(
+        WatchField::parse("18,x", &layout).err().unwrap(),
+        "Expected pos,len, got '18,x'"
+    );
+    assert!(WatchField::parse("NAME", &layout).is_err());
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_field_watch_updates_on_step() {
+    let mut state = debugger(INPUT, PIPELINE);
+    state.toggle_watch(2);
+    state.set_watch_field("w1", "18,10", &layout());
+    let values = |state: &DebuggerState| {
+        let watch = &state.watches[0];
+        (watch.values.clone(), watch.previous.clone())
+    };
+    let none: (Vec<String>, Vec<String>) = (Vec::new(), Vec::new());
+
+    // Pipe point 2 (after CHANGE) is revealed by the third step
+    state.advance();
+    state.advance();
+    assert_eq!(values(&state), none);
+    state.advance();
+    assert_eq!(values(&state), (vec!["REVENUE   ".to_string()], Vec::new()));
+
+    // The filtered record never reaches the watch, so its value stays
+    for _ in 0..2 {
+        state.advance();
+    }
+    assert_eq!(values(&state).0, ["REVENUE   "]);
+
+    // The same value again is not a change
+    for _ in 0..3 {
+        state.advance();
+    }
+    assert!(state.is_finished());
+    assert_eq!(values(&state), (vec!["REVENUE   ".to_string()], Vec::new()));
+
+    // A watch after the FILTER sees the department change
+    let mut state = debugger(INPUT, PIPELINE);
+    state.toggle_watch(0);
+    state.set_watch_field("w1", "0,8", &layout());
+    state.advance();
+    assert_eq!(values(&state).0, ["SMITH   "]);
+    for _ in 0..3 {
+        state.advance();
+    }
+    assert_eq!(
+        values(&state),
+        (vec!["JONES   ".to_string()], vec!["SMITH   ".to_string()])
+    );
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_field_watch_on_rewind() {
+    let mut state = debugger(INPUT, PIPELINE);
+    state.toggle_watch(0);
+    state.set_watch_field("w1", "0,8", &layout());
+    for _ in 0..4 {
+        state.advance();
+    }
+    let at_step_4 = (
+        state.watches[0].values.clone(),
+        state.watches[0].previous.clone(),
+    );
+    assert_eq!(at_step_4.0, ["JONES   "]);
+
+    // Rewinding forgets the values; stepping back replays them
+    state.rewind();
+    assert!(state.watches[0].values.is_empty());
+    assert!(state.watches[0].previous.is_empty());
+    assert_eq!(state.watches[0].field_text, "0,8");
+
+    state.jump_to(6);
+    assert_eq!(state.watches[0].values, ["DOE     "]);
+    state.step_back();
+    state.step_back();
+    assert_eq!(
+        (
+            state.watches[0].values.clone(),
+            state.watches[0].previous.clone()
+        ),
+        at_step_4
+    );
+
+    // Changing the field starts over from the current step
+    state.set_watch_field("w1", "8,10", &layout());
+    assert_eq!(state.watches[0].values, ["MARY      "]);
+    assert!(state.watches[0].previous.is_empty());
+    state.set_watch_field("w1", "", &layout());
+    assert!(matches!(state.watches[0].field, Ok(None)));
+    assert!(state.watches[0].values.is_empty());
+}
+
+/// The test pipeline with a field watch on the CHANGE output and an
+/// unconditional breakpoint on the FILTER output.
+fn watched_debugger() -> DebuggerState {
+    let mut state = debugger(INPUT, PIPELINE);
+    state.toggle_watch(2);
+    state.set_watch_field("w1", "18,10", &layout());
+    state.toggle_breakpoint(1);
+    state
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_step_back_past_first_record() {
+    let mut state = watched_debugger();
+    state.step_back();
+    assert_eq!(state.current_step, 0);
+    assert!(state == watched_debugger());
+
+    for _ in 0..3 {
+        state.advance();
+    }
+    assert_eq!(
+        state.accumulated_output,
+        "SMITH   JOHN      REVENUE     00050000"
+    );
+    state.step_back();
+    assert_eq!(state.current_step, 2);
+    assert!(state.accumulated_output.is_empty());
+
+    // Stepping back more often than there are steps stays at step 0
+    for _ in 0..5 {
+        state.step_back();
+    }
+    assert!(state == watched_debugger());
+    assert!(state.watches[0].values.is_empty());
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_jump_to_breakpoint_after_rewind() {
+    let mut state = watched_debugger();
+    assert_eq!(run_to_breakpoint(&mut state), Some(2));
+    assert_eq!(run_to_breakpoint(&mut state), Some(5));
+    state.rewind();
+
+    // Landing on a breakpoint step by jumping does not count as a hit,
+    // and running on stops where a forward run would
+    state.jump_to(5);
+    assert_eq!(state.current_step, 5);
+    assert_eq!(state.hit_breakpoint, None);
+    assert_eq!(run_to_breakpoint(&mut state), Some(7));
+    assert_eq!(state.hit_breakpoint, Some(1));
+
+    state.jump_to(2);
+    assert_eq!(state.hit_breakpoint, None);
+    assert_eq!(run_to_breakpoint(&mut state), Some(5));
+    assert_eq!(run_to_breakpoint(&mut state), Some(7));
+    assert_eq!(run_to_breakpoint(&mut state), None);
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_jump_to_matches_forward_run() {
+    let mut state = watched_debugger();
+    let total = state.total_steps;
+    while !state.is_finished() {
+        state.advance();
+    }
+    // Jump backwards from the end to every step, and past the last one
+    for step in (0..=total + 1).rev() {
+        let mut fresh = watched_debugger();
+        for _ in 0..step.min(total) {
+            fresh.advance();
+        }
+        fresh.hit_breakpoint = None;
+        state.jump_to(step);
+        assert!(state == fresh, "jump_to({step}) differs from a forward run");
+    }
+    assert_eq!(state.current_step, 0);
+}
@@ -3,7 +3,35 @@
 //! Replaces the duplicated DSL parser with direct calls to the library's
 //! `execute_pipeline_rat` and `execute_pipeline_rat_debug` functions.
 
+use std::borrow::Cow;
+use std::time::{Duration, UNIX_EPOCH};
+
 use naive_pipe::RatDebugTrace;
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::diagnostic::{Severity, diagnose};
+use pipelines_rs::{HostVars, RecordLayout};
+
+/// Host variables for STAMP, using the browser clock (wasm has no system clock).
+fn host_vars() -> HostVars {
+    let millis = js_sys::Date::now().max(0.0) as u64;
+    HostVars::new()
+        .with_time(UNIX_EPOCH + Duration::from_millis(millis))
+        .with_step("BROWSER")
+}
+
+/// The pipeline as DSL text: pipelines typed in CMS Pipelines syntax
+/// (`locate 1-5 /x/ | take 3`) are translated, and DSL text is kept as is.
+pub fn to_dsl(pipeline_text: &str) -> Cow<'_, str> {
+    Syntax::Auto
+        .to_dsl(pipeline_text)
+        .unwrap_or(Cow::Borrowed(pipeline_text))
+}
+
+/// The fields the pipeline's `LAYOUT` lines declare, for naming fields in
+/// breakpoint conditions; empty if the pipeline does not parse.
+pub fn pipeline_layout(pipeline_text: &str) -> RecordLayout {
+    pipelines_rs::pipeline_layout(&to_dsl(pipeline_text)).unwrap_or_default()
+}
 
 /// Execute a pipeline using the record-at-a-time executor.
 ///
@@ -12,7 +40,7 @@ pub fn execute_pipeline(
     input_text: &str,
     pipeline_text: &str,
 ) -> Result<(String, usize, usize), String> {
-    naive_pipe::execute_pipeline_rat(input_text, pipeline_text)
+    naive_pipe::execute_pipeline_rat_with_vars(input_text, &to_dsl(pipeline_text), &host_vars())
 }
 
 /// Execute a pipeline with debug tracing using the record-at-a-time executor.
@@ -22,7 +50,36 @@ pub fn execute_pipeline_debug(
     input_text: &str,
     pipeline_text: &str,
 ) -> Result<(String, usize, usize, RatDebugTrace), String> {
-    naive_pipe::execute_pipeline_rat_debug(input_text, pipeline_text)
+    naive_pipe::execute_pipeline_rat_debug_with_vars(
+        input_text,
+        &to_dsl(pipeline_text),
+        &host_vars(),
+    )
+}
+
+/// Explain a pipeline without running it: its stages, the fields each
+/// touches, and warnings (see `pipelines_rs::explain`).
+///
+/// Returns (explanation_text, warning_count) on success.
+pub fn explain_pipeline(pipeline_text: &str) -> Result<(String, usize), String> {
+    let plan = pipelines_rs::PipelinePlan::parse(&to_dsl(pipeline_text))?;
+    let explanation = pipelines_rs::explain::explain(&plan);
+    Ok((explanation.to_string(), explanation.warnings.len()))
+}
+
+/// Check a pipeline as it is typed (see `pipelines_rs::diagnostic`).
+///
+/// Returns each problem, rendered with its line and a marker under its
+/// columns, and whether it is an error rather than a warning.
+pub fn lint_pipeline(pipeline_text: &str) -> Vec<(String, bool)> {
+    if pipeline_text.trim().is_empty() {
+        return Vec::new();
+    }
+    let text = to_dsl(pipeline_text);
+    diagnose(&text)
+        .iter()
+        .map(|d| (d.render("pipeline", &text), d.severity == Severity::Error))
+        .collect()
 }
 
 /// A parsed pipeline line for debugger display.
@@ -39,7 +96,7 @@ pub fn parse_pipeline_lines(pipeline_text: &str) -> Vec<PipelineLine> {
     let mut lines = Vec::new();
     let mut stage_index: usize = 0;
 
-    for line in pipeline_text.lines() {
+    for line in to_dsl(pipeline_text).lines() {
         let trimmed = line.trim();
 
         // Skip empty lines and comments
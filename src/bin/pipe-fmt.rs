@@ -0,0 +1,73 @@
+//! CLI tool to format pipeline (.pipe) files canonically (see
+//! `pipelines_rs::format`).
+//!
+//! Exit codes: 0 when the files are formatted (with `--check`, when they
+//! already were), 1 when `--check` finds a file to format, 2 when a file
+//! cannot be read, parsed, or written.
+
+use clap::Parser;
+use pipelines_rs::format::format_pipeline;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+/// Format pipeline files in place: one stage per line, upper-case
+/// keywords, and uniform spacing.
+#[derive(Parser)]
+#[command(name = "pipe-fmt")]
+struct Cli {
+    /// Pipeline definition files (.pipe); with none, read stdin and write
+    /// stdout
+    files: Vec<String>,
+
+    /// Report the files that need formatting instead of formatting them
+    #[arg(long)]
+    check: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    if cli.files.is_empty() {
+        let mut text = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut text) {
+            eprintln!("Error reading stdin: {e}");
+            process::exit(2);
+        }
+        match format_pipeline(&text) {
+            Ok(formatted) if cli.check => process::exit(i32::from(formatted != text)),
+            Ok(formatted) => print!("{formatted}"),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                process::exit(2);
+            }
+        }
+        return;
+    }
+
+    let mut status = 0;
+    for path in &cli.files {
+        let result = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read: {e}"))
+            .and_then(|text| Ok((format_pipeline(&text)?, text)));
+        let (formatted, text) = match result {
+            Ok(pair) => pair,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                status = 2;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        if cli.check {
+            println!("{path}: needs formatting");
+            status = status.max(1);
+        } else if let Err(e) = fs::write(path, &formatted) {
+            eprintln!("{path}: cannot write: {e}");
+            status = 2;
+        }
+    }
+    process::exit(status);
+}
@@ -0,0 +1,51 @@
+//! CLI tool to translate DFSORT control statements into a pipeline (see
+//! `pipelines_rs::dfsort`).
+
+use clap::Parser;
+use pipelines_rs::dfsort::translate;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+/// Translate DFSORT control statements (SORT, INCLUDE/OMIT, INREC, OUTREC,
+/// SUM) into .pipe DSL.
+#[derive(Parser)]
+#[command(name = "pipe-import-dfsort")]
+struct Cli {
+    /// File of control statements (default: stdin)
+    input: Option<String>,
+
+    /// Write the pipeline to this file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<String>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut cards = String::new();
+    let read = match &cli.input {
+        Some(path) => fs::read_to_string(path).map(|text| cards = text),
+        None => io::stdin().read_to_string(&mut cards).map(drop),
+    };
+    if let Err(e) = read {
+        let name = cli.input.as_deref().unwrap_or("stdin");
+        eprintln!("Error reading control statements from '{name}': {e}");
+        process::exit(1);
+    }
+
+    let plan = translate(&cards).unwrap_or_else(|e| {
+        eprintln!("DFSORT error: {e}");
+        process::exit(1);
+    });
+    let text = format!("{plan}\n");
+    match &cli.output {
+        Some(path) => {
+            if let Err(e) = fs::write(path, text) {
+                eprintln!("Error writing '{path}': {e}");
+                process::exit(1);
+            }
+        }
+        None => print!("{text}"),
+    }
+}
@@ -0,0 +1,67 @@
+//! CLI tool to propose a LAYOUT block for an undocumented fixed-width file.
+
+use clap::Parser;
+use pipelines_rs::Record;
+use pipelines_rs::infer::infer_layout;
+use std::fs;
+use std::process;
+
+/// Infer field boundaries and types from sample records.
+#[derive(Parser)]
+#[command(name = "pipe-infer")]
+struct Cli {
+    /// Input data file (80-byte fixed-width records)
+    input: String,
+
+    /// Number of records to sample from the start of the file
+    #[arg(short, long, default_value_t = 1000)]
+    sample: usize,
+
+    /// Show each field's range, type, and first value on stderr
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let input_text = match fs::read_to_string(&cli.input) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error reading input file '{}': {e}", cli.input);
+            process::exit(1);
+        }
+    };
+
+    let records: Vec<Record> = input_text
+        .lines()
+        .take(cli.sample)
+        .map(Record::from_str)
+        .collect();
+    let layout = infer_layout(&records);
+
+    if layout.is_empty() {
+        eprintln!("No fields found in {} sampled records", records.len());
+        process::exit(1);
+    }
+
+    if cli.verbose {
+        eprintln!("Sampled {} records", records.len());
+        for field in layout.fields() {
+            let example = records
+                .iter()
+                .map(|r| r.field(field.start, field.len).trim())
+                .find(|v| !v.is_empty())
+                .unwrap_or("");
+            eprintln!(
+                "  {:<8} {:>2},{:<2} {:<7} {example}",
+                field.name,
+                field.start,
+                field.len,
+                field.field_type.name()
+            );
+        }
+    }
+
+    println!("{layout}");
+}
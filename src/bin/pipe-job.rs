@@ -0,0 +1,275 @@
+//! CLI tool to run a job file: pipeline steps run in order, like the steps
+//! of a JCL job (see `pipelines_rs::job`).
+//!
+//! Each step's condition code is logged on stderr, and the exit status is
+//! the highest code of the steps that ran.
+
+use clap::Parser;
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::io::{Compression, Encoder, decoder};
+use pipelines_rs::job::{Dataset, Job, JobStep, StepOutcome, max_code};
+use pipelines_rs::{
+    Command, ConditionCode, HostVars, InputFiles, PipelineError, PipelinePlan, PipelineRun, Record,
+    execute_plan_with_inputs, input_file,
+};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::process;
+
+/// Exit status of a job that cannot start.
+const FATAL: i32 = ConditionCode::Fatal.code();
+
+/// Run the steps of a job file in order.
+#[derive(Parser)]
+#[command(name = "pipe-job")]
+struct Cli {
+    /// Job definition file
+    job: String,
+
+    /// Start at STEP, skipping the steps before it
+    #[arg(long, value_name = "STEP")]
+    restart: Option<String>,
+
+    /// Show each step's pipeline and datasets on stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Syntax of the steps' pipelines: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+}
+
+/// Temporary datasets (`&&NAME`) written so far, by name.
+type Temps = BTreeMap<String, String>;
+
+fn main() {
+    let cli = Cli::parse();
+
+    let job = match fs::read_to_string(&cli.job) {
+        Ok(text) => Job::parse(&text).unwrap_or_else(|e| {
+            eprintln!("Job error: {e}");
+            process::exit(FATAL);
+        }),
+        Err(e) => {
+            eprintln!("Error reading job file '{}': {e}", cli.job);
+            process::exit(FATAL);
+        }
+    };
+    let start = match &cli.restart {
+        Some(name) => job.step_index(name).unwrap_or_else(|| {
+            eprintln!("Error: --restart {name} names no step of job {}", job.name);
+            process::exit(FATAL);
+        }),
+        None => 0,
+    };
+
+    eprintln!("JOB {}", job.name);
+    for step in &job.steps[..start] {
+        eprintln!(
+            "{:<8} NOT RUN  restarted at {}",
+            step.name, job.steps[start].name
+        );
+    }
+    let mut temps = Temps::new();
+    let mut done: Vec<StepOutcome> = Vec::new();
+    for step in &job.steps[start..] {
+        if let Some(reason) = step.skip_reason(&done) {
+            eprintln!("{:<8} FLUSH    {reason}", step.name);
+            continue;
+        }
+        if cli.verbose {
+            describe(step);
+        }
+        let code = match run_step(step, cli.syntax, &mut temps) {
+            Ok(run) => {
+                eprintln!(
+                    "{:<8} RC {:>2}    {} in -> {} out",
+                    step.name, run.condition_code, run.input_count, run.output_count
+                );
+                for warning in &run.warnings {
+                    eprintln!("{:<8} Warning: {warning}", step.name);
+                }
+                run.condition_code
+            }
+            Err(e) => {
+                eprintln!("{:<8} RC {:>2}    {e}", step.name, ConditionCode::Fatal);
+                ConditionCode::Fatal
+            }
+        };
+        done.push(StepOutcome::new(&step.name, code));
+    }
+    let max = max_code(&done);
+    eprintln!("JOB {} MAXCC {max}", job.name);
+    process::exit(max.code());
+}
+
+/// Writes a step's pipeline and datasets to stderr.
+fn describe(step: &JobStep) {
+    eprintln!("{:<8} PIPE     {}", step.name, step.pipeline);
+    for input in &step.inputs {
+        eprintln!("{:<8} IN       {input}", "");
+    }
+    match &step.output {
+        Some(output) => eprintln!("{:<8} OUT      {output}", ""),
+        None => eprintln!("{:<8} OUT      (stdout)", ""),
+    }
+    for (name, dataset) in &step.dds {
+        eprintln!("{:<8} DD       {name} {dataset}", "");
+    }
+}
+
+/// Runs one step: reads its inputs, runs its pipeline, and writes its
+/// outputs.
+fn run_step(step: &JobStep, syntax: Syntax, temps: &mut Temps) -> Result<PipelineRun, String> {
+    let text = fs::read_to_string(&step.pipeline)
+        .map_err(|e| format!("cannot read pipeline file '{}': {e}", step.pipeline))?;
+    let plan = PipelinePlan::parse(&syntax.to_dsl(&text)?)?;
+    let dataset = |name: &str| {
+        step.dd(name)
+            .cloned()
+            .unwrap_or_else(|| Dataset::File(name.to_string()))
+    };
+    // Every SPLIT output needs a dataset before the step runs, as a job
+    // step allocates its datasets first
+    for cmd in plan.all_commands() {
+        if let Command::Split { name, .. } = cmd
+            && step.dd(name).is_none()
+        {
+            return Err(format!("SPLIT {name} needs a dataset (DD {name} ...)"));
+        }
+    }
+
+    let input = match (input_file(plan.commands()), &step.inputs[..]) {
+        (Some(name), []) => read_dataset(&dataset(name), temps)?,
+        (Some(name), [_, ..]) => {
+            return Err(format!("the pipeline reads < {name}; drop the IN datasets"));
+        }
+        (None, []) => {
+            return Err(
+                "an IN dataset is required unless the pipeline starts with < name".to_string(),
+            );
+        }
+        (None, inputs) => inputs
+            .iter()
+            .map(|input| read_dataset(input, temps))
+            .collect::<Result<String, _>>()?,
+    };
+    let vars = step.parms.iter().fold(
+        HostVars::new()
+            .with_step(&step.name)
+            .with_env_used_by(plan.all_commands()),
+        |vars, (name, value)| vars.with_parm(name, value),
+    );
+    // Files are opened as the segments reading them start
+    let mut inputs = InputFiles::new();
+    for name in plan.segments().iter().filter_map(|s| s.file()) {
+        inputs = match dataset(name) {
+            Dataset::File(path) => inputs.with_file(name, path),
+            temp => inputs.with_text(name, read_dataset(&temp, temps)?),
+        };
+    }
+
+    let run = execute_plan_with_inputs(&plan, &input, &None, &vars, &inputs)?;
+
+    // A pipeline ending in `>` or `>>` writes its output there instead
+    if !matches!(plan.commands().last(), Some(Command::FileOut { .. })) {
+        let text = match run.output.as_str() {
+            "" => String::new(),
+            output => format!("{output}\n"),
+        };
+        match &step.output {
+            Some(output) => write_dataset(output, &text, false, temps)?,
+            None => print!("{text}"),
+        }
+    }
+    for (name, records) in run.outputs.iter() {
+        write_dataset(&dataset(name), &lines(records), false, temps)?;
+    }
+    let mut written: Vec<&str> = Vec::new();
+    for cmd in plan.all_commands() {
+        if let Command::FileOut { path, append } = cmd
+            && !written.contains(&path.as_str())
+        {
+            let records = run.files.get(path).unwrap_or_default();
+            write_dataset(&dataset(path), &lines(records), *append, temps)?;
+            written.push(path);
+        }
+    }
+    Ok(run)
+}
+
+/// Records as text, one line each.
+fn lines(records: &[Record]) -> String {
+    records
+        .iter()
+        .map(|r| format!("{}\n", r.as_str().trim_end()))
+        .collect()
+}
+
+/// Reads a dataset's text: a temporary dataset written by an earlier step,
+/// or a file, decompressed if it ends in `.gz` or `.zst`.
+fn read_dataset(dataset: &Dataset, temps: &Temps) -> Result<String, String> {
+    let mut text = match dataset {
+        Dataset::Temp(name) => temps
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("temporary dataset &&{name} has not been written"))?,
+        Dataset::File(path) => {
+            let mut text = String::new();
+            File::open(path)
+                .map_err(PipelineError::from)
+                .and_then(|file| decoder(BufReader::new(file), Compression::from_path(path)))
+                .and_then(|mut reader| Ok(reader.read_to_string(&mut text)?))
+                .map_err(|e| format!("cannot read '{path}': {e}"))?;
+            text
+        }
+    };
+    // Concatenated inputs each start on a line of their own
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// Writes or appends text to a dataset: a temporary dataset, or a file,
+/// creating its directory and compressing it if it ends in `.gz` or `.zst`.
+fn write_dataset(
+    dataset: &Dataset,
+    text: &str,
+    append: bool,
+    temps: &mut Temps,
+) -> Result<(), String> {
+    let path = match dataset {
+        Dataset::Temp(name) => {
+            let temp = temps.entry(name.clone()).or_default();
+            if !append {
+                temp.clear();
+            }
+            temp.push_str(text);
+            return Ok(());
+        }
+        Dataset::File(path) => path,
+    };
+    if let Some(parent) = Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("cannot create the directory of '{path}': {e}"))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(PipelineError::from)
+        .and_then(|file| {
+            let mut encoder = Encoder::new(file, Compression::from_path(path))?;
+            encoder.write_all(text.as_bytes())?;
+            encoder.finish()
+        })
+        .map(drop)
+        .map_err(|e| format!("cannot write '{path}': {e}"))
+}
@@ -0,0 +1,55 @@
+//! CLI tool to check pipeline (.pipe) files without running them (see
+//! `pipelines_rs::diagnostic`).
+//!
+//! The exit status is a condition code: 0 when every file is clean, 4 when
+//! there are only warnings, 8 when a pipeline has errors, and 12 when a
+//! file cannot be read (see `pipelines_rs::condcode`).
+
+use clap::Parser;
+use pipelines_rs::ConditionCode;
+use pipelines_rs::diagnostic::{Severity, diagnose};
+use std::fs;
+use std::process;
+
+/// Check pipeline files, reporting each error with its line and columns.
+#[derive(Parser)]
+#[command(name = "pipe-lint")]
+struct Cli {
+    /// Pipeline definition files (.pipe)
+    #[arg(required = true)]
+    pipelines: Vec<String>,
+
+    /// Print errors only (warnings still set the exit status)
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut rc = ConditionCode::Ok;
+    for path in &cli.pipelines {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Error reading pipeline file '{path}': {e}");
+                rc = rc.max(ConditionCode::Fatal);
+                continue;
+            }
+        };
+        let diagnostics = diagnose(&text);
+        for diagnostic in &diagnostics {
+            rc = rc.max(match diagnostic.severity {
+                Severity::Error => ConditionCode::Error,
+                Severity::Warning => ConditionCode::Warning,
+            });
+            if !cli.quiet || diagnostic.severity == Severity::Error {
+                println!("{}\n", diagnostic.render(path, &text));
+            }
+        }
+        if diagnostics.is_empty() && !cli.quiet {
+            println!("{path}: ok");
+        }
+    }
+    process::exit(rc.code());
+}
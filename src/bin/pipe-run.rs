@@ -1,11 +1,30 @@
 //! CLI tool to run pipeline (.pipe) files against input data (batched executor).
+//!
+//! The exit status is the run's condition code: 0 for a clean run, 4 for
+//! warnings, 8 for error records, and 12 for a run that fails (see
+//! `pipelines_rs::condcode`).
 
 use clap::Parser;
-use pipelines_rs::execute_pipeline;
-use std::fs;
-use std::io::{self, Write};
+use pipelines_rs::cli::{parse_dd, parse_output, parse_parm};
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::dot::pipeline_to_dot;
+use pipelines_rs::ebcdic;
+use pipelines_rs::explain::explain;
+use pipelines_rs::io::{Compression, Encoder, decoder, input_paths};
+use pipelines_rs::{
+    Command, ConditionCode, HostVars, InputFiles, JobMetrics, MetricsRegistry, OutputFiles,
+    PipelinePlan, PipelineRun, RECORD_WIDTH, execute_plan_streaming_to, input_file, parse_commands,
+    pipeline_lrecl,
+};
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
 use std::process;
+use std::time::Instant;
+
+/// Exit status of a run that cannot start or fails.
+const FATAL: i32 = ConditionCode::Fatal.code();
 
 /// Run a pipeline file against input data (batched executor).
 #[derive(Parser)]
@@ -14,16 +33,63 @@ struct Cli {
     /// Pipeline definition file (.pipe)
     pipeline: String,
 
-    /// Input data file (80-byte fixed-width records, or /dev/stdin)
-    input: String,
+    /// Input data files or glob patterns (80-byte fixed-width records),
+    /// read in order as one stream, or - for stdin; omit them when the
+    /// pipeline starts with `< path`
+    input: Vec<String>,
 
-    /// Write output to file instead of stdout
-    #[arg(short, long)]
-    output: Option<String>,
+    /// Write output to file instead of stdout (- for stdout); NAME=PATH
+    /// writes the records of `SPLIT NAME` to PATH (repeatable)
+    #[arg(short, long = "output", value_name = "[NAME=]PATH", value_parser = parse_output)]
+    outputs: Vec<(Option<String>, String)>,
 
     /// Show paths, executor, and record counts on stderr
     #[arg(short, long)]
     verbose: bool,
+
+    /// Read `< NAME` from PATH instead of a file called NAME (repeatable)
+    #[arg(long = "in", value_name = "NAME=PATH", value_parser = parse_dd)]
+    inputs: Vec<(String, String)>,
+
+    /// Write `> NAME` or `>> NAME` to PATH instead of a file called NAME
+    /// (repeatable)
+    #[arg(long = "out", value_name = "NAME=PATH", value_parser = parse_dd)]
+    outs: Vec<(String, String)>,
+
+    /// Set a STAMP parameter, read as PARM.NAME (repeatable)
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_parm)]
+    parms: Vec<(String, String)>,
+
+    /// Job step name for STAMP's STEP variable (default: pipeline file name)
+    #[arg(long)]
+    step: Option<String>,
+
+    /// Load PLUGIN stages from a shared library (repeatable)
+    #[cfg(feature = "plugins")]
+    #[arg(long = "plugin", value_name = "LIBRARY")]
+    plugins: Vec<String>,
+
+    /// Write Prometheus metrics for this run to a file (textfile collector format)
+    #[arg(long)]
+    metrics_file: Option<String>,
+
+    /// Print per-stage record counts and wall time on stderr
+    #[arg(long)]
+    stats: bool,
+
+    /// Write a Graphviz DOT graph of the pipeline, annotated with record counts
+    #[arg(long)]
+    dot: Option<String>,
+
+    /// Print the pipeline's stages, the fields each touches, and warnings
+    /// about them instead of running it (exit status 4 if there are
+    /// warnings)
+    #[arg(long)]
+    explain: bool,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
 }
 
 fn main() {
@@ -33,55 +99,408 @@ fn main() {
         Ok(content) => content,
         Err(e) => {
             eprintln!("Error reading pipeline file '{}': {e}", cli.pipeline);
-            process::exit(1);
+            process::exit(FATAL);
         }
     };
+    let pipeline_text = match cli.syntax.to_dsl(&pipeline_text) {
+        Ok(text) => text.into_owned(),
+        Err(e) => {
+            eprintln!("Pipeline error: {e}");
+            process::exit(FATAL);
+        }
+    };
+    if cli.explain {
+        let explanation = PipelinePlan::parse(&pipeline_text)
+            .map(|plan| explain(&plan))
+            .unwrap_or_else(|e| {
+                eprintln!("Pipeline error: {e}");
+                process::exit(FATAL);
+            });
+        println!("{explanation}");
+        let code = if explanation.warnings.is_empty() {
+            ConditionCode::Ok
+        } else {
+            ConditionCode::Warning
+        };
+        process::exit(code.code());
+    }
 
-    let input_text = match fs::read_to_string(&cli.input) {
-        Ok(content) => content,
+    // An unparseable pipeline is reported when it runs
+    let commands = parse_commands(&pipeline_text).unwrap_or_default();
+    // Stages of the main pipeline and of any segments after it
+    let all_commands: Vec<Command> = PipelinePlan::parse(&pipeline_text)
+        .map(|plan| plan.all_commands().cloned().collect())
+        .unwrap_or_default();
+    // Names read by segments starting with `? < NAME`
+    let segment_inputs: Vec<String> = PipelinePlan::parse(&pipeline_text)
+        .map(|plan| {
+            plan.segments()
+                .iter()
+                .filter_map(|s| s.file().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    let checked = check_outputs(&cli.outputs, &all_commands).and_then(|path| {
+        check_dds(&cli.inputs, &cli.outs, &all_commands, &segment_inputs)?;
+        Ok(path)
+    });
+    let output_path = match checked {
+        Ok(path) => path.filter(|path| *path != "-"),
         Err(e) => {
-            eprintln!("Error reading input file '{}': {e}", cli.input);
-            process::exit(1);
+            eprintln!("Error: {e}");
+            process::exit(FATAL);
+        }
+    };
+    let input_args = match (input_file(&commands), &cli.input[..]) {
+        (None, [_, ..]) => cli.input.clone(),
+        (Some(path), []) => vec![resolve(&cli.inputs, path).to_string()],
+        (Some(path), [_, ..]) => {
+            eprintln!("Error: the pipeline reads '{path}' (< {path}); omit the input file");
+            process::exit(FATAL);
+        }
+        (None, []) => {
+            eprintln!("Error: an input file is required unless the pipeline starts with < path");
+            process::exit(FATAL);
         }
     };
+    let input_paths = input_paths(&input_args).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(FATAL);
+    });
+    let lrecl = pipeline_lrecl(&pipeline_text).unwrap_or(RECORD_WIDTH);
+    // Every input must exist before the first is read, as a job step
+    // allocates all of its datasets before it runs
+    for path in input_paths.iter().filter(|path| *path != "-") {
+        if let Err(e) = File::open(path) {
+            input_error(path, e);
+        }
+    }
+    // A pipeline ending in `>` or `>>` writes its output there, not stdout
+    let file_last = matches!(commands.last(), Some(Command::FileOut { .. }));
+
+    #[cfg(feature = "plugins")]
+    for library in &cli.plugins {
+        match pipelines_rs::plugin::load_library(library) {
+            Ok(names) if cli.verbose => eprintln!("Plugin:   {library} ({})", names.join(", ")),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error loading plugin: {e}");
+                process::exit(FATAL);
+            }
+        }
+    }
 
     if cli.verbose {
         eprintln!("Pipeline: {}", cli.pipeline);
-        eprintln!("Input:    {}", cli.input);
-        eprintln!("Output:   {}", cli.output.as_deref().unwrap_or("(stdout)"));
+        for path in &input_paths {
+            eprintln!("Input:    {}", input_name(path));
+        }
+        for name in &segment_inputs {
+            eprintln!("Input:    {} (< {name})", resolve(&cli.inputs, name));
+        }
+        match output_path {
+            Some(path) => eprintln!("Output:   {path}"),
+            None if !file_last => eprintln!("Output:   (stdout)"),
+            None => {}
+        }
+        for (name, path) in &cli.outputs {
+            if let Some(name) = name {
+                eprintln!("Output:   {path} (SPLIT {name})");
+            }
+        }
+        for cmd in &all_commands {
+            if let Command::FileOut { path, .. } = cmd {
+                eprintln!("Output:   {} ({})", resolve(&cli.outs, path), cmd.name());
+            }
+        }
         eprintln!("Executor: batched");
     }
 
-    match execute_pipeline(&input_text, &pipeline_text) {
-        Ok((output, input_count, output_count)) => {
-            if let Some(out_path) = &cli.output {
-                if let Some(parent) = Path::new(out_path.as_str()).parent()
-                    && !parent.as_os_str().is_empty()
-                    && fs::create_dir_all(parent).is_err()
-                {
-                    eprintln!("Error creating output directory for '{out_path}'");
-                    process::exit(1);
-                }
-                if let Err(e) = fs::write(out_path, &output) {
-                    eprintln!("Error writing output file '{out_path}': {e}");
-                    process::exit(1);
-                }
-            } else {
-                if let Err(e) = io::stdout().write_all(output.as_bytes()) {
-                    eprintln!("Error writing output: {e}");
-                    process::exit(1);
-                }
-                if !output.is_empty() && !output.ends_with('\n') {
-                    println!();
+    // SPLIT outputs and `>` files are opened before the run and written
+    // as records pass
+    let splits = cli
+        .outputs
+        .iter()
+        .filter_map(|(name, path)| Some((name.as_deref()?, path.as_str())));
+    let mut files = match OutputFiles::open(&all_commands, splits, &cli.outs) {
+        Ok(files) => match ebcdic::output_code_page(&commands) {
+            Some(code_page) => files.with_ebcdic(code_page, lrecl),
+            None => files,
+        },
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(FATAL);
+        }
+    };
+
+    let started = Instant::now();
+    let vars = HostVars::for_pipeline_file(
+        Path::new(&cli.pipeline),
+        cli.step.as_deref(),
+        &cli.parms,
+        &all_commands,
+    );
+    // Segments starting with `? < NAME` open their inputs as they start
+    let inputs = segment_inputs
+        .iter()
+        .fold(InputFiles::new(), |inputs, name| {
+            inputs.with_file(name, resolve(&cli.inputs, name))
+        });
+    // The inputs are read one after another, counting each one's records
+    let counts = vec![Cell::new(0); input_paths.len()];
+    let lines = input_paths.iter().zip(&counts).flat_map(|(path, count)| {
+        ebcdic::input_lines(open_input(path), &commands, lrecl).map(move |line| {
+            let line = line.unwrap_or_else(|e| input_error(path, e));
+            if !line.is_empty() {
+                count.set(count.get() + 1);
+            }
+            line
+        })
+    });
+    // The output is opened with the first record, so a run that fails
+    // before writing one leaves no output file
+    let mut output: Option<Encoder<Box<dyn Write>>> = None;
+    let mut write_failed = false;
+    let result = PipelinePlan::parse(&pipeline_text).and_then(|plan| {
+        execute_plan_streaming_to(&plan, lines, &None, &vars, &inputs, &mut files, |record| {
+            let writer = output.get_or_insert_with(|| open_output(output_path, file_last));
+            ebcdic::write_output(writer, record, &commands, lrecl).map_err(|e| {
+                write_failed = true;
+                output_error(output_path, e)
+            })
+        })
+    });
+    if let Some(path) = &cli.metrics_file {
+        write_metrics(path, &cli.pipeline, &result, started);
+    }
+
+    match result {
+        Ok(run) => {
+            if let Some(dot_path) = &cli.dot {
+                write_dot(dot_path, &pipeline_text, &run.debug_info);
+            }
+            if let Err(e) = files.finish() {
+                eprintln!("Error: {e}");
+                process::exit(FATAL);
+            }
+            let writer = output.unwrap_or_else(|| open_output(output_path, file_last));
+            if let Err(e) = writer.finish() {
+                eprintln!("{}", output_error(output_path, e));
+                process::exit(FATAL);
+            }
+            if cli.verbose {
+                if input_paths.len() > 1 {
+                    for (path, count) in input_paths.iter().zip(&counts) {
+                        eprintln!("Read:     {} from {}", count.get(), input_name(path));
+                    }
                 }
+                eprintln!(
+                    "Records:  {} in -> {} out",
+                    run.input_count, run.output_count
+                );
+            }
+            if cli.stats {
+                eprintln!("{}", run.metrics);
+            }
+            for warning in &run.warnings {
+                eprintln!("Warning:  {warning}");
             }
             if cli.verbose {
-                eprintln!("Records:  {input_count} in -> {output_count} out");
+                eprintln!(
+                    "RC:       {} ({} warnings, {} error records)",
+                    run.condition_code,
+                    run.warnings.len(),
+                    run.error_records
+                );
             }
+            process::exit(run.condition_code.code());
+        }
+        Err(e) if write_failed => {
+            eprintln!("{e}");
+            process::exit(FATAL);
         }
         Err(e) => {
             eprintln!("Pipeline error: {e}");
-            process::exit(1);
+            process::exit(FATAL);
         }
     }
 }
+
+/// Open the main output: the `-o` file, compressed if it ends in `.gz` or
+/// `.zst`, stdout, or nothing if the pipeline ends by writing a file;
+/// exits on failure.
+fn open_output(path: Option<&str>, file_last: bool) -> Encoder<Box<dyn Write>> {
+    let writer: Box<dyn Write> = match path {
+        Some(path) => {
+            create_parent(path);
+            match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("{}", output_error(Some(path), e));
+                    process::exit(FATAL);
+                }
+            }
+        }
+        None if file_last => Box::new(io::sink()),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let compression = path.map_or(Compression::None, Compression::from_path);
+    Encoder::new(writer, compression).unwrap_or_else(|e| {
+        eprintln!("{}", output_error(path, e));
+        process::exit(FATAL);
+    })
+}
+
+/// Describe an error writing the main output.
+fn output_error(path: Option<&str>, e: impl std::fmt::Display) -> String {
+    match path {
+        Some(path) => format!("Error writing output file '{path}': {e}"),
+        None => format!("Error writing output: {e}"),
+    }
+}
+
+/// Open the input file, or stdin for `-`, decompressing a `.gz` or `.zst`
+/// file as it is read; exits if it cannot be opened.
+fn open_input(path: &str) -> Box<dyn BufRead> {
+    if path == "-" {
+        return Box::new(io::stdin().lock());
+    }
+    let file = File::open(path).unwrap_or_else(|e| input_error(path, e));
+    decoder(BufReader::new(file), Compression::from_path(path))
+        .unwrap_or_else(|e| input_error(path, e))
+}
+
+/// The input file's name for messages: `(stdin)` for `-`.
+fn input_name(path: &str) -> &str {
+    if path == "-" { "(stdin)" } else { path }
+}
+
+/// Report an input file that cannot be read, and exit.
+fn input_error(path: &str, e: impl std::fmt::Display) -> ! {
+    match path {
+        "-" => eprintln!("Error reading stdin: {e}"),
+        _ => eprintln!("Error reading input file '{path}': {e}"),
+    }
+    process::exit(FATAL);
+}
+
+/// Create an output file's directory; exits on failure.
+fn create_parent(path: &str) {
+    if let Some(parent) = Path::new(path).parent()
+        && !parent.as_os_str().is_empty()
+        && fs::create_dir_all(parent).is_err()
+    {
+        eprintln!("Error creating output directory for '{path}'");
+        process::exit(FATAL);
+    }
+}
+
+/// Check that every SPLIT output has a `-o NAME=PATH` and every named
+/// `-o` a SPLIT stage, returning the main output path if one is given.
+fn check_outputs<'a>(
+    outputs: &'a [(Option<String>, String)],
+    commands: &[Command],
+) -> Result<Option<&'a str>, String> {
+    let split_names: Vec<&str> = commands
+        .iter()
+        .filter_map(|cmd| match cmd {
+            Command::Split { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let mut main = None;
+    for (name, path) in outputs {
+        match name {
+            Some(name) if !split_names.contains(&name.as_str()) => {
+                return Err(format!("-o {name}=... names no SPLIT stage"));
+            }
+            Some(_) => {}
+            None if main.is_some() => return Err("more than one unnamed -o".to_string()),
+            None => main = Some(path.as_str()),
+        }
+    }
+    for name in split_names {
+        if !outputs.iter().any(|(n, _)| n.as_deref() == Some(name)) {
+            return Err(format!(
+                "SPLIT {name} needs an output file (-o {name}=PATH)"
+            ));
+        }
+    }
+    Ok(main)
+}
+
+/// Check that every `--in NAME` is read by a `< NAME` stage and every
+/// `--out NAME` written by a `> NAME` or `>> NAME` stage.
+fn check_dds(
+    inputs: &[(String, String)],
+    outs: &[(String, String)],
+    commands: &[Command],
+    segment_inputs: &[String],
+) -> Result<(), String> {
+    for (name, _) in inputs {
+        let read = input_file(commands) == Some(name) || segment_inputs.contains(name);
+        if !read {
+            return Err(format!("--in {name}=... names no < {name} stage"));
+        }
+    }
+    for (name, _) in outs {
+        let written = commands
+            .iter()
+            .any(|cmd| matches!(cmd, Command::FileOut { path, .. } if path == name));
+        if !written {
+            return Err(format!("--out {name}=... names no > {name} stage"));
+        }
+    }
+    Ok(())
+}
+
+/// The path `--in` or `--out` gives for `name`, or `name` itself.
+fn resolve<'a>(dds: &'a [(String, String)], name: &'a str) -> &'a str {
+    dds.iter()
+        .rev()
+        .find(|(n, _)| n == name)
+        .map_or(name, |(_, path)| path)
+}
+
+/// Write the pipeline as a DOT graph with per-stage counts from the run.
+fn write_dot(path: &str, pipeline_text: &str, debug_info: &[pipelines_rs::DebugInfo]) {
+    let commands = match parse_commands(pipeline_text) {
+        Ok(commands) => commands,
+        Err(e) => {
+            eprintln!("Error rendering DOT graph: {e}");
+            return;
+        }
+    };
+    if let Err(e) = fs::write(path, pipeline_to_dot(&commands, Some(debug_info))) {
+        eprintln!("Error writing DOT file '{path}': {e}");
+    }
+}
+
+/// Write Prometheus metrics for the run, named after the pipeline file stem.
+fn write_metrics(
+    path: &str,
+    pipeline_path: &str,
+    result: &Result<PipelineRun, String>,
+    started: Instant,
+) {
+    let name = Path::new(pipeline_path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| pipeline_path.to_string());
+    let mut job = JobMetrics::new(name, "batch");
+    job.duration = started.elapsed();
+    match result {
+        Ok(run) => {
+            job.records_in = run.input_count;
+            job.records_out = run.output_count;
+            job.set_stages(&run.debug_info);
+        }
+        Err(_) => job.success = false,
+    }
+
+    let mut registry = MetricsRegistry::new();
+    registry.record(&job);
+    if let Err(e) = fs::write(path, registry.render()) {
+        eprintln!("Error writing metrics file '{path}': {e}");
+    }
+}
@@ -0,0 +1,221 @@
+//! CLI tool to regression-test a directory of pipelines against golden
+//! output files (see `pipelines_rs::golden`).
+//!
+//! Each `NAME.pipe` runs against `NAME.data` (or the `--input` file) and
+//! its output is compared with `NAME.expected`. Exit codes: 0 when every
+//! pipeline matches, 1 when one differs, fails, or has no expected file,
+//! 2 when the directory cannot be read.
+
+use clap::Parser;
+use pipelines_rs::cli::parse_parm;
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::ebcdic::{self, CodePage};
+use pipelines_rs::golden::{compare, summary};
+use pipelines_rs::{
+    HostVars, RECORD_WIDTH, Record, execute_pipeline_with_vars, parse_commands, pipeline_lrecl,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Run each pipeline in a directory and compare its output to the
+/// expected output stored beside it.
+#[derive(Parser)]
+#[command(name = "pipe-test")]
+struct Cli {
+    /// Directory of pipeline files (.pipe)
+    #[arg(default_value = "specs")]
+    dir: PathBuf,
+
+    /// Input for pipelines without a NAME.data file of their own
+    #[arg(short, long)]
+    input: Option<PathBuf>,
+
+    /// Only run pipelines whose name contains this text
+    #[arg(short, long)]
+    filter: Option<String>,
+
+    /// Write each pipeline's output as its expected file instead of
+    /// comparing
+    #[arg(long)]
+    bless: bool,
+
+    /// Most differences to report per pipeline
+    #[arg(long, default_value_t = 5)]
+    max_diffs: usize,
+
+    /// Set a STAMP parameter, read as PARM.NAME (repeatable)
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_parm)]
+    parms: Vec<(String, String)>,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+}
+
+/// The result of one pipeline.
+enum Outcome {
+    Pass,
+    Blessed,
+    /// No expected file yet.
+    New,
+    /// The output differs; the lines report how.
+    Differs(Vec<String>),
+    /// A file cannot be read or the pipeline fails.
+    Error(String),
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let entries = match fs::read_dir(&cli.dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Error reading directory '{}': {e}", cli.dir.display());
+            process::exit(2);
+        }
+    };
+    let mut pipelines: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pipe"))
+        .filter(|path| {
+            cli.filter
+                .as_ref()
+                .is_none_or(|filter| name(path).contains(filter.as_str()))
+        })
+        .collect();
+    pipelines.sort();
+
+    println!("running {} pipelines", pipelines.len());
+    let (mut passed, mut failed, mut blessed) = (0, 0, 0);
+    for pipeline in &pipelines {
+        let name = name(pipeline);
+        match run(&cli, pipeline) {
+            Outcome::Pass => {
+                passed += 1;
+                println!("ok       {name}");
+            }
+            Outcome::Blessed => {
+                blessed += 1;
+                println!("blessed  {name}");
+            }
+            Outcome::New => {
+                failed += 1;
+                println!("NEW      {name}: no expected file (run with --bless)");
+            }
+            Outcome::Differs(report) => {
+                failed += 1;
+                println!("FAILED   {name}: {}", report[0]);
+                for line in &report[1..] {
+                    println!("  {line}");
+                }
+            }
+            Outcome::Error(e) => {
+                failed += 1;
+                println!("ERROR    {name}: {e}");
+            }
+        }
+    }
+
+    let result = if failed == 0 { "ok" } else { "FAILED" };
+    print!("\ntest result: {result}. {passed} passed; {failed} failed");
+    if cli.bless {
+        print!("; {blessed} blessed");
+    }
+    println!();
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Runs one pipeline and compares its output, or blesses it.
+fn run(cli: &Cli, pipeline: &Path) -> Outcome {
+    let pipeline_text = match fs::read_to_string(pipeline) {
+        Ok(text) => text,
+        Err(e) => return Outcome::Error(format!("cannot read pipeline: {e}")),
+    };
+    let pipeline_text = match cli.syntax.to_dsl(&pipeline_text) {
+        Ok(text) => text.into_owned(),
+        Err(e) => return Outcome::Error(e),
+    };
+    // An unparseable pipeline is reported when it runs
+    let commands = parse_commands(&pipeline_text).unwrap_or_default();
+    let lrecl = pipeline_lrecl(&pipeline_text).unwrap_or(RECORD_WIDTH);
+
+    let paired = pipeline.with_extension("data");
+    let Some(input) = paired.exists().then_some(paired).or(cli.input.clone()) else {
+        return Outcome::Error(format!(
+            "no input file ({} or --input)",
+            pipeline.with_extension("data").display()
+        ));
+    };
+    let input_text = match read(&input, ebcdic::input_code_page(&commands), lrecl) {
+        Ok(text) => text,
+        Err(e) => return Outcome::Error(format!("cannot read input: {e}")),
+    };
+
+    let vars = HostVars::for_pipeline_file(pipeline, None, &cli.parms, &commands);
+    let output = match execute_pipeline_with_vars(&input_text, &pipeline_text, &vars) {
+        Ok((output, _, _)) => output,
+        Err(e) => return Outcome::Error(e),
+    };
+
+    let expected_path = pipeline.with_extension("expected");
+    let output_code_page = ebcdic::output_code_page(&commands);
+    if cli.bless {
+        let mut text = output;
+        if !text.is_empty() && output_code_page.is_none() {
+            text.push('\n');
+        }
+        return match fs::write(
+            &expected_path,
+            ebcdic::encode_output(text, &commands, lrecl),
+        ) {
+            Ok(()) => Outcome::Blessed,
+            Err(e) => Outcome::Error(format!("cannot write expected file: {e}")),
+        };
+    }
+    if !expected_path.exists() {
+        return Outcome::New;
+    }
+    let expected_text = match read(&expected_path, output_code_page, lrecl) {
+        Ok(text) => text,
+        Err(e) => return Outcome::Error(format!("cannot read expected file: {e}")),
+    };
+
+    let records = |text: &str| -> Vec<Record> {
+        text.lines()
+            .map(|line| Record::from_str_lrecl(line, lrecl))
+            .collect()
+    };
+    let expected = records(&expected_text);
+    let actual = records(&output);
+    let differences = compare(&expected, &actual);
+    if differences.is_empty() {
+        return Outcome::Pass;
+    }
+    let mut report = vec![summary(&expected, &actual, &differences)];
+    for difference in differences.iter().take(cli.max_diffs) {
+        report.extend(difference.to_string().lines().map(str::to_string));
+    }
+    if differences.len() > cli.max_diffs {
+        report.push(format!("... {} more", differences.len() - cli.max_diffs));
+    }
+    Outcome::Differs(report)
+}
+
+/// Reads a data file, decoding EBCDIC if the pipeline converts it.
+fn read(path: &Path, code_page: Option<CodePage>, lrecl: usize) -> Result<String, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    match code_page {
+        Some(code_page) => Ok(ebcdic::decode_records(&bytes, code_page, lrecl)),
+        None => String::from_utf8(bytes).map_err(|_| "file is not UTF-8 text".to_string()),
+    }
+}
+
+/// The pipeline's name: its file name without `.pipe`.
+fn name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
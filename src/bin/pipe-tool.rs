@@ -0,0 +1,159 @@
+//! CLI tool to run ICETOOL-style reports over a data file (see
+//! `pipelines_rs::toolkit`).
+//!
+//! `verify` exits with status 8 when it finds invalid numbers, as a step
+//! with error records does (see `pipelines_rs::condcode`).
+
+use clap::{Parser, Subcommand};
+use pipelines_rs::toolkit::{occur, stats, unique, verify};
+use pipelines_rs::{ConditionCode, Record, SumField, SumFormat};
+use std::fs;
+use std::process;
+
+/// Report on the fields of a data file, as ICETOOL does.
+#[derive(Parser)]
+#[command(name = "pipe-tool")]
+struct Cli {
+    #[command(subcommand)]
+    operator: Operator,
+}
+
+#[derive(Subcommand)]
+enum Operator {
+    /// List each value of a field and how many records hold it (OCCUR)
+    Occur {
+        /// Input data file
+        input: String,
+
+        /// Field to count, as pos,len (0-based)
+        #[arg(long, value_name = "POS,LEN", value_parser = parse_field)]
+        on: SumField,
+
+        /// Only list values held by more than one record
+        #[arg(long, conflicts_with = "nodups")]
+        alldups: bool,
+
+        /// Only list values held by one record
+        #[arg(long)]
+        nodups: bool,
+    },
+    /// Count the distinct values of a field (UNIQUE)
+    Unique {
+        /// Input data file
+        input: String,
+
+        /// Field to count, as pos,len (0-based)
+        #[arg(long, value_name = "POS,LEN", value_parser = parse_field)]
+        on: SumField,
+    },
+    /// Show the count, minimum, maximum, average, and total of numeric
+    /// fields (STATS)
+    Stats {
+        /// Input data file
+        input: String,
+
+        /// Numeric field, as pos,len or pos,len,ZD for zoned decimal
+        /// (repeatable)
+        #[arg(long, value_name = "POS,LEN[,ZD]", value_parser = parse_field, required = true)]
+        on: Vec<SumField>,
+    },
+    /// List the records whose numeric fields are not numbers (VERIFY)
+    Verify {
+        /// Input data file
+        input: String,
+
+        /// Numeric field, as pos,len or pos,len,ZD for zoned decimal
+        /// (repeatable)
+        #[arg(long, value_name = "POS,LEN[,ZD]", value_parser = parse_field, required = true)]
+        on: Vec<SumField>,
+    },
+}
+
+/// Parses a field: `pos,len`, optionally followed by `,ZD`.
+fn parse_field(s: &str) -> Result<SumField, String> {
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let number = |n: &str| {
+        n.parse::<usize>()
+            .map_err(|_| format!("expected pos,len[,ZD], got '{s}'"))
+    };
+    let (pos, len, zoned) = match parts[..] {
+        [pos, len] => (number(pos)?, number(len)?, false),
+        [pos, len, format] if format.eq_ignore_ascii_case("ZD") => {
+            (number(pos)?, number(len)?, true)
+        }
+        _ => return Err(format!("expected pos,len[,ZD], got '{s}'")),
+    };
+    if len == 0 {
+        return Err(format!("field '{s}' has length 0"));
+    }
+    let field = SumField::new(pos, len);
+    Ok(if zoned { field.zoned() } else { field })
+}
+
+/// Writes a field as it is given on the command line.
+fn label(field: &SumField) -> String {
+    match field.format {
+        SumFormat::Signed => format!("{},{}", field.start, field.len),
+        SumFormat::Zoned => format!("{},{},ZD", field.start, field.len),
+    }
+}
+
+/// Reads the records of a data file, one per line.
+fn read_records(path: &str) -> Vec<Record> {
+    match fs::read_to_string(path) {
+        Ok(text) => text.lines().map(Record::from_str).collect(),
+        Err(e) => {
+            eprintln!("Error reading input file '{path}': {e}");
+            process::exit(ConditionCode::Fatal.code());
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    match cli.operator {
+        Operator::Occur {
+            input,
+            on,
+            alldups,
+            nodups,
+        } => {
+            let records = read_records(&input);
+            let occurrences = occur(&records, on.start, on.len)
+                .into_iter()
+                .filter(|o| (!alldups || o.count > 1) && (!nodups || o.count == 1));
+            for occurrence in occurrences {
+                println!(
+                    "{:<width$}  {:>10}",
+                    occurrence.value,
+                    occurrence.count,
+                    width = on.len
+                );
+            }
+        }
+        Operator::Unique { input, on } => {
+            let records = read_records(&input);
+            println!("{}", unique(&records, on.start, on.len));
+        }
+        Operator::Stats { input, on } => {
+            let records = read_records(&input);
+            for field in on {
+                println!("{}  {}", label(&field), stats(&records, field));
+            }
+        }
+        Operator::Verify { input, on } => {
+            let records = read_records(&input);
+            let mut found = false;
+            for field in on {
+                for invalid in verify(&records, field) {
+                    println!("{}  {invalid}", label(&field));
+                    found = true;
+                }
+            }
+            if found {
+                process::exit(ConditionCode::Error.code());
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! CLI tool to regression-test a pipeline against a golden output file.
+//!
+//! Exit codes: 0 when the output matches, 1 when it differs, 2 when the
+//! pipeline or a file cannot be read or run.
+
+use clap::Parser;
+use pipelines_rs::cli::parse_parm;
+use pipelines_rs::cms::Syntax;
+use pipelines_rs::ebcdic::{self, CodePage};
+use pipelines_rs::golden::{compare, summary};
+use pipelines_rs::{
+    HostVars, RECORD_WIDTH, Record, execute_pipeline_with_vars, parse_commands, pipeline_lrecl,
+};
+use std::fs;
+use std::path::Path;
+use std::process;
+
+/// Run a pipeline and compare its output to an expected output file.
+#[derive(Parser)]
+#[command(name = "pipe-verify")]
+struct Cli {
+    /// Pipeline definition file (.pipe)
+    pipeline: String,
+
+    /// Input data file (fixed-width records)
+    input: String,
+
+    /// Expected output file
+    expected: String,
+
+    /// Most differences to report
+    #[arg(long, default_value_t = 20)]
+    max_diffs: usize,
+
+    /// Replace the expected file with the actual output instead of comparing
+    #[arg(long)]
+    update: bool,
+
+    /// Set a STAMP parameter, read as PARM.NAME (repeatable)
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_parm)]
+    parms: Vec<(String, String)>,
+
+    /// Job step name for STAMP's STEP variable (default: pipeline file name)
+    #[arg(long)]
+    step: Option<String>,
+
+    /// Print a line for passing pipelines too
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Pipeline syntax: dsl, cms (CMS Pipelines), or auto
+    #[arg(long, default_value = "auto", value_parser = str::parse::<Syntax>)]
+    syntax: Syntax,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let pipeline_text = match fs::read_to_string(&cli.pipeline) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error reading pipeline file '{}': {e}", cli.pipeline);
+            process::exit(2);
+        }
+    };
+    let pipeline_text = match cli.syntax.to_dsl(&pipeline_text) {
+        Ok(text) => text.into_owned(),
+        Err(e) => {
+            eprintln!("ERROR {}: {e}", cli.pipeline);
+            process::exit(2);
+        }
+    };
+    // An unparseable pipeline is reported when it runs
+    let commands = parse_commands(&pipeline_text).unwrap_or_default();
+    let lrecl = pipeline_lrecl(&pipeline_text).unwrap_or(RECORD_WIDTH);
+
+    // The input and expected files are EBCDIC if the pipeline converts them
+    let read = |path: &str, what: &str, code_page: Option<CodePage>| {
+        let text = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| match code_page {
+                Some(code_page) => Ok(ebcdic::decode_records(&bytes, code_page, lrecl)),
+                None => String::from_utf8(bytes).map_err(|_| "file is not UTF-8 text".to_string()),
+            });
+        text.unwrap_or_else(|e| {
+            eprintln!("Error reading {what} file '{path}': {e}");
+            process::exit(2);
+        })
+    };
+    let input_text = read(&cli.input, "input", ebcdic::input_code_page(&commands));
+
+    let vars = HostVars::for_pipeline_file(
+        Path::new(&cli.pipeline),
+        cli.step.as_deref(),
+        &cli.parms,
+        &commands,
+    );
+
+    let output = match execute_pipeline_with_vars(&input_text, &pipeline_text, &vars) {
+        Ok((output, _, _)) => output,
+        Err(e) => {
+            eprintln!("ERROR {}: {e}", cli.pipeline);
+            process::exit(2);
+        }
+    };
+
+    let output_code_page = ebcdic::output_code_page(&commands);
+    if cli.update {
+        let mut text = output.clone();
+        if !text.is_empty() && output_code_page.is_none() {
+            text.push('\n');
+        }
+        if let Err(e) = fs::write(&cli.expected, ebcdic::encode_output(text, &commands, lrecl)) {
+            eprintln!("Error writing expected file '{}': {e}", cli.expected);
+            process::exit(2);
+        }
+        println!("UPDATED {}", cli.expected);
+        return;
+    }
+
+    let expected: Vec<Record> = read(&cli.expected, "expected", output_code_page)
+        .lines()
+        .map(|line| Record::from_str_lrecl(line, lrecl))
+        .collect();
+    let actual: Vec<Record> = output
+        .lines()
+        .map(|line| Record::from_str_lrecl(line, lrecl))
+        .collect();
+    let differences = compare(&expected, &actual);
+
+    if differences.is_empty() {
+        if cli.verbose {
+            println!("PASS {} ({} records)", cli.pipeline, actual.len());
+        }
+        return;
+    }
+
+    println!(
+        "FAIL {}: {}",
+        cli.pipeline,
+        summary(&expected, &actual, &differences)
+    );
+    for difference in differences.iter().take(cli.max_diffs) {
+        for line in difference.to_string().lines() {
+            println!("  {line}");
+        }
+    }
+    if differences.len() > cli.max_diffs {
+        println!("  ... {} more", differences.len() - cli.max_diffs);
+    }
+    process::exit(1);
+}
@@ -0,0 +1,90 @@
+//! Argument parsers shared by the command-line tools.
+
+/// Parses a `--set NAME=VALUE` parameter argument; the value may be empty.
+pub fn parse_parm(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{arg}'")),
+    }
+}
+
+/// Parses an `--in` or `--out` `NAME=PATH` argument, naming the file a
+/// `? < NAME` segment reads or a `> NAME` stage writes.
+pub fn parse_dd(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, path)) if !name.is_empty() && !path.is_empty() => {
+            Ok((name.to_string(), path.to_string()))
+        }
+        _ => Err(format!("expected NAME=PATH, got '{arg}'")),
+    }
+}
+
+/// Parses a `-o [NAME=]PATH` argument, where NAME names the output of a
+/// `SPLIT NAME` stage; NAME is uppercased.
+pub fn parse_output(arg: &str) -> Result<(Option<String>, String), String> {
+    match arg.split_once('=') {
+        Some((name, path))
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+        {
+            if path.is_empty() {
+                return Err(format!("expected NAME=PATH, got '{arg}'"));
+            }
+            Ok((Some(name.to_uppercase()), path.to_string()))
+        }
+        _ => Ok((None, arg.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_parm() {
+        let parm = |name: &str, value: &str| Ok((name.to_string(), value.to_string()));
+        assert_eq!(parse_parm("REGION=EAST"), parm("REGION", "EAST"));
+        assert_eq!(parse_parm("EMPTY="), parm("EMPTY", ""));
+        assert_eq!(parse_parm("EXPR=A=B"), parm("EXPR", "A=B"));
+        assert_eq!(
+            parse_parm("REGION").unwrap_err(),
+            "expected NAME=VALUE, got 'REGION'"
+        );
+        assert!(parse_parm("=EAST").is_err());
+    }
+
+    #[test]
+    fn test_parse_dd() {
+        assert_eq!(
+            parse_dd("TRANS=data/trans.txt"),
+            Ok(("TRANS".to_string(), "data/trans.txt".to_string()))
+        );
+        assert_eq!(
+            parse_dd("OUT=a=b.txt"),
+            Ok(("OUT".to_string(), "a=b.txt".to_string()))
+        );
+        for arg in ["TRANS", "TRANS=", "=trans.txt", ""] {
+            assert_eq!(
+                parse_dd(arg).unwrap_err(),
+                format!("expected NAME=PATH, got '{arg}'")
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(parse_output("out.txt"), Ok((None, "out.txt".to_string())));
+        assert_eq!(
+            parse_output("east=east.txt"),
+            Ok((Some("EAST".to_string()), "east.txt".to_string()))
+        );
+        // A name must be a word, so a path with `=` elsewhere is the main output
+        assert_eq!(
+            parse_output("./a=b.txt"),
+            Ok((None, "./a=b.txt".to_string()))
+        );
+        assert!(parse_output("EAST=").is_err());
+    }
+}
@@ -0,0 +1,600 @@
+//! CMS Pipelines syntax.
+//!
+//! Besides the line-oriented DSL, pipelines can be written as on VM/CMS:
+//! stages separated by `|` on one logical line, lowercase stage names,
+//! columns counting from 1, and CMS operands:
+//!
+//! ```text
+//! pipe < sales data a | locate 19-28 /SALES/ | spec 1-8 1 29.8 nw | console
+//! ```
+//!
+//! [`parse`] translates each stage into the DSL stage that does the same
+//! work and parses the result, so a CMS pipeline runs on both executors and
+//! prints back as DSL text ([`to_dsl`]). The stages translated are:
+//!
+//! | CMS Pipelines | DSL |
+//! |---------------|-----|
+//! | `< fn ft [fm]`, `> fn ft [fm]`, `>> fn ft [fm]` | `< fn.ft`, `> fn.ft`, `>> fn.ft` |
+//! | `console`, `cons`, `terminal`, `term` | `CONSOLE` |
+//! | `locate [range] /s/`, `nlocate [range] /s/` | `LOCATE`, `NLOCATE` |
+//! | `find text`, `nfind text` | `LOCATE`, `NLOCATE` of the leading columns (`_` is a blank) |
+//! | `take [first\|last] [n]` | `TAKE n`, `TAKE LAST n` |
+//! | `drop [first\|last] [n]` | `SKIP n`, `DROP LAST n` |
+//! | `count lines` | `COUNT` |
+//! | `change [range] /a/b/ [n]`, `xlate [range] ...` | `CHANGE`, `XLATE` |
+//! | `chop [n]`, `pad n [c]` | `TRUNC n`, `PAD n [c]` |
+//! | `duplicate [n]`, `dup [n]` | `DUPLICATE n` |
+//! | `unique [range]` | `UNIQUE` |
+//! | `sort [range [a\|d]] ...` | `SORT` |
+//! | `specs ...`, `spec ...` | `SPECS`, with columns counting from 1 and `n`/`nw` for `NEXT`/`NEXTWORD` |
+//!
+//! A range is `n`, `n-m`, `n.len`, `n-*`, or `*-m`, counting from 1; `*`
+//! is the end of the 80-byte record. Other stages (`literal`, `hole`,
+//! `reverse`, `between`, `fanin`, ...) are written the same way in both,
+//! and stages with no CMS counterpart, such as `FILTER`, keep their DSL
+//! syntax.
+//!
+//! `pipe (endchar ?)` makes `?` start another pipeline, which reads a
+//! labeled stage's secondary output (`? a: | ...`) as in the DSL, and
+//! `pipe (sep !)` uses `!` as the stage separator.
+//!
+//! Front ends that take either syntax use [`Syntax::Auto`]: text the DSL
+//! parser accepts is DSL; text it rejects but this module accepts is CMS
+//! Pipelines.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{HostVars, cms, execute_plan};
+//!
+//! let plan = cms::parse("pipe literal SMITH JOHN | spec 7-10 1 /,/ next 1-5 nw | console").unwrap();
+//! assert_eq!(
+//!     plan.to_string(),
+//!     "PIPE LITERAL SMITH JOHN\n| SPECS 6,4 0 /,/ NEXT 0,5 NEXTWORD\n| CONSOLE\n?"
+//! );
+//! let run = execute_plan(&plan, "", &None, &HostVars::new()).unwrap();
+//! assert_eq!(run.output, "JOHN, SMITH");
+//! ```
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use crate::dsl::delimit_with;
+use crate::plan::is_label;
+use crate::specs::binary_conversion_error;
+use crate::{PipelinePlan, RECORD_WIDTH};
+
+/// Which syntax pipeline text is written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// The line-oriented DSL.
+    Dsl,
+    /// CMS Pipelines.
+    Cms,
+    /// DSL if the DSL parser accepts it, otherwise CMS Pipelines if that
+    /// parses.
+    #[default]
+    Auto,
+}
+
+impl Syntax {
+    /// Returns `text` as DSL text, translating it from CMS Pipelines
+    /// syntax if need be.
+    ///
+    /// With [`Syntax::Auto`], text that parses as neither is returned
+    /// unchanged, so running it reports the DSL parser's error.
+    pub fn to_dsl(self, text: &str) -> Result<Cow<'_, str>, String> {
+        match self {
+            Syntax::Dsl => Ok(Cow::Borrowed(text)),
+            Syntax::Cms => to_dsl(text).map(Cow::Owned),
+            Syntax::Auto if PipelinePlan::parse(text).is_ok() => Ok(Cow::Borrowed(text)),
+            Syntax::Auto => Ok(to_dsl(text).map_or(Cow::Borrowed(text), Cow::Owned)),
+        }
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dsl" => Ok(Syntax::Dsl),
+            "cms" => Ok(Syntax::Cms),
+            "auto" => Ok(Syntax::Auto),
+            _ => Err(format!("unknown syntax '{s}' (expected dsl, cms, or auto)")),
+        }
+    }
+}
+
+/// Parses a pipeline written in CMS Pipelines syntax.
+///
+/// # Errors
+///
+/// Fails, quoting the stage, on stages and operands that cannot be
+/// translated or that the DSL rejects.
+pub fn parse(text: &str) -> Result<PipelinePlan, String> {
+    let (dsl, stages) = translate(text)?;
+    PipelinePlan::parse(&dsl).map_err(|e| {
+        // Point DSL errors at the CMS stage they came from
+        let stage = e
+            .strip_prefix("Line ")
+            .and_then(|rest| rest.split_once(": "))
+            .and_then(|(line, message)| {
+                Some((stages.get(line.parse::<usize>().ok()? - 1)?, message))
+            });
+        match stage {
+            Some((stage, message)) => format!("Stage '{stage}': {message}"),
+            None => e,
+        }
+    })
+}
+
+/// Translates a pipeline written in CMS Pipelines syntax into DSL text.
+pub fn to_dsl(text: &str) -> Result<String, String> {
+    parse(text).map(|plan| plan.to_string())
+}
+
+/// Translates the pipeline into DSL text, one stage per line, returning
+/// it with the CMS stage each line came from.
+fn translate(text: &str) -> Result<(String, Vec<String>), String> {
+    // A pipeline is one logical line, however it is broken up
+    let line = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut rest = line.as_str();
+    if let Some((word, after)) = split_word(rest)
+        && word.eq_ignore_ascii_case("PIPE")
+    {
+        rest = after;
+    }
+    let (separator, endchar, rest) = options(rest)?;
+
+    let mut dsl = Vec::new();
+    let mut stages = Vec::new();
+    let pipelines: Vec<&str> = match endchar {
+        Some(endchar) => rest.split(endchar).collect(),
+        None => vec![rest],
+    };
+    for (index, pipeline) in pipelines.into_iter().enumerate() {
+        for (position, stage) in pipeline.split(separator).enumerate() {
+            let stage = stage.trim();
+            let prefix = match (index, position) {
+                (0, 0) => "PIPE",
+                (_, 0) => "?",
+                _ => "|",
+            };
+            let dsl_stage = labeled_stage(stage).map_err(|e| format!("Stage '{stage}': {e}"))?;
+            dsl.push(format!("{prefix} {dsl_stage}"));
+            stages.push(stage.to_string());
+        }
+    }
+    dsl.push("?".to_string());
+    Ok((dsl.join("\n"), stages))
+}
+
+/// Reads the options in parentheses after `pipe`: the stage separator
+/// (`|` unless set) and the end character (none unless set), returning
+/// them with the text after the options.
+fn options(text: &str) -> Result<(char, Option<char>, &str), String> {
+    let Some(after) = text.strip_prefix('(') else {
+        return Ok(('|', None, text));
+    };
+    let (options, rest) = after
+        .split_once(')')
+        .ok_or("the pipe options have no closing parenthesis")?;
+    let mut separator = '|';
+    let mut endchar = None;
+    let mut words = options.split_whitespace();
+    while let Some(option) = words.next() {
+        let mut character = || match words.next().map(|w| w.chars().collect::<Vec<_>>()) {
+            Some(chars) if chars.len() == 1 => Ok(chars[0]),
+            _ => Err(format!("pipe option {option} needs a single character")),
+        };
+        match option.to_ascii_uppercase().as_str() {
+            "SEP" | "SEPARATOR" | "STAGESEP" => separator = character()?,
+            "END" | "ENDCHAR" => endchar = Some(character()?),
+            "NAME" => {
+                words.next();
+            }
+            _ => return Err(format!("pipe option '{option}' is not supported")),
+        }
+    }
+    if endchar == Some(separator) {
+        return Err("the end character and stage separator must differ".to_string());
+    }
+    Ok((separator, endchar, rest.trim_start()))
+}
+
+/// Translates a stage, keeping its label (`a:`) if it has one.
+fn labeled_stage(stage: &str) -> Result<String, String> {
+    match split_word(stage) {
+        Some((word, rest)) if word.strip_suffix(':').is_some_and(is_label) => {
+            if rest.is_empty() {
+                Ok(word.to_string())
+            } else {
+                Ok(format!("{word} {}", translate_stage(rest)?))
+            }
+        }
+        _ => translate_stage(stage),
+    }
+}
+
+/// Splits off the first word, returning it and the rest, trimmed.
+fn split_word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if text.is_empty() {
+        return None;
+    }
+    Some(
+        text.split_once(char::is_whitespace)
+            .map_or((text, ""), |(word, rest)| (word, rest.trim_start())),
+    )
+}
+
+/// Translates one stage into DSL.
+fn translate_stage(stage: &str) -> Result<String, String> {
+    if stage.is_empty() {
+        return Err("empty stage".to_string());
+    }
+    if let Some(rest) = stage.strip_prefix(">>") {
+        return Ok(format!(">> {}", fileid(rest)?));
+    }
+    if let Some(rest) = stage.strip_prefix('>') {
+        return Ok(format!("> {}", fileid(rest)?));
+    }
+    if let Some(rest) = stage.strip_prefix('<') {
+        return Ok(format!("< {}", fileid(rest)?));
+    }
+    let (verb, rest) = split_word(stage).unwrap_or_default();
+    let no_operands = |name: &str| {
+        if rest.is_empty() {
+            Ok(name.to_string())
+        } else {
+            Err(format!("{name} operands '{rest}' are not supported"))
+        }
+    };
+    match verb.to_ascii_uppercase().as_str() {
+        "CONSOLE" | "CONS" | "TERMINAL" | "TERM" => no_operands("CONSOLE"),
+        verb @ ("LOCATE" | "NLOCATE" | "CHANGE" | "XLATE" | "UNIQUE") => {
+            Ok(format!("{verb} {}", with_range(rest)?)
+                .trim_end()
+                .to_string())
+        }
+        verb @ ("FIND" | "NFIND") => {
+            let text = stage[verb.len()..].strip_prefix(' ').unwrap_or("");
+            if text.is_empty() {
+                return Err(format!("{verb} needs text"));
+            }
+            if text.contains(' ') {
+                return Err(format!(
+                    "{verb} blanks (which match any character) are not supported"
+                ));
+            }
+            let text = text.replace('_', " ");
+            let locate = if verb == "FIND" { "LOCATE" } else { "NLOCATE" };
+            Ok(format!(
+                "{locate} 0,{} {}",
+                text.len(),
+                delimit_with(&text, '/')
+            ))
+        }
+        verb @ ("TAKE" | "DROP") => {
+            let mut words = rest.split_whitespace().peekable();
+            let last = words.peek().is_some_and(|w| w.eq_ignore_ascii_case("LAST"));
+            if words
+                .peek()
+                .is_some_and(|w| w.eq_ignore_ascii_case("FIRST") || w.eq_ignore_ascii_case("LAST"))
+            {
+                words.next();
+            }
+            let n = match words.next() {
+                None => 1,
+                Some(n) => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("{verb} needs a number, not '{n}'"))?,
+            };
+            if let Some(word) = words.next() {
+                return Err(format!("unexpected '{word}'"));
+            }
+            Ok(match (verb, last) {
+                ("TAKE", false) => format!("TAKE {n}"),
+                ("TAKE", true) => format!("TAKE LAST {n}"),
+                (_, false) => format!("SKIP {n}"),
+                (_, true) => format!("DROP LAST {n}"),
+            })
+        }
+        "COUNT" if rest.eq_ignore_ascii_case("LINES") => Ok("COUNT".to_string()),
+        "COUNT" => Err("only COUNT LINES is supported".to_string()),
+        "CHOP" => match rest {
+            "" => Ok(format!("TRUNC {RECORD_WIDTH}")),
+            n if n.parse::<usize>().is_ok() => Ok(format!("TRUNC {n}")),
+            _ => Err(format!(
+                "CHOP '{rest}' is not supported (only a column count)"
+            )),
+        },
+        "PAD" => match split_word(rest) {
+            Some((side, _)) if side.eq_ignore_ascii_case("LEFT") => {
+                Err("PAD LEFT is not supported".to_string())
+            }
+            Some((side, after)) if side.eq_ignore_ascii_case("RIGHT") => Ok(format!("PAD {after}")),
+            _ => Ok(format!("PAD {rest}")),
+        },
+        "DUPLICATE" | "DUP" => Ok(format!("DUPLICATE {rest}").trim_end().to_string()),
+        "SORT" => sort(rest),
+        "SPECS" | "SPEC" => specs(rest),
+        "SPLIT" | "STRIP" | "JOIN" | "FANOUT" | "GATE" | "STEM" | "VAR" | "STACK" => {
+            Err(format!("{} is not supported", verb.to_ascii_uppercase()))
+        }
+        _ => Ok(stage.to_string()),
+    }
+}
+
+/// Converts a CMS file identifier (`fn ft [fm]`, or a path) to a path.
+fn fileid(text: &str) -> Result<String, String> {
+    match text.split_whitespace().collect::<Vec<_>>()[..] {
+        [path] => Ok(path.to_string()),
+        [name, kind] | [name, kind, _] => Ok(format!("{name}.{kind}")),
+        [] => Err("needs a file".to_string()),
+        _ => Err(format!("'{}' is not a file identifier", text.trim())),
+    }
+}
+
+/// Converts a CMS column range, counting from 1, to `pos,len`, or returns
+/// `None` if `word` is not a range.
+fn range(word: &str) -> Option<Result<(usize, usize), String>> {
+    if !word.starts_with(|c: char| c.is_ascii_digit() || c == '*') {
+        return None;
+    }
+    let column = |n: &str| match n.parse::<usize>() {
+        Ok(n) if (1..=RECORD_WIDTH).contains(&n) => Ok(n),
+        _ => Err(format!("invalid column '{n}' in range '{word}'")),
+    };
+    let parsed = (|| {
+        let (first, last) = if let Some((first, len)) = word.split_once('.') {
+            let first = column(first)?;
+            let len: usize = len
+                .parse()
+                .ok()
+                .filter(|&len| len >= 1)
+                .ok_or_else(|| format!("invalid length in range '{word}'"))?;
+            (first, first + len - 1)
+        } else {
+            match word.split_once('-') {
+                Some((first, last)) => {
+                    let first = if first == "*" { 1 } else { column(first)? };
+                    let last = if last == "*" {
+                        RECORD_WIDTH
+                    } else {
+                        column(last)?
+                    };
+                    (first, last)
+                }
+                None if word == "*" => (1, RECORD_WIDTH),
+                None => (column(word)?, column(word)?),
+            }
+        };
+        if last < first || last > RECORD_WIDTH {
+            return Err(format!("invalid range '{word}'"));
+        }
+        Ok((first - 1, last - first + 1))
+    })();
+    Some(parsed)
+}
+
+/// Converts a leading range in `operands` to `pos,len`.
+fn with_range(operands: &str) -> Result<String, String> {
+    match split_word(operands) {
+        Some((word, rest)) => match range(word) {
+            Some(field) => {
+                let (pos, len) = field?;
+                Ok(format!("{pos},{len} {rest}").trim_end().to_string())
+            }
+            None => Ok(operands.to_string()),
+        },
+        None => Ok(String::new()),
+    }
+}
+
+/// Translates SORT operands: ranges, each optionally followed by `A` or
+/// `D`, or just `A` or `D` for the whole record.
+fn sort(operands: &str) -> Result<String, String> {
+    let mut keys: Vec<String> = Vec::new();
+    // Whether the last word was a range, which an order may follow
+    let mut after_range = false;
+    for word in operands.split_whitespace() {
+        let order = match word.to_ascii_uppercase().as_str() {
+            "A" | "ASCENDING" => Some(""),
+            "D" | "DESCENDING" => Some(" D"),
+            _ => None,
+        };
+        match (order, keys.last_mut()) {
+            (Some(order), Some(key)) if after_range => key.push_str(order),
+            (Some(order), None) => keys.push(format!("0,{RECORD_WIDTH}{order}")),
+            (Some(_), Some(_)) => return Err(format!("SORT order '{word}' must follow a range")),
+            (None, _) => match range(word) {
+                Some(field) => {
+                    let (pos, len) = field?;
+                    keys.push(format!("{pos},{len}"));
+                }
+                None => return Err(format!("SORT operand '{word}' is not supported")),
+            },
+        }
+        after_range = order.is_none();
+    }
+    Ok(format!("SORT {}", keys.join("; ")).trim_end().to_string())
+}
+
+/// Translates SPECS items: each an input (range, delimited string, or
+/// `number`), an optional conversion, and an output (column, range,
+/// `next`, or `nextword`, with an optional `.width`).
+fn specs(operands: &str) -> Result<String, String> {
+    let mut items = Vec::new();
+    let mut rest = operands.trim_start();
+    while !rest.is_empty() {
+        let input_text;
+        (input_text, rest) = if rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '*') {
+            split_word(rest).unwrap_or_default()
+        } else {
+            // A delimited string, kept as written
+            let delimiter = rest.chars().next().unwrap_or_default();
+            let end = rest[delimiter.len_utf8()..]
+                .find(delimiter)
+                .ok_or_else(|| format!("unclosed delimiter '{delimiter}'"))?;
+            let at = end + 2 * delimiter.len_utf8();
+            (&rest[..at], rest[at..].trim_start())
+        };
+        let input = match range(input_text) {
+            Some(field) => {
+                let (pos, len) = field?;
+                format!("{pos},{len}")
+            }
+            None if ["NUMBER", "RECNO"].contains(&input_text.to_ascii_uppercase().as_str()) => {
+                "RECNO".to_string()
+            }
+            None if input_text.starts_with(|c: char| c.is_ascii_alphanumeric()) => {
+                return Err(format!("SPECS input '{input_text}' is not supported"));
+            }
+            None => input_text.to_string(),
+        };
+        let (mut word, mut after) = split_word(rest).ok_or("SPECS input has no output")?;
+        if let Some(e) = binary_conversion_error(word) {
+            return Err(e);
+        }
+        let conversion = match word.to_ascii_uppercase().as_str() {
+            "UPPER" | "LOWER" | "STRIP" => {
+                let conversion = word.to_ascii_uppercase();
+                (word, after) = split_word(after).ok_or("SPECS input has no output")?;
+                Some(conversion)
+            }
+            _ => None,
+        };
+        rest = after;
+        items.push(input);
+        items.extend(conversion);
+        items.push(output(word)?);
+    }
+    if items.is_empty() {
+        return Err("SPECS needs an input and an output".to_string());
+    }
+    Ok(format!("SPECS {}", items.join(" ")))
+}
+
+/// Translates a SPECS output.
+fn output(word: &str) -> Result<String, String> {
+    let (place, width) = match word.split_once('.') {
+        Some((place, width)) if !place.starts_with(|c: char| c.is_ascii_digit()) => {
+            (place, Some(width))
+        }
+        _ => (word, None),
+    };
+    let place = match place.to_ascii_uppercase().as_str() {
+        "N" | "NEXT" => "NEXT".to_string(),
+        "NW" | "NEXTWORD" => "NEXTWORD".to_string(),
+        _ => match range(place) {
+            // A range gives the column and the width
+            Some(field) => {
+                let (pos, len) = field?;
+                return Ok(if place.contains(['-', '.']) {
+                    format!("{pos}.{len}")
+                } else {
+                    pos.to_string()
+                });
+            }
+            None => return Err(format!("SPECS output '{word}' is not supported")),
+        },
+    };
+    Ok(match width {
+        Some(width) => format!("{place}.{width}"),
+        None => place,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HostVars, execute_plan};
+
+    const SALES: &str = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+
+    fn dsl(text: &str) -> String {
+        to_dsl(text).unwrap()
+    }
+
+    #[test]
+    fn test_translate_stages() {
+        assert_eq!(
+            dsl("pipe < sales data a | locate 19-28 /SALES/ | take first 1 | > out file a"),
+            "PIPE < sales.data\n| LOCATE 18,10 /SALES/\n| TAKE 1\n| > out.file\n?"
+        );
+        assert_eq!(
+            dsl("cons | drop | drop last 2 | take last | nfind DOE | count lines | cons"),
+            "PIPE CONSOLE\n| SKIP 1\n| DROP LAST 2\n| TAKE LAST 1\n| NLOCATE 0,3 /DOE/\n| COUNT\n| CONSOLE\n?"
+        );
+        assert_eq!(
+            dsl(
+                "literal  two  blanks | chop 10 | change 1.5 /a/b/ | dup | sort 1-8 d 9-* | unique 19-28"
+            ),
+            "PIPE LITERAL two  blanks\n| TRUNC 10\n| CHANGE 0,5 \"a\" \"b\"\n| DUPLICATE 1\n| SORT 0,8 D; 8,72\n| UNIQUE 18,10\n?"
+        );
+        assert_eq!(
+            dsl("pipe console\n| spec number 1.5 /:/ n 1-8 lower nw 29-36 20-27 | console"),
+            "PIPE CONSOLE\n| SPECS RECNO 0.5 /:/ NEXT 0,8 LOWER NEXTWORD 28,8 19.8\n| CONSOLE\n?"
+        );
+        // DSL stages with no CMS counterpart pass through
+        assert_eq!(
+            dsl("console | filter 18,10 = \"SALES\" | console"),
+            "PIPE CONSOLE\n| FILTER 18,10 = \"SALES\"\n| CONSOLE\n?"
+        );
+    }
+
+    #[test]
+    fn test_multistream() {
+        let text = "pipe (end ? sep !) console ! a: find SMITH ! console ? a: ! > rejects txt";
+        assert_eq!(
+            dsl(text),
+            "PIPE CONSOLE\n| a: LOCATE 0,5 /SMITH/\n| CONSOLE\n? a:\n| > rejects.txt\n?"
+        );
+        let run = execute_plan(&parse(text).unwrap(), SALES, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.output.lines().count(), 1);
+        assert_eq!(run.files.get("rejects.txt").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |text: &str| parse(text).unwrap_err();
+        assert_eq!(
+            err("console | locate 0-5 /x/ | console"),
+            "Stage 'locate 0-5 /x/': invalid column '0' in range '0-5'"
+        );
+        assert_eq!(
+            err("console | count words"),
+            "Stage 'count words': only COUNT LINES is supported"
+        );
+        assert_eq!(
+            err("console | bogus | console"),
+            "Stage 'bogus': Unknown command: bogus"
+        );
+        assert!(err("pipe (trace) console | console").contains("not supported"));
+        assert!(err("console | spec w1 1 | console").contains("w1"));
+        assert!(err("console | spec 1-4 c2d 1 | console").contains("binary"));
+        assert!(err("console | pad left 10").contains("LEFT"));
+    }
+
+    #[test]
+    fn test_syntax() {
+        let dsl_text = "PIPE CONSOLE\n| LOCATE 18,10 /SALES/\n| CONSOLE\n?";
+        let cms_text = "console | locate 19-28 /SALES/ | console";
+        assert_eq!(Syntax::Auto.to_dsl(dsl_text).unwrap(), dsl_text);
+        assert_eq!(Syntax::Auto.to_dsl(cms_text).unwrap(), dsl_text);
+        assert_eq!(Syntax::Auto.to_dsl("bogus").unwrap(), "bogus");
+        assert_eq!(Syntax::Dsl.to_dsl(cms_text).unwrap(), cms_text);
+        assert!(Syntax::Cms.to_dsl("console | count words").is_err());
+        assert_eq!("CMS".parse::<Syntax>(), Ok(Syntax::Cms));
+        assert!("rexx".parse::<Syntax>().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Condition codes.
+//!
+//! Like a job step, a pipeline run ends with a condition code that a
+//! scheduler can test to decide what runs next:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0 | The run was clean |
+//! | 4 | Warnings: input records longer than LRECL, CSV values truncated to fit their fields |
+//! | 8 | Record-level errors: records a stage diverted as bad, such as invalid dates dropped by `REFORMAT DATE` |
+//! | 12 | Fatal: the run failed, or had more error records than allowed |
+//!
+//! An `RC` line in the pipeline sets the thresholds:
+//!
+//! ```text
+//! RC WARN 1 ERROR 10 FATAL 1000
+//! PIPE CONSOLE
+//! | REFORMAT DATE 0 YYYYMMDD TO MMDDYY
+//! | CONSOLE
+//! ```
+//!
+//! `WARN n` gives code 4 from n warnings on (default 1), `ERROR n` code 8
+//! from n error records on (default 1; fewer count as warnings), and
+//! `FATAL n` code 12 from n error records on (default never). The
+//! [`PipelineRun`](crate::PipelineRun) of a batch run holds its code;
+//! `pipe-run` exits with it.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{ConditionCode, HostVars, execute_pipeline_run};
+//!
+//! let pipeline = "RC ERROR 2\nPIPE CONSOLE | REFORMAT DATE 0 YYYYMMDD TO MMDDYY | CONSOLE";
+//! let run = execute_pipeline_run("20240131\n20241345", pipeline, &None, &HostVars::new()).unwrap();
+//! assert_eq!(run.output, "013124");
+//! assert_eq!(run.error_records, 1);
+//! assert_eq!(run.condition_code, ConditionCode::Warning);
+//! assert_eq!(run.condition_code.code(), 4);
+//! ```
+
+use std::fmt;
+
+/// How a run ended, from clean to fatal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConditionCode {
+    /// Code 0: no warnings or errors.
+    #[default]
+    Ok,
+    /// Code 4: warnings only.
+    Warning,
+    /// Code 8: records diverted as errors.
+    Error,
+    /// Code 12: the run failed or had too many errors.
+    Fatal,
+}
+
+impl ConditionCode {
+    /// The numeric code: 0, 4, 8, or 12.
+    pub const fn code(self) -> i32 {
+        match self {
+            ConditionCode::Ok => 0,
+            ConditionCode::Warning => 4,
+            ConditionCode::Error => 8,
+            ConditionCode::Fatal => 12,
+        }
+    }
+}
+
+impl fmt::Display for ConditionCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.code(), f)
+    }
+}
+
+/// The counts of warnings and error records at which a run's condition
+/// code rises, set by the pipeline's `RC` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConditionCodes {
+    /// Warnings from which the code is 4.
+    pub warn: usize,
+    /// Error records from which the code is 8.
+    pub error: usize,
+    /// Error records from which the code is 12, if any.
+    pub fatal: Option<usize>,
+}
+
+impl Default for ConditionCodes {
+    fn default() -> Self {
+        Self {
+            warn: 1,
+            error: 1,
+            fatal: None,
+        }
+    }
+}
+
+impl ConditionCodes {
+    /// The condition code of a run with `warnings` warnings and `errors`
+    /// error records. Error records below the `ERROR` threshold count as
+    /// warnings.
+    pub fn classify(&self, warnings: usize, errors: usize) -> ConditionCode {
+        if self.fatal.is_some_and(|fatal| errors >= fatal) {
+            ConditionCode::Fatal
+        } else if errors >= self.error {
+            ConditionCode::Error
+        } else if warnings + errors >= self.warn {
+            ConditionCode::Warning
+        } else {
+            ConditionCode::Ok
+        }
+    }
+}
+
+/// Prints the thresholds as an `RC` line.
+impl fmt::Display for ConditionCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RC WARN {} ERROR {}", self.warn, self.error)?;
+        if let Some(fatal) = self.fatal {
+            write!(f, " FATAL {fatal}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let codes = ConditionCodes::default();
+        assert_eq!(codes.classify(0, 0), ConditionCode::Ok);
+        assert_eq!(codes.classify(2, 0), ConditionCode::Warning);
+        assert_eq!(codes.classify(0, 1), ConditionCode::Error);
+
+        let codes = ConditionCodes {
+            warn: 3,
+            error: 10,
+            fatal: Some(100),
+        };
+        assert_eq!(codes.classify(2, 0), ConditionCode::Ok);
+        assert_eq!(codes.classify(2, 1), ConditionCode::Warning);
+        assert_eq!(codes.classify(0, 10), ConditionCode::Error);
+        assert_eq!(codes.classify(0, 100), ConditionCode::Fatal);
+        assert_eq!(codes.to_string(), "RC WARN 3 ERROR 10 FATAL 100");
+        assert_eq!(ConditionCode::Fatal.code(), 12);
+        assert!(ConditionCode::Warning < ConditionCode::Error);
+    }
+}
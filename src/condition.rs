@@ -0,0 +1,427 @@
+//! FILTER conditions.
+//!
+//! A [`Condition`] compares record fields with values and combines the
+//! comparisons with `AND` and `OR`. Fields are compared as text (blanks
+//! trimmed, in byte order), as whole numbers (`NUM`), or as zoned decimal
+//! (`ZD`). A record whose field is not a number fails a numeric comparison.
+//! With the `regex` feature, a field can also be matched against a regular
+//! expression (`~`).
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{CompareOp, Condition, Record};
+//!
+//! let high_sales = Condition::number(28, 8, CompareOp::Gt, 55000)
+//!     .and(Condition::text(18, 10, CompareOp::Eq, "SALES"));
+//!
+//! assert!(!high_sales.matches(&Record::from_str("SMITH   JOHN      SALES     00050000")));
+//! assert!(high_sales.matches(&Record::from_str("DOE     JANE      SALES     00060000")));
+//! assert_eq!(high_sales.to_string(), r#"28,8 NUM > 55000 AND 18,10 = "SALES""#);
+//! ```
+
+use std::fmt;
+
+use crate::Record;
+use crate::dsl::{CompareOp, delimit_with};
+use crate::stage::SumField;
+
+/// How a field is read for a numeric comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// Whole number with an optional sign; blank is zero (`NUM`).
+    Plain,
+    /// Zoned decimal with an overpunch sign (`ZD`).
+    Zoned,
+}
+
+/// A FILTER condition: field comparisons joined by `AND` and `OR`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Compare the `pos, len` field, trimmed, with text.
+    Text {
+        pos: usize,
+        len: usize,
+        op: CompareOp,
+        value: String,
+    },
+    /// Compare the `pos, len` field, read as a number, with a number.
+    Number {
+        pos: usize,
+        len: usize,
+        format: NumberFormat,
+        op: CompareOp,
+        value: i64,
+    },
+    /// Match the `pos, len` field, trimmed, against a regular expression.
+    #[cfg(feature = "regex")]
+    Regex {
+        pos: usize,
+        len: usize,
+        pattern: Pattern,
+    },
+    /// Both conditions hold.
+    And(Box<Condition>, Box<Condition>),
+    /// Either condition holds.
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Compares the `pos, len` field as text.
+    pub fn text(pos: usize, len: usize, op: CompareOp, value: &str) -> Self {
+        Condition::Text {
+            pos,
+            len,
+            op,
+            value: value.to_string(),
+        }
+    }
+
+    /// Compares the `pos, len` field as a whole number.
+    pub fn number(pos: usize, len: usize, op: CompareOp, value: i64) -> Self {
+        Condition::Number {
+            pos,
+            len,
+            format: NumberFormat::Plain,
+            op,
+            value,
+        }
+    }
+
+    /// Compares the `pos, len` field as zoned decimal.
+    pub fn zoned(pos: usize, len: usize, op: CompareOp, value: i64) -> Self {
+        Condition::Number {
+            pos,
+            len,
+            format: NumberFormat::Zoned,
+            op,
+            value,
+        }
+    }
+
+    /// Matches the `pos, len` field against `regex`.
+    #[cfg(feature = "regex")]
+    pub fn regex(pos: usize, len: usize, regex: regex::Regex) -> Self {
+        Condition::Regex {
+            pos,
+            len,
+            pattern: Pattern(regex),
+        }
+    }
+
+    /// Holds when both `self` and `other` hold.
+    pub fn and(self, other: Condition) -> Self {
+        Condition::And(Box::new(self), Box::new(other))
+    }
+
+    /// Holds when `self` or `other` holds.
+    pub fn or(self, other: Condition) -> Self {
+        Condition::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Returns true if `record` meets the condition.
+    pub fn matches(&self, record: &Record) -> bool {
+        match self {
+            Condition::Text {
+                pos,
+                len,
+                op,
+                value,
+            } => op.compare(record.field(*pos, *len).trim(), value.trim()),
+            Condition::Number {
+                pos,
+                len,
+                format,
+                op,
+                value,
+            } => {
+                let field = match format {
+                    NumberFormat::Plain => SumField::new(*pos, *len).read(record),
+                    NumberFormat::Zoned => record.field_zoned(*pos, *len),
+                };
+                field.is_some_and(|field| op.compare(field, *value))
+            }
+            #[cfg(feature = "regex")]
+            Condition::Regex { pos, len, pattern } => record.field_matches(*pos, *len, &pattern.0),
+            Condition::And(left, right) => left.matches(record) && right.matches(record),
+            Condition::Or(left, right) => left.matches(record) || right.matches(record),
+        }
+    }
+
+    /// Returns true if a field the condition compares as a number holds
+    /// something else (a blank `NUM` field is zero). Such a record fails
+    /// those comparisons. Only comparisons that `matches` evaluates count:
+    /// the right side of `AND` when the left matched, and of `OR` when it
+    /// did not.
+    pub fn has_bad_number(&self, record: &Record) -> bool {
+        match self {
+            Condition::Text { .. } => false,
+            Condition::Number {
+                pos, len, format, ..
+            } => match format {
+                NumberFormat::Plain => SumField::new(*pos, *len).read(record).is_none(),
+                NumberFormat::Zoned => record.field_zoned(*pos, *len).is_none(),
+            },
+            #[cfg(feature = "regex")]
+            Condition::Regex { .. } => false,
+            Condition::And(left, right) => {
+                left.has_bad_number(record)
+                    || (left.matches(record) && right.has_bad_number(record))
+            }
+            Condition::Or(left, right) => {
+                left.has_bad_number(record)
+                    || (!left.matches(record) && right.has_bad_number(record))
+            }
+        }
+    }
+
+    /// Returns the `(pos, len)` fields the condition compares, left to
+    /// right.
+    pub fn fields(&self) -> Vec<(usize, usize)> {
+        match self {
+            Condition::Text { pos, len, .. } | Condition::Number { pos, len, .. } => {
+                vec![(*pos, *len)]
+            }
+            #[cfg(feature = "regex")]
+            Condition::Regex { pos, len, .. } => vec![(*pos, *len)],
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let mut fields = left.fields();
+                fields.extend(right.fields());
+                fields
+            }
+        }
+    }
+
+    /// Explains why `record` fails the condition by the value of each field
+    /// in a comparison that does not hold: `value was "MARKETING"` when the
+    /// condition reads one field, `18,10 was "MARKETING", 28,8 was
+    /// "00040000"` when it reads several. Returns `None` if the record
+    /// meets the condition.
+    pub fn explain_failure(&self, record: &Record) -> Option<String> {
+        if self.matches(record) {
+            return None;
+        }
+        let mut failed = Vec::new();
+        self.failed_fields(record, &mut failed);
+        let value = |pos: usize, len: usize| format!("\"{}\"", record.field(pos, len).trim());
+        let mut fields = self.fields();
+        fields.sort_unstable();
+        fields.dedup();
+        if let ([_], [(pos, len)]) = (fields.as_slice(), failed.as_slice()) {
+            return Some(format!("value was {}", value(*pos, *len)));
+        }
+        let values: Vec<String> = failed
+            .iter()
+            .map(|&(pos, len)| format!("{pos},{len} was {}", value(pos, len)))
+            .collect();
+        Some(values.join(", "))
+    }
+
+    /// Adds the fields of the comparisons `record` fails, as far as
+    /// evaluation goes: the right of an `AND` only if its left holds.
+    fn failed_fields(&self, record: &Record, failed: &mut Vec<(usize, usize)>) {
+        match self {
+            Condition::And(left, right) if left.matches(record) => {
+                right.failed_fields(record, failed)
+            }
+            Condition::And(left, _) => left.failed_fields(record, failed),
+            Condition::Or(left, right) => {
+                left.failed_fields(record, failed);
+                right.failed_fields(record, failed);
+            }
+            _ if self.matches(record) => {}
+            _ => {
+                for field in self.fields() {
+                    if !failed.contains(&field) {
+                        failed.push(field);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes `child` of an `AND` (`and` true) or `OR`, in parentheses
+    /// where needed to parse back to the same tree.
+    fn fmt_child(
+        f: &mut fmt::Formatter<'_>,
+        child: &Condition,
+        and: bool,
+        right: bool,
+    ) -> fmt::Result {
+        let grouped = match child {
+            Condition::Or(..) => and || right,
+            Condition::And(..) => and && right,
+            _ => false,
+        };
+        if grouped {
+            write!(f, "({child})")
+        } else {
+            write!(f, "{child}")
+        }
+    }
+}
+
+/// Renders the condition as FILTER text.
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Text {
+                pos,
+                len,
+                op,
+                value,
+            } => {
+                write!(
+                    f,
+                    "{pos},{len} {} {}",
+                    op.symbol(),
+                    delimit_with(value, '"')
+                )
+            }
+            Condition::Number {
+                pos,
+                len,
+                format,
+                op,
+                value,
+            } => {
+                let mode = match format {
+                    NumberFormat::Plain => "NUM",
+                    NumberFormat::Zoned => "ZD",
+                };
+                write!(f, "{pos},{len} {mode} {} {value}", op.symbol())
+            }
+            #[cfg(feature = "regex")]
+            Condition::Regex { pos, len, pattern } => {
+                write!(f, "{pos},{len} ~ {}", delimit_with(pattern.0.as_str(), '/'))
+            }
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let and = matches!(self, Condition::And(..));
+                Self::fmt_child(f, left, and, false)?;
+                write!(f, " {} ", if and { "AND" } else { "OR" })?;
+                Self::fmt_child(f, right, and, true)
+            }
+        }
+    }
+}
+
+/// A compiled regular expression in a [`Condition`]; patterns with the
+/// same text are equal.
+#[cfg(feature = "regex")]
+#[derive(Debug, Clone)]
+pub struct Pattern(pub regex::Regex);
+
+#[cfg(feature = "regex")]
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl Eq for Pattern {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_ordering() {
+        let record = Record::from_str("  BETA    ");
+        assert!(Condition::text(0, 10, CompareOp::Gt, "ALPHA").matches(&record));
+        assert!(Condition::text(0, 10, CompareOp::Le, "BETA").matches(&record));
+        assert!(!Condition::text(0, 10, CompareOp::Lt, "BETA").matches(&record));
+    }
+
+    #[test]
+    fn test_number_formats() {
+        let record = Record::from_str("  -12 001J      ");
+        assert!(Condition::number(0, 5, CompareOp::Lt, -5).matches(&record));
+        assert!(Condition::zoned(6, 4, CompareOp::Eq, -11).matches(&record));
+        assert!(Condition::number(10, 4, CompareOp::Eq, 0).matches(&record));
+        assert!(!Condition::number(6, 4, CompareOp::Ne, 0).matches(&record));
+    }
+
+    #[test]
+    fn test_and_or() {
+        let a = Condition::text(0, 1, CompareOp::Eq, "A");
+        let b = Condition::text(1, 1, CompareOp::Eq, "B");
+        let c = Condition::text(2, 1, CompareOp::Eq, "C");
+        let either = a.clone().or(b.clone()).and(c.clone());
+        assert!(either.matches(&Record::from_str("AXC")));
+        assert!(!either.matches(&Record::from_str("ABX")));
+        assert_eq!(
+            either.to_string(),
+            r#"(0,1 = "A" OR 1,1 = "B") AND 2,1 = "C""#
+        );
+        assert_eq!(
+            a.clone().and(b.clone().and(c.clone())).to_string(),
+            r#"0,1 = "A" AND (1,1 = "B" AND 2,1 = "C")"#
+        );
+        assert_eq!(
+            a.and(b).or(c).to_string(),
+            r#"0,1 = "A" AND 1,1 = "B" OR 2,1 = "C""#
+        );
+    }
+
+    #[test]
+    fn test_bad_number_follows_short_circuit() {
+        let detail = Condition::text(0, 1, CompareOp::Eq, "D");
+        let amount = Condition::number(2, 4, CompareOp::Gt, 15);
+        let header = Record::from_str("H NAME");
+        let bad_detail = Record::from_str("D NAME");
+        // AND reads the amount only of records the first test keeps
+        let and = detail.clone().and(amount.clone());
+        assert!(!and.has_bad_number(&header));
+        assert!(and.has_bad_number(&bad_detail));
+        // OR reads it only of records the first test rejects
+        let or = detail.or(amount);
+        assert!(!or.has_bad_number(&bad_detail));
+        assert!(or.has_bad_number(&header));
+    }
+
+    #[test]
+    fn test_explain_failure() {
+        let record = Record::from_str("SMITH   JOHN      MARKETING 00040000");
+        let sales = Condition::text(18, 10, CompareOp::Eq, "SALES");
+        assert_eq!(
+            sales.explain_failure(&record).as_deref(),
+            Some(r#"value was "MARKETING""#)
+        );
+        assert_eq!(
+            Condition::text(18, 10, CompareOp::Ne, "SALES")
+                .or(Condition::text(18, 10, CompareOp::Eq, "X"))
+                .explain_failure(&Record::from_str("                  SALES")),
+            Some(r#"value was "SALES""#.to_string())
+        );
+
+        let pay = Condition::number(28, 8, CompareOp::Gt, 50000);
+        let both = pay.clone().and(sales.clone());
+        assert_eq!(
+            both.explain_failure(&record).as_deref(),
+            Some(r#"28,8 was "00040000""#)
+        );
+        let rich = Record::from_str("DOE     JANE      MARKETING 00090000");
+        assert_eq!(
+            both.explain_failure(&rich).as_deref(),
+            Some(r#"18,10 was "MARKETING""#)
+        );
+        assert_eq!(
+            sales.or(pay).explain_failure(&record).as_deref(),
+            Some(r#"18,10 was "MARKETING", 28,8 was "00040000""#)
+        );
+        assert_eq!(
+            both.explain_failure(&Record::from_str("DOE     JANE      SALES     00090000")),
+            None
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let date = Condition::regex(0, 12, regex::Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap());
+        assert!(date.matches(&Record::from_str("2026-02-04  X")));
+        assert!(!date.matches(&Record::from_str("04/02/2026  X")));
+        assert_eq!(date.to_string(), r"0,12 ~ /^\d{4}-\d{2}-\d{2}$/");
+    }
+}
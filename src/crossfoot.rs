@@ -0,0 +1,327 @@
+//! Crossfoot balancing controls.
+//!
+//! Financial batch jobs verify that amounts foot: the detail amounts on a
+//! record sum to its total field, or the detail records of a batch sum to
+//! the control total carried on its trailer. The [`Crossfoot`] stage checks
+//! one such rule, passing records that balance downstream and routing
+//! violations to a reject stream with a reason code.
+//!
+//! Amounts are signed whole numbers in display form (`00050000`, `-125`);
+//! blank amounts count as zero.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Record, Stage};
+//! use pipelines_rs::crossfoot::{Crossfoot, CrossfootRule, RejectReason};
+//!
+//! // Columns 0-5 + 6-11 must equal the total in 12-17
+//! let mut crossfoot = Crossfoot::new(CrossfootRule::RecordSum {
+//!     addends: vec![(0, 6), (6, 6)],
+//!     total: (12, 6),
+//! });
+//!
+//! assert!(crossfoot.process(Record::from_str("000100000200000300")).is_some());
+//! assert!(crossfoot.process(Record::from_str("000100000200000999")).is_none());
+//!
+//! let rejects = crossfoot.take_rejects();
+//! assert_eq!(rejects[0].reason, RejectReason::RecordOutOfBalance);
+//! assert_eq!(rejects[0].reason.code(), "CF01");
+//! ```
+
+use std::fmt;
+
+use crate::Record;
+use crate::stage::Stage;
+
+/// A balancing rule checked by [`Crossfoot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrossfootRule {
+    /// Each record's `addends` fields must sum to its `total` field.
+    RecordSum {
+        /// Amount fields as (pos, len).
+        addends: Vec<(usize, usize)>,
+        /// Total field as (pos, len).
+        total: (usize, usize),
+    },
+    /// The `amount` field of detail records must sum to the `total` field
+    /// of the next trailer record (one whose `trailer` field equals
+    /// `trailer_value`). The running sum restarts after each trailer.
+    Trailer {
+        /// Detail amount field as (pos, len).
+        amount: (usize, usize),
+        /// Field identifying trailer records, as (pos, len).
+        trailer: (usize, usize),
+        /// Value of the `trailer` field on trailer records.
+        trailer_value: String,
+        /// Control total field on the trailer, as (pos, len).
+        total: (usize, usize),
+    },
+}
+
+/// Why a record was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// A record's addends do not sum to its total (`CF01`).
+    RecordOutOfBalance,
+    /// Detail amounts do not sum to the trailer's control total (`CF02`).
+    TrailerOutOfBalance,
+    /// An amount or total field is not a number (`CF03`).
+    NonNumeric,
+    /// Detail records were not followed by a trailer (`CF04`).
+    MissingTrailer,
+}
+
+impl RejectReason {
+    /// Returns the reason code written on reject reports.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::RecordOutOfBalance => "CF01",
+            RejectReason::TrailerOutOfBalance => "CF02",
+            RejectReason::NonNumeric => "CF03",
+            RejectReason::MissingTrailer => "CF04",
+        }
+    }
+}
+
+/// A record that failed a crossfoot rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reject {
+    /// The offending record (the trailer, for trailer imbalances).
+    pub record: Record,
+    /// The rule that was violated.
+    pub reason: RejectReason,
+    /// Human-readable detail, e.g. `expected 300, found 999`.
+    pub message: String,
+}
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.reason.code(), self.message)
+    }
+}
+
+/// Crossfoot stage - passes balanced records, rejects the rest.
+///
+/// For [`CrossfootRule::Trailer`], detail records always pass (their
+/// balance is only known at the trailer); an out-of-balance trailer is
+/// rejected. Call [`Crossfoot::finish`] after the last record to reject a
+/// batch that never reached its trailer.
+pub struct Crossfoot {
+    rule: CrossfootRule,
+    running: i64,
+    details: usize,
+    rejects: Vec<Reject>,
+}
+
+impl Crossfoot {
+    /// Creates a crossfoot stage for the given rule.
+    pub fn new(rule: CrossfootRule) -> Self {
+        Self {
+            rule,
+            running: 0,
+            details: 0,
+            rejects: Vec::new(),
+        }
+    }
+
+    /// Returns the rejects collected so far.
+    pub fn rejects(&self) -> &[Reject] {
+        &self.rejects
+    }
+
+    /// Removes and returns the rejects collected so far.
+    pub fn take_rejects(&mut self) -> Vec<Reject> {
+        std::mem::take(&mut self.rejects)
+    }
+
+    /// Ends the input. Rejects an unterminated batch under a trailer rule.
+    pub fn finish(&mut self) {
+        if matches!(self.rule, CrossfootRule::Trailer { .. }) && self.details > 0 {
+            let message = format!(
+                "{} detail records (sum {}) without a trailer",
+                self.details, self.running
+            );
+            self.reject(Record::new(), RejectReason::MissingTrailer, message);
+            self.running = 0;
+            self.details = 0;
+        }
+    }
+
+    fn reject(&mut self, record: Record, reason: RejectReason, message: String) -> Option<Record> {
+        self.rejects.push(Reject {
+            record,
+            reason,
+            message,
+        });
+        None
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        match &self.rule {
+            CrossfootRule::RecordSum { addends, total } => {
+                let mut sum = 0i64;
+                for &(pos, len) in addends {
+                    match amount(&record, pos, len) {
+                        Some(value) => sum += value,
+                        None => {
+                            let message = format!("amount at {pos},{len} is not numeric");
+                            return self.reject(record, RejectReason::NonNumeric, message);
+                        }
+                    }
+                }
+                let (pos, len) = *total;
+                match amount(&record, pos, len) {
+                    Some(expected) if expected == sum => Some(record),
+                    Some(expected) => {
+                        let message = format!("expected {expected}, found {sum}");
+                        self.reject(record, RejectReason::RecordOutOfBalance, message)
+                    }
+                    None => {
+                        let message = format!("total at {pos},{len} is not numeric");
+                        self.reject(record, RejectReason::NonNumeric, message)
+                    }
+                }
+            }
+            CrossfootRule::Trailer {
+                amount: (amount_pos, amount_len),
+                trailer: (trailer_pos, trailer_len),
+                trailer_value,
+                total: (total_pos, total_len),
+            } => {
+                let (amount_pos, amount_len) = (*amount_pos, *amount_len);
+                let (total_pos, total_len) = (*total_pos, *total_len);
+                if record.field_eq(*trailer_pos, *trailer_len, trailer_value) {
+                    let sum = self.running;
+                    self.running = 0;
+                    self.details = 0;
+                    match amount(&record, total_pos, total_len) {
+                        Some(expected) if expected == sum => Some(record),
+                        Some(expected) => {
+                            let message = format!("expected {expected}, found {sum}");
+                            self.reject(record, RejectReason::TrailerOutOfBalance, message)
+                        }
+                        None => {
+                            let message =
+                                format!("total at {total_pos},{total_len} is not numeric");
+                            self.reject(record, RejectReason::NonNumeric, message)
+                        }
+                    }
+                } else {
+                    match amount(&record, amount_pos, amount_len) {
+                        Some(value) => {
+                            self.running += value;
+                            self.details += 1;
+                            Some(record)
+                        }
+                        None => {
+                            let message =
+                                format!("amount at {amount_pos},{amount_len} is not numeric");
+                            self.reject(record, RejectReason::NonNumeric, message)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Stage for Crossfoot {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Ends the input; see [`Crossfoot::finish`].
+    fn flush(&mut self) -> Vec<Record> {
+        self.finish();
+        Vec::new()
+    }
+}
+
+/// Parses a signed whole-number amount; blank is zero.
+fn amount(record: &Record, pos: usize, len: usize) -> Option<i64> {
+    let value = record.field(pos, len).trim();
+    if value.is_empty() {
+        return Some(0);
+    }
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(crossfoot: &mut Crossfoot, lines: &[&str]) -> Vec<String> {
+        lines
+            .iter()
+            .filter_map(|l| crossfoot.process(Record::from_str(l)))
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_record_sum() {
+        let mut crossfoot = Crossfoot::new(CrossfootRule::RecordSum {
+            addends: vec![(0, 4), (4, 4)],
+            total: (8, 5),
+        });
+        let passed = run(
+            &mut crossfoot,
+            &[
+                "0010002000030",
+                "0010002000031",
+                "00100A2000030",
+                "-0100020+0010",
+            ],
+        );
+        assert_eq!(passed, ["0010002000030", "-0100020+0010"]);
+
+        let rejects = crossfoot.take_rejects();
+        assert_eq!(rejects.len(), 2);
+        assert_eq!(rejects[0].to_string(), "CF01 expected 31, found 30");
+        assert_eq!(rejects[1].reason, RejectReason::NonNumeric);
+        assert!(crossfoot.rejects().is_empty());
+    }
+
+    #[test]
+    fn test_trailer_balance() {
+        let mut crossfoot = Crossfoot::new(CrossfootRule::Trailer {
+            amount: (2, 6),
+            trailer: (0, 1),
+            trailer_value: "T".to_string(),
+            total: (2, 6),
+        });
+        let passed = run(
+            &mut crossfoot,
+            &[
+                "D 000100", "D 000250", "T 000350", // balanced batch
+                "D 000100", "T 000999", // out of balance
+                "D 000005",
+            ],
+        );
+        assert_eq!(passed.len(), 5);
+        crossfoot.finish();
+
+        let rejects = crossfoot.take_rejects();
+        assert_eq!(rejects.len(), 2);
+        assert_eq!(rejects[0].reason, RejectReason::TrailerOutOfBalance);
+        assert_eq!(rejects[0].record.as_str().trim_end(), "T 000999");
+        assert_eq!(rejects[0].message, "expected 999, found 100");
+        assert_eq!(rejects[1].reason.code(), "CF04");
+    }
+
+    #[test]
+    fn test_amount_parsing() {
+        let record = Record::from_str("  42 -7 +3 1-2");
+        assert_eq!(amount(&record, 0, 4), Some(42));
+        assert_eq!(amount(&record, 4, 3), Some(-7));
+        assert_eq!(amount(&record, 7, 3), Some(3));
+        assert_eq!(amount(&record, 10, 4), None);
+        assert_eq!(amount(&record, 40, 4), Some(0));
+    }
+}
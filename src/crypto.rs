@@ -0,0 +1,567 @@
+//! Field-level encryption.
+//!
+//! `ENCRYPT` protects sensitive columns (account numbers, names) while the
+//! rest of the record stays readable and processable; `DECRYPT` restores
+//! them. Each field is sealed with AES-256-GCM under a fresh random nonce,
+//! and the nonce, ciphertext, and tag are written as hex or base64 text.
+//!
+//! The sealed text is longer than the value: `(len + 28) * 2` columns in
+//! hex, `4 * ceil((len + 28) / 3)` in base64. It can overwrite the field
+//! itself when the field is wide enough, or go to another range (`TO`)
+//! in an expanded layout. The field's trailing blanks are not encrypted.
+//!
+//! The 256-bit key is read from an environment variable (`PIPELINES_KEY`
+//! by default) as 64 hex digits, or from a key file holding either the
+//! 32 raw bytes or the hex digits.
+//!
+//! A record whose field does not open under the key is an error record
+//! (see [`crate::reject`]); [`Decrypt`] drops it unless given an
+//! [`ErrorPolicy`].
+//!
+//! The cipher itself requires the `crypto` feature. Without it the
+//! commands still parse, but pipelines using them fail before running.
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "crypto")]
+//! # {
+//! use pipelines_rs::crypto::{CryptSpec, Encoding, decrypt_stage, encrypt_stage};
+//! use pipelines_rs::Record;
+//!
+//! let key = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+//! let encrypt = CryptSpec::new((0, 8), (8, 48), Encoding::Base64).with_key_hex(key);
+//! let decrypt = CryptSpec::new((8, 48), (0, 8), Encoding::Base64).with_key_hex(key);
+//!
+//! let sealed = encrypt_stage(&encrypt).unwrap().process_batch(vec![Record::from_str("SECRET")]);
+//! let sealed = sealed[0].clone();
+//! assert_ne!(sealed.field(8, 48).trim(), "");
+//!
+//! let mut cleared = sealed.clone();
+//! cleared.set_field(0, 8, "");
+//! let opened = decrypt_stage(&decrypt).unwrap().process_batch(vec![cleared]);
+//! assert_eq!(opened[0].field(0, 8).trim(), "SECRET");
+//! # }
+//! ```
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::reject::{ErrorPolicy, Reason, Rejects};
+use crate::sink::Sink;
+use crate::stage::Stage;
+
+/// Environment variable holding the key when none is specified.
+pub const DEFAULT_KEY_ENV: &str = "PIPELINES_KEY";
+
+/// Bytes added to each value: 12-byte nonce plus 16-byte tag.
+const OVERHEAD: usize = 28;
+
+/// A boxed encryption or decryption stage.
+pub type CryptStage = Box<dyn Stage + Send>;
+
+/// Text encoding of the sealed bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// Lowercase hex, two columns per byte.
+    #[default]
+    Hex,
+    /// Standard base64 with padding.
+    Base64,
+}
+
+impl Encoding {
+    /// Returns the DSL keyword: `HEX` or `BASE64`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Hex => "HEX",
+            Encoding::Base64 => "BASE64",
+        }
+    }
+
+    /// Columns needed to hold a sealed value of `plain_len` bytes.
+    pub fn sealed_len(&self, plain_len: usize) -> usize {
+        let bytes = plain_len + OVERHEAD;
+        match self {
+            Encoding::Hex => bytes * 2,
+            Encoding::Base64 => bytes.div_ceil(3) * 4,
+        }
+    }
+}
+
+/// Where the key comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// Environment variable holding 64 hex digits.
+    Env(String),
+    /// File holding 32 raw bytes or 64 hex digits.
+    File(String),
+    /// Key given directly as 64 hex digits (`KEYHEX` in the DSL, for
+    /// tests).
+    Hex(String),
+}
+
+impl Default for KeySource {
+    fn default() -> Self {
+        KeySource::Env(DEFAULT_KEY_ENV.to_string())
+    }
+}
+
+/// An `ENCRYPT` or `DECRYPT` specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CryptSpec {
+    /// Field read, as (pos, len).
+    pub field: (usize, usize),
+    /// Field written, as (pos, len); may be the same as `field`.
+    pub dest: (usize, usize),
+    /// Text encoding of sealed values.
+    pub encoding: Encoding,
+    /// Key location.
+    pub key: KeySource,
+}
+
+impl CryptSpec {
+    /// Creates a spec using the default key source.
+    pub fn new(field: (usize, usize), dest: (usize, usize), encoding: Encoding) -> Self {
+        Self {
+            field,
+            dest,
+            encoding,
+            key: KeySource::default(),
+        }
+    }
+
+    /// Uses a key given as 64 hex digits.
+    pub fn with_key_hex(mut self, key: &str) -> Self {
+        self.key = KeySource::Hex(key.to_string());
+        self
+    }
+
+    /// Checks that the encrypted value of `field` fits in `dest`.
+    pub fn check_encrypt_width(&self) -> std::result::Result<(), String> {
+        let needed = self.encoding.sealed_len(self.field.1);
+        if self.dest.1 < needed {
+            return Err(format!(
+                "ENCRYPT {},{} {} needs {} columns, but the output field has {}",
+                self.field.0,
+                self.field.1,
+                self.encoding.name(),
+                needed,
+                self.dest.1
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Creates a stage that seals `spec.field` into `spec.dest`.
+pub fn encrypt_stage(spec: &CryptSpec) -> Result<CryptStage> {
+    spec.check_encrypt_width().map_err(PipelineError::Crypto)?;
+    imp::encrypt_stage(spec)
+}
+
+/// Creates a stage that opens `spec.field` into `spec.dest`.
+///
+/// Records whose field does not open are dropped; see [`Decrypt`].
+pub fn decrypt_stage(spec: &CryptSpec) -> Result<CryptStage> {
+    Ok(Box::new(Decrypt::new(spec)?))
+}
+
+/// Opens a sealed field into its output field.
+///
+/// A record whose field does not decode or authenticate under the key
+/// (a wrong key, or a tampered or damaged value) has reason `BADSEAL`; one
+/// whose value does not fit in the output field has reason `OVERFLOW`.
+/// By default such records are dropped. With [`Decrypt::rejects`] they go
+/// to a reject sink instead, and with [`ErrorPolicy::Fail`] the first one
+/// stops the stage.
+pub struct Decrypt<R = ()> {
+    cipher: imp::Cipher,
+    spec: CryptSpec,
+    rejects: Rejects<R>,
+}
+
+impl Decrypt {
+    /// Creates the stage, loading the key.
+    pub fn new(spec: &CryptSpec) -> Result<Self> {
+        Ok(Self {
+            cipher: imp::Cipher::load(&spec.key)?,
+            spec: spec.clone(),
+            rejects: Rejects::default(),
+        })
+    }
+
+    /// Sends records whose field does not open to `sink`, each with its
+    /// reason appended.
+    pub fn rejects<S: Sink>(self, sink: S) -> Decrypt<S> {
+        Decrypt {
+            cipher: self.cipher,
+            spec: self.spec,
+            rejects: self.rejects.with_sink(sink),
+        }
+    }
+}
+
+impl<R: Sink> Decrypt<R> {
+    /// Sets what happens to a record whose field does not open.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.rejects = self.rejects.with_policy(policy);
+        self
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.rejects.error()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.rejects.take_error()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, mut record: Record) -> Option<Record> {
+        if self.rejects.stopped() {
+            return None;
+        }
+        let (pos, len) = self.spec.field;
+        let Some(plain) = self
+            .cipher
+            .open(self.spec.encoding, record.field(pos, len).trim())
+        else {
+            // Under Ignore the record is dropped too
+            self.rejects
+                .reject(&record, Reason::BadSeal, self.spec.field);
+            return None;
+        };
+        let (dest_pos, dest_len) = self.spec.dest;
+        if plain.len() > dest_len {
+            self.rejects
+                .reject(&record, Reason::Overflow, self.spec.dest);
+            return None;
+        }
+        if self.spec.dest != self.spec.field {
+            record.set_field(pos, len, "");
+        }
+        record.set_field(dest_pos, dest_len, &plain);
+        Some(record)
+    }
+}
+
+impl<R: Sink> Stage for Decrypt<R> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Checks that the key for every `ENCRYPT` and `DECRYPT` command loads.
+pub fn validate(commands: &[crate::Command]) -> std::result::Result<(), String> {
+    for cmd in commands {
+        let result = match cmd {
+            crate::Command::Encrypt { spec } => encrypt_stage(spec),
+            crate::Command::Decrypt { spec } => decrypt_stage(spec),
+            _ => continue,
+        };
+        result.map_err(|e| format!("{}: {e}", cmd.name()))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "crypto")]
+mod imp {
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    use super::{CryptSpec, CryptStage, Encoding, KeySource};
+    use crate::Record;
+    use crate::error::{PipelineError, Result};
+    use crate::stage::Stage;
+
+    fn load_key(source: &KeySource) -> Result<Aes256Gcm> {
+        let bytes = match source {
+            KeySource::Env(name) => {
+                let text = std::env::var(name).map_err(|_| {
+                    PipelineError::Crypto(format!("key variable {name} is not set"))
+                })?;
+                decode_hex(text.trim())
+            }
+            KeySource::File(path) => {
+                let data = std::fs::read(path).map_err(|e| {
+                    PipelineError::Crypto(format!("cannot read key file '{path}': {e}"))
+                })?;
+                if data.len() == 32 {
+                    Some(data)
+                } else {
+                    std::str::from_utf8(&data)
+                        .ok()
+                        .and_then(|text| decode_hex(text.trim()))
+                }
+            }
+            KeySource::Hex(text) => decode_hex(text.trim()),
+        };
+        match bytes {
+            Some(bytes) if bytes.len() == 32 => {
+                Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)))
+            }
+            _ => Err(PipelineError::Crypto(
+                "key must be 32 bytes (64 hex digits)".to_string(),
+            )),
+        }
+    }
+
+    fn decode_hex(text: &str) -> Option<Vec<u8>> {
+        if !text.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    fn encode(encoding: Encoding, bytes: &[u8]) -> String {
+        match encoding {
+            Encoding::Hex => bytes.iter().map(|b| format!("{b:02x}")).collect(),
+            Encoding::Base64 => STANDARD.encode(bytes),
+        }
+    }
+
+    fn decode(encoding: Encoding, text: &str) -> Option<Vec<u8>> {
+        match encoding {
+            Encoding::Hex => decode_hex(text),
+            Encoding::Base64 => STANDARD.decode(text).ok(),
+        }
+    }
+
+    /// Seals a field with a fresh nonce per record.
+    struct Encrypt {
+        cipher: Aes256Gcm,
+        spec: CryptSpec,
+    }
+
+    impl Encrypt {
+        /// Processes one record, returning the record it passes on, if any.
+        pub fn process(&mut self, mut record: Record) -> Option<Record> {
+            let (pos, len) = self.spec.field;
+            let plain = record.field(pos, len).trim_end().to_string();
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let ciphertext = self
+                .cipher
+                .encrypt(&nonce, plain.as_bytes())
+                .expect("AES-GCM encryption of a record field cannot fail");
+            let mut sealed = nonce.to_vec();
+            sealed.extend(ciphertext);
+
+            let (dest_pos, dest_len) = self.spec.dest;
+            if self.spec.dest != self.spec.field {
+                record.set_field(pos, len, "");
+            }
+            record.set_field(dest_pos, dest_len, &encode(self.spec.encoding, &sealed));
+            Some(record)
+        }
+    }
+
+    impl Stage for Encrypt {
+        fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+            output.extend(self.process(record));
+        }
+    }
+
+    /// A loaded key, for opening sealed fields.
+    pub(crate) struct Cipher(Aes256Gcm);
+
+    impl Cipher {
+        pub(crate) fn load(source: &KeySource) -> Result<Self> {
+            load_key(source).map(Cipher)
+        }
+
+        /// Returns the value sealed in `text`, or `None` if it does not
+        /// decode or authenticate.
+        pub(crate) fn open(&self, encoding: Encoding, text: &str) -> Option<String> {
+            let sealed = decode(encoding, text)?;
+            if sealed.len() < 12 {
+                return None;
+            }
+            let (nonce, ciphertext) = sealed.split_at(12);
+            let plain = self.0.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+            String::from_utf8(plain).ok()
+        }
+    }
+
+    pub(super) fn encrypt_stage(spec: &CryptSpec) -> Result<CryptStage> {
+        Ok(Box::new(Encrypt {
+            cipher: load_key(&spec.key)?,
+            spec: spec.clone(),
+        }))
+    }
+}
+
+#[cfg(not(feature = "crypto"))]
+mod imp {
+    use super::{CryptSpec, CryptStage, Encoding, KeySource};
+    use crate::error::{PipelineError, Result};
+
+    fn unavailable() -> PipelineError {
+        PipelineError::Crypto("pipelines-rs was built without the `crypto` feature".to_string())
+    }
+
+    /// No key can be loaded without the cipher.
+    pub(crate) enum Cipher {}
+
+    impl Cipher {
+        pub(crate) fn load(_source: &KeySource) -> Result<Self> {
+            Err(unavailable())
+        }
+
+        pub(crate) fn open(&self, _encoding: Encoding, _text: &str) -> Option<String> {
+            match *self {}
+        }
+    }
+
+    pub(super) fn encrypt_stage(_spec: &CryptSpec) -> Result<CryptStage> {
+        Err(unavailable())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_len() {
+        assert_eq!(Encoding::Hex.sealed_len(8), 72);
+        assert_eq!(Encoding::Base64.sealed_len(8), 48);
+        assert_eq!(Encoding::Base64.sealed_len(32), 80);
+    }
+
+    #[test]
+    fn test_encrypt_width_check() {
+        let spec = CryptSpec::new((0, 8), (8, 47), Encoding::Base64);
+        assert!(spec.check_encrypt_width().unwrap_err().contains("needs 48"));
+        assert!(encrypt_stage(&spec).is_err());
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    #[test]
+    fn test_requires_feature() {
+        let spec = CryptSpec::new((0, 8), (8, 48), Encoding::Base64);
+        let err = decrypt_stage(&spec).err().unwrap();
+        assert!(err.to_string().contains("`crypto` feature"));
+    }
+
+    #[cfg(feature = "crypto")]
+    mod cipher {
+        use super::*;
+        use crate::Record;
+
+        const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+        /// Runs one record through a boxed stage.
+        fn process(stage: &mut CryptStage, record: Record) -> Option<Record> {
+            let mut output = Vec::new();
+            stage.process_into(record, &mut output);
+            output.pop()
+        }
+
+        #[test]
+        fn test_round_trip_hex() {
+            let spec = CryptSpec::new((0, 12), (0, 80), Encoding::Hex).with_key_hex(KEY);
+            let mut encrypt = encrypt_stage(&spec).unwrap();
+            let a = process(&mut encrypt, Record::from_str("ACCT-1234")).unwrap();
+            let b = process(&mut encrypt, Record::from_str("ACCT-1234")).unwrap();
+            assert_ne!(a, b, "each record gets a fresh nonce");
+            assert!(a.as_str().trim_end().bytes().all(|c| c.is_ascii_hexdigit()));
+
+            let spec = CryptSpec::new((0, 80), (0, 12), Encoding::Hex).with_key_hex(KEY);
+            let mut decrypt = decrypt_stage(&spec).unwrap();
+            assert_eq!(
+                process(&mut decrypt, a).unwrap().as_str().trim_end(),
+                "ACCT-1234"
+            );
+        }
+
+        #[test]
+        fn test_tampered_or_wrong_key_is_dropped() {
+            let spec = CryptSpec::new((0, 8), (10, 48), Encoding::Base64).with_key_hex(KEY);
+            let sealed = process(
+                &mut encrypt_stage(&spec).unwrap(),
+                Record::from_str("JONES   SALES"),
+            )
+            .unwrap();
+            assert_eq!(sealed.field(0, 8).trim(), "");
+
+            let open = CryptSpec::new((10, 48), (0, 8), Encoding::Base64);
+            let wrong_key = open.clone().with_key_hex(&KEY.replace('0', "f"));
+            assert!(process(&mut decrypt_stage(&wrong_key).unwrap(), sealed.clone()).is_none());
+
+            let mut tampered = sealed.clone();
+            let flipped = if tampered.field(20, 1) == "A" {
+                "B"
+            } else {
+                "A"
+            };
+            tampered.set_field(20, 1, flipped);
+            let mut decrypt = decrypt_stage(&open.with_key_hex(KEY)).unwrap();
+            assert!(process(&mut decrypt, tampered).is_none());
+            assert_eq!(
+                process(&mut decrypt, sealed).unwrap().field(0, 8).trim(),
+                "JONES"
+            );
+        }
+
+        #[test]
+        fn test_rejects() {
+            let spec = CryptSpec::new((0, 12), (12, 80), Encoding::Base64).with_key_hex(KEY);
+            let sealed = process(
+                &mut encrypt_stage(&spec).unwrap(),
+                Record::from_str("ACCT-1234"),
+            )
+            .unwrap();
+
+            let mut errors = Vec::new();
+            let open = CryptSpec::new((12, 56), (0, 4), Encoding::Base64).with_key_hex(KEY);
+            let mut decrypt = Decrypt::new(&open).unwrap().rejects(&mut errors);
+            assert!(decrypt.process(sealed.clone()).is_none());
+            assert!(decrypt.process(Record::from_str("not sealed")).is_none());
+            drop(decrypt);
+            let reasons: Vec<_> = errors
+                .iter()
+                .map(|r| r.field(80, 16).trim_end().to_string())
+                .collect();
+            assert_eq!(reasons, ["OVERFLOW 0,4", "BADSEAL 12,56"]);
+
+            let wide = CryptSpec::new((12, 56), (0, 12), Encoding::Base64);
+            let wrong_key = wide.clone().with_key_hex(&KEY.replace('0', "f"));
+            let mut failed = Decrypt::new(&wrong_key)
+                .unwrap()
+                .on_error(ErrorPolicy::Fail);
+            assert!(failed.process(sealed.clone()).is_none());
+            assert!(failed.error().unwrap().to_string().contains("BADSEAL"));
+            let mut decrypt = Decrypt::new(&wide.with_key_hex(KEY)).unwrap();
+            assert_eq!(
+                decrypt.process(sealed).unwrap().field(0, 12).trim(),
+                "ACCT-1234"
+            );
+        }
+
+        #[test]
+        fn test_key_sources() {
+            let dir = tempfile::tempdir().unwrap();
+            let raw = dir.path().join("raw.key");
+            std::fs::write(&raw, [7u8; 32]).unwrap();
+            let mut spec = CryptSpec::new((0, 4), (4, 64), Encoding::Hex);
+            spec.key = KeySource::File(raw.to_string_lossy().to_string());
+            assert!(encrypt_stage(&spec).is_ok());
+
+            spec.key = KeySource::Hex("abcd".to_string());
+            assert!(encrypt_stage(&spec).is_err());
+            spec.key = KeySource::Env("PIPELINES_RS_NO_SUCH_KEY".to_string());
+            let err = encrypt_stage(&spec).err().unwrap();
+            assert!(
+                err.to_string()
+                    .contains("PIPELINES_RS_NO_SUCH_KEY is not set")
+            );
+        }
+    }
+}
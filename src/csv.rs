@@ -0,0 +1,523 @@
+//! CSV import and export through a record layout.
+//!
+//! Most data arrives as CSV, not as fixed-width records. [`CsvSource`]
+//! reads CSV lines and places each column in a field of a
+//! [`RecordLayout`]: the columns in field order, or, with a header line,
+//! by column name. [`CsvSink`] and the [`ToCsv`] stage do the reverse,
+//! writing one CSV line per record with a column per field.
+//!
+//! Quoting follows RFC 4180 within a line: a quoted value may hold commas,
+//! and `""` stands for a quote. Values are placed like this:
+//!
+//! - `CHAR` and `DATE` fields are left-justified and padded with blanks.
+//!   A longer value is truncated, with a warning in
+//!   [`CsvSource::warnings`].
+//! - `NUMERIC` fields are right-justified and zero-padded, like
+//!   [`Record::set_field_num`]; a number too wide for its field is an
+//!   error, since truncating it would change its value.
+//! - Missing columns leave the field blank; extra columns are ignored with
+//!   a warning.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::csv::{CsvSource, to_csv_line};
+//! use pipelines_rs::RecordLayout;
+//!
+//! let layout: RecordLayout = "LAYOUT NAME 0,10; DEPT 10,10; SALARY 20,8 NUMERIC"
+//!     .parse()
+//!     .unwrap();
+//! let lines = ["name,salary,dept", "\"SMITH, JR\",50000,SALES"];
+//!
+//! let mut source = CsvSource::new(lines.iter(), &layout).with_header();
+//! let record = source.next().unwrap().unwrap();
+//! assert_eq!(record.field(0, 28), "SMITH, JR SALES     00050000");
+//! assert!(source.next().is_none());
+//!
+//! assert_eq!(to_csv_line(&record, &layout), "\"SMITH, JR\",SALES,50000");
+//! ```
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::error::{PipelineError, Result};
+use crate::layout::{FieldType, LayoutField, RecordLayout};
+use crate::record::{MAX_LRECL, Record};
+use crate::sink::Sink;
+use crate::stage::Stage;
+
+/// Splits a CSV line into its values, removing quotes.
+///
+/// # Errors
+///
+/// Fails with [`PipelineError::Csv`] if a quoted value is not closed, or
+/// its closing quote is followed by anything but a comma.
+pub fn split_csv_line(line: &str) -> Result<Vec<String>> {
+    let mut values = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        value.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => value.push(c),
+                    None => {
+                        return Err(PipelineError::Csv(format!(
+                            "unclosed quote in value {}",
+                            values.len() + 1
+                        )));
+                    }
+                }
+            }
+            if let Some(c) = chars.next_if(|&c| c != ',') {
+                return Err(PipelineError::Csv(format!(
+                    "'{c}' after the closing quote of value {}",
+                    values.len() + 1
+                )));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                value.push(c);
+            }
+        }
+        values.push(value);
+        // Only a comma or the end of the line can follow a value
+        if chars.next().is_none() {
+            return Ok(values);
+        }
+    }
+}
+
+/// Quotes a value if it holds a comma, a quote, or a line break.
+pub fn quote_csv(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Returns the CSV value of a field: its text without trailing blanks, and
+/// for `NUMERIC` fields without leading zeros when it is a whole number.
+fn field_text(record: &Record, field: &LayoutField) -> String {
+    let text = record.field(field.start, field.len).trim_end();
+    if field.field_type == FieldType::Numeric
+        && let Ok(n) = text.trim_start().parse::<i64>()
+    {
+        return n.to_string();
+    }
+    text.to_string()
+}
+
+/// Returns a record as a CSV line with one value per layout field.
+pub fn to_csv_line(record: &Record, layout: &RecordLayout) -> String {
+    layout
+        .fields()
+        .iter()
+        .map(|f| quote_csv(&field_text(record, f)).into_owned())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Returns the CSV header line naming the layout fields.
+pub fn csv_header(layout: &RecordLayout) -> String {
+    layout
+        .fields()
+        .iter()
+        .map(|f| quote_csv(&f.name).into_owned())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Reads CSV lines as records of a layout.
+///
+/// Empty lines are skipped. Each item is a record or the error for its
+/// line; reading continues after an error.
+pub struct CsvSource<I> {
+    lines: I,
+    layout: RecordLayout,
+    header: bool,
+    /// Column feeding each layout field, once known
+    columns: Option<Vec<Option<usize>>>,
+    line: usize,
+    warnings: Vec<String>,
+}
+
+impl<I, S> CsvSource<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    /// Creates a source placing the columns of each line in the fields of
+    /// `layout`, in order.
+    pub fn new(lines: I, layout: &RecordLayout) -> Self {
+        Self {
+            lines,
+            layout: layout.clone(),
+            header: false,
+            columns: None,
+            line: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Reads the first line as column names, and places each column in the
+    /// field of that name (ignoring case). Columns no field names are
+    /// ignored, and fields no column names are left blank.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// Returns the warnings so far: values truncated to fit their fields,
+    /// and lines with more columns than fields.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Maps the header's column names to layout fields.
+    fn read_header(&mut self, names: &[String]) {
+        let columns = self
+            .layout
+            .fields()
+            .iter()
+            .map(|f| {
+                names
+                    .iter()
+                    .position(|n| n.trim().eq_ignore_ascii_case(&f.name))
+            })
+            .collect();
+        self.columns = Some(columns);
+    }
+
+    /// Builds the record for one line's values.
+    fn build(&mut self, values: &[String]) -> Result<Record> {
+        let line = self.line;
+        let fields = self.layout.fields();
+        if self.columns.is_none() && values.len() > fields.len() {
+            self.warnings.push(format!(
+                "line {line}: {} columns for {} fields; the rest are ignored",
+                values.len(),
+                fields.len()
+            ));
+        }
+        let mut record = Record::blank(self.layout.lrecl());
+        for (i, field) in fields.iter().enumerate() {
+            let column = match &self.columns {
+                Some(columns) => columns[i],
+                None => Some(i),
+            };
+            let Some(value) = column.and_then(|c| values.get(c)) else {
+                continue;
+            };
+            let number = value.trim();
+            if field.field_type == FieldType::Numeric
+                && !number.is_empty()
+                && number.parse::<f64>().is_ok()
+            {
+                record
+                    .set_field_num(field.start, field.len, number)
+                    .map_err(|e| PipelineError::Csv(format!("line {line}: {}: {e}", field.name)))?;
+                continue;
+            }
+            if value.len() > field.len {
+                self.warnings.push(format!(
+                    "line {line}: {} '{value}' truncated to {} bytes",
+                    field.name, field.len
+                ));
+            }
+            record.set_field(field.start, field.len, value);
+        }
+        Ok(record)
+    }
+}
+
+impl<I, S> Iterator for CsvSource<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let line = line.as_ref();
+            if line.is_empty() {
+                continue;
+            }
+            let values = match split_csv_line(line) {
+                Ok(values) => values,
+                Err(e) => {
+                    return Some(Err(PipelineError::Csv(format!(
+                        "line {}: {}",
+                        self.line,
+                        e.to_string().trim_start_matches("CSV error: ")
+                    ))));
+                }
+            };
+            if self.header && self.columns.is_none() {
+                self.read_header(&values);
+                continue;
+            }
+            return Some(self.build(&values));
+        }
+    }
+}
+
+/// Writes records to an `io::Write` as CSV lines, one value per layout
+/// field, optionally after a header line.
+///
+/// Like [`WriteSink`](crate::WriteSink), writing stops at the first error,
+/// which [`CsvSink::finish`] returns.
+pub struct CsvSink<W: Write> {
+    inner: W,
+    layout: RecordLayout,
+    header: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Creates a sink writing the fields of `layout` to `inner`.
+    pub fn new(inner: W, layout: &RecordLayout) -> Self {
+        Self {
+            inner,
+            layout: layout.clone(),
+            header: false,
+            error: None,
+        }
+    }
+
+    /// Writes a header line of field names first, even if no records come.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// Writes the header line if it is still due.
+    fn write_header(&mut self) -> io::Result<()> {
+        if std::mem::take(&mut self.header) {
+            writeln!(self.inner, "{}", csv_header(&self.layout))?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the writer and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from writing or flushing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.write_header()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Sink for CsvSink<W> {
+    fn accept(&mut self, record: &Record) {
+        if self.error.is_none()
+            && let Err(e) = self
+                .write_header()
+                .and_then(|()| writeln!(self.inner, "{}", to_csv_line(record, &self.layout)))
+        {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// ToCsv stage - turns each record into a record holding its CSV line,
+/// after a header line if asked for.
+///
+/// A line longer than the record grows the record to fit (up to
+/// [`MAX_LRECL`]).
+#[derive(Debug, Clone)]
+pub struct ToCsv {
+    layout: RecordLayout,
+    header: bool,
+    started: bool,
+}
+
+impl ToCsv {
+    /// Creates a stage writing the fields of `layout`.
+    pub fn new(layout: &RecordLayout) -> Self {
+        Self {
+            layout: layout.clone(),
+            header: false,
+            started: false,
+        }
+    }
+
+    /// Writes a header line of field names first, even if no records come.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// Returns the record holding a CSV line.
+    fn line_record(&self, line: &str) -> Record {
+        let lrecl = line.len().clamp(self.layout.lrecl(), MAX_LRECL);
+        Record::from_str_lrecl(line, lrecl)
+    }
+
+    /// Returns the header record if it is still due.
+    fn header_record(&mut self) -> Option<Record> {
+        let due = self.header && !self.started;
+        self.started = true;
+        due.then(|| self.line_record(&csv_header(&self.layout)))
+    }
+}
+
+impl Stage for ToCsv {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.header_record());
+        output.push(self.line_record(&to_csv_line(&record, &self.layout)));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        let header = self.header_record();
+        self.started = false;
+        header.into_iter().collect()
+    }
+}
+
+/// A `FROM CSV` or `TO CSV` specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvSpec {
+    /// Fields the CSV columns map to.
+    pub layout: RecordLayout,
+    /// Is there a header line of column names?
+    pub header: bool,
+}
+
+impl CsvSpec {
+    /// Creates a source reading `lines` as this spec describes.
+    pub fn source<I, S>(&self, lines: I) -> CsvSource<I>
+    where
+        I: Iterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let source = CsvSource::new(lines, &self.layout);
+        if self.header {
+            source.with_header()
+        } else {
+            source
+        }
+    }
+
+    /// Creates the `TO CSV` stage this spec describes.
+    pub fn stage(&self) -> ToCsv {
+        let stage = ToCsv::new(&self.layout);
+        if self.header {
+            stage.with_header()
+        } else {
+            stage
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+
+    fn layout() -> RecordLayout {
+        "LAYOUT NAME 0,8; DEPT 8,10; PAY 18,8 NUMERIC"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_split_csv_line() {
+        let split = |line: &str| split_csv_line(line).unwrap();
+        assert_eq!(split("A,B,C"), ["A", "B", "C"]);
+        assert_eq!(
+            split("\"A,1\",\"say \"\"hi\"\"\",C"),
+            ["A,1", "say \"hi\"", "C"]
+        );
+        assert_eq!(split("A,,\"\""), ["A", "", ""]);
+        assert_eq!(split("A,"), ["A", ""]);
+        assert_eq!(split(""), [""]);
+        assert_eq!(split(" A , B"), [" A ", " B"]);
+        assert!(
+            split_csv_line("\"A,B")
+                .unwrap_err()
+                .to_string()
+                .contains("unclosed")
+        );
+        assert!(split_csv_line("\"A\"B,C").is_err());
+    }
+
+    #[test]
+    fn test_source_places_fields() {
+        let lines = ["SMITH,SALES,50000", "", "WASHINGTON,ENGINEERING,-75", "DOE"];
+        let mut source = CsvSource::new(lines.iter(), &layout());
+        let records: Vec<Record> = source.by_ref().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].field(0, 26), "SMITH   SALES     00050000");
+        assert_eq!(records[1].field(0, 26), "WASHINGTENGINEERIN-0000075");
+        assert_eq!(records[2].field(0, 26).trim_end(), "DOE");
+        assert_eq!(
+            source.warnings(),
+            [
+                "line 3: NAME 'WASHINGTON' truncated to 8 bytes",
+                "line 3: DEPT 'ENGINEERING' truncated to 10 bytes"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_source_errors_and_extra_columns() {
+        let lines = ["A,B,123456789", "A,\"B", "A,B,1,EXTRA", "A,B,N/A"];
+        let mut source = CsvSource::new(lines.iter(), &layout());
+        let err = source.next().unwrap().unwrap_err().to_string();
+        assert!(err.contains("line 1: PAY"), "{err}");
+        let err = source.next().unwrap().unwrap_err().to_string();
+        assert_eq!(err, "CSV error: line 2: unclosed quote in value 2");
+        assert_eq!(source.next().unwrap().unwrap().field(18, 8), "00000001");
+        assert_eq!(source.next().unwrap().unwrap().field(18, 8), "N/A     ");
+        assert_eq!(
+            source.warnings(),
+            ["line 3: 4 columns for 3 fields; the rest are ignored"]
+        );
+    }
+
+    #[test]
+    fn test_csv_sink_and_stage() {
+        let layout = layout();
+        let record = Record::from_str("SMITH   SALES     00050000");
+
+        let mut sink = CsvSink::new(Vec::new(), &layout).with_header();
+        sink.accept(&record);
+        let text = String::from_utf8(sink.finish().unwrap()).unwrap();
+        assert_eq!(text, "NAME,DEPT,PAY\nSMITH,SALES,50000\n");
+        let empty = CsvSink::new(Vec::new(), &layout).with_header();
+        assert_eq!(empty.finish().unwrap(), b"NAME,DEPT,PAY\n");
+
+        let out: Vec<Record> = Pipeline::new(vec![record.clone(), record].into_iter())
+            .stage(ToCsv::new(&layout).with_header())
+            .collect();
+        let lines: Vec<&str> = out.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(
+            lines,
+            ["NAME,DEPT,PAY", "SMITH,SALES,50000", "SMITH,SALES,50000"]
+        );
+
+        let mut stage = ToCsv::new(&layout).with_header();
+        assert_eq!(stage.flush()[0].as_str().trim_end(), "NAME,DEPT,PAY");
+
+        let wide: RecordLayout = "LAYOUT A 0,80".parse().unwrap();
+        let line = ToCsv::new(&wide).line_record(&"X,".repeat(50));
+        assert_eq!(line.lrecl(), 100);
+    }
+}
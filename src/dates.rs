@@ -0,0 +1,442 @@
+//! Dates in fixed-width fields.
+//!
+//! Mainframe files hold dates as digits in one of a few layouts:
+//! `YYYYMMDD`, `MMDDYY`, or Julian `YYDDD` (year and day of the year).
+//! [`Record::field_date`] reads a field as a [`Date`], checking that the
+//! date is real (no February 30th), and [`ReformatDate`] rewrites a date
+//! field from one layout to another.
+//!
+//! Two-digit years use a fixed century window: years below
+//! [`CENTURY_PIVOT`] are in the 2000s, the rest in the 1900s. Writing a
+//! two-digit layout keeps only the last two digits of the year.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::dates::{DateFormat, ReformatDate};
+//! use pipelines_rs::{Record, Stage};
+//!
+//! let record = Record::from_str("SMITH   19850315");
+//! let hired = record.field_date(8, 8, DateFormat::Yyyymmdd).unwrap();
+//! assert_eq!((hired.year(), hired.month(), hired.day()), (1985, 3, 15));
+//!
+//! let mut julian = ReformatDate::new(8, DateFormat::Yyyymmdd, DateFormat::Julian);
+//! let out = julian.process(record).unwrap();
+//! assert_eq!(out.field(8, 8), "85074   ");
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::reject::{ErrorPolicy, Reason, Rejects};
+use crate::sink::Sink;
+use crate::stage::Stage;
+
+/// Two-digit years below this are in the 2000s; the rest are in the 1900s.
+pub const CENTURY_PIVOT: u32 = 50;
+
+/// How a date is written in a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `YYYYMMDD`, e.g. `19850315`.
+    Yyyymmdd,
+    /// `MMDDYY`, e.g. `031585`.
+    Mmddyy,
+    /// Julian `YYDDD`: year and day of the year, e.g. `85074`.
+    Julian,
+}
+
+impl DateFormat {
+    /// Returns the number of columns a date takes.
+    pub fn width(&self) -> usize {
+        match self {
+            DateFormat::Yyyymmdd => 8,
+            DateFormat::Mmddyy => 6,
+            DateFormat::Julian => 5,
+        }
+    }
+
+    /// Returns the layout's name, as written in the DSL.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateFormat::Yyyymmdd => "YYYYMMDD",
+            DateFormat::Mmddyy => "MMDDYY",
+            DateFormat::Julian => "YYDDD",
+        }
+    }
+}
+
+impl fmt::Display for DateFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = PipelineError;
+
+    /// Parses a layout name, ignoring case.
+    fn from_str(s: &str) -> Result<Self> {
+        [DateFormat::Yyyymmdd, DateFormat::Mmddyy, DateFormat::Julian]
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                PipelineError::InvalidDate(format!(
+                    "unknown date format '{s}' (expected YYYYMMDD, MMDDYY, or YYDDD)"
+                ))
+            })
+    }
+}
+
+/// A calendar date. Dates order chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: u32,
+    month: u32,
+    day: u32,
+}
+
+impl Date {
+    /// Creates a date, or returns `None` if there is no such day.
+    pub fn new(year: u32, month: u32, day: u32) -> Option<Self> {
+        let real = (1..=9999).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day);
+        real.then_some(Self { year, month, day })
+    }
+
+    /// Creates the date that is day `ordinal` (from 1) of `year`, or
+    /// returns `None` if the year is shorter.
+    pub fn from_ordinal(year: u32, ordinal: u32) -> Option<Self> {
+        let mut day = ordinal;
+        for month in 1..=12 {
+            let days = days_in_month(year, month);
+            if day <= days {
+                return Self::new(year, month, day);
+            }
+            day -= days;
+        }
+        None
+    }
+
+    /// Returns the year.
+    pub fn year(&self) -> u32 {
+        self.year
+    }
+
+    /// Returns the month, from 1.
+    pub fn month(&self) -> u32 {
+        self.month
+    }
+
+    /// Returns the day of the month, from 1.
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    /// Returns the day of the year, from 1.
+    pub fn ordinal(&self) -> u32 {
+        (1..self.month)
+            .map(|month| days_in_month(self.year, month))
+            .sum::<u32>()
+            + self.day
+    }
+
+    /// Parses `text`, exactly as wide as `format`, as a date.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::InvalidDate`] if `text` is not all
+    /// digits of the right width, or names a day that does not exist.
+    pub fn parse(text: &str, format: DateFormat) -> Result<Self> {
+        let invalid = || PipelineError::InvalidDate(format!("'{text}' is not a {format} date"));
+        if text.len() != format.width() || !text.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let number = |range: std::ops::Range<usize>| text[range].parse::<u32>().unwrap_or(0);
+        let date = match format {
+            DateFormat::Yyyymmdd => Self::new(number(0..4), number(4..6), number(6..8)),
+            DateFormat::Mmddyy => Self::new(window(number(4..6)), number(0..2), number(2..4)),
+            DateFormat::Julian => Self::from_ordinal(window(number(0..2)), number(2..5)),
+        };
+        date.ok_or_else(invalid)
+    }
+
+    /// Writes the date in `format`.
+    pub fn format(&self, format: DateFormat) -> String {
+        let yy = self.year % 100;
+        match format {
+            DateFormat::Yyyymmdd => format!("{:04}{:02}{:02}", self.year, self.month, self.day),
+            DateFormat::Mmddyy => format!("{:02}{:02}{yy:02}", self.month, self.day),
+            DateFormat::Julian => format!("{yy:02}{:03}", self.ordinal()),
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    /// Writes the date as `YYYY-MM-DD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Is `year` a leap year in the Gregorian calendar?
+pub fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+/// Returns the number of days in `month` (from 1) of `year`.
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Places a two-digit year in its century (see [`CENTURY_PIVOT`]).
+fn window(yy: u32) -> u32 {
+    if yy < CENTURY_PIVOT {
+        2000 + yy
+    } else {
+        1900 + yy
+    }
+}
+
+impl Record {
+    /// Reads a field as a date in `format`, ignoring surrounding blanks.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if the field extends
+    /// past the record, and [`PipelineError::InvalidDate`] if it does not
+    /// hold a real date in `format`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    /// use pipelines_rs::dates::DateFormat;
+    ///
+    /// let record = Record::from_str("022988 022989");
+    /// assert!(record.field_date(0, 6, DateFormat::Mmddyy).is_ok());
+    /// assert!(record.field_date(7, 6, DateFormat::Mmddyy).is_err()); // not a leap year
+    /// ```
+    pub fn field_date(&self, start: usize, length: usize, format: DateFormat) -> Result<Date> {
+        Date::parse(self.try_field(start, length)?.trim(), format)
+    }
+}
+
+/// ReformatDate stage - rewrites a date field from one [`DateFormat`] to
+/// another (`REFORMAT DATE` in the DSL).
+///
+/// The new date is written over the old one, or at another column with
+/// [`ReformatDate::at`]. In place, a narrower date is padded with blanks
+/// to the old width, and a wider one overwrites the columns after it.
+///
+/// A record whose field is not a real date is handled by the stage's
+/// [`ErrorPolicy`]: by default it passes unchanged; with
+/// [`ReformatDate::rejects`] it goes to a reject sink with reason
+/// `BADDATE`.
+pub struct ReformatDate<R = ()> {
+    pos: usize,
+    from: DateFormat,
+    to: DateFormat,
+    dest: Option<usize>,
+    rejects: Rejects<R>,
+}
+
+impl ReformatDate {
+    /// Creates a stage rewriting the `from` date at `pos` as a `to` date.
+    pub fn new(pos: usize, from: DateFormat, to: DateFormat) -> Self {
+        Self {
+            pos,
+            from,
+            to,
+            dest: None,
+            rejects: Rejects::default(),
+        }
+    }
+
+    /// Sends records without a real date to `sink`, each with its reason
+    /// appended (see [`crate::reject`]).
+    pub fn rejects<S: Sink>(self, sink: S) -> ReformatDate<S> {
+        ReformatDate {
+            pos: self.pos,
+            from: self.from,
+            to: self.to,
+            dest: self.dest,
+            rejects: self.rejects.with_sink(sink),
+        }
+    }
+}
+
+impl<R: Sink> ReformatDate<R> {
+    /// Writes the new date at column `dest`, leaving the old one.
+    pub fn at(mut self, dest: usize) -> Self {
+        self.dest = Some(dest);
+        self
+    }
+
+    /// Sets what happens to a record without a real date.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.rejects = self.rejects.with_policy(policy);
+        self
+    }
+
+    /// Returns the record with its date rewritten.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the field does not hold a real date.
+    pub fn convert(&self, record: &Record) -> Result<Record> {
+        let date = record.field_date(self.pos, self.from.width(), self.from)?;
+        let (dest, width) = match self.dest {
+            Some(dest) => (dest, self.to.width()),
+            None => (self.pos, self.from.width().max(self.to.width())),
+        };
+        let mut out = record.clone();
+        out.set_field(dest, width, &date.format(self.to));
+        Ok(out)
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.rejects.error()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.rejects.take_error()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if self.rejects.stopped() {
+            return None;
+        }
+        match self.convert(&record) {
+            Ok(out) => Some(out),
+            Err(_) => {
+                let field = (self.pos, self.from.width());
+                self.rejects
+                    .reject(&record, Reason::InvalidDate, field)
+                    .then_some(record)
+            }
+        }
+    }
+}
+
+impl<R: Sink> Stage for ReformatDate<R> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// A `REFORMAT DATE` specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateSpec {
+    /// Column of the date (0-based).
+    pub pos: usize,
+    /// Layout of the date read.
+    pub from: DateFormat,
+    /// Layout of the date written.
+    pub to: DateFormat,
+    /// Column the new date is written at; over the old date if `None`.
+    pub dest: Option<usize>,
+}
+
+impl DateSpec {
+    /// Creates the stage this spec describes.
+    pub fn stage(&self) -> ReformatDate {
+        let stage = ReformatDate::new(self.pos, self.from, self.to);
+        match self.dest {
+            Some(dest) => stage.at(dest),
+            None => stage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let date = Date::new(1985, 3, 15).unwrap();
+        assert_eq!(Date::parse("19850315", DateFormat::Yyyymmdd).unwrap(), date);
+        assert_eq!(Date::parse("031585", DateFormat::Mmddyy).unwrap(), date);
+        assert_eq!(Date::parse("85074", DateFormat::Julian).unwrap(), date);
+        assert_eq!(date.to_string(), "1985-03-15");
+
+        // Two-digit years below the pivot are in the 2000s
+        let date = Date::parse("010249", DateFormat::Mmddyy).unwrap();
+        assert_eq!(date.year(), 2049);
+        assert_eq!(
+            Date::parse("50001", DateFormat::Julian).unwrap().year(),
+            1950
+        );
+    }
+
+    #[test]
+    fn test_real_dates_only() {
+        let bad = |text, format| Date::parse(text, format).is_err();
+        assert!(bad("19850230", DateFormat::Yyyymmdd));
+        assert!(bad("19851301", DateFormat::Yyyymmdd));
+        assert!(bad("19000229", DateFormat::Yyyymmdd));
+        assert!(!bad("20000229", DateFormat::Yyyymmdd));
+        assert!(bad("00000101", DateFormat::Yyyymmdd));
+        assert!(bad("85366", DateFormat::Julian));
+        assert!(!bad("84366", DateFormat::Julian));
+        assert!(bad("85000", DateFormat::Julian));
+        assert!(bad("1985-3-1", DateFormat::Yyyymmdd));
+        assert!(bad("0315", DateFormat::Mmddyy));
+
+        let err = Date::parse("19850230", DateFormat::Yyyymmdd).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid date: '19850230' is not a YYYYMMDD date"
+        );
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        let date = Date::new(2024, 12, 31).unwrap();
+        assert_eq!(date.ordinal(), 366);
+        assert_eq!(date.format(DateFormat::Yyyymmdd), "20241231");
+        assert_eq!(date.format(DateFormat::Mmddyy), "123124");
+        assert_eq!(date.format(DateFormat::Julian), "24366");
+        for format in [DateFormat::Yyyymmdd, DateFormat::Mmddyy, DateFormat::Julian] {
+            assert_eq!(Date::parse(&date.format(format), format).unwrap(), date);
+            assert_eq!(format.name().parse::<DateFormat>().unwrap(), format);
+        }
+        assert_eq!("mmddyy".parse::<DateFormat>().unwrap(), DateFormat::Mmddyy);
+        assert!("DDMMYY".parse::<DateFormat>().is_err());
+    }
+
+    #[test]
+    fn test_reformat_date_stage() {
+        let mut stage = ReformatDate::new(0, DateFormat::Yyyymmdd, DateFormat::Mmddyy);
+        let out = stage.process(Record::from_str("19850315X")).unwrap();
+        assert_eq!(out.field(0, 9), "031585  X");
+
+        let mut stage = ReformatDate::new(0, DateFormat::Julian, DateFormat::Yyyymmdd).at(10);
+        let out = stage.process(Record::from_str("85074")).unwrap();
+        assert_eq!(out.field(0, 18), "85074     19850315");
+
+        // Bad dates pass unchanged by default
+        let out = stage.process(Record::from_str("85400")).unwrap();
+        assert_eq!(out.as_str().trim_end(), "85400");
+
+        let mut rejected = Vec::new();
+        let mut stage =
+            ReformatDate::new(0, DateFormat::Julian, DateFormat::Yyyymmdd).rejects(&mut rejected);
+        assert!(stage.process(Record::from_str("85400")).is_none());
+        assert!(stage.process(Record::from_str("85001")).is_some());
+        drop(stage);
+        assert_eq!(rejected[0].field(80, 16).trim_end(), "BADDATE 0,5");
+    }
+}
@@ -0,0 +1,258 @@
+//! Implied-decimal numbers.
+//!
+//! COBOL amounts such as `PIC S9(6)V99` are stored as digits with the
+//! decimal point implied: `0007500019` with two decimals is 75000.19.
+//! [`Record::field_decimal`] reads such a field as a [`Decimal`], an exact
+//! count of hundredths (or whatever the scale is), so amounts add up with
+//! no floating-point error.
+//!
+//! The sign may be a separate `+` or `-` before or after the digits
+//! (`SIGN LEADING/TRAILING SEPARATE`), or overpunched on the last digit as
+//! in zoned decimal (`000750001R` is -75000.19, see [`Record::field_zoned`]).
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::Record;
+//!
+//! let record = Record::from_str("0007500019 000000050-");
+//! let salary = record.field_decimal(0, 10, 2).unwrap();
+//! let refund = record.field_decimal(11, 10, 2).unwrap();
+//! assert_eq!(salary.to_string(), "75000.19");
+//! assert_eq!(salary.checked_add(refund).unwrap().to_string(), "74999.69");
+//! ```
+
+use std::fmt;
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::record::zoned_value;
+
+/// An exact decimal number: `units` counted in 10^-`scale`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Decimal {
+    units: i64,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Creates the number `units` × 10^-`scale`, e.g. `Decimal::new(1999, 2)`
+    /// is 19.99.
+    pub fn new(units: i64, scale: u32) -> Self {
+        Self { units, scale }
+    }
+
+    /// Returns the value in units of 10^-`scale`.
+    pub fn units(&self) -> i64 {
+        self.units
+    }
+
+    /// Returns the number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Returns the same number with `scale` decimals, or `None` if that
+    /// would drop nonzero digits or overflow.
+    pub fn rescale(&self, scale: u32) -> Option<Self> {
+        let units = if scale >= self.scale {
+            self.units
+                .checked_mul(10i64.checked_pow(scale - self.scale)?)?
+        } else {
+            let divisor = 10i64.checked_pow(self.scale - scale)?;
+            if self.units % divisor != 0 {
+                return None;
+            }
+            self.units / divisor
+        };
+        Some(Self { units, scale })
+    }
+
+    /// Adds two numbers exactly, at the larger scale, or returns `None` on
+    /// overflow.
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let scale = self.scale.max(other.scale);
+        let units = self
+            .rescale(scale)?
+            .units
+            .checked_add(other.rescale(scale)?.units)?;
+        Some(Self { units, scale })
+    }
+
+    /// Returns the nearest `f64`, e.g. for averages and reports.
+    pub fn to_f64(&self) -> f64 {
+        self.units as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Writes the number with its decimal point, e.g. `-0.05`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.units < 0 { "-" } else { "" };
+        let digits = self.units.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{whole}.{fraction}")
+    }
+}
+
+/// Reads digits signed by a separate leading or trailing `+`/`-`, or by an
+/// overpunch on the last digit.
+fn signed_value(text: &str) -> Option<i64> {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (negative, rest) = if let Some(rest) = text.strip_prefix(['+', '-']) {
+        (text.starts_with('-'), rest)
+    } else if let Some(rest) = text.strip_suffix(['+', '-']) {
+        (text.ends_with('-'), rest)
+    } else {
+        return zoned_value(text);
+    };
+    if !digits(rest) {
+        return None;
+    }
+    let value: i64 = rest.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+impl Record {
+    /// Reads a field as a number with `scale` implied decimal places,
+    /// ignoring surrounding blanks.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if the field extends
+    /// past the record, and [`PipelineError::NotNumeric`] if it is blank,
+    /// holds anything but digits and a sign, or does not fit in an `i64`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// // PIC S9(6)V99 with an overpunched sign: -1234.56
+    /// let record = Record::from_str("0012345O");
+    /// let amount = record.field_decimal(0, 8, 2).unwrap();
+    /// assert_eq!(amount.units(), -123456);
+    /// assert_eq!(amount.to_string(), "-1234.56");
+    /// ```
+    pub fn field_decimal(&self, start: usize, length: usize, scale: u32) -> Result<Decimal> {
+        let text = self.try_field(start, length)?.trim();
+        let units = signed_value(text).ok_or_else(|| PipelineError::NotNumeric {
+            start,
+            length,
+            value: text.to_string(),
+        })?;
+        Ok(Decimal::new(units, scale))
+    }
+
+    /// Writes `value` into a field with `scale` implied decimal places:
+    /// zero-padded digits with a leading `-` for negatives.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if the field extends
+    /// past the record, and [`PipelineError::ValueTooWide`] if the value
+    /// has more decimals than `scale` or does not fit; the record is then
+    /// unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Decimal, Record};
+    ///
+    /// let mut record = Record::new();
+    /// record.set_field_decimal(0, 10, 2, Decimal::new(75, 1)).unwrap();
+    /// assert_eq!(record.field(0, 10), "0000000750");
+    /// ```
+    pub fn set_field_decimal(
+        &mut self,
+        start: usize,
+        length: usize,
+        scale: u32,
+        value: Decimal,
+    ) -> Result<()> {
+        let too_wide = || PipelineError::ValueTooWide {
+            value: value.to_string(),
+            length,
+        };
+        let units = value.rescale(scale).ok_or_else(too_wide)?.units;
+        self.set_field_num(start, length, units)
+            .map_err(|e| match e {
+                PipelineError::ValueTooWide { .. } => too_wide(),
+                e => e,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_decimal_signs() {
+        let read = |text: &str| {
+            Record::from_str(text)
+                .field_decimal(0, 12, 2)
+                .map(|d| d.units())
+        };
+        assert_eq!(read("0007500019").unwrap(), 7500019);
+        assert_eq!(read("-007500019").unwrap(), -7500019);
+        assert_eq!(read("+007500019").unwrap(), 7500019);
+        assert_eq!(read("007500019-").unwrap(), -7500019);
+        assert_eq!(read("  00750001R").unwrap(), -7500019);
+        assert_eq!(read("00750001I").unwrap(), 7500019);
+        assert!(read("").is_err());
+        assert!(read("75000.19").is_err());
+        assert!(read("-").is_err());
+        assert!(read("+0075-").is_err());
+        let err = read("12X4").unwrap_err();
+        assert_eq!(err.to_string(), "field 0:12 is not a number: '12X4'");
+    }
+
+    #[test]
+    fn test_decimal_arithmetic() {
+        let a = Decimal::new(7500019, 2);
+        let b = Decimal::new(-5, 3);
+        assert_eq!(a.checked_add(b).unwrap(), Decimal::new(75000185, 3));
+        assert_eq!(b.to_string(), "-0.005");
+        assert_eq!(Decimal::new(42, 0).to_string(), "42");
+        assert_eq!(a.rescale(4), Some(Decimal::new(750001900, 4)));
+        assert_eq!(a.rescale(1), None);
+        assert_eq!(Decimal::new(1500, 2).rescale(0), Some(Decimal::new(15, 0)));
+        assert!(Decimal::new(i64::MAX, 0).checked_add(a).is_none());
+        assert_eq!(Decimal::new(-150, 2).to_f64(), -1.5);
+    }
+
+    #[test]
+    fn test_set_field_decimal() {
+        let mut record = Record::new();
+        record
+            .set_field_decimal(0, 8, 2, Decimal::new(-1234, 2))
+            .unwrap();
+        assert_eq!(record.field(0, 8), "-0001234");
+        assert_eq!(
+            record.field_decimal(0, 8, 2).unwrap(),
+            Decimal::new(-1234, 2)
+        );
+
+        let err = record
+            .set_field_decimal(0, 8, 1, Decimal::new(5, 2))
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::ValueTooWide { .. }));
+        assert!(
+            record
+                .set_field_decimal(0, 4, 2, Decimal::new(100, 0))
+                .is_err()
+        );
+        assert!(
+            record
+                .set_field_decimal(78, 4, 0, Decimal::new(1, 0))
+                .is_err()
+        );
+        assert_eq!(record.field(0, 8), "-0001234");
+    }
+}
@@ -0,0 +1,835 @@
+//! DFSORT control statements.
+//!
+//! [`translate`] turns the control statements of a DFSORT step into a
+//! [`PipelinePlan`] that does the same work, so an existing sort job can be
+//! moved to a pipeline (`pipe-import-dfsort` writes the plan as DSL text).
+//! The stages run in DFSORT's order:
+//!
+//! | Statement | Stage |
+//! |-----------|-------|
+//! | `INCLUDE COND=(...)` | `FILTER` keeping the records that meet the condition |
+//! | `OMIT COND=(...)` | `FILTER` keeping the records that do not |
+//! | `INREC FIELDS=(...)` or `BUILD=(...)` | `SELECT`, or `SPECS` if there are constants |
+//! | `SORT FIELDS=(...)` | `SORT` |
+//! | `SUM FIELDS=(...)` or `FIELDS=NONE` | the `SUM` of the `SORT` stage |
+//! | `OUTREC FIELDS=(...)` or `BUILD=(...)` | `SELECT` or `SPECS` |
+//!
+//! Positions are converted from DFSORT's columns, counting from 1, to the
+//! DSL's, counting from 0. Fields may be `CH` (text) or `ZD`, `FS`, and
+//! `CSF` (numbers written as characters); `PD`, `BI`, and the other binary
+//! formats cannot occur in text records and are rejected. `SORT
+//! FIELDS=COPY`, `OPTION COPY`, and `OPTION EQUALS` (the pipeline sort is
+//! always stable) are accepted, as is `RECORD LENGTH=n`, which sets the
+//! record length; without it the record length is wide enough for every
+//! field named. `END` ends the statements.
+//!
+//! Anything else, such as `MERGE`, `OUTFIL`, `IFTHEN`, or hexadecimal
+//! constants, is an error naming it, rather than a plan that quietly does
+//! something different.
+//!
+//! Conditions compare a field with a constant: `C'text'` for `CH` fields,
+//! a signed whole number for numeric ones. As with any FILTER, text fields
+//! are compared with their blanks trimmed, and a record whose numeric
+//! field is not a number fails the comparison (where DFSORT would stop
+//! with a data exception).
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::dfsort::translate;
+//!
+//! let cards = "
+//!   INCLUDE COND=(19,10,CH,EQ,C'SALES')
+//!   SORT FIELDS=(29,8,ZD,D)
+//!   OUTREC FIELDS=(1,8,29,8)
+//! ";
+//! let plan = translate(cards).unwrap();
+//! assert_eq!(
+//!     plan.to_string(),
+//!     "PIPE CONSOLE\n| FILTER 18,10 = \"SALES\"\n| SORT 28,8 D NUM\n| SELECT 0,8,0; 28,8,8\n| CONSOLE\n?"
+//! );
+//! ```
+
+use crate::dsl::{Command, CompareOp};
+use crate::specs::{Placement, SpecInput, SpecItem};
+use crate::stage::{SortKey, SumField};
+use crate::{Condition, MAX_LRECL, PipelinePlan, RECORD_WIDTH};
+
+/// Translates DFSORT control statements into a pipeline that reads its
+/// input from CONSOLE and writes its output to CONSOLE.
+///
+/// # Errors
+///
+/// Fails, naming the statement, on statements, operands, and formats that
+/// have no equivalent stage, and on malformed or repeated statements.
+pub fn translate(cards: &str) -> Result<PipelinePlan, String> {
+    let mut translator = Translator::default();
+    for statement in statements(cards)? {
+        if statement.name == "END" {
+            break;
+        }
+        translator
+            .statement(&statement)
+            .map_err(|e| format!("{}: {e}", statement.name))?;
+    }
+    translator.plan()
+}
+
+/// One control statement, its continuation cards joined: the statement
+/// name and its operands, without comments.
+#[derive(Debug, PartialEq)]
+struct Statement {
+    name: String,
+    operands: String,
+}
+
+/// Splits control cards into statements. A `*` in column 1 marks a
+/// comment card; a statement whose operands end in a comma continues on
+/// the next card; anything after the operands is a comment.
+fn statements(cards: &str) -> Result<Vec<Statement>, String> {
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut continued = false;
+    for card in cards.lines() {
+        if card.starts_with('*') || card.trim().is_empty() {
+            continue;
+        }
+        if continued {
+            let operands = first_word(card.trim_start());
+            let last = statements
+                .last_mut()
+                .expect("a statement is being continued");
+            last.operands.push_str(operands);
+            continued = operands.ends_with(',');
+            continue;
+        }
+        let card = card.trim_start();
+        let name = first_word(card);
+        let operands = first_word(card[name.len()..].trim_start());
+        continued = operands.ends_with(',');
+        statements.push(Statement {
+            name: name.to_ascii_uppercase(),
+            operands: operands.to_string(),
+        });
+    }
+    if continued {
+        return Err("the last statement ends in a comma but is not continued".to_string());
+    }
+    Ok(statements)
+}
+
+/// Returns the text up to the first blank outside a quoted constant.
+fn first_word(text: &str) -> &str {
+    let mut quoted = false;
+    for (at, c) in text.char_indices() {
+        if c == '\'' {
+            quoted = !quoted;
+        } else if c.is_whitespace() && !quoted {
+            return &text[..at];
+        }
+    }
+    text
+}
+
+/// A token of a statement's operands.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Comma,
+    Equals,
+    Word(String),
+    /// A `C'...'` constant.
+    Text(String),
+}
+
+/// Splits operands into tokens.
+fn tokens(operands: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = operands.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '=' => tokens.push(Token::Equals),
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if "(),='".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                if chars.next_if_eq(&'\'').is_none() {
+                    tokens.push(Token::Word(word.to_ascii_uppercase()));
+                    continue;
+                }
+                // A constant: C'...', with '' for a quote
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.next_if_eq(&'\'').is_some() => text.push('\''),
+                        Some('\'') => break,
+                        Some(c) => text.push(c),
+                        None => return Err(format!("unterminated constant {word}'{text}")),
+                    }
+                }
+                match word.to_ascii_uppercase().as_str() {
+                    "C" => tokens.push(Token::Text(text)),
+                    "X" => return Err(format!("hexadecimal constant X'{text}' is not supported")),
+                    _ => return Err(format!("unsupported constant {word}'{text}'")),
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// An operand: a keyword and its value, if it has one.
+type Keyword = (String, Option<Vec<Token>>);
+
+/// Parses operands into `KEYWORD=value` pairs, each value a word or the
+/// tokens inside its parentheses. A keyword without a value (as in
+/// `OPTION EQUALS`) has none.
+fn keywords(operands: &str) -> Result<Vec<Keyword>, String> {
+    let mut tokens = tokens(operands)?.into_iter().peekable();
+    let mut keywords = Vec::new();
+    while let Some(token) = tokens.next() {
+        let Token::Word(keyword) = token else {
+            return Err(format!("expected a keyword in '{operands}'"));
+        };
+        let value = if tokens.next_if_eq(&Token::Equals).is_some() {
+            match tokens.next() {
+                Some(Token::Word(word)) => Some(vec![Token::Word(word)]),
+                Some(Token::Open) => {
+                    let mut depth = 1;
+                    let mut inner = Vec::new();
+                    for token in tokens.by_ref() {
+                        depth += match token {
+                            Token::Open => 1,
+                            Token::Close => -1,
+                            _ => 0,
+                        };
+                        if depth == 0 {
+                            break;
+                        }
+                        inner.push(token);
+                    }
+                    if depth != 0 {
+                        return Err(format!("unbalanced parentheses after {keyword}="));
+                    }
+                    Some(inner)
+                }
+                _ => return Err(format!("{keyword}= needs a value")),
+            }
+        } else {
+            None
+        };
+        match tokens.next() {
+            None | Some(Token::Comma) => {}
+            Some(_) => return Err(format!("expected a comma after {keyword}")),
+        }
+        keywords.push((keyword, value));
+    }
+    Ok(keywords)
+}
+
+/// The value of a keyword as a single word.
+fn word_value(keyword: &str, value: &Option<Vec<Token>>) -> Result<String, String> {
+    match value.as_deref() {
+        Some([Token::Word(word)]) => Ok(word.clone()),
+        _ => Err(format!("{keyword}= needs a single value")),
+    }
+}
+
+/// The items of a parenthesized list, split at commas.
+fn items(tokens: &[Token]) -> Vec<&Token> {
+    tokens.iter().filter(|t| **t != Token::Comma).collect()
+}
+
+/// How a field's bytes are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// `CH`: text.
+    Character,
+    /// `ZD`: zoned decimal.
+    Zoned,
+    /// `FS` or `CSF`: a whole number with an optional leading sign.
+    Signed,
+}
+
+impl Format {
+    fn parse(word: &str) -> Result<Self, String> {
+        match word {
+            "CH" | "AC" => Ok(Format::Character),
+            "ZD" => Ok(Format::Zoned),
+            "FS" | "CSF" => Ok(Format::Signed),
+            "PD" | "BI" | "FI" | "FL" | "PD0" | "AQ" | "ASL" | "AST" | "CLO" | "CSL" | "CST"
+            | "CTO" | "OL" | "OT" | "SS" | "TL" | "TT" => Err(format!(
+                "format {word} is not supported (records are text: use CH, ZD, FS, or CSF)"
+            )),
+            _ => Err(format!("unknown format '{word}'")),
+        }
+    }
+
+    fn is_format(word: &str) -> bool {
+        Format::parse(word).is_ok()
+    }
+}
+
+/// Builds the plan from the statements seen so far.
+#[derive(Default)]
+struct Translator {
+    filter: Option<Command>,
+    inrec: Option<Command>,
+    sort: Option<Vec<SortKey>>,
+    copy: bool,
+    sum: Option<Vec<SumField>>,
+    outrec: Option<Command>,
+    lrecl: Option<usize>,
+    /// The furthest column any field reaches.
+    extent: usize,
+    seen: Vec<String>,
+}
+
+impl Translator {
+    fn statement(&mut self, statement: &Statement) -> Result<(), String> {
+        let name = statement.name.as_str();
+        let repeats = matches!(name, "OPTION");
+        if !repeats && self.seen.iter().any(|seen| seen == name) {
+            return Err("may only appear once".to_string());
+        }
+        self.seen.push(name.to_string());
+        let keywords = keywords(&statement.operands)?;
+        match name {
+            "SORT" => self.sort(&keywords),
+            "INCLUDE" | "OMIT" => {
+                if self.filter.is_some() {
+                    return Err("only one of INCLUDE and OMIT may appear".to_string());
+                }
+                let condition = self.condition(&keywords, name == "OMIT")?;
+                self.filter = Some(Command::filter(condition));
+                Ok(())
+            }
+            "INREC" => {
+                self.inrec = Some(self.build(&keywords)?);
+                Ok(())
+            }
+            "OUTREC" => {
+                self.outrec = Some(self.build(&keywords)?);
+                Ok(())
+            }
+            "SUM" => self.sum(&keywords),
+            "OPTION" => {
+                for (keyword, _) in &keywords {
+                    match keyword.as_str() {
+                        "COPY" => self.copy = true,
+                        "EQUALS" => {}
+                        other => return Err(format!("option {other} is not supported")),
+                    }
+                }
+                Ok(())
+            }
+            "RECORD" => self.record(&keywords),
+            "MERGE" => Err("not supported (concatenate the inputs and SORT them)".to_string()),
+            "OUTFIL" => Err("not supported (use SPLIT for each output)".to_string()),
+            _ => Err("statement is not supported".to_string()),
+        }
+    }
+
+    /// Converts a DFSORT field, counting from 1, to a DSL field.
+    fn field(&mut self, pos: &Token, len: &Token) -> Result<(usize, usize), String> {
+        let (Token::Word(pos), Token::Word(len)) = (pos, len) else {
+            return Err("expected a position and length".to_string());
+        };
+        let pos: usize = pos
+            .parse()
+            .ok()
+            .filter(|&p| p >= 1)
+            .ok_or_else(|| format!("invalid position '{pos}'"))?;
+        let len: usize = len
+            .parse()
+            .ok()
+            .filter(|&l| l >= 1)
+            .ok_or_else(|| format!("invalid length '{len}'"))?;
+        self.extent = self.extent.max(pos - 1 + len);
+        Ok((pos - 1, len))
+    }
+
+    fn sort(&mut self, keywords: &[Keyword]) -> Result<(), String> {
+        let mut fields = None;
+        let mut format = None;
+        for (keyword, value) in keywords {
+            match keyword.as_str() {
+                "FIELDS" => fields = value.clone(),
+                "FORMAT" => format = Some(Format::parse(&word_value(keyword, value)?)?),
+                "EQUALS" => {}
+                other => return Err(format!("{other} is not supported")),
+            }
+        }
+        let fields = fields.ok_or("FIELDS= is required")?;
+        if fields == [Token::Word("COPY".to_string())] {
+            self.copy = true;
+            return Ok(());
+        }
+        let items = items(&fields);
+        let mut keys = Vec::new();
+        let mut at = 0;
+        while at < items.len() {
+            let (pos, len) = self.field(items[at], items.get(at + 1).ok_or("incomplete key")?)?;
+            at += 2;
+            let key_format = match items.get(at) {
+                Some(Token::Word(word)) if Format::is_format(word) => {
+                    at += 1;
+                    Format::parse(word)?
+                }
+                Some(Token::Word(word)) if !matches!(word.as_str(), "A" | "D") => {
+                    Format::parse(word)?
+                }
+                _ => format.ok_or("each key needs a format, or FORMAT= all of them")?,
+            };
+            let mut key = SortKey::new(pos, len);
+            if key_format != Format::Character {
+                key = key.numeric();
+            }
+            match items.get(at) {
+                Some(Token::Word(order)) if order == "A" => {}
+                Some(Token::Word(order)) if order == "D" => key = key.descending(),
+                _ => return Err("each key needs an order, A or D".to_string()),
+            }
+            at += 1;
+            keys.push(key);
+        }
+        self.sort = Some(keys);
+        Ok(())
+    }
+
+    fn sum(&mut self, keywords: &[Keyword]) -> Result<(), String> {
+        let mut fields = None;
+        let mut format = None;
+        for (keyword, value) in keywords {
+            match keyword.as_str() {
+                "FIELDS" => fields = value.clone(),
+                "FORMAT" => format = Some(Format::parse(&word_value(keyword, value)?)?),
+                other => return Err(format!("{other} is not supported")),
+            }
+        }
+        let fields = fields.ok_or("FIELDS= is required")?;
+        if fields == [Token::Word("NONE".to_string())] {
+            self.sum = Some(Vec::new());
+            return Ok(());
+        }
+        let items = items(&fields);
+        let mut sums = Vec::new();
+        let mut at = 0;
+        while at < items.len() {
+            let (pos, len) = self.field(items[at], items.get(at + 1).ok_or("incomplete field")?)?;
+            at += 2;
+            let field_format = match items.get(at) {
+                Some(Token::Word(word)) if !word.starts_with(|c: char| c.is_ascii_digit()) => {
+                    at += 1;
+                    Format::parse(word)?
+                }
+                _ => format.ok_or("each field needs a format, or FORMAT= all of them")?,
+            };
+            sums.push(match field_format {
+                Format::Zoned => SumField::new(pos, len).zoned(),
+                Format::Signed => SumField::new(pos, len),
+                Format::Character => return Err("CH fields cannot be summed".to_string()),
+            });
+        }
+        self.sum = Some(sums);
+        Ok(())
+    }
+
+    fn record(&mut self, keywords: &[Keyword]) -> Result<(), String> {
+        for (keyword, value) in keywords {
+            match keyword.as_str() {
+                "TYPE" => match word_value(keyword, value)?.as_str() {
+                    "F" | "V" => {}
+                    other => return Err(format!("TYPE={other} is not supported")),
+                },
+                "LENGTH" => {
+                    // LENGTH=(l1,l2,...): the first is the input length
+                    let first = match value.as_deref() {
+                        Some([Token::Word(word), ..]) => word,
+                        _ => return Err("LENGTH= needs a value".to_string()),
+                    };
+                    let lrecl = first
+                        .parse()
+                        .ok()
+                        .filter(|l| (1..=MAX_LRECL).contains(l))
+                        .ok_or_else(|| format!("invalid LENGTH '{first}'"))?;
+                    self.lrecl = Some(lrecl);
+                }
+                other => return Err(format!("{other} is not supported")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses `COND=(...)` into a condition, or, for OMIT, the condition
+    /// that holds when it does not.
+    fn condition(&mut self, keywords: &[Keyword], omit: bool) -> Result<Condition, String> {
+        let mut cond = None;
+        let mut format = None;
+        for (keyword, value) in keywords {
+            match keyword.as_str() {
+                "COND" => cond = value.clone(),
+                "FORMAT" => format = Some(Format::parse(&word_value(keyword, value)?)?),
+                other => return Err(format!("{other} is not supported")),
+            }
+        }
+        let cond = cond.ok_or("COND= is required")?;
+        let mut parser = CondParser {
+            items: items(&cond),
+            at: 0,
+            format,
+            omit,
+            translator: self,
+        };
+        let condition = parser.or()?;
+        if parser.at < parser.items.len() {
+            return Err(format!("unexpected {:?} in COND", parser.items[parser.at]));
+        }
+        Ok(condition)
+    }
+
+    /// Parses `FIELDS=(...)` or `BUILD=(...)` of INREC or OUTREC into a
+    /// SELECT, or a SPECS if it has constants.
+    fn build(&mut self, keywords: &[Keyword]) -> Result<Command, String> {
+        let fields = match keywords {
+            [(keyword, Some(fields))] if keyword == "FIELDS" || keyword == "BUILD" => fields,
+            [(keyword, _), ..] if keyword == "FIELDS" || keyword == "BUILD" => {
+                return Err(format!("{keyword}=(...) must be the only operand"));
+            }
+            [(keyword, _), ..] => return Err(format!("{keyword} is not supported")),
+            [] => return Err("FIELDS= or BUILD= is required".to_string()),
+        };
+        let items = items(fields);
+        let mut specs = Vec::new();
+        let mut column = 0;
+        let mut at = 0;
+        while at < items.len() {
+            match items[at] {
+                Token::Text(text) => {
+                    specs.push(SpecItem::literal(text).at(column));
+                    column += text.len();
+                    at += 1;
+                }
+                Token::Word(word) if word.ends_with(':') => {
+                    column = word[..word.len() - 1]
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&c| c >= 1)
+                        .ok_or_else(|| format!("invalid column '{word}'"))?
+                        - 1;
+                    at += 1;
+                }
+                Token::Word(word) if word.ends_with('X') || word.ends_with('Z') => {
+                    let blanks = match &word[..word.len() - 1] {
+                        "" => 1,
+                        n => n.parse().map_err(|_| format!("invalid item '{word}'"))?,
+                    };
+                    if word.ends_with('Z') {
+                        return Err(format!("binary zeros '{word}' are not supported"));
+                    }
+                    column += blanks;
+                    at += 1;
+                }
+                pos => {
+                    let (pos, len) =
+                        self.field(pos, items.get(at + 1).ok_or("incomplete field")?)?;
+                    if let Some(Token::Word(word)) = items.get(at + 2)
+                        && word.starts_with(|c: char| c.is_ascii_alphabetic())
+                        && !word.ends_with('X')
+                    {
+                        return Err(format!("edit or conversion '{word}' is not supported"));
+                    }
+                    specs.push(SpecItem::range(pos, len).at(column));
+                    column += len;
+                    at += 2;
+                }
+            }
+        }
+        if column > MAX_LRECL {
+            return Err(format!("the records built are {column} bytes long"));
+        }
+        self.extent = self.extent.max(column);
+        let fields: Option<Vec<(usize, usize, usize)>> = specs
+            .iter()
+            .map(|item| match (&item.input, item.placement) {
+                (SpecInput::Range(pos, len), Placement::Column(dest)) => Some((*pos, *len, dest)),
+                _ => None,
+            })
+            .collect();
+        Ok(match fields {
+            Some(fields) => Command::Select { fields },
+            None => Command::Specs { items: specs },
+        })
+    }
+
+    fn plan(self) -> Result<PipelinePlan, String> {
+        if self.sum.is_some() && (self.copy || self.sort.is_none()) {
+            return Err("SUM: needs SORT FIELDS=(...) to bring equal keys together".to_string());
+        }
+        if self.copy && self.sort.is_some() {
+            return Err("OPTION COPY and SORT FIELDS=(...) conflict".to_string());
+        }
+        let mut plan = PipelinePlan::new(Command::Console);
+        for command in [self.filter, self.inrec].into_iter().flatten() {
+            plan = plan.stage(command);
+        }
+        if let Some(keys) = self.sort {
+            plan = plan.stage(Command::Sort {
+                keys,
+                sum: self.sum,
+            });
+        }
+        if let Some(command) = self.outrec {
+            plan = plan.stage(command);
+        }
+        let lrecl = match self.lrecl {
+            Some(lrecl) if lrecl < self.extent => {
+                return Err(format!(
+                    "RECORD: LENGTH={lrecl} is shorter than the fields named, which reach column {}",
+                    self.extent
+                ));
+            }
+            Some(lrecl) => lrecl,
+            None => self.extent.max(RECORD_WIDTH),
+        };
+        Ok(plan.stage(Command::Console).with_lrecl(lrecl))
+    }
+}
+
+/// Recursive-descent parser for `COND=(...)`, where AND binds tighter
+/// than OR. For OMIT it builds the opposite condition: each comparison
+/// reversed and AND and OR swapped.
+struct CondParser<'a, 't> {
+    items: Vec<&'t Token>,
+    at: usize,
+    format: Option<Format>,
+    omit: bool,
+    translator: &'a mut Translator,
+}
+
+impl CondParser<'_, '_> {
+    /// Consumes the connector `word` or `symbol` if it is next.
+    fn connector(&mut self, word: &str, symbol: &str) -> bool {
+        let found =
+            matches!(self.items.get(self.at), Some(Token::Word(w)) if w == word || w == symbol);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    /// Joins two conditions with AND (`and`) or OR, swapped for OMIT.
+    fn join(&self, left: Condition, right: Condition, and: bool) -> Condition {
+        if and != self.omit {
+            left.and(right)
+        } else {
+            left.or(right)
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.connector("OR", "|") {
+            let right = self.and()?;
+            condition = self.join(condition, right, false);
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.primary()?;
+        while self.connector("AND", "&") {
+            let right = self.primary()?;
+            condition = self.join(condition, right, true);
+        }
+        Ok(condition)
+    }
+
+    /// primary := ( condition ) | pos,len[,format],op,constant
+    fn primary(&mut self) -> Result<Condition, String> {
+        if self.items.get(self.at) == Some(&&Token::Open) {
+            self.at += 1;
+            let condition = self.or()?;
+            if self.items.get(self.at) != Some(&&Token::Close) {
+                return Err("unbalanced parentheses in COND".to_string());
+            }
+            self.at += 1;
+            return Ok(condition);
+        }
+        let item = |at: usize| self.items.get(at).copied().ok_or("incomplete comparison");
+        let (pos, len) = (item(self.at)?, item(self.at + 1)?);
+        let (pos, len) = self.translator.field(pos, len)?;
+        self.at += 2;
+        let format = match item(self.at)? {
+            Token::Word(word) if operator(word).is_none() => {
+                self.at += 1;
+                Format::parse(word)?
+            }
+            _ => self
+                .format
+                .ok_or("each comparison needs a format, or FORMAT= all of them")?,
+        };
+        let op = match item(self.at)? {
+            Token::Word(word) => operator(word),
+            _ => None,
+        }
+        .ok_or("expected EQ, NE, GT, GE, LT, or LE")?;
+        let op = if self.omit { opposite(op) } else { op };
+        let constant = item(self.at + 1)?;
+        self.at += 2;
+        match (format, constant) {
+            (Format::Character, Token::Text(text)) => Ok(Condition::text(pos, len, op, text)),
+            (Format::Character, _) => Err("CH fields are compared with C'...'".to_string()),
+            (format, Token::Word(number)) => {
+                let value: i64 = number
+                    .parse()
+                    .map_err(|_| format!("'{number}' is not a number"))?;
+                Ok(match format {
+                    Format::Zoned => Condition::zoned(pos, len, op, value),
+                    _ => Condition::number(pos, len, op, value),
+                })
+            }
+            (_, Token::Text(_)) => Err("numeric fields are compared with numbers".to_string()),
+            _ => Err("expected a constant".to_string()),
+        }
+    }
+}
+
+/// Parses a DFSORT comparison operator (`EQ`, `NE`, `GT`, `GE`, `LT`,
+/// `LE`).
+fn operator(word: &str) -> Option<CompareOp> {
+    match word {
+        "EQ" => Some(CompareOp::Eq),
+        "NE" => Some(CompareOp::Ne),
+        "GT" => Some(CompareOp::Gt),
+        "GE" => Some(CompareOp::Ge),
+        "LT" => Some(CompareOp::Lt),
+        "LE" => Some(CompareOp::Le),
+        _ => None,
+    }
+}
+
+/// The operator that holds exactly when `op` does not.
+fn opposite(op: CompareOp) -> CompareOp {
+    match op {
+        CompareOp::Eq => CompareOp::Ne,
+        CompareOp::Ne => CompareOp::Eq,
+        CompareOp::Lt => CompareOp::Ge,
+        CompareOp::Le => CompareOp::Gt,
+        CompareOp::Gt => CompareOp::Le,
+        CompareOp::Ge => CompareOp::Lt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HostVars, execute_plan};
+
+    const SALES: &str = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000
+BROWN   BOB       MARKETING 00055000";
+
+    fn pipe(cards: &str) -> String {
+        translate(cards).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_statements() {
+        let cards = "* Sales by department
+  SORT FIELDS=(19,10,CH,A,
+               29,8,ZD,D)    department, then salary
+  OUTREC BUILD=(C'IT''S ',1,8)
+  END
+  MERGE FIELDS=COPY";
+        let statements = statements(cards).unwrap();
+        assert_eq!(statements.len(), 4);
+        assert_eq!(statements[0].operands, "FIELDS=(19,10,CH,A,29,8,ZD,D)");
+        assert_eq!(statements[1].operands, "BUILD=(C'IT''S ',1,8)");
+        assert_eq!(
+            pipe(cards),
+            "PIPE CONSOLE\n| SORT 18,10; 28,8 D NUM\n| SPECS /IT'S / 0 0,8 5\n| CONSOLE\n?"
+        );
+    }
+
+    #[test]
+    fn test_translate_and_run() {
+        let cards = "
+  INCLUDE COND=(19,10,CH,EQ,C'SALES',OR,19,10,CH,EQ,C'MARKETING')
+  SORT FIELDS=(29,8,A),FORMAT=ZD
+  OUTREC FIELDS=(1,8,2X,29,8)";
+        let plan = translate(cards).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "PIPE CONSOLE\n| FILTER 18,10 = \"SALES\" OR 18,10 = \"MARKETING\"\n| SORT 28,8 NUM\n| SELECT 0,8,0; 28,8,10\n| CONSOLE\n?"
+        );
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+        let run = execute_plan(&plan, SALES, &None, &HostVars::new()).unwrap();
+        assert_eq!(
+            run.output,
+            "SMITH     00050000\nBROWN     00055000\nDOE       00060000"
+        );
+
+        let cards = "
+ OMIT COND=((29,8,ZD,GT,55000,AND,19,10,CH,EQ,C'SALES'),OR,1,5,CH,EQ,C'JONES')
+ SORT FIELDS=(19,10,CH,A)
+ SUM FIELDS=(29,8,ZD)";
+        let plan = translate(cards).unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "PIPE CONSOLE\n| FILTER (28,8 ZD <= 55000 OR 18,10 != \"SALES\") AND 0,5 != \"JONES\"\n| SORT 18,10 SUM 28,8 ZD\n| CONSOLE\n?"
+        );
+        let run = execute_plan(&plan, SALES, &None, &HostVars::new()).unwrap();
+        assert_eq!(
+            run.output.lines().map(|l| &l[18..36]).collect::<Vec<_>>(),
+            ["MARKETING 00055000", "SALES     00050000"]
+        );
+
+        assert_eq!(
+            pipe("SORT FIELDS=(1,5,CH,A)\nSUM FIELDS=NONE\nRECORD TYPE=F,LENGTH=120"),
+            "LRECL 120\nPIPE CONSOLE\n| SORT 0,5 SUM NONE\n| CONSOLE\n?"
+        );
+        assert_eq!(
+            pipe("OPTION COPY\nINREC FIELDS=(81,10)"),
+            "LRECL 90\nPIPE CONSOLE\n| SELECT 80,10,0\n| CONSOLE\n?"
+        );
+    }
+
+    #[test]
+    fn test_translate_errors() {
+        let err = |cards: &str| translate(cards).unwrap_err();
+        assert_eq!(
+            err("MERGE FIELDS=(1,5,CH,A)"),
+            "MERGE: not supported (concatenate the inputs and SORT them)"
+        );
+        assert!(err("SORT FIELDS=(1,4,PD,A)").contains("format PD is not supported"));
+        assert!(err("SORT FIELDS=(1,4,CH)").contains("order"));
+        assert!(err("SORT FIELDS=(0,4,CH,A)").contains("invalid position"));
+        assert_eq!(
+            err("SORT FIELDS=(1,4,CH,A)\nSORT FIELDS=COPY"),
+            "SORT: may only appear once"
+        );
+        assert!(err("INCLUDE COND=(1,4,CH,EQ,X'C1')").contains("hexadecimal"));
+        assert!(err("INCLUDE COND=(1,4,ZD,EQ,C'A')").contains("numbers"));
+        assert!(
+            err("INCLUDE COND=(1,4,CH,EQ,C'A')\nOMIT COND=(1,1,CH,EQ,C'B')").contains("one of")
+        );
+        assert!(err("SUM FIELDS=NONE").contains("needs SORT"));
+        assert!(err("OUTREC FIELDS=(1,8,ZD,EDIT=(TTT))").contains("not supported"));
+        assert!(err("SORT FIELDS=(1,4,CH,A),").contains("not continued"));
+        assert!(err("RECORD LENGTH=10\nSORT FIELDS=(11,4,CH,A)").contains("shorter"));
+        assert_eq!(
+            err("JOINKEYS FILE=F1"),
+            "JOINKEYS: statement is not supported"
+        );
+    }
+}
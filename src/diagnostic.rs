@@ -0,0 +1,418 @@
+//! Pipeline diagnostics.
+//!
+//! [`check`] parses and validates a pipeline without running it. When the
+//! pipeline is wrong, the [`Diagnostic`] says where: the line and the
+//! columns of the stage, or of the word in it that is at fault. It also
+//! gives the syntax the stage expects and, for a misspelled stage or field
+//! name, the name that was probably meant:
+//!
+//! ```text
+//! error: Unknown command: SELCT
+//!  --> filter.pipe:2:3
+//!   |
+//! 2 | | SELCT 0,8,0
+//!   |   ^^^^^
+//!   = expected: SELECT src,len,dest; ...
+//!   = help: did you mean SELECT?
+//! ```
+//!
+//! [`diagnose`] goes on past a bad stage to report every one, and adds the
+//! warnings of [`explain`](crate::explain) for a pipeline that parses.
+//! `pipe-lint` prints them, and the Web UI lists them under the pipeline
+//! as it is typed.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::diagnostic::check;
+//!
+//! let err = check("PIPE CONSOLE\n| SELCT 0,8,0\n| CONSOLE").unwrap_err();
+//! let span = err.span.unwrap();
+//! assert_eq!((span.line, span.column, span.len), (2, 3, 5));
+//! assert_eq!(err.suggestion.as_deref(), Some("SELECT"));
+//! assert_eq!(err.to_string(), "Line 2: Unknown command: SELCT");
+//! ```
+
+use std::fmt;
+
+use crate::dsl::{STAGE_SYNTAX, parse_pipeline_spanned};
+use crate::explain::explain;
+use crate::plan::PipelinePlan;
+
+/// Most errors [`diagnose`] reports.
+const MAX_ERRORS: usize = 20;
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The pipeline cannot run.
+    Error,
+    /// The pipeline runs, but probably not as intended.
+    Warning,
+}
+
+/// Where a diagnostic points: a line and columns, counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Line number.
+    pub line: usize,
+    /// First column, in characters.
+    pub column: usize,
+    /// Number of characters.
+    pub len: usize,
+}
+
+impl Span {
+    /// Returns the span of `part`, a slice of `line`, on line `line_num`.
+    pub(crate) fn within(line_num: usize, line: &str, part: &str) -> Self {
+        let offset = (part.as_ptr() as usize).saturating_sub(line.as_ptr() as usize);
+        Self {
+            line: line_num,
+            column: line.get(..offset).map_or(0, |s| s.chars().count()) + 1,
+            len: part.chars().count(),
+        }
+    }
+}
+
+/// A problem found in a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Error or warning.
+    pub severity: Severity,
+    /// What is wrong.
+    pub message: String,
+    /// Where, if the problem is in one stage.
+    pub span: Option<Span>,
+    /// The syntax of the stage at fault.
+    pub expected: Option<String>,
+    /// The stage or field name probably meant.
+    pub suggestion: Option<String>,
+}
+
+impl Diagnostic {
+    /// Creates an error with no location.
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span: None,
+            expected: None,
+            suggestion: None,
+        }
+    }
+
+    /// Creates a warning with no location.
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    /// Locates the diagnostic at `span`.
+    pub fn at(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    /// Renders the diagnostic with the line it points at, marking the
+    /// columns, for the pipeline `text` read from `name`.
+    pub fn render(&self, name: &str, text: &str) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let mut out = format!("{severity}: {}", self.message);
+        let mut gutter = String::new();
+        match self.span {
+            Some(span) => {
+                let number = span.line.to_string();
+                gutter = " ".repeat(number.len());
+                let line = text.lines().nth(span.line - 1).unwrap_or_default();
+                out.push_str(&format!(
+                    "\n{gutter}--> {name}:{}:{}\n{gutter} |\n{number} | {line}\n{gutter} | {}{}",
+                    span.line,
+                    span.column,
+                    " ".repeat(span.column - 1),
+                    "^".repeat(span.len.max(1))
+                ));
+            }
+            None => out.push_str(&format!("\n --> {name}")),
+        }
+        if let Some(expected) = &self.expected {
+            out.push_str(&format!("\n{gutter} = expected: {expected}"));
+        }
+        if let Some(suggestion) = &self.suggestion {
+            out.push_str(&format!("\n{gutter} = help: did you mean {suggestion}?"));
+        }
+        out
+    }
+}
+
+/// Prints the diagnostic as the parser's errors read: `Line N: message`.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "Line {}: {}", span.line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Parses and validates a pipeline, as [`PipelinePlan::parse`] does,
+/// locating any error.
+pub fn check(text: &str) -> Result<PipelinePlan, Diagnostic> {
+    let (plan, _) = parse_pipeline_spanned(text).map_err(|d| refine(d, text))?;
+    plan.validate().map_err(Diagnostic::error)?;
+    Ok(plan)
+}
+
+/// Reports every bad stage of a pipeline, or if there are none, the
+/// pipeline's error or the warnings about it.
+///
+/// After each bad stage, the rest of the pipeline is checked as though
+/// that stage were not there. Errors that concern the whole pipeline are
+/// only reported when no stage is bad, since they often follow from one.
+pub fn diagnose(text: &str) -> Vec<Diagnostic> {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let mut diagnostics = Vec::new();
+    while diagnostics.len() < MAX_ERRORS {
+        match check(&lines.join("\n")) {
+            Ok(plan) => {
+                if diagnostics.is_empty() {
+                    let warnings = explain(&plan).warnings;
+                    diagnostics.extend(warnings.into_iter().map(Diagnostic::warning));
+                }
+                break;
+            }
+            Err(diagnostic) => {
+                let Some(span) = diagnostic.span else {
+                    if diagnostics.is_empty() {
+                        diagnostics.push(diagnostic);
+                    }
+                    break;
+                };
+                // Blank out the stage, which the parser then skips
+                let stage = stage_span(&diagnostic, &lines);
+                let line = &mut lines[span.line - 1];
+                let blanked: String = line
+                    .chars()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        if (stage.0..stage.1).contains(&i) {
+                            ' '
+                        } else {
+                            c
+                        }
+                    })
+                    .collect();
+                if blanked == *line {
+                    diagnostics.push(diagnostic);
+                    break;
+                }
+                *line = blanked;
+                diagnostics.push(diagnostic);
+            }
+        }
+    }
+    diagnostics
+}
+
+/// The character range of the whole stage a diagnostic is in, on its
+/// line: from its span to the next `|`, or the end of the line.
+fn stage_span(diagnostic: &Diagnostic, lines: &[String]) -> (usize, usize) {
+    let span = diagnostic.span.expect("stage diagnostics have a span");
+    let line: Vec<char> = lines[span.line - 1].chars().collect();
+    let start = line[..span.column - 1]
+        .iter()
+        .rposition(|&c| c == '|')
+        .map_or(0, |i| i + 1);
+    let end = line[span.column - 1..]
+        .iter()
+        .position(|&c| c == '|')
+        .map_or(line.len(), |i| span.column - 1 + i);
+    (start, end)
+}
+
+/// Adds what the parser cannot say to a stage error: the word at fault,
+/// the stage's syntax, and the name probably meant.
+fn refine(mut diagnostic: Diagnostic, text: &str) -> Diagnostic {
+    let Some(span) = diagnostic.span else {
+        return diagnostic;
+    };
+    let line = text.lines().nth(span.line - 1).unwrap_or_default();
+    let stage: String = line.chars().skip(span.column - 1).take(span.len).collect();
+
+    // The stage name, after any label
+    let mut words = word_spans(&stage);
+    if words
+        .first()
+        .is_some_and(|(_, word)| word.len() > 1 && word.ends_with(':'))
+    {
+        words.remove(0);
+    }
+    let Some(&(name_at, name)) = words.first() else {
+        return diagnostic;
+    };
+    let narrow = |at: usize, word: &str| Span {
+        line: span.line,
+        column: span.column + stage[..at].chars().count(),
+        len: word.chars().count(),
+    };
+
+    let message = diagnostic.message.clone();
+    if message.starts_with("Unknown command:") {
+        diagnostic.span = Some(narrow(name_at, name));
+        diagnostic.suggestion = closest(name, STAGE_SYNTAX.iter().map(|(n, _)| *n));
+        diagnostic.expected = diagnostic.suggestion.as_deref().and_then(syntax);
+        return diagnostic;
+    }
+    diagnostic.expected = syntax(name);
+    if let Some(field) = quoted(&message, "Unknown field '") {
+        // "Unknown field 'X' (LAYOUT defines A, B)"
+        let names = message
+            .split_once("LAYOUT defines ")
+            .map_or("", |(_, names)| names.trim_end_matches(')'));
+        diagnostic.suggestion = closest(field, names.split(", "));
+    }
+    if let Some(word) = quoted(&message, "'")
+        && let Some(&(at, found)) = words[1..].iter().find(|(_, w)| w.contains(word))
+    {
+        let at = at + found.find(word).unwrap_or(0);
+        diagnostic.span = Some(narrow(at, word));
+    }
+    diagnostic
+}
+
+/// Returns the syntax of stage `name` (any case).
+fn syntax(name: &str) -> Option<String> {
+    let upper = name.to_ascii_uppercase();
+    let symbol = if upper.starts_with(">>") {
+        ">>"
+    } else if upper.starts_with(['<', '>']) {
+        &upper[..1]
+    } else {
+        &upper
+    };
+    STAGE_SYNTAX
+        .iter()
+        .find(|(n, _)| *n == symbol)
+        .map(|(_, syntax)| syntax.to_string())
+}
+
+/// Returns the text quoted with `'` after `prefix` in `message`.
+fn quoted<'a>(message: &'a str, prefix: &str) -> Option<&'a str> {
+    let (_, rest) = message.split_once(prefix)?;
+    let (word, _) = rest.split_once('\'')?;
+    (!word.is_empty()).then_some(word)
+}
+
+/// Splits text into words with their byte offsets.
+fn word_spans(text: &str) -> Vec<(usize, &str)> {
+    text.split_whitespace()
+        .map(|word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+        .collect()
+}
+
+/// Returns the candidate closest to `word` (ignoring case), if it is
+/// within a third of its length of it.
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let word = word.to_ascii_uppercase();
+    let limit = (word.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (distance(&word, &candidate.to_ascii_uppercase()), candidate))
+        .filter(|&(d, _)| d > 0 && d <= limit)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Edit distance: the fewest characters inserted, deleted, or replaced to
+/// turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb))
+                .min(above + 1)
+                .min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn located(err: &Diagnostic) -> Option<(usize, usize, usize)> {
+        err.span.map(|s| (s.line, s.column, s.len))
+    }
+
+    #[test]
+    fn test_check() {
+        let err = check("PIPE CONSOLE | FILTER 18,10 ** \"SALES\" | CONSOLE").unwrap_err();
+        assert_eq!(located(&err), Some((1, 16, 23)));
+        assert!(err.expected.unwrap().starts_with("FILTER pos,len"));
+
+        let err =
+            check("LAYOUT DEPT 18,10\nPIPE CONSOLE\n| a: UNIQUE DEPTT\n| CONSOLE").unwrap_err();
+        assert_eq!(located(&err), Some((3, 13, 5)));
+        assert_eq!(err.suggestion.as_deref(), Some("DEPT"));
+
+        let err = check("PIPE CONSOLE\n| TAKE 3\n| TAKE x").unwrap_err();
+        assert_eq!(located(&err), Some((3, 3, 6)));
+        assert_eq!(err.expected.as_deref(), Some("TAKE n | TAKE LAST n"));
+
+        assert_eq!(check("PIPE UPPER | CONSOLE").unwrap_err().span, None);
+        assert!(check("PIPE CONSOLE | CONSOLE").is_ok());
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let text = "PIPE CONSOLE\n| SELCT 0,8,0 | TAKE x\n| LOCAT /X/\n| CONSOLE";
+        let diagnostics = diagnose(text);
+        let found: Vec<(usize, usize, Option<&str>)> = diagnostics
+            .iter()
+            .map(|d| {
+                let span = d.span.unwrap();
+                (span.line, span.column, d.suggestion.as_deref())
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, 3, Some("SELECT")),
+                (2, 17, None),
+                (3, 3, Some("LOCATE"))
+            ]
+        );
+
+        let warnings = diagnose("PIPE CONSOLE | SELECT 75,10,0 | CONSOLE");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert!(diagnose("PIPE CONSOLE | CONSOLE").is_empty());
+        assert_eq!(diagnose("").len(), 1);
+    }
+
+    #[test]
+    fn test_render() {
+        let text = "PIPE CONSOLE\n| SELCT 0,8,0\n| CONSOLE";
+        assert_eq!(
+            check(text).unwrap_err().render("a.pipe", text),
+            "error: Unknown command: SELCT
+ --> a.pipe:2:3
+  |
+2 | | SELCT 0,8,0
+  |   ^^^^^
+  = expected: SELECT src,len,dest; ...
+  = help: did you mean SELECT?"
+        );
+        assert_eq!(distance("SELCT", "SELECT"), 1);
+        assert_eq!(closest("XYZZY", ["SELECT"].into_iter()), None);
+    }
+}
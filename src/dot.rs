@@ -0,0 +1,114 @@
+//! Graphviz DOT export for parsed pipelines.
+//!
+//! Renders a pipeline as a left-to-right graph of stages. When per-stage
+//! debug info from a run is supplied, each node is annotated with its
+//! input/output record counts and each edge with the number of records
+//! that flowed across it.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{execute_pipeline_debug, parse_commands};
+//! use pipelines_rs::dot::pipeline_to_dot;
+//!
+//! let text = "PIPE CONSOLE | LOCATE /SALES/ | CONSOLE";
+//! let commands = parse_commands(text).unwrap();
+//! let (_, _, _, info) = execute_pipeline_debug("SALES\nENG", text, &None).unwrap();
+//!
+//! let dot = pipeline_to_dot(&commands, Some(&info));
+//! assert!(dot.starts_with("digraph pipeline {"));
+//! assert!(dot.contains("s1 -> s2 [label=\"1\"]"));
+//! ```
+
+use std::fmt::Write;
+
+use crate::{Command, DebugInfo};
+
+/// Render a pipeline as a DOT graph.
+///
+/// `debug_info`, if given, must come from running the same pipeline (one
+/// entry per stage, as returned by `execute_pipeline_debug`).
+pub fn pipeline_to_dot(commands: &[Command], debug_info: Option<&[DebugInfo]>) -> String {
+    let mut out = String::new();
+    out.push_str("digraph pipeline {\n");
+    out.push_str("    rankdir=LR;\n");
+    out.push_str("    node [shape=box, fontname=\"Courier\"];\n");
+
+    for (idx, cmd) in commands.iter().enumerate() {
+        let mut label = cmd.to_string();
+        if let Some(info) = debug_info.and_then(|d| d.get(idx)) {
+            let _ = write!(
+                label,
+                "\n{} in / {} out",
+                info.input_count, info.output_count
+            );
+        }
+        let shape = if idx == 0 { ", shape=invhouse" } else { "" };
+        let _ = writeln!(out, "    s{idx} [label=\"{}\"{shape}];", escape(&label));
+    }
+
+    for idx in 1..commands.len() {
+        let from = idx - 1;
+        match debug_info.and_then(|d| d.get(from)) {
+            Some(info) => {
+                let _ = writeln!(
+                    out,
+                    "    s{from} -> s{idx} [label=\"{}\"];",
+                    info.output_count
+                );
+            }
+            None => {
+                let _ = writeln!(out, "    s{from} -> s{idx};");
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+/// Escape a string for use inside a double-quoted DOT label.
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{execute_pipeline_debug, parse_commands};
+
+    const PIPELINE: &str = r#"PIPE CONSOLE
+| FILTER 18,10 = "SALES"
+| COUNT
+| CONSOLE
+?"#;
+
+    #[test]
+    fn test_dot_without_counts() {
+        let commands = parse_commands(PIPELINE).unwrap();
+        let dot = pipeline_to_dot(&commands, None);
+        assert!(dot.contains(r#"s1 [label="FILTER 18,10 = \"SALES\""];"#));
+        assert!(dot.contains("s0 -> s1;"));
+        assert!(dot.contains("s2 -> s3;"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_dot_with_counts() {
+        let input = "SMITH   JOHN      SALES     \nJONES   MARY      ENGINEER  ";
+        let commands = parse_commands(PIPELINE).unwrap();
+        let (_, _, _, info) = execute_pipeline_debug(input, PIPELINE, &None).unwrap();
+        let dot = pipeline_to_dot(&commands, Some(&info));
+        assert!(dot.contains(r#"\n2 in / 1 out"#));
+        assert!(dot.contains(r#"s0 -> s1 [label="2"];"#));
+        assert!(dot.contains(r#"s2 -> s3 [label="1"];"#));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -15,31 +15,122 @@
 //! - `?` on its own line marks end of pipeline
 //!
 //! Stage position rules:
-//! - First stage must be a source: CONSOLE, LITERAL, or HOLE
+//! - First stage must be a source: CONSOLE, LITERAL, HOLE, or `<`
 //! - Any stage can be in the middle (CONSOLE passes through while printing)
 //! - Any stage can be last (output discarded if not a sink like CONSOLE)
 //!
 //! Supported stages:
-//! - `CONSOLE` - Read from input (first), pass through (middle), or write to output (last)
+//! - `CONSOLE` - Read from input (first), echo to stderr and pass through (middle), or write to output (last)
+//! - `DUMP [HEX|RULER]` - Write each record to stderr as a hex dump or under a column ruler, passing it on
 //! - `FILTER pos,len = "value"` - Keep records where field equals value
 //! - `FILTER pos,len != "value"` - Omit records where field equals value
+//! - `FILTER pos,len ZD op n` - Compare a zoned decimal field (`=`, `!=`, `<`, `<=`, `>`, `>=`)
+//! - `FILTER pos,len NUM op n` - Compare a numeric field
+//! - `FILTER pos,len ~ /pattern/` - Match a regular expression (`regex` feature)
+//! - `FILTER cond AND cond`, `cond OR cond`, `(cond)` - Combine comparisons (`<`, `>` work on text too)
 //! - `HOLE` - Discard all input, output nothing (like /dev/null)
+//! - `FANIN` - Pass the input, then each stream fed to the stage's label
+//! - `FANINANY` - Pass the input and the streams fed to it, a record from each in turn
+//! - `< path` - Read records from a file (first stage only)
+//! - `FROM CSV [HEADER] [fields]` - Read CSV input into layout fields (first stage only)
+//! - `TO CSV [HEADER] [fields]` - Write layout fields as CSV lines
+//! - `> path` / `>> path` - Write / append records to a file, passing them on
 //! - `SELECT p1,l1,d1; p2,l2,d2; ...` - Select and reposition fields
+//! - `OVERLAY p1,l1,d1; p2,l2,d2; ...` - Copy fields into other columns, keeping the rest of the record
 //! - `TAKE n` - Keep first n records
 //! - `SKIP n` - Skip first n records
+//! - `TAKE LAST n` - Keep the last n records
+//! - `DROP LAST n` - Drop the last n records (e.g. a trailer)
+//! - `RECNO n-m [BY s], ...` - Keep records by input position, counting from 1
+//! - `SAMPLE fraction [SEED n]` - Keep a reproducible random fraction of the records
 //! - `LOCATE "pattern"` - Keep records containing pattern (grep-like)
 //! - `LOCATE pos,len "pattern"` - Keep records where field contains pattern
 //! - `NLOCATE "pattern"` - Keep records NOT containing pattern
+//! - `BETWEEN [pos,len] /start/ /end/ [EXCLUSIVE]` - Keep sections from a start marker to an end marker
 //! - `COUNT` - Count records and emit count as a single record
-//! - `CHANGE "old" "new"` - Replace occurrences of old with new (sed-like)
-//! - `LITERAL "text"` - Append a literal record to the stream
+//! - `SUM|MIN|MAX|AVG pos,len` - Emit the total, smallest, largest, or average of a numeric field
+//! - `CHANGE [pos,len] /old/new/ [n]` - Replace occurrences of old with new (sed-like)
+//! - `LITERAL text` - Write a literal record ahead of the input records
 //! - `UPPER` - Convert records to uppercase
 //! - `LOWER` - Convert records to lowercase
 //! - `REVERSE` - Reverse characters in each record
-//! - `DUPLICATE n` - Repeat each record n times
+//! - `PAD n [c]` - Fill each record with c (default blank) out to n columns
+//! - `TRUNC n` - Keep the first n columns, blanking the rest
+//! - `SQUISH` - Squeeze runs of blanks to a single blank
+//! - `XLATE [pos,len] [UPPER|LOWER|FILE path] [from to ...]` - Translate bytes through a table
+//! - `REFORMAT DATE pos fmt TO fmt [dest]` - Rewrite a YYYYMMDD, MMDDYY, or YYDDD date, dropping invalid dates
+//! - `DUPLICATE [n]` - Write each record and n more copies (default 1)
+//! - `UNIQUE [pos,len]` - Keep the first record with each key (default: whole record)
+//! - `SORT [pos,len [A|D] [CH|NUM]; ...] [SUM pos,len [ZD]; ... | SUM NONE]` - Order records by key fields, optionally totalling or dropping records with equal keys
+//! - `DELAY n ms` - Pause n milliseconds before passing each record
+//! - `PROFILE [pos,len [type]; ...]` - Emit per-field data-quality statistics
+//! - `STAMP pos,len VAR; ...` - Write host variables (DATE, ENV.x, ...) into each record
+//! - `STAMP HEADER pos,len VAR; ...` - Emit a header record of host variables first
+//! - `PLUGIN name [args]` - Run a stage registered in [`crate::plugin`]
+//! - `ENCRYPT pos,len [TO pos,len] [HEX|BASE64] [key]` - Seal a field with AES-GCM
+//! - `DECRYPT pos,len [TO pos,len] [HEX|BASE64] [key]` - Open a sealed field
+//! - `CONVERT ASCII [CP037|CP1047]` - Read EBCDIC input (directly after `PIPE CONSOLE`)
+//! - `CONVERT EBCDIC [CP037|CP1047]` - Write EBCDIC output (directly before the last `CONSOLE`)
+//! - `SPECS input [conversion] output ...` - Build records from fields, literals, and RECNO
+//! - `SPLIT name [IF condition] [BUILD p1,l1,d1; ...]` - Copy records to a named output
 //! - Lines starting with `#` are comments
-
-use crate::{Pipeline, Record};
+//!
+//! A stage may be labeled (`a: LOCATE /X/`). After `?`, a segment starting
+//! with the label alone (`? a:`) reads the records that stage drops, its
+//! secondary output; FILTER, LOCATE, NLOCATE, BETWEEN, TAKE, SKIP, DROP,
+//! UNIQUE, RECNO, SAMPLE, and REFORMAT DATE (invalid dates) have one. A segment ending in CONSOLE adds its records to the
+//! output; one ending in a label alone (`| f:`) feeds that stage's
+//! secondary input, which FANIN and FANINANY join with their primary input.
+//! A segment may instead start with `? < name`, reading an input the host
+//! supplies under that name.
+//!
+//! A `LAYOUT NAME pos,len [type]; ...` line names fields for the stages
+//! after it, which can then write `FILTER DEPT = "SALES"` instead of
+//! `FILTER 18,10 = "SALES"`. SELECT takes `NAME,dest`. Fields may not
+//! overlap, and names are case-insensitive.
+//!
+//! An `LRECL n` line, before any LAYOUT or stage, sets the record length
+//! (80 by default), e.g. `LRECL 132` for print lines.
+//!
+//! An `RC [WARN n] [ERROR n] [FATAL n]` line sets the warning and error
+//! counts at which the run's condition code rises to 4, 8, or 12 (see
+//! [`crate::condcode`]).
+//!
+//! [`parse`] and [`execute`] are the entry points for front ends; the web
+//! UIs, `pipe-run`, and the record-at-a-time executor all parse with them.
+//! Parsing produces a [`PipelinePlan`], which [`execute_plan`] runs.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::io::BufRead;
+
+use std::time::Duration;
+
+use crate::condcode::{ConditionCode, ConditionCodes};
+use crate::condition::{Condition, NumberFormat};
+use crate::crypto::{self, CryptSpec, Encoding, KeySource};
+use crate::csv::CsvSpec;
+use crate::dates::{DateFormat, DateSpec};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::ebcdic::{self, Charset, CodePage};
+use crate::group::{Aggregate, AggregateKind, Total};
+use crate::hostvars::{HostVars, StampValue, is_valid_name};
+use crate::infiles::{InputFiles, SideReader, not_supplied};
+use crate::instrument::{RunSpan, StageSpan, stage_complete};
+use crate::metrics::{PipelineMetrics, StageMetrics, Timer};
+use crate::outfiles::SideWriter;
+use crate::plan::{PipelinePlan, is_label};
+use crate::plugin;
+use crate::sink::Sink;
+use crate::specs::{Conversion, Placement, SpecInput, SpecItem, Specs, binary_conversion_error};
+use crate::split::SplitOutputs;
+use crate::xlate::{self, TableSource, XlateSpec};
+use crate::{
+    Between, Change, Console, Dedup, Dump, DumpFormat, ErrorPolicy, FieldType, KeyFormat,
+    LayoutField, Locate, MAX_LRECL, Pad, Pipeline, ProfileStage, Recno, RecnoRange, Record,
+    RecordLayout, RecordPool, Sample, Select, Sort, SortKey, SortOrder, Squish, SumBy, SumField,
+    SumFormat, ThrottleStage, Trunc,
+};
 
 /// Callback type for stage start events: `(stage_index, stage_name)`.
 type StageStartCallback = Box<dyn Fn(usize, &str) + 'static>;
@@ -104,72 +195,32 @@ pub fn execute_pipeline(
     input_text: &str,
     pipeline_text: &str,
 ) -> Result<(String, usize, usize), String> {
-    // Parse pipeline commands
-    let commands = parse_commands(pipeline_text)?;
-
-    // Validate pipeline structure
-    if commands.is_empty() {
-        return Err("Pipeline is empty".to_string());
-    }
-
-    // Need at least 2 stages (source and something to receive output)
-    if commands.len() < 2 {
-        return Err("Pipeline must have at least 2 stages".to_string());
-    }
-
-    // Check first stage can be first (source)
-    let first = commands.first().unwrap();
-    if !first.can_be_first() {
-        return Err(format!(
-            "{} cannot be the first stage (try CONSOLE, LITERAL, or HOLE)",
-            first.name()
-        ));
-    }
-
-    // Any stage can be last - if not a sink, output is simply discarded
-    // Any stage can be in the middle - CONSOLE passes through while printing
-
-    // Get initial records based on first stage type
-    let input_records: Vec<Record> = match first {
-        Command::Console => {
-            // CONSOLE reads from input text
-            input_text
-                .lines()
-                .filter(|line| !line.is_empty())
-                .map(Record::from_str)
-                .collect()
-        }
-        Command::Literal { text } => {
-            // LITERAL generates a single record
-            vec![Record::from_str(text)]
-        }
-        Command::Hole => {
-            // HOLE generates an empty stream
-            vec![]
-        }
-        _ => {
-            // Other can_be_first stages would be handled here
-            return Err(format!("Unhandled source stage: {}", first.name()));
-        }
-    };
-
-    let input_count = input_records.len();
-
-    // Apply all commands after the first (source)
-    // Any stage can be last - it transforms and the result is output
-    let remaining_commands = &commands[1..];
-    let output_records = apply_commands(input_records, remaining_commands)?;
-
-    let output_count = output_records.len();
+    execute_pipeline_with_vars(input_text, pipeline_text, &HostVars::new())
+}
 
-    // Format output (CONSOLE writes to output)
-    let output_text = output_records
-        .iter()
-        .map(|r| r.as_str().trim_end())
-        .collect::<Vec<_>>()
-        .join("\n");
+/// Execute a pipeline, resolving `STAMP` host variables from `vars`.
+///
+/// Returns (output_text, input_count, output_count) on success.
+pub fn execute_pipeline_with_vars(
+    input_text: &str,
+    pipeline_text: &str,
+    vars: &HostVars,
+) -> Result<(String, usize, usize), String> {
+    let run = execute(input_text, pipeline_text, vars)?;
+    Ok((run.output, run.input_count, run.output_count))
+}
 
-    Ok((output_text, input_count, output_count))
+/// Execute a pipeline, resolving `STAMP` host variables from `vars`, and
+/// return everything it produces.
+///
+/// This and [`parse`] are the entry points for front ends: every front end
+/// built on them accepts the same commands with the same meaning.
+pub fn execute(
+    input_text: &str,
+    pipeline_text: &str,
+    vars: &HostVars,
+) -> Result<PipelineRun, String> {
+    execute_pipeline_run(input_text, pipeline_text, &None, vars)
 }
 
 /// Execute a pipeline with debug callbacks for stage-by-stage inspection.
@@ -180,44 +231,225 @@ pub fn execute_pipeline_debug(
     pipeline_text: &str,
     debug: &Option<DebugCallbacks>,
 ) -> Result<(String, usize, usize, Vec<DebugInfo>), String> {
-    let commands = parse_commands(pipeline_text)?;
+    execute_pipeline_debug_with_vars(input_text, pipeline_text, debug, &HostVars::new())
+}
 
-    if commands.is_empty() {
-        return Err("Pipeline is empty".to_string());
-    }
+/// Execute a pipeline with debug callbacks, resolving `STAMP` host
+/// variables from `vars`.
+///
+/// Returns (output_text, input_count, output_count, debug_info) on success.
+pub fn execute_pipeline_debug_with_vars(
+    input_text: &str,
+    pipeline_text: &str,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+) -> Result<(String, usize, usize, Vec<DebugInfo>), String> {
+    let run = execute_pipeline_run(input_text, pipeline_text, debug, vars)?;
+    Ok((
+        run.output,
+        run.input_count,
+        run.output_count,
+        run.debug_info,
+    ))
+}
 
-    if commands.len() < 2 {
-        return Err("Pipeline must have at least 2 stages".to_string());
-    }
+/// Everything a pipeline run produces.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineRun {
+    /// Output text, one line per record.
+    pub output: String,
+    /// Records read by the source stage.
+    pub input_count: usize,
+    /// Records written by the last stage.
+    pub output_count: usize,
+    /// Per-stage counts (and records, with debug callbacks).
+    pub debug_info: Vec<DebugInfo>,
+    /// Records copied to named outputs by `SPLIT` stages.
+    pub outputs: SplitOutputs,
+    /// Records written by `>` and `>>` stages, by path.
+    pub files: SplitOutputs,
+    /// Per-stage counts and wall time.
+    pub metrics: PipelineMetrics,
+    /// Warnings, such as input records truncated to LRECL.
+    pub warnings: Vec<String>,
+    /// Records stages diverted as errors, such as invalid dates.
+    pub error_records: usize,
+    /// The run's condition code, from the warnings and error records and
+    /// the pipeline's `RC` thresholds.
+    pub condition_code: ConditionCode,
+}
 
-    let first = commands.first().unwrap();
-    if !first.can_be_first() {
-        return Err(format!(
-            "{} cannot be first stage (try CONSOLE, LITERAL, or HOLE)",
-            first.name()
-        ));
-    }
+/// Execute a pipeline like [`execute_pipeline_debug_with_vars`], also
+/// returning the named outputs written by `SPLIT` stages.
+pub fn execute_pipeline_run(
+    input_text: &str,
+    pipeline_text: &str,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+) -> Result<PipelineRun, String> {
+    execute_plan(
+        &PipelinePlan::parse(pipeline_text)?,
+        input_text,
+        debug,
+        vars,
+    )
+}
 
-    let input_records: Vec<Record> = match first {
-        Command::Console => input_text
-            .lines()
-            .filter(|line| !line.is_empty())
-            .map(Record::from_str)
-            .collect(),
-        Command::Literal { text } => {
-            vec![Record::from_str(text)]
-        }
-        Command::Hole => {
-            vec![]
-        }
-        _ => {
-            return Err(format!("Unhandled source stage: {}", first.name()));
+/// Execute a parsed or built [`PipelinePlan`] on input text, one stage at a
+/// time over all records.
+pub fn execute_plan(
+    plan: &PipelinePlan,
+    input_text: &str,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+) -> Result<PipelineRun, String> {
+    execute_plan_with_inputs(plan, input_text, debug, vars, &InputFiles::new())
+}
+
+/// Execute a plan like [`execute_plan`], with segments starting with
+/// `? < name` reading the inputs `inputs` opens.
+pub fn execute_plan_with_inputs(
+    plan: &PipelinePlan,
+    input_text: &str,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+    inputs: &dyn SideReader,
+) -> Result<PipelineRun, String> {
+    let mut output = Vec::new();
+    let side = RunSide::default().with_inputs(inputs);
+    let mut run = run_plan(plan, input_text.lines(), debug, vars, side, |record| {
+        output.push(record.as_str().trim_end().to_string());
+        Ok(())
+    })?;
+    run.output = output.join("\n");
+    Ok(run)
+}
+
+/// Execute a plan like [`execute_plan`], reading input lines only as the
+/// source stage needs them and passing each output record to `emit`
+/// instead of returning the output text, which is left empty. The records
+/// of SPLIT outputs and `>` and `>>` stages are kept in the run's
+/// `outputs` and `files`; see [`execute_plan_streaming_to`] to write them
+/// as they pass instead.
+///
+/// Without segments or debug callbacks, each record is pulled from `lines`
+/// through every stage and emitted as it comes out, so only stages that
+/// need all their input (SORT, COUNT, TAKE LAST) hold records, and they
+/// emit them at end of input. A failure part way through leaves the
+/// records emitted so far. Otherwise stages run one at a time over all
+/// records, which are held in memory between stages, though the input and
+/// output text never are. `emit` failing stops the run with its error.
+pub fn execute_plan_streaming<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+    emit: F,
+) -> Result<PipelineRun, String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    run_plan(plan, lines, debug, vars, RunSide::default(), emit)
+}
+
+/// Execute a plan like [`execute_plan_streaming`], passing each record a
+/// SPLIT stage copies or a `>` or `>>` stage writes to `writer` as the
+/// stage passes it on, so the run's `outputs` and `files` are left empty.
+/// Segments starting with `? < name` read the inputs `inputs` opens.
+///
+/// Streamed, a record reaches `writer` before the next input line is
+/// read. A write that fails stops the run with its error.
+pub fn execute_plan_streaming_to<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+    inputs: &dyn SideReader,
+    writer: &mut dyn SideWriter,
+    emit: F,
+) -> Result<PipelineRun, String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let side = RunSide::new(writer).with_inputs(inputs);
+    run_plan(plan, lines, debug, vars, side, emit)
+}
+
+/// Runs a plan for [`execute_plan_streaming`], with the side outputs its
+/// stages write to.
+fn run_plan<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    debug: &Option<DebugCallbacks>,
+    vars: &HostVars,
+    side: RunSide,
+    mut emit: F,
+) -> Result<PipelineRun, String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    plan.validate()?;
+    let lrecl = plan.lrecl();
+    check_runtime(&plan.all_commands().cloned().collect::<Vec<_>>(), vars)?;
+
+    let run_span = RunSpan::enter("batch", plan.all_commands().count());
+    let first = plan.source();
+    // Source warnings count toward the condition code: CSV values cut to
+    // fit their fields, and input lines cut to LRECL
+    let warnings = RefCell::new(Vec::new());
+    let check_length = matches!(first, Command::Console | Command::FileIn { .. });
+    let read = Cell::new(0);
+    let lines = lines.inspect(|line| {
+        let line = line.as_ref();
+        if check_length && !line.is_empty() {
+            read.set(read.get() + 1);
+            if line.trim_end().len() > lrecl {
+                warnings.borrow_mut().push(format!(
+                    "record {} is longer than LRECL {lrecl} and was truncated",
+                    read.get()
+                ));
+            }
         }
-    };
+    });
+    if debug.is_none() && plan.segments().is_empty() {
+        return stream_plan(plan, lines, vars, &warnings, &run_span, side, emit);
+    }
+    let source_span = StageSpan::new(0, first.name());
+    let entered = source_span.enter();
+    let timer = Timer::start();
+    let input_records = stream_records_with(first, lines, lrecl, Record::from_str_lrecl, |w| {
+        warnings.borrow_mut().push(w)
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+    let warnings = warnings.into_inner();
+    let source_elapsed = timer.elapsed();
+    drop(entered);
+    stage_complete(0, first.name(), 0, input_records.len());
 
     let input_count = input_records.len();
-    let mut debug_info: Vec<DebugInfo> = Vec::new();
-    let mut current_records = input_records;
+    let mut state = RunState {
+        debug,
+        vars,
+        lrecl,
+        debug_info: vec![None; plan.all_commands().count()],
+        elapsed: vec![Duration::ZERO; plan.all_commands().count()],
+        side,
+        secondary: Vec::new(),
+        connected: plan
+            .segments()
+            .iter()
+            .filter(|s| s.file().is_none())
+            .map(|s| s.input())
+            .collect(),
+        feeds: Vec::new(),
+    };
+    let current_records = input_records;
 
     // Record debug info for the source stage (stage 0).
     // The source was already evaluated above; don't re-apply it.
@@ -248,63 +480,822 @@ pub fn execute_pipeline_debug(
         } else {
             DebugInfo::new(source_name, 0, source_output_count)
         };
-        debug_info.push(info);
+        state.debug_info[0] = Some(info);
+        state.elapsed[0] = source_elapsed;
+    }
+
+    // The main pipeline (after its source) and each segment run as chains
+    // of stages. A segment waits for the records its labeled stage drops,
+    // unless it reads a named input, and a labeled FANIN or FANINANY waits
+    // for the segments feeding it; stages are numbered in the order they
+    // are written.
+    let mut chains = vec![Chain {
+        stages: plan
+            .stages()
+            .iter()
+            .enumerate()
+            .map(|(idx, cmd)| (cmd, plan.label_of(idx + 1)))
+            .collect(),
+        input: None,
+        target: None,
+        first: 1,
+        pos: 0,
+        records: Some(current_records),
+        done: false,
+    }];
+    let mut first = plan.commands().len();
+    for segment in plan.segments() {
+        let records = match segment.file() {
+            Some(name) => Some(state.side.read_input(name, lrecl)?),
+            None => None,
+        };
+        chains.push(Chain {
+            stages: segment
+                .commands()
+                .iter()
+                .enumerate()
+                .map(|(idx, cmd)| (cmd, segment.label_of(idx)))
+                .collect(),
+            input: Some(segment.input()).filter(|_| segment.file().is_none()),
+            target: segment.target(),
+            first,
+            pos: 0,
+            records,
+            done: false,
+        });
+        first += segment.commands().len();
+    }
+    while chains.iter().any(|chain| !chain.done) {
+        let mut progress = false;
+        for idx in 0..chains.len() {
+            let pending: Vec<&str> = chains
+                .iter()
+                .filter(|chain| !chain.done)
+                .filter_map(|chain| chain.target)
+                .collect();
+            progress |= state.advance(&mut chains[idx], idx, &pending)?;
+        }
+        if !progress {
+            return Err(
+                "Pipeline streams form a loop: a FANIN or FANINANY waits for its own records"
+                    .to_string(),
+            );
+        }
+    }
+
+    // The main pipeline's records, then those of each segment ending in
+    // CONSOLE, make the output
+    let mut output_records = Vec::new();
+    for (idx, chain) in chains.iter_mut().enumerate() {
+        let to_output = idx == 0
+            || (chain.target.is_none()
+                && chain
+                    .stages
+                    .last()
+                    .is_some_and(|(cmd, _)| **cmd == Command::Console));
+        if to_output {
+            output_records.extend(chain.records.take().unwrap_or_default());
+        }
+    }
+
+    for record in &output_records {
+        emit(record)?;
     }
 
-    // Apply remaining commands (after source stage)
+    run_span.finish(input_count, output_records.len());
+    Ok(finish_run(
+        plan,
+        state.debug_info,
+        &state.elapsed,
+        state.side,
+        warnings,
+        (input_count, output_records.len()),
+    ))
+}
+
+/// Runs a plan without segments for [`execute_plan_streaming`], pulling
+/// each record from `lines` through every stage and emitting it as it
+/// comes out.
+fn stream_plan<I, S, F>(
+    plan: &PipelinePlan,
+    lines: I,
+    vars: &HostVars,
+    warnings: &RefCell<Vec<String>>,
+    run_span: &RunSpan,
+    side: RunSide,
+    mut emit: F,
+) -> Result<PipelineRun, String>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+    F: FnMut(&Record) -> Result<(), String>,
+{
+    let lrecl = plan.lrecl();
+    let commands: Vec<&Command> = plan.all_commands().collect();
+    // Records out of each stage, and the time spent in it and the stages
+    // before it
+    let counts = vec![Cell::new(0); commands.len()];
+    let totals = vec![Cell::new(Duration::ZERO); commands.len()];
+    let source_error = RefCell::new(None);
+
+    let first = plan.source();
+    let source = stream_records_with(first, lines, lrecl, Record::from_str_lrecl, |w| {
+        warnings.borrow_mut().push(w)
+    })?
+    .map_while(|record| {
+        record
+            .map_err(|e| *source_error.borrow_mut() = Some(e))
+            .ok()
+    });
+    let mut records: Records = Box::new(CountedStage::new(0, first, source, &counts, &totals));
     for (idx, cmd) in commands.iter().enumerate().skip(1) {
-        let stage_name = cmd.name().to_string();
+        // The last CONSOLE is the output itself, not an echo of it
+        if idx + 1 < commands.len() || **cmd != Command::Console {
+            records = stream_command(records, cmd, vars, lrecl, &side)?;
+        }
+        records = Box::new(CountedStage::new(idx, cmd, records, &counts, &totals));
+    }
+    for record in records {
+        emit(&record)?;
+    }
+    if let Some(e) = source_error.into_inner().or_else(|| side.failure.take()) {
+        return Err(e);
+    }
 
-        if let Some(debug) = debug
-            && let Some(on_start) = &debug.on_stage_start
-        {
-            on_start(idx, &stage_name);
+    let mut debug_info = Vec::with_capacity(commands.len());
+    let mut elapsed = Vec::with_capacity(commands.len());
+    for (idx, cmd) in commands.iter().enumerate() {
+        let records_in = idx.checked_sub(1).map_or(0, |prev| counts[prev].get());
+        let records_out = counts[idx].get();
+        stage_complete(idx, cmd.name(), records_in, records_out);
+        debug_info.push(Some(DebugInfo::new(
+            cmd.name().to_string(),
+            records_in,
+            records_out,
+        )));
+        let upstream = idx
+            .checked_sub(1)
+            .map_or(Duration::ZERO, |prev| totals[prev].get());
+        elapsed.push(totals[idx].get().saturating_sub(upstream));
+    }
+    let input_count = counts[0].get();
+    let output_count = counts[commands.len() - 1].get();
+    run_span.finish(input_count, output_count);
+    Ok(finish_run(
+        plan,
+        debug_info,
+        &elapsed,
+        side,
+        warnings.take(),
+        (input_count, output_count),
+    ))
+}
+
+/// A stage's output in a streamed run, counting the records it passes and
+/// timing the calls into it, which include the stages before it.
+struct CountedStage<'a> {
+    records: Records<'a>,
+    span: StageSpan,
+    count: &'a Cell<usize>,
+    total: &'a Cell<Duration>,
+}
+
+impl<'a> CountedStage<'a> {
+    fn new(
+        idx: usize,
+        cmd: &Command,
+        records: impl Iterator<Item = Record> + 'a,
+        counts: &'a [Cell<usize>],
+        totals: &'a [Cell<Duration>],
+    ) -> Self {
+        Self {
+            records: Box::new(records),
+            span: StageSpan::new(idx, cmd.name()),
+            count: &counts[idx],
+            total: &totals[idx],
         }
+    }
+}
+
+impl Iterator for CountedStage<'_> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        let _entered = self.span.enter();
+        let timer = Timer::start();
+        let record = self.records.next();
+        self.total.set(self.total.get() + timer.elapsed());
+        self.count
+            .set(self.count.get() + usize::from(record.is_some()));
+        record
+    }
+}
 
-        let input_count_stage = current_records.len();
-        let input_records_clone = debug.as_ref().map(|_| current_records.clone());
+/// What the stages of a run produce besides their output: records copied
+/// to named outputs and written to files, a count of error records, and
+/// the first error that stops the run part way (a SORT work file or side
+/// output that cannot be written).
+#[derive(Default)]
+struct RunSide<'w> {
+    outputs: RefCell<SplitOutputs>,
+    files: RefCell<SplitOutputs>,
+    errors: Cell<usize>,
+    failure: RefCell<Option<String>>,
+    /// Takes the records of `outputs` and `files` as they pass, if the
+    /// caller gave one
+    writer: Option<RefCell<&'w mut dyn SideWriter>>,
+    /// Opens the inputs of `? < name` segments, if the caller gave one
+    inputs: Option<&'w dyn SideReader>,
+}
 
-        current_records = apply_command(current_records, cmd)?;
+impl<'w> RunSide<'w> {
+    fn new(writer: &'w mut dyn SideWriter) -> Self {
+        Self {
+            writer: Some(RefCell::new(writer)),
+            ..Self::default()
+        }
+    }
 
-        let output_count_stage = current_records.len();
-        let output_records_clone = debug.as_ref().map(|_| current_records.clone());
+    /// Reads named inputs from `inputs`.
+    fn with_inputs(mut self, inputs: &'w dyn SideReader) -> Self {
+        self.inputs = Some(inputs);
+        self
+    }
 
-        if let Some(debug) = debug
-            && let Some(on_complete) = &debug.on_stage_complete
-        {
-            on_complete(idx, output_count_stage);
+    /// Reads the records of the input named `name`, skipping empty lines,
+    /// for a segment starting with `? < name`.
+    fn read_input(&self, name: &str, lrecl: usize) -> Result<Vec<Record>, String> {
+        let inputs = self.inputs.ok_or_else(|| not_supplied(name))?;
+        inputs
+            .open_input(name)?
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+            .map(|line| {
+                line.map(|line| Record::from_str_lrecl(&line, lrecl))
+                    .map_err(|e| format!("cannot read input {name}: {e}"))
+            })
+            .collect()
+    }
+
+    /// Starts SPLIT output `name`, which exists even if no record
+    /// reaches it (the writer's host opened it already).
+    fn open_output(&self, name: &str) {
+        if self.writer.is_none() {
+            self.outputs.borrow_mut().append(name, []);
         }
+    }
 
-        let info = if let Some(input_recs) = input_records_clone {
-            DebugInfo::with_records(
-                stage_name,
-                input_count_stage,
-                output_count_stage,
-                input_recs,
-                output_records_clone.unwrap_or_default(),
-            )
-        } else {
-            DebugInfo::new(stage_name, input_count_stage, output_count_stage)
+    /// Starts the file of a `>` or `>>` stage, like [`open_output`]
+    /// (Self::open_output).
+    fn open_file(&self, path: &str) {
+        if self.writer.is_none() {
+            self.files.borrow_mut().append(path, []);
+        }
+    }
+
+    /// Copies `record` to SPLIT output `name`, returning false if it
+    /// cannot be written, which fails the run.
+    fn write_output(&self, name: &str, record: Record) -> bool {
+        match &self.writer {
+            Some(writer) => self.check(writer.borrow_mut().write_output(name, &record)),
+            None => {
+                self.outputs.borrow_mut().append(name, [record]);
+                true
+            }
+        }
+    }
+
+    /// Writes `record` to the file of a `>` or `>>` stage, like
+    /// [`write_output`](Self::write_output).
+    fn write_file(&self, path: &str, record: &Record) -> bool {
+        match &self.writer {
+            Some(writer) => self.check(writer.borrow_mut().write_file(path, record)),
+            None => {
+                self.files.borrow_mut().append(path, [record.clone()]);
+                true
+            }
+        }
+    }
+
+    /// Keeps the first error that fails the run.
+    fn check(&self, result: Result<(), String>) -> bool {
+        result
+            .map_err(|e| self.failure.borrow_mut().get_or_insert(e).clone())
+            .is_ok()
+    }
+}
+
+/// Counts the error records a stage rejects.
+struct ErrorCount<'a>(&'a Cell<usize>);
+
+impl Sink for ErrorCount<'_> {
+    fn accept(&mut self, _record: &Record) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+/// Builds the result of a run from its per-stage debug info and times,
+/// side outputs, warnings, and record counts (in, then out).
+fn finish_run(
+    plan: &PipelinePlan,
+    debug_info: Vec<Option<DebugInfo>>,
+    elapsed: &[Duration],
+    side: RunSide,
+    warnings: Vec<String>,
+    (input_count, output_count): (usize, usize),
+) -> PipelineRun {
+    // Records REFORMAT DATE drops for invalid dates are error records,
+    // whether or not a segment reads them, as are those other stages reject
+    let error_records = plan
+        .all_commands()
+        .zip(&debug_info)
+        .filter(|(cmd, _)| matches!(cmd, Command::ReformatDate { .. }))
+        .filter_map(|(_, info)| info.as_ref())
+        .map(|info| info.input_count.saturating_sub(info.output_count))
+        .sum::<usize>()
+        + side.errors.get();
+    let metrics = PipelineMetrics {
+        stages: debug_info
+            .iter()
+            .zip(elapsed)
+            .filter_map(|(info, elapsed)| {
+                let info = info.as_ref()?;
+                let stage =
+                    StageMetrics::new(&info.stage_name, info.input_count, info.output_count);
+                Some(stage.with_elapsed(*elapsed))
+            })
+            .collect(),
+    };
+    PipelineRun {
+        output: String::new(),
+        input_count,
+        output_count,
+        metrics,
+        debug_info: debug_info.into_iter().flatten().collect(),
+        outputs: side.outputs.into_inner(),
+        files: side.files.into_inner(),
+        condition_code: plan
+            .condition_codes()
+            .classify(warnings.len(), error_records),
+        warnings,
+        error_records,
+    }
+}
+
+/// The main pipeline or a segment, run as far as its inputs allow.
+struct Chain<'a> {
+    stages: Vec<(&'a Command, Option<&'a str>)>,
+    /// Label whose secondary output a segment reads
+    input: Option<&'a str>,
+    /// Label of the stage a segment feeds (`| f:`)
+    target: Option<&'a str>,
+    /// Number of the first stage, for debug info
+    first: usize,
+    /// Index of the next stage to run
+    pos: usize,
+    /// Records between stages, once the chain has its input
+    records: Option<Vec<Record>>,
+    done: bool,
+}
+
+/// What the stages of a run share, in the main pipeline and the segments
+/// after it.
+struct RunState<'a, 'w> {
+    debug: &'a Option<DebugCallbacks>,
+    vars: &'a HostVars,
+    lrecl: usize,
+    /// Debug info by stage number, filled in as stages run
+    debug_info: Vec<Option<DebugInfo>>,
+    /// Wall time by stage number
+    elapsed: Vec<Duration>,
+    side: RunSide<'w>,
+    /// Records dropped by labeled stages, by label, until their segment runs
+    secondary: Vec<(String, Vec<Record>)>,
+    /// Labels whose secondary output a segment reads
+    connected: Vec<&'a str>,
+    /// Records of finished segments, with the segment number and the label
+    /// of the stage they feed
+    feeds: Vec<(usize, &'a str, Vec<Record>)>,
+}
+
+impl<'a> RunState<'a, '_> {
+    /// Runs the stages of chain `idx` up to the end or to a FANIN or
+    /// FANINANY whose label a `pending` segment still feeds. Returns true
+    /// if anything ran.
+    fn advance(
+        &mut self,
+        chain: &mut Chain<'a>,
+        idx: usize,
+        pending: &[&str],
+    ) -> Result<bool, String> {
+        if chain.done {
+            return Ok(false);
+        }
+        let mut progress = false;
+        let records = match chain.records.take() {
+            Some(records) => records,
+            None => match chain.input.and_then(|label| self.take_secondary(label)) {
+                Some(records) => {
+                    progress = true;
+                    records
+                }
+                None => return Ok(false),
+            },
         };
 
-        debug_info.push(info);
+        let waits = |(cmd, label): &(&Command, Option<&str>)| {
+            cmd.has_secondary_input() && label.is_some_and(|label| pending.contains(&label))
+        };
+        let end = chain.stages[chain.pos..]
+            .iter()
+            .position(waits)
+            .map_or(chain.stages.len(), |n| chain.pos + n);
+        let finished = end == chain.stages.len();
+        let records = self.run(
+            records,
+            &chain.stages[chain.pos..end],
+            chain.first + chain.pos,
+            finished && chain.target.is_none(),
+        )?;
+        progress |= end > chain.pos;
+        chain.pos = end;
+
+        match chain.target {
+            Some(target) if finished => self.feeds.push((idx, target, records)),
+            _ => chain.records = Some(records),
+        }
+        if finished {
+            chain.done = true;
+            progress = true;
+        }
+        Ok(progress)
     }
 
-    let output_count = current_records.len();
-    let output_text = current_records
-        .iter()
-        .map(|r| r.as_str().trim_end())
-        .collect::<Vec<_>>()
-        .join("\n");
+    /// Applies labeled stages, numbered from `first`, to records in turn,
+    /// keeping the records that connected stages drop for their segments.
+    /// If `output` is set, a last CONSOLE is the output rather than an
+    /// echo of it.
+    fn run(
+        &mut self,
+        mut records: Vec<Record>,
+        stages: &[(&Command, Option<&str>)],
+        first: usize,
+        output: bool,
+    ) -> Result<Vec<Record>, String> {
+        for (pos, (cmd, label)) in stages.iter().enumerate() {
+            let idx = first + pos;
+            let stage_name = cmd.name().to_string();
+
+            if let Some(debug) = self.debug
+                && let Some(on_start) = &debug.on_stage_start
+            {
+                on_start(idx, &stage_name);
+            }
+
+            let span = StageSpan::new(idx, &stage_name);
+            let entered = span.enter();
+            let timer = Timer::start();
+            // FANIN and FANINANY read the streams fed to their label too
+            if cmd.has_secondary_input()
+                && let Some(label) = label
+            {
+                let streams = self.take_feeds(label);
+                records = join_streams(cmd, records, streams);
+            }
+
+            let input_count_stage = records.len();
+            let input_records_clone = self.debug.as_ref().map(|_| records.clone());
+
+            let connected = label.filter(|label| self.connected.contains(label));
+            records = match connected {
+                Some(label) => {
+                    let bad = records.iter().filter(|r| has_bad_number(cmd, r)).count();
+                    self.side.errors.set(self.side.errors.get() + bad);
+                    let (kept, dropped) = select_records(records, cmd, self.lrecl)?;
+                    self.secondary.push((label.to_string(), dropped));
+                    kept
+                }
+                // The last CONSOLE is the output itself, not an echo of it
+                None if output && pos + 1 == stages.len() && **cmd == Command::Console => records,
+                None => apply_command(records, cmd, self.vars, self.lrecl, &self.side)?,
+            };
+
+            self.elapsed[idx] = timer.elapsed();
+            drop(entered);
+            let output_count_stage = records.len();
+            stage_complete(idx, &stage_name, input_count_stage, output_count_stage);
+            let output_records_clone = self.debug.as_ref().map(|_| records.clone());
+
+            if let Some(debug) = self.debug
+                && let Some(on_complete) = &debug.on_stage_complete
+            {
+                on_complete(idx, output_count_stage);
+            }
+
+            let info = if let Some(input_recs) = input_records_clone {
+                DebugInfo::with_records(
+                    stage_name,
+                    input_count_stage,
+                    output_count_stage,
+                    input_recs,
+                    output_records_clone.unwrap_or_default(),
+                )
+            } else {
+                DebugInfo::new(stage_name, input_count_stage, output_count_stage)
+            };
+
+            self.debug_info[idx] = Some(info);
+        }
+        Ok(records)
+    }
+
+    /// Removes and returns the records dropped by the stage labeled `label`,
+    /// once it has run.
+    fn take_secondary(&mut self, label: &str) -> Option<Vec<Record>> {
+        let idx = self.secondary.iter().position(|(name, _)| name == label)?;
+        Some(self.secondary.swap_remove(idx).1)
+    }
+
+    /// Removes and returns the streams fed to the stage labeled `label`, in
+    /// segment order.
+    fn take_feeds(&mut self, label: &str) -> Vec<Vec<Record>> {
+        let (mut fed, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut self.feeds)
+            .into_iter()
+            .partition(|(_, target, _)| *target == label);
+        self.feeds = rest;
+        fed.sort_by_key(|(idx, _, _)| *idx);
+        fed.into_iter().map(|(_, _, records)| records).collect()
+    }
+}
+
+/// Joins a FANIN or FANINANY stage's primary input with the streams fed to
+/// it: FANIN passes each stream whole, one after another; FANINANY takes
+/// one record from each stream in turn until all are empty.
+fn join_streams(cmd: &Command, primary: Vec<Record>, streams: Vec<Vec<Record>>) -> Vec<Record> {
+    let mut streams: Vec<_> = std::iter::once(primary)
+        .chain(streams)
+        .map(Vec::into_iter)
+        .collect();
+    match cmd {
+        Command::Faninany => {
+            let mut joined = Vec::new();
+            loop {
+                let before = joined.len();
+                for stream in &mut streams {
+                    joined.extend(stream.next());
+                }
+                if joined.len() == before {
+                    return joined;
+                }
+            }
+        }
+        _ => streams.into_iter().flatten().collect(),
+    }
+}
+
+/// Splits records at a stage with a secondary output into the records it
+/// passes and the records it drops, each in their original order.
+fn select_records(
+    records: Vec<Record>,
+    cmd: &Command,
+    lrecl: usize,
+) -> Result<(Vec<Record>, Vec<Record>), String> {
+    // REFORMAT DATE passes rewritten records and drops invalid dates
+    if let Command::ReformatDate { spec } = cmd {
+        let stage = spec.stage();
+        let (mut kept, mut dropped) = (Vec::new(), Vec::new());
+        for record in records {
+            match stage.convert(&record) {
+                Ok(out) => kept.push(out),
+                Err(_) => dropped.push(record),
+            }
+        }
+        return Ok((kept, dropped));
+    }
+    // TAKE LAST and DROP LAST split at the same point from the start
+    let first = |n: usize| records.len().saturating_sub(n);
+    let mut keep: Box<dyn FnMut(&Record) -> bool + '_> = match cmd {
+        Command::FilterEq { pos, len, value } => Box::new(|r| r.field_eq(*pos, *len, value)),
+        Command::FilterNe { pos, len, value } => Box::new(|r| !r.field_eq(*pos, *len, value)),
+        Command::FilterZoned {
+            pos,
+            len,
+            op,
+            value,
+        } => Box::new(|r| {
+            r.field_zoned(*pos, *len)
+                .is_some_and(|field| op.compare(field, *value))
+        }),
+        Command::FilterExpr { condition } => Box::new(|r| condition.matches(r)),
+        Command::Locate { pattern, field } | Command::Nlocate { pattern, field } => {
+            let mut locate = Locate::new(pattern);
+            if let Some((pos, len)) = *field {
+                locate = locate.within(pos, len);
+            }
+            let wanted = matches!(cmd, Command::Locate { .. });
+            Box::new(move |r| locate.matches(r) == wanted)
+        }
+        Command::Take { n } => {
+            let mut seen = 0;
+            Box::new(move |_| {
+                seen += 1;
+                seen <= *n
+            })
+        }
+        Command::Skip { n } => {
+            let mut seen = 0;
+            Box::new(move |_| {
+                seen += 1;
+                seen > *n
+            })
+        }
+        Command::Between {
+            start,
+            end,
+            field,
+            inclusive,
+        } => {
+            let mut between = between_stage(start, end, *field, *inclusive);
+            Box::new(move |r| between.process(r.clone()).is_some())
+        }
+        Command::TakeLast { n } | Command::DropLast { n } => {
+            let (split, wanted) = (first(*n), matches!(cmd, Command::TakeLast { .. }));
+            let mut seen = 0;
+            Box::new(move |_| {
+                seen += 1;
+                (seen > split) == wanted
+            })
+        }
+        Command::Recno { ranges } => {
+            let mut recno = Recno::new(ranges.clone());
+            Box::new(move |r| recno.matches(r))
+        }
+        Command::Sample { fraction, seed } => {
+            let mut sample = Sample::new(*fraction, *seed);
+            Box::new(move |r| sample.matches(r))
+        }
+        Command::Unique { field } => {
+            let mut dedup = Dedup::new(vec![field.unwrap_or((0, lrecl))]);
+            Box::new(move |r| dedup.process(r.clone()).is_some())
+        }
+        _ => return Err(format!("{} has no secondary output", cmd.name())),
+    };
+    Ok(records.into_iter().partition(|r| keep(r)))
+}
+
+/// Builds the stage for a BETWEEN command.
+fn between_stage(
+    start: &str,
+    end: &str,
+    field: Option<(usize, usize)>,
+    inclusive: bool,
+) -> Between {
+    let mut between = Between::new(start, end);
+    if let Some((pos, len)) = field {
+        between = between.within(pos, len);
+    }
+    if !inclusive {
+        between = between.exclusive();
+    }
+    between
+}
+
+/// Reads the records produced by the source stage `first`: one per
+/// non-empty input line for `CONSOLE`, one per CSV line for `FROM CSV`
+/// (passing its truncation warnings to `warn`), the literal text for
+/// `LITERAL`, and none for `HOLE`.
+///
+/// The core never opens files: for `< path` the host reads the file (see
+/// [`input_file`]) and passes its text as `input_text`.
+pub fn source_records(
+    first: &Command,
+    input_text: &str,
+    lrecl: usize,
+    warn: impl FnMut(String),
+) -> Result<Vec<Record>, String> {
+    stream_records(first, input_text.lines(), lrecl, warn)?.collect()
+}
+
+/// Records read by a source stage, from [`stream_records`].
+pub type RecordStream<'a> = Box<dyn Iterator<Item = Result<Record, String>> + 'a>;
+
+/// Reads the records produced by the source stage `first` like
+/// [`source_records`], from input lines pulled one at a time as the
+/// records are needed.
+///
+/// `FROM CSV` passes its warnings to `warn` once the lines run out.
+pub fn stream_records<'a, I, S, W>(
+    first: &Command,
+    lines: I,
+    lrecl: usize,
+    warn: W,
+) -> Result<RecordStream<'a>, String>
+where
+    I: Iterator<Item = S> + 'a,
+    S: AsRef<str>,
+    W: FnMut(String) + 'a,
+{
+    stream_records_with(first, lines, lrecl, Record::from_str_lrecl, warn)
+}
+
+/// Reads the records produced by the source stage `first` like
+/// [`stream_records`], building each record read from an input line in a
+/// spare buffer from `pool` when it has one.
+///
+/// The caller recycles the records it has finished with into the pool;
+/// the pool is borrowed only while a record is built.
+pub fn stream_records_pooled<'a, I, S, W>(
+    first: &Command,
+    lines: I,
+    lrecl: usize,
+    pool: &'a RefCell<RecordPool>,
+    warn: W,
+) -> Result<RecordStream<'a>, String>
+where
+    I: Iterator<Item = S> + 'a,
+    S: AsRef<str>,
+    W: FnMut(String) + 'a,
+{
+    stream_records_with(
+        first,
+        lines,
+        lrecl,
+        |line, lrecl| pool.borrow_mut().from_str_lrecl(line, lrecl),
+        warn,
+    )
+}
+
+/// Reads a source stage's records, building those read from input lines
+/// with `make` and passing `FROM CSV` warnings to `warn` once the lines
+/// run out.
+fn stream_records_with<'a, I, S, M, W>(
+    first: &Command,
+    lines: I,
+    lrecl: usize,
+    make: M,
+    mut warn: W,
+) -> Result<RecordStream<'a>, String>
+where
+    I: Iterator<Item = S> + 'a,
+    S: AsRef<str>,
+    M: Fn(&str, usize) -> Record + 'a,
+    W: FnMut(String) + 'a,
+{
+    match first {
+        Command::Console | Command::FileIn { .. } => Ok(Box::new(
+            lines
+                .filter(|line| !line.as_ref().is_empty())
+                .map(move |line| Ok(make(line.as_ref(), lrecl))),
+        )),
+        Command::FromCsv { spec } => {
+            let mut source = spec.source(lines);
+            let mut warned = false;
+            Ok(Box::new(std::iter::from_fn(move || {
+                let next = source.next();
+                if next.is_none() && !warned {
+                    for warning in source.warnings() {
+                        warn(format!("FROM CSV: {warning}"));
+                    }
+                    warned = true;
+                }
+                next.map(|record| record.map_err(|e| e.to_string()))
+            })))
+        }
+        Command::Literal { text } => Ok(Box::new(std::iter::once(Ok(Record::from_str_lrecl(
+            text, lrecl,
+        ))))),
+        Command::Hole => Ok(Box::new(std::iter::empty())),
+        _ => Err(format!("Unhandled source stage: {}", first.name())),
+    }
+}
 
-    Ok((output_text, input_count, output_count, debug_info))
+/// Returns the file named by a `< path` source stage, which the host reads
+/// in place of its usual input.
+pub fn input_file(commands: &[Command]) -> Option<&str> {
+    match commands.first() {
+        Some(Command::FileIn { path }) => Some(path),
+        _ => None,
+    }
+}
+
+/// Checks what parsing cannot: that `STAMP` variables are set, `PLUGIN`
+/// stages are registered, `ENCRYPT`/`DECRYPT` keys load, and `XLATE`
+/// table files load.
+pub fn check_runtime(commands: &[Command], vars: &HostVars) -> Result<(), String> {
+    vars.validate(commands)?;
+    plugin::validate(commands)?;
+    crypto::validate(commands)?;
+    xlate::validate(commands)
 }
 
 /// Parsed pipeline command.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     /// CONSOLE - Read from input or write to output
     Console,
+    /// DUMP [HEX|RULER] - write each record to stderr as a hex dump or
+    /// under a column ruler, passing it on
+    Dump { format: DumpFormat },
     /// FILTER pos,len = "value"
     FilterEq {
         pos: usize,
@@ -317,12 +1308,30 @@ pub enum Command {
         len: usize,
         value: String,
     },
+    /// FILTER pos,len ZD op n - compare a zoned decimal field to a number
+    /// (records whose field is not zoned decimal are dropped)
+    FilterZoned {
+        pos: usize,
+        len: usize,
+        op: CompareOp,
+        value: i64,
+    },
+    /// FILTER condition - any other FILTER: ordered text or NUM
+    /// comparisons, or comparisons joined by AND, OR, and parentheses
+    FilterExpr { condition: Condition },
     /// SELECT p1,l1,d1; p2,l2,d2; ...
     Select { fields: Vec<(usize, usize, usize)> },
+    /// OVERLAY p1,l1,d1; p2,l2,d2; ... - copy fields within each record,
+    /// keeping the rest of it
+    Overlay { fields: Vec<(usize, usize, usize)> },
     /// TAKE n
     Take { n: usize },
     /// SKIP n
     Skip { n: usize },
+    /// TAKE LAST n - keep the last n records
+    TakeLast { n: usize },
+    /// DROP LAST n - drop the last n records
+    DropLast { n: usize },
     /// LOCATE "pattern" - keep records containing pattern
     Locate {
         pattern: String,
@@ -335,10 +1344,34 @@ pub enum Command {
         /// Optional field restriction (pos, len)
         field: Option<(usize, usize)>,
     },
+    /// BETWEEN /start/ /end/ [EXCLUSIVE] - keep each section from a record
+    /// containing start through the next containing end
+    Between {
+        start: String,
+        end: String,
+        /// Optional field restriction (pos, len)
+        field: Option<(usize, usize)>,
+        /// Keep the marker records (not EXCLUSIVE)
+        inclusive: bool,
+    },
     /// COUNT - count records and emit summary
     Count,
+    /// SUM|MIN|MAX|AVG pos,len - total, smallest, largest, or average of a
+    /// numeric field, emitted as a single summary record
+    Statistic {
+        kind: AggregateKind,
+        pos: usize,
+        len: usize,
+    },
     /// CHANGE "old" "new" - replace occurrences
-    Change { old: String, new: String },
+    Change {
+        old: String,
+        new: String,
+        /// Optional field restriction (pos, len)
+        field: Option<(usize, usize)>,
+        /// Most replacements per record (all if `None`)
+        count: Option<usize>,
+    },
     /// LITERAL "text" - append a literal record
     Literal { text: String },
     /// UPPER - convert to uppercase
@@ -347,56 +1380,591 @@ pub enum Command {
     Lower,
     /// REVERSE - reverse characters in record
     Reverse,
-    /// DUPLICATE n - repeat each record n times
+    /// PAD n [c] - fill each record with c out to n columns
+    Pad { len: usize, fill: char },
+    /// TRUNC n - keep the first n columns, blanking the rest
+    Trunc { len: usize },
+    /// SQUISH - squeeze runs of blanks to a single blank
+    Squish,
+    /// XLATE [pos,len] [UPPER|LOWER|FILE path] [from to ...] - translate
+    /// bytes through a table
+    Xlate { spec: XlateSpec },
+    /// REFORMAT DATE pos fmt TO fmt [dest] - rewrite a date field in
+    /// another layout, dropping records without a real date
+    ReformatDate { spec: DateSpec },
+    /// FROM CSV [HEADER] [fields] - read the input as CSV, placing the
+    /// columns in layout fields (source only)
+    FromCsv { spec: CsvSpec },
+    /// TO CSV [HEADER] [fields] - write layout fields as CSV lines
+    ToCsv { spec: CsvSpec },
+    /// DUPLICATE [n] - write each record and n more copies (CMS Pipelines
+    /// semantics: `DUPLICATE 1`, the default, doubles the records)
     Duplicate { n: usize },
+    /// UNIQUE [pos,len] - keep the first record with each key, or each
+    /// distinct record
+    Unique { field: Option<(usize, usize)> },
+    /// SORT [pos,len [A|D] [CH|NUM]; ...] [SUM pos,len [ZD]; ... | SUM
+    /// NONE] - order records by key fields (default: the whole record);
+    /// with SUM, records with equal keys collapse into the first, with the
+    /// fields totalled (`SUM NONE`: none, so later duplicates are dropped)
+    Sort {
+        keys: Vec<SortKey>,
+        sum: Option<Vec<SumField>>,
+    },
+    /// RECNO n-m [BY s], ... - keep records by input position, counting
+    /// from 1 (`n-*` runs to the end of the input)
+    Recno { ranges: Vec<RecnoRange> },
+    /// SAMPLE fraction [SEED n] - keep a reproducible random fraction of
+    /// the records
+    Sample { fraction: f64, seed: u64 },
     /// HOLE - discard all input, output nothing (like /dev/null)
     Hole,
+    /// FANIN - pass the primary input, then each secondary input stream in
+    /// turn, in segment order
+    Fanin,
+    /// FANINANY - pass records from the primary and secondary input
+    /// streams as they arrive (in the batch executor, one from each stream
+    /// in turn)
+    Faninany,
+    /// `< path` - read records from a file (first stage only); the host
+    /// reads the file
+    FileIn { path: String },
+    /// `> path` or `>> path` - write or append records to a file, passing
+    /// them on unchanged; the host writes the file
+    FileOut { path: String, append: bool },
+    /// DELAY n ms - pause before passing each record (for demonstrations)
+    Delay { ms: u64 },
+    /// PROFILE [pos,len [type]; ...] - report per-field statistics
+    /// (fields are inferred from the data when none are given)
+    Profile {
+        fields: Vec<(usize, usize, FieldType)>,
+    },
+    /// STAMP [HEADER] pos,len VAR; pos,len "text"; ... - write host variables
+    /// into each record, or into one header record emitted before the input
+    Stamp {
+        header: bool,
+        fields: Vec<(usize, usize, StampValue)>,
+    },
+    /// PLUGIN name [args] - run a stage provided at runtime
+    Plugin { name: String, args: String },
+    /// ENCRYPT pos,len [TO pos,len] [HEX|BASE64] [key] - seal a field
+    Encrypt { spec: CryptSpec },
+    /// DECRYPT pos,len [TO pos,len] [HEX|BASE64] [key] - open a sealed field
+    Decrypt { spec: CryptSpec },
+    /// CONVERT ASCII|EBCDIC [code page] - mark the input or output file as
+    /// EBCDIC; the file is converted when read or written
+    Convert { to: Charset, code_page: CodePage },
+    /// SPECS input [conversion] output ... - build each record from input
+    /// fields, literals, and the record number (like CMS Pipelines SPECS)
+    Specs { items: Vec<SpecItem> },
+    /// SPLIT name [IF condition] [BUILD src,len,dest; ...] - copy records
+    /// meeting a FILTER condition, optionally rebuilt, to a named output
+    /// (like DFSORT OUTFIL); all records pass on unchanged
+    Split {
+        name: String,
+        condition: Option<Box<Command>>,
+        build: Option<Vec<(usize, usize, usize)>>,
+    },
 }
 
-impl Command {
-    /// Can this stage be the first stage in a pipeline (source)?
-    /// Sources generate or read records without needing upstream input.
-    pub fn can_be_first(&self) -> bool {
-        // CONSOLE reads from input, LITERAL generates a record, HOLE generates empty stream
-        matches!(
-            self,
-            Command::Console | Command::Literal { .. } | Command::Hole
-        )
-    }
+/// Comparison operator for numeric FILTER modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
 
-    /// Get the stage name for error messages.
-    pub fn name(&self) -> &'static str {
+impl CompareOp {
+    /// Returns the operator as written in the DSL.
+    pub fn symbol(&self) -> &'static str {
         match self {
-            Command::Console => "CONSOLE",
-            Command::FilterEq { .. } | Command::FilterNe { .. } => "FILTER",
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    /// Parses an operator symbol.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "=" | "==" => Some(CompareOp::Eq),
+            "!=" | "<>" => Some(CompareOp::Ne),
+            "<" => Some(CompareOp::Lt),
+            "<=" => Some(CompareOp::Le),
+            ">" => Some(CompareOp::Gt),
+            ">=" => Some(CompareOp::Ge),
+            _ => None,
+        }
+    }
+
+    /// Applies the operator to `left` and `right`.
+    pub fn compare<T: Ord>(&self, left: T, right: T) -> bool {
+        match self {
+            CompareOp::Eq => left == right,
+            CompareOp::Ne => left != right,
+            CompareOp::Lt => left < right,
+            CompareOp::Le => left <= right,
+            CompareOp::Gt => left > right,
+            CompareOp::Ge => left >= right,
+        }
+    }
+}
+
+impl Command {
+    /// Can this stage be the first stage in a pipeline (source)?
+    /// Sources generate or read records without needing upstream input.
+    pub fn can_be_first(&self) -> bool {
+        // CONSOLE reads from input, LITERAL generates a record, HOLE generates
+        // empty stream, < reads a file
+        matches!(
+            self,
+            Command::Console
+                | Command::Literal { .. }
+                | Command::Hole
+                | Command::FileIn { .. }
+                | Command::FromCsv { .. }
+        )
+    }
+
+    /// Does this stage have a secondary output, which a labeled stage can
+    /// route to a segment? Selection stages write the records they drop
+    /// there, in order.
+    pub fn has_secondary_output(&self) -> bool {
+        matches!(
+            self,
+            Command::FilterEq { .. }
+                | Command::FilterNe { .. }
+                | Command::FilterZoned { .. }
+                | Command::FilterExpr { .. }
+                | Command::Locate { .. }
+                | Command::Nlocate { .. }
+                | Command::Between { .. }
+                | Command::Take { .. }
+                | Command::Skip { .. }
+                | Command::TakeLast { .. }
+                | Command::DropLast { .. }
+                | Command::Unique { .. }
+                | Command::Recno { .. }
+                | Command::Sample { .. }
+                | Command::ReformatDate { .. }
+        )
+    }
+
+    /// Does this stage have secondary inputs, which segments ending in its
+    /// label (`| f:`) feed? FANIN and FANINANY join them with the primary
+    /// input.
+    pub fn has_secondary_input(&self) -> bool {
+        matches!(self, Command::Fanin | Command::Faninany)
+    }
+
+    /// Get the stage name for error messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Console => "CONSOLE",
+            Command::Dump { .. } => "DUMP",
+            Command::FilterEq { .. }
+            | Command::FilterNe { .. }
+            | Command::FilterZoned { .. }
+            | Command::FilterExpr { .. } => "FILTER",
             Command::Select { .. } => "SELECT",
-            Command::Take { .. } => "TAKE",
+            Command::Overlay { .. } => "OVERLAY",
+            Command::Take { .. } | Command::TakeLast { .. } => "TAKE",
+            Command::DropLast { .. } => "DROP",
             Command::Skip { .. } => "SKIP",
             Command::Locate { .. } => "LOCATE",
             Command::Nlocate { .. } => "NLOCATE",
+            Command::Between { .. } => "BETWEEN",
             Command::Count => "COUNT",
+            Command::Statistic { kind, .. } => kind.name(),
             Command::Change { .. } => "CHANGE",
             Command::Literal { .. } => "LITERAL",
             Command::Upper => "UPPER",
             Command::Lower => "LOWER",
             Command::Reverse => "REVERSE",
+            Command::Pad { .. } => "PAD",
+            Command::Trunc { .. } => "TRUNC",
+            Command::Squish => "SQUISH",
+            Command::Xlate { .. } => "XLATE",
+            Command::ReformatDate { .. } => "REFORMAT DATE",
+            Command::FromCsv { .. } => "FROM CSV",
+            Command::ToCsv { .. } => "TO CSV",
             Command::Duplicate { .. } => "DUPLICATE",
+            Command::Unique { .. } => "UNIQUE",
+            Command::Sort { .. } => "SORT",
+            Command::Recno { .. } => "RECNO",
+            Command::Sample { .. } => "SAMPLE",
             Command::Hole => "HOLE",
+            Command::Fanin => "FANIN",
+            Command::Faninany => "FANINANY",
+            Command::Delay { .. } => "DELAY",
+            Command::Profile { .. } => "PROFILE",
+            Command::Stamp { .. } => "STAMP",
+            Command::Plugin { .. } => "PLUGIN",
+            Command::Encrypt { .. } => "ENCRYPT",
+            Command::Decrypt { .. } => "DECRYPT",
+            Command::Convert { .. } => "CONVERT",
+            Command::Specs { .. } => "SPECS",
+            Command::Split { .. } => "SPLIT",
+            Command::FileIn { .. } => "<",
+            Command::FileOut { append: false, .. } => ">",
+            Command::FileOut { append: true, .. } => ">>",
+        }
+    }
+}
+
+/// Renders the command as canonical DSL text (without the `|` prefix).
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::FilterEq { pos, len, value } => {
+                write!(f, "FILTER {pos},{len} = {}", delimit_with(value, '"'))
+            }
+            Command::FilterNe { pos, len, value } => {
+                write!(f, "FILTER {pos},{len} != {}", delimit_with(value, '"'))
+            }
+            Command::FilterZoned {
+                pos,
+                len,
+                op,
+                value,
+            } => write!(f, "FILTER {pos},{len} ZD {} {value}", op.symbol()),
+            Command::FilterExpr { condition } => write!(f, "FILTER {condition}"),
+            Command::Select { fields } | Command::Overlay { fields } => {
+                let specs: Vec<String> = fields
+                    .iter()
+                    .map(|(src, len, dest)| format!("{src},{len},{dest}"))
+                    .collect();
+                write!(f, "{} {}", self.name(), specs.join("; "))
+            }
+            Command::Take { n } => write!(f, "TAKE {n}"),
+            Command::Skip { n } => write!(f, "SKIP {n}"),
+            Command::Pad { len, fill: ' ' } => write!(f, "PAD {len}"),
+            Command::Pad { len, fill } => write!(f, "PAD {len} {fill}"),
+            Command::Trunc { len } => write!(f, "TRUNC {len}"),
+            Command::Xlate { spec } => {
+                write!(f, "XLATE")?;
+                if let Some((pos, len)) = spec.field {
+                    write!(f, " {pos},{len}")?;
+                }
+                match &spec.source {
+                    TableSource::Identity => {}
+                    TableSource::Upper => write!(f, " UPPER")?,
+                    TableSource::Lower => write!(f, " LOWER")?,
+                    TableSource::File(path) => write!(f, " FILE {path}")?,
+                }
+                for &(from, to) in &spec.pairs {
+                    write!(f, " {} {}", xlate_byte(from), xlate_byte(to))?;
+                }
+                Ok(())
+            }
+            Command::ReformatDate { spec } => {
+                write!(f, "REFORMAT DATE {} {} TO {}", spec.pos, spec.from, spec.to)?;
+                if let Some(dest) = spec.dest {
+                    write!(f, " {dest}")?;
+                }
+                Ok(())
+            }
+            Command::FromCsv { spec } | Command::ToCsv { spec } => {
+                write!(f, "{}", self.name())?;
+                if spec.header {
+                    write!(f, " HEADER")?;
+                }
+                let fields = spec.layout.to_string();
+                write!(f, " {}", fields.trim_start_matches("LAYOUT "))
+            }
+            Command::Dump {
+                format: DumpFormat::Hex,
+            } => write!(f, "DUMP"),
+            Command::Dump {
+                format: DumpFormat::Ruler,
+            } => write!(f, "DUMP RULER"),
+            Command::TakeLast { n } | Command::DropLast { n } => {
+                write!(f, "{} LAST {n}", self.name())
+            }
+            Command::Locate { pattern, field } | Command::Nlocate { pattern, field } => {
+                write!(f, "{}", self.name())?;
+                if let Some((pos, len)) = field {
+                    write!(f, " {pos},{len}")?;
+                }
+                write!(f, " {}", delimit_with(pattern, '/'))
+            }
+            Command::Between {
+                start,
+                end,
+                field,
+                inclusive,
+            } => {
+                write!(f, "BETWEEN")?;
+                if let Some((pos, len)) = field {
+                    write!(f, " {pos},{len}")?;
+                }
+                write!(
+                    f,
+                    " {} {}",
+                    delimit_with(start, '/'),
+                    delimit_with(end, '/')
+                )?;
+                if !inclusive {
+                    write!(f, " EXCLUSIVE")?;
+                }
+                Ok(())
+            }
+            Command::Change {
+                old,
+                new,
+                field,
+                count,
+            } => {
+                write!(f, "CHANGE")?;
+                if let Some((pos, len)) = field {
+                    write!(f, " {pos},{len}")?;
+                }
+                let delim = pick_delimiter(&[old, new], '"');
+                write!(f, " {delim}{old}{delim} {delim}{new}{delim}")?;
+                if let Some(n) = count {
+                    write!(f, " {n}")?;
+                }
+                Ok(())
+            }
+            Command::Statistic { kind, pos, len } => write!(f, "{} {pos},{len}", kind.name()),
+            Command::Literal { text } => write!(f, "LITERAL {text}"),
+            Command::Duplicate { n } => write!(f, "DUPLICATE {n}"),
+            Command::Unique { field: None } => write!(f, "UNIQUE"),
+            Command::Unique {
+                field: Some((pos, len)),
+            } => write!(f, "UNIQUE {pos},{len}"),
+            Command::Sort { keys, sum } => {
+                write!(f, "SORT")?;
+                for (idx, key) in keys.iter().enumerate() {
+                    let sep = if idx == 0 { " " } else { "; " };
+                    write!(f, "{sep}{},{}", key.start, key.len)?;
+                    if key.order == SortOrder::Descending {
+                        write!(f, " D")?;
+                    }
+                    if key.format == KeyFormat::Numeric {
+                        write!(f, " NUM")?;
+                    }
+                }
+                match sum.as_deref() {
+                    None => Ok(()),
+                    Some([]) => write!(f, " SUM NONE"),
+                    Some(fields) => {
+                        for (idx, field) in fields.iter().enumerate() {
+                            let sep = if idx == 0 { " SUM " } else { "; " };
+                            write!(f, "{sep}{},{}", field.start, field.len)?;
+                            if field.format == SumFormat::Zoned {
+                                write!(f, " ZD")?;
+                            }
+                        }
+                        Ok(())
+                    }
+                }
+            }
+            Command::Recno { ranges } => {
+                let items: Vec<String> = ranges
+                    .iter()
+                    .map(|range| {
+                        let mut item = match range.last {
+                            Some(last) if last == range.first && range.step == 1 => {
+                                last.to_string()
+                            }
+                            Some(last) => format!("{}-{last}", range.first),
+                            None => format!("{}-*", range.first),
+                        };
+                        if range.step > 1 {
+                            item.push_str(&format!(" BY {}", range.step));
+                        }
+                        item
+                    })
+                    .collect();
+                write!(f, "RECNO {}", items.join(", "))
+            }
+            Command::Sample { fraction, seed: 0 } => write!(f, "SAMPLE {fraction}"),
+            Command::Sample { fraction, seed } => write!(f, "SAMPLE {fraction} SEED {seed}"),
+            Command::Delay { ms } => write!(f, "DELAY {ms} MS"),
+            Command::Profile { fields } => {
+                write!(f, "PROFILE")?;
+                for (idx, (pos, len, field_type)) in fields.iter().enumerate() {
+                    let sep = if idx == 0 { " " } else { "; " };
+                    write!(f, "{sep}{pos},{len} {}", field_type.name())?;
+                }
+                Ok(())
+            }
+            Command::Stamp { header, fields } => {
+                write!(f, "STAMP")?;
+                if *header {
+                    write!(f, " HEADER")?;
+                }
+                for (idx, (pos, len, value)) in fields.iter().enumerate() {
+                    let sep = if idx == 0 { " " } else { "; " };
+                    match value {
+                        StampValue::Var(name) => write!(f, "{sep}{pos},{len} {name}")?,
+                        StampValue::Text(text) => {
+                            write!(f, "{sep}{pos},{len} {}", delimit_with(text, '"'))?
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Command::Plugin { name, args } if args.is_empty() => write!(f, "PLUGIN {name}"),
+            Command::Plugin { name, args } => write!(f, "PLUGIN {name} {args}"),
+            Command::Encrypt { spec } | Command::Decrypt { spec } => {
+                let (pos, len) = spec.field;
+                write!(f, "{} {pos},{len}", self.name())?;
+                if spec.dest != spec.field {
+                    write!(f, " TO {},{}", spec.dest.0, spec.dest.1)?;
+                }
+                write!(f, " {}", spec.encoding.name())?;
+                match &spec.key {
+                    KeySource::Env(name) if name == crypto::DEFAULT_KEY_ENV => Ok(()),
+                    KeySource::Env(name) => write!(f, " KEYENV {name}"),
+                    KeySource::File(path) => write!(f, " KEYFILE {path}"),
+                    KeySource::Hex(hex) => write!(f, " KEYHEX {hex}"),
+                }
+            }
+            Command::Convert { to, code_page } => {
+                write!(f, "CONVERT {} {}", to.name(), code_page.name())
+            }
+            Command::Specs { items } => {
+                write!(f, "SPECS")?;
+                for item in items {
+                    match &item.input {
+                        SpecInput::Range(pos, len) => write!(f, " {pos},{len}")?,
+                        SpecInput::Literal(text) => write!(f, " {}", delimit_with(text, '/'))?,
+                        SpecInput::Recno => write!(f, " RECNO")?,
+                    }
+                    if let Some(conversion) = item.conversion {
+                        write!(f, " {}", conversion.name())?;
+                    }
+                    match item.placement {
+                        Placement::Column(col) => write!(f, " {col}")?,
+                        Placement::Next => write!(f, " NEXT")?,
+                        Placement::NextWord => write!(f, " NEXTWORD")?,
+                    }
+                    if let Some(width) = item.width {
+                        write!(f, ".{width}")?;
+                    }
+                }
+                Ok(())
+            }
+            Command::Split {
+                name,
+                condition,
+                build,
+            } => {
+                write!(f, "SPLIT {name}")?;
+                if let Some(condition) = condition {
+                    let text = condition.to_string();
+                    write!(f, " IF {}", text.strip_prefix("FILTER ").unwrap_or(&text))?;
+                }
+                if let Some(fields) = build {
+                    let text = Command::Select {
+                        fields: fields.clone(),
+                    }
+                    .to_string();
+                    write!(
+                        f,
+                        " BUILD {}",
+                        text.strip_prefix("SELECT ").unwrap_or(&text)
+                    )?;
+                }
+                Ok(())
+            }
+            Command::FileIn { path } | Command::FileOut { path, .. } => {
+                write!(f, "{} {path}", self.name())
+            }
+            Command::Console
+            | Command::Count
+            | Command::Upper
+            | Command::Lower
+            | Command::Reverse
+            | Command::Squish
+            | Command::Hole
+            | Command::Fanin
+            | Command::Faninany => write!(f, "{}", self.name()),
         }
     }
 }
 
-/// Parse DSL text into commands.
+/// Choose a delimiter that does not occur in any of `parts`.
+fn pick_delimiter(parts: &[&str], preferred: char) -> char {
+    std::iter::once(preferred)
+        .chain("/\"'.:,;!%".chars())
+        .find(|d| parts.iter().all(|p| !p.contains(*d)))
+        .unwrap_or(preferred)
+}
+
+/// Wrap `value` in a delimiter that does not occur in it.
+pub(crate) fn delimit_with(value: &str, preferred: char) -> String {
+    let delim = pick_delimiter(&[value], preferred);
+    format!("{delim}{value}{delim}")
+}
+
+/// Parse DSL text into the commands of the main pipeline (see
+/// [`PipelinePlan::segments`] for the segments after it).
 pub fn parse_commands(text: &str) -> Result<Vec<Command>, String> {
-    let mut commands = Vec::new();
+    parse_pipeline(text).map(PipelinePlan::into_commands)
+}
+
+/// Returns the record length set by the pipeline's `LRECL` line (80 if
+/// there is none).
+pub fn pipeline_lrecl(text: &str) -> Result<usize, String> {
+    parse_pipeline(text).map(|plan| plan.lrecl())
+}
+
+/// Parses pipeline text into commands, checking that a source stage
+/// (CONSOLE, LITERAL, HOLE, or `<`) comes first and at least one stage follows.
+///
+/// See [`PipelinePlan::parse`] to keep the record length as well.
+pub fn parse(text: &str) -> Result<Vec<Command>, String> {
+    PipelinePlan::parse(text).map(PipelinePlan::into_commands)
+}
+
+/// Returns the fields declared by the pipeline's `LAYOUT` lines.
+pub fn pipeline_layout(text: &str) -> Result<RecordLayout, String> {
+    parse_pipeline_layout(text).map(|(_, layout)| layout)
+}
+
+/// Parse pipeline text into an unvalidated plan: the main pipeline, any
+/// segments after `?`, stage labels, and the record length.
+pub(crate) fn parse_pipeline(text: &str) -> Result<PipelinePlan, String> {
+    parse_pipeline_layout(text).map(|(plan, _)| plan)
+}
+
+/// Parse pipeline text like [`parse_pipeline`], also returning the layout
+/// its `LAYOUT` lines declare.
+fn parse_pipeline_layout(text: &str) -> Result<(PipelinePlan, RecordLayout), String> {
+    parse_pipeline_spanned(text).map_err(|d| d.to_string())
+}
+
+/// Parse pipeline text like [`parse_pipeline_layout`], locating errors at
+/// the stage they are in.
+pub(crate) fn parse_pipeline_spanned(
+    text: &str,
+) -> Result<(PipelinePlan, RecordLayout), Diagnostic> {
+    let mut plan = PipelinePlan::empty();
+    let mut layout = RecordLayout::new();
+    let mut lrecl_set = false;
+    let mut rc_set = false;
+    // Set by `?`: the next stage starts a segment
+    let mut ended = false;
 
     // Normalize: split each source line on '|' so that both multi-line and
-    // single-line pipeline definitions work.  We keep track of the original
-    // line number for error messages.
+    // single-line pipeline definitions work, and before each `?` that
+    // starts a segment.  We keep track of the original line number for
+    // error messages.
+    let lines: Vec<&str> = text.lines().collect();
     let mut segments: Vec<(usize, &str)> = Vec::new();
-    for (line_num, line) in text.lines().enumerate() {
+    for (line_num, line) in lines.iter().enumerate() {
         for part in line.split('|') {
-            segments.push((line_num, part));
+            segments.extend(split_at_segments(part).into_iter().map(|p| (line_num, p)));
         }
     }
 
@@ -408,6 +1976,15 @@ pub fn parse_commands(text: &str) -> Result<Vec<Command>, String> {
             continue;
         }
 
+        // A leading ? ends the pipeline before it
+        let segment = match segment.strip_prefix('?') {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                ended = !plan.commands().is_empty();
+                rest.trim()
+            }
+            _ => segment,
+        };
+
         // Handle "PIPE COMMAND" - extract command after PIPE
         let segment = if segment.to_uppercase().starts_with("PIPE ") {
             segment[5..].trim()
@@ -419,42 +1996,327 @@ pub fn parse_commands(text: &str) -> Result<Vec<Command>, String> {
         };
 
         // Remove trailing ? (explicit end of pipeline)
+        let ends = segment.ends_with('?');
         let segment = segment.trim_end_matches('?').trim();
 
         // Skip if segment is now empty
         if segment.is_empty() {
+            ended |= ends && !plan.commands().is_empty();
+            continue;
+        }
+
+        let span = Span::within(line_num + 1, lines[line_num], segment);
+        let at_line = |e: String| Diagnostic::error(e).at(span);
+        let keyword = segment.split_whitespace().next().unwrap_or_default();
+
+        // LRECL sets the record length; it must come before anything that
+        // refers to columns
+        if keyword.eq_ignore_ascii_case("LRECL") {
+            if lrecl_set || !layout.is_empty() || !plan.commands().is_empty() {
+                return Err(at_line(
+                    "LRECL must come once, before LAYOUT and the stages".to_string(),
+                ));
+            }
+            layout = RecordLayout::with_lrecl(parse_lrecl(segment).map_err(at_line)?);
+            plan = plan.with_lrecl(layout.lrecl());
+            lrecl_set = true;
+            continue;
+        }
+
+        // RC sets the condition code thresholds; like LRECL it is not a stage
+        if keyword.eq_ignore_ascii_case("RC") {
+            if rc_set {
+                return Err(at_line("RC must come only once".to_string()));
+            }
+            plan = plan.with_condition_codes(parse_rc(segment).map_err(at_line)?);
+            rc_set = true;
+            continue;
+        }
+
+        // LAYOUT declares field names for later stages; it is not a stage
+        if keyword.eq_ignore_ascii_case("LAYOUT") {
+            extend_layout(&mut layout, segment).map_err(at_line)?;
             continue;
         }
 
-        let cmd = parse_command(segment).map_err(|e| format!("Line {}: {}", line_num + 1, e))?;
-        commands.push(cmd);
+        // A label names the stage after it (`a: LOCATE /X/`); after `?`,
+        // a label alone starts a segment reading that stage's secondary
+        // output, and at the end of a segment it feeds the segment's
+        // records to that stage's secondary input
+        let (label, segment) = match keyword.strip_suffix(':') {
+            Some(name) if is_label(name) => {
+                (Some(name.to_string()), segment[keyword.len()..].trim())
+            }
+            _ => (None, segment),
+        };
+        if ended {
+            match label {
+                Some(name) if segment.is_empty() => {
+                    plan.push_segment(name);
+                    ended = ends;
+                    continue;
+                }
+                None if segment.starts_with('<') => {
+                    if let Command::FileIn { path } = parse_file(segment).map_err(at_line)? {
+                        plan.push_file_segment(path);
+                        ended = ends;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            return Err(at_line(
+                "a pipeline after ? must start with a label alone (? a:) or < name".to_string(),
+            ));
+        }
+        if plan.segment_ended() {
+            return Err(at_line(
+                "a segment ends at a label alone (| f:); start another with ?".to_string(),
+            ));
+        }
+        if segment.is_empty() {
+            let name = label.unwrap_or_default();
+            if plan.end_segment(name.clone()) {
+                ended = ends;
+                continue;
+            }
+            return Err(at_line(format!("Label '{name}:' needs a stage")));
+        }
+
+        let cmd = parse_command(segment, &layout).map_err(at_line)?;
+        plan.push(cmd, label);
+        ended = ends;
+    }
+
+    ebcdic::validate(plan.commands()).map_err(Diagnostic::error)?;
+    Ok((plan, layout))
+}
+
+/// Splits a `|`-separated part of a line before each `?` that ends one
+/// pipeline and starts a segment: a `?` word followed by a label (`a:`),
+/// `<`, `PIPE`, or nothing. Other `?`s, as in `LOCATE / ? /`, are left alone.
+pub(crate) fn split_at_segments(part: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (idx, _) in part.match_indices('?') {
+        let before = &part[..idx];
+        let after = &part[idx + 1..];
+        let word = before.is_empty() || before.ends_with(char::is_whitespace);
+        let next = after.split_whitespace().next();
+        let starts_segment = next.is_some_and(|w| {
+            w.eq_ignore_ascii_case("PIPE")
+                || w.starts_with('<')
+                || w.strip_suffix(':').is_some_and(is_label)
+        });
+        if idx > start && word && after.starts_with(char::is_whitespace) && starts_segment {
+            parts.push(&part[start..=idx]);
+            start = idx;
+        }
+    }
+    parts.push(&part[start..]);
+    parts
+}
+
+/// Parse an `LRECL n` line.
+fn parse_lrecl(line: &str) -> Result<usize, String> {
+    let mut words = line.split_whitespace().skip(1);
+    let lrecl = words
+        .next()
+        .and_then(|w| w.parse::<usize>().ok())
+        .filter(|n| (1..=MAX_LRECL).contains(n))
+        .ok_or(format!(
+            "LRECL requires a record length from 1 to {MAX_LRECL}"
+        ))?;
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in LRECL", word));
+    }
+    Ok(lrecl)
+}
+
+/// Parse an `RC [WARN n] [ERROR n] [FATAL n]` line.
+fn parse_rc(line: &str) -> Result<ConditionCodes, String> {
+    let words: Vec<&str> = line.split_whitespace().skip(1).collect();
+    if words.is_empty() {
+        return Err("RC requires WARN n, ERROR n, or FATAL n".to_string());
+    }
+    let mut codes = ConditionCodes::default();
+    for pair in words.chunks(2) {
+        let count = pair
+            .get(1)
+            .and_then(|w| w.parse::<usize>().ok())
+            .filter(|n| *n >= 1)
+            .ok_or(format!(
+                "RC {} requires a count of at least 1",
+                pair[0].to_uppercase()
+            ));
+        match pair[0].to_uppercase().as_str() {
+            "WARN" => codes.warn = count?,
+            "ERROR" => codes.error = count?,
+            "FATAL" => codes.fatal = Some(count?),
+            _ => return Err(format!("Unexpected '{}' in RC", pair[0])),
+        }
+    }
+    Ok(codes)
+}
+
+/// Add the fields of a `LAYOUT` line to the layout declared so far.
+fn extend_layout(layout: &mut RecordLayout, line: &str) -> Result<(), String> {
+    let declared = RecordLayout::parse_lrecl(line, layout.lrecl()).map_err(|e| e.to_string())?;
+    for field in declared.fields() {
+        if find_field(field.name.as_str(), layout).is_some() {
+            return Err(format!(
+                "invalid layout: field {} is defined more than once",
+                field.name
+            ));
+        }
+        layout
+            .add(field.name.clone(), field.start, field.len, field.field_type)
+            .map_err(|e| e.to_string())?;
     }
+    layout.check_overlaps().map_err(|e| e.to_string())
+}
+
+/// Find a LAYOUT field by name, ignoring case.
+fn find_field<'a>(name: &str, layout: &'a RecordLayout) -> Option<&'a LayoutField> {
+    layout
+        .fields()
+        .iter()
+        .find(|f| f.name.eq_ignore_ascii_case(name))
+}
 
-    Ok(commands)
+/// Resolve a LAYOUT field name used in place of `pos,len`.
+/// Returns `Ok(None)` if `word` is not a name (it does not start with a letter).
+fn layout_field(word: &str, layout: &RecordLayout) -> Result<Option<(usize, usize)>, String> {
+    if !word.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Ok(None);
+    }
+    match find_field(word, layout) {
+        Some(field) => Ok(Some((field.start, field.len))),
+        None if layout.is_empty() => Err(format!("Unknown field '{}' (no LAYOUT declared)", word)),
+        None => {
+            let names: Vec<&str> = layout.fields().iter().map(|f| f.name.as_str()).collect();
+            Err(format!(
+                "Unknown field '{}' (LAYOUT defines {})",
+                word,
+                names.join(", ")
+            ))
+        }
+    }
 }
 
-/// Parse a single command line.
-fn parse_command(line: &str) -> Result<Command, String> {
+/// Each stage keyword [`parse_command`] accepts, with its syntax, for
+/// diagnostics.
+pub(crate) const STAGE_SYNTAX: &[(&str, &str)] = &[
+    ("<", "< path"),
+    (">", "> path"),
+    (">>", ">> path"),
+    ("AVG", "AVG pos,len"),
+    ("BETWEEN", "BETWEEN [pos,len] /start/ /end/ [EXCLUSIVE]"),
+    ("CHANGE", "CHANGE [pos,len] /old/new/ [n]"),
+    ("CONSOLE", "CONSOLE"),
+    ("CONVERT", "CONVERT ASCII|EBCDIC [code page]"),
+    ("COUNT", "COUNT"),
+    ("DECRYPT", "DECRYPT pos,len [TO pos,len] [HEX|BASE64] [key]"),
+    ("DELAY", "DELAY n [MS]"),
+    ("DROP", "DROP LAST n"),
+    ("DUMP", "DUMP [HEX|RULER]"),
+    ("DUPLICATE", "DUPLICATE [n]"),
+    ("ENCRYPT", "ENCRYPT pos,len [TO pos,len] [HEX|BASE64] [key]"),
+    ("FANIN", "FANIN"),
+    ("FANINANY", "FANINANY"),
+    (
+        "FILTER",
+        "FILTER pos,len = \"value\" (or != < <= > >=, NUM or ZD n, joined by AND, OR)",
+    ),
+    ("FROM", "FROM CSV [HEADER] [fields]"),
+    ("HOLE", "HOLE"),
+    ("LITERAL", "LITERAL text"),
+    ("LOCATE", "LOCATE [pos,len] /pattern/"),
+    ("LOWER", "LOWER"),
+    ("MAX", "MAX pos,len"),
+    ("MIN", "MIN pos,len"),
+    ("NLOCATE", "NLOCATE [pos,len] /pattern/"),
+    ("OVERLAY", "OVERLAY src,len,dest; ..."),
+    ("PAD", "PAD n [c]"),
+    ("PLUGIN", "PLUGIN name [args]"),
+    ("PROFILE", "PROFILE [pos,len [type]; ...]"),
+    ("RECNO", "RECNO n-m [BY s], ..."),
+    ("REFORMAT", "REFORMAT DATE pos fmt TO fmt [dest]"),
+    ("REVERSE", "REVERSE"),
+    ("SAMPLE", "SAMPLE fraction [SEED n]"),
+    ("SELECT", "SELECT src,len,dest; ..."),
+    ("SKIP", "SKIP n"),
+    (
+        "SORT",
+        "SORT [pos,len [A|D] [CH|NUM]; ...] [SUM pos,len [ZD]; ... | SUM NONE]",
+    ),
+    ("SPECS", "SPECS input [conversion] output ..."),
+    (
+        "SPLIT",
+        "SPLIT name [IF condition] [BUILD src,len,dest; ...]",
+    ),
+    ("SQUISH", "SQUISH"),
+    ("STAMP", "STAMP [HEADER] pos,len VAR|\"text\"; ..."),
+    ("SUM", "SUM pos,len"),
+    ("TAKE", "TAKE n | TAKE LAST n"),
+    ("TO", "TO CSV [HEADER] [fields]"),
+    ("TRUNC", "TRUNC n"),
+    ("UNIQUE", "UNIQUE [pos,len]"),
+    ("UPPER", "UPPER"),
+    (
+        "XLATE",
+        "XLATE [pos,len] [UPPER|LOWER|FILE path] [from to ...]",
+    ),
+];
+
+/// Parse a single command line, resolving field names against `layout`.
+pub(crate) fn parse_command(line: &str, layout: &RecordLayout) -> Result<Command, String> {
     let upper = line.to_uppercase();
 
     if upper == "CONSOLE" || upper.starts_with("CONSOLE ") {
         Ok(Command::Console)
+    } else if line.starts_with('<') || line.starts_with('>') {
+        parse_file(line)
+    } else if upper == "DUMP" || upper.starts_with("DUMP ") {
+        parse_dump(line)
     } else if upper.starts_with("FILTER") {
-        parse_filter(line)
+        parse_filter(line, layout)
     } else if upper.starts_with("SELECT") {
-        parse_select(line)
+        parse_select(line, "SELECT", layout)
+    } else if upper == "SORT" || upper.starts_with("SORT ") {
+        parse_sort(line, layout)
+    } else if upper == "OVERLAY" || upper.starts_with("OVERLAY ") {
+        parse_select(line, "OVERLAY", layout)
     } else if upper.starts_with("TAKE") {
         parse_take(line)
+    } else if upper == "DROP" || upper.starts_with("DROP ") {
+        parse_drop(line)
     } else if upper.starts_with("SKIP") {
         parse_skip(line)
+    } else if upper == "RECNO" || upper.starts_with("RECNO ") {
+        parse_recno(line)
+    } else if upper == "SAMPLE" || upper.starts_with("SAMPLE ") {
+        parse_sample(line)
     } else if upper.starts_with("NLOCATE") {
-        parse_nlocate(line)
+        parse_nlocate(line, layout)
     } else if upper.starts_with("LOCATE") {
-        parse_locate(line)
+        parse_locate(line, layout)
+    } else if upper == "BETWEEN" || upper.starts_with("BETWEEN ") {
+        parse_between(line, layout)
     } else if upper == "COUNT" || upper.starts_with("COUNT ") {
         Ok(Command::Count)
+    } else if let Some(kind) = [
+        AggregateKind::Sum,
+        AggregateKind::Min,
+        AggregateKind::Max,
+        AggregateKind::Avg,
+    ]
+    .into_iter()
+    .find(|kind| upper == kind.name() || upper.starts_with(&format!("{} ", kind.name())))
+    {
+        parse_statistic(line, kind, layout)
     } else if upper.starts_with("CHANGE") {
-        parse_change(line)
+        parse_change(line, layout)
     } else if upper.starts_with("LITERAL") {
         parse_literal(line)
     } else if upper == "UPPER" || upper.starts_with("UPPER ") {
@@ -463,10 +2325,48 @@ fn parse_command(line: &str) -> Result<Command, String> {
         Ok(Command::Lower)
     } else if upper == "REVERSE" || upper.starts_with("REVERSE ") {
         Ok(Command::Reverse)
+    } else if upper == "PAD" || upper.starts_with("PAD ") {
+        parse_pad(line)
+    } else if upper == "TRUNC" || upper.starts_with("TRUNC ") {
+        parse_trunc(line)
+    } else if upper == "SQUISH" || upper.starts_with("SQUISH ") {
+        Ok(Command::Squish)
+    } else if upper == "XLATE" || upper.starts_with("XLATE ") {
+        parse_xlate(line, layout)
+    } else if upper == "REFORMAT" || upper.starts_with("REFORMAT ") {
+        parse_reformat(line, layout)
+    } else if upper == "FROM" || upper.starts_with("FROM ") {
+        parse_csv(line, "FROM", layout)
+    } else if upper == "TO" || upper.starts_with("TO ") {
+        parse_csv(line, "TO", layout)
     } else if upper.starts_with("DUPLICATE") {
         parse_duplicate(line)
+    } else if upper == "UNIQUE" || upper.starts_with("UNIQUE ") {
+        parse_unique(line, layout)
     } else if upper == "HOLE" || upper.starts_with("HOLE ") {
         Ok(Command::Hole)
+    } else if upper == "FANINANY" || upper.starts_with("FANINANY ") {
+        Ok(Command::Faninany)
+    } else if upper == "FANIN" || upper.starts_with("FANIN ") {
+        Ok(Command::Fanin)
+    } else if upper == "DELAY" || upper.starts_with("DELAY ") {
+        parse_delay(line)
+    } else if upper == "PROFILE" || upper.starts_with("PROFILE ") {
+        parse_profile(line, layout)
+    } else if upper == "STAMP" || upper.starts_with("STAMP ") {
+        parse_stamp(line, layout)
+    } else if upper == "PLUGIN" || upper.starts_with("PLUGIN ") {
+        parse_plugin(line)
+    } else if upper == "ENCRYPT" || upper.starts_with("ENCRYPT ") {
+        parse_crypt(line, "ENCRYPT", layout)
+    } else if upper == "DECRYPT" || upper.starts_with("DECRYPT ") {
+        parse_crypt(line, "DECRYPT", layout)
+    } else if upper == "CONVERT" || upper.starts_with("CONVERT ") {
+        parse_convert(line)
+    } else if upper == "SPECS" || upper.starts_with("SPECS ") {
+        parse_specs(line, layout)
+    } else if upper == "SPLIT" || upper.starts_with("SPLIT ") {
+        parse_split(line, layout)
     } else {
         Err(format!(
             "Unknown command: {}",
@@ -476,52 +2376,238 @@ fn parse_command(line: &str) -> Result<Command, String> {
 }
 
 /// Parse FILTER command.
-fn parse_filter(line: &str) -> Result<Command, String> {
-    // FILTER pos,len = "value" or FILTER pos,len != "value"
-    let rest = line[6..].trim(); // Skip "FILTER"
-
-    // Find the operator
-    let (field_part, op, value) = if let Some(idx) = rest.find("!=") {
-        let field_part = rest[..idx].trim();
-        let value_part = rest[idx + 2..].trim();
-        (field_part, "!=", value_part)
-    } else if let Some(idx) = rest.find('=') {
-        let field_part = rest[..idx].trim();
-        let value_part = rest[idx + 1..].trim();
-        (field_part, "=", value_part)
-    } else {
-        return Err("FILTER requires = or != operator".to_string());
+/// Format: FILTER comparison [AND|OR comparison ...], with parentheses for
+/// grouping; AND binds tighter than OR. A comparison is
+/// `field [NUM|ZD] op value`: pos,len or a LAYOUT field name, one of
+/// = != < <= > >=, and a delimited string, or a whole number after NUM or
+/// ZD. `field ~ /pattern/` matches a regular expression (with the `regex`
+/// feature). A lone `=` or `!=` text comparison, or a lone ZD comparison, keeps
+/// its own command.
+fn parse_filter(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    parse_condition(&line[6..], layout).map(Command::filter) // Skip "FILTER"
+}
+
+/// Parses the condition of a FILTER, such as `18,10 = "SALES" AND 28,8 NUM
+/// > 50000`, naming fields by position or by the layout's field names.
+pub fn parse_condition(text: &str, layout: &RecordLayout) -> Result<Condition, String> {
+    let mut parser = FilterParser {
+        rest: text.trim(),
+        layout,
     };
+    let condition = parser.or()?;
+    if let Some(word) = parser.rest.split_whitespace().next() {
+        return Err(format!("Unexpected '{}' in FILTER", word));
+    }
+    Ok(condition)
+}
 
-    // Parse pos,len
-    let parts: Vec<&str> = field_part.split(',').collect();
-    if parts.len() != 2 {
-        return Err("FILTER requires pos,len before operator".to_string());
+impl Command {
+    /// Builds the FILTER stage for `condition`, in the simple form a single
+    /// text or zoned decimal comparison parses to.
+    pub fn filter(condition: Condition) -> Command {
+        match condition {
+            Condition::Text {
+                pos,
+                len,
+                op: CompareOp::Eq,
+                value,
+            } => Command::FilterEq { pos, len, value },
+            Condition::Text {
+                pos,
+                len,
+                op: CompareOp::Ne,
+                value,
+            } => Command::FilterNe { pos, len, value },
+            Condition::Number {
+                pos,
+                len,
+                format: NumberFormat::Zoned,
+                op,
+                value,
+            } => Command::FilterZoned {
+                pos,
+                len,
+                op,
+                value,
+            },
+            condition => Command::FilterExpr { condition },
+        }
     }
+}
 
-    let pos: usize = parts[0]
-        .trim()
-        .parse()
-        .map_err(|_| "Invalid position number")?;
-    let len: usize = parts[1]
-        .trim()
-        .parse()
-        .map_err(|_| "Invalid length number")?;
+/// Recursive-descent parser for the condition of a FILTER.
+struct FilterParser<'a> {
+    rest: &'a str,
+    layout: &'a RecordLayout,
+}
+
+impl<'a> FilterParser<'a> {
+    /// Consume `keyword` (any case) if it is the next word.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let rest = self.rest.trim_start();
+        let found = rest
+            .get(..keyword.len())
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+            && rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| c.is_whitespace() || "()=!<>~".contains(c));
+        if found {
+            self.rest = &rest[keyword.len()..];
+        }
+        found
+    }
 
-    // Parse quoted value
-    let value = parse_quoted_string(value)?;
+    /// Take the next word, which ends at a blank, a parenthesis, or an
+    /// operator symbol.
+    fn word(&mut self) -> &'a str {
+        let rest = self.rest.trim_start();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "()=!<>~".contains(c))
+            .unwrap_or(rest.len());
+        self.rest = &rest[end..];
+        &rest[..end]
+    }
 
-    if op == "!=" {
-        Ok(Command::FilterNe { pos, len, value })
-    } else {
-        Ok(Command::FilterEq { pos, len, value })
+    /// condition := and-term [OR and-term ...]
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.keyword("OR") {
+            condition = condition.or(self.and()?);
+        }
+        Ok(condition)
+    }
+
+    /// and-term := factor [AND factor ...]
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.factor()?;
+        while self.keyword("AND") {
+            condition = condition.and(self.factor()?);
+        }
+        Ok(condition)
+    }
+
+    /// factor := ( condition ) | comparison
+    fn factor(&mut self) -> Result<Condition, String> {
+        match self.rest.trim_start().strip_prefix('(') {
+            Some(inner) => {
+                self.rest = inner;
+                let condition = self.or()?;
+                self.rest = self
+                    .rest
+                    .trim_start()
+                    .strip_prefix(')')
+                    .ok_or("FILTER has '(' without a matching ')'")?;
+                Ok(condition)
+            }
+            None => self.comparison(),
+        }
+    }
+
+    /// comparison := field [NUM|ZD] op value
+    fn comparison(&mut self) -> Result<Condition, String> {
+        let field = self.word();
+        if field.is_empty() {
+            return Err("FILTER requires pos,len before operator".to_string());
+        }
+        let format = if self.keyword("NUM") {
+            Some(NumberFormat::Plain)
+        } else if self.keyword("ZD") || self.keyword("ZONED") {
+            Some(NumberFormat::Zoned)
+        } else {
+            None
+        };
+        let rest = self.rest.trim_start();
+        let end = rest
+            .find(|c: char| !"=!<>~".contains(c))
+            .unwrap_or(rest.len());
+        let symbol = &rest[..end];
+        let op = CompareOp::from_symbol(symbol);
+        self.rest = &rest[end..];
+
+        let Some(format) = format else {
+            if symbol == "~" {
+                let (pos, len) = self.text_field(field)?;
+                let (pattern, rest) = parse_delimited_string(self.rest)?;
+                self.rest = rest;
+                return regex_condition(pos, len, &pattern);
+            }
+            let op = op.ok_or("FILTER requires an operator (=, !=, <, <=, >, >=, ~)")?;
+            let (pos, len) = self.text_field(field)?;
+            let (value, rest) = parse_delimited_string(self.rest)?;
+            self.rest = rest;
+            return Ok(Condition::text(pos, len, op, &value));
+        };
+
+        let mode = match format {
+            NumberFormat::Plain => "NUM",
+            NumberFormat::Zoned => "ZD",
+        };
+        let (pos, len) = parse_range(Some(field), "FILTER", self.layout)?;
+        let op =
+            op.ok_or_else(|| format!("FILTER {mode} requires an operator (=, !=, <, <=, >, >=)"))?;
+        let value: i64 = self
+            .word()
+            .parse()
+            .map_err(|_| format!("FILTER {mode} requires a whole number to compare with"))?;
+        Ok(match format {
+            NumberFormat::Plain => Condition::number(pos, len, op, value),
+            NumberFormat::Zoned => Condition::zoned(pos, len, op, value),
+        })
+    }
+
+    /// Parse pos,len or a LAYOUT field name for a text comparison.
+    fn text_field(&self, field: &str) -> Result<(usize, usize), String> {
+        if let Some(field) = layout_field(field, self.layout)? {
+            return Ok(field);
+        }
+        let parts: Vec<&str> = field.split(',').collect();
+        if parts.len() != 2 {
+            return Err("FILTER requires pos,len before operator".to_string());
+        }
+        let pos: usize = parts[0]
+            .trim()
+            .parse()
+            .map_err(|_| "Invalid position number")?;
+        let len: usize = parts[1]
+            .trim()
+            .parse()
+            .map_err(|_| "Invalid length number")?;
+        Ok((pos, len))
+    }
+}
+
+/// Parse SUM, MIN, MAX, or AVG.
+/// Format: SUM pos,len (or a LAYOUT field name)
+fn parse_statistic(
+    line: &str,
+    kind: AggregateKind,
+    layout: &RecordLayout,
+) -> Result<Command, String> {
+    let mut words = line[kind.name().len()..].split_whitespace();
+    let (pos, len) = parse_range(words.next(), kind.name(), layout)?;
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in {}", word, kind.name()));
     }
+    Ok(Command::Statistic { kind, pos, len })
 }
 
-/// Parse SELECT command.
-fn parse_select(line: &str) -> Result<Command, String> {
-    // SELECT p1,l1,d1; p2,l2,d2; ...
-    let rest = line[6..].trim(); // Skip "SELECT"
+/// Compile the pattern of a `FILTER pos,len ~ /pattern/` comparison.
+#[cfg(feature = "regex")]
+fn regex_condition(pos: usize, len: usize, pattern: &str) -> Result<Condition, String> {
+    let regex = regex::Regex::new(pattern).map_err(|e| format!("Invalid FILTER pattern: {e}"))?;
+    Ok(Condition::regex(pos, len, regex))
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex_condition(_pos: usize, _len: usize, _pattern: &str) -> Result<Condition, String> {
+    Err("FILTER ~ needs pipelines-rs built with the `regex` feature".to_string())
+}
+
+/// Parse SELECT or OVERLAY command.
+fn parse_select(line: &str, verb: &str, layout: &RecordLayout) -> Result<Command, String> {
+    // SELECT p1,l1,d1; p2,l2,d2; ... (or NAME,d for a LAYOUT field)
+    let rest = line[verb.len()..].trim();
 
     let mut fields = Vec::new();
 
@@ -532,10 +2618,24 @@ fn parse_select(line: &str) -> Result<Command, String> {
         }
 
         let parts: Vec<&str> = field_spec.split(',').collect();
+        if let Some((src_pos, len)) = layout_field(parts[0].trim(), layout)? {
+            if parts.len() != 2 {
+                return Err(format!(
+                    "{} field '{}' requires NAME,dest_pos",
+                    verb, field_spec
+                ));
+            }
+            let dest_pos: usize = parts[1]
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid destination position in '{}'", field_spec))?;
+            fields.push((src_pos, len, dest_pos));
+            continue;
+        }
         if parts.len() != 3 {
             return Err(format!(
-                "SELECT field '{}' requires src_pos,len,dest_pos",
-                field_spec
+                "{} field '{}' requires src_pos,len,dest_pos",
+                verb, field_spec
             ));
         }
 
@@ -556,19 +2656,45 @@ fn parse_select(line: &str) -> Result<Command, String> {
     }
 
     if fields.is_empty() {
-        return Err("SELECT requires at least one field specification".to_string());
+        return Err(format!(
+            "{} requires at least one field specification",
+            verb
+        ));
     }
 
-    Ok(Command::Select { fields })
+    if verb == "OVERLAY" {
+        Ok(Command::Overlay { fields })
+    } else {
+        Ok(Command::Select { fields })
+    }
 }
 
 /// Parse TAKE command.
 fn parse_take(line: &str) -> Result<Command, String> {
     let rest = line[4..].trim(); // Skip "TAKE"
+    if let Some(n) = strip_last(rest) {
+        let n: usize = n.parse().map_err(|_| "TAKE LAST requires a number")?;
+        return Ok(Command::TakeLast { n });
+    }
     let n: usize = rest.parse().map_err(|_| "TAKE requires a number")?;
     Ok(Command::Take { n })
 }
 
+/// Parse DROP LAST command (SKIP drops the first records).
+fn parse_drop(line: &str) -> Result<Command, String> {
+    let rest = line[4..].trim(); // Skip "DROP"
+    let n =
+        strip_last(rest).ok_or("DROP requires LAST n (use SKIP n to drop the first records)")?;
+    let n: usize = n.parse().map_err(|_| "DROP LAST requires a number")?;
+    Ok(Command::DropLast { n })
+}
+
+/// Returns the operand after a leading `LAST` keyword, if there is one.
+fn strip_last(rest: &str) -> Option<&str> {
+    let (word, n) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    word.eq_ignore_ascii_case("LAST").then(|| n.trim())
+}
+
 /// Parse SKIP command.
 fn parse_skip(line: &str) -> Result<Command, String> {
     let rest = line[4..].trim(); // Skip "SKIP"
@@ -576,6 +2702,73 @@ fn parse_skip(line: &str) -> Result<Command, String> {
     Ok(Command::Skip { n })
 }
 
+/// Parse RECNO command.
+/// Format: RECNO n[-m|-*] [BY s], ...
+fn parse_recno(line: &str) -> Result<Command, String> {
+    let rest = line[5..].trim(); // Skip "RECNO"
+    let number = |word: &str| match word.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "Invalid record number '{word}' in RECNO (records count from 1)"
+        )),
+    };
+
+    let mut ranges = Vec::new();
+    for item in rest.split(',') {
+        let mut words = item.split_whitespace();
+        let Some(word) = words.next() else {
+            return Err("RECNO requires record numbers, e.g. RECNO 1-10, 50".to_string());
+        };
+        let mut range = match word.split_once('-') {
+            None => RecnoRange::single(number(word)?),
+            Some((first, "*")) => RecnoRange::starting(number(first)?),
+            Some((first, last)) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if last < first {
+                    return Err(format!("RECNO range '{word}' ends before it starts"));
+                }
+                RecnoRange::new(first, last)
+            }
+        };
+        match (words.next(), words.next(), words.next()) {
+            (None, _, _) => {}
+            (Some(by), Some(step), None) if by.eq_ignore_ascii_case("BY") => {
+                let step: usize =
+                    step.parse().ok().filter(|&s| s > 0).ok_or_else(|| {
+                        format!("RECNO BY requires a positive step, got '{step}'")
+                    })?;
+                range = range.by(step);
+            }
+            _ => return Err(format!("Unexpected '{}' in RECNO", item.trim())),
+        }
+        ranges.push(range);
+    }
+    Ok(Command::Recno { ranges })
+}
+
+/// Parse SAMPLE command.
+/// Format: SAMPLE fraction [SEED n]
+/// The seed defaults to 0, so a pipeline keeps the same records each run.
+fn parse_sample(line: &str) -> Result<Command, String> {
+    let mut words = line[6..].split_whitespace(); // Skip "SAMPLE"
+    let fraction: f64 = words
+        .next()
+        .and_then(|w| w.parse().ok())
+        .filter(|f| (0.0..=1.0).contains(f))
+        .ok_or("SAMPLE requires a fraction from 0 to 1, e.g. SAMPLE 0.01")?;
+    let seed = match (words.next(), words.next()) {
+        (None, _) => 0,
+        (Some(word), Some(seed)) if word.eq_ignore_ascii_case("SEED") => seed
+            .parse()
+            .map_err(|_| format!("Invalid SAMPLE seed '{seed}'"))?,
+        (Some(word), _) => return Err(format!("Unexpected '{word}' in SAMPLE")),
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{word}' in SAMPLE"));
+    }
+    Ok(Command::Sample { fraction, seed })
+}
+
 /// Parse a delimited string using CMS Pipelines convention.
 /// The first non-blank character is the delimiter, and the string
 /// continues until the next occurrence of that delimiter.
@@ -600,12 +2793,6 @@ fn parse_delimited_string(s: &str) -> Result<(String, &str), String> {
     }
 }
 
-/// Parse a quoted string value (legacy helper, delegates to parse_delimited_string).
-fn parse_quoted_string(s: &str) -> Result<String, String> {
-    let (result, _) = parse_delimited_string(s)?;
-    Ok(result)
-}
-
 /// Parse LOCATE command.
 /// CMS Pipelines: Uses first non-blank char as delimiter.
 /// Formats:
@@ -613,13 +2800,25 @@ fn parse_quoted_string(s: &str) -> Result<String, String> {
 ///   LOCATE "pattern"       - search entire record (" is delimiter)
 ///   LOCATE .pattern.       - search entire record (. is delimiter)
 ///   LOCATE pos,len /pattern/ - search specific field
-fn parse_locate(line: &str) -> Result<Command, String> {
+///   LOCATE NAME /pattern/    - search a LAYOUT field
+fn parse_locate(line: &str, layout: &RecordLayout) -> Result<Command, String> {
     let rest = line[6..].trim(); // Skip "LOCATE"
 
     if rest.is_empty() {
         return Err("LOCATE requires a pattern".to_string());
     }
 
+    // A leading LAYOUT field name selects the field to search
+    if let Some((name, pattern_part)) = rest.split_once(char::is_whitespace)
+        && let Some(field) = find_field(name, layout)
+    {
+        let (pattern, _) = parse_delimited_string(pattern_part)?;
+        return Ok(Command::Locate {
+            pattern,
+            field: Some((field.start, field.len)),
+        });
+    }
+
     // If first char is a digit, parse field spec first
     if rest.chars().next().unwrap().is_ascii_digit() {
         // Find where the field spec ends (after the comma and second number)
@@ -659,13 +2858,24 @@ fn parse_locate(line: &str) -> Result<Command, String> {
 
 /// Parse NLOCATE command.
 /// CMS Pipelines: Uses first non-blank char as delimiter (same as LOCATE).
-fn parse_nlocate(line: &str) -> Result<Command, String> {
+fn parse_nlocate(line: &str, layout: &RecordLayout) -> Result<Command, String> {
     let rest = line[7..].trim(); // Skip "NLOCATE"
 
     if rest.is_empty() {
         return Err("NLOCATE requires a pattern".to_string());
     }
 
+    // A leading LAYOUT field name selects the field to search
+    if let Some((name, pattern_part)) = rest.split_once(char::is_whitespace)
+        && let Some(field) = find_field(name, layout)
+    {
+        let (pattern, _) = parse_delimited_string(pattern_part)?;
+        return Ok(Command::Nlocate {
+            pattern,
+            field: Some((field.start, field.len)),
+        });
+    }
+
     // If first char is a digit, parse field spec first
     if rest.chars().next().unwrap().is_ascii_digit() {
         let mut parts = rest.splitn(2, |c: char| !c.is_ascii_digit() && c != ',');
@@ -700,25 +2910,100 @@ fn parse_nlocate(line: &str) -> Result<Command, String> {
     }
 }
 
-/// Parse CHANGE command.
-/// CMS Pipelines: Uses first non-blank char as delimiter.
-/// Both strings must use the SAME delimiter.
-/// Format: CHANGE /old/ /new/ or CHANGE "old" "new"
-fn parse_change(line: &str) -> Result<Command, String> {
-    let rest = line[6..].trim(); // Skip "CHANGE"
+/// Parse BETWEEN command: `BETWEEN [pos,len] /start/ /end/ [INCLUSIVE|EXCLUSIVE]`.
+fn parse_between(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut rest = line[7..].trim(); // Skip "BETWEEN"
 
     if rest.is_empty() {
-        return Err("CHANGE requires two delimited strings".to_string());
+        return Err("BETWEEN requires two delimited strings".to_string());
     }
 
-    // Parse first delimited string
-    let (old, after_first) = parse_delimited_string(rest)?;
-
-    // Parse second delimited string (must use same delimiter as first)
-    let (new, _) = parse_delimited_string(after_first)?;
-
-    Ok(Command::Change { old, new })
-}
+    // An optional LAYOUT field name or pos,len restricts the search
+    let mut field = None;
+    if let Some((word, after)) = rest.split_once(char::is_whitespace)
+        && (word.starts_with(|c: char| c.is_ascii_digit()) || find_field(word, layout).is_some())
+    {
+        field = Some(parse_range(Some(word), "BETWEEN", layout)?);
+        rest = after.trim_start();
+    }
+
+    let (start, after) = parse_delimited_string(rest)?;
+    if after.trim().is_empty() {
+        return Err("BETWEEN requires an end string after the start string".to_string());
+    }
+    let (end, after) = parse_delimited_string(after)?;
+    let inclusive = match after.trim().to_uppercase().as_str() {
+        "" | "INCLUSIVE" => true,
+        "EXCLUSIVE" => false,
+        other => {
+            return Err(format!(
+                "Unexpected '{other}' in BETWEEN (use INCLUSIVE or EXCLUSIVE)"
+            ));
+        }
+    };
+
+    Ok(Command::Between {
+        start,
+        end,
+        field,
+        inclusive,
+    })
+}
+
+/// Parse CHANGE command.
+/// CMS Pipelines: Uses first non-blank char as delimiter.
+/// Both strings must use the SAME delimiter.
+/// Format: CHANGE /old/ /new/ or CHANGE "old" "new"
+fn parse_change(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut rest = line[6..].trim(); // Skip "CHANGE"
+
+    if rest.is_empty() {
+        return Err("CHANGE requires two delimited strings".to_string());
+    }
+
+    // An optional LAYOUT field name or pos,len restricts the search
+    let mut field = None;
+    if let Some((word, after)) = rest.split_once(char::is_whitespace)
+        && (word.starts_with(|c: char| c.is_ascii_digit()) || find_field(word, layout).is_some())
+    {
+        field = Some(parse_range(Some(word), "CHANGE", layout)?);
+        rest = after.trim_start();
+    }
+
+    // Parse first delimited string
+    let delim = rest.chars().next().unwrap_or('/');
+    let (old, after_first) = parse_delimited_string(rest)?;
+
+    // The second string either shares the closing delimiter (/old/new/)
+    // or is delimited on its own (/old/ /new/)
+    let (new, after) = if after_first.is_empty() || after_first.starts_with(char::is_whitespace) {
+        parse_delimited_string(after_first)?
+    } else {
+        let end = after_first
+            .find(delim)
+            .ok_or_else(|| format!("Unclosed delimiter '{}'", delim))?;
+        (
+            after_first[..end].to_string(),
+            &after_first[end + delim.len_utf8()..],
+        )
+    };
+
+    // An optional count limits the replacements per record
+    let count = match after.trim() {
+        "" => None,
+        n => match n.parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => return Err(format!("CHANGE count must be a positive number, got '{n}'")),
+        },
+    };
+
+    Ok(Command::Change {
+        old,
+        new,
+        field,
+        count,
+    })
+}
 
 /// Parse LITERAL command.
 /// CMS Pipelines: LITERAL does NOT use delimiters.
@@ -737,149 +3022,1023 @@ fn parse_literal(line: &str) -> Result<Command, String> {
 }
 
 /// Parse DUPLICATE command.
-/// Format: DUPLICATE n
+/// Format: DUPLICATE [n]
 fn parse_duplicate(line: &str) -> Result<Command, String> {
     let rest = line[9..].trim(); // Skip "DUPLICATE"
-    let n: usize = rest.parse().map_err(|_| "DUPLICATE requires a number")?;
-    if n == 0 {
-        return Err("DUPLICATE count must be at least 1".to_string());
+    if rest.is_empty() {
+        return Ok(Command::Duplicate { n: 1 });
     }
+    let n: usize = rest
+        .parse()
+        .map_err(|_| "DUPLICATE takes a number of extra copies (0 or more)")?;
     Ok(Command::Duplicate { n })
 }
 
-/// Apply commands to records.
-fn apply_commands(records: Vec<Record>, commands: &[Command]) -> Result<Vec<Record>, String> {
-    // We need to collect and re-create pipeline for each command
-    // because the Pipeline type changes with each operation
-    let mut current: Vec<Record> = records;
+/// Parse PAD command.
+/// Format: PAD n [c]
+fn parse_pad(line: &str) -> Result<Command, String> {
+    let mut words = line[3..].split_whitespace(); // Skip "PAD"
+    let len: usize = words
+        .next()
+        .and_then(|w| w.parse().ok())
+        .ok_or("PAD requires a length")?;
+    let fill = match words.next() {
+        None => ' ',
+        Some(word) => {
+            let mut chars = word.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => c,
+                _ => return Err(format!("PAD fill must be one character, got '{word}'")),
+            }
+        }
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{word}' in PAD"));
+    }
+    Ok(Command::Pad { len, fill })
+}
+
+/// Parse TRUNC command.
+fn parse_trunc(line: &str) -> Result<Command, String> {
+    let rest = line[5..].trim(); // Skip "TRUNC"
+    let len: usize = rest.parse().map_err(|_| "TRUNC requires a column number")?;
+    Ok(Command::Trunc { len })
+}
 
-    for cmd in commands {
-        current = apply_command(current, cmd)?;
+/// Parse XLATE command.
+/// Format: XLATE [pos,len] [UPPER|LOWER|FILE path] [from to ...]
+/// Each from/to is one character or two hex digits. With no table the
+/// pairs change the identity table; with neither, XLATE uppercases.
+fn parse_xlate(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut words = line[5..].split_whitespace().peekable(); // Skip "XLATE"
+    let mut spec = XlateSpec::default();
+    if let Some(&word) = words.peek()
+        && (word.contains(',') || find_field(word, layout).is_some())
+    {
+        spec.field = Some(parse_range(words.next(), "XLATE", layout)?);
+    }
+    let source = match words.peek().map(|w| w.to_uppercase()).as_deref() {
+        Some("UPPER") => Some(TableSource::Upper),
+        Some("LOWER") => Some(TableSource::Lower),
+        Some("FILE") => {
+            words.next();
+            let path = words.peek().ok_or("XLATE FILE requires a path")?;
+            Some(TableSource::File(path.to_string()))
+        }
+        _ => None,
+    };
+    if source.is_some() {
+        words.next();
     }
+    while let Some(from) = words.next() {
+        let to = words
+            .next()
+            .ok_or_else(|| format!("XLATE '{from}' needs a character to translate to"))?;
+        spec.pairs
+            .push((parse_xlate_byte(from)?, parse_xlate_byte(to)?));
+    }
+    spec.source = match source {
+        Some(source) => source,
+        None if spec.pairs.is_empty() => TableSource::Upper,
+        None => TableSource::Identity,
+    };
+    Ok(Command::Xlate { spec })
+}
 
-    Ok(current)
+/// Parse DUMP command.
+/// Format: DUMP [HEX|RULER]
+fn parse_dump(line: &str) -> Result<Command, String> {
+    let mut words = line[4..].split_whitespace(); // Skip "DUMP"
+    let format = match words.next().map(|w| w.to_uppercase()).as_deref() {
+        None | Some("HEX") => DumpFormat::Hex,
+        Some("RULER") => DumpFormat::Ruler,
+        Some(_) => return Err("DUMP format must be HEX or RULER".to_string()),
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in DUMP", word));
+    }
+    Ok(Command::Dump { format })
 }
 
-/// Apply a single command to records.
-fn apply_command(records: Vec<Record>, cmd: &Command) -> Result<Vec<Record>, String> {
-    match cmd {
-        Command::Console => {
-            // Console in the middle of pipeline just passes through
-            Ok(records)
-        }
-        Command::FilterEq { pos, len, value } => {
-            let pos = *pos;
-            let len = *len;
-            let value = value.clone();
-            Ok(Pipeline::new(records.into_iter())
-                .filter(move |r| r.field_eq(pos, len, &value))
-                .collect())
-        }
-        Command::FilterNe { pos, len, value } => {
-            let pos = *pos;
-            let len = *len;
-            let value = value.clone();
-            Ok(Pipeline::new(records.into_iter())
-                .filter(move |r| !r.field_eq(pos, len, &value))
-                .collect())
-        }
-        Command::Select { fields } => {
-            let fields = fields.clone();
-            Ok(Pipeline::new(records.into_iter()).select(fields).collect())
-        }
-        Command::Take { n } => Ok(Pipeline::new(records.into_iter()).take(*n).collect()),
-        Command::Skip { n } => Ok(Pipeline::new(records.into_iter()).skip(*n).collect()),
-        Command::Locate { pattern, field } => {
-            let pattern = pattern.clone();
-            match field {
-                Some((pos, len)) => {
-                    let pos = *pos;
-                    let len = *len;
-                    Ok(Pipeline::new(records.into_iter())
-                        .filter(move |r| r.field_contains(pos, len, &pattern))
-                        .collect())
+/// Parse REFORMAT command.
+/// Format: REFORMAT DATE pos fmt TO fmt [dest], where each fmt is
+/// YYYYMMDD, MMDDYY, or YYDDD. A LAYOUT field name may replace pos or
+/// dest; without dest the new date replaces the old one.
+fn parse_reformat(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut words = line[8..].split_whitespace(); // Skip "REFORMAT"
+    match words.next() {
+        Some(word) if word.eq_ignore_ascii_case("DATE") => {}
+        _ => return Err("REFORMAT supports only DATE".to_string()),
+    }
+    let column = |word: Option<&str>, what: &str| -> Result<usize, String> {
+        let word = word.ok_or_else(|| format!("REFORMAT DATE requires {what}"))?;
+        if let Some((pos, _)) = layout_field(word, layout)? {
+            return Ok(pos);
+        }
+        word.parse()
+            .map_err(|_| format!("REFORMAT DATE {what} '{word}' is not a column"))
+    };
+    let format = |word: Option<&str>| -> Result<DateFormat, String> {
+        word.ok_or("REFORMAT DATE requires formats, e.g. YYYYMMDD TO MMDDYY")?
+            .parse()
+            .map_err(|e: crate::PipelineError| e.to_string())
+    };
+    let pos = column(words.next(), "a position")?;
+    let from = format(words.next())?;
+    match words.next() {
+        Some(word) if word.eq_ignore_ascii_case("TO") => {}
+        _ => return Err(format!("REFORMAT DATE {from} requires TO and a format")),
+    }
+    let to = format(words.next())?;
+    let dest = match words.next() {
+        Some(word) => Some(column(Some(word), "destination")?),
+        None => None,
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in REFORMAT DATE", word));
+    }
+    let spec = DateSpec {
+        pos,
+        from,
+        to,
+        dest,
+    };
+    let end = match dest {
+        Some(dest) => (pos + from.width()).max(dest + to.width()),
+        None => pos + from.width().max(to.width()),
+    };
+    if end > layout.lrecl() {
+        return Err(format!(
+            "REFORMAT DATE dates run past the {}-byte record",
+            layout.lrecl()
+        ));
+    }
+    Ok(Command::ReformatDate { spec })
+}
+
+/// Parse FROM CSV or TO CSV command.
+/// Format: FROM CSV [HEADER] [fields], where fields are written as in a
+/// LAYOUT line; without them the fields of the LAYOUT declared so far.
+fn parse_csv(line: &str, verb: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut words = line[verb.len()..].trim().splitn(2, char::is_whitespace);
+    if !words.next().is_some_and(|w| w.eq_ignore_ascii_case("CSV")) {
+        return Err(format!("{verb} supports only CSV"));
+    }
+    let rest = words.next().unwrap_or("").trim();
+    let (header, fields) = match rest.split_once(char::is_whitespace) {
+        Some((word, fields)) if word.eq_ignore_ascii_case("HEADER") => (true, fields.trim()),
+        None if rest.eq_ignore_ascii_case("HEADER") => (true, ""),
+        _ => (false, rest),
+    };
+    let layout = if fields.is_empty() {
+        if layout.is_empty() {
+            return Err(format!(
+                "{verb} CSV requires fields or a LAYOUT declared before it"
+            ));
+        }
+        layout.clone()
+    } else {
+        let fields = RecordLayout::parse_lrecl(&format!("LAYOUT {fields}"), layout.lrecl())
+            .map_err(|e| format!("{verb} CSV: {e}"))?;
+        fields
+            .check_overlaps()
+            .map_err(|e| format!("{verb} CSV: {e}"))?;
+        fields
+    };
+    let spec = CsvSpec { layout, header };
+    Ok(match verb {
+        "FROM" => Command::FromCsv { spec },
+        _ => Command::ToCsv { spec },
+    })
+}
+
+/// Parse an XLATE character: one character, or two hex digits.
+fn parse_xlate_byte(word: &str) -> Result<u8, String> {
+    match word.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        [_, _] => u8::from_str_radix(word, 16)
+            .map_err(|_| format!("XLATE '{word}' is not one character or two hex digits")),
+        _ => Err(format!(
+            "XLATE '{word}' is not one character or two hex digits"
+        )),
+    }
+}
+
+/// Format an XLATE character the way [`parse_xlate_byte`] reads it.
+fn xlate_byte(byte: u8) -> String {
+    if byte.is_ascii_graphic() && byte != b'|' {
+        (byte as char).to_string()
+    } else {
+        format!("{byte:02X}")
+    }
+}
+
+/// Parse UNIQUE command.
+/// Format: UNIQUE [pos,len]
+fn parse_unique(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut words = line[6..].split_whitespace(); // Skip "UNIQUE"
+    let field = match words.next() {
+        Some(word) => Some(parse_range(Some(word), "UNIQUE", layout)?),
+        None => None,
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in UNIQUE", word));
+    }
+    Ok(Command::Unique { field })
+}
+
+/// Parse SORT command.
+/// Format: SORT [pos,len [A|D] [CH|NUM]; ...] [SUM pos,len [ZD]; ... | SUM NONE]
+fn parse_sort(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let rest = line[4..].trim(); // Skip "SORT"
+    let upper = rest.to_ascii_uppercase();
+    // SUM is the first word SUM, not part of a field name
+    let sum_at = upper.match_indices("SUM").map(|(at, _)| at).find(|&at| {
+        (at == 0 || upper[..at].ends_with(char::is_whitespace))
+            && upper[at + 3..]
+                .chars()
+                .next()
+                .is_none_or(char::is_whitespace)
+    });
+    let (keys_text, sum_text) = match sum_at {
+        Some(at) => (&rest[..at], Some(rest[at + 3..].trim())),
+        None => (rest, None),
+    };
+
+    let mut keys = Vec::new();
+    for key_spec in keys_text.split(';').map(str::trim) {
+        if key_spec.is_empty() {
+            continue;
+        }
+        let mut words = key_spec.split_whitespace();
+        let (pos, len) = parse_range(words.next(), "SORT", layout)?;
+        let mut key = SortKey::new(pos, len);
+        for word in words {
+            match word.to_uppercase().as_str() {
+                "A" => {}
+                "D" => key = key.descending(),
+                "CH" => {}
+                "NUM" => key = key.numeric(),
+                _ => {
+                    return Err(format!(
+                        "Unknown SORT option '{}' (expected A, D, CH, or NUM)",
+                        word
+                    ));
+                }
+            }
+        }
+        keys.push(key);
+    }
+
+    let sum = match sum_text {
+        None => None,
+        Some("") => return Err("SUM requires pos,len fields or NONE".to_string()),
+        Some(none) if none.eq_ignore_ascii_case("NONE") => Some(Vec::new()),
+        Some(fields_text) => {
+            let mut fields = Vec::new();
+            for field_spec in fields_text.split(';').map(str::trim) {
+                if field_spec.is_empty() {
+                    continue;
                 }
-                None => {
-                    // Search entire record
-                    Ok(Pipeline::new(records.into_iter())
-                        .filter(move |r| r.as_str().contains(pattern.as_str()))
-                        .collect())
+                let mut words = field_spec.split_whitespace();
+                let (pos, len) = parse_range(words.next(), "SUM", layout)?;
+                let field = match words.next().map(|w| w.to_uppercase()).as_deref() {
+                    None | Some("NUM") => SumField::new(pos, len),
+                    Some("ZD") => SumField::new(pos, len).zoned(),
+                    Some(other) => {
+                        return Err(format!(
+                            "Unknown SUM format '{}' (expected NUM or ZD)",
+                            other
+                        ));
+                    }
+                };
+                if let Some(word) = words.next() {
+                    return Err(format!("Unexpected '{}' in SORT SUM", word));
                 }
+                fields.push(field);
             }
+            Some(fields)
+        }
+    };
+    Ok(Command::Sort { keys, sum })
+}
+
+/// Creates the sort a SORT stage runs: on `keys`, or on the whole
+/// `lrecl`-byte record if there are none.
+pub fn sort_for(keys: &[SortKey], lrecl: usize) -> Sort {
+    if keys.is_empty() {
+        Sort::new(vec![SortKey::new(0, lrecl)])
+    } else {
+        Sort::new(keys.to_vec())
+    }
+}
+
+/// Streams the records of `sort` in order as its runs are merged,
+/// ending early with the error of a work file that cannot be read, which
+/// goes to `fail`. With `sum`, the records with each key collapse into
+/// the first, totalling the `sum` fields (dropping the rest if there are
+/// none).
+pub fn sorted_records<'a>(
+    sort: &mut Sort,
+    sum: Option<&[SumField]>,
+    mut fail: impl FnMut(String) + 'a,
+) -> Box<dyn Iterator<Item = Record> + 'a> {
+    let fields: Vec<(usize, usize)> = sort.keys().iter().map(|k| (k.start, k.len)).collect();
+    let sorted = match sort.sorted() {
+        Ok(sorted) => Pipeline::new(sorted.map_while(move |record| {
+            record
+                .map_err(|e| fail(format!("SORT work file: {e}")))
+                .ok()
+        })),
+        Err(e) => {
+            fail(format!("SORT work file: {e}"));
+            return Box::new(std::iter::empty());
+        }
+    };
+    match sum {
+        None => Box::new(sorted),
+        Some([]) => Box::new(sorted.stage(Dedup::new(fields))),
+        Some(sums) => Box::new(sorted.stage(SumBy::new(fields, sums.to_vec()))),
+    }
+}
+
+/// Runs a SORT stage on a stream of records, reading them all when its
+/// first record is pulled. Past [`SORT_MEMORY`](crate::SORT_MEMORY) the
+/// records are spilled to sorted runs in temporary files; an error
+/// writing or reading one ends the output and is kept in `failure`.
+fn sort_stream<'a>(
+    mut records: Records<'a>,
+    keys: &[SortKey],
+    sum: Option<&'a [SumField]>,
+    lrecl: usize,
+    failure: &'a RefCell<Option<String>>,
+) -> Records<'a> {
+    let fail = move |e: String| {
+        failure.borrow_mut().get_or_insert(e);
+    };
+    let mut sort = sort_for(keys, lrecl);
+    Box::new(std::iter::once(()).flat_map(move |()| -> Records<'a> {
+        if let Err(e) = records.try_for_each(|record| sort.push(record)) {
+            fail(format!("SORT work file: {e}"));
+            return Box::new(std::iter::empty());
+        }
+        sorted_records(&mut sort, sum, fail)
+    }))
+}
+
+/// Parse DELAY command.
+/// Format: DELAY n [MS]
+fn parse_delay(line: &str) -> Result<Command, String> {
+    let rest = line[5..].trim(); // Skip "DELAY"
+    let mut words = rest.split_whitespace();
+    let ms: u64 = words
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or("DELAY requires a number of milliseconds")?;
+    match words.next() {
+        None => {}
+        Some(unit) if unit.eq_ignore_ascii_case("MS") => {}
+        Some(unit) => return Err(format!("Unknown DELAY unit '{}' (expected MS)", unit)),
+    }
+    Ok(Command::Delay { ms })
+}
+
+/// Parse PROFILE command.
+/// Format: PROFILE [pos,len [CHAR|NUMERIC|DATE]; ...]
+/// A LAYOUT field name may replace pos,len; its type is the default.
+fn parse_profile(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let rest = line[7..].trim(); // Skip "PROFILE"
+
+    let mut fields = Vec::new();
+    for field_spec in rest.split(';') {
+        let field_spec = field_spec.trim();
+        if field_spec.is_empty() {
+            continue;
         }
-        Command::Nlocate { pattern, field } => {
-            let pattern = pattern.clone();
-            match field {
-                Some((pos, len)) => {
-                    let pos = *pos;
-                    let len = *len;
-                    Ok(Pipeline::new(records.into_iter())
-                        .filter(move |r| !r.field_contains(pos, len, &pattern))
-                        .collect())
+
+        let mut words = field_spec.split_whitespace();
+        let range = words.next().unwrap_or("");
+        let (pos, len, declared) = match layout_field(range, layout)? {
+            Some((pos, len)) => (pos, len, find_field(range, layout).map(|f| f.field_type)),
+            None => {
+                let parts: Vec<&str> = range.split(',').collect();
+                if parts.len() != 2 {
+                    return Err(format!("PROFILE field '{}' requires pos,len", field_spec));
+                }
+                let pos: usize = parts[0]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid position in '{}'", field_spec))?;
+                let len: usize = parts[1]
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid length in '{}'", field_spec))?;
+                if len == 0 || pos + len > layout.lrecl() {
+                    return Err(format!(
+                        "PROFILE field '{}' is outside the {}-byte record",
+                        field_spec,
+                        layout.lrecl()
+                    ));
+                }
+                (pos, len, None)
+            }
+        };
+
+        let field_type = match words.next().map(|w| w.to_uppercase()).as_deref() {
+            None => declared.unwrap_or(FieldType::Char),
+            Some("CHAR") => FieldType::Char,
+            Some("NUMERIC") => FieldType::Numeric,
+            Some("DATE") => FieldType::Date,
+            Some(other) => {
+                return Err(format!(
+                    "Unknown PROFILE field type '{}' (expected CHAR, NUMERIC, or DATE)",
+                    other
+                ));
+            }
+        };
+        fields.push((pos, len, field_type));
+    }
+
+    Ok(Command::Profile { fields })
+}
+
+/// Parse STAMP command.
+/// Format: STAMP [HEADER] pos,len VAR; pos,len "text"; ...
+/// A value starting with a letter is a host variable name; anything else
+/// is a delimited string written as-is.
+fn parse_stamp(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut rest = line[5..].trim(); // Skip "STAMP"
+
+    let header = rest
+        .split_whitespace()
+        .next()
+        .is_some_and(|w| w.eq_ignore_ascii_case("HEADER"));
+    if header {
+        rest = rest[6..].trim();
+    }
+
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let (range, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let (pos, len) = parse_range(Some(range), "STAMP", layout)?;
+
+        let after = after.trim_start();
+        let (value, after) = match after.chars().next() {
+            None => return Err(format!("STAMP field '{}' requires a value", range)),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let end = after.find([' ', '\t', ';']).unwrap_or(after.len());
+                let name = normalize_var_name(&after[..end]);
+                if !is_valid_name(&name) {
+                    return Err(format!(
+                        "Unknown host variable '{}' (expected DATE, TIME, TIMESTAMP, STEP, ENV.name, or PARM.name)",
+                        &after[..end]
+                    ));
                 }
-                None => Ok(Pipeline::new(records.into_iter())
-                    .filter(move |r| !r.as_str().contains(pattern.as_str()))
-                    .collect()),
-            }
-        }
-        Command::Count => {
-            // Count records and emit a single summary record
-            let count = records.len();
-            Ok(vec![Record::from_str(&count.to_string())])
-        }
-        Command::Change { old, new } => {
-            // Replace all occurrences of old with new in each record
-            let old = old.clone();
-            let new = new.clone();
-            Ok(Pipeline::new(records.into_iter())
-                .map(move |r| {
-                    let content = r.as_str().replace(&old, &new);
-                    Record::from_str(&content)
-                })
-                .collect())
+                (StampValue::Var(name), &after[end..])
+            }
+            Some(_) => {
+                let (text, after) = parse_delimited_string(after)?;
+                (StampValue::Text(text), after)
+            }
+        };
+        fields.push((pos, len, value));
+
+        let after = after.trim_start();
+        rest = match after.strip_prefix(';') {
+            Some(next) => next.trim_start(),
+            None if after.is_empty() => after,
+            None => return Err(format!("Expected ';' before '{}'", after)),
+        };
+    }
+
+    if fields.is_empty() {
+        return Err("STAMP requires at least one pos,len value".to_string());
+    }
+    Ok(Command::Stamp { header, fields })
+}
+
+/// Parse PLUGIN command.
+/// Format: PLUGIN name [args]
+/// The arguments are passed to the plugin's factory as written.
+fn parse_plugin(line: &str) -> Result<Command, String> {
+    let rest = line[6..].trim(); // Skip "PLUGIN"
+    let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if name.is_empty() {
+        return Err("PLUGIN requires a stage name".to_string());
+    }
+    Ok(Command::Plugin {
+        name: name.to_uppercase(),
+        args: args.trim().to_string(),
+    })
+}
+
+/// Parse ENCRYPT or DECRYPT command.
+/// Format: ENCRYPT pos,len [TO pos,len] [HEX|BASE64] [KEYENV name | KEYFILE path | KEYHEX hex]
+/// The output field defaults to the input field; the key defaults to
+/// the PIPELINES_KEY environment variable.
+fn parse_crypt(line: &str, verb: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let mut words = line[verb.len()..].split_whitespace();
+
+    let field = parse_range(words.next(), verb, layout)?;
+    let mut spec = CryptSpec::new(field, field, Encoding::Hex);
+    while let Some(word) = words.next() {
+        match word.to_uppercase().as_str() {
+            "TO" => spec.dest = parse_range(words.next(), verb, layout)?,
+            "HEX" => spec.encoding = Encoding::Hex,
+            "BASE64" => spec.encoding = Encoding::Base64,
+            keyword @ ("KEYENV" | "KEYFILE" | "KEYHEX") => {
+                let value = words
+                    .next()
+                    .ok_or_else(|| format!("{} requires a value", keyword))?
+                    .to_string();
+                spec.key = match keyword {
+                    "KEYENV" => KeySource::Env(value),
+                    "KEYFILE" => KeySource::File(value),
+                    _ => KeySource::Hex(value),
+                };
+            }
+            _ => return Err(format!("Unexpected '{}' in {}", word, verb)),
+        }
+    }
+
+    if verb == "ENCRYPT" {
+        spec.check_encrypt_width()?;
+        Ok(Command::Encrypt { spec })
+    } else {
+        Ok(Command::Decrypt { spec })
+    }
+}
+
+/// Parse CONVERT command.
+/// Format: CONVERT ASCII|EBCDIC [CP037|CP1047]
+/// The code page defaults to CP037.
+fn parse_convert(line: &str) -> Result<Command, String> {
+    let mut words = line[7..].split_whitespace(); // Skip "CONVERT"
+    let to = match words.next().map(|w| w.to_uppercase()).as_deref() {
+        Some("ASCII") => Charset::Ascii,
+        Some("EBCDIC") => Charset::Ebcdic,
+        _ => return Err("CONVERT requires ASCII or EBCDIC".to_string()),
+    };
+    let code_page = match words.next() {
+        None => CodePage::default(),
+        Some(word) => CodePage::from_name(word)
+            .ok_or_else(|| format!("Unknown code page '{}' (expected CP037 or CP1047)", word))?,
+    };
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in CONVERT", word));
+    }
+    Ok(Command::Convert { to, code_page })
+}
+
+/// Parse SPECS command.
+/// Format: SPECS input [conversion] output ...
+/// An input is pos,len, a LAYOUT field, a delimited literal, or RECNO; a
+/// conversion is ZEROPAD, UNPAD, UPPER, LOWER, or STRIP; an output is a column,
+/// NEXT, or NEXTWORD, optionally followed by `.width`.
+fn parse_specs(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    fn next_word(s: &str) -> (&str, &str) {
+        let s = s.trim_start();
+        s.split_once(char::is_whitespace)
+            .map_or((s, ""), |(word, rest)| (word, rest.trim_start()))
+    }
+
+    let mut rest = line[5..].trim_start(); // Skip "SPECS"
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        let input = if rest.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            let (word, after) = next_word(rest);
+            rest = after;
+            if word.eq_ignore_ascii_case("RECNO") {
+                SpecInput::Recno
+            } else {
+                let (pos, len) = parse_range(Some(word), "SPECS", layout)?;
+                SpecInput::Range(pos, len)
+            }
+        } else {
+            let (text, after) = parse_delimited_string(rest)?;
+            rest = after;
+            SpecInput::Literal(text)
+        };
+
+        let (mut word, after) = next_word(rest);
+        rest = after;
+        if let Some(e) = binary_conversion_error(word) {
+            return Err(e);
+        }
+        let conversion = Conversion::from_name(word);
+        if conversion.is_some() {
+            (word, rest) = next_word(rest);
+        }
+        if word.is_empty() {
+            return Err("SPECS input has no output column (n, NEXT, or NEXTWORD)".to_string());
+        }
+
+        let (place, width) = match word.split_once('.') {
+            Some((place, width)) => match width.parse::<usize>() {
+                Ok(width) if width > 0 => (place, Some(width)),
+                _ => return Err(format!("Invalid SPECS width in '{}'", word)),
+            },
+            None => (word, None),
+        };
+        let placement = if place.eq_ignore_ascii_case("NEXT") {
+            Placement::Next
+        } else if place.eq_ignore_ascii_case("NEXTWORD") {
+            Placement::NextWord
+        } else {
+            let col: usize = place.parse().map_err(|_| {
+                format!(
+                    "Invalid SPECS output '{}' (expected n, NEXT, or NEXTWORD)",
+                    word
+                )
+            })?;
+            if col >= layout.lrecl() {
+                return Err(format!(
+                    "SPECS column {} is outside the {}-byte record",
+                    col,
+                    layout.lrecl()
+                ));
+            }
+            Placement::Column(col)
+        };
+
+        items.push(SpecItem {
+            input,
+            conversion,
+            placement,
+            width,
+        });
+    }
+    if items.is_empty() {
+        return Err("SPECS requires an input and an output column".to_string());
+    }
+    Ok(Command::Specs { items })
+}
+
+/// Parse a file stage.
+/// Format: < path, > path, or >> path
+fn parse_file(line: &str) -> Result<Command, String> {
+    let (verb, rest) = if let Some(rest) = line.strip_prefix(">>") {
+        (">>", rest)
+    } else {
+        line.split_at(1)
+    };
+    let mut words = rest.split_whitespace();
+    let path = words
+        .next()
+        .ok_or_else(|| format!("{verb} requires a file name"))?
+        .to_string();
+    if let Some(word) = words.next() {
+        return Err(format!("Unexpected '{}' in {verb}", word));
+    }
+    Ok(match verb {
+        "<" => Command::FileIn { path },
+        _ => Command::FileOut {
+            path,
+            append: verb == ">>",
+        },
+    })
+}
+
+/// Parse SPLIT command.
+/// Format: SPLIT name [IF condition] [BUILD src,len,dest; ...]
+/// The condition is what FILTER takes; BUILD is what SELECT takes.
+fn parse_split(line: &str, layout: &RecordLayout) -> Result<Command, String> {
+    let rest = line[5..].trim(); // Skip "SPLIT"
+    let (name, mut rest) = rest
+        .split_once(char::is_whitespace)
+        .map_or((rest, ""), |(name, rest)| (name, rest.trim()));
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("SPLIT requires an output name (letters, digits, _ and -)".to_string());
+    }
+
+    // BUILD is last, so its keyword is the last word of that spelling
+    let upper = rest.to_uppercase();
+    let build_at = upper.rmatch_indices("BUILD").map(|(i, _)| i).find(|&i| {
+        (i == 0 || upper[..i].ends_with(char::is_whitespace))
+            && upper[i + 5..].starts_with(char::is_whitespace)
+    });
+    let build = match build_at {
+        Some(i) => {
+            let spec = &rest[i + 5..];
+            rest = rest[..i].trim();
+            match parse_select(&format!("SELECT {spec}"), "SELECT", layout)? {
+                Command::Select { fields } => Some(fields),
+                _ => unreachable!("parse_select returns SELECT"),
+            }
+        }
+        None => None,
+    };
+
+    let condition = if rest.is_empty() {
+        None
+    } else if rest.len() > 3 && rest.get(..3).is_some_and(|w| w.eq_ignore_ascii_case("IF ")) {
+        Some(Box::new(parse_filter(
+            &format!("FILTER {}", rest[3..].trim()),
+            layout,
+        )?))
+    } else {
+        return Err(format!(
+            "Unexpected '{}' in SPLIT (expected IF or BUILD)",
+            rest.split_whitespace().next().unwrap_or(rest)
+        ));
+    };
+
+    Ok(Command::Split {
+        name: name.to_uppercase(),
+        condition,
+        build,
+    })
+}
+
+/// Parse a `pos,len` range that must lie within the record, or a LAYOUT field name.
+fn parse_range(
+    word: Option<&str>,
+    verb: &str,
+    layout: &RecordLayout,
+) -> Result<(usize, usize), String> {
+    let word = word.ok_or_else(|| format!("{} requires pos,len", verb))?;
+    if let Some(field) = layout_field(word, layout)? {
+        return Ok(field);
+    }
+    let (pos, len) = word
+        .split_once(',')
+        .ok_or_else(|| format!("{} field '{}' requires pos,len", verb, word))?;
+    let pos: usize = pos
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid position in '{}'", word))?;
+    let len: usize = len
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid length in '{}'", word))?;
+    if len == 0 || pos + len > layout.lrecl() {
+        return Err(format!(
+            "{} field '{}' is outside the {}-byte record",
+            verb,
+            word,
+            layout.lrecl()
+        ));
+    }
+    Ok((pos, len))
+}
+
+/// Uppercase a host variable name, keeping the case of ENV and PARM names.
+fn normalize_var_name(name: &str) -> String {
+    match name.split_once('.') {
+        Some((prefix, rest)) => format!("{}.{}", prefix.to_uppercase(), rest),
+        None => name.to_uppercase(),
+    }
+}
+
+/// Records passing between the stages of a streamed run.
+type Records<'a> = Box<dyn Iterator<Item = Record> + 'a>;
+
+/// The test a FILTER command applies to a record.
+type FilterTest<'a> = Box<dyn Fn(&Record) -> bool + 'a>;
+
+/// Apply a single command to `lrecl`-byte records, adding any records
+/// it copies to named outputs or writes to files, and any error records,
+/// to `side`.
+fn apply_command(
+    records: Vec<Record>,
+    cmd: &Command,
+    vars: &HostVars,
+    lrecl: usize,
+    side: &RunSide,
+) -> Result<Vec<Record>, String> {
+    let records = stream_command(Box::new(records.into_iter()), cmd, vars, lrecl, side)?;
+    let records = records.collect();
+    match side.failure.take() {
+        Some(e) => Err(e),
+        None => Ok(records),
+    }
+}
+
+/// Apply a single command to a stream of `lrecl`-byte records, returning
+/// its output stream. Records pass through as they are pulled; stages that
+/// need all their input (SORT, COUNT, TAKE LAST) emit at its end.
+fn stream_command<'a>(
+    records: Records<'a>,
+    cmd: &'a Command,
+    vars: &'a HostVars,
+    lrecl: usize,
+    side: &'a RunSide,
+) -> Result<Records<'a>, String> {
+    let RunSide {
+        errors, failure, ..
+    } = side;
+    if let Some(test) = filter_test(cmd) {
+        return Ok(Box::new(records.filter(move |r| {
+            if has_bad_number(cmd, r) {
+                errors.set(errors.get() + 1);
+            }
+            test(r)
+        })));
+    }
+    let pipeline = Pipeline::new(records);
+    Ok(match cmd {
+        // Console in the middle of pipeline echoes records to stderr and
+        // passes them through
+        Command::Console => Box::new(pipeline.stage(Console::new())),
+        Command::Dump { format } => Box::new(pipeline.stage(Dump::new(*format))),
+        Command::FilterEq { .. }
+        | Command::FilterNe { .. }
+        | Command::FilterZoned { .. }
+        | Command::FilterExpr { .. } => unreachable!("FILTER is applied above"),
+        Command::Select { fields } => Box::new(pipeline.select(fields.clone())),
+        Command::Overlay { fields } => Box::new(pipeline.overlay(fields.clone())),
+        // Later records are still read, so the stages before TAKE see
+        // them all
+        Command::Take { n } => {
+            let mut seen = 0;
+            Box::new(pipeline.filter(move |_| {
+                seen += 1;
+                seen <= *n
+            }))
+        }
+        Command::Skip { n } => Box::new(pipeline.skip(*n)),
+        Command::TakeLast { n } => Box::new(pipeline.take_last(*n)),
+        Command::DropLast { n } => Box::new(pipeline.drop_last(*n)),
+        Command::Locate { pattern, field } | Command::Nlocate { pattern, field } => {
+            let mut locate = match cmd {
+                Command::Locate { .. } => Locate::new(pattern),
+                _ => Locate::nlocate(pattern),
+            };
+            if let Some((pos, len)) = *field {
+                locate = locate.within(pos, len);
+            }
+            Box::new(pipeline.stage(locate))
         }
+        Command::Between {
+            start,
+            end,
+            field,
+            inclusive,
+        } => Box::new(pipeline.stage(between_stage(start, end, *field, *inclusive))),
+        // Count records and emit a single summary record
+        Command::Count => Box::new(pipeline.stage(Total::new(Aggregate::count(), lrecl))),
+        // Blank fields and values that are not numbers are error records
+        Command::Statistic { kind, pos, len } => Box::new(pipeline.stage(
+            Total::new(Aggregate::new(*kind, *pos, *len), lrecl).rejects(ErrorCount(errors)),
+        )),
+        Command::Change {
+            old,
+            new,
+            field,
+            count,
+        } => {
+            let mut change = Change::new(old, new);
+            if let Some((pos, len)) = *field {
+                change = change.within(pos, len);
+            }
+            if let Some(n) = *count {
+                change = change.count(n);
+            }
+            Box::new(pipeline.stage(change))
+        }
+        // CMS Pipelines: LITERAL is a "prefix" filter. It outputs its
+        // literal text FIRST, then passes through all input records.
         Command::Literal { text } => {
-            // CMS Pipelines: LITERAL is a "prefix" filter.
-            // It outputs its literal text FIRST, then passes through all input records.
-            let mut result = vec![Record::from_str(text)];
-            result.extend(records);
-            Ok(result)
-        }
-        Command::Upper => {
-            // Convert all records to uppercase
-            Ok(Pipeline::new(records.into_iter())
-                .map(|r| Record::from_str(&r.as_str().to_uppercase()))
-                .collect())
-        }
-        Command::Lower => {
-            // Convert all records to lowercase
-            Ok(Pipeline::new(records.into_iter())
-                .map(|r| Record::from_str(&r.as_str().to_lowercase()))
-                .collect())
-        }
-        Command::Reverse => {
-            // Reverse characters in each record (trim first to avoid reversing trailing spaces)
-            Ok(Pipeline::new(records.into_iter())
-                .map(|r| {
-                    let reversed: String = r.as_str().trim_end().chars().rev().collect();
-                    Record::from_str(&reversed)
-                })
-                .collect())
-        }
-        Command::Duplicate { n } => {
-            // Repeat each record n times
-            let n = *n;
-            Ok(records
-                .into_iter()
-                .flat_map(|r| std::iter::repeat_n(r, n))
-                .collect())
-        }
-        Command::Hole => {
-            // Discard all input records, output nothing (like /dev/null)
-            // Consume records but produce empty output
-            drop(records);
-            Ok(vec![])
+            Box::new(pipeline.literal(Record::from_str_lrecl(text, lrecl)))
+        }
+        Command::Upper => Box::new(
+            pipeline.map(|r| Record::from_str_lrecl(&r.as_str().to_uppercase(), r.lrecl())),
+        ),
+        Command::Lower => Box::new(
+            pipeline.map(|r| Record::from_str_lrecl(&r.as_str().to_lowercase(), r.lrecl())),
+        ),
+        // Reverse characters in each record (trim first to avoid reversing
+        // trailing spaces)
+        Command::Reverse => Box::new(pipeline.map(|r| {
+            let reversed: String = r.as_str().trim_end().chars().rev().collect();
+            Record::from_str_lrecl(&reversed, r.lrecl())
+        })),
+        Command::Pad { len, fill } => Box::new(pipeline.stage(Pad::new(*len, *fill))),
+        Command::Trunc { len } => Box::new(pipeline.stage(Trunc::new(*len))),
+        Command::Squish => Box::new(pipeline.stage(Squish::new())),
+        Command::Xlate { spec } => {
+            Box::new(pipeline.stage(spec.stage().map_err(|e| e.to_string())?))
         }
+        Command::ReformatDate { spec } => {
+            Box::new(pipeline.stage(spec.stage().on_error(ErrorPolicy::Reject)))
+        }
+        Command::FromCsv { .. } => return Err("FROM CSV can only be the first stage".to_string()),
+        Command::ToCsv { spec } => Box::new(pipeline.stage(spec.stage())),
+        Command::Duplicate { n } => Box::new(pipeline.duplicate(*n)),
+        Command::Recno { ranges } => Box::new(pipeline.recno(ranges.clone())),
+        Command::Sample { fraction, seed } => Box::new(pipeline.sample(*fraction, *seed)),
+        Command::Unique { field } => Box::new(pipeline.dedup(vec![field.unwrap_or((0, lrecl))])),
+        Command::Sort { keys, sum } => {
+            sort_stream(Box::new(pipeline), keys, sum.as_deref(), lrecl, failure)
+        }
+        // Discard all input records, output nothing (like /dev/null)
+        Command::Hole => Box::new(pipeline.filter(|_| false)),
+        // With no segments feeding them, FANIN and FANINANY have only
+        // their primary input to pass on
+        Command::Fanin | Command::Faninany => Box::new(pipeline),
+        // Pace records through
+        Command::Delay { ms } => {
+            Box::new(pipeline.stage(ThrottleStage::new(Duration::from_millis(*ms))))
+        }
+        // Consume all records, then emit the profile report
+        Command::Profile { fields } => Box::new(pipeline.stage(ProfileStage::from_fields(fields))),
+        // Like LITERAL: the header goes first, then all input records
+        Command::Stamp {
+            header: true,
+            fields,
+        } => Box::new(std::iter::once(vars.header(fields, lrecl)).chain(pipeline)),
+        Command::Stamp {
+            header: false,
+            fields,
+        } => Box::new(pipeline.map(move |mut r| {
+            vars.stamp(&mut r, fields);
+            r
+        })),
+        Command::Plugin { name, args } => {
+            Box::new(pipeline.stage(plugin::create(name, args).map_err(|e| e.to_string())?))
+        }
+        Command::Encrypt { spec } => {
+            Box::new(pipeline.stage(crypto::encrypt_stage(spec).map_err(|e| e.to_string())?))
+        }
+        // Records whose field does not open are error records
+        Command::Decrypt { spec } => {
+            let decrypt = crypto::Decrypt::new(spec).map_err(|e| e.to_string())?;
+            Box::new(pipeline.stage(decrypt.rejects(ErrorCount(errors))))
+        }
+        // The file was decoded on input (or is encoded on output)
+        Command::Convert { .. } => Box::new(pipeline),
+        Command::Specs { items } => Box::new(pipeline.stage(Specs::new(items.clone()))),
+        Command::Split {
+            name,
+            condition,
+            build,
+        } => {
+            let test = match condition.as_deref() {
+                Some(condition) => Some(filter_test(condition).ok_or_else(|| {
+                    format!(
+                        "SPLIT IF needs a FILTER condition, not {}",
+                        condition.name()
+                    )
+                })?),
+                None => None,
+            };
+            side.open_output(name);
+            Box::new(pipeline.map_while(move |record| {
+                if test.as_ref().is_none_or(|test| test(&record)) {
+                    let copy = match build {
+                        Some(fields) => Select::new(fields.clone()).process(record.clone()),
+                        None => Some(record.clone()),
+                    };
+                    if let Some(copy) = copy
+                        && !side.write_output(name, copy)
+                    {
+                        return None;
+                    }
+                }
+                Some(record)
+            }))
+        }
+        Command::FileIn { .. } => return Err("< can only be the first stage".to_string()),
+        Command::FileOut { path, .. } => {
+            side.open_file(path);
+            Box::new(
+                pipeline.map_while(move |record| side.write_file(path, &record).then_some(record)),
+            )
+        }
+    })
+}
+
+/// The test a FILTER command applies to each record, or `None` for other
+/// commands.
+fn filter_test(cmd: &Command) -> Option<FilterTest<'_>> {
+    match cmd {
+        Command::FilterEq { pos, len, value } => Some(Box::new(|r| r.field_eq(*pos, *len, value))),
+        Command::FilterNe { pos, len, value } => Some(Box::new(|r| !r.field_eq(*pos, *len, value))),
+        Command::FilterZoned {
+            pos,
+            len,
+            op,
+            value,
+        } => Some(Box::new(|r| {
+            r.field_zoned(*pos, *len)
+                .is_some_and(|field| op.compare(field, *value))
+        })),
+        Command::FilterExpr { condition } => Some(Box::new(|r| condition.matches(r))),
+        _ => None,
+    }
+}
+
+/// Does a FILTER comparing numbers find a field of `record` that is not
+/// a number? Such records are error records.
+fn has_bad_number(cmd: &Command, record: &Record) -> bool {
+    match cmd {
+        Command::FilterZoned { pos, len, .. } => record.field_zoned(*pos, *len).is_none(),
+        Command::FilterExpr { condition } => condition.has_bad_number(record),
+        _ => false,
     }
 }
 
@@ -887,9 +4046,14 @@ fn apply_command(records: Vec<Record>, cmd: &Command) -> Result<Vec<Record>, Str
 mod tests {
     use super::*;
 
+    /// Parse one command with no LAYOUT declared.
+    fn parse(line: &str) -> Result<Command, String> {
+        parse_command(line, &RecordLayout::new())
+    }
+
     #[test]
     fn test_parse_filter_eq() {
-        let cmd = parse_command(r#"FILTER 18,10 = "SALES""#).unwrap();
+        let cmd = parse(r#"FILTER 18,10 = "SALES""#).unwrap();
         match cmd {
             Command::FilterEq { pos, len, value } => {
                 assert_eq!(pos, 18);
@@ -902,7 +4066,7 @@ mod tests {
 
     #[test]
     fn test_parse_filter_ne() {
-        let cmd = parse_command(r#"FILTER 18,10 != "SALES""#).unwrap();
+        let cmd = parse(r#"FILTER 18,10 != "SALES""#).unwrap();
         match cmd {
             Command::FilterNe { pos, len, value } => {
                 assert_eq!(pos, 18);
@@ -915,7 +4079,7 @@ mod tests {
 
     #[test]
     fn test_parse_select() {
-        let cmd = parse_command("SELECT 0,8,0; 28,8,8").unwrap();
+        let cmd = parse("SELECT 0,8,0; 28,8,8").unwrap();
         match cmd {
             Command::Select { fields } => {
                 assert_eq!(fields.len(), 2);
@@ -927,19 +4091,358 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_take() {
-        let cmd = parse_command("TAKE 5").unwrap();
-        match cmd {
-            Command::Take { n } => assert_eq!(n, 5),
-            _ => panic!("Expected Take"),
-        }
+    fn test_parse_overlay() {
+        let cmd = parse("OVERLAY 0,8,72; 28,8,40").unwrap();
+        assert_eq!(
+            cmd,
+            Command::Overlay {
+                fields: vec![(0, 8, 72), (28, 8, 40)]
+            }
+        );
+        assert_eq!(cmd.to_string(), "OVERLAY 0,8,72; 28,8,40");
+        assert!(
+            parse("OVERLAY 0,8")
+                .unwrap_err()
+                .contains("OVERLAY field '0,8' requires src_pos,len,dest_pos")
+        );
+        assert!(parse("OVERLAY").unwrap_err().contains("at least one"));
     }
 
     #[test]
-    fn test_parse_console() {
-        let cmd = parse_command("CONSOLE").unwrap();
-        assert!(matches!(cmd, Command::Console));
-    }
+    fn test_execute_overlay() {
+        let input = "SMITH   JOHN      SALES     00050000";
+        let pipeline = "PIPE CONSOLE | OVERLAY 0,8,40; 18,5,50 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(
+            output,
+            "SMITH   JOHN      SALES     00050000    SMITH     SALES"
+        );
+    }
+
+    #[test]
+    fn test_parse_recno() {
+        let cmd = parse("RECNO 1-10, 50, 100-200 BY 5").unwrap();
+        assert_eq!(
+            cmd,
+            Command::Recno {
+                ranges: vec![
+                    RecnoRange::new(1, 10),
+                    RecnoRange::single(50),
+                    RecnoRange::new(100, 200).by(5),
+                ]
+            }
+        );
+        for text in [
+            "RECNO 1-10, 50, 100-200 BY 5",
+            "RECNO 7-* BY 3",
+            "RECNO 4-4 BY 2",
+        ] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(parse("recno 3,4").unwrap().to_string(), "RECNO 3, 4");
+        assert!(
+            parse("RECNO")
+                .unwrap_err()
+                .contains("requires record numbers")
+        );
+        assert!(parse("RECNO 0-5").unwrap_err().contains("count from 1"));
+        assert!(parse("RECNO 9-5").unwrap_err().contains("ends before"));
+        assert!(
+            parse("RECNO 1-5 BY 0")
+                .unwrap_err()
+                .contains("positive step")
+        );
+        assert!(
+            parse("RECNO 1-5 EVERY 2")
+                .unwrap_err()
+                .contains("Unexpected")
+        );
+    }
+
+    #[test]
+    fn test_execute_recno() {
+        let input = "R1\nR2\nR3\nR4\nR5\nR6\nR7";
+        let pipeline = "PIPE CONSOLE | RECNO 1, 3-* BY 2 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "R1\nR3\nR5\nR7");
+    }
+
+    #[test]
+    fn test_parse_sample() {
+        assert_eq!(
+            parse("SAMPLE 0.25").unwrap(),
+            Command::Sample {
+                fraction: 0.25,
+                seed: 0
+            }
+        );
+        assert_eq!(
+            parse("sample 0.1 seed 42").unwrap(),
+            Command::Sample {
+                fraction: 0.1,
+                seed: 42
+            }
+        );
+        for text in ["SAMPLE 0.25", "SAMPLE 1 SEED 7"] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert!(parse("SAMPLE").unwrap_err().contains("fraction"));
+        assert!(parse("SAMPLE 10").unwrap_err().contains("from 0 to 1"));
+        assert!(parse("SAMPLE 0.5 SEED x").unwrap_err().contains("seed"));
+        assert!(parse("SAMPLE 0.5 42").unwrap_err().contains("Unexpected"));
+    }
+
+    #[test]
+    fn test_execute_sample() {
+        let input: Vec<String> = (0..200).map(|n| format!("R{n}")).collect();
+        let input = input.join("\n");
+        let run = |pipeline: &str| execute_pipeline(&input, pipeline).unwrap().0;
+        let kept = run("PIPE CONSOLE | SAMPLE 0.5 SEED 9 | CONSOLE");
+        assert_eq!(kept, run("PIPE CONSOLE | SAMPLE 0.5 SEED 9 | CONSOLE"));
+        assert_ne!(kept, run("PIPE CONSOLE | SAMPLE 0.5 SEED 10 | CONSOLE"));
+        assert!((60..140).contains(&kept.lines().count()));
+    }
+
+    #[test]
+    fn test_parse_take() {
+        let cmd = parse("TAKE 5").unwrap();
+        match cmd {
+            Command::Take { n } => assert_eq!(n, 5),
+            _ => panic!("Expected Take"),
+        }
+    }
+
+    #[test]
+    fn test_parse_pad_trunc_squish() {
+        assert_eq!(
+            parse("PAD 10").unwrap(),
+            Command::Pad { len: 10, fill: ' ' }
+        );
+        assert_eq!(
+            parse("pad 10 *").unwrap(),
+            Command::Pad { len: 10, fill: '*' }
+        );
+        assert_eq!(parse("TRUNC 5").unwrap(), Command::Trunc { len: 5 });
+        assert_eq!(parse("SQUISH").unwrap(), Command::Squish);
+        for cmd in ["PAD 10", "PAD 10 .", "TRUNC 0", "SQUISH"] {
+            assert_eq!(parse(cmd).unwrap().to_string(), cmd);
+        }
+        assert!(parse("PAD").unwrap_err().contains("length"));
+        assert!(parse("PAD 5 ab").unwrap_err().contains("one character"));
+        assert!(parse("TRUNC x").unwrap_err().contains("column"));
+    }
+
+    #[test]
+    fn test_execute_pad_trunc_squish() {
+        let input = "SMITH   JOHN      SALES\nDOE     JANE";
+        let pipeline = "PIPE CONSOLE | SQUISH | PAD 15 . | TRUNC 12 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "SMITH JOHN S\nDOE JANE....");
+    }
+
+    #[test]
+    fn test_parse_xlate() {
+        assert_eq!(
+            parse("XLATE").unwrap(),
+            Command::Xlate {
+                spec: XlateSpec::default()
+            }
+        );
+        let Command::Xlate { spec } = parse("xlate 0,5 lower - / 40 5F").unwrap() else {
+            panic!("expected XLATE");
+        };
+        assert_eq!(spec.field, Some((0, 5)));
+        assert_eq!(spec.source, TableSource::Lower);
+        assert_eq!(spec.pairs, vec![(b'-', b'/'), (0x40, b'_')]);
+
+        let cmd = parse("XLATE a b").unwrap();
+        assert!(matches!(&cmd, Command::Xlate { spec } if spec.source == TableSource::Identity));
+        for text in [
+            "XLATE UPPER",
+            "XLATE 10,4 FILE tables/rot13.tbl",
+            "XLATE 0,8 a b 20 _",
+        ] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(parse("XLATE 5F 7C").unwrap().to_string(), "XLATE _ 7C");
+        assert!(parse("XLATE FILE").unwrap_err().contains("path"));
+        assert!(parse("XLATE a").unwrap_err().contains("translate to"));
+        assert!(parse("XLATE abc d").unwrap_err().contains("hex digits"));
+        assert!(parse("XLATE zz d").unwrap_err().contains("hex digits"));
+    }
+
+    #[test]
+    fn test_execute_xlate() {
+        let input = "smith-john\njones-mary";
+        let pipeline = "PIPE CONSOLE | XLATE 0,5 | XLATE - , | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "SMITH,john\nJONES,mary");
+
+        let dir = std::env::temp_dir().join(format!("dsl-xlate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("digits.tbl");
+        let table: Vec<u8> = (0..=255u8)
+            .map(|b| if b.is_ascii_digit() { b'#' } else { b })
+            .collect();
+        std::fs::write(&path, table).unwrap();
+        let pipeline = format!("PIPE CONSOLE | XLATE FILE {} | CONSOLE", path.display());
+        let (output, _, _) = execute_pipeline("CARD 4111", &pipeline).unwrap();
+        assert_eq!(output, "CARD ####");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        let err = execute_pipeline("CARD 4111", &pipeline).unwrap_err();
+        assert!(err.contains("XLATE"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_reformat_date() {
+        assert_eq!(
+            parse("REFORMAT DATE 44 YYYYMMDD TO MMDDYY").unwrap(),
+            Command::ReformatDate {
+                spec: DateSpec {
+                    pos: 44,
+                    from: DateFormat::Yyyymmdd,
+                    to: DateFormat::Mmddyy,
+                    dest: None,
+                }
+            }
+        );
+        for text in [
+            "REFORMAT DATE 0 YYDDD TO YYYYMMDD",
+            "REFORMAT DATE 44 YYYYMMDD TO MMDDYY 60",
+        ] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(
+            parse("reformat date 10 mmddyy to yyddd")
+                .unwrap()
+                .to_string(),
+            "REFORMAT DATE 10 MMDDYY TO YYDDD"
+        );
+        assert!(parse("REFORMAT 0,8").unwrap_err().contains("only DATE"));
+        assert!(parse("REFORMAT DATE").unwrap_err().contains("position"));
+        assert!(
+            parse("REFORMAT DATE 0 DDMMYY TO YYDDD")
+                .unwrap_err()
+                .contains("DDMMYY")
+        );
+        assert!(
+            parse("REFORMAT DATE 0 YYDDD MMDDYY")
+                .unwrap_err()
+                .contains("TO")
+        );
+        assert!(
+            parse("REFORMAT DATE 76 YYDDD TO YYYYMMDD")
+                .unwrap_err()
+                .contains("past")
+        );
+        assert!(
+            parse("REFORMAT DATE 0 YYDDD TO MMDDYY 9 X")
+                .unwrap_err()
+                .contains("Unexpected")
+        );
+    }
+
+    #[test]
+    fn test_execute_reformat_date() {
+        let input = "SMITH   19850315
+JONES   19850230
+DOE     20000229";
+        let pipeline = "LAYOUT NAME 0,8; HIRED 8,8\n\
+            PIPE CONSOLE\n\
+            | d: REFORMAT DATE HIRED YYYYMMDD TO YYDDD\n\
+            | CONSOLE\n\
+            ? d:\n\
+            | CHANGE /JONES/BAD JONES/\n\
+            | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "SMITH   85074\nDOE     00060\nBAD JONES   19850230");
+
+        // Without a connected label, invalid dates are dropped
+        let pipeline = "PIPE CONSOLE | REFORMAT DATE 8 YYYYMMDD TO MMDDYY 20 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(
+            output,
+            "SMITH   19850315    031585\nDOE     20000229    022900"
+        );
+    }
+
+    #[test]
+    fn test_parse_between() {
+        assert_eq!(
+            parse("BETWEEN /START/ /END/").unwrap(),
+            Command::Between {
+                start: "START".to_string(),
+                end: "END".to_string(),
+                field: None,
+                inclusive: true,
+            }
+        );
+        let cmd = parse("between 0,5 'PAGE' 'TOTAL' exclusive").unwrap();
+        assert_eq!(cmd.to_string(), "BETWEEN 0,5 /PAGE/ /TOTAL/ EXCLUSIVE");
+        assert_eq!(parse(&cmd.to_string()).unwrap(), cmd);
+        assert!(parse("BETWEEN /A/").unwrap_err().contains("end string"));
+        assert!(
+            parse("BETWEEN /A/ /B/ BOTH")
+                .unwrap_err()
+                .contains("EXCLUSIVE")
+        );
+    }
+
+    #[test]
+    fn test_execute_between() {
+        let input = "REPORT\n*** SALES\nSMITH\nDOE\n*** END\n*** SALES\nJONES\nFOOTER";
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | BETWEEN /SALES/ /END/ | CONSOLE").unwrap();
+        assert_eq!(
+            output,
+            "*** SALES\nSMITH\nDOE\n*** END\n*** SALES\nJONES\nFOOTER"
+        );
+        let pipeline = "PIPE CONSOLE | BETWEEN 0,3 /***/ /***/ EXCLUSIVE | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "SMITH\nDOE\nJONES\nFOOTER");
+        // The records outside the sections are the secondary output
+        let pipeline = "PIPE CONSOLE | b: BETWEEN /SMITH/ /DOE/ | HOLE ? b: | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(
+            output,
+            "REPORT\n*** SALES\n*** END\n*** SALES\nJONES\nFOOTER"
+        );
+    }
+
+    #[test]
+    fn test_parse_take_last_and_drop_last() {
+        assert_eq!(parse("TAKE LAST 2").unwrap(), Command::TakeLast { n: 2 });
+        assert_eq!(parse("drop last 1").unwrap(), Command::DropLast { n: 1 });
+        assert_eq!(Command::DropLast { n: 1 }.to_string(), "DROP LAST 1");
+        assert!(
+            parse("TAKE LAST")
+                .unwrap_err()
+                .contains("TAKE LAST requires")
+        );
+        assert!(parse("DROP 3").unwrap_err().contains("SKIP"));
+    }
+
+    #[test]
+    fn test_execute_take_last_and_drop_last() {
+        let input = "HEADER\nA\nB\nTRAILER";
+        let (output, _, count) =
+            execute_pipeline(input, "PIPE CONSOLE | SKIP 1 | DROP LAST 1 | CONSOLE").unwrap();
+        assert_eq!(output, "A\nB");
+        assert_eq!(count, 2);
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | TAKE LAST 2 | CONSOLE").unwrap();
+        assert_eq!(output, "B\nTRAILER");
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | TAKE LAST 9 | CONSOLE").unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_parse_console() {
+        let cmd = parse("CONSOLE").unwrap();
+        assert!(matches!(cmd, Command::Console));
+    }
 
     #[test]
     fn test_execute_pipeline() {
@@ -1073,6 +4576,197 @@ DOE     JANE      SALES     00060000";
         assert!(result.is_ok(), "Expected success but got: {:?}", result);
     }
 
+    #[test]
+    fn test_parse_dump() {
+        assert_eq!(
+            parse("DUMP").unwrap(),
+            Command::Dump {
+                format: DumpFormat::Hex
+            }
+        );
+        for text in ["DUMP", "DUMP RULER"] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(parse("dump hex").unwrap().to_string(), "DUMP");
+        assert!(parse("DUMP OCTAL").unwrap_err().contains("HEX or RULER"));
+        assert!(parse("DUMP HEX 2").unwrap_err().contains("Unexpected"));
+
+        // DUMP passes records through unchanged
+        let pipeline = "PIPE CONSOLE | DUMP | DUMP RULER | CONSOLE";
+        let (output, _, _) = execute_pipeline("A\nB", pipeline).unwrap();
+        assert_eq!(output, "A\nB");
+    }
+
+    #[test]
+    fn test_pipeline_layout() {
+        let text = "LAYOUT NAME 0,8\nLAYOUT PAY 8,8 NUMERIC\nPIPE CONSOLE | CONSOLE";
+        let layout = pipeline_layout(text).unwrap();
+        assert_eq!(layout.to_string(), "LAYOUT NAME 0,8; PAY 8,8 NUMERIC");
+        assert!(
+            pipeline_layout("PIPE CONSOLE | CONSOLE")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(pipeline_layout("LAYOUT NAME 0,8; NAME 8,8").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let mut layout = RecordLayout::new();
+        layout.add("NAME", 0, 8, FieldType::Char).unwrap();
+        layout.add("PAY", 8, 8, FieldType::Numeric).unwrap();
+        assert_eq!(
+            parse_command("FROM CSV HEADER", &layout).unwrap(),
+            Command::FromCsv {
+                spec: CsvSpec {
+                    layout: layout.clone(),
+                    header: true,
+                }
+            }
+        );
+        assert_eq!(
+            parse_command("to csv", &layout).unwrap().to_string(),
+            "TO CSV NAME 0,8; PAY 8,8 NUMERIC"
+        );
+        for text in ["FROM CSV HEADER NAME 0,8; PAY 8,8 NUMERIC", "TO CSV A 0,1"] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert!(parse("FROM CSV").unwrap_err().contains("LAYOUT"));
+        assert!(parse("FROM JSON").unwrap_err().contains("only CSV"));
+        assert!(
+            parse("TO CSV A 0,4; B 2,4")
+                .unwrap_err()
+                .contains("overlap")
+        );
+        assert!(parse("TO CSV A 78,4").is_err());
+    }
+
+    #[test]
+    fn test_execute_csv() {
+        let pipeline = "LAYOUT NAME 0,8; DEPT 8,10; PAY 18,8 NUMERIC
+PIPE FROM CSV HEADER
+| FILTER DEPT = \"SALES\"
+| TO CSV HEADER NAME 0,8; PAY 18,8 NUMERIC
+| CONSOLE";
+        let input = "name,dept,pay\n\"SMITH, J\",SALES,50000\nJONES,ENGINEER,75000\n";
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "NAME,PAY\n\"SMITH, J\",50000");
+        assert_eq!((input_count, output_count), (2, 2));
+
+        let err = execute_pipeline("A,\"B", "LAYOUT A 0,4\nPIPE FROM CSV | CONSOLE").unwrap_err();
+        assert!(err.contains("unclosed quote"), "{err}");
+        let err = execute_pipeline("", "LAYOUT A 0,4\nPIPE CONSOLE | FROM CSV | CONSOLE");
+        assert!(err.unwrap_err().contains("first stage"));
+    }
+
+    #[test]
+    fn test_execute_plan_streaming() {
+        let plan = PipelinePlan::parse("PIPE CONSOLE | LOCATE /a/ | UPPER | CONSOLE").unwrap();
+        let lines = ["alpha", "", "beta", "gamma"].into_iter();
+        let mut emitted = Vec::new();
+        let run = execute_plan_streaming(&plan, lines, &None, &HostVars::new(), |record| {
+            emitted.push(record.as_str().trim_end().to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(emitted, ["ALPHA", "BETA", "GAMMA"]);
+        assert_eq!((run.input_count, run.output_count), (3, 3));
+        assert!(run.output.is_empty());
+
+        let err = execute_plan_streaming(&plan, "a".lines(), &None, &HostVars::new(), |_| {
+            Err("disk full".to_string())
+        });
+        assert_eq!(err.unwrap_err(), "disk full");
+    }
+
+    #[test]
+    fn test_execute_plan_streaming_record_at_a_time() {
+        // Each record is emitted before the next line is read, and SORT
+        // emits only at end of input
+        let read = Cell::new(0);
+        let lines = ["b", "a", "c"]
+            .into_iter()
+            .inspect(|_| read.set(read.get() + 1));
+        let plan = PipelinePlan::parse("PIPE CONSOLE | UPPER | CONSOLE").unwrap();
+        let mut seen = Vec::new();
+        let run = execute_plan_streaming(&plan, lines.clone(), &None, &HostVars::new(), |r| {
+            seen.push((r.as_str().trim_end().to_string(), read.get()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, [("B".into(), 1), ("A".into(), 2), ("C".into(), 3)]);
+        assert_eq!(run.metrics.stages.len(), 3);
+        assert_eq!(run.debug_info[1].input_count, 3);
+
+        read.set(0);
+        let plan = PipelinePlan::parse("PIPE CONSOLE | SORT | CONSOLE").unwrap();
+        let mut seen = Vec::new();
+        execute_plan_streaming(&plan, lines, &None, &HostVars::new(), |r| {
+            seen.push((r.as_str().trim_end().to_string(), read.get()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(seen, [("a".into(), 3), ("b".into(), 3), ("c".into(), 3)]);
+    }
+
+    /// Logs each side record with the number of input lines read when it
+    /// was written, failing on a record starting with "BAD".
+    struct SideLog<'a> {
+        read: &'a Cell<usize>,
+        log: Vec<String>,
+    }
+
+    impl SideWriter for SideLog<'_> {
+        fn write_output(&mut self, name: &str, record: &Record) -> Result<(), String> {
+            self.write_file(&format!("SPLIT {name}"), record)
+        }
+
+        fn write_file(&mut self, path: &str, record: &Record) -> Result<(), String> {
+            if record.as_str().starts_with("BAD") {
+                return Err(format!("cannot write {path}"));
+            }
+            let text = record.as_str().trim_end();
+            self.log.push(format!("{path} {text} {}", self.read.get()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_execute_plan_streaming_to_writes_side_records_as_read() {
+        let read = Cell::new(0);
+        let lines = ["b", "ab", "c"]
+            .into_iter()
+            .inspect(|_| read.set(read.get() + 1));
+        let plan =
+            PipelinePlan::parse(r#"PIPE CONSOLE | SPLIT A IF 0,2 = "ab" | > all.txt | CONSOLE"#)
+                .unwrap();
+        let mut side = SideLog {
+            read: &read,
+            log: Vec::new(),
+        };
+        let (vars, inputs) = (HostVars::new(), InputFiles::new());
+        let run =
+            execute_plan_streaming_to(&plan, lines, &None, &vars, &inputs, &mut side, |_| Ok(()))
+                .unwrap();
+        assert_eq!(
+            side.log,
+            ["all.txt b 1", "SPLIT A ab 2", "all.txt ab 2", "all.txt c 3"]
+        );
+        assert!(run.outputs.is_empty());
+        assert!(run.files.is_empty());
+
+        // A failed write stops the run
+        let lines = ["ok", "BAD", "never"].into_iter();
+        let mut output = Vec::new();
+        let err = execute_plan_streaming_to(&plan, lines, &None, &vars, &inputs, &mut side, |r| {
+            output.push(r.as_str().trim_end().to_string());
+            Ok(())
+        })
+        .unwrap_err();
+        assert_eq!(err, "cannot write all.txt");
+        assert_eq!(output, ["ok"]);
+    }
+
     #[test]
     fn test_console_in_middle() {
         // CONSOLE in middle passes through (useful for debugging)
@@ -1124,6 +4818,226 @@ DOE     JANE      SALES     00060000";
         assert!(output.is_empty());
     }
 
+    #[test]
+    fn test_command_display_round_trips() {
+        let text = r#"PIPE CONSOLE
+| FILTER 18,10 != "SALES"
+| SELECT 0,8,0; 28,8,8
+| LOCATE 18,10 /ENG/
+| NLOCATE /a/b/
+| CHANGE /x"y/ /z/
+| LITERAL hello there
+| DUPLICATE 2
+| PROFILE 0,8; 28,8 numeric
+| DELAY 5
+| STAMP header 0,8 date; 10,4 "RUN:"
+| COUNT"#;
+        let commands = parse_commands(text).unwrap();
+        let rendered: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+        assert_eq!(rendered[1], r#"FILTER 18,10 != "SALES""#);
+        assert_eq!(rendered[2], "SELECT 0,8,0; 28,8,8");
+        assert_eq!(rendered[4], "NLOCATE /a/");
+        assert_eq!(rendered[5], "CHANGE /x\"y/ /z/");
+        assert_eq!(rendered[8], "PROFILE 0,8 CHAR; 28,8 NUMERIC");
+        assert_eq!(rendered[9], "DELAY 5 MS");
+        assert_eq!(rendered[10], r#"STAMP HEADER 0,8 DATE; 10,4 "RUN:""#);
+        let reparsed = parse_commands(&rendered.join("\n| ")).unwrap();
+        let again: Vec<String> = reparsed.iter().map(|c| c.to_string()).collect();
+        assert_eq!(rendered, again);
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let cmd = parse("PROFILE").unwrap();
+        assert!(matches!(cmd, Command::Profile { ref fields } if fields.is_empty()));
+
+        let cmd = parse("PROFILE 0,8; 28,8 NUMERIC; 36,10 date").unwrap();
+        match cmd {
+            Command::Profile { fields } => assert_eq!(
+                fields,
+                vec![
+                    (0, 8, FieldType::Char),
+                    (28, 8, FieldType::Numeric),
+                    (36, 10, FieldType::Date)
+                ]
+            ),
+            _ => panic!("Expected Profile"),
+        }
+
+        assert!(parse("PROFILE 0,8 MONEY").is_err());
+        assert!(parse("PROFILE 75,10").is_err());
+        assert!(parse("PROFILE 8").is_err());
+    }
+
+    #[test]
+    fn test_parse_delay() {
+        assert!(matches!(
+            parse("DELAY 250 ms").unwrap(),
+            Command::Delay { ms: 250 }
+        ));
+        assert!(matches!(
+            parse("DELAY 0").unwrap(),
+            Command::Delay { ms: 0 }
+        ));
+        assert!(parse("DELAY").is_err());
+        assert!(parse("DELAY 2 s").is_err());
+    }
+
+    #[test]
+    fn test_execute_delay_passes_records() {
+        let input = "A\nB";
+        let (output, _, output_count) =
+            execute_pipeline(input, "PIPE CONSOLE | DELAY 1 MS | CONSOLE").unwrap();
+        assert_eq!(output_count, 2);
+        assert_eq!(output, "A\nB");
+    }
+
+    #[test]
+    fn test_execute_profile() {
+        let input = "SMITH   SALES\nJONES   \nDOE     SALES";
+        let pipeline = "PIPE CONSOLE | PROFILE 0,8; 8,5 | CONSOLE";
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(input_count, 3);
+        assert_eq!(output_count, 4);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0].trim_end(), "PROFILE 3 RECORDS");
+        let dept: Vec<&str> = lines[3].split_whitespace().collect();
+        assert_eq!(dept[4], "33%");
+        assert_eq!(dept[5], "1");
+    }
+
+    #[test]
+    fn test_parse_stamp() {
+        let cmd = parse("STAMP 40,8 DATE; 50,6 env.HOME; 60,5 /X;Y/").unwrap();
+        let Command::Stamp { header, fields } = cmd else {
+            panic!("expected STAMP");
+        };
+        assert!(!header);
+        assert_eq!(
+            fields,
+            vec![
+                (40, 8, StampValue::Var("DATE".to_string())),
+                (50, 6, StampValue::Var("ENV.HOME".to_string())),
+                (60, 5, StampValue::Text("X;Y".to_string())),
+            ]
+        );
+        assert!(parse("STAMP").is_err());
+        assert!(parse("STAMP HEADER").is_err());
+        assert!(parse("STAMP 0,8 HOSTNAME").is_err());
+        assert!(parse("STAMP 76,8 DATE").is_err());
+        assert!(parse("STAMP 0,8 DATE 10,6 TIME").is_err());
+    }
+
+    #[test]
+    fn test_execute_stamp() {
+        use std::time::{Duration, UNIX_EPOCH};
+        let vars = HostVars::new()
+            .with_time(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            .with_parm("RUN", "7");
+        let pipeline =
+            "PIPE CONSOLE | STAMP HEADER 0,4 \"RUN \"; 4,2 PARM.RUN | STAMP 10,8 DATE | CONSOLE";
+        let (output, input_count, output_count) =
+            execute_pipeline_with_vars("A\nB", pipeline, &vars).unwrap();
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 3);
+        assert_eq!(
+            output,
+            "RUN 7     20231114\nA         20231114\nB         20231114"
+        );
+    }
+
+    #[test]
+    fn test_stamp_missing_parm_fails() {
+        let result = execute_pipeline("A", "PIPE CONSOLE | STAMP 0,4 PARM.RUN | CONSOLE");
+        assert!(result.unwrap_err().contains("RUN"));
+    }
+
+    #[test]
+    fn test_parse_plugin() {
+        let cmd = parse("PLUGIN rot13").unwrap();
+        assert!(
+            matches!(cmd, Command::Plugin { ref name, ref args } if name == "ROT13" && args.is_empty())
+        );
+        let cmd = parse("PLUGIN mask  0,4  X").unwrap();
+        assert_eq!(cmd.to_string(), "PLUGIN MASK 0,4  X");
+        assert!(parse("PLUGIN").is_err());
+    }
+
+    #[test]
+    fn test_execute_plugin() {
+        plugin::register("DSL-TEST-DROP", |args| {
+            let pattern = args.to_string();
+            Ok(Box::new(crate::Filter::new(move |r: &Record| {
+                !r.as_str().contains(&pattern)
+            })))
+        });
+        let pipeline = "PIPE CONSOLE | PLUGIN dsl-test-drop B | CONSOLE";
+        let (output, _, output_count) = execute_pipeline("A\nB\nC", pipeline).unwrap();
+        assert_eq!(output_count, 2);
+        assert_eq!(output, "A\nC");
+
+        let err = execute_pipeline("A", "PIPE CONSOLE | PLUGIN dsl-test-missing | CONSOLE");
+        assert!(
+            err.unwrap_err()
+                .contains("no plugin stage named DSL-TEST-MISSING")
+        );
+    }
+
+    #[test]
+    fn test_parse_crypt() {
+        let cmd = parse("ENCRYPT 0,8 TO 32,48 base64 KEYENV CARD_KEY").unwrap();
+        let Command::Encrypt { ref spec } = cmd else {
+            panic!("expected ENCRYPT");
+        };
+        assert_eq!(spec.field, (0, 8));
+        assert_eq!(spec.dest, (32, 48));
+        assert_eq!(spec.encoding, Encoding::Base64);
+        assert_eq!(spec.key, KeySource::Env("CARD_KEY".to_string()));
+        assert_eq!(
+            cmd.to_string(),
+            "ENCRYPT 0,8 TO 32,48 BASE64 KEYENV CARD_KEY"
+        );
+
+        let cmd = parse("DECRYPT 32,48 TO 0,8 BASE64").unwrap();
+        assert_eq!(cmd.to_string(), "DECRYPT 32,48 TO 0,8 BASE64");
+
+        // A sealed 8-byte value needs 72 hex columns
+        assert!(parse("ENCRYPT 0,8").unwrap_err().contains("needs 72"));
+        assert!(parse("ENCRYPT 0,8 TO 8,72").is_ok());
+        assert!(parse("ENCRYPT 0,8 TO 8,72 KEYFILE").is_err());
+        assert!(parse("DECRYPT").is_err());
+    }
+
+    #[cfg(not(feature = "crypto"))]
+    #[test]
+    fn test_crypt_requires_feature() {
+        let result = execute_pipeline("A", "PIPE CONSOLE | DECRYPT 0,8 | CONSOLE");
+        assert!(result.unwrap_err().contains("`crypto` feature"));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_execute_crypt_round_trip() {
+        let key = "KEYHEX 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let pipeline = format!(
+            "PIPE CONSOLE | ENCRYPT 0,8 TO 16,48 BASE64 {key} | DECRYPT 16,48 TO 0,8 BASE64 {key} | CONSOLE"
+        );
+        let input = "SMITH   SALES\nJONES   ENGINEER";
+        let (output, _, _) = execute_pipeline(input, &pipeline).unwrap();
+        assert_eq!(output, "SMITH   SALES\nJONES   ENGINEER");
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_execute_decrypt_error_records() {
+        let key = "KEYHEX 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let pipeline = format!("PIPE CONSOLE | DECRYPT 0,48 TO 0,8 BASE64 {key} | CONSOLE");
+        let run = execute_pipeline_run("NOT SEALED", &pipeline, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.output_count, 0);
+        assert_eq!(run.error_records, 1);
+        assert_eq!(run.condition_code, ConditionCode::Error);
+    }
+
     #[test]
     fn test_debug_info_new() {
         let info = DebugInfo::new("CONSOLE".to_string(), 3, 2);
@@ -1174,4 +5088,1462 @@ DOE     JANE      SALES     00060000";
         assert!(debug_info[0].input_records.is_none());
         assert!(debug_info[0].output_records.is_none());
     }
+
+    #[test]
+    fn test_layout_field_names() {
+        let text = r#"LAYOUT LASTNAME 0,8; DEPT 18,10; SALARY 28,8 NUMERIC
+PIPE CONSOLE
+| FILTER dept = "SALES"
+| LOCATE LASTNAME /S/
+| SELECT LASTNAME,0; SALARY,8
+| CONSOLE
+?"#;
+        let commands = parse_commands(text).unwrap();
+        let rendered: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            rendered,
+            [
+                "CONSOLE",
+                r#"FILTER 18,10 = "SALES""#,
+                "LOCATE 0,8 /S/",
+                "SELECT 0,8,0; 28,8,8",
+                "CONSOLE"
+            ]
+        );
+
+        let input = "SMITH   JOHN      SALES     00050000\nJONES   MARY      ENGINEER  00075000";
+        let (output, _, _) = execute_pipeline(input, text).unwrap();
+        assert_eq!(output, "SMITH   00050000");
+    }
+
+    #[test]
+    fn test_layout_field_types_and_ranges() {
+        let mut layout = RecordLayout::new();
+        extend_layout(&mut layout, "LAYOUT LAST 0,8; SALARY 28,8 NUMERIC").unwrap();
+
+        let cmd = parse_command("PROFILE LAST; SALARY; salary CHAR", &layout).unwrap();
+        let Command::Profile { fields } = cmd else {
+            panic!("expected PROFILE");
+        };
+        assert_eq!(
+            fields,
+            vec![
+                (0, 8, FieldType::Char),
+                (28, 8, FieldType::Numeric),
+                (28, 8, FieldType::Char),
+            ]
+        );
+
+        let cmd = parse_command("STAMP LAST STEP", &layout).unwrap();
+        assert!(matches!(cmd, Command::Stamp { ref fields, .. } if fields[0].0 == 0));
+        let cmd = parse_command("DECRYPT SALARY TO LAST KEYHEX 00", &layout).unwrap();
+        assert!(
+            matches!(cmd, Command::Decrypt { ref spec } if spec.field == (28, 8) && spec.dest == (0, 8))
+        );
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let err = |text: &str| parse_commands(text).unwrap_err();
+        assert_eq!(
+            err("LAYOUT DEPT 18,10; CODE 20,4"),
+            "Line 1: invalid layout: fields DEPT (18,10) and CODE (20,4) overlap"
+        );
+        assert_eq!(
+            err("LAYOUT DEPT 18,10\nLAYOUT CODE 24,4"),
+            "Line 2: invalid layout: fields DEPT (18,10) and CODE (24,4) overlap"
+        );
+        assert_eq!(
+            err("LAYOUT DEPT 18,10\nLAYOUT dept 40,4"),
+            "Line 2: invalid layout: field dept is defined more than once"
+        );
+        assert_eq!(
+            err("LAYOUT DEPT 18,10\nPIPE CONSOLE | FILTER DEPTNO = \"X\""),
+            "Line 2: Unknown field 'DEPTNO' (LAYOUT defines DEPT)"
+        );
+        assert_eq!(
+            err("PIPE CONSOLE | FILTER DEPT = \"X\""),
+            "Line 1: Unknown field 'DEPT' (no LAYOUT declared)"
+        );
+        assert_eq!(
+            err("LAYOUT DEPT 18,10\nPIPE CONSOLE | SELECT DEPT,0,8"),
+            "Line 2: SELECT field 'DEPT,0,8' requires NAME,dest_pos"
+        );
+    }
+
+    #[test]
+    fn test_parse_convert() {
+        let cmd = parse("CONVERT ascii").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::Convert {
+                to: Charset::Ascii,
+                code_page: CodePage::Cp037
+            }
+        ));
+        let cmd = parse("CONVERT EBCDIC cp1047").unwrap();
+        assert_eq!(cmd.to_string(), "CONVERT EBCDIC CP1047");
+        assert!(parse("CONVERT").is_err());
+        assert!(parse("CONVERT EBCDIC CP500").is_err());
+        assert!(parse("CONVERT EBCDIC CP037 X").is_err());
+    }
+
+    #[test]
+    fn test_convert_placement() {
+        let commands =
+            parse_commands("PIPE CONSOLE | CONVERT ASCII | UPPER | CONVERT EBCDIC 1047 | CONSOLE")
+                .unwrap();
+        assert_eq!(ebcdic::input_code_page(&commands), Some(CodePage::Cp037));
+        assert_eq!(ebcdic::output_code_page(&commands), Some(CodePage::Cp1047));
+
+        let (output, _, _) = execute_pipeline(
+            "abc",
+            "PIPE CONSOLE | CONVERT ASCII | UPPER | CONVERT EBCDIC | CONSOLE",
+        )
+        .unwrap();
+        assert_eq!(output, "ABC");
+
+        assert_eq!(
+            parse_commands("PIPE CONSOLE | UPPER | CONVERT ASCII | CONSOLE").unwrap_err(),
+            "CONVERT ASCII must directly follow PIPE CONSOLE"
+        );
+        assert_eq!(
+            parse_commands("PIPE CONSOLE | CONVERT EBCDIC | UPPER | CONSOLE").unwrap_err(),
+            "CONVERT EBCDIC must directly precede the output CONSOLE"
+        );
+    }
+
+    #[test]
+    fn test_parse_filter_zoned() {
+        let cmd = parse("FILTER 28,8 zd <= -5").unwrap();
+        assert!(matches!(
+            cmd,
+            Command::FilterZoned {
+                pos: 28,
+                len: 8,
+                op: CompareOp::Le,
+                value: -5
+            }
+        ));
+        assert_eq!(cmd.to_string(), "FILTER 28,8 ZD <= -5");
+        assert!(parse("FILTER 28,8 ZD ~ 5").is_err());
+        assert!(parse("FILTER 28,8 ZD > five").is_err());
+        assert!(parse("FILTER 76,8 ZD > 0").is_err());
+    }
+
+    #[test]
+    fn test_execute_filter_zoned() {
+        let input = "SMITH   00005000\nJONES   0000250}\nDOE     0000010J\nBLANK";
+        let pipeline = "PIPE CONSOLE | FILTER 8,8 ZD < 0 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "JONES   0000250}\nDOE     0000010J");
+
+        let pipeline = "LAYOUT AMOUNT 8,8\nPIPE CONSOLE | FILTER AMOUNT ZD >= 0 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "SMITH   00005000");
+    }
+
+    #[test]
+    fn test_lrecl() {
+        let input = "SMITH   JOHN      SALES     00050000";
+        let pipeline = "LRECL 132\nPIPE CONSOLE | SELECT 0,8,0; 28,8,124 | CONSOLE";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, format!("SMITH{}00050000", " ".repeat(119)));
+        assert_eq!(pipeline_lrecl(pipeline), Ok(132));
+        assert_eq!(pipeline_lrecl("PIPE CONSOLE | CONSOLE"), Ok(80));
+
+        let pipeline =
+            "LRECL 132\nLAYOUT TOTAL 120,12\nPIPE LITERAL TOTALS | STAMP TOTAL /END/ | CONSOLE";
+        let (output, _, _) = execute_pipeline("", pipeline).unwrap();
+        assert!(output.ends_with(&format!("TOTALS{}END", " ".repeat(114))));
+    }
+
+    #[test]
+    fn test_lrecl_errors() {
+        let err = |pipeline: &str| execute_pipeline("A", pipeline).unwrap_err();
+        assert!(
+            err("PIPE CONSOLE | FILTER 124,8 ZD > 0 | CONSOLE")
+                .contains("outside the 80-byte record")
+        );
+        assert!(err("LRECL 0\nPIPE CONSOLE | CONSOLE").contains("from 1 to 32760"));
+        assert!(err("LRECL 132 256\nPIPE CONSOLE | CONSOLE").contains("Unexpected '256'"));
+        assert!(
+            err("LAYOUT A 0,1\nLRECL 132\nPIPE CONSOLE | CONSOLE")
+                .contains("LRECL must come once, before LAYOUT and the stages")
+        );
+    }
+
+    #[test]
+    fn test_condition_codes() {
+        let run = |input: &str, pipeline: &str| {
+            execute_pipeline_run(input, pipeline, &None, &HostVars::new()).unwrap()
+        };
+        let dates = "PIPE CONSOLE | REFORMAT DATE 0 YYYYMMDD TO MMDDYY | CONSOLE";
+        assert_eq!(run("20240131", dates).condition_code, ConditionCode::Ok);
+
+        let bad = run("20240131\n20241345\n20240230", dates);
+        assert_eq!(bad.error_records, 2);
+        assert_eq!(bad.condition_code, ConditionCode::Error);
+        let fatal = run(
+            "20240131\n20241345\n20240230",
+            &format!("RC ERROR 3 FATAL 2\n{dates}"),
+        );
+        assert_eq!(fatal.condition_code, ConditionCode::Fatal);
+
+        let long = run(&"X".repeat(81), "PIPE CONSOLE | CONSOLE");
+        assert_eq!(
+            long.warnings,
+            ["record 1 is longer than LRECL 80 and was truncated"]
+        );
+        assert_eq!(long.condition_code, ConditionCode::Warning);
+        let quiet = run(&"X".repeat(81), "RC WARN 2\nPIPE CONSOLE | CONSOLE");
+        assert_eq!(quiet.condition_code, ConditionCode::Ok);
+
+        let plan = PipelinePlan::parse("rc warn 2 fatal 9\nPIPE CONSOLE | CONSOLE").unwrap();
+        assert_eq!(
+            plan.to_string(),
+            "RC WARN 2 ERROR 1 FATAL 9\nPIPE CONSOLE\n| CONSOLE\n?"
+        );
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+
+        let err = |pipeline: &str| execute_pipeline("A", pipeline).unwrap_err();
+        assert!(err("RC\nPIPE CONSOLE | CONSOLE").contains("RC requires WARN n"));
+        assert!(err("RC WARN 0\nPIPE CONSOLE | CONSOLE").contains("at least 1"));
+        assert!(err("RC ERROR\nPIPE CONSOLE | CONSOLE").contains("RC ERROR requires"));
+        assert!(err("RC LIMIT 3\nPIPE CONSOLE | CONSOLE").contains("Unexpected 'LIMIT'"));
+        assert!(err("RC WARN 1\nRC WARN 2\nPIPE CONSOLE | CONSOLE").contains("only once"));
+    }
+
+    #[test]
+    fn test_statistic_error_records() {
+        let run = |pipeline: &str| {
+            execute_pipeline_run(
+                "00012\n12X45\n     \n00030",
+                pipeline,
+                &None,
+                &HostVars::new(),
+            )
+            .unwrap()
+        };
+        let sum = run("PIPE CONSOLE | SUM 0,5 | CONSOLE");
+        assert_eq!(sum.output.trim_end(), "42");
+        assert_eq!(sum.error_records, 2);
+        assert_eq!(sum.condition_code, ConditionCode::Error);
+
+        // Blanks are left out of an average rather than counted as zero;
+        // fewer error records than RC ERROR give a warning
+        let avg = run("RC ERROR 3\nPIPE CONSOLE | AVG 0,5 | CONSOLE");
+        assert_eq!(avg.output.trim_end(), "21");
+        assert_eq!(avg.condition_code, ConditionCode::Warning);
+
+        // The batch executor counts them too
+        let min = run("PIPE CONSOLE | a: TAKE 3 | MIN 0,5 | CONSOLE ? a: | HOLE");
+        assert_eq!(min.output.trim_end(), "12");
+        assert_eq!(min.error_records, 2);
+        assert_eq!(run("PIPE CONSOLE | COUNT | CONSOLE").error_records, 0);
+    }
+
+    #[test]
+    fn test_filter_number_error_records() {
+        let run = |pipeline: &str| {
+            execute_pipeline_run(
+                "00012\n12X45\n     \n00030",
+                pipeline,
+                &None,
+                &HostVars::new(),
+            )
+            .unwrap()
+        };
+        let num = run("PIPE CONSOLE | FILTER 0,5 NUM >= 0 | CONSOLE");
+        assert_eq!(num.output_count, 3);
+        assert_eq!(num.error_records, 1);
+        assert_eq!(num.condition_code, ConditionCode::Error);
+
+        // A blank zoned field is not a number either
+        let zoned = run("PIPE CONSOLE | FILTER 0,5 ZD > 0 | CONSOLE");
+        assert_eq!(zoned.output_count, 2);
+        assert_eq!(zoned.error_records, 2);
+
+        let both = run(r#"PIPE CONSOLE | FILTER 0,1 = "0" OR 0,5 NUM > 20 | CONSOLE"#);
+        assert_eq!(both.error_records, 1);
+        let labeled = run("PIPE CONSOLE | a: FILTER 0,5 NUM > 20 | CONSOLE ? a: | HOLE");
+        assert_eq!(labeled.output_count, 1);
+        assert_eq!(labeled.error_records, 1);
+        assert_eq!(
+            run(r#"PIPE CONSOLE | FILTER 0,5 = "12X45" | CONSOLE"#).error_records,
+            0
+        );
+    }
+
+    #[test]
+    fn test_parse_split() {
+        let cmd = parse(r#"SPLIT sales IF 18,10 = "SALES" BUILD 0,8,0; 28,8,8"#).unwrap();
+        assert_eq!(
+            cmd.to_string(),
+            r#"SPLIT SALES IF 18,10 = "SALES" BUILD 0,8,0; 28,8,8"#
+        );
+        assert_eq!(
+            parse(&cmd.to_string()).unwrap().to_string(),
+            cmd.to_string()
+        );
+        assert_eq!(parse("SPLIT ALL").unwrap().to_string(), "SPLIT ALL");
+        assert_eq!(
+            parse("SPLIT LOSS IF 28,8 ZD < 0").unwrap().to_string(),
+            "SPLIT LOSS IF 28,8 ZD < 0"
+        );
+
+        assert!(parse("SPLIT").is_err());
+        assert!(parse("SPLIT A/B").is_err());
+        assert!(parse("SPLIT X WHERE 0,1 = \"A\"").is_err());
+        assert!(parse("SPLIT X BUILD 0,8").is_err());
+    }
+
+    #[test]
+    fn test_execute_metrics() {
+        let input = "SMITH   JOHN      SALES     00050000\n\
+                     JONES   MARY      ENGINEER  00075000\n\
+                     DOE     JANE      SALES     00060000";
+        let pipeline = r#"PIPE CONSOLE | LOCATE /SALES/ | DUPLICATE 2 | CONSOLE"#;
+        let run = execute_pipeline_run(input, pipeline, &None, &HostVars::new()).unwrap();
+        let stages: Vec<(&str, usize, usize, usize)> = run
+            .metrics
+            .stages
+            .iter()
+            .map(|s| (s.name.as_str(), s.records_in, s.records_out, s.filtered()))
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                ("CONSOLE", 0, 3, 0),
+                ("LOCATE", 3, 2, 1),
+                ("DUPLICATE", 2, 6, 0),
+                ("CONSOLE", 6, 6, 0),
+            ]
+        );
+        assert!(run.metrics.slowest().is_some());
+    }
+
+    #[test]
+    fn test_execute_split() {
+        let input = "SMITH   JOHN      SALES     00050000\n\
+                     JONES   MARY      ENGINEER  00075000\n\
+                     DOE     JANE      SALES     00060000";
+        let pipeline = r#"PIPE CONSOLE
+| SPLIT SALES IF 18,10 = "SALES" BUILD 0,8,0
+| SPLIT ALL
+| TAKE 1
+| SPLIT FIRST
+| CONSOLE
+?"#;
+        let run = execute_pipeline_run(input, pipeline, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.output_count, 1);
+        let names: Vec<_> = run.outputs.names().collect();
+        assert_eq!(names, ["SALES", "ALL", "FIRST"]);
+        let sales: Vec<_> = run
+            .outputs
+            .get("SALES")
+            .unwrap()
+            .iter()
+            .map(|r| r.as_str().trim_end())
+            .collect();
+        assert_eq!(sales, ["SMITH", "DOE"]);
+        assert_eq!(run.outputs.get("ALL").unwrap().len(), 3);
+        assert_eq!(run.outputs.get("FIRST").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_file_stages() {
+        assert_eq!(
+            parse("< data/in.txt").unwrap(),
+            Command::FileIn {
+                path: "data/in.txt".to_string()
+            }
+        );
+        let cmd = parse(">> out.txt").unwrap();
+        assert_eq!(
+            cmd,
+            Command::FileOut {
+                path: "out.txt".to_string(),
+                append: true
+            }
+        );
+        assert_eq!(cmd.to_string(), ">> out.txt");
+        assert_eq!(parse(">out.txt").unwrap().to_string(), "> out.txt");
+        assert!(parse("<").is_err());
+        assert!(parse("> a b").is_err());
+    }
+
+    #[test]
+    fn test_execute_file_stages() {
+        let pipeline = "PIPE < in.txt | > all.txt | TAKE 1 | >> first.txt";
+        let commands = parse_commands(pipeline).unwrap();
+        assert_eq!(input_file(&commands), Some("in.txt"));
+        let run = execute_pipeline_run("AAA\nBBB", pipeline, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.input_count, 2);
+        assert_eq!(run.output, "AAA");
+        assert_eq!(run.files.get("all.txt").unwrap().len(), 2);
+        assert_eq!(run.files.get("first.txt").unwrap().len(), 1);
+        assert!(run.outputs.is_empty());
+        assert_eq!(
+            PipelinePlan::parse("PIPE CONSOLE | < in.txt | CONSOLE").unwrap_err(),
+            "< can only be the first stage"
+        );
+    }
+
+    #[test]
+    fn test_parse_segments() {
+        let text = "PIPE CONSOLE\n| a: LOCATE /X/\n| CONSOLE\n? a:\n| b: TAKE 1\n| CONSOLE\n? b:\n| HOLE\n?";
+        let plan = PipelinePlan::parse(text).unwrap();
+        assert_eq!(plan.commands().len(), 3);
+        assert_eq!(plan.label_of(1), Some("a"));
+        assert_eq!(plan.segments().len(), 2);
+        assert_eq!(plan.segments()[0].input(), "a");
+        assert_eq!(plan.segments()[0].label_of(0), Some("b"));
+        assert_eq!(plan.all_commands().count(), 6);
+        assert_eq!(plan.to_string(), text);
+
+        // The same pipeline on one line
+        let line = "PIPE CONSOLE | a: LOCATE /X/ | CONSOLE ? a: | b: TAKE 1 | CONSOLE ? b: | HOLE";
+        assert_eq!(PipelinePlan::parse(line).unwrap(), plan);
+
+        // A ? inside a stage's operands does not start a segment
+        let plan = PipelinePlan::parse("PIPE CONSOLE | LOCATE / ? / | CONSOLE ?").unwrap();
+        assert!(plan.segments().is_empty());
+    }
+
+    #[test]
+    fn test_segment_errors() {
+        let error = |text: &str| PipelinePlan::parse(text).unwrap_err();
+        assert!(error("PIPE CONSOLE | CONSOLE\n? CONSOLE").contains("label alone"));
+        assert!(error("PIPE CONSOLE | a: | CONSOLE").contains("needs a stage"));
+        assert!(error("PIPE CONSOLE | CONSOLE ? a: | CONSOLE").contains("no labeled stage"));
+        assert!(error("PIPE CONSOLE | a: UPPER | CONSOLE ? a: | CONSOLE").contains("no secondary"));
+        assert!(error("PIPE CONSOLE | a: TAKE 1 | a: SKIP 1 | CONSOLE").contains("more than once"));
+        assert!(error("PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: ?").contains("at least one stage"));
+        assert!(
+            error("PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: | CONSOLE ? a: | CONSOLE")
+                .contains("already connected")
+        );
+        assert!(error("PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: | < x").contains("first stage"));
+    }
+
+    #[test]
+    fn test_execute_segments() {
+        let input = "SMITH   JOHN      SALES     00050000\n\
+                     JONES   MARY      ENGINEER  00075000\n\
+                     DOE     JANE      SALES     00060000\n\
+                     WILSON  BOB       MARKETING 00055000";
+        // Route the records FILTER drops to a segment of their own
+        let pipeline = r#"PIPE CONSOLE
+| sales: FILTER 18,10 = "SALES"
+| SELECT 0,8,0
+| CONSOLE
+? sales:
+| others: LOCATE /ENGINEER/
+| SELECT 0,8,0; 18,10,8
+| > engineers.txt
+? others:
+| COUNT
+| CONSOLE
+?"#;
+        let run = execute_pipeline_run(input, pipeline, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.output, "SMITH\nDOE\n1");
+        assert_eq!(run.output_count, 3);
+        assert_eq!(run.input_count, 4);
+        let engineers = run.files.get("engineers.txt").unwrap();
+        assert_eq!(engineers.len(), 1);
+        assert_eq!(engineers[0].as_str().trim_end(), "JONES   ENGINEER");
+        // Every stage, in the main pipeline and the segments, is reported
+        assert_eq!(run.debug_info.len(), 9);
+        assert_eq!(run.debug_info[4].stage_name, "LOCATE");
+        assert_eq!(run.debug_info[4].input_count, 2);
+    }
+
+    #[test]
+    fn test_secondary_outputs_of_selection_stages() {
+        let input = "A\nB\nA\nC";
+        let rejected = |stage: &str| {
+            let pipeline = format!("PIPE CONSOLE | r: {stage} | HOLE ? r: | CONSOLE");
+            execute_pipeline(input, &pipeline).unwrap().0
+        };
+        assert_eq!(rejected("TAKE 1"), "B\nA\nC");
+        assert_eq!(rejected("SKIP 3"), "A\nB\nA");
+        assert_eq!(rejected("TAKE LAST 1"), "A\nB\nA");
+        assert_eq!(rejected("DROP LAST 3"), "B\nA\nC");
+        assert_eq!(rejected("UNIQUE"), "A");
+        assert_eq!(rejected("RECNO 2-*"), "A");
+        assert_eq!(rejected("SAMPLE 0"), input);
+        assert_eq!(rejected("NLOCATE /A/"), "A\nA");
+        assert_eq!(rejected(r#"FILTER 0,1 != "B""#), "B");
+        // An unconnected label changes nothing
+        let (output, _, _) = execute_pipeline(input, "PIPE CONSOLE | r: TAKE 2 | CONSOLE").unwrap();
+        assert_eq!(output, "A\nB");
+    }
+
+    #[test]
+    fn test_parse_fanin() {
+        assert_eq!(parse("FANIN").unwrap(), Command::Fanin);
+        assert_eq!(parse("faninany").unwrap(), Command::Faninany);
+        assert!(Command::Fanin.has_secondary_input());
+        assert!(!Command::Fanin.has_secondary_output());
+
+        let text = "PIPE CONSOLE\n| a: LOCATE /X/\n| f: FANIN\n| CONSOLE\n? a:\n| UPPER\n| f:\n?";
+        let plan = PipelinePlan::parse(text).unwrap();
+        assert_eq!(plan.segments()[0].target(), Some("f"));
+        assert_eq!(plan.segments()[0].commands(), &[Command::Upper]);
+        assert_eq!(plan.to_string(), text);
+        let line = "PIPE CONSOLE | a: LOCATE /X/ | f: FANIN | CONSOLE ? a: | UPPER | f: ?";
+        assert_eq!(PipelinePlan::parse(line).unwrap(), plan);
+
+        // A segment can feed a FANIN without stages of its own
+        let plan =
+            PipelinePlan::parse("PIPE CONSOLE | a: TAKE 1 | f: FANIN | CONSOLE ? a: | f:").unwrap();
+        assert!(plan.segments()[0].commands().is_empty());
+    }
+
+    #[test]
+    fn test_fanin_errors() {
+        let error = |text: &str| PipelinePlan::parse(text).unwrap_err();
+        assert!(
+            error("PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: | f: | UPPER")
+                .contains("ends at a label")
+        );
+        assert!(error("PIPE CONSOLE | a: TAKE 1 | CONSOLE ? a: | f:").contains("no labeled stage"));
+        assert!(
+            error("PIPE CONSOLE | a: TAKE 1 | f: UPPER | CONSOLE ? a: | f:")
+                .contains("no secondary input")
+        );
+        // A labeled FANIN that nothing feeds is fine
+        assert!(PipelinePlan::parse("PIPE CONSOLE | f: FANIN | CONSOLE").is_ok());
+    }
+
+    #[test]
+    fn test_execute_fanin() {
+        let input = "A1\nB1\nA2\nB2\nA3";
+        // FANIN passes the primary input, then each segment's records
+        let pipeline = "PIPE CONSOLE | a: LOCATE /A/ | f: FANIN | CONSOLE ? a: | LOWER | f:";
+        let (output, _, count) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "A1\nA2\nA3\nb1\nb2");
+        assert_eq!(count, 5);
+
+        // FANINANY takes one record from each stream in turn
+        let pipeline = "PIPE CONSOLE | a: LOCATE /A/ | f: FANINANY | CONSOLE ? a: | LOWER | f:";
+        let (output, _, _) = execute_pipeline(input, pipeline).unwrap();
+        assert_eq!(output, "A1\nb1\nA2\nb2\nA3");
+
+        // Streams from several segments, joined in a segment, in segment order
+        let pipeline = "PIPE CONSOLE | a: TAKE 1 | HOLE\n\
+                        ? a: | b: TAKE 2 | f: FANIN | CONSOLE\n\
+                        ? b: | c: TAKE 1 | f:\n\
+                        ? c: | UPPER | LITERAL X | f:";
+        let run = execute_pipeline_run(input, pipeline, &None, &HostVars::new()).unwrap();
+        assert_eq!(run.output, "B1\nA2\nB2\nX\nA3");
+        // Stages are reported in the order they are written
+        let names: Vec<&str> = run
+            .debug_info
+            .iter()
+            .map(|d| d.stage_name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "CONSOLE", "TAKE", "HOLE", "TAKE", "FANIN", "CONSOLE", "TAKE", "UPPER", "LITERAL"
+            ]
+        );
+        assert_eq!(run.debug_info[4].input_count, 5);
+
+        // An unlabeled FANIN has only its primary input
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | FANIN | TAKE 1 | CONSOLE").unwrap();
+        assert_eq!(output, "A1");
+
+        // A FANIN fed by records that must first pass through it never runs
+        let pipeline = "PIPE CONSOLE | f: FANIN | a: TAKE 1 | CONSOLE ? a: | f:";
+        assert!(
+            execute_pipeline(input, pipeline)
+                .unwrap_err()
+                .contains("loop")
+        );
+    }
+
+    #[test]
+    fn test_named_inputs() {
+        let pipeline = "PIPE < MASTER | f: FANIN | CONSOLE ? < TRANS | LOWER | f:";
+        let plan = PipelinePlan::parse(pipeline).unwrap();
+        assert_eq!(plan.segments()[0].file(), Some("TRANS"));
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+
+        let inputs = InputFiles::new().with_text("TRANS", "B2\n\nA9");
+        let run = execute_plan_with_inputs(&plan, "A1\nB1", &None, &HostVars::new(), &inputs);
+        let run = run.unwrap();
+        assert_eq!(run.output, "A1\nB1\nb2\na9");
+        assert_eq!(run.input_count, 2);
+
+        let error = execute_plan(&plan, "A1", &None, &HostVars::new()).unwrap_err();
+        assert!(error.contains("TRANS is not supplied"));
+        let error = |text: &str| PipelinePlan::parse(text).unwrap_err();
+        assert!(error("PIPE CONSOLE | CONSOLE ? < TRANS").contains("needs at least one stage"));
+        assert!(error("PIPE CONSOLE | CONSOLE ? <").contains("requires a file name"));
+    }
+
+    #[test]
+    fn test_parse_locate_simple() {
+        let cmd = parse(r#"LOCATE "SALES""#).unwrap();
+        match cmd {
+            Command::Locate { pattern, field } => {
+                assert_eq!(pattern, "SALES");
+                assert!(field.is_none());
+            }
+            _ => panic!("Expected Locate"),
+        }
+    }
+
+    #[test]
+    fn test_parse_locate_with_field() {
+        let cmd = parse(r#"LOCATE 18,10 "SALES""#).unwrap();
+        match cmd {
+            Command::Locate { pattern, field } => {
+                assert_eq!(pattern, "SALES");
+                assert_eq!(field, Some((18, 10)));
+            }
+            _ => panic!("Expected Locate with field"),
+        }
+    }
+
+    #[test]
+    fn test_parse_locate_slash_delimiters() {
+        let cmd = parse(r#"LOCATE /ERROR/"#).unwrap();
+        match cmd {
+            Command::Locate { pattern, field } => {
+                assert_eq!(pattern, "ERROR");
+                assert!(field.is_none());
+            }
+            _ => panic!("Expected Locate"),
+        }
+    }
+
+    #[test]
+    fn test_parse_nlocate() {
+        let cmd = parse(r#"NLOCATE "SALES""#).unwrap();
+        match cmd {
+            Command::Nlocate { pattern, field } => {
+                assert_eq!(pattern, "SALES");
+                assert!(field.is_none());
+            }
+            _ => panic!("Expected Nlocate"),
+        }
+    }
+
+    #[test]
+    fn test_execute_locate_with_field() {
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+SALESGUY BOB      MARKETING 00040000";
+        let pipeline = r#"PIPE CONSOLE
+| LOCATE 18,10 "SALES"
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        // Only SMITH has SALES in the department field (18,10)
+        // SALESGUY has SALES in name but not in field 18,10
+        assert_eq!(input_count, 3);
+        assert_eq!(output_count, 1);
+        assert!(output.contains("SMITH"));
+        assert!(!output.contains("SALESGUY"));
+    }
+
+    #[test]
+    fn test_execute_nlocate() {
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+        let pipeline = r#"PIPE CONSOLE
+| NLOCATE "SALES"
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 3);
+        assert_eq!(output_count, 1);
+        assert!(!output.contains("SMITH"));
+        assert!(output.contains("JONES"));
+        assert!(!output.contains("DOE"));
+    }
+
+    #[test]
+    fn test_parse_count() {
+        let cmd = parse("COUNT").unwrap();
+        assert!(matches!(cmd, Command::Count));
+    }
+
+    #[test]
+    fn test_execute_count_after_filter() {
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+        let pipeline = r#"PIPE CONSOLE
+| LOCATE "SALES"
+| COUNT
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 3);
+        assert_eq!(output_count, 1);
+        assert_eq!(output, "2");
+    }
+
+    #[test]
+    fn test_parse_change() {
+        let cmd = parse(r#"CHANGE "SALES" "MARKETING""#).unwrap();
+        match cmd {
+            Command::Change { old, new, .. } => {
+                assert_eq!(old, "SALES");
+                assert_eq!(new, "MARKETING");
+            }
+            _ => panic!("Expected Change"),
+        }
+    }
+
+    #[test]
+    fn test_parse_change_slash_delimiters() {
+        let cmd = parse(r#"CHANGE /old/ /new/"#).unwrap();
+        match cmd {
+            Command::Change { old, new, .. } => {
+                assert_eq!(old, "old");
+                assert_eq!(new, "new");
+            }
+            _ => panic!("Expected Change"),
+        }
+    }
+
+    #[test]
+    fn test_execute_change() {
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+        let pipeline = r#"PIPE CONSOLE
+| CHANGE "SALES" "MKTG"
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 3);
+        assert_eq!(output_count, 3);
+        assert!(output.contains("MKTG"));
+        assert!(!output.contains("SALES"));
+        // ENGINEER should be unchanged
+        assert!(output.contains("ENGINEER"));
+    }
+
+    #[test]
+    fn test_execute_change_to_empty() {
+        let input = "ERROR: Something went wrong
+INFO: All is well
+ERROR: Another problem";
+        let pipeline = r#"PIPE CONSOLE
+| CHANGE "ERROR: " ""
+| CONSOLE
+?"#;
+
+        let (output, _input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(output_count, 3);
+        assert!(output.contains("Something went wrong"));
+        assert!(!output.contains("ERROR:"));
+    }
+
+    #[test]
+    fn test_parse_literal() {
+        let cmd = parse(r#"LITERAL Hello World"#).unwrap();
+        match cmd {
+            Command::Literal { text } => {
+                assert_eq!(text, "Hello World");
+            }
+            _ => panic!("Expected Literal"),
+        }
+    }
+
+    #[test]
+    fn test_parse_literal_slash_delimiters() {
+        let cmd = parse(r#"LITERAL /test data/"#).unwrap();
+        match cmd {
+            Command::Literal { text } => {
+                // CMS-style: delimiters are part of the literal text
+                assert_eq!(text, "/test data/");
+            }
+            _ => panic!("Expected Literal"),
+        }
+    }
+
+    #[test]
+    fn test_execute_literal_append() {
+        let input = "LINE ONE
+LINE TWO";
+        let pipeline = r#"PIPE CONSOLE
+| LITERAL "FOOTER"
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 3);
+        assert!(output.contains("LINE ONE"));
+        assert!(output.contains("LINE TWO"));
+        assert!(output.contains("FOOTER"));
+    }
+
+    #[test]
+    fn test_execute_literal_with_empty_input() {
+        let input = "";
+        let pipeline = r#"PIPE CONSOLE
+| LITERAL ONLY RECORD
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 0);
+        assert_eq!(output_count, 1);
+        assert_eq!(output, "ONLY RECORD");
+    }
+
+    #[test]
+    fn test_execute_multiple_literals() {
+        let input = "DATA";
+        let pipeline = r#"PIPE CONSOLE
+| LITERAL "HEADER"
+| LITERAL "FOOTER"
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 1);
+        assert_eq!(output_count, 3);
+        // Order should be: DATA, HEADER, FOOTER (each LITERAL appends)
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_parse_upper() {
+        let cmd = parse("UPPER").unwrap();
+        assert!(matches!(cmd, Command::Upper));
+    }
+
+    #[test]
+    fn test_parse_lower() {
+        let cmd = parse("LOWER").unwrap();
+        assert!(matches!(cmd, Command::Lower));
+    }
+
+    #[test]
+    fn test_execute_upper() {
+        let input = "Hello World
+mixed CASE text";
+        let pipeline = r#"PIPE CONSOLE
+| UPPER
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 2);
+        assert!(output.contains("HELLO WORLD"));
+        assert!(output.contains("MIXED CASE TEXT"));
+        assert!(!output.contains("Hello"));
+        assert!(!output.contains("mixed"));
+    }
+
+    #[test]
+    fn test_execute_lower() {
+        let input = "Hello World
+MIXED CASE TEXT";
+        let pipeline = r#"PIPE CONSOLE
+| LOWER
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 2);
+        assert!(output.contains("hello world"));
+        assert!(output.contains("mixed case text"));
+        assert!(!output.contains("Hello"));
+        assert!(!output.contains("MIXED"));
+    }
+
+    #[test]
+    fn test_execute_upper_lower_chain() {
+        let input = "Test Data";
+        let pipeline = r#"PIPE CONSOLE
+| UPPER
+| LOWER
+| CONSOLE
+?"#;
+
+        let (output, _input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(output_count, 1);
+        assert!(output.contains("test data"));
+    }
+
+    #[test]
+    fn test_parse_reverse() {
+        let cmd = parse("REVERSE").unwrap();
+        assert!(matches!(cmd, Command::Reverse));
+    }
+
+    #[test]
+    fn test_execute_reverse() {
+        let input = "Hello
+World";
+        let pipeline = r#"PIPE CONSOLE
+| REVERSE
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 2);
+        assert!(output.contains("olleH"));
+        assert!(output.contains("dlroW"));
+    }
+
+    #[test]
+    fn test_execute_reverse_twice() {
+        let input = "Hello World";
+        let pipeline = r#"PIPE CONSOLE
+| REVERSE
+| REVERSE
+| CONSOLE
+?"#;
+
+        let (output, _input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(output_count, 1);
+        // Reversing twice should give back original
+        assert_eq!(output, "Hello World");
+    }
+
+    #[test]
+    fn test_execute_reverse_palindrome() {
+        let input = "radar
+level";
+        let pipeline = r#"PIPE CONSOLE
+| REVERSE
+| CONSOLE
+?"#;
+
+        let (output, _input_count, _output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        // Palindromes should be the same after reverse
+        assert!(output.contains("radar"));
+        assert!(output.contains("level"));
+    }
+
+    #[test]
+    fn test_parse_duplicate() {
+        let cmd = parse("DUPLICATE 3").unwrap();
+        match cmd {
+            Command::Duplicate { n } => assert_eq!(n, 3),
+            _ => panic!("Expected Duplicate"),
+        }
+    }
+
+    #[test]
+    fn test_parse_duplicate_default_and_errors() {
+        assert_eq!(parse("DUPLICATE").unwrap(), Command::Duplicate { n: 1 });
+        assert_eq!(parse("DUPLICATE 0").unwrap(), Command::Duplicate { n: 0 });
+        assert!(parse("DUPLICATE -1").is_err());
+        assert!(parse("DUPLICATE two").is_err());
+    }
+
+    #[test]
+    fn test_execute_duplicate() {
+        let input = "A
+B";
+        let pipeline = r#"PIPE CONSOLE
+| DUPLICATE 1
+| CONSOLE
+?"#;
+
+        let (output, input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(input_count, 2);
+        assert_eq!(output_count, 4); // 2 records * 2 = 4
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["A", "A", "B", "B"]);
+    }
+
+    #[test]
+    fn test_execute_duplicate_three() {
+        let input = "X";
+        let pipeline = r#"PIPE CONSOLE
+| DUPLICATE 2
+| CONSOLE
+?"#;
+
+        let (output, _input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        assert_eq!(output_count, 3);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["X", "X", "X"]);
+    }
+
+    #[test]
+    fn test_execute_duplicate_zero() {
+        let input = "Original";
+        let pipeline = r#"PIPE CONSOLE
+| DUPLICATE 0
+| CONSOLE
+?"#;
+
+        let (output, _input_count, output_count) = execute_pipeline(input, pipeline).unwrap();
+
+        // DUPLICATE 0 should just pass through unchanged
+        assert_eq!(output_count, 1);
+        assert_eq!(output, "Original");
+    }
+
+    #[test]
+    fn test_parse_and_execute_entry_points() {
+        let commands = super::parse("PIPE CONSOLE | UPPER | CONSOLE ?").unwrap();
+        assert_eq!(commands.len(), 3);
+        assert!(super::parse("CONSOLE").unwrap_err().contains("at least 2"));
+        assert!(
+            super::parse("UPPER | CONSOLE")
+                .unwrap_err()
+                .contains("first stage")
+        );
+
+        let run = execute("a\n\nb", "CONSOLE | UPPER | CONSOLE", &HostVars::new()).unwrap();
+        assert_eq!(run.output, "A\nB");
+        assert_eq!(run.input_count, 2);
+        assert!(source_records(&Command::Upper, "a", 80, drop).is_err());
+    }
+
+    #[test]
+    fn test_stream_records_pooled() {
+        let pool = RefCell::new(RecordPool::new());
+        pool.borrow_mut().recycle(Record::from_str("OLD DATA"));
+        let mut records = stream_records_pooled(
+            &Command::Console,
+            ["A", "", "B"].into_iter(),
+            80,
+            &pool,
+            drop,
+        )
+        .unwrap();
+        let first = records.next().unwrap().unwrap();
+        assert_eq!(first, Record::from_str("A"));
+        assert!(pool.borrow().is_empty());
+
+        pool.borrow_mut().recycle(first);
+        assert_eq!(records.next().unwrap().unwrap(), Record::from_str("B"));
+        assert!(pool.borrow().is_empty());
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_source_warnings_go_to_caller() {
+        let plan = PipelinePlan::parse("LAYOUT A 0,2\nPIPE FROM CSV | CONSOLE").unwrap();
+        let mut warnings = Vec::new();
+        let records = source_records(plan.source(), "ABCD\nAB", 80, |w| warnings.push(w)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("FROM CSV: "), "{warnings:?}");
+    }
+
+    #[test]
+    fn test_parse_change_options() {
+        let layout: RecordLayout = "LAYOUT DEPT 18,10".parse().unwrap();
+        let cases = [
+            ("CHANGE /SALES/MKTG/", None, None, "SALES", "MKTG"),
+            ("CHANGE /ERROR: //", None, None, "ERROR: ", ""),
+            ("CHANGE 18,10 /A/B/ 2", Some((18, 10)), Some(2), "A", "B"),
+            ("CHANGE dept \"A\" \"B\"", Some((18, 10)), None, "A", "B"),
+        ];
+        for (line, want_field, want_count, want_old, want_new) in cases {
+            match parse_command(line, &layout).unwrap() {
+                Command::Change {
+                    old,
+                    new,
+                    field,
+                    count,
+                } => {
+                    assert_eq!((old.as_str(), new.as_str()), (want_old, want_new), "{line}");
+                    assert_eq!((field, count), (want_field, want_count), "{line}");
+                }
+                other => panic!("Expected Change, got {other:?}"),
+            }
+        }
+        assert!(parse("CHANGE /A/B/ 0").unwrap_err().contains("positive"));
+        assert!(parse("CHANGE /A/B").unwrap_err().contains("Unclosed"));
+        assert!(parse("CHANGE 75,10 /A/B/").unwrap_err().contains("outside"));
+    }
+
+    #[test]
+    fn test_execute_change_range_and_count() {
+        let input = "SALES   SALES SALES";
+        let (output, _, _) = execute_pipeline(
+            input,
+            "PIPE CONSOLE | CHANGE 8,11 /SALES/MKTG/ 1 | CONSOLE ?",
+        )
+        .unwrap();
+        assert_eq!(output, "SALES   MKTG SALES");
+
+        let plan = PipelinePlan::parse("CONSOLE | CHANGE 8,11 /SALES/MKTG/ 1 | CONSOLE").unwrap();
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_execute_change_empty_search() {
+        let input = "SALES   SMITH";
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | CHANGE //Z/ | CONSOLE ?").unwrap();
+        assert_eq!(output, "ZSALES   SMITH");
+
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | CHANGE 8,5 //*/ | CONSOLE ?").unwrap();
+        assert_eq!(output, "SALES   *SMITH");
+
+        let plan = PipelinePlan::parse("CONSOLE | CHANGE //Z/ | CONSOLE").unwrap();
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_parse_filter_expressions() {
+        let layout: RecordLayout = "LAYOUT DEPT 18,10; SALARY 28,8".parse().unwrap();
+        let cmd =
+            parse_command(r#"FILTER salary num > 60000 and DEPT = "SALES""#, &layout).unwrap();
+        assert_eq!(
+            cmd,
+            Command::FilterExpr {
+                condition: Condition::number(28, 8, CompareOp::Gt, 60000).and(Condition::text(
+                    18,
+                    10,
+                    CompareOp::Eq,
+                    "SALES"
+                )),
+            }
+        );
+        assert_eq!(
+            cmd.to_string(),
+            r#"FILTER 28,8 NUM > 60000 AND 18,10 = "SALES""#
+        );
+        assert_eq!(
+            parse_condition(r#" dept == "SALES" "#, &layout).unwrap(),
+            Condition::text(18, 10, CompareOp::Eq, "SALES")
+        );
+        assert!(parse_condition("", &layout).is_err());
+
+        let cmd = parse(r#"FILTER (0,8 >= "M" OR 28,8 ZD<0) AND 18,10 != /ENGINEER/"#).unwrap();
+        assert_eq!(
+            cmd.to_string(),
+            r#"FILTER (0,8 >= "M" OR 28,8 ZD < 0) AND 18,10 != "ENGINEER""#
+        );
+        assert_eq!(parse(&cmd.to_string()).unwrap(), cmd);
+
+        // Lone = and != comparisons keep their own commands
+        assert!(matches!(
+            parse(r#"FILTER (18,10="SALES")"#).unwrap(),
+            Command::FilterEq {
+                pos: 18,
+                len: 10,
+                ..
+            }
+        ));
+
+        assert!(parse(r#"FILTER 0,1 = "A" AND"#).is_err());
+        assert!(parse(r#"FILTER (0,1 = "A""#).unwrap_err().contains("')'"));
+        assert!(
+            parse(r#"FILTER 0,1 = "A" "B""#)
+                .unwrap_err()
+                .contains("Unexpected")
+        );
+        assert!(
+            parse("FILTER 0,1 NUM > x")
+                .unwrap_err()
+                .contains("whole number")
+        );
+        assert!(parse("FILTER 0,1 => 5").unwrap_err().contains("operator"));
+        assert!(
+            parse("FILTER 78,8 NUM > 0")
+                .unwrap_err()
+                .contains("outside")
+        );
+    }
+
+    #[test]
+    fn test_execute_filter_expressions() {
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000
+ADAMS   ALAN      SALES     00061000";
+        let run = |filter: &str| {
+            let (output, _, _) =
+                execute_pipeline(input, &format!("PIPE CONSOLE | {filter} | CONSOLE ?")).unwrap();
+            output
+                .lines()
+                .map(|line| line[..8].trim().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            run(r#"FILTER 28,8 NUM > 60000 AND 18,10 = "SALES""#),
+            ["ADAMS"]
+        );
+        assert_eq!(
+            run(r#"FILTER 28,8 NUM <= 60000 OR 0,8 < "B""#),
+            ["SMITH", "DOE", "ADAMS"]
+        );
+        assert_eq!(
+            run(r#"FILTER 0,8 > "D" AND (18,10 = "ENGINEER" OR 28,8 NUM = 50000)"#),
+            ["SMITH", "JONES"]
+        );
+    }
+
+    #[cfg(not(feature = "regex"))]
+    #[test]
+    fn test_filter_regex_requires_feature() {
+        let err = parse("FILTER 0,8 ~ /^E/").unwrap_err();
+        assert!(err.contains("`regex` feature"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_filter_regex() {
+        let layout: RecordLayout = "LAYOUT ID 0,8; HIRED 8,10".parse().unwrap();
+        let cmd = parse_command(r"FILTER id ~ /^E\d{5}$/ AND HIRED~'^\d{4}/'", &layout).unwrap();
+        assert_eq!(
+            cmd.to_string(),
+            r#"FILTER 0,8 ~ /^E\d{5}$/ AND 8,10 ~ "^\d{4}/""#
+        );
+        assert_eq!(parse(&cmd.to_string()).unwrap(), cmd);
+        assert!(
+            parse("FILTER 0,8 ~ /(/")
+                .unwrap_err()
+                .contains("Invalid FILTER pattern")
+        );
+        assert!(parse("FILTER 0,8 NUM ~ 5").is_err());
+
+        let input = "E01234  2026/02/04\nX999    2026/02/05\nE12345  04/02/2026";
+        let (output, _, _) =
+            execute_pipeline(input, r"PIPE CONSOLE | FILTER 0,8 ~ /^E\d{5}$/ | CONSOLE ?").unwrap();
+        assert_eq!(output, "E01234  2026/02/04\nE12345  04/02/2026");
+    }
+
+    #[test]
+    fn test_statistics() {
+        let layout: RecordLayout = "LAYOUT SALARY 28,8".parse().unwrap();
+        let cmd = parse_command("avg salary", &layout).unwrap();
+        assert_eq!(
+            cmd,
+            Command::Statistic {
+                kind: AggregateKind::Avg,
+                pos: 28,
+                len: 8
+            }
+        );
+        assert_eq!(cmd.to_string(), "AVG 28,8");
+        assert_eq!(parse("MIN 28,8").unwrap().name(), "MIN");
+        assert!(parse("SUM").is_err());
+        assert!(parse("MAX 78,8").unwrap_err().contains("outside"));
+        assert!(
+            parse("SUM 28,8 ZD")
+                .unwrap_err()
+                .contains("Unexpected 'ZD'")
+        );
+
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+        let run = |stage: &str| {
+            execute_pipeline(input, &format!("PIPE CONSOLE | {stage} | CONSOLE ?"))
+                .unwrap()
+                .0
+        };
+        assert_eq!(run("SUM 28,8"), "185000");
+        assert_eq!(run("MIN 28,8"), "50000");
+        assert_eq!(run("MAX 28,8"), "75000");
+        assert_eq!(run("AVG 28,8"), "61666");
+        assert_eq!(run("LOCATE /NONE/ | SUM 28,8"), "0");
+        assert_eq!(run("LOCATE /NONE/ | COUNT"), "0");
+    }
+
+    #[test]
+    fn test_unique() {
+        let layout: RecordLayout = "LAYOUT DEPT 18,10".parse().unwrap();
+        let cmd = parse_command("unique dept", &layout).unwrap();
+        assert_eq!(
+            cmd,
+            Command::Unique {
+                field: Some((18, 10))
+            }
+        );
+        assert_eq!(cmd.to_string(), "UNIQUE 18,10");
+        assert_eq!(parse("UNIQUE").unwrap().to_string(), "UNIQUE");
+        assert!(parse("UNIQUE 78,8").unwrap_err().contains("outside"));
+        assert!(parse("UNIQUE 0,8 9,1").unwrap_err().contains("Unexpected"));
+
+        let input = "SMITH   JOHN      SALES
+JONES   MARY      ENGINEER
+SMITH   JOHN      SALES
+DOE     JANE      SALES";
+        let (output, _, _) = execute_pipeline(input, "PIPE CONSOLE | UNIQUE | CONSOLE ?").unwrap();
+        assert_eq!(output.lines().count(), 3);
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | UNIQUE 18,10 | CONSOLE ?").unwrap();
+        assert_eq!(
+            output.lines().map(|l| &l[..5]).collect::<Vec<_>>(),
+            ["SMITH", "JONES"]
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        let layout: RecordLayout = "LAYOUT DEPT 18,10; SUMMARY 28,8".parse().unwrap();
+        let cmd = parse_command("sort dept; summary d num sum summary zd", &layout).unwrap();
+        assert_eq!(
+            cmd,
+            Command::Sort {
+                keys: vec![
+                    SortKey::new(18, 10),
+                    SortKey::new(28, 8).descending().numeric()
+                ],
+                sum: Some(vec![SumField::new(28, 8).zoned()]),
+            }
+        );
+        assert_eq!(cmd.to_string(), "SORT 18,10; 28,8 D NUM SUM 28,8 ZD");
+        assert_eq!(parse("SORT").unwrap().to_string(), "SORT");
+        assert_eq!(
+            parse("SORT 0,5 A CH SUM NONE").unwrap().to_string(),
+            "SORT 0,5 SUM NONE"
+        );
+        assert!(parse("SORT 78,8").unwrap_err().contains("outside"));
+        assert!(
+            parse("SORT 0,8 X")
+                .unwrap_err()
+                .contains("Unknown SORT option")
+        );
+        assert!(parse("SORT 0,8 SUM").unwrap_err().contains("NONE"));
+
+        let input = "SMITH   JOHN      SALES     00050000
+JONES   MARY      ENGINEER  00075000
+DOE     JANE      SALES     00060000";
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | SORT 18,10; 0,8 | CONSOLE ?").unwrap();
+        assert_eq!(
+            output.lines().map(|l| &l[..5]).collect::<Vec<_>>(),
+            ["JONES", "DOE  ", "SMITH"]
+        );
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | SORT 18,10 D SUM 28,8 | CONSOLE ?").unwrap();
+        assert_eq!(
+            output.lines().map(|l| &l[18..36]).collect::<Vec<_>>(),
+            ["SALES     00110000", "ENGINEER  00075000"]
+        );
+        let (output, _, _) =
+            execute_pipeline(input, "PIPE CONSOLE | SORT 18,10 SUM NONE | CONSOLE ?").unwrap();
+        assert_eq!(
+            output.lines().map(|l| &l[..5]).collect::<Vec<_>>(),
+            ["JONES", "SMITH"]
+        );
+    }
+
+    #[test]
+    fn test_sorted_records_merge_spilled_runs() {
+        // Four 80-byte records per run; keys repeat across the runs
+        let mut sort = sort_for(&[SortKey::new(0, 2)], 80).with_memory(320);
+        for n in 0..20 {
+            let record = Record::from_str(&format!("{:02}{:08}", n % 5, n));
+            sort.push(record).unwrap();
+        }
+        assert_eq!(sort.runs(), 5);
+        let mut failures = Vec::new();
+        let sums = [SumField::new(2, 8)];
+        let summed: Vec<_> = sorted_records(&mut sort, Some(&sums), |e| failures.push(e))
+            .map(|r| r.field(0, 10).to_string())
+            .collect();
+        assert_eq!(
+            summed,
+            [
+                "0000000030",
+                "0100000034",
+                "0200000038",
+                "0300000042",
+                "0400000046"
+            ]
+        );
+        assert!(failures.is_empty());
+
+        // No keys sorts on the whole record
+        assert_eq!(sort_for(&[], 80).keys(), [SortKey::new(0, 80)]);
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        let layout: RecordLayout = "LAYOUT FIRST 8,10".parse().unwrap();
+        let cmd = parse_command(
+            "specs first strip 0 /has id/ nextword 0,8 zeropad nextword.10 recno 70",
+            &layout,
+        )
+        .unwrap();
+        assert_eq!(
+            cmd,
+            Command::Specs {
+                items: vec![
+                    SpecItem::range(8, 10).convert(Conversion::Strip).at(0),
+                    SpecItem::literal("has id").next_word(),
+                    SpecItem::range(0, 8)
+                        .convert(Conversion::ZeroPad)
+                        .next_word()
+                        .width(10),
+                    SpecItem::recno().at(70),
+                ],
+            }
+        );
+        assert_eq!(
+            cmd.to_string(),
+            "SPECS 8,10 STRIP 0 /has id/ NEXTWORD 0,8 ZEROPAD NEXTWORD.10 RECNO 70"
+        );
+        assert_eq!(parse(&cmd.to_string()).unwrap(), cmd);
+
+        assert!(parse("SPECS").is_err());
+        assert!(parse("SPECS 0,8").unwrap_err().contains("output column"));
+        assert!(parse("SPECS 0,8 NEXT.0").unwrap_err().contains("width"));
+        assert!(parse("SPECS 0,8 80").unwrap_err().contains("outside"));
+        assert!(
+            parse("SPECS 0,8 X2C 0")
+                .unwrap_err()
+                .contains("Invalid SPECS output")
+        );
+        assert!(parse("SPECS /open 0").unwrap_err().contains("Unclosed"));
+        assert!(
+            parse("SPECS 0,8 C2D 0")
+                .unwrap_err()
+                .contains("use ZEROPAD")
+        );
+    }
+
+    #[test]
+    fn test_execute_specs() {
+        let input = "SMITH   JOHN      SALES     00050000\nDOE     JANE      SALES     00060000";
+        let (output, _, _) = execute_pipeline(
+            input,
+            "PIPE CONSOLE | SPECS RECNO 0.2 8,10 STRIP NEXTWORD 0,8 UPPER NEXTWORD /:/ NEXT 28,8 UNPAD NEXTWORD | CONSOLE ?",
+        )
+        .unwrap();
+        assert_eq!(output, " 1 JOHN SMITH   : 50000\n 2 JANE DOE     : 60000");
+    }
 }
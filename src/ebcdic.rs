@@ -0,0 +1,408 @@
+//! EBCDIC code page conversion.
+//!
+//! Mainframe datasets are EBCDIC: fixed 80-byte records with no line
+//! separators. Records in this crate hold ASCII, so EBCDIC data is
+//! converted when it is read and written. Printable ASCII maps one-to-one
+//! onto both supported code pages; other EBCDIC bytes (binary fields,
+//! national characters) decode as `?`.
+//!
+//! In the DSL, `CONVERT ASCII [cp]` directly after the input source marks
+//! the input as EBCDIC, and `CONVERT EBCDIC [cp]` at the end marks the
+//! output as EBCDIC. The CLIs do the conversion when they read and write
+//! the files; inside the pipeline the stages pass records through.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::Record;
+//! use pipelines_rs::ebcdic::CodePage;
+//!
+//! let record = Record::from_ebcdic(&[0xC8, 0xC5, 0xD3, 0xD3, 0xD6], CodePage::Cp037);
+//! assert_eq!(record.as_str().trim_end(), "HELLO");
+//! assert_eq!(&record.to_ebcdic(CodePage::Cp037)[..6], &[0xC8, 0xC5, 0xD3, 0xD3, 0xD6, 0x40]);
+//! ```
+
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+
+use crate::Command;
+use crate::record::{RECORD_WIDTH, Record};
+
+/// An EBCDIC code page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodePage {
+    /// IBM-037, US/Canada (the default).
+    #[default]
+    Cp037,
+    /// IBM-1047, Latin-1 Open Systems (z/OS UNIX).
+    Cp1047,
+}
+
+impl CodePage {
+    /// Returns the DSL keyword for the code page.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CodePage::Cp037 => "CP037",
+            CodePage::Cp1047 => "CP1047",
+        }
+    }
+
+    /// Parses a code page keyword (case-insensitive; `037` and `1047`
+    /// are accepted without the prefix).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().trim_start_matches("CP") {
+            "037" | "37" => Some(CodePage::Cp037),
+            "1047" => Some(CodePage::Cp1047),
+            _ => None,
+        }
+    }
+
+    fn printable(&self) -> &'static [u8; 95] {
+        match self {
+            CodePage::Cp037 => &CP037_PRINTABLE,
+            CodePage::Cp1047 => &CP1047_PRINTABLE,
+        }
+    }
+
+    fn decode_table(&self) -> &'static [u8; 256] {
+        match self {
+            CodePage::Cp037 => &CP037_DECODE,
+            CodePage::Cp1047 => &CP1047_DECODE,
+        }
+    }
+
+    /// Converts one ASCII byte to EBCDIC. Bytes other than printable
+    /// ASCII become an EBCDIC `?`.
+    pub fn encode(&self, byte: u8) -> u8 {
+        match byte {
+            0x20..=0x7E => self.printable()[usize::from(byte - 0x20)],
+            _ => self.printable()[usize::from(b'?' - 0x20)],
+        }
+    }
+
+    /// Converts one EBCDIC byte to ASCII. Bytes with no printable ASCII
+    /// equivalent become `?`.
+    pub fn decode(&self, byte: u8) -> u8 {
+        self.decode_table()[usize::from(byte)]
+    }
+}
+
+/// The character set a `CONVERT` stage converts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// `CONVERT ASCII`: the input is EBCDIC.
+    Ascii,
+    /// `CONVERT EBCDIC`: the output is EBCDIC.
+    Ebcdic,
+}
+
+impl Charset {
+    /// Returns the DSL keyword for the character set.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Charset::Ascii => "ASCII",
+            Charset::Ebcdic => "EBCDIC",
+        }
+    }
+}
+
+/// EBCDIC for ASCII 0x20 (space) through 0x7E (`~`) in CP037.
+const CP037_PRINTABLE: [u8; 95] = [
+    0x40, 0x5A, 0x7F, 0x7B, 0x5B, 0x6C, 0x50, 0x7D, // space ! " # $ % & '
+    0x4D, 0x5D, 0x5C, 0x4E, 0x6B, 0x60, 0x4B, 0x61, // ( ) * + , - . /
+    0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, // 0-7
+    0xF8, 0xF9, 0x7A, 0x5E, 0x4C, 0x7E, 0x6E, 0x6F, // 8 9 : ; < = > ?
+    0x7C, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, // @ A-G
+    0xC8, 0xC9, 0xD1, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, // H-O
+    0xD7, 0xD8, 0xD9, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, // P-W
+    0xE7, 0xE8, 0xE9, 0xBA, 0xE0, 0xBB, 0xB0, 0x6D, // X Y Z [ \ ] ^ _
+    0x79, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, // ` a-g
+    0x88, 0x89, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, // h-o
+    0x97, 0x98, 0x99, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, // p-w
+    0xA7, 0xA8, 0xA9, 0xC0, 0x4F, 0xD0, 0xA1, // x y z { | } ~
+];
+
+/// CP1047 differs from CP037 only in `[`, `]`, and `^`.
+const CP1047_PRINTABLE: [u8; 95] = {
+    let mut table = CP037_PRINTABLE;
+    table[(b'[' - 0x20) as usize] = 0xAD;
+    table[(b']' - 0x20) as usize] = 0xBD;
+    table[(b'^' - 0x20) as usize] = 0x5F;
+    table
+};
+
+const CP037_DECODE: [u8; 256] = invert(&CP037_PRINTABLE);
+const CP1047_DECODE: [u8; 256] = invert(&CP1047_PRINTABLE);
+
+/// Builds the EBCDIC-to-ASCII table from the printable encode table.
+const fn invert(printable: &[u8; 95]) -> [u8; 256] {
+    let mut table = [b'?'; 256];
+    let mut idx = 0;
+    while idx < printable.len() {
+        table[printable[idx] as usize] = idx as u8 + 0x20;
+        idx += 1;
+    }
+    table
+}
+
+impl Record {
+    /// Creates a record from EBCDIC bytes.
+    ///
+    /// The bytes are truncated to 80 or padded with spaces if shorter.
+    #[must_use]
+    pub fn from_ebcdic(bytes: &[u8], code_page: CodePage) -> Self {
+        Self::from_ebcdic_lrecl(bytes, code_page, RECORD_WIDTH)
+    }
+
+    /// Creates an `lrecl`-byte record from EBCDIC bytes.
+    #[must_use]
+    pub fn from_ebcdic_lrecl(bytes: &[u8], code_page: CodePage, lrecl: usize) -> Self {
+        let ascii: Vec<u8> = bytes
+            .iter()
+            .take(lrecl)
+            .map(|&b| code_page.decode(b))
+            .collect();
+        Record::from_bytes_lrecl(&ascii, lrecl)
+    }
+
+    /// Returns the record as EBCDIC bytes, one per column.
+    #[must_use]
+    pub fn to_ebcdic(&self, code_page: CodePage) -> Vec<u8> {
+        self.as_bytes()
+            .iter()
+            .map(|&b| code_page.encode(b))
+            .collect()
+    }
+}
+
+/// Decodes an EBCDIC dataset of fixed `lrecl`-byte records into ASCII
+/// lines. A short final record is padded.
+pub fn decode_records(bytes: &[u8], code_page: CodePage, lrecl: usize) -> String {
+    bytes
+        .chunks(lrecl)
+        .map(|chunk| {
+            Record::from_ebcdic_lrecl(chunk, code_page, lrecl)
+                .as_str()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Encodes ASCII lines as an EBCDIC dataset of fixed `lrecl`-byte records.
+pub fn encode_records(text: &str, code_page: CodePage, lrecl: usize) -> Vec<u8> {
+    text.lines()
+        .flat_map(|line| Record::from_str_lrecl(line, lrecl).to_ebcdic(code_page))
+        .collect()
+}
+
+/// Decodes a pipeline's input file: EBCDIC `lrecl`-byte records if the
+/// pipeline starts with `CONVERT ASCII`, otherwise UTF-8 text.
+pub fn decode_input(bytes: Vec<u8>, commands: &[Command], lrecl: usize) -> Result<String, String> {
+    match input_code_page(commands) {
+        Some(code_page) => Ok(decode_records(&bytes, code_page, lrecl)),
+        None => String::from_utf8(bytes)
+            .map_err(|_| "input is not UTF-8 text (use CONVERT ASCII to read EBCDIC)".to_string()),
+    }
+}
+
+/// Encodes a pipeline's output: EBCDIC `lrecl`-byte records if the
+/// pipeline ends with `CONVERT EBCDIC`, otherwise the text as is.
+pub fn encode_output(text: String, commands: &[Command], lrecl: usize) -> Vec<u8> {
+    match output_code_page(commands) {
+        Some(code_page) => encode_records(&text, code_page, lrecl),
+        None => text.into_bytes(),
+    }
+}
+
+/// Reads a pipeline's input a line at a time, decoded as [`decode_input`]
+/// decodes the whole file, so that large files need not fit in memory.
+///
+/// Iteration ends after the first error.
+pub fn input_lines<'a, R: BufRead + 'a>(
+    mut reader: R,
+    commands: &[Command],
+    lrecl: usize,
+) -> Box<dyn Iterator<Item = Result<String, String>> + 'a> {
+    let Some(code_page) = input_code_page(commands) else {
+        return Box::new(reader.lines().scan(false, |failed, line| {
+            if *failed {
+                return None;
+            }
+            *failed = line.is_err();
+            Some(line.map_err(|e| match e.kind() {
+                ErrorKind::InvalidData => {
+                    "input is not UTF-8 text (use CONVERT ASCII to read EBCDIC)".to_string()
+                }
+                _ => e.to_string(),
+            }))
+        }));
+    };
+    let mut chunk = Vec::with_capacity(lrecl);
+    let mut failed = false;
+    Box::new(std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        chunk.clear();
+        match reader.by_ref().take(lrecl as u64).read_to_end(&mut chunk) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(Record::from_ebcdic_lrecl(&chunk, code_page, lrecl)
+                .as_str()
+                .to_string())),
+            Err(e) => {
+                failed = true;
+                Some(Err(e.to_string()))
+            }
+        }
+    }))
+}
+
+/// Writes one of a pipeline's output records, encoded as
+/// [`encode_output`] encodes the whole output: an EBCDIC `lrecl`-byte
+/// record, or a line without trailing blanks.
+pub fn write_output(
+    writer: &mut impl Write,
+    record: &Record,
+    commands: &[Command],
+    lrecl: usize,
+) -> io::Result<()> {
+    match output_code_page(commands) {
+        Some(code_page) => {
+            writer.write_all(&Record::from_str_lrecl(record.as_str(), lrecl).to_ebcdic(code_page))
+        }
+        None => writeln!(writer, "{}", record.as_str().trim_end()),
+    }
+}
+
+/// Returns the code page of a `CONVERT ASCII` stage reading EBCDIC input.
+pub fn input_code_page(commands: &[Command]) -> Option<CodePage> {
+    match commands.get(1) {
+        Some(Command::Convert {
+            to: Charset::Ascii,
+            code_page,
+        }) => Some(*code_page),
+        _ => None,
+    }
+}
+
+/// Returns the code page of a `CONVERT EBCDIC` stage writing EBCDIC output.
+pub fn output_code_page(commands: &[Command]) -> Option<CodePage> {
+    match commands {
+        [
+            ..,
+            Command::Convert {
+                to: Charset::Ebcdic,
+                code_page,
+            },
+            Command::Console,
+        ] => Some(*code_page),
+        _ => None,
+    }
+}
+
+/// Checks that `CONVERT ASCII` directly follows the input source and that
+/// `CONVERT EBCDIC` directly precedes the output `CONSOLE`, where the
+/// files are converted.
+pub fn validate(commands: &[Command]) -> Result<(), String> {
+    for (idx, cmd) in commands.iter().enumerate() {
+        if let Command::Convert { to, .. } = cmd {
+            let placed = match to {
+                Charset::Ascii => {
+                    idx == 1 && matches!(commands[0], Command::Console | Command::FileIn { .. })
+                }
+                Charset::Ebcdic => {
+                    idx + 2 == commands.len() && matches!(commands[idx + 1], Command::Console)
+                }
+            };
+            if !placed {
+                return Err(match to {
+                    Charset::Ascii => "CONVERT ASCII must directly follow PIPE CONSOLE".to_string(),
+                    Charset::Ebcdic => {
+                        "CONVERT EBCDIC must directly precede the output CONSOLE".to_string()
+                    }
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printable_round_trip() {
+        for code_page in [CodePage::Cp037, CodePage::Cp1047] {
+            for byte in 0x20..=0x7E {
+                assert_eq!(code_page.decode(code_page.encode(byte)), byte);
+            }
+        }
+    }
+
+    #[test]
+    fn test_code_page_differences() {
+        let text = Record::from_str("A[1]^b");
+        assert_eq!(
+            &text.to_ebcdic(CodePage::Cp037)[..6],
+            &[0xC1, 0xBA, 0xF1, 0xBB, 0xB0, 0x82]
+        );
+        assert_eq!(
+            &text.to_ebcdic(CodePage::Cp1047)[..6],
+            &[0xC1, 0xAD, 0xF1, 0xBD, 0x5F, 0x82]
+        );
+        // Unmapped bytes (here low-values and CP037's not sign) decode as '?'
+        assert_eq!(CodePage::Cp037.decode(0x00), b'?');
+        assert_eq!(CodePage::Cp037.decode(0x5F), b'?');
+    }
+
+    #[test]
+    fn test_dataset_round_trip() {
+        let text = "SMITH   JOHN      SALES     00050000\nJONES   MARY";
+        for lrecl in [RECORD_WIDTH, 132] {
+            let bytes = encode_records(text, CodePage::Cp1047, lrecl);
+            assert_eq!(bytes.len(), 2 * lrecl);
+            let decoded = decode_records(&bytes, CodePage::Cp1047, lrecl);
+            let lines: Vec<_> = decoded.lines().map(str::trim_end).collect();
+            assert_eq!(lines, text.lines().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_streaming_matches_whole_file() {
+        let text = "SMITH   JOHN\nJONES   MARY";
+        for pipeline in [
+            "PIPE CONSOLE | CONSOLE",
+            "PIPE CONSOLE | CONVERT ASCII | CONVERT EBCDIC | CONSOLE",
+        ] {
+            let commands = crate::parse_commands(pipeline).unwrap();
+            let bytes = encode_output(text.to_string(), &commands, RECORD_WIDTH);
+            let lines: Vec<String> = input_lines(&bytes[..], &commands, RECORD_WIDTH)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            let decoded = decode_input(bytes.clone(), &commands, RECORD_WIDTH).unwrap();
+            assert_eq!(lines, decoded.lines().collect::<Vec<_>>());
+
+            let mut written = Vec::new();
+            for line in &lines {
+                let record = Record::from_str(line);
+                write_output(&mut written, &record, &commands, RECORD_WIDTH).unwrap();
+            }
+            assert_eq!(written.trim_ascii_end(), bytes.trim_ascii_end());
+        }
+
+        let commands = crate::parse_commands("PIPE CONSOLE | CONSOLE").unwrap();
+        let mut lines = input_lines(&b"ok\n\xC8\xC5\n"[..], &commands, RECORD_WIDTH);
+        assert_eq!(lines.next(), Some(Ok("ok".to_string())));
+        assert!(lines.next().unwrap().unwrap_err().contains("not UTF-8"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn test_code_page_names() {
+        assert_eq!(CodePage::from_name("cp037"), Some(CodePage::Cp037));
+        assert_eq!(CodePage::from_name("1047"), Some(CodePage::Cp1047));
+        assert_eq!(CodePage::from_name("CP500"), None);
+        assert_eq!(CodePage::Cp1047.name(), "CP1047");
+    }
+}
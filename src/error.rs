@@ -13,14 +13,85 @@ pub enum PipelineError {
         record_len: usize,
     },
 
+    /// Value has more digits than the field can hold.
+    #[error("value {value} does not fit in a {length}-byte field")]
+    ValueTooWide { value: String, length: usize },
+
+    /// Field does not hold a number of the type read.
+    #[error("field {start}:{length} is not a number: '{value}'")]
+    NotNumeric {
+        start: usize,
+        length: usize,
+        value: String,
+    },
+
     /// Invalid record length (must be exactly 80 bytes).
     #[error("invalid record length: expected 80, got {0}")]
     InvalidRecordLength(usize),
 
+    /// Byte that a record cannot hold, from a strict constructor such as
+    /// `Record::try_from_str`.
+    #[error("non-ASCII byte 0x{byte:02X} at offset {offset}")]
+    NonAscii { offset: usize, byte: u8 },
+
+    /// Data longer than the record it is stored in, from a strict
+    /// constructor such as `Record::try_from_str`.
+    #[error("{length} bytes do not fit in a {lrecl}-byte record")]
+    RecordTooLong { length: usize, lrecl: usize },
+
     /// I/O error during file operations.
     #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// Malformed Record Descriptor Word in a variable-length dataset.
+    #[error("invalid RDW at byte {offset}: {reason}")]
+    InvalidRdw { offset: u64, reason: String },
+
+    /// Fixed-length input that ends part way through a record.
+    #[error("short record at byte {offset}: {length} of {lrecl} bytes before end of input")]
+    ShortRecord {
+        offset: u64,
+        length: usize,
+        lrecl: usize,
+    },
+
+    /// Input file pattern that is malformed or matches no files.
+    #[error("input pattern '{pattern}': {reason}")]
+    InputPattern { pattern: String, reason: String },
+
+    /// Date field holds no real date, or a date format is unknown.
+    #[error("invalid date: {0}")]
+    InvalidDate(String),
+
+    /// CSV line that cannot be read, or a value that does not fit its field.
+    #[error("CSV error: {0}")]
+    Csv(String),
+
+    /// Record that cannot be converted to Arrow, or a Parquet write failure.
+    #[error("Arrow error: {0}")]
+    Arrow(String),
+
+    /// JSON value that does not describe a record in its layout.
+    #[error("JSON error: {0}")]
+    Json(String),
+
+    /// Invalid record layout definition.
+    #[error("invalid layout: {0}")]
+    InvalidLayout(String),
+
+    /// Encryption key could not be loaded, or a field cannot hold its
+    /// encrypted value.
+    #[error("crypto error: {0}")]
+    Crypto(String),
+
+    /// Translate table could not be read, or is not 256 bytes.
+    #[error("translate table error: {0}")]
+    Xlate(String),
+
+    /// Plugin stage could not be loaded or created.
+    #[error("plugin error: {0}")]
+    Plugin(String),
+
     /// Custom stage error.
     #[error("stage error: {0}")]
     Stage(String),
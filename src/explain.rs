@@ -0,0 +1,523 @@
+//! Pipeline explanations.
+//!
+//! [`explain`] describes a parsed plan without running it: each pipeline
+//! and segment with its stages in order, the fields each stage reads and
+//! writes, and warnings about layout mistakes that parse but go wrong on
+//! real data, such as a field that runs past the end of the record. Field
+//! ranges are `pos,len`, counting from 0, as in the DSL.
+//!
+//! `pipe-run --explain` prints the explanation instead of running the
+//! pipeline, and the Web UI shows it with the Plan button.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::PipelinePlan;
+//! use pipelines_rs::explain::explain;
+//!
+//! let plan = PipelinePlan::parse("PIPE CONSOLE | SELECT 0,8,0; 75,10,10 | CONSOLE").unwrap();
+//! let explanation = explain(&plan);
+//! assert_eq!(
+//!     explanation.warnings,
+//!     ["stage 2 SELECT reads columns 75-84, past the end of the 80-byte record"]
+//! );
+//! assert!(explanation.to_string().contains("reads 0,8 75,10; writes 0,8 10,10"));
+//! ```
+
+use std::fmt;
+
+use crate::dsl::Command;
+use crate::plan::{PipelinePlan, Segment};
+use crate::specs::{Placement, SpecInput};
+
+/// Whether a stage reads or writes a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A field a stage reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldUse {
+    /// Read or written.
+    pub access: Access,
+    /// Starting column (0-based).
+    pub start: usize,
+    /// Field length.
+    pub len: usize,
+}
+
+impl FieldUse {
+    fn read(start: usize, len: usize) -> Self {
+        Self {
+            access: Access::Read,
+            start,
+            len,
+        }
+    }
+
+    fn write(start: usize, len: usize) -> Self {
+        Self {
+            access: Access::Write,
+            start,
+            len,
+        }
+    }
+
+    /// The column just past the field.
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// The field's columns as `first-last`.
+    fn columns(&self) -> String {
+        format!("{}-{}", self.start, self.end().saturating_sub(1))
+    }
+}
+
+/// Returns the fields `command` reads and writes, in the order written.
+///
+/// Stages that work on the whole record (`UPPER`, `LOCATE` without a
+/// field) touch no fields, and `SPECS` items placed after an item whose
+/// width depends on the data are left out.
+pub fn field_uses(command: &Command) -> Vec<FieldUse> {
+    let read = |field: &Option<(usize, usize)>| {
+        field
+            .iter()
+            .map(|&(start, len)| FieldUse::read(start, len))
+            .collect()
+    };
+    let read_write = |field: &Option<(usize, usize)>| {
+        field
+            .iter()
+            .flat_map(|&(start, len)| [FieldUse::read(start, len), FieldUse::write(start, len)])
+            .collect()
+    };
+    let copies = |fields: &[(usize, usize, usize)]| {
+        let reads = fields.iter().map(|&(src, len, _)| FieldUse::read(src, len));
+        let writes = fields
+            .iter()
+            .map(|&(_, len, dest)| FieldUse::write(dest, len));
+        reads.chain(writes).collect()
+    };
+    match command {
+        Command::FilterEq { pos, len, .. }
+        | Command::FilterNe { pos, len, .. }
+        | Command::FilterZoned { pos, len, .. }
+        | Command::Statistic { pos, len, .. } => vec![FieldUse::read(*pos, *len)],
+        Command::FilterExpr { condition } => condition
+            .fields()
+            .into_iter()
+            .map(|(start, len)| FieldUse::read(start, len))
+            .collect(),
+        Command::Select { fields } | Command::Overlay { fields } => copies(fields),
+        Command::Locate { field, .. }
+        | Command::Nlocate { field, .. }
+        | Command::Between { field, .. }
+        | Command::Unique { field } => read(field),
+        Command::Change { field, .. } => read_write(field),
+        Command::Xlate { spec } => read_write(&spec.field),
+        Command::Pad { len, .. } => vec![FieldUse::write(0, *len)],
+        Command::ReformatDate { spec } => vec![
+            FieldUse::read(spec.pos, spec.from.width()),
+            FieldUse::write(spec.dest.unwrap_or(spec.pos), spec.to.width()),
+        ],
+        Command::FromCsv { spec } => spec
+            .layout
+            .fields()
+            .iter()
+            .map(|field| FieldUse::write(field.start, field.len))
+            .collect(),
+        Command::ToCsv { spec } => spec
+            .layout
+            .fields()
+            .iter()
+            .map(|field| FieldUse::read(field.start, field.len))
+            .collect(),
+        Command::Sort { keys, sum } => {
+            let sum = sum.as_deref().unwrap_or_default();
+            let keys = keys.iter().map(|key| FieldUse::read(key.start, key.len));
+            let reads = sum
+                .iter()
+                .map(|field| FieldUse::read(field.start, field.len));
+            let writes = sum
+                .iter()
+                .map(|field| FieldUse::write(field.start, field.len));
+            keys.chain(reads).chain(writes).collect()
+        }
+        Command::Profile { fields } => fields
+            .iter()
+            .map(|&(start, len, _)| FieldUse::read(start, len))
+            .collect(),
+        Command::Stamp { fields, .. } => fields
+            .iter()
+            .map(|&(start, len, _)| FieldUse::write(start, len))
+            .collect(),
+        Command::Encrypt { spec } | Command::Decrypt { spec } => vec![
+            FieldUse::read(spec.field.0, spec.field.1),
+            FieldUse::write(spec.dest.0, spec.dest.1),
+        ],
+        Command::Specs { items } => {
+            let mut uses = Vec::new();
+            let mut writes = Vec::new();
+            // The column after the last item, while widths are known
+            let mut next = Some(0);
+            for item in items {
+                if let SpecInput::Range(start, len) = item.input {
+                    uses.push(FieldUse::read(start, len));
+                }
+                let start = match item.placement {
+                    Placement::Column(col) => Some(col),
+                    Placement::Next => next,
+                    Placement::NextWord => next.map(|next| if next == 0 { 0 } else { next + 1 }),
+                };
+                let width = item.fixed_width();
+                if let (Some(start), Some(width)) = (start, width) {
+                    writes.push(FieldUse::write(start, width));
+                }
+                next = start.zip(width).map(|(start, width)| start + width);
+            }
+            uses.extend(writes);
+            uses
+        }
+        Command::Split {
+            condition, build, ..
+        } => {
+            let mut uses = condition.as_deref().map(field_uses).unwrap_or_default();
+            // BUILD writes the copy sent to the output, not the record
+            uses.extend(
+                build
+                    .iter()
+                    .flatten()
+                    .map(|&(src, len, _)| FieldUse::read(src, len)),
+            );
+            uses
+        }
+        Command::Console
+        | Command::Dump { .. }
+        | Command::Take { .. }
+        | Command::Skip { .. }
+        | Command::TakeLast { .. }
+        | Command::DropLast { .. }
+        | Command::Count
+        | Command::Literal { .. }
+        | Command::Upper
+        | Command::Lower
+        | Command::Reverse
+        | Command::Trunc { .. }
+        | Command::Squish
+        | Command::Duplicate { .. }
+        | Command::Recno { .. }
+        | Command::Sample { .. }
+        | Command::Hole
+        | Command::Fanin
+        | Command::Faninany
+        | Command::FileIn { .. }
+        | Command::FileOut { .. }
+        | Command::Delay { .. }
+        | Command::Plugin { .. }
+        | Command::Convert { .. } => Vec::new(),
+    }
+}
+
+/// One stage of an explained pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageExplanation {
+    /// The stage's label, if it has one.
+    pub label: Option<String>,
+    /// The stage.
+    pub command: Command,
+    /// The fields it reads and writes.
+    pub fields: Vec<FieldUse>,
+}
+
+/// The main pipeline or a segment, explained.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineExplanation {
+    /// How the pipeline starts: `PIPE`, or `? a:` or `? < NAME` for a
+    /// segment.
+    pub start: String,
+    /// What the pipeline reads, in words.
+    pub reads: String,
+    /// Its stages in order.
+    pub stages: Vec<StageExplanation>,
+    /// The labeled stage whose secondary input a segment feeds.
+    pub target: Option<String>,
+}
+
+/// A plan explained: its pipelines and warnings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// The record length.
+    pub lrecl: usize,
+    /// The main pipeline, then each segment.
+    pub pipelines: Vec<PipelineExplanation>,
+    /// Likely mistakes, such as fields past the end of the record.
+    pub warnings: Vec<String>,
+}
+
+/// Explains a plan: its pipelines, the fields each stage touches, and
+/// warnings about them.
+pub fn explain(plan: &PipelinePlan) -> Explanation {
+    let lrecl = plan.lrecl();
+    let mut pipelines = vec![PipelineExplanation {
+        start: "PIPE".to_string(),
+        reads: match plan.source() {
+            Command::FileIn { path } => format!("file {path}"),
+            Command::FromCsv { .. } => "CSV lines".to_string(),
+            Command::Literal { .. } | Command::Hole => "no input".to_string(),
+            _ => "the input records".to_string(),
+        },
+        stages: stages(plan.commands(), |i| plan.label_of(i)),
+        target: None,
+    }];
+    for segment in plan.segments() {
+        pipelines.push(explain_segment(plan, segment));
+    }
+
+    let mut warnings = Vec::new();
+    for pipeline in &pipelines {
+        for (index, stage) in pipeline.stages.iter().enumerate() {
+            let at = match pipeline.start.as_str() {
+                "PIPE" => format!("stage {}", index + 1),
+                start => format!("{start} stage {}", index + 1),
+            };
+            warnings.extend(stage_warnings(&at, stage, lrecl));
+        }
+    }
+    Explanation {
+        lrecl,
+        pipelines,
+        warnings,
+    }
+}
+
+/// Explains a segment.
+fn explain_segment(plan: &PipelinePlan, segment: &Segment) -> PipelineExplanation {
+    let (start, reads) = match segment.file() {
+        Some(file) => (format!("? < {file}"), format!("input {file}")),
+        None => {
+            let input = segment.input();
+            let dropped_by = labeled(plan, input).map_or(String::new(), |(number, command)| {
+                format!(" (stage {number} {})", command.name())
+            });
+            (
+                format!("? {input}:"),
+                format!("the records {input}:{dropped_by} drops"),
+            )
+        }
+    };
+    PipelineExplanation {
+        start,
+        reads,
+        stages: stages(segment.commands(), |i| segment.label_of(i)),
+        target: segment.target().map(str::to_string),
+    }
+}
+
+/// Finds the main pipeline's stage labeled `label`, with its number.
+fn labeled<'a>(plan: &'a PipelinePlan, label: &str) -> Option<(usize, &'a Command)> {
+    plan.commands()
+        .iter()
+        .enumerate()
+        .find(|(i, _)| plan.label_of(*i) == Some(label))
+        .map(|(i, command)| (i + 1, command))
+}
+
+/// Explains stages, given their labels.
+fn stages<'a>(
+    commands: &[Command],
+    label_of: impl Fn(usize) -> Option<&'a str>,
+) -> Vec<StageExplanation> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(i, command)| StageExplanation {
+            label: label_of(i).map(str::to_string),
+            command: command.clone(),
+            fields: field_uses(command),
+        })
+        .collect()
+}
+
+/// Warns about a stage's fields: any past the end of the record, and
+/// writes that overlap.
+fn stage_warnings(at: &str, stage: &StageExplanation, lrecl: usize) -> Vec<String> {
+    let name = stage.command.name();
+    let mut warnings = Vec::new();
+    for field in &stage.fields {
+        if field.end() > lrecl {
+            let verb = match field.access {
+                Access::Read => "reads",
+                Access::Write => "writes",
+            };
+            warnings.push(format!(
+                "{at} {name} {verb} columns {}, past the end of the {lrecl}-byte record",
+                field.columns()
+            ));
+        }
+    }
+    let writes: Vec<&FieldUse> = stage
+        .fields
+        .iter()
+        .filter(|field| field.access == Access::Write)
+        .collect();
+    for (i, first) in writes.iter().enumerate() {
+        for second in &writes[i + 1..] {
+            if first.start < second.end() && second.start < first.end() {
+                warnings.push(format!(
+                    "{at} {name} writes columns {} and {}, which overlap",
+                    first.columns(),
+                    second.columns()
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Prints the explanation: each pipeline's stages with the fields they
+/// touch, then the warnings.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = |stage: &StageExplanation| match &stage.label {
+            Some(label) => format!("{label}: {}", stage.command),
+            None => stage.command.to_string(),
+        };
+        let width = self
+            .pipelines
+            .iter()
+            .flat_map(|p| &p.stages)
+            .map(|stage| text(stage).len())
+            .max()
+            .unwrap_or(0)
+            .min(40);
+        writeln!(f, "LRECL {}", self.lrecl)?;
+        for pipeline in &self.pipelines {
+            writeln!(f, "{} reads {}", pipeline.start, pipeline.reads)?;
+            for (index, stage) in pipeline.stages.iter().enumerate() {
+                let fields = describe(&stage.fields);
+                let line = format!("{:>4}  {:<width$}  {fields}", index + 1, text(stage));
+                writeln!(f, "{}", line.trim_end())?;
+            }
+            if let Some(target) = &pipeline.target {
+                writeln!(f, "  -> {target}: secondary input")?;
+            }
+        }
+        match self.warnings.len() {
+            0 => write!(f, "No warnings"),
+            n => {
+                write!(f, "{n} warning{}:", if n == 1 { "" } else { "s" })?;
+                for warning in &self.warnings {
+                    write!(f, "\n  {warning}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Describes fields as `reads 0,8 18,10; writes 0,8`.
+fn describe(fields: &[FieldUse]) -> String {
+    let list = |access: Access| {
+        fields
+            .iter()
+            .filter(|field| field.access == access)
+            .map(|field| format!("{},{}", field.start, field.len))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let parts: Vec<String> = [(Access::Read, "reads"), (Access::Write, "writes")]
+        .into_iter()
+        .map(|(access, verb)| (list(access), verb))
+        .filter(|(list, _)| !list.is_empty())
+        .map(|(list, verb)| format!("{verb} {list}"))
+        .collect();
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explained(text: &str) -> Explanation {
+        explain(&PipelinePlan::parse(text).unwrap())
+    }
+
+    #[test]
+    fn test_field_uses() {
+        let plan = PipelinePlan::parse(
+            "PIPE CONSOLE
+| FILTER 18,10 = \"SALES\" AND 28,8 NUM > 50000
+| SPECS 0,8 10 RECNO NEXTWORD 8,2 UPPER NEXT.4
+| SORT 18,10 SUM 28,8
+| CONSOLE",
+        )
+        .unwrap();
+        let uses: Vec<String> = plan
+            .commands()
+            .iter()
+            .map(|c| describe(&field_uses(c)))
+            .collect();
+        assert_eq!(
+            uses,
+            [
+                "",
+                "reads 18,10 28,8",
+                "reads 0,8 8,2; writes 10,8 19,10 29,4",
+                "reads 18,10 28,8; writes 28,8",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_warnings() {
+        let explanation = explained(
+            "LRECL 40
+PIPE CONSOLE
+| a: LOCATE 30,20 /X/
+| SELECT 0,8,0; 4,8,6
+| CONSOLE
+? a:
+| SELECT 35,10,0
+| > rejects.txt",
+        );
+        assert_eq!(
+            explanation.warnings,
+            [
+                "stage 2 LOCATE reads columns 30-49, past the end of the 40-byte record",
+                "stage 3 SELECT writes columns 0-7 and 6-13, which overlap",
+                "? a: stage 1 SELECT reads columns 35-44, past the end of the 40-byte record",
+            ]
+        );
+        assert!(
+            explained("PIPE CONSOLE | SELECT 0,80,0 | CONSOLE")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let explanation = explained(
+            "PIPE CONSOLE
+| a: LOCATE 18,10 /SALES/
+| CONSOLE
+? a:
+| > rejects.txt",
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "LRECL 80
+PIPE reads the input records
+   1  CONSOLE
+   2  a: LOCATE 18,10 /SALES/  reads 18,10
+   3  CONSOLE
+? a: reads the records a: (stage 2 LOCATE) drops
+   1  > rejects.txt
+No warnings"
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! Canonical formatting of pipeline files.
+//!
+//! [`format_pipeline`] rewrites a pipeline in one layout, so that files by
+//! different authors read alike and diffs show only real changes:
+//!
+//! - one stage per line: `PIPE` before the first, `| ` before the rest,
+//!   labels after the bar (`| a: LOCATE /X/`)
+//! - `?` on a line of its own, or before the segment it starts (`? a:`,
+//!   `? < NAME`), or after a stage whose text ends in `?` (`COUNT ? ?`)
+//! - stage names and keywords in upper case (`take last 3` is `TAKE LAST 3`)
+//! - one space between words, none around the commas of a field, and `; `
+//!   between fields (`0, 8,0;8,10,8` is `0,8,0; 8,10,8`)
+//! - one space around comparison operators (`18,10="SALES"` is
+//!   `18,10 = "SALES"`)
+//! - `LAYOUT`, `LRECL`, and `RC` lines as their own lines, in upper case
+//! - comments kept, each on a line of its own, and at most one blank line
+//!   in a row
+//!
+//! Text in double quotes is left alone. A stage is only rewritten if it
+//! still parses to the same command, so a stage whose text a change would
+//! alter, such as `LITERAL` text or a `/pattern/` with blanks, keeps its
+//! spacing. The formatted pipeline parses to the same plan as the original.
+//! `pipe-fmt` formats files in place.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::format::format_pipeline;
+//!
+//! let text = "pipe console | filter 18,10 = \"SALES\" | select 0,8,0;28,8,8 | console ?";
+//! assert_eq!(
+//!     format_pipeline(text).unwrap(),
+//!     "PIPE CONSOLE\n| FILTER 18,10 = \"SALES\"\n| SELECT 0,8,0; 28,8,8\n| CONSOLE\n?\n"
+//! );
+//! ```
+
+use crate::dsl::{STAGE_SYNTAX, parse_command, parse_pipeline, pipeline_layout, split_at_segments};
+use crate::layout::RecordLayout;
+use crate::plan::is_label;
+use crate::record::RECORD_WIDTH;
+
+/// Formats a pipeline's text canonically.
+///
+/// Returns an error if the pipeline does not parse.
+pub fn format_pipeline(text: &str) -> Result<String, String> {
+    let plan = parse_pipeline(text)?;
+    let layout = pipeline_layout(text)?;
+
+    let mut formatter = Formatter {
+        layout: &layout,
+        lrecl: RECORD_WIDTH,
+        lines: Vec::new(),
+        started: false,
+        end: false,
+    };
+    for line in text.lines() {
+        formatter.line(line);
+    }
+    let formatted = formatter.finish();
+
+    if parse_pipeline(&formatted).as_ref() != Ok(&plan)
+        || pipeline_layout(&formatted).as_ref() != Ok(&layout)
+    {
+        return Err("formatting would change the pipeline".to_string());
+    }
+    Ok(formatted)
+}
+
+/// Formatting state: the lines written so far.
+struct Formatter<'a> {
+    /// The fields the pipeline's `LAYOUT` lines declare.
+    layout: &'a RecordLayout,
+    /// The record length, for `LAYOUT` lines.
+    lrecl: usize,
+    lines: Vec<String>,
+    /// Whether a stage has been written.
+    started: bool,
+    /// Whether a `?` waits to be written.
+    end: bool,
+}
+
+impl Formatter<'_> {
+    /// Formats a source line, which may hold several stages.
+    fn line(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            if self.lines.last().is_some_and(|l| !l.is_empty()) {
+                self.push(String::new());
+            }
+            return;
+        }
+        // Split as the parser does
+        for part in line.split('|') {
+            for segment in split_at_segments(part) {
+                self.segment(segment.trim());
+            }
+        }
+    }
+
+    /// Formats one stage, comment, or directive, with any `?` around it.
+    fn segment(&mut self, segment: &str) {
+        if segment.is_empty() {
+            return;
+        }
+        if segment.starts_with('#') {
+            self.push(segment.to_string());
+            return;
+        }
+
+        let segment = match segment.strip_prefix('?') {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                self.end |= self.started;
+                rest.trim()
+            }
+            _ => segment,
+        };
+        let segment = if segment.to_uppercase().starts_with("PIPE ") {
+            segment[5..].trim()
+        } else if segment.eq_ignore_ascii_case("PIPE") {
+            return;
+        } else {
+            segment
+        };
+        let ends = segment.ends_with('?');
+        let segment = segment.trim_end_matches('?').trim();
+
+        if !segment.is_empty() {
+            self.stage(segment);
+            // A stage ending in `?` (`COUNT ? ?`) keeps the `?` that ends
+            // the pipeline on its line: moved to a line of its own, the
+            // stage's `?` would be read as the end instead
+            if ends
+                && segment.ends_with('?')
+                && let Some(line) = self.lines.last_mut()
+            {
+                line.push_str(" ?");
+                return;
+            }
+        }
+        self.end |= ends && self.started;
+    }
+
+    /// Formats a stage, with any label, or a directive.
+    fn stage(&mut self, segment: &str) {
+        let keyword = segment.split_whitespace().next().unwrap_or_default();
+        match keyword.to_uppercase().as_str() {
+            "LRECL" => {
+                self.lrecl = segment
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(self.lrecl);
+                self.push(upper_words(segment));
+                return;
+            }
+            "RC" => {
+                self.push(upper_words(segment));
+                return;
+            }
+            "LAYOUT" => {
+                let line = RecordLayout::parse_lrecl(segment, self.lrecl)
+                    .map_or_else(|_| segment.to_string(), |layout| layout.to_string());
+                self.push(line);
+                return;
+            }
+            _ => {}
+        }
+
+        let (label, stage) = match keyword.strip_suffix(':') {
+            Some(name) if is_label(name) => (Some(keyword), segment[keyword.len()..].trim()),
+            _ => (None, segment),
+        };
+        let label = label.map_or(String::new(), |l| format!("{l} "));
+
+        // `? a:` and `? < NAME` start a segment
+        if self.end && (stage.is_empty() || stage.starts_with('<')) {
+            self.end = false;
+            let line = format!("? {label}{}", self.format_stage(stage));
+            self.lines.push(line.trim_end().to_string());
+            return;
+        }
+        let prefix = if self.started { "| " } else { "PIPE " };
+        self.started = true;
+        let line = format!("{prefix}{label}{}", self.format_stage(stage));
+        self.push(line.trim_end().to_string());
+    }
+
+    /// Formats a stage's text, keeping it as written if formatting would
+    /// change the command it parses to.
+    fn format_stage(&self, stage: &str) -> String {
+        let Ok(command) = parse_command(stage, self.layout) else {
+            return stage.to_string();
+        };
+        let named = match stage.split_once(char::is_whitespace) {
+            Some((name, rest)) => format!("{} {rest}", name.to_uppercase()),
+            None => stage.to_uppercase(),
+        };
+        let spaced = normalize_spacing(&named);
+        [
+            upper_keywords(&space_operators(&spaced), self.layout),
+            upper_keywords(&spaced, self.layout),
+            spaced,
+            named,
+        ]
+        .into_iter()
+        .find(|candidate| parse_command(candidate, self.layout).as_ref() == Ok(&command))
+        .unwrap_or_else(|| stage.to_string())
+    }
+
+    /// Writes a line, after any `?` waiting to be written.
+    fn push(&mut self, line: String) {
+        if self.end {
+            self.end = false;
+            self.lines.push("?".to_string());
+        }
+        self.lines.push(line);
+    }
+
+    /// Returns the formatted text.
+    fn finish(mut self) -> String {
+        if self.end {
+            self.lines.push("?".to_string());
+        }
+        while self.lines.last().is_some_and(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+/// Returns `text` in upper case with one space between words.
+fn upper_words(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Collapses blanks outside double quotes to one space, with none around
+/// commas and none before semicolons, and one space after them.
+fn normalize_spacing(text: &str) -> String {
+    let mut out = String::new();
+    let mut quoted = false;
+    let mut space = false;
+    let mut after_comma = false;
+    for c in text.chars() {
+        if !quoted && c.is_whitespace() {
+            space = !after_comma;
+            continue;
+        }
+        match c {
+            ',' if !quoted => {
+                out.push(c);
+                space = false;
+                after_comma = true;
+                continue;
+            }
+            ';' if !quoted => {
+                out.push(c);
+                space = true;
+                after_comma = false;
+                continue;
+            }
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+        if space && !out.is_empty() {
+            out.push(' ');
+        }
+        space = false;
+        after_comma = false;
+        out.push(c);
+    }
+    out
+}
+
+/// Puts one space before and after each comparison operator (`=`, `!=`,
+/// `<`, `>=`, and so on) outside double quotes, in text whose blanks
+/// [`normalize_spacing`] collapsed.
+fn space_operators(text: &str) -> String {
+    let mut out = String::new();
+    let mut quoted = false;
+    let mut in_op = false;
+    for c in text.chars() {
+        let op = !quoted && matches!(c, '=' | '!' | '<' | '>');
+        if op && !in_op {
+            if out.ends_with(' ') {
+                out.pop();
+            }
+            if !out.is_empty() {
+                out.push(' ');
+            }
+        } else if !op && in_op {
+            out.push(' ');
+            if c == ' ' {
+                in_op = false;
+                continue;
+            }
+        }
+        if c == '"' {
+            quoted = !quoted;
+        }
+        in_op = op;
+        out.push(c);
+    }
+    out
+}
+
+/// Upper-cases the stage keywords (those in the stages' syntax, such as
+/// `LAST` or `HEADER`) outside double quotes, except the names of fields.
+fn upper_keywords(text: &str, layout: &RecordLayout) -> String {
+    let mut out = String::new();
+    let mut quoted = false;
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once('\0')) {
+        if !quoted && (c.is_ascii_alphanumeric() || c == '_') {
+            word.push(c);
+            continue;
+        }
+        let field = layout
+            .fields()
+            .iter()
+            .any(|f| f.name.eq_ignore_ascii_case(&word));
+        if is_keyword(&word) && !field {
+            word.make_ascii_uppercase();
+        }
+        out.push_str(&word);
+        word.clear();
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c != '\0' {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Whether `word` (any case) is a keyword of a stage's syntax.
+fn is_keyword(word: &str) -> bool {
+    let upper = word.to_ascii_uppercase();
+    !word.is_empty()
+        && word.starts_with(|c: char| c.is_ascii_alphabetic())
+        && STAGE_SYNTAX.iter().any(|(_, syntax)| {
+            syntax
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|w| w == upper)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_pipeline() {
+        let text = "\
+# Sales report
+layout  name 0,8;dept 18,10 ;  salary 28,8 numeric
+
+
+PIPE CONSOLE
+   |  filter dept = \"SALES\"   and salary >= \"00050000\"
+ | select name, 0;salary ,10
+|take last 3 ?
+";
+        assert_eq!(
+            format_pipeline(text).unwrap(),
+            "\
+# Sales report
+LAYOUT name 0,8; dept 18,10; salary 28,8 NUMERIC
+
+PIPE CONSOLE
+| FILTER dept = \"SALES\" AND salary >= \"00050000\"
+| SELECT name,0; salary,10
+| TAKE LAST 3
+?
+"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_meaning() {
+        let text = "PIPE CONSOLE\n| literal  two  blanks\n| locate / last /\n| change \"a ,b\" \"c\"\n| CONSOLE";
+        assert_eq!(
+            format_pipeline(text).unwrap(),
+            "PIPE CONSOLE\n| LITERAL  two  blanks\n| LOCATE / last /\n| CHANGE \"a ,b\" \"c\"\n| CONSOLE\n"
+        );
+        assert!(format_pipeline("PIPE CONSOLE | BOGUS | CONSOLE").is_err());
+    }
+
+    #[test]
+    fn test_format_spaces_operators() {
+        let text = "PIPE CONSOLE\n| filter 18,10=\"SALES\"\n| FILTER 0,1!=\"=\" or 2,3  >=\"AB\"\n| split A if 0,5 num>20\n| locate /a=b/\n| CONSOLE";
+        let formatted = format_pipeline(text).unwrap();
+        assert_eq!(
+            formatted,
+            "PIPE CONSOLE\n| FILTER 18,10 = \"SALES\"\n| FILTER 0,1 != \"=\" OR 2,3 >= \"AB\"\n| SPLIT A IF 0,5 NUM > 20\n| LOCATE /a=b/\n| CONSOLE\n"
+        );
+        assert_eq!(format_pipeline(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_segments() {
+        let text = "lrecl 132\nrc warn 2\npipe console | a: take 5 | f: fanin | console ? a: | f: ?\n# done";
+        let formatted = format_pipeline(text).unwrap();
+        assert_eq!(
+            formatted,
+            "LRECL 132\nRC WARN 2\nPIPE CONSOLE\n| a: TAKE 5\n| f: FANIN\n| CONSOLE\n? a:\n| f:\n?\n# done\n"
+        );
+        assert_eq!(format_pipeline(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_is_stable() {
+        let texts = [
+            "PIPE CONSOLE | COUNT ? ?",
+            "pipe console | count ? ?\n# done",
+            "PIPE CONSOLE | LOCATE / ? / ?",
+            "PIPE CONSOLE | a: LOCATE /X/ | COUNT ? ? a: | CONSOLE ?",
+        ];
+        for text in texts {
+            let formatted = format_pipeline(text).unwrap();
+            assert_eq!(format_pipeline(&formatted).unwrap(), formatted, "{text}");
+        }
+        assert_eq!(
+            format_pipeline("PIPE CONSOLE | COUNT ? ?").unwrap(),
+            "PIPE CONSOLE\n| COUNT ? ?\n"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Golden-file comparison of pipeline output.
+//!
+//! [`compare`] matches a pipeline's output against the records of an
+//! expected (golden) file, pairwise, and lists the differences. A changed
+//! record is reported by column range, using the `pos,len` positions of the
+//! DSL, so a regression points at the field that moved. `pipe-verify`
+//! checks one pipeline this way, and `pipe-test` a directory of them.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::Record;
+//! use pipelines_rs::golden::{compare, summary};
+//!
+//! let expected = vec![Record::from_str("SMITH   SALES"), Record::from_str("JONES")];
+//! let actual = vec![Record::from_str("SMITH   SALEZ")];
+//! let differences = compare(&expected, &actual);
+//! assert_eq!(
+//!     summary(&expected, &actual, &differences),
+//!     "1 changed records; expected 2 records, found 1"
+//! );
+//! assert_eq!(
+//!     differences[0].to_string(),
+//!     "record 1:\n  12,1: expected \"S\", found \"Z\""
+//! );
+//! assert_eq!(differences[1].to_string(), "record 2: missing \"JONES\"");
+//! ```
+
+use std::fmt;
+
+use crate::Record;
+
+/// A difference between the expected and actual output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// Record `index` differs from the expected record.
+    Changed {
+        index: usize,
+        expected: Record,
+        actual: Record,
+    },
+    /// Expected record `index` is missing from the output.
+    Missing { index: usize, expected: Record },
+    /// Output record `index` was not expected.
+    Extra { index: usize, actual: Record },
+}
+
+/// Prints the difference; record numbers are 1-based, and each changed
+/// column range of a changed record is on a line of its own.
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Changed {
+                index,
+                expected,
+                actual,
+            } => {
+                write!(f, "record {}:", index + 1)?;
+                for diff in expected.diff(actual) {
+                    write!(
+                        f,
+                        "\n  {},{}: expected {:?}, found {:?}",
+                        diff.start,
+                        diff.len,
+                        expected.field(diff.start, diff.len),
+                        actual.field(diff.start, diff.len)
+                    )?;
+                }
+                Ok(())
+            }
+            Difference::Missing { index, expected } => write!(
+                f,
+                "record {}: missing {:?}",
+                index + 1,
+                expected.as_str().trim_end()
+            ),
+            Difference::Extra { index, actual } => write!(
+                f,
+                "record {}: unexpected {:?}",
+                index + 1,
+                actual.as_str().trim_end()
+            ),
+        }
+    }
+}
+
+/// Compares records pairwise, then reports any left over on either side.
+pub fn compare(expected: &[Record], actual: &[Record]) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    for (index, (e, a)) in expected.iter().zip(actual).enumerate() {
+        if e != a {
+            differences.push(Difference::Changed {
+                index,
+                expected: e.clone(),
+                actual: a.clone(),
+            });
+        }
+    }
+    for (index, e) in expected.iter().enumerate().skip(actual.len()) {
+        differences.push(Difference::Missing {
+            index,
+            expected: e.clone(),
+        });
+    }
+    for (index, a) in actual.iter().enumerate().skip(expected.len()) {
+        differences.push(Difference::Extra {
+            index,
+            actual: a.clone(),
+        });
+    }
+    differences
+}
+
+/// Summarizes a comparison on one line: the number of changed records,
+/// and the record counts if they differ.
+pub fn summary(expected: &[Record], actual: &[Record], differences: &[Difference]) -> String {
+    let changed = differences
+        .iter()
+        .filter(|d| matches!(d, Difference::Changed { .. }))
+        .count();
+    let mut summary = format!("{changed} changed records");
+    if expected.len() != actual.len() {
+        summary.push_str(&format!(
+            "; expected {} records, found {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(lines: &[&str]) -> Vec<Record> {
+        lines.iter().map(|line| Record::from_str(line)).collect()
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = records(&["AAAA", "BBBB", "CCCC"]);
+        assert!(compare(&expected, &expected).is_empty());
+
+        let actual = records(&["AAAA", "BXXB"]);
+        let differences = compare(&expected, &actual);
+        assert_eq!(differences.len(), 2);
+        assert!(matches!(
+            differences[0],
+            Difference::Changed { index: 1, .. }
+        ));
+        assert!(matches!(
+            differences[1],
+            Difference::Missing { index: 2, .. }
+        ));
+
+        let differences = compare(&actual, &expected);
+        assert!(matches!(differences[1], Difference::Extra { index: 2, .. }));
+    }
+
+    #[test]
+    fn test_display() {
+        let expected = records(&["SMITH   00050000"]);
+        let actual = records(&["SMITH   00060001", "EXTRA"]);
+        let differences = compare(&expected, &actual);
+        assert_eq!(
+            differences[0].to_string(),
+            "record 1:\n  11,1: expected \"5\", found \"6\"\n  15,1: expected \"0\", found \"1\""
+        );
+        assert_eq!(differences[1].to_string(), "record 2: unexpected \"EXTRA\"");
+    }
+
+    #[test]
+    fn test_summary() {
+        let expected = records(&["A", "B"]);
+        let actual = records(&["A", "C"]);
+        let differences = compare(&expected, &actual);
+        assert_eq!(
+            summary(&expected, &actual, &differences),
+            "1 changed records"
+        );
+        assert_eq!(
+            summary(&expected, &[], &compare(&expected, &[])),
+            "0 changed records; expected 2 records, found 0"
+        );
+    }
+}
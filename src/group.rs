@@ -0,0 +1,536 @@
+//! Group-by with aggregates.
+//!
+//! A [`GroupBy`] stage collects records with equal key fields into groups
+//! and emits one summary record per group, in order of each group's first
+//! record. The summary holds the key fields followed by the requested
+//! [`Aggregate`]s: a record count, or the sum, minimum, maximum, or average
+//! of a numeric field.
+//!
+//! By default the keys and then the aggregates are laid out left to right,
+//! one blank column apart. [`Aggregate::at`] puts an aggregate at a given
+//! column and width instead; later aggregates follow it.
+//!
+//! A [`Total`] stage computes one aggregate over all of its input and emits
+//! it as a single record (the DSL's `COUNT`, `SUM`, `MIN`, `MAX`, `AVG`).
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Aggregate, GroupBy, Record, Stage};
+//!
+//! let mut group = GroupBy::new(
+//!     vec![(18, 10)],
+//!     vec![Aggregate::count().at(11, 3), Aggregate::sum(28, 8)],
+//! );
+//!
+//! let summary = group.process_batch(vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//!     Record::from_str("DOE     JANE      SALES     00060000"),
+//! ]);
+//!
+//! assert_eq!(summary[0].as_str().trim_end(), "SALES      002 00110000");
+//! assert_eq!(summary[1].as_str().trim_end(), "ENGINEER   001 00075000");
+//! ```
+
+use std::collections::HashMap;
+
+use crate::Record;
+use crate::error::PipelineError;
+use crate::reject::{ErrorPolicy, Reason, Rejects};
+use crate::sink::Sink;
+use crate::stage::{Stage, SumField, key_of};
+
+/// Default width of a count.
+const COUNT_WIDTH: usize = 8;
+
+/// What an [`Aggregate`] computes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateKind {
+    /// Number of records in the group.
+    Count,
+    /// Total of the field.
+    Sum,
+    /// Smallest value of the field.
+    Min,
+    /// Largest value of the field.
+    Max,
+    /// Average of the field, truncated toward zero.
+    Avg,
+}
+
+impl AggregateKind {
+    /// Returns the DSL command name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AggregateKind::Count => "COUNT",
+            AggregateKind::Sum => "SUM",
+            AggregateKind::Min => "MIN",
+            AggregateKind::Max => "MAX",
+            AggregateKind::Avg => "AVG",
+        }
+    }
+}
+
+/// One value computed per group by [`GroupBy`].
+///
+/// Field values are whole numbers with an optional sign (or zoned decimal
+/// after [`Aggregate::zoned`]); blank fields count as zero, and values that
+/// are not numbers are skipped. Results are written as zero-padded whole
+/// numbers. A result too wide for its column is written as asterisks, and
+/// a minimum, maximum, or average of no numbers is left blank.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aggregate {
+    kind: AggregateKind,
+    field: Option<SumField>,
+    at: Option<(usize, usize)>,
+}
+
+impl Aggregate {
+    /// Counts the records in each group.
+    pub fn count() -> Self {
+        Self {
+            kind: AggregateKind::Count,
+            field: None,
+            at: None,
+        }
+    }
+
+    /// Totals the field at `start, len`.
+    pub fn sum(start: usize, len: usize) -> Self {
+        Self::new(AggregateKind::Sum, start, len)
+    }
+
+    /// Finds the smallest value of the field at `start, len`.
+    pub fn min(start: usize, len: usize) -> Self {
+        Self::new(AggregateKind::Min, start, len)
+    }
+
+    /// Finds the largest value of the field at `start, len`.
+    pub fn max(start: usize, len: usize) -> Self {
+        Self::new(AggregateKind::Max, start, len)
+    }
+
+    /// Averages the field at `start, len`.
+    pub fn avg(start: usize, len: usize) -> Self {
+        Self::new(AggregateKind::Avg, start, len)
+    }
+
+    /// Computes `kind` for the field at `start, len`. A count only uses the
+    /// field's width.
+    pub fn new(kind: AggregateKind, start: usize, len: usize) -> Self {
+        Self {
+            kind,
+            field: Some(SumField::new(start, len)),
+            at: None,
+        }
+    }
+
+    /// Reads the field as zoned decimal.
+    pub fn zoned(mut self) -> Self {
+        self.field = self.field.map(SumField::zoned);
+        self
+    }
+
+    /// Writes the result at column `start`, `len` wide. Without this the
+    /// result follows the previous output field, as wide as its input
+    /// field (8 columns for a count).
+    pub fn at(mut self, start: usize, len: usize) -> Self {
+        self.at = Some((start, len));
+        self
+    }
+
+    /// Returns what this aggregate computes.
+    pub fn kind(&self) -> AggregateKind {
+        self.kind
+    }
+
+    fn width(&self) -> usize {
+        self.field.map_or(COUNT_WIDTH, |f| f.len)
+    }
+}
+
+/// Values accumulated for one aggregate of one group.
+#[derive(Debug, Clone, Copy, Default)]
+struct Accumulator {
+    numbers: i128,
+    sum: i128,
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl Accumulator {
+    fn add(&mut self, value: i64) {
+        self.numbers += 1;
+        self.sum += i128::from(value);
+        self.min = Some(self.min.map_or(value, |m| m.min(value)));
+        self.max = Some(self.max.map_or(value, |m| m.max(value)));
+    }
+
+    /// Returns the `kind` result over `records` records, if there is one.
+    fn result(&self, kind: AggregateKind, records: usize) -> Option<i128> {
+        match kind {
+            AggregateKind::Count => Some(records as i128),
+            AggregateKind::Sum => Some(self.sum),
+            AggregateKind::Min => self.min.map(i128::from),
+            AggregateKind::Max => self.max.map(i128::from),
+            AggregateKind::Avg => (self.numbers > 0).then(|| self.sum / self.numbers),
+        }
+    }
+}
+
+/// The records seen so far for one key.
+struct Group {
+    first: Record,
+    records: usize,
+    accumulators: Vec<Accumulator>,
+}
+
+/// GroupBy stage - emits one summary record per distinct key.
+///
+/// Groups need every record, so `process` holds each record and returns
+/// `None`; [`Stage::flush`] returns the summaries. `process_batch`
+/// returns them directly. The input need not be sorted.
+pub struct GroupBy {
+    keys: Vec<(usize, usize)>,
+    aggregates: Vec<Aggregate>,
+    groups: Vec<Group>,
+    index: HashMap<String, usize>,
+}
+
+impl GroupBy {
+    /// Creates a stage computing `aggregates` for each distinct value of
+    /// the `(start, len)` key fields.
+    pub fn new(keys: Vec<(usize, usize)>, aggregates: Vec<Aggregate>) -> Self {
+        Self {
+            keys,
+            aggregates,
+            groups: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Returns the key fields.
+    pub fn keys(&self) -> &[(usize, usize)] {
+        &self.keys
+    }
+
+    /// Returns the aggregates.
+    pub fn aggregates(&self) -> &[Aggregate] {
+        &self.aggregates
+    }
+
+    fn summarize(&self, group: &Group) -> Record {
+        let mut summary = Record::blank(group.first.lrecl());
+        let mut col = 0;
+        for &(start, len) in &self.keys {
+            summary.set_field(col, len, group.first.field(start, len));
+            col += len + 1;
+        }
+        for (aggregate, acc) in self.aggregates.iter().zip(&group.accumulators) {
+            let (start, len) = aggregate.at.unwrap_or((col, aggregate.width()));
+            if let Some(value) = acc.result(aggregate.kind, group.records) {
+                write_total(&mut summary, start, len, value);
+            }
+            col = start + len + 1;
+        }
+        summary
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let key = key_of(&self.keys, &record);
+        let idx = match self.index.get(&key) {
+            Some(&idx) => idx,
+            None => {
+                self.index.insert(key, self.groups.len());
+                self.groups.push(Group {
+                    first: record.clone(),
+                    records: 0,
+                    accumulators: vec![Accumulator::default(); self.aggregates.len()],
+                });
+                self.groups.len() - 1
+            }
+        };
+        let group = &mut self.groups[idx];
+        group.records += 1;
+        for (aggregate, acc) in self.aggregates.iter().zip(&mut group.accumulators) {
+            if let Some(value) = aggregate.field.and_then(|f| f.read(&record)) {
+                acc.add(value);
+            }
+        }
+        None
+    }
+}
+
+impl Stage for GroupBy {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Returns the summary records.
+    fn flush(&mut self) -> Vec<Record> {
+        self.index.clear();
+        std::mem::take(&mut self.groups)
+            .iter()
+            .map(|group| self.summarize(group))
+            .collect()
+    }
+}
+
+/// Total stage - emits one record holding an aggregate of all its input.
+///
+/// The result is written as a plain number at column 0 (a minimum,
+/// maximum, or average of no numbers gives a blank record). `process`
+/// returns `None`; [`Stage::flush`] returns the record, even for no input.
+///
+/// As with [`GroupBy`], blank fields count as zero and values that are not
+/// numbers are skipped. With [`Total::on_error`] or [`Total::rejects`], a
+/// blank field or a value that is not a number is handled by an
+/// [`ErrorPolicy`] instead and left out of the result; a stage stopped by
+/// [`ErrorPolicy::Fail`] writes no record.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Aggregate, Record, Stage, Total};
+///
+/// let mut total = Total::new(Aggregate::sum(28, 8), 80);
+/// let result = total.process_batch(vec![
+///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+///     Record::from_str("DOE     JANE      SALES     00060000"),
+/// ]);
+///
+/// assert_eq!(result[0].as_str().trim_end(), "110000");
+/// ```
+pub struct Total<R = ()> {
+    aggregate: Aggregate,
+    lrecl: usize,
+    records: usize,
+    accumulator: Accumulator,
+    rejects: Rejects<R>,
+}
+
+impl Total {
+    /// Creates a stage computing `aggregate`, whose record is `lrecl`
+    /// bytes long. [`Aggregate::at`] is ignored.
+    pub fn new(aggregate: Aggregate, lrecl: usize) -> Self {
+        Self {
+            aggregate,
+            lrecl,
+            records: 0,
+            accumulator: Accumulator::default(),
+            rejects: Rejects::default(),
+        }
+    }
+
+    /// Sends records whose field is blank or not a number to `sink`, each
+    /// with its reason appended (see [`crate::reject`]).
+    pub fn rejects<S: Sink>(self, sink: S) -> Total<S> {
+        Total {
+            aggregate: self.aggregate,
+            lrecl: self.lrecl,
+            records: self.records,
+            accumulator: self.accumulator,
+            rejects: self.rejects.with_sink(sink),
+        }
+    }
+}
+
+impl<R: Sink> Total<R> {
+    /// Sets what happens to a record whose field is blank or not a number.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.rejects = self.rejects.with_policy(policy);
+        self
+    }
+
+    /// Returns the aggregate.
+    pub fn aggregate(&self) -> &Aggregate {
+        &self.aggregate
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.rejects.error()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.rejects.take_error()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if self.rejects.stopped() {
+            return None;
+        }
+        self.records += 1;
+        let field = self.aggregate.field?;
+        let value = field.read(&record);
+        let number = value.is_some() && !record.field(field.start, field.len).trim().is_empty();
+        // Under Ignore a blank field still counts as zero
+        if (number
+            || self
+                .rejects
+                .reject(&record, Reason::NotNumeric, (field.start, field.len)))
+            && let Some(value) = value
+        {
+            self.accumulator.add(value);
+        }
+        None
+    }
+}
+
+impl<R: Sink> Stage for Total<R> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Returns the summary record.
+    fn flush(&mut self) -> Vec<Record> {
+        if self.rejects.stopped() {
+            return Vec::new();
+        }
+        let text = self
+            .accumulator
+            .result(self.aggregate.kind, self.records)
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+        self.records = 0;
+        self.accumulator = Accumulator::default();
+        vec![Record::from_str_lrecl(&text, self.lrecl)]
+    }
+}
+
+/// Writes `value` zero-padded into `start, len`, or asterisks if it does
+/// not fit.
+pub(crate) fn write_total(record: &mut Record, start: usize, len: usize, value: i128) {
+    let written = i64::try_from(value)
+        .ok()
+        .and_then(|v| SumField::new(start, len).write(record, v).ok());
+    if written.is_none() {
+        record.set_field(start, len, &"*".repeat(len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summarize(
+        keys: Vec<(usize, usize)>,
+        aggregates: Vec<Aggregate>,
+        lines: &[&str],
+    ) -> Vec<String> {
+        GroupBy::new(keys, aggregates)
+            .process_batch(lines.iter().map(|s| Record::from_str(s)).collect())
+            .iter()
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_group_by_aggregates() {
+        let lines = ["A 010", "B 005", "A 030", "A -04"];
+        let summary = summarize(
+            vec![(0, 1)],
+            vec![
+                Aggregate::count().at(2, 2),
+                Aggregate::sum(2, 3),
+                Aggregate::min(2, 3),
+                Aggregate::max(2, 3),
+                Aggregate::avg(2, 3),
+            ],
+            &lines,
+        );
+        assert_eq!(summary, ["A 03 036 -04 030 012", "B 01 005 005 005 005"]);
+    }
+
+    #[test]
+    fn test_group_by_layout() {
+        let lines = ["SMITH   SALES 10", "DOE     SALES 20"];
+        let summary = summarize(
+            vec![(8, 5)],
+            vec![Aggregate::sum(14, 2).at(20, 4), Aggregate::count()],
+            &lines,
+        );
+        assert_eq!(summary, ["SALES               0030 00000002"]);
+    }
+
+    #[test]
+    fn test_group_by_skips_non_numbers() {
+        let lines = ["A XX", "A 07", "B N/"];
+        let summary = summarize(
+            vec![(0, 1)],
+            vec![
+                Aggregate::count().at(2, 1),
+                Aggregate::avg(2, 2),
+                Aggregate::max(2, 2),
+            ],
+            &lines,
+        );
+        assert_eq!(summary, ["A 2 07 07", "B 1"]);
+    }
+
+    #[test]
+    fn test_group_by_overflow_and_zoned() {
+        let lines = ["A 9I", "A 9I", "A 0B"];
+        let summary = summarize(
+            vec![(0, 1)],
+            vec![Aggregate::sum(2, 2).zoned(), Aggregate::min(2, 2).zoned()],
+            &lines,
+        );
+        assert_eq!(summary, ["A ** 02"]);
+    }
+
+    #[test]
+    fn test_total() {
+        let lines = ["A 010", "B -05", "C XXX", "D    "];
+        let total = |aggregate| {
+            Total::new(aggregate, 80)
+                .process_batch(lines.iter().map(|s| Record::from_str(s)).collect())
+                .iter()
+                .map(|r| r.as_str().trim_end().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(total(Aggregate::count()), ["4"]);
+        assert_eq!(total(Aggregate::sum(2, 3)), ["5"]);
+        assert_eq!(total(Aggregate::min(2, 3)), ["-5"]);
+        assert_eq!(total(Aggregate::max(2, 3)), ["10"]);
+        assert_eq!(total(Aggregate::avg(2, 3)), ["1"]);
+
+        let mut empty = Total::new(Aggregate::max(2, 3), 132);
+        let record = &empty.flush()[0];
+        assert_eq!((record.lrecl(), record.as_str().trim_end()), (132, ""));
+        assert_eq!(
+            Total::new(Aggregate::sum(2, 3), 80).flush()[0]
+                .as_str()
+                .trim_end(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_total_rejects() {
+        let records = || {
+            ["A 010", "B 12X", "C    ", "D 020"]
+                .map(Record::from_str)
+                .to_vec()
+        };
+        let mut errors = Vec::new();
+        let avg = Total::new(Aggregate::avg(2, 3), 80)
+            .rejects(&mut errors)
+            .process_batch(records());
+        assert_eq!(avg[0].as_str().trim_end(), "15");
+        let reasons: Vec<_> = errors
+            .iter()
+            .map(|r| r.field(80, 16).trim_end().to_string())
+            .collect();
+        assert_eq!(reasons, ["NOTNUM 2,3", "NOTNUM 2,3"]);
+        assert_eq!(errors[0].field(0, 5), "B 12X");
+
+        let mut failed = Total::new(Aggregate::min(2, 3), 80).on_error(ErrorPolicy::Fail);
+        assert!(failed.process_batch(records()).is_empty());
+        assert!(failed.take_error().unwrap().to_string().contains("'B 12X'"));
+    }
+}
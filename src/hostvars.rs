@@ -0,0 +1,382 @@
+//! Host variables: runtime values stamped into records.
+//!
+//! Batch jobs routinely stamp the run date, a job step name, or a
+//! parameter onto every record or onto a header. [`HostVars`] holds the
+//! values for one run; the DSL `STAMP` command writes them into columns.
+//!
+//! Variable names:
+//!
+//! | Name          | Value                                   |
+//! |---------------|-----------------------------------------|
+//! | `DATE`        | Run date, `YYYYMMDD` (UTC)              |
+//! | `TIME`        | Run time, `HHMMSS` (UTC)                |
+//! | `TIMESTAMP`   | `YYYY-MM-DD-HH.MM.SS` (UTC)             |
+//! | `STEP`        | Job step name                           |
+//! | `ENV.name`    | Environment variable `name`, if allowed |
+//! | `PARM.name`   | Parameter `name` (e.g. from `--set`)    |
+//!
+//! `ENV.` reads only the environment variables the host allows with
+//! [`HostVars::with_env`], none by default, so a host that runs pipelines
+//! written by others, such as an HTTP service, does not expose its
+//! environment. The CLIs run their user's own pipelines and allow the
+//! variables those name ([`HostVars::for_pipeline_file`]).
+//!
+//! The date and time are captured once, when the `HostVars` is created,
+//! so every record in a run carries the same stamp. WebAssembly has no
+//! system clock, so there the time must be supplied with
+//! [`HostVars::with_time`].
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use pipelines_rs::hostvars::HostVars;
+//!
+//! let vars = HostVars::new()
+//!     .with_time(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+//!     .with_step("STEP010")
+//!     .with_parm("REGION", "EAST");
+//!
+//! assert_eq!(vars.resolve("DATE").unwrap(), "20231114");
+//! assert_eq!(vars.resolve("TIME").unwrap(), "221320");
+//! assert_eq!(vars.resolve("STEP").unwrap(), "STEP010");
+//! assert_eq!(vars.resolve("PARM.REGION").unwrap(), "EAST");
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Command;
+use crate::Record;
+
+/// A value written by `STAMP`: a host variable or literal text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StampValue {
+    /// Host variable name, e.g. `DATE` or `ENV.USER`.
+    Var(String),
+    /// Literal text, e.g. a `RUN DATE:` label on a header.
+    Text(String),
+}
+
+/// Returns true if `name` is a valid host variable name.
+///
+/// Checked when a pipeline is parsed; whether `ENV.` and `PARM.` values
+/// are actually set is checked when it runs.
+pub fn is_valid_name(name: &str) -> bool {
+    match name {
+        "DATE" | "TIME" | "TIMESTAMP" | "STEP" => true,
+        _ => ["ENV.", "PARM."].iter().any(|prefix| {
+            name.strip_prefix(prefix).is_some_and(|rest| {
+                !rest.is_empty()
+                    && rest
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            })
+        }),
+    }
+}
+
+/// Runtime values for one pipeline run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostVars {
+    /// Seconds since the Unix epoch at the start of the run.
+    now: u64,
+    step: String,
+    parms: BTreeMap<String, String>,
+    /// Environment variables `ENV.` may read.
+    env: BTreeSet<String>,
+}
+
+impl Default for HostVars {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostVars {
+    /// Captures the current time, with an empty step name, no parameters,
+    /// and no environment variables allowed.
+    pub fn new() -> Self {
+        Self {
+            now: epoch_seconds(current_time()),
+            step: String::new(),
+            parms: BTreeMap::new(),
+            env: BTreeSet::new(),
+        }
+    }
+
+    /// Uses `time` instead of the current time.
+    pub fn with_time(mut self, time: SystemTime) -> Self {
+        self.now = epoch_seconds(time);
+        self
+    }
+
+    /// Sets the job step name.
+    pub fn with_step(mut self, step: impl Into<String>) -> Self {
+        self.step = step.into();
+        self
+    }
+
+    /// Sets a `PARM.name` parameter.
+    pub fn with_parm(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.parms.insert(name.into(), value.into());
+        self
+    }
+
+    /// Allows `ENV.name` to read the environment variable `name`.
+    pub fn with_env(mut self, name: impl Into<String>) -> Self {
+        self.env.insert(name.into());
+        self
+    }
+
+    /// Allows every environment variable that the `STAMP` commands name.
+    /// For hosts whose user wrote the pipeline, not for pipelines from
+    /// untrusted clients.
+    pub fn with_env_used_by<'a>(self, commands: impl IntoIterator<Item = &'a Command>) -> Self {
+        commands
+            .into_iter()
+            .filter_map(|cmd| match cmd {
+                Command::Stamp { fields, .. } => Some(fields),
+                _ => None,
+            })
+            .flatten()
+            .filter_map(|(_, _, value)| match value {
+                StampValue::Var(name) => name.strip_prefix("ENV."),
+                StampValue::Text(_) => None,
+            })
+            .fold(self, |vars, name| vars.with_env(name))
+    }
+
+    /// Host variables for a command-line run of the pipeline file
+    /// `pipeline`, as `pipe-run` and `pipe-run-rat` build them: `STEP` is
+    /// `step`, or else the file name without its extension; `parms` are the
+    /// `--set` parameters; and the environment variables the `STAMP`
+    /// commands name are allowed.
+    pub fn for_pipeline_file<'a>(
+        pipeline: &Path,
+        step: Option<&str>,
+        parms: &[(String, String)],
+        commands: impl IntoIterator<Item = &'a Command>,
+    ) -> Self {
+        let step = step.map(str::to_string).unwrap_or_else(|| {
+            pipeline
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+        parms.iter().fold(
+            Self::new().with_step(step).with_env_used_by(commands),
+            |vars, (name, value)| vars.with_parm(name, value),
+        )
+    }
+
+    /// Looks up a host variable.
+    pub fn resolve(&self, name: &str) -> Result<String, String> {
+        let (year, month, day, hour, minute, second) = civil_time(self.now);
+        match name {
+            "DATE" => Ok(format!("{year:04}{month:02}{day:02}")),
+            "TIME" => Ok(format!("{hour:02}{minute:02}{second:02}")),
+            "TIMESTAMP" => Ok(format!(
+                "{year:04}-{month:02}-{day:02}-{hour:02}.{minute:02}.{second:02}"
+            )),
+            "STEP" => Ok(self.step.clone()),
+            _ => {
+                if let Some(var) = name.strip_prefix("ENV.") {
+                    if !self.env.contains(var) {
+                        return Err(format!(
+                            "Environment variable {var} is not available to this pipeline"
+                        ));
+                    }
+                    std::env::var(var).map_err(|_| format!("Environment variable {var} is not set"))
+                } else if let Some(parm) = name.strip_prefix("PARM.") {
+                    self.parms.get(parm).cloned().ok_or_else(|| {
+                        format!("Parameter {parm} is not set (use --set {parm}=...)")
+                    })
+                } else {
+                    Err(format!("Unknown host variable: {name}"))
+                }
+            }
+        }
+    }
+
+    /// Checks that every variable used by `STAMP` commands can be resolved.
+    pub fn validate(&self, commands: &[Command]) -> Result<(), String> {
+        for cmd in commands {
+            if let Command::Stamp { fields, .. } = cmd {
+                for (_, _, value) in fields {
+                    if let StampValue::Var(name) = value {
+                        self.resolve(name)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds an `lrecl`-byte header record from the `STAMP HEADER` fields.
+    pub fn header(&self, fields: &[(usize, usize, StampValue)], lrecl: usize) -> Record {
+        let mut record = Record::blank(lrecl);
+        self.stamp(&mut record, fields);
+        record
+    }
+
+    /// Writes each `(pos, len, value)` into the record, padded or truncated
+    /// to the field. Unresolvable variables are written as blanks (call
+    /// [`HostVars::validate`] first to report them).
+    pub fn stamp(&self, record: &mut Record, fields: &[(usize, usize, StampValue)]) {
+        for (pos, len, value) in fields {
+            let text = match value {
+                StampValue::Var(name) => self.resolve(name).unwrap_or_default(),
+                StampValue::Text(text) => text.clone(),
+            };
+            record.set_field(*pos, *len, &text);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn current_time() -> SystemTime {
+    SystemTime::now()
+}
+
+/// `SystemTime::now` panics on wasm32; start at the epoch instead.
+#[cfg(target_arch = "wasm32")]
+fn current_time() -> SystemTime {
+    UNIX_EPOCH
+}
+
+fn epoch_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Converts seconds since the epoch to UTC (year, month, day, hour, minute, second).
+fn civil_time(secs: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let (hour, minute, second) = (
+        (rem / 3600) as u32,
+        (rem / 60 % 60) as u32,
+        (rem % 60) as u32,
+    );
+
+    // Days-to-civil conversion (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, hour, minute, second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> HostVars {
+        HostVars::new().with_time(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn test_civil_time() {
+        assert_eq!(civil_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(civil_time(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(civil_time(1_735_689_599), (2024, 12, 31, 23, 59, 59));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let vars = at(1_735_689_599).with_step("STEP1").with_parm("RUN", "42");
+        assert_eq!(vars.resolve("DATE").unwrap(), "20241231");
+        assert_eq!(vars.resolve("TIME").unwrap(), "235959");
+        assert_eq!(vars.resolve("TIMESTAMP").unwrap(), "2024-12-31-23.59.59");
+        assert_eq!(vars.resolve("STEP").unwrap(), "STEP1");
+        assert_eq!(vars.resolve("PARM.RUN").unwrap(), "42");
+        assert!(vars.resolve("PARM.MISSING").is_err());
+        assert!(vars.resolve("ENV.PIPELINES_RS_SURELY_UNSET").is_err());
+        assert!(vars.resolve("HOSTNAME").is_err());
+    }
+
+    #[test]
+    fn test_env_allow_list() {
+        // PATH is set wherever the tests run
+        let path = std::env::var("PATH").unwrap();
+        assert!(
+            at(0)
+                .resolve("ENV.PATH")
+                .unwrap_err()
+                .contains("not available")
+        );
+        assert_eq!(at(0).with_env("PATH").resolve("ENV.PATH").unwrap(), path);
+
+        let stamp = Command::Stamp {
+            header: false,
+            fields: vec![
+                (0, 8, StampValue::Var("ENV.PATH".to_string())),
+                (8, 4, StampValue::Text("PATH".to_string())),
+            ],
+        };
+        let vars = at(0).with_env_used_by([&stamp]);
+        assert_eq!(vars.resolve("ENV.PATH").unwrap(), path);
+        assert!(
+            vars.resolve("ENV.HOME")
+                .unwrap_err()
+                .contains("not available")
+        );
+    }
+
+    #[test]
+    fn test_for_pipeline_file() {
+        let stamp = Command::Stamp {
+            header: false,
+            fields: vec![(0, 8, StampValue::Var("ENV.PATH".to_string()))],
+        };
+        let parms = [("RUN".to_string(), "42".to_string())];
+        let vars =
+            HostVars::for_pipeline_file(Path::new("jobs/daily.pipe"), None, &parms, [&stamp]);
+        assert_eq!(vars.resolve("STEP").unwrap(), "daily");
+        assert_eq!(vars.resolve("PARM.RUN").unwrap(), "42");
+        assert!(vars.resolve("ENV.PATH").is_ok());
+        assert!(vars.resolve("ENV.HOME").is_err());
+
+        let vars = HostVars::for_pipeline_file(Path::new("daily.pipe"), Some("STEP010"), &[], []);
+        assert_eq!(vars.resolve("STEP").unwrap(), "STEP010");
+        assert!(vars.resolve("ENV.PATH").is_err());
+    }
+
+    #[test]
+    fn test_stamp() {
+        let vars = at(0).with_step("STEP020");
+        let mut record = Record::from_str("SMITH");
+        vars.stamp(
+            &mut record,
+            &[
+                (10, 8, StampValue::Var("DATE".to_string())),
+                (20, 4, StampValue::Var("STEP".to_string())),
+                (30, 4, StampValue::Text("RUN:".to_string())),
+            ],
+        );
+        assert_eq!(
+            record.as_str().trim_end(),
+            "SMITH     19700101  STEP      RUN:"
+        );
+    }
+
+    #[test]
+    fn test_valid_names() {
+        assert!(is_valid_name("DATE"));
+        assert!(is_valid_name("ENV.USER"));
+        assert!(is_valid_name("PARM.REGION_1"));
+        assert!(!is_valid_name("ENV."));
+        assert!(!is_valid_name("date"));
+        assert!(!is_valid_name("USER"));
+    }
+}
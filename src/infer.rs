@@ -0,0 +1,173 @@
+//! Layout inference from sample records.
+//!
+//! Undocumented fixed-width files usually still have visible structure:
+//! columns that are blank in every record separate the fields. The
+//! analyzer proposes a [`RecordLayout`] from those gaps and guesses each
+//! field's type from its values.
+//!
+//! Fields are assumed to be left-aligned with trailing padding, so each
+//! field extends from its first non-blank column up to the start of the
+//! next field. Fields are named `FIELD1`, `FIELD2`, ... for the user to
+//! rename.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::Record;
+//! use pipelines_rs::infer::infer_layout;
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//! ];
+//! let layout = infer_layout(&records);
+//! assert_eq!(
+//!     layout.to_string(),
+//!     "LAYOUT FIELD1 0,8; FIELD2 8,10; FIELD3 18,10; FIELD4 28,8 NUMERIC"
+//! );
+//! ```
+
+use crate::layout::{FieldType, RecordLayout};
+use crate::record::{RECORD_WIDTH, Record};
+
+/// Proposes a layout for a sample of records.
+///
+/// Returns an empty layout if every record is blank.
+pub fn infer_layout(records: &[Record]) -> RecordLayout {
+    let lrecl = records.iter().map(Record::lrecl).fold(0, usize::max);
+    let mut used = vec![false; lrecl];
+    for record in records {
+        for (col, byte) in record.as_bytes().iter().enumerate() {
+            if *byte != b' ' {
+                used[col] = true;
+            }
+        }
+    }
+
+    let starts: Vec<usize> = (0..lrecl)
+        .filter(|&col| used[col] && (col == 0 || !used[col - 1]))
+        .collect();
+    let last_used = (0..lrecl).rev().find(|&col| used[col]);
+
+    let mut layout = RecordLayout::with_lrecl(lrecl.max(RECORD_WIDTH));
+    for (idx, &start) in starts.iter().enumerate() {
+        let end = match starts.get(idx + 1) {
+            Some(&next) => next,
+            None => last_used.map_or(start + 1, |col| col + 1),
+        };
+        let len = end - start;
+        let field_type = infer_type(records.iter().map(|r| r.field(start, len).trim()));
+        layout
+            .add(format!("FIELD{}", idx + 1), start, len, field_type)
+            .expect("inferred fields are unique and within the record");
+    }
+    layout
+}
+
+/// Guesses the type of a field from its (trimmed) values.
+///
+/// Blank values are ignored; a field with no non-blank values is `Char`.
+fn infer_type<'a>(values: impl Iterator<Item = &'a str>) -> FieldType {
+    let values: Vec<&str> = values.filter(|v| !v.is_empty()).collect();
+    if values.is_empty() {
+        FieldType::Char
+    } else if values.iter().all(|v| is_date(v)) {
+        FieldType::Date
+    } else if values.iter().all(|v| is_numeric(v)) {
+        FieldType::Numeric
+    } else {
+        FieldType::Char
+    }
+}
+
+/// Returns true for an optionally signed decimal number (`-12`, `00050000`, `3.50`).
+pub(crate) fn is_numeric(value: &str) -> bool {
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let mut seen_digit = false;
+    let mut seen_point = false;
+    for c in digits.chars() {
+        match c {
+            '0'..='9' => seen_digit = true,
+            '.' if !seen_point => seen_point = true,
+            _ => return false,
+        }
+    }
+    seen_digit
+}
+
+/// Returns true for `YYYYMMDD`, `YYYY-MM-DD`, or `MM/DD/YYYY` with a
+/// plausible month and day.
+fn is_date(value: &str) -> bool {
+    let b = value.as_bytes();
+    let (year, month, day) = match b.len() {
+        8 => (&value[0..4], &value[4..6], &value[6..8]),
+        10 if b[4] == b'-' && b[7] == b'-' => (&value[0..4], &value[5..7], &value[8..10]),
+        10 if b[2] == b'/' && b[5] == b'/' => (&value[6..10], &value[0..2], &value[3..5]),
+        _ => return false,
+    };
+    let all_digits = |s: &str| s.bytes().all(|c| c.is_ascii_digit());
+    if !(all_digits(year) && all_digits(month) && all_digits(day)) {
+        return false;
+    }
+    let year: u32 = year.parse().unwrap_or(0);
+    let month: u32 = month.parse().unwrap_or(0);
+    let day: u32 = day.parse().unwrap_or(0);
+    year >= 1000 && (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_layout_from_employee_data() {
+        let records: Vec<Record> = [
+            "SMITH   JOHN      SALES     00050000",
+            "JONES   MARY      ENGINEER  00075000",
+            "DOE     JANE      SALES     00060000",
+        ]
+        .iter()
+        .map(|s| Record::from_str(s))
+        .collect();
+
+        let layout = infer_layout(&records);
+        let fields = layout.fields();
+        assert_eq!(fields.len(), 4);
+        assert_eq!((fields[1].start, fields[1].len), (8, 10));
+        assert_eq!(fields[2].field_type, FieldType::Char);
+        assert_eq!(fields[3].field_type, FieldType::Numeric);
+        assert_eq!((fields[3].start, fields[3].len), (28, 8));
+    }
+
+    #[test]
+    fn test_infer_dates_and_blank_values() {
+        let records = vec![
+            Record::from_str("A001 20240115  -12.50"),
+            Record::from_str("A002 20231231        "),
+            Record::from_str("A003 19991001  7"),
+        ];
+        let layout = infer_layout(&records);
+        assert_eq!(
+            layout.to_string(),
+            "LAYOUT FIELD1 0,5; FIELD2 5,10 DATE; FIELD3 15,6 NUMERIC"
+        );
+    }
+
+    #[test]
+    fn test_infer_empty_sample() {
+        assert!(infer_layout(&[]).is_empty());
+        assert!(infer_layout(&[Record::new()]).is_empty());
+    }
+
+    #[test]
+    fn test_value_classification() {
+        assert!(is_numeric("00050000"));
+        assert!(is_numeric("-3.5"));
+        assert!(!is_numeric("1.2.3"));
+        assert!(!is_numeric("-"));
+        assert!(is_date("2024-01-15"));
+        assert!(is_date("01/15/2024"));
+        assert!(!is_date("00050000"));
+        assert!(!is_date("20241315"));
+    }
+}
@@ -0,0 +1,135 @@
+//! Inputs a run reads besides its main one, opened as they are needed.
+//!
+//! A segment starting with `? < TRANS` reads the input named `TRANS`, the
+//! way a job step reads a DD. A [`SideReader`] opens it when the run sets
+//! the segment up, and the run reads its records from there (see
+//! [`execute_plan_streaming_to`](crate::dsl::execute_plan_streaming_to)
+//! and [`execute_plan_with_inputs`](crate::dsl::execute_plan_with_inputs)).
+//!
+//! [`InputFiles`] is the reader of the command-line runners: each name is
+//! read from the path `--in NAME=PATH` gives, or from the file of that
+//! name, and files ending in `.gz` or `.zst` are decompressed as they are
+//! read. Hosts that hold an input in memory supply its text instead.
+//!
+//! # Example
+//!
+//! ```
+//! use std::io::Read;
+//! use pipelines_rs::infiles::{InputFiles, SideReader};
+//!
+//! let inputs = InputFiles::new().with_text("TRANS", "A1\nB2\n");
+//! let mut text = String::new();
+//! inputs.open_input("TRANS").unwrap().read_to_string(&mut text).unwrap();
+//! assert_eq!(text, "A1\nB2\n");
+//! assert!(inputs.open_input("MASTER").is_err());
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+use crate::io::{Compression, decoder};
+
+/// Opens the inputs that segments starting with `? < name` read.
+pub trait SideReader {
+    /// Opens the input named `name`.
+    fn open_input(&self, name: &str) -> Result<Box<dyn BufRead + '_>, String>;
+}
+
+/// Where an input's records come from.
+enum Input {
+    /// A file, or stdin for `-`
+    Path(String),
+    Text(String),
+}
+
+/// The inputs of a run's `? < name` segments, by name. Files are opened
+/// only when a segment reads them.
+#[derive(Default)]
+pub struct InputFiles {
+    inputs: Vec<(String, Input)>,
+}
+
+impl InputFiles {
+    /// A table without inputs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the input `name` from the file at `path`, or from stdin if
+    /// `path` is `-`.
+    #[must_use]
+    pub fn with_file(mut self, name: impl Into<String>, path: impl Into<String>) -> Self {
+        self.inputs.push((name.into(), Input::Path(path.into())));
+        self
+    }
+
+    /// Supplies the text of the input `name`, for hosts that hold it.
+    #[must_use]
+    pub fn with_text(mut self, name: impl Into<String>, text: impl Into<String>) -> Self {
+        self.inputs.push((name.into(), Input::Text(text.into())));
+        self
+    }
+}
+
+impl SideReader for InputFiles {
+    fn open_input(&self, name: &str) -> Result<Box<dyn BufRead + '_>, String> {
+        match self.inputs.iter().rev().find(|(n, _)| n == name) {
+            Some((_, Input::Text(text))) => Ok(Box::new(text.as_bytes())),
+            Some((_, Input::Path(path))) if path == "-" => Ok(Box::new(io::stdin().lock())),
+            Some((_, Input::Path(path))) => {
+                let error = |e: &dyn std::fmt::Display| {
+                    format!("cannot open input {name} file '{path}': {e}")
+                };
+                let file = File::open(path).map_err(|e| error(&e))?;
+                decoder(BufReader::new(file), Compression::from_path(path)).map_err(|e| error(&e))
+            }
+            None => Err(not_supplied(name)),
+        }
+    }
+}
+
+/// The error for a segment reading an input its host did not supply.
+pub(crate) fn not_supplied(name: &str) -> String {
+    format!("Input {name} is not supplied (use --in {name}=...)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn read(inputs: &InputFiles, name: &str) -> Result<String, String> {
+        let mut text = String::new();
+        inputs
+            .open_input(name)?
+            .read_to_string(&mut text)
+            .map_err(|e| e.to_string())?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_open_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trans.txt");
+        std::fs::write(&path, "A1\nB2\n").unwrap();
+        let inputs = InputFiles::new()
+            .with_file("TRANS", path.to_str().unwrap())
+            .with_text("MASTER", "OLD")
+            .with_text("MASTER", "NEW");
+
+        assert_eq!(read(&inputs, "TRANS").unwrap(), "A1\nB2\n");
+        // The last one given for a name is read
+        assert_eq!(read(&inputs, "MASTER").unwrap(), "NEW");
+        assert!(read(&inputs, "OTHER").unwrap_err().contains("--in OTHER="));
+    }
+
+    #[test]
+    fn test_missing_file_fails_when_opened() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("none.txt");
+        // Naming a file that does not exist fails only when it is read
+        let inputs = InputFiles::new().with_file("TRANS", path.to_str().unwrap());
+        let err = read(&inputs, "TRANS").unwrap_err();
+        assert!(err.starts_with("cannot open input TRANS file"), "{err}");
+    }
+}
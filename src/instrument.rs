@@ -0,0 +1,220 @@
+//! Spans and events for the `tracing` crate.
+//!
+//! With the `tracing` feature, the executors report each run as a
+//! `pipeline` span and each stage as a `stage` span inside it, entered
+//! around every call into the stage, so a subscriber's busy time for a
+//! stage span is the time spent in that stage. The record-at-a-time
+//! executor also opens a `record` span per input record at `TRACE` level;
+//! subscribers filtering at `DEBUG` or above skip them at little cost.
+//!
+//! | Span or event | Level | Fields |
+//! |---------------|-------|--------|
+//! | `pipeline` span | `INFO` | `executor`, `stages`, `records_in`, `records_out` |
+//! | `stage` span | `INFO` | `index`, `name` |
+//! | `record` span | `TRACE` | `index` |
+//! | stage complete event | `DEBUG` | `index`, `name`, `records_in`, `records_out` |
+//!
+//! Stages are numbered as in [`DebugInfo`](crate::DebugInfo), the source
+//! stage 0. The batch executor reports each stage's counts in a stage
+//! complete event; the record-at-a-time executors in `naive-pipe` report
+//! the run's counts on the `pipeline` span. Threaded stages' spans are
+//! created on their own threads, outside the `pipeline` span.
+//!
+//! Without the feature every function here does nothing, so executors call
+//! them unconditionally.
+
+use std::marker::PhantomData;
+
+/// The span of a whole pipeline run, entered while it is alive.
+#[must_use = "the run span ends when dropped"]
+pub struct RunSpan {
+    #[cfg(feature = "tracing")]
+    entered: tracing::span::EnteredSpan,
+}
+
+impl RunSpan {
+    /// Opens and enters the span of a run by `executor` over `stages`
+    /// stages.
+    pub fn enter(executor: &'static str, stages: usize) -> Self {
+        #[cfg(feature = "tracing")]
+        return Self {
+            entered: tracing::info_span!(
+                "pipeline",
+                executor,
+                stages,
+                records_in = tracing::field::Empty,
+                records_out = tracing::field::Empty,
+            )
+            .entered(),
+        };
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = (executor, stages);
+            Self {}
+        }
+    }
+
+    /// Records the run's input and output counts on its span.
+    pub fn finish(&self, records_in: usize, records_out: usize) {
+        #[cfg(feature = "tracing")]
+        {
+            self.entered.record("records_in", records_in);
+            self.entered.record("records_out", records_out);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (records_in, records_out);
+    }
+}
+
+/// The span of one stage, entered around each call into it.
+#[derive(Clone)]
+pub struct StageSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl StageSpan {
+    /// Creates the span of stage `index`, named after the stage.
+    pub fn new(index: usize, name: &str) -> Self {
+        #[cfg(feature = "tracing")]
+        return Self {
+            span: tracing::info_span!("stage", index, name),
+        };
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = (index, name);
+            Self {}
+        }
+    }
+
+    /// Enters the span until the returned guard is dropped.
+    pub fn enter(&self) -> SpanGuard<'_> {
+        SpanGuard {
+            #[cfg(feature = "tracing")]
+            _entered: self.span.enter(),
+            _span: PhantomData,
+        }
+    }
+}
+
+/// Keeps a stage or record span entered until dropped.
+#[must_use = "the span is exited when the guard is dropped"]
+pub struct SpanGuard<'a> {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::Entered<'a>,
+    _span: PhantomData<&'a ()>,
+}
+
+/// Keeps the `TRACE`-level span of one input record entered until dropped.
+#[must_use = "the record span ends when dropped"]
+pub struct RecordSpan {
+    #[cfg(feature = "tracing")]
+    _entered: tracing::span::EnteredSpan,
+}
+
+impl RecordSpan {
+    /// Opens and enters the span of input record `index`, counting from 0.
+    pub fn enter(index: usize) -> Self {
+        #[cfg(feature = "tracing")]
+        return Self {
+            _entered: tracing::trace_span!("record", index).entered(),
+        };
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = index;
+            Self {}
+        }
+    }
+}
+
+/// Reports that stage `index` has processed all of its records.
+pub fn stage_complete(index: usize, name: &str, records_in: usize, records_out: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(index, name, records_in, records_out, "stage complete");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (index, name, records_in, records_out);
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use tracing::span::{Attributes, Id, Record as Values};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::{HostVars, execute_pipeline_run};
+
+    /// Subscriber recording the name of each span entered, in order, and
+    /// counting events.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        next: Arc<AtomicU64>,
+        names: Arc<Mutex<Vec<(u64, String)>>>,
+        entered: Arc<Mutex<Vec<String>>>,
+        events: Arc<Mutex<usize>>,
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let id = self.next.fetch_add(1, Ordering::SeqCst) + 1;
+            let name = span.metadata().name().to_string();
+            self.names.lock().unwrap().push((id, name));
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _: &Id, _: &Values<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, _: &Event<'_>) {
+            *self.events.lock().unwrap() += 1;
+        }
+
+        fn enter(&self, span: &Id) {
+            let names = self.names.lock().unwrap();
+            if let Some((_, name)) = names.iter().find(|(id, _)| *id == span.into_u64()) {
+                self.entered.lock().unwrap().push(name.clone());
+            }
+        }
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    impl Recorder {
+        /// Returns how many times a span called `name` was entered.
+        fn count(&self, name: &str) -> usize {
+            self.entered
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|n| *n == name)
+                .count()
+        }
+    }
+
+    #[test]
+    fn test_batch_executor_spans() {
+        let recorder = Recorder::default();
+        let run = tracing::subscriber::with_default(recorder.clone(), || {
+            execute_pipeline_run(
+                "A\nB\nC",
+                "PIPE CONSOLE | LOCATE /B/ | UPPER | CONSOLE",
+                &None,
+                &HostVars::new(),
+            )
+        })
+        .unwrap();
+        assert_eq!(run.output, "B");
+        assert_eq!(recorder.count("pipeline"), 1);
+        // One span per stage, entered around each record pulled through it
+        let names = recorder.names.lock().unwrap();
+        assert_eq!(names.iter().filter(|(_, n)| n == "stage").count(), 4);
+        assert!(recorder.count("stage") > 4);
+        assert_eq!(*recorder.events.lock().unwrap(), 4);
+    }
+}
@@ -0,0 +1,877 @@
+//! Fixed-length (RECFM=FB) and variable-length (RECFM=VB) record I/O, and
+//! compressed files.
+//!
+//! z/OS fixed-length datasets transferred in binary are the records laid
+//! end to end, exactly LRECL bytes each, with no line terminators.
+//! `FbReader` cuts them back into `Record`s and `FbWriter` writes them out
+//! again, padding or truncating each record to the LRECL (80 bytes unless
+//! set). Reading such a file as text lines would join or split records
+//! wherever a newline byte happened to fall.
+//!
+//! z/OS variable-length datasets transferred in binary with their RDWs
+//! (e.g. `ftp` with `quote site rdw`) are a sequence of logical records,
+//! each preceded by a 4-byte Record Descriptor Word:
+//!
+//! | Bytes | Meaning                                              |
+//! |-------|------------------------------------------------------|
+//! | 0-1   | Record length, big-endian, including the 4-byte RDW  |
+//! | 2-3   | Segment flags; zero for records that are not spanned |
+//!
+//! `VbReader` maps each logical record into a `Record`, padding short
+//! records with spaces and truncating long ones to the LRECL (80 bytes
+//! unless set). `VbWriter` does the reverse, dropping trailing blanks as
+//! variable-length datasets usually do. Either can convert EBCDIC data.
+//!
+//! Archived datasets are usually compressed. With the `compress` feature,
+//! [`decoder()`] reads a gzip (`.gz`) or zstd (`.zst`) stream as it is
+//! decompressed and [`Encoder`] compresses what is written to it, so
+//! records never pass through a decompressed temporary file. Either wraps
+//! the readers and writers above, or plain text lines.
+//! [`Compression::from_path`] picks the format from a file name.
+//!
+//! With the `mmap` feature, [`MmapSource`] maps a fixed-block file into
+//! memory and hands out [`RecordView`](crate::RecordView)s of it, so a scan
+//! that only filters or counts makes no read call per block and copies no
+//! record.
+//!
+//! Like concatenated DD statements, several input files can be read as one
+//! stream, one after another: [`input_paths`] expands the file names and
+//! glob patterns (`data/2024-*.dat`) a command line gives into that list.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::Record;
+//! use pipelines_rs::io::{VbReader, VbWriter};
+//!
+//! let mut writer = VbWriter::new(Vec::new());
+//! writer.write_record(&Record::from_str("HELLO")).unwrap();
+//! let bytes = writer.into_inner();
+//! assert_eq!(bytes, b"\x00\x09\x00\x00HELLO");
+//!
+//! let records: Vec<Record> = VbReader::new(&bytes[..]).collect::<Result<_, _>>().unwrap();
+//! assert_eq!(records[0].as_str().trim_end(), "HELLO");
+//! ```
+//!
+//! ```
+//! use pipelines_rs::Record;
+//! use pipelines_rs::io::{FbReader, FbWriter};
+//!
+//! let mut writer = FbWriter::new(Vec::new()).with_lrecl(5);
+//! writer.write_record(&Record::from_str("AB")).unwrap();
+//! writer.write_record(&Record::from_str("CDEFGH")).unwrap();
+//! let bytes = writer.into_inner();
+//! assert_eq!(bytes, b"AB   CDEFG");
+//!
+//! let records: Vec<Record> = FbReader::new(&bytes[..])
+//!     .with_lrecl(5)
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(records[1].as_str(), "CDEFG");
+//! ```
+
+#[cfg(feature = "compress")]
+use std::io::BufReader;
+use std::io::{BufRead, ErrorKind, Read, Write};
+
+use crate::ebcdic::CodePage;
+use crate::error::{PipelineError, Result};
+use crate::record::{RECORD_WIDTH, Record};
+
+/// Reads fixed-length records laid end to end.
+pub struct FbReader<R> {
+    inner: R,
+    lrecl: usize,
+    code_page: Option<CodePage>,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> FbReader<R> {
+    /// Creates a reader of ASCII records with the default 80-byte LRECL.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            lrecl: RECORD_WIDTH,
+            code_page: None,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Sets the length of each record in the input.
+    #[must_use]
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl;
+        self
+    }
+
+    /// Decodes the record data from an EBCDIC code page.
+    #[must_use]
+    pub fn with_ebcdic(mut self, code_page: CodePage) -> Self {
+        self.code_page = Some(code_page);
+        self
+    }
+
+    /// Reads the next record, or `None` at end of input.
+    ///
+    /// Fails with [`PipelineError::ShortRecord`] if the input ends part
+    /// way through a record.
+    pub fn read_record(&mut self) -> Result<Option<Record>> {
+        let mut data = vec![0; self.lrecl];
+        let got = read_full(&mut self.inner, &mut data)?;
+        if got == 0 {
+            return Ok(None);
+        }
+        if got < self.lrecl {
+            return Err(PipelineError::ShortRecord {
+                offset: self.offset,
+                length: got,
+                lrecl: self.lrecl,
+            });
+        }
+        self.offset += self.lrecl as u64;
+
+        Ok(Some(match self.code_page {
+            Some(code_page) => Record::from_ebcdic_lrecl(&data, code_page, self.lrecl),
+            None => Record::from_bytes_lrecl(&data, self.lrecl),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for FbReader<R> {
+    type Item = Result<Record>;
+
+    /// Yields records until end of input or the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// A fixed-block file mapped into memory, read as record views.
+///
+/// The views borrow the mapped pages, so records the scan only looks at
+/// are never copied. The records must be ASCII: the bytes of an EBCDIC
+/// record are replaced, in a copy, as [`RecordView::new`] does.
+///
+/// Mapping is only sound while nothing changes the file, so
+/// [`open`](Self::open) is unsafe; a job reads its input datasets, it does
+/// not share them.
+///
+/// # Example
+///
+/// ```no_run
+/// use pipelines_rs::io::MmapSource;
+///
+/// // SAFETY: the extract is written before the job and only read by it
+/// let source = unsafe { MmapSource::open("extract.fb") }.unwrap().with_lrecl(80);
+/// let sales = source
+///     .views()
+///     .unwrap()
+///     .filter(|r| r.field_eq(18, 10, "SALES"))
+///     .count();
+/// println!("{sales} sales records of {}", source.len());
+/// ```
+///
+/// [`RecordView::new`]: crate::RecordView::new
+#[cfg(feature = "mmap")]
+pub struct MmapSource {
+    map: memmap2::Mmap,
+    lrecl: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl MmapSource {
+    /// Maps a file of records with the default 80-byte LRECL.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write, truncate or remove the file while the
+    /// `MmapSource` or a view of it lives: the views borrow the mapped
+    /// pages, so a change to the file changes bytes already handed out,
+    /// and truncating it makes reading them fault.
+    pub unsafe fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the file is only read, and the caller guarantees nothing
+        // changes it while it is mapped
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(Self {
+            map,
+            lrecl: RECORD_WIDTH,
+        })
+    }
+
+    /// Sets the length of each record in the file.
+    #[must_use]
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl.max(1);
+        self
+    }
+
+    /// Returns the number of records in the file, counting a short last
+    /// one.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.map.len().div_ceil(self.lrecl)
+    }
+
+    /// Returns true if the file is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the mapped bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Returns a view of each record in turn.
+    ///
+    /// Fails with [`PipelineError::ShortRecord`] if the file ends part way
+    /// through a record, as [`FbReader`] does.
+    pub fn views(&self) -> Result<impl Iterator<Item = crate::RecordView<'_>>> {
+        let length = self.map.len() % self.lrecl;
+        if length != 0 {
+            return Err(PipelineError::ShortRecord {
+                offset: (self.map.len() - length) as u64,
+                length,
+                lrecl: self.lrecl,
+            });
+        }
+        Ok(crate::RecordView::chunks(&self.map, self.lrecl))
+    }
+}
+
+/// Writes fixed-length records end to end.
+pub struct FbWriter<W: Write> {
+    inner: W,
+    lrecl: usize,
+    code_page: Option<CodePage>,
+}
+
+impl<W: Write> FbWriter<W> {
+    /// Creates a writer of ASCII records with the default 80-byte LRECL.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            lrecl: RECORD_WIDTH,
+            code_page: None,
+        }
+    }
+
+    /// Sets the length of each record written.
+    #[must_use]
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl;
+        self
+    }
+
+    /// Encodes the record data in an EBCDIC code page.
+    #[must_use]
+    pub fn with_ebcdic(mut self, code_page: CodePage) -> Self {
+        self.code_page = Some(code_page);
+        self
+    }
+
+    /// Writes one record, padded with blanks or truncated to the LRECL.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let bytes = record.as_bytes();
+        let used = self.lrecl.min(bytes.len());
+        let mut data = vec![b' '; self.lrecl];
+        data[..used].copy_from_slice(&bytes[..used]);
+        if let Some(code_page) = self.code_page {
+            data.iter_mut().for_each(|b| *b = code_page.encode(*b));
+        }
+        self.inner.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Length of a Record Descriptor Word.
+pub const RDW_LEN: usize = 4;
+
+/// Reads RDW-prefixed variable-length records.
+pub struct VbReader<R> {
+    inner: R,
+    lrecl: usize,
+    code_page: Option<CodePage>,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> VbReader<R> {
+    /// Creates a reader of ASCII records with the default 80-byte LRECL.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            lrecl: RECORD_WIDTH,
+            code_page: None,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Sets the length of the records read; longer data is truncated.
+    #[must_use]
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl;
+        self
+    }
+
+    /// Decodes the record data from an EBCDIC code page.
+    #[must_use]
+    pub fn with_ebcdic(mut self, code_page: CodePage) -> Self {
+        self.code_page = Some(code_page);
+        self
+    }
+
+    /// Reads the next record, or `None` at end of input.
+    pub fn read_record(&mut self) -> Result<Option<Record>> {
+        let mut rdw = [0; RDW_LEN];
+        let got = read_full(&mut self.inner, &mut rdw)?;
+        if got == 0 {
+            return Ok(None);
+        }
+        if got < RDW_LEN {
+            return Err(self.invalid(format!("only {got} bytes left for the RDW")));
+        }
+        let length = u16::from_be_bytes([rdw[0], rdw[1]]) as usize;
+        if length < RDW_LEN {
+            return Err(self.invalid(format!("record length {length} is less than 4")));
+        }
+        if rdw[2] != 0 || rdw[3] != 0 {
+            return Err(self.invalid("spanned records are not supported".to_string()));
+        }
+
+        let mut data = vec![0; length - RDW_LEN];
+        let got = read_full(&mut self.inner, &mut data)?;
+        if got < data.len() {
+            return Err(self.invalid(format!(
+                "record needs {} data bytes, only {got} left",
+                data.len()
+            )));
+        }
+        self.offset += length as u64;
+
+        Ok(Some(match self.code_page {
+            Some(code_page) => Record::from_ebcdic_lrecl(&data, code_page, self.lrecl),
+            None => Record::from_bytes_lrecl(&data, self.lrecl),
+        }))
+    }
+
+    fn invalid(&self, reason: String) -> PipelineError {
+        PipelineError::InvalidRdw {
+            offset: self.offset,
+            reason,
+        }
+    }
+}
+
+impl<R: Read> Iterator for VbReader<R> {
+    type Item = Result<Record>;
+
+    /// Yields records until end of input or the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Writes records with RDWs.
+pub struct VbWriter<W: Write> {
+    inner: W,
+    lrecl: usize,
+    code_page: Option<CodePage>,
+}
+
+impl<W: Write> VbWriter<W> {
+    /// Creates a writer of ASCII records with the default 80-byte LRECL.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            lrecl: RECORD_WIDTH,
+            code_page: None,
+        }
+    }
+
+    /// Sets the most data bytes written per record.
+    #[must_use]
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl;
+        self
+    }
+
+    /// Encodes the record data in an EBCDIC code page.
+    #[must_use]
+    pub fn with_ebcdic(mut self, code_page: CodePage) -> Self {
+        self.code_page = Some(code_page);
+        self
+    }
+
+    /// Writes one record without its trailing blanks.
+    ///
+    /// Fails if the data is too long for the 2-byte RDW length.
+    pub fn write_record(&mut self, record: &Record) -> Result<()> {
+        let bytes = record.as_bytes();
+        let text = &bytes[..self.lrecl.min(bytes.len())];
+        let used = text.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+        let data: Vec<u8> = match self.code_page {
+            Some(code_page) => text[..used].iter().map(|&b| code_page.encode(b)).collect(),
+            None => text[..used].to_vec(),
+        };
+
+        let length = u16::try_from(data.len() + RDW_LEN).map_err(|_| {
+            PipelineError::Stage(format!(
+                "record of {} bytes is too long for an RDW",
+                data.len()
+            ))
+        })?;
+        self.inner.write_all(&length.to_be_bytes())?;
+        self.inner.write_all(&[0, 0])?;
+        self.inner.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Expands input file arguments into the files to read, in order. An
+/// argument containing `*`, `?`, or `[` is a glob pattern, replaced by the
+/// files it matches sorted by name; any other argument, including `-` for
+/// stdin, is kept as given.
+///
+/// # Errors
+///
+/// Fails with [`PipelineError::InputPattern`] if a pattern is malformed or
+/// matches no files.
+pub fn input_paths(args: &[impl AsRef<str>]) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for arg in args {
+        let arg = arg.as_ref();
+        if !arg.contains(['*', '?', '[']) {
+            paths.push(arg.to_string());
+            continue;
+        }
+        let pattern_error = |reason: String| PipelineError::InputPattern {
+            pattern: arg.to_string(),
+            reason,
+        };
+        let mut matched = glob::glob(arg)
+            .map_err(|e| pattern_error(e.to_string()))?
+            .filter_map(std::result::Result::ok)
+            .filter(|path| path.is_file())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        if matched.is_empty() {
+            return Err(pattern_error("matches no files".to_string()));
+        }
+        matched.sort();
+        paths.extend(matched);
+    }
+    Ok(paths)
+}
+
+/// Compression of a file's bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Stored as is
+    None,
+    /// gzip (`.gz`)
+    Gzip,
+    /// Zstandard (`.zst`)
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression a file name's extension implies: `.gz` is
+    /// gzip, `.zst` is zstd, and anything else is uncompressed.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".gz") {
+            Self::Gzip
+        } else if path.ends_with(".zst") {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// Wraps `reader` to decompress it as it is read. Concatenated gzip members
+/// or zstd frames, as appending to a compressed file leaves, are read as
+/// one stream.
+///
+/// Without the `compress` feature, only [`Compression::None`] can be read.
+pub fn decoder<'a, R: BufRead + 'a>(
+    reader: R,
+    compression: Compression,
+) -> Result<Box<dyn BufRead + 'a>> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        #[cfg(feature = "compress")]
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "compress")]
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        #[cfg(not(feature = "compress"))]
+        _ => return Err(compress_unsupported()),
+    })
+}
+
+/// Compresses what is written to it. Call [`Encoder::finish`] at the end to
+/// write the compressed stream's trailer.
+pub struct Encoder<W: Write> {
+    inner: EncoderInner<W>,
+}
+
+enum EncoderInner<W: Write> {
+    None(W),
+    #[cfg(feature = "compress")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "compress")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Creates an encoder writing `compression` to `inner`, at the
+    /// format's default level.
+    ///
+    /// Without the `compress` feature, only [`Compression::None`] can be
+    /// written.
+    pub fn new(inner: W, compression: Compression) -> Result<Self> {
+        let inner = match compression {
+            Compression::None => EncoderInner::None(inner),
+            #[cfg(feature = "compress")]
+            Compression::Gzip => EncoderInner::Gzip(flate2::write::GzEncoder::new(
+                inner,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "compress")]
+            Compression::Zstd => EncoderInner::Zstd(zstd::Encoder::new(inner, 0)?),
+            #[cfg(not(feature = "compress"))]
+            _ => return Err(compress_unsupported()),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Writes the end of the compressed stream, flushes, and returns the
+    /// underlying writer.
+    #[cfg(feature = "compress")]
+    pub fn finish(self) -> Result<W> {
+        let mut inner = match self.inner {
+            EncoderInner::None(inner) => inner,
+            EncoderInner::Gzip(encoder) => encoder.finish()?,
+            EncoderInner::Zstd(encoder) => encoder.finish()?,
+        };
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    /// Flushes and returns the underlying writer.
+    #[cfg(not(feature = "compress"))]
+    pub fn finish(self) -> Result<W> {
+        let EncoderInner::None(mut inner) = self.inner;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            EncoderInner::None(inner) => inner.write(buf),
+            #[cfg(feature = "compress")]
+            EncoderInner::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "compress")]
+            EncoderInner::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.inner {
+            EncoderInner::None(inner) => inner.flush(),
+            #[cfg(feature = "compress")]
+            EncoderInner::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "compress")]
+            EncoderInner::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(not(feature = "compress"))]
+fn compress_unsupported() -> PipelineError {
+    PipelineError::Io(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "gzip and zstd files require the compress feature",
+    ))
+}
+
+/// Reads until `buf` is full or the input ends, returning the bytes read.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(bytes: &[u8]) -> Result<Vec<Record>> {
+        VbReader::new(bytes).collect()
+    }
+
+    #[test]
+    fn test_read_records() {
+        let bytes = b"\x00\x07\x00\x00ABC\x00\x04\x00\x00\x00\x06\x00\x00XY";
+        let records = read_all(bytes).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_str().trim_end(), "ABC");
+        assert!(records[1].is_blank());
+        assert_eq!(records[2].as_str().trim_end(), "XY");
+    }
+
+    #[test]
+    fn test_read_long_record_truncates() {
+        let mut bytes = vec![0x00, 104, 0, 0];
+        bytes.extend(std::iter::repeat_n(b'A', 100));
+        let records = read_all(&bytes).unwrap();
+        assert_eq!(records[0].as_str(), "A".repeat(80));
+
+        let records: Vec<Record> = VbReader::new(&bytes[..])
+            .with_lrecl(10)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records[0].as_str(), "A".repeat(10));
+
+        let records: Vec<Record> = VbReader::new(&bytes[..])
+            .with_lrecl(132)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records[0].as_str().trim_end(), "A".repeat(100));
+        assert_eq!(records[0].lrecl(), 132);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let err = read_all(b"\x00\x07\x00\x00ABC\x00\x02\x00\x00").unwrap_err();
+        assert!(err.to_string().contains("at byte 7"), "{err}");
+        assert!(read_all(b"\x00\x09\x00\x00ABC").is_err());
+        assert!(read_all(b"\x00\x07\x01\x00ABC").is_err());
+        assert!(read_all(b"\x00\x07").is_err());
+
+        let mut reader = VbReader::new(&b"\x00"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_ebcdic_round_trip() {
+        let mut writer = VbWriter::new(Vec::new()).with_ebcdic(CodePage::Cp037);
+        writer.write_record(&Record::from_str("HELLO  ")).unwrap();
+        writer.write_record(&Record::new()).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(&bytes[..9], b"\x00\x09\x00\x00\xC8\xC5\xD3\xD3\xD6");
+        assert_eq!(&bytes[9..], b"\x00\x04\x00\x00");
+
+        let records: Vec<Record> = VbReader::new(&bytes[..])
+            .with_ebcdic(CodePage::Cp037)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(records[0].as_str().trim_end(), "HELLO");
+        assert!(records[1].is_blank());
+    }
+
+    #[test]
+    fn test_fb_round_trip() {
+        let mut writer = FbWriter::new(Vec::new());
+        writer.write_record(&Record::from_str("LINE\nONE")).unwrap();
+        writer.write_record(&Record::new()).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 160);
+        assert!(!bytes[8..].contains(&b'\n'));
+
+        let records: Vec<Record> = FbReader::new(&bytes[..]).collect::<Result<_>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_str().trim_end(), "LINE\nONE");
+        assert!(records[1].is_blank());
+    }
+
+    #[test]
+    fn test_fb_ebcdic_and_short_record() {
+        let mut writer = FbWriter::new(Vec::new())
+            .with_lrecl(4)
+            .with_ebcdic(CodePage::Cp037);
+        writer.write_record(&Record::from_str("HI")).unwrap();
+        let mut bytes = writer.into_inner();
+        assert_eq!(bytes, b"\xC8\xC9\x40\x40");
+
+        bytes.extend(b"\xC8\xC9");
+        let mut reader = FbReader::new(&bytes[..])
+            .with_lrecl(4)
+            .with_ebcdic(CodePage::Cp037);
+        assert_eq!(reader.next().unwrap().unwrap().as_str(), "HI  ");
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "short record at byte 4: 2 of 4 bytes before end of input"
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_writer_lrecl() {
+        let mut writer = VbWriter::new(Vec::new()).with_lrecl(3);
+        writer.write_record(&Record::from_str("ABCDEF")).unwrap();
+        assert_eq!(writer.into_inner(), b"\x00\x07\x00\x00ABC");
+    }
+
+    #[test]
+    fn test_input_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["2024-02.dat", "2024-01.dat", "2023-12.dat"] {
+            std::fs::write(dir.path().join(name), "A\n").unwrap();
+        }
+        let pattern = format!("{}/2024-*.dat", dir.path().display());
+        let paths = input_paths(&["first.dat", &pattern, "-"]).unwrap();
+        let names: Vec<&str> = paths
+            .iter()
+            .map(|p| p.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(names, ["first.dat", "2024-01.dat", "2024-02.dat", "-"]);
+
+        let missing = format!("{}/2025-*.dat", dir.path().display());
+        let err = input_paths(&[missing]).unwrap_err();
+        assert!(err.to_string().ends_with("matches no files"));
+        assert!(input_paths(&["[z"]).is_err());
+    }
+
+    #[test]
+    fn test_compression_from_path() {
+        assert_eq!(Compression::from_path("day.data.gz"), Compression::Gzip);
+        assert_eq!(Compression::from_path("day.data.zst"), Compression::Zstd);
+        assert_eq!(Compression::from_path("day.gz.data"), Compression::None);
+
+        #[cfg(not(feature = "compress"))]
+        assert!(Encoder::new(Vec::new(), Compression::Zstd).is_err());
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_compressed_round_trip() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            // Two streams end to end, as appending to a file leaves them
+            let mut bytes = Vec::new();
+            for text in ["A1\nB1\n", "C1\n"] {
+                let mut encoder = Encoder::new(Vec::new(), compression).unwrap();
+                encoder.write_all(text.as_bytes()).unwrap();
+                bytes.extend(encoder.finish().unwrap());
+            }
+            if compression != Compression::None {
+                assert_ne!(&bytes[..2], b"A1");
+            }
+            let lines: Vec<String> = decoder(&bytes[..], compression)
+                .unwrap()
+                .lines()
+                .collect::<std::io::Result<_>>()
+                .unwrap();
+            assert_eq!(lines, ["A1", "B1", "C1"]);
+        }
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_compressed_fb_records() {
+        let mut writer =
+            FbWriter::new(Encoder::new(Vec::new(), Compression::Gzip).unwrap()).with_lrecl(4);
+        writer.write_record(&Record::from_str("AB")).unwrap();
+        let bytes = writer.into_inner().finish().unwrap();
+
+        let reader = FbReader::new(decoder(&bytes[..], Compression::Gzip).unwrap()).with_lrecl(4);
+        let records: Vec<Record> = reader.collect::<Result<_>>().unwrap();
+        assert_eq!(records[0].as_str(), "AB  ");
+
+        let err = decoder(&b"not zstd"[..], Compression::Zstd)
+            .and_then(|mut r| Ok(r.read_to_end(&mut Vec::new())?));
+        assert!(err.is_err());
+    }
+
+    /// Maps a file of the test's own temporary directory.
+    #[cfg(feature = "mmap")]
+    fn map(path: &std::path::Path) -> MmapSource {
+        // SAFETY: nothing else knows the temporary file, and the test
+        // does not write it while it is mapped
+        unsafe { MmapSource::open(path) }.unwrap()
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sales.fb");
+        std::fs::write(&path, b"SMITH SALES JONES ENG   DOE   SALES ").unwrap();
+
+        let source = map(&path).with_lrecl(12);
+        assert_eq!(source.len(), 3);
+        let sales: Vec<_> = source
+            .views()
+            .unwrap()
+            .filter(|r| r.field_eq(6, 6, "SALES"))
+            .collect();
+        assert_eq!(sales.len(), 2);
+        assert!(sales[1].is_borrowed());
+        assert_eq!(sales[1].to_record().field(0, 6).trim(), "DOE");
+
+        let err = map(&path).with_lrecl(10).views().err();
+        assert_eq!(
+            err.unwrap().to_string(),
+            "short record at byte 30: 6 of 10 bytes before end of input"
+        );
+
+        let empty = dir.path().join("empty.fb");
+        std::fs::write(&empty, b"").unwrap();
+        let source = map(&empty);
+        assert!(source.is_empty());
+        assert_eq!(source.views().unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,530 @@
+//! Jobs: several pipeline steps run in order, like the steps of a JCL job.
+//!
+//! A job file names its steps, the pipeline each runs, and the datasets
+//! they read and write. A dataset is a file, or a temporary dataset
+//! (`&&NAME`) that one step writes and later steps read, kept only for the
+//! length of the job. Each step ends with a condition code (see
+//! [`crate::condcode`]), and a step's `COND` tests decide from the codes of
+//! the steps before it whether it runs:
+//!
+//! ```text
+//! # Nightly sales report
+//! JOB NIGHTLY
+//!
+//! STEP EXTRACT
+//!   PIPE specs/filter-sales.pipe
+//!   IN   specs/input-fixed-80.data
+//!   OUT  &&SALES
+//!
+//! STEP REPORT
+//!   PIPE specs/sales-report.pipe
+//!   IN   &&SALES
+//!   OUT  work/sales-report.txt
+//!   COND 4 LT
+//! ```
+//!
+//! Step statements:
+//!
+//! | Statement | Meaning |
+//! |-----------|---------|
+//! | `PIPE path` | The pipeline file the step runs (required) |
+//! | `IN dataset` | Main input; several are read in order as one |
+//! | `OUT dataset` | Main output (default: the job's stdout) |
+//! | `DD NAME dataset` | Dataset for `< NAME`, `? < NAME`, `> NAME`, `>> NAME`, or `SPLIT NAME` |
+//! | `SET NAME=VALUE` | STAMP parameter, read as `PARM.NAME` |
+//! | `COND code op [step]` | Skip the step if `code op rc` holds for an earlier step (repeatable) |
+//! | `COND EVEN` / `COND ONLY` | Run the step even if, or only if, an earlier step failed |
+//!
+//! As in JCL, `COND 4 LT` skips the step when 4 is less than the code of
+//! any earlier step that ran (or of the named step), so it runs only while
+//! every code so far is 4 or less. Operators are `GT`, `GE`, `EQ`, `NE`,
+//! `LT`, and `LE`. Once a step fails (code 12), later steps are skipped
+//! unless they say `COND EVEN` or `COND ONLY`.
+//!
+//! The core never opens files: [`Job`] parses the job and decides which
+//! steps run, and the host (`pipe-job`) reads and writes the datasets.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::ConditionCode;
+//! use pipelines_rs::job::{Job, StepOutcome};
+//!
+//! let job = Job::parse("JOB J\nSTEP A PIPE a.pipe\nSTEP B PIPE b.pipe COND 4 LT").unwrap();
+//! assert_eq!(job.steps.len(), 2);
+//!
+//! let done = [StepOutcome::new("A", ConditionCode::Error)];
+//! assert_eq!(
+//!     job.steps[1].skip_reason(&done).unwrap(),
+//!     "COND 4 LT is true for A (RC 8)"
+//! );
+//! ```
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::SplitWhitespace;
+
+use crate::condcode::ConditionCode;
+
+/// The words of a job file line.
+type Words<'a> = Peekable<SplitWhitespace<'a>>;
+
+/// A job: its name and its steps, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The job name from the `JOB` line.
+    pub name: String,
+    /// The steps, in the order they run.
+    pub steps: Vec<JobStep>,
+}
+
+/// One step of a job.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobStep {
+    /// The step name, also the step's `STEP` host variable.
+    pub name: String,
+    /// Path of the pipeline file the step runs.
+    pub pipeline: String,
+    /// Main inputs, read in order as one stream.
+    pub inputs: Vec<Dataset>,
+    /// Main output, or `None` for the job's stdout.
+    pub output: Option<Dataset>,
+    /// Datasets bound to names the pipeline reads or writes.
+    pub dds: Vec<(String, Dataset)>,
+    /// STAMP parameters.
+    pub parms: Vec<(String, String)>,
+    /// Tests that skip the step when one holds.
+    pub cond: Vec<CondTest>,
+    /// Whether the step runs after an earlier step failed.
+    pub after_failure: AfterFailure,
+}
+
+/// Where a step reads or writes records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dataset {
+    /// A file.
+    File(String),
+    /// A temporary dataset (`&&NAME`), kept for the length of the job.
+    Temp(String),
+}
+
+/// A `COND code op [step]` test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CondTest {
+    /// The code compared with each step's condition code.
+    pub code: i32,
+    /// How `code` is compared with the step's code.
+    pub op: CondOp,
+    /// The step whose code is tested, or `None` for every earlier step.
+    pub step: Option<String>,
+}
+
+/// The comparison of a [`CondTest`], `code op rc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CondOp {
+    /// `GT`: code greater than rc.
+    Gt,
+    /// `GE`: code greater than or equal to rc.
+    Ge,
+    /// `EQ`: code equal to rc.
+    Eq,
+    /// `NE`: code not equal to rc.
+    Ne,
+    /// `LT`: code less than rc.
+    Lt,
+    /// `LE`: code less than or equal to rc.
+    Le,
+}
+
+/// Whether a step runs after an earlier step failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AfterFailure {
+    /// Skip the step (the default).
+    #[default]
+    Skip,
+    /// Run the step anyway (`COND EVEN`).
+    Even,
+    /// Run the step only after a failure (`COND ONLY`).
+    Only,
+}
+
+/// The condition code of a step that ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutcome {
+    /// The step name.
+    pub step: String,
+    /// The step's condition code; [`ConditionCode::Fatal`] if it failed.
+    pub code: ConditionCode,
+}
+
+impl StepOutcome {
+    /// Records that step `step` ended with `code`.
+    pub fn new(step: impl Into<String>, code: ConditionCode) -> Self {
+        Self {
+            step: step.into(),
+            code,
+        }
+    }
+}
+
+impl Dataset {
+    /// Parses a dataset: `&&NAME` for a temporary dataset, anything else
+    /// a file path.
+    pub fn parse(word: &str) -> Self {
+        match word.strip_prefix("&&") {
+            Some(name) => Dataset::Temp(name.to_uppercase()),
+            None => Dataset::File(word.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dataset::File(path) => write!(f, "{path}"),
+            Dataset::Temp(name) => write!(f, "&&{name}"),
+        }
+    }
+}
+
+impl CondOp {
+    fn parse(word: &str) -> Option<Self> {
+        match word.to_uppercase().as_str() {
+            "GT" => Some(CondOp::Gt),
+            "GE" => Some(CondOp::Ge),
+            "EQ" => Some(CondOp::Eq),
+            "NE" => Some(CondOp::Ne),
+            "LT" => Some(CondOp::Lt),
+            "LE" => Some(CondOp::Le),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `code op rc` holds.
+    pub fn holds(self, code: i32, rc: i32) -> bool {
+        match self {
+            CondOp::Gt => code > rc,
+            CondOp::Ge => code >= rc,
+            CondOp::Eq => code == rc,
+            CondOp::Ne => code != rc,
+            CondOp::Lt => code < rc,
+            CondOp::Le => code <= rc,
+        }
+    }
+}
+
+impl fmt::Display for CondOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CondOp::Gt => "GT",
+            CondOp::Ge => "GE",
+            CondOp::Eq => "EQ",
+            CondOp::Ne => "NE",
+            CondOp::Lt => "LT",
+            CondOp::Le => "LE",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for CondTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "COND {} {}", self.code, self.op)?;
+        if let Some(step) = &self.step {
+            write!(f, " {step}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Job {
+    /// Parses a job file.
+    ///
+    /// Statements are a keyword and its words, several to a line if need
+    /// be; `#` starts a comment line. Step names must be unique, and a
+    /// `COND` test may name only an earlier step.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut steps: Vec<JobStep> = Vec::new();
+        for (line_num, line) in text.lines().enumerate() {
+            let at_line = |e: String| format!("Line {}: {}", line_num + 1, e);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace().peekable();
+            while let Some(keyword) = words.next() {
+                match keyword.to_uppercase().as_str() {
+                    "JOB" if name.is_none() && steps.is_empty() => {
+                        name = Some(
+                            required(&mut words, keyword, "a job name")
+                                .map_err(at_line)?
+                                .to_uppercase(),
+                        );
+                    }
+                    "JOB" => return Err(at_line("JOB must come once, first".to_string())),
+                    "STEP" => {
+                        let step = required(&mut words, keyword, "a step name")
+                            .map_err(at_line)?
+                            .to_uppercase();
+                        if steps.iter().any(|s| s.name == step) {
+                            return Err(at_line(format!("Step {step} is defined more than once")));
+                        }
+                        steps.push(JobStep {
+                            name: step,
+                            ..JobStep::default()
+                        });
+                    }
+                    statement => {
+                        let Some(step) = steps.last_mut() else {
+                            return Err(at_line(format!("{statement} must follow a STEP")));
+                        };
+                        match statement {
+                            "PIPE" => {
+                                step.pipeline = required(&mut words, keyword, "a pipeline file")
+                                    .map_err(at_line)?
+                                    .to_string()
+                            }
+                            "IN" => step.inputs.push(Dataset::parse(
+                                required(&mut words, keyword, "a dataset").map_err(at_line)?,
+                            )),
+                            "OUT" => {
+                                step.output = Some(Dataset::parse(
+                                    required(&mut words, keyword, "a dataset").map_err(at_line)?,
+                                ))
+                            }
+                            "DD" => {
+                                let dd = required(&mut words, keyword, "a name and a dataset")
+                                    .map_err(at_line)?
+                                    .to_string();
+                                let dataset = Dataset::parse(
+                                    required(&mut words, keyword, "a name and a dataset")
+                                        .map_err(at_line)?,
+                                );
+                                step.dds.push((dd, dataset));
+                            }
+                            "SET" => {
+                                let parm =
+                                    required(&mut words, keyword, "NAME=VALUE").map_err(at_line)?;
+                                match parm.split_once('=') {
+                                    Some((n, v)) if !n.is_empty() => {
+                                        step.parms.push((n.to_string(), v.to_string()))
+                                    }
+                                    _ => {
+                                        return Err(at_line(format!(
+                                            "SET expects NAME=VALUE, got '{parm}'"
+                                        )));
+                                    }
+                                }
+                            }
+                            "COND" => {
+                                let first = required(
+                                    &mut words,
+                                    keyword,
+                                    "a code and an operator, EVEN, or ONLY",
+                                )
+                                .map_err(at_line)?;
+                                match first.to_uppercase().as_str() {
+                                    "EVEN" => step.after_failure = AfterFailure::Even,
+                                    "ONLY" => step.after_failure = AfterFailure::Only,
+                                    _ => {
+                                        let code = first.parse().map_err(|_| {
+                                            at_line(format!("Invalid COND code '{first}'"))
+                                        })?;
+                                        let op = required(&mut words, keyword, "an operator")
+                                            .map_err(at_line)?;
+                                        let op = CondOp::parse(op).ok_or_else(|| {
+                                            at_line(format!(
+                                                "Invalid COND operator '{op}' (use GT, GE, EQ, NE, LT, or LE)"
+                                            ))
+                                        })?;
+                                        // A step name follows unless the next word
+                                        // starts another statement
+                                        let target = words
+                                            .next_if(|w| !is_keyword(w))
+                                            .map(str::to_uppercase);
+                                        step.cond.push(CondTest {
+                                            code,
+                                            op,
+                                            step: target,
+                                        });
+                                    }
+                                }
+                            }
+                            _ => return Err(at_line(format!("Unknown job statement '{keyword}'"))),
+                        }
+                    }
+                }
+            }
+        }
+        let name = name.ok_or("A job starts with a JOB line")?;
+        if steps.is_empty() {
+            return Err(format!("Job {name} has no steps"));
+        }
+        for (idx, step) in steps.iter().enumerate() {
+            if step.pipeline.is_empty() {
+                return Err(format!("Step {} needs a PIPE file", step.name));
+            }
+            for test in &step.cond {
+                if let Some(target) = &test.step
+                    && !steps[..idx].iter().any(|s| &s.name == target)
+                {
+                    return Err(format!("Step {}: {test} names no earlier step", step.name));
+                }
+            }
+        }
+        Ok(Self { name, steps })
+    }
+
+    /// Returns the index of step `name`, for restarting the job there.
+    pub fn step_index(&self, name: &str) -> Option<usize> {
+        self.steps
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl JobStep {
+    /// Returns why the step is skipped, given the outcomes of the steps
+    /// that ran before it, or `None` if it runs.
+    pub fn skip_reason(&self, done: &[StepOutcome]) -> Option<String> {
+        let failed = done.iter().find(|o| o.code == ConditionCode::Fatal);
+        match (self.after_failure, failed) {
+            (AfterFailure::Skip, Some(o)) => return Some(format!("{} failed", o.step)),
+            (AfterFailure::Only, None) => return Some("COND ONLY and no step failed".to_string()),
+            _ => {}
+        }
+        self.cond.iter().find_map(|test| {
+            done.iter()
+                .filter(|o| test.step.as_ref().is_none_or(|s| *s == o.step))
+                .find(|o| test.op.holds(test.code, o.code.code()))
+                .map(|o| format!("{test} is true for {} (RC {})", o.step, o.code))
+        })
+    }
+
+    /// Returns the dataset bound to `name` by a `DD` statement.
+    pub fn dd(&self, name: &str) -> Option<&Dataset> {
+        self.dds
+            .iter()
+            .rev()
+            .find(|(dd, _)| dd.eq_ignore_ascii_case(name))
+            .map(|(_, dataset)| dataset)
+    }
+}
+
+/// Returns the next word of a statement, which `keyword` requires.
+fn required<'a>(words: &mut Words<'a>, keyword: &str, what: &str) -> Result<&'a str, String> {
+    words
+        .next()
+        .ok_or_else(|| format!("{} requires {what}", keyword.to_uppercase()))
+}
+
+/// Returns true if `word` starts a job statement.
+fn is_keyword(word: &str) -> bool {
+    matches!(
+        word.to_uppercase().as_str(),
+        "JOB" | "STEP" | "PIPE" | "IN" | "OUT" | "DD" | "SET" | "COND"
+    )
+}
+
+/// Returns the highest condition code of the steps that ran, the job's
+/// code.
+pub fn max_code(done: &[StepOutcome]) -> ConditionCode {
+    done.iter().map(|o| o.code).max().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB: &str = "\
+# Two steps through a temporary dataset
+JOB nightly
+STEP extract
+  PIPE extract.pipe
+  IN   a.data
+  IN   b.data.gz
+  OUT  &&sales
+  DD   TRANS trans.data
+  SET  REGION=EAST
+STEP report PIPE report.pipe IN &&SALES OUT report.txt COND 4 LT COND 8 EQ extract
+STEP cleanup PIPE cleanup.pipe COND EVEN
+";
+
+    #[test]
+    fn test_parse_job() {
+        let job = Job::parse(JOB).unwrap();
+        assert_eq!(job.name, "NIGHTLY");
+        let names: Vec<&str> = job.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["EXTRACT", "REPORT", "CLEANUP"]);
+
+        let extract = &job.steps[0];
+        assert_eq!(extract.pipeline, "extract.pipe");
+        assert_eq!(
+            extract.inputs,
+            [
+                Dataset::File("a.data".to_string()),
+                Dataset::File("b.data.gz".to_string())
+            ]
+        );
+        assert_eq!(extract.output, Some(Dataset::Temp("SALES".to_string())));
+        assert_eq!(
+            extract.dd("trans"),
+            Some(&Dataset::File("trans.data".to_string()))
+        );
+        assert_eq!(extract.parms, [("REGION".to_string(), "EAST".to_string())]);
+
+        let report = &job.steps[1];
+        assert_eq!(report.inputs, [Dataset::Temp("SALES".to_string())]);
+        assert_eq!(report.cond.len(), 2);
+        assert_eq!(report.cond[0].to_string(), "COND 4 LT");
+        assert_eq!(report.cond[1].to_string(), "COND 8 EQ EXTRACT");
+        assert_eq!(job.steps[2].after_failure, AfterFailure::Even);
+        assert_eq!(job.step_index("report"), Some(1));
+    }
+
+    #[test]
+    fn test_skip_logic() {
+        let job = Job::parse(JOB).unwrap();
+        let (report, cleanup) = (&job.steps[1], &job.steps[2]);
+        let ran = |code| [StepOutcome::new("EXTRACT", code)];
+
+        assert_eq!(report.skip_reason(&ran(ConditionCode::Warning)), None);
+        assert_eq!(
+            report.skip_reason(&ran(ConditionCode::Error)).unwrap(),
+            "COND 4 LT is true for EXTRACT (RC 8)"
+        );
+        assert_eq!(
+            report.skip_reason(&ran(ConditionCode::Fatal)).unwrap(),
+            "EXTRACT failed"
+        );
+        assert_eq!(cleanup.skip_reason(&ran(ConditionCode::Fatal)), None);
+        assert_eq!(report.skip_reason(&[]), None);
+
+        let only = Job::parse("JOB J\nSTEP A PIPE a.pipe COND ONLY").unwrap();
+        assert!(only.steps[0].skip_reason(&ran(ConditionCode::Ok)).is_some());
+        assert_eq!(only.steps[0].skip_reason(&ran(ConditionCode::Fatal)), None);
+        assert_eq!(
+            max_code(&[
+                StepOutcome::new("A", ConditionCode::Warning),
+                StepOutcome::new("B", ConditionCode::Ok)
+            ]),
+            ConditionCode::Warning
+        );
+    }
+
+    #[test]
+    fn test_job_errors() {
+        let err = |text: &str| Job::parse(text).unwrap_err();
+        assert_eq!(err("STEP A PIPE a.pipe"), "A job starts with a JOB line");
+        assert_eq!(err("JOB J"), "Job J has no steps");
+        assert_eq!(err("JOB J\nSTEP A"), "Step A needs a PIPE file");
+        assert!(err("JOB J\nPIPE a.pipe").contains("PIPE must follow a STEP"));
+        assert!(err("JOB J\nSTEP A PIPE a\nSTEP a PIPE b").contains("defined more than once"));
+        assert!(err("JOB J\nSTEP A PIPE a COND 4 XX").contains("Invalid COND operator"));
+        assert!(err("JOB J\nSTEP A PIPE a COND 4 LT B").contains("names no earlier step"));
+        assert!(err("JOB J\nSTEP A PIPE a SET X").contains("NAME=VALUE"));
+        assert!(err("JOB J\nSTEP A PIPE").contains("PIPE requires a pipeline file"));
+        assert!(err("JOB J\nSTEP A PIPE a FOO").contains("Unknown job statement 'FOO'"));
+    }
+}
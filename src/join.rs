@@ -0,0 +1,333 @@
+//! Joining two record streams on key fields.
+//!
+//! A [`Join`] pairs each record of a pipeline (the left input, DFSORT's
+//! F1) with every record of a second input (the right input, F2) whose key
+//! fields are equal, like DFSORT `JOINKEYS`. The [`JoinKind`] chooses
+//! whether paired records, unpaired records, or both are written.
+//!
+//! Output records are built from a list of [`JoinField`]s copied one after
+//! another, like `REFORMAT FIELDS=(F1:1,8,F2:9,10,?)`. Fields of a missing
+//! side are left blank. Without a layout, the left record (or, for an
+//! unpaired right record, the right record) is written as it is.
+//!
+//! Unlike DFSORT the inputs are not sorted: output follows the left input,
+//! with unpaired right records at the end in their input order. The right
+//! input is held in memory.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Join, JoinField, Record};
+//!
+//! let employees = vec![
+//!     Record::from_str("SMITH   D01"),
+//!     Record::from_str("JONES   D02"),
+//! ];
+//! let departments = vec![Record::from_str("D01 SALES")];
+//!
+//! let join = Join::new(vec![(8, 3)], vec![(0, 3)])
+//!     .layout(vec![JoinField::Left(0, 8), JoinField::Right(4, 5)]);
+//! let joined: Vec<_> = join.apply(employees.into_iter(), departments).collect();
+//!
+//! assert_eq!(joined.len(), 1);
+//! assert_eq!(joined[0].as_str().trim_end(), "SMITH   SALES");
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::Record;
+use crate::stage::key_of;
+
+/// Which records a [`Join`] writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinKind {
+    /// Paired records only (DFSORT default).
+    #[default]
+    Inner,
+    /// Paired records and unpaired left records (`UNPAIRED,F1`).
+    LeftOuter,
+    /// Paired records and unpaired records of both inputs (`UNPAIRED,F1,F2`).
+    FullOuter,
+    /// Unpaired left records only (`UNPAIRED,F1,ONLY`).
+    LeftUnpaired,
+    /// Unpaired right records only (`UNPAIRED,F2,ONLY`).
+    RightUnpaired,
+    /// Unpaired records of both inputs (`UNPAIRED,ONLY`).
+    Unpaired,
+}
+
+impl JoinKind {
+    fn paired(self) -> bool {
+        matches!(self, Self::Inner | Self::LeftOuter | Self::FullOuter)
+    }
+
+    fn unpaired_left(self) -> bool {
+        matches!(
+            self,
+            Self::LeftOuter | Self::FullOuter | Self::LeftUnpaired | Self::Unpaired
+        )
+    }
+
+    fn unpaired_right(self) -> bool {
+        matches!(self, Self::FullOuter | Self::RightUnpaired | Self::Unpaired)
+    }
+}
+
+/// One field of a joined output record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinField {
+    /// `(start, len)` of the left record (`F1:`).
+    Left(usize, usize),
+    /// `(start, len)` of the right record (`F2:`).
+    Right(usize, usize),
+    /// One byte telling where the record came from (`?`): `B` for a pair,
+    /// `1` for an unpaired left record, `2` for an unpaired right record.
+    Indicator,
+}
+
+impl JoinField {
+    fn len(self) -> usize {
+        match self {
+            Self::Left(_, len) | Self::Right(_, len) => len,
+            Self::Indicator => 1,
+        }
+    }
+}
+
+/// Join configuration: key fields of each input, which records to write,
+/// and the output layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Join {
+    left_keys: Vec<(usize, usize)>,
+    right_keys: Vec<(usize, usize)>,
+    kind: JoinKind,
+    layout: Vec<JoinField>,
+}
+
+impl Join {
+    /// Creates an inner join matching the left `(start, len)` key fields
+    /// with the right ones, in order.
+    pub fn new(left_keys: Vec<(usize, usize)>, right_keys: Vec<(usize, usize)>) -> Self {
+        Self {
+            left_keys,
+            right_keys,
+            kind: JoinKind::Inner,
+            layout: Vec::new(),
+        }
+    }
+
+    /// Sets which records are written.
+    pub fn kind(mut self, kind: JoinKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Writes paired and unpaired left records.
+    pub fn left_outer(self) -> Self {
+        self.kind(JoinKind::LeftOuter)
+    }
+
+    /// Sets the fields of the output records.
+    pub fn layout(mut self, fields: Vec<JoinField>) -> Self {
+        self.layout = fields;
+        self
+    }
+
+    /// Joins `left` with `right`, streaming the left input.
+    pub fn apply<I, J>(self, left: I, right: J) -> JoinIter<I>
+    where
+        I: Iterator<Item = Record>,
+        J: IntoIterator<Item = Record>,
+    {
+        let right: Vec<Record> = right.into_iter().collect();
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, record) in right.iter().enumerate() {
+            index
+                .entry(key_of(&self.right_keys, record))
+                .or_default()
+                .push(i);
+        }
+        JoinIter {
+            left,
+            matched: vec![false; right.len()],
+            right,
+            index,
+            join: self,
+            pending: VecDeque::new(),
+            left_done: false,
+            right_pos: 0,
+        }
+    }
+
+    /// Builds an output record from a pair or an unpaired record.
+    fn combine(&self, left: Option<&Record>, right: Option<&Record>) -> Record {
+        if self.layout.is_empty() {
+            return left.or(right).cloned().unwrap_or_default();
+        }
+        let width: usize = self.layout.iter().map(|f| f.len()).sum();
+        let lrecl = left.or(right).map_or(width, |r| r.lrecl().max(width));
+        let mut output = Record::blank(lrecl);
+        let mut col = 0;
+        for field in &self.layout {
+            let value = match (*field, left, right) {
+                (JoinField::Left(start, len), Some(r), _)
+                | (JoinField::Right(start, len), _, Some(r)) => r.field(start, len),
+                (JoinField::Indicator, Some(_), Some(_)) => "B",
+                (JoinField::Indicator, Some(_), None) => "1",
+                (JoinField::Indicator, None, _) => "2",
+                _ => "",
+            };
+            output.set_field(col, field.len(), value);
+            col += field.len();
+        }
+        output
+    }
+}
+
+/// Iterator over joined records, from [`Join::apply`].
+pub struct JoinIter<I> {
+    left: I,
+    right: Vec<Record>,
+    index: HashMap<String, Vec<usize>>,
+    matched: Vec<bool>,
+    join: Join,
+    pending: VecDeque<Record>,
+    left_done: bool,
+    right_pos: usize,
+}
+
+impl<I: Iterator<Item = Record>> Iterator for JoinIter<I> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        loop {
+            if let Some(record) = self.pending.pop_front() {
+                return Some(record);
+            }
+            if self.left_done {
+                break;
+            }
+            let Some(left) = self.left.next() else {
+                self.left_done = true;
+                break;
+            };
+            let key = key_of(&self.join.left_keys, &left);
+            match self.index.get(&key) {
+                Some(matches) => {
+                    for &i in matches {
+                        self.matched[i] = true;
+                        if self.join.kind.paired() {
+                            let pair = self.join.combine(Some(&left), Some(&self.right[i]));
+                            self.pending.push_back(pair);
+                        }
+                    }
+                }
+                None if self.join.kind.unpaired_left() => {
+                    self.pending.push_back(self.join.combine(Some(&left), None));
+                }
+                None => {}
+            }
+        }
+
+        if !self.join.kind.unpaired_right() {
+            return None;
+        }
+        while self.right_pos < self.right.len() {
+            let i = self.right_pos;
+            self.right_pos += 1;
+            if !self.matched[i] {
+                return Some(self.join.combine(None, Some(&self.right[i])));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn employees() -> Vec<Record> {
+        ["SMITH   D01", "JONES   D02", "DOE     D01", "WILSON  D09"]
+            .iter()
+            .map(|s| Record::from_str(s))
+            .collect()
+    }
+
+    fn departments() -> Vec<Record> {
+        ["D01 SALES", "D02 ENGINEER", "D03 MARKETING", "D02 RESEARCH"]
+            .iter()
+            .map(|s| Record::from_str(s))
+            .collect()
+    }
+
+    fn run(join: Join) -> Vec<String> {
+        join.layout(vec![
+            JoinField::Indicator,
+            JoinField::Left(0, 8),
+            JoinField::Right(4, 10),
+        ])
+        .apply(employees().into_iter(), departments())
+        .map(|r| r.as_str().trim_end().to_string())
+        .collect()
+    }
+
+    #[test]
+    fn test_inner_join() {
+        let joined = run(Join::new(vec![(8, 3)], vec![(0, 3)]));
+        assert_eq!(
+            joined,
+            [
+                "BSMITH   SALES",
+                "BJONES   ENGINEER",
+                "BJONES   RESEARCH",
+                "BDOE     SALES"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_left_outer_join() {
+        let joined = run(Join::new(vec![(8, 3)], vec![(0, 3)]).left_outer());
+        assert_eq!(joined.len(), 5);
+        assert_eq!(joined[4], "1WILSON");
+    }
+
+    #[test]
+    fn test_unpaired() {
+        let join = Join::new(vec![(8, 3)], vec![(0, 3)]);
+        assert_eq!(
+            run(join.clone().kind(JoinKind::Unpaired)),
+            ["1WILSON", "2        MARKETING"]
+        );
+        assert_eq!(
+            run(join.clone().kind(JoinKind::RightUnpaired)),
+            ["2        MARKETING"]
+        );
+        assert_eq!(run(join.clone().kind(JoinKind::LeftUnpaired)), ["1WILSON"]);
+        let full = run(join.kind(JoinKind::FullOuter));
+        assert_eq!(full.len(), 6);
+        assert_eq!(full[5], "2        MARKETING");
+    }
+
+    #[test]
+    fn test_join_without_layout() {
+        let joined: Vec<_> = Join::new(vec![(8, 3)], vec![(0, 3)])
+            .kind(JoinKind::FullOuter)
+            .apply(employees().into_iter(), departments())
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect();
+        assert_eq!(joined[0], "SMITH   D01");
+        assert_eq!(joined.last().unwrap(), "D03 MARKETING");
+    }
+
+    #[test]
+    fn test_wide_layout_grows_record() {
+        let joined: Vec<_> = Join::new(vec![(8, 3)], vec![(0, 3)])
+            .layout(vec![JoinField::Left(0, 80), JoinField::Right(0, 80)])
+            .apply(employees().into_iter(), departments())
+            .collect();
+        assert_eq!(joined[0].lrecl(), 160);
+        assert_eq!(joined[0].field(80, 9), "D01 SALES");
+    }
+}
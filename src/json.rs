@@ -0,0 +1,360 @@
+//! JSON conversion (requires the `serde` feature).
+//!
+//! A [`Record`] serializes as its text, all `lrecl` columns with their
+//! trailing blanks, and deserializes strictly from that text (see
+//! [`Record::try_from_str`]), so records can travel in any serde format.
+//!
+//! [`Record::to_json`] goes further and maps each field of a
+//! [`RecordLayout`] to a key of a JSON object, for tools that know nothing
+//! of column positions; [`Record::from_json`] builds the record back:
+//!
+//! | Field type | JSON value |
+//! |------------|------------|
+//! | `CHAR`, `DATE` | string, trailing blanks removed |
+//! | `NUMERIC` | number (`00050000` is `50000`); `null` if blank; string if not a number |
+//!
+//! Object keys come out in name order.
+//!
+//! [`JsonlSource`] and [`JsonlSink`] apply the same mapping to JSON Lines,
+//! one object per line, to bridge fixed-width data and log pipelines.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Record, RecordLayout};
+//! use serde_json::json;
+//!
+//! let layout: RecordLayout = "LAYOUT LASTNAME 0,8; DEPT 18,10; SALARY 28,8 NUMERIC"
+//!     .parse()
+//!     .unwrap();
+//! let record = Record::from_str("SMITH   JOHN      SALES     00050000");
+//!
+//! let value = record.to_json(&layout);
+//! assert_eq!(value, json!({"DEPT": "SALES", "LASTNAME": "SMITH", "SALARY": 50000}));
+//!
+//! let back = Record::from_json(&value, &layout).unwrap();
+//! assert_eq!(back.field(28, 8), "00050000");
+//! ```
+
+use std::io::{self, Write};
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_json::{Map, Number, Value};
+
+use crate::error::{PipelineError, Result};
+use crate::layout::{FieldType, LayoutField, RecordLayout};
+use crate::record::{MAX_LRECL, RECORD_WIDTH, Record};
+use crate::sink::Sink;
+
+impl Serialize for Record {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    /// Reads a record from its text; text longer than 80 columns sets the
+    /// record length, up to [`MAX_LRECL`].
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let lrecl = text.len().clamp(RECORD_WIDTH, MAX_LRECL);
+        Record::try_from_str_lrecl(&text, lrecl).map_err(de::Error::custom)
+    }
+}
+
+/// Returns the JSON value of a field's text.
+fn field_value(field: &LayoutField, text: &str) -> Value {
+    let text = text.trim_end();
+    if field.field_type != FieldType::Numeric {
+        return Value::String(text.to_string());
+    }
+    let number = text.trim_start();
+    if number.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(n) = number.parse::<i64>() {
+        return Value::from(n);
+    }
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map_or_else(|| Value::String(text.to_string()), Value::Number)
+}
+
+impl Record {
+    /// Returns the record as a JSON object with one key per layout field.
+    #[must_use]
+    pub fn to_json(&self, layout: &RecordLayout) -> Value {
+        let object: Map<String, Value> = layout
+            .fields()
+            .iter()
+            .map(|f| {
+                let text = self.field(f.start, f.len);
+                (f.name.clone(), field_value(f, text))
+            })
+            .collect();
+        Value::Object(object)
+    }
+
+    /// Builds a `layout.lrecl()`-byte record from a JSON object keyed by
+    /// layout field names.
+    ///
+    /// Strings are written as they are and numbers zero-padded, as by
+    /// [`Record::set_field_num`]; `null` and missing keys leave the field
+    /// blank.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::Json`] if the value is not an object or
+    /// has a key the layout does not define, or a field's value is not a
+    /// string, number, or `null`, and with [`PipelineError::ValueTooWide`]
+    /// or [`PipelineError::NonAscii`] if a value does not fit its field.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Record, RecordLayout};
+    /// use serde_json::json;
+    ///
+    /// let layout: RecordLayout = "LAYOUT NAME 0,8; QTY 8,4 NUMERIC".parse().unwrap();
+    /// let record = Record::from_json(&json!({"NAME": "BOLT", "QTY": 12}), &layout).unwrap();
+    /// assert_eq!(record.field(0, 12), "BOLT    0012");
+    /// ```
+    pub fn from_json(value: &Value, layout: &RecordLayout) -> Result<Record> {
+        let object = value
+            .as_object()
+            .ok_or_else(|| PipelineError::Json(format!("expected an object, got {value}")))?;
+        let mut record = Record::blank(layout.lrecl());
+        for (name, value) in object {
+            let field = layout
+                .get(name)
+                .ok_or_else(|| PipelineError::Json(format!("no field {name} in the layout")))?;
+            match value {
+                Value::Null => {}
+                Value::Number(n) => record.set_field_num(field.start, field.len, n)?,
+                Value::String(s) => {
+                    if s.len() > field.len {
+                        return Err(PipelineError::ValueTooWide {
+                            value: s.clone(),
+                            length: field.len,
+                        });
+                    }
+                    let text = Record::try_from_str_lrecl(s, field.len)?;
+                    record.patch(field.start, &text, 0, field.len);
+                }
+                other => {
+                    return Err(PipelineError::Json(format!(
+                        "field {name} must be a string, number, or null, got {other}"
+                    )));
+                }
+            }
+        }
+        Ok(record)
+    }
+}
+
+/// Reads JSON Lines as records of a layout, with [`Record::from_json`].
+///
+/// Empty lines are skipped. Each item is a record or the error for its
+/// line; reading continues after an error.
+pub struct JsonlSource<I> {
+    lines: I,
+    layout: RecordLayout,
+    line: usize,
+}
+
+impl<I, S> JsonlSource<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    /// Creates a source reading objects keyed by the fields of `layout`.
+    pub fn new(lines: I, layout: &RecordLayout) -> Self {
+        Self {
+            lines,
+            layout: layout.clone(),
+            line: 0,
+        }
+    }
+}
+
+impl<I, S> Iterator for JsonlSource<I>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let line = line.as_ref().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let at_line = |e: PipelineError| {
+                let e = e.to_string();
+                PipelineError::Json(format!(
+                    "line {}: {}",
+                    self.line,
+                    e.trim_start_matches("JSON error: ")
+                ))
+            };
+            let record = serde_json::from_str::<Value>(line)
+                .map_err(|e| PipelineError::Json(e.to_string()))
+                .and_then(|value| Record::from_json(&value, &self.layout))
+                .map_err(at_line);
+            return Some(record);
+        }
+    }
+}
+
+/// Writes records to an `io::Write` as JSON Lines, one object per record
+/// from [`Record::to_json`].
+///
+/// Like [`WriteSink`](crate::WriteSink), writing stops at the first error,
+/// which [`JsonlSink::finish`] returns.
+pub struct JsonlSink<W: Write> {
+    inner: W,
+    layout: RecordLayout,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonlSink<W> {
+    /// Creates a sink writing the fields of `layout` to `inner`.
+    pub fn new(inner: W, layout: &RecordLayout) -> Self {
+        Self {
+            inner,
+            layout: layout.clone(),
+            error: None,
+        }
+    }
+
+    /// Flushes the writer and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from writing or flushing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Sink for JsonlSink<W> {
+    fn accept(&mut self, record: &Record) {
+        if self.error.is_none()
+            && let Err(e) = writeln!(self.inner, "{}", record.to_json(&self.layout))
+        {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn layout() -> RecordLayout {
+        "LAYOUT NAME 0,8; HIRED 8,8 DATE; PAY 16,8 NUMERIC"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let record = Record::from_str("SMITH   19870301");
+        let text = serde_json::to_string(&record).unwrap();
+        assert_eq!(text.len(), RECORD_WIDTH + 2);
+        let back: Record = serde_json::from_str(&text).unwrap();
+        assert_eq!(back, record);
+
+        let long: Record = serde_json::from_value(json!("X".repeat(100))).unwrap();
+        assert_eq!(long.lrecl(), 100);
+        assert!(serde_json::from_value::<Record>(json!("caf\u{e9}")).is_err());
+        assert!(serde_json::from_value::<Record>(json!("X".repeat(MAX_LRECL + 1))).is_err());
+    }
+
+    #[test]
+    fn test_to_json_values() {
+        let layout = layout();
+        let read = |text: &str| Record::from_str(text).to_json(&layout);
+        assert_eq!(
+            read("SMITH   19870301-0000150"),
+            json!({"NAME": "SMITH", "HIRED": "19870301", "PAY": -150})
+        );
+        assert_eq!(read("")["PAY"], Value::Null);
+        assert_eq!(read("                 1234.50")["PAY"], json!(1234.5));
+        assert_eq!(read("                12X4")["PAY"], json!("12X4"));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let layout = layout();
+        let record = Record::from_json(&json!({"PAY": -150, "NAME": "SMITH"}), &layout).unwrap();
+        assert_eq!(record.field(0, 24), "SMITH           -0000150");
+        assert_eq!(record.to_json(&layout)["PAY"], json!(-150));
+
+        let err = |value: Value| Record::from_json(&value, &layout).unwrap_err();
+        assert!(matches!(err(json!([1])), PipelineError::Json(_)));
+        assert_eq!(
+            err(json!({"DEPT": "SALES"})).to_string(),
+            "JSON error: no field DEPT in the layout"
+        );
+        assert!(matches!(err(json!({"NAME": true})), PipelineError::Json(_)));
+        assert!(matches!(
+            err(json!({"NAME": "WASHINGTON"})),
+            PipelineError::ValueTooWide { .. }
+        ));
+        assert!(matches!(
+            err(json!({"PAY": 123456789})),
+            PipelineError::ValueTooWide { .. }
+        ));
+        assert!(matches!(
+            err(json!({"NAME": "CAF\u{c9}"})),
+            PipelineError::NonAscii { .. }
+        ));
+    }
+
+    #[test]
+    fn test_jsonl_source_and_sink() {
+        let layout = layout();
+        let lines = [
+            r#"{"NAME": "SMITH", "PAY": 150}"#,
+            "",
+            r#"{"NAME": "JONES", "DEPT": "SALES"}"#,
+            "not json",
+        ];
+        let mut source = JsonlSource::new(lines.iter(), &layout);
+        let record = source.next().unwrap().unwrap();
+        assert_eq!(record.field(0, 24), "SMITH           00000150");
+        let err = source.next().unwrap().unwrap_err().to_string();
+        assert_eq!(err, "JSON error: line 3: no field DEPT in the layout");
+        assert!(
+            source
+                .next()
+                .unwrap()
+                .unwrap_err()
+                .to_string()
+                .contains("line 4:")
+        );
+        assert!(source.next().is_none());
+
+        let mut sink = JsonlSink::new(Vec::new(), &layout);
+        sink.accept(&record);
+        sink.accept(&Record::from_str("DOE"));
+        let text = String::from_utf8(sink.finish().unwrap()).unwrap();
+        assert_eq!(
+            text,
+            "{\"HIRED\":\"\",\"NAME\":\"SMITH\",\"PAY\":150}\n\
+             {\"HIRED\":\"\",\"NAME\":\"DOE\",\"PAY\":null}\n"
+        );
+    }
+}
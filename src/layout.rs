@@ -0,0 +1,349 @@
+//! Named field layouts for fixed-width records.
+//!
+//! A [`RecordLayout`] maps field names to column ranges (and a coarse data
+//! type), so code can refer to `DEPT` instead of `18,10`. Layouts render as
+//! a DSL `LAYOUT` block:
+//!
+//! ```text
+//! LAYOUT LASTNAME 0,8; DEPT 18,10; SALARY 28,8 NUMERIC
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{FieldType, Record, RecordLayout};
+//!
+//! let mut layout = RecordLayout::new();
+//! layout.add("LASTNAME", 0, 8, FieldType::Char).unwrap();
+//! layout.add("DEPT", 18, 10, FieldType::Char).unwrap();
+//!
+//! let record = Record::from_str("SMITH   JOHN      SALES     ");
+//! assert_eq!(layout.value(&record, "DEPT").map(str::trim), Some("SALES"));
+//! assert_eq!(layout.to_string(), "LAYOUT LASTNAME 0,8; DEPT 18,10");
+//!
+//! let parsed: RecordLayout = "LAYOUT LASTNAME 0,8; DEPT 18,10".parse().unwrap();
+//! assert_eq!(parsed, layout);
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{PipelineError, Result};
+use crate::record::{RECORD_WIDTH, Record};
+
+/// Coarse data type of a layout field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldType {
+    /// Free-form character data.
+    #[default]
+    Char,
+    /// Unsigned or signed decimal digits, optionally with a decimal point.
+    Numeric,
+    /// A calendar date (`YYYYMMDD`, `YYYY-MM-DD`, or `MM/DD/YYYY`).
+    Date,
+}
+
+impl FieldType {
+    /// Returns the keyword used in a `LAYOUT` block.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Char => "CHAR",
+            FieldType::Numeric => "NUMERIC",
+            FieldType::Date => "DATE",
+        }
+    }
+
+    /// Parses a `LAYOUT` type keyword (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "CHAR" => Some(FieldType::Char),
+            "NUMERIC" => Some(FieldType::Numeric),
+            "DATE" => Some(FieldType::Date),
+            _ => None,
+        }
+    }
+}
+
+/// A named column range within a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutField {
+    /// Field name (e.g. `DEPT`).
+    pub name: String,
+    /// Starting position (0-indexed).
+    pub start: usize,
+    /// Length in bytes.
+    pub len: usize,
+    /// Data type.
+    pub field_type: FieldType,
+}
+
+/// An ordered set of named fields describing a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordLayout {
+    fields: Vec<LayoutField>,
+    lrecl: usize,
+}
+
+impl RecordLayout {
+    /// Creates an empty layout for 80-byte records.
+    pub fn new() -> Self {
+        Self::with_lrecl(RECORD_WIDTH)
+    }
+
+    /// Creates an empty layout for `lrecl`-byte records.
+    pub fn with_lrecl(lrecl: usize) -> Self {
+        Self {
+            fields: Vec::new(),
+            lrecl,
+        }
+    }
+
+    /// Returns the record length the fields must fit in.
+    pub fn lrecl(&self) -> usize {
+        self.lrecl
+    }
+
+    /// Adds a field.
+    ///
+    /// Fails if the name is empty or already defined, or if the range is
+    /// empty or extends past the record length.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        start: usize,
+        len: usize,
+        field_type: FieldType,
+    ) -> Result<()> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(PipelineError::InvalidLayout("empty field name".into()));
+        }
+        if self.get(&name).is_some() {
+            return Err(PipelineError::InvalidLayout(format!(
+                "field {name} is defined more than once"
+            )));
+        }
+        if len == 0 || start + len > self.lrecl {
+            return Err(PipelineError::InvalidLayout(format!(
+                "field {name} ({start},{len}) is outside the {}-byte record",
+                self.lrecl
+            )));
+        }
+        self.fields.push(LayoutField {
+            name,
+            start,
+            len,
+            field_type,
+        });
+        Ok(())
+    }
+
+    /// Returns the fields in declaration order.
+    pub fn fields(&self) -> &[LayoutField] {
+        &self.fields
+    }
+
+    /// Looks up a field by name.
+    pub fn get(&self, name: &str) -> Option<&LayoutField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Extracts a named field from a record.
+    pub fn value<'a>(&self, record: &'a Record, name: &str) -> Option<&'a str> {
+        self.get(name).map(|f| record.field(f.start, f.len))
+    }
+
+    /// Returns true if no fields are defined.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Fails if any two fields share a column.
+    ///
+    /// [`add`](Self::add) allows overlapping fields, like a COBOL
+    /// `REDEFINES`; a parsed `LAYOUT` block does not.
+    pub fn check_overlaps(&self) -> Result<()> {
+        for (idx, a) in self.fields.iter().enumerate() {
+            for b in &self.fields[idx + 1..] {
+                if a.start < b.start + b.len && b.start < a.start + a.len {
+                    return Err(PipelineError::InvalidLayout(format!(
+                        "fields {} ({},{}) and {} ({},{}) overlap",
+                        a.name, a.start, a.len, b.name, b.start, b.len
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RecordLayout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if `name` can name a layout field: a letter followed by
+/// letters, digits, `_`, or `-`.
+pub fn is_valid_field_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+impl FromStr for RecordLayout {
+    type Err = PipelineError;
+
+    /// Parses a `LAYOUT` block for 80-byte records, the inverse of
+    /// `Display`. Fields may not overlap.
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse_lrecl(text, RECORD_WIDTH)
+    }
+}
+
+impl RecordLayout {
+    /// Parses a `LAYOUT` block for `lrecl`-byte records.
+    pub fn parse_lrecl(text: &str, lrecl: usize) -> Result<Self> {
+        let text = text.trim();
+        let rest = text
+            .get(..6)
+            .filter(|keyword| keyword.eq_ignore_ascii_case("LAYOUT"))
+            .map(|_| &text[6..])
+            .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            .ok_or_else(|| PipelineError::InvalidLayout("expected LAYOUT".into()))?;
+
+        let mut layout = RecordLayout::with_lrecl(lrecl);
+        for spec in rest.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            let invalid =
+                || PipelineError::InvalidLayout(format!("'{spec}' should be NAME pos,len [type]"));
+            let mut words = spec.split_whitespace();
+            let name = words.next().ok_or_else(invalid)?;
+            if !is_valid_field_name(name) {
+                return Err(PipelineError::InvalidLayout(format!(
+                    "invalid field name '{name}'"
+                )));
+            }
+            let (start, len) = words
+                .next()
+                .and_then(|r| r.split_once(','))
+                .ok_or_else(invalid)?;
+            let start: usize = start.parse().map_err(|_| invalid())?;
+            let len: usize = len.parse().map_err(|_| invalid())?;
+            let field_type = match words.next() {
+                None => FieldType::Char,
+                Some(word) => FieldType::from_name(word).ok_or_else(|| {
+                    PipelineError::InvalidLayout(format!(
+                        "field {name} has unknown type '{word}' (expected CHAR, NUMERIC, or DATE)"
+                    ))
+                })?,
+            };
+            if words.next().is_some() {
+                return Err(invalid());
+            }
+            layout.add(name, start, len, field_type)?;
+        }
+
+        if layout.is_empty() {
+            return Err(PipelineError::InvalidLayout(
+                "LAYOUT requires at least one field".into(),
+            ));
+        }
+        layout.check_overlaps()?;
+        Ok(layout)
+    }
+}
+
+impl fmt::Display for RecordLayout {
+    /// Renders the layout as a single-line `LAYOUT` block. `CHAR` is the
+    /// default type and is omitted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LAYOUT")?;
+        for (idx, field) in self.fields.iter().enumerate() {
+            let sep = if idx == 0 { " " } else { "; " };
+            write!(f, "{sep}{} {},{}", field.name, field.start, field.len)?;
+            if field.field_type != FieldType::Char {
+                write!(f, " {}", field.field_type.name())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_lookup() {
+        let mut layout = RecordLayout::new();
+        layout.add("LAST", 0, 8, FieldType::Char).unwrap();
+        layout.add("SALARY", 28, 8, FieldType::Numeric).unwrap();
+
+        let record = Record::from_str("SMITH   JOHN      SALES     00050000");
+        assert_eq!(layout.value(&record, "LAST"), Some("SMITH   "));
+        assert_eq!(layout.value(&record, "SALARY"), Some("00050000"));
+        assert_eq!(layout.value(&record, "MISSING"), None);
+        assert_eq!(layout.fields().len(), 2);
+    }
+
+    #[test]
+    fn test_add_rejects_invalid_fields() {
+        let mut layout = RecordLayout::new();
+        layout.add("A", 0, 8, FieldType::Char).unwrap();
+        assert!(layout.add("A", 8, 8, FieldType::Char).is_err());
+        assert!(layout.add("B", 75, 10, FieldType::Char).is_err());
+        assert!(layout.add("C", 10, 0, FieldType::Char).is_err());
+        assert!(layout.add("", 10, 1, FieldType::Char).is_err());
+    }
+
+    #[test]
+    fn test_display_layout_block() {
+        let mut layout = RecordLayout::new();
+        layout.add("LAST", 0, 8, FieldType::Char).unwrap();
+        layout.add("SALARY", 28, 8, FieldType::Numeric).unwrap();
+        layout.add("HIRED", 36, 8, FieldType::Date).unwrap();
+        assert_eq!(
+            layout.to_string(),
+            "LAYOUT LAST 0,8; SALARY 28,8 NUMERIC; HIRED 36,8 DATE"
+        );
+    }
+
+    #[test]
+    fn test_parse_layout_block() {
+        let text = "LAYOUT LAST 0,8; SALARY 28,8 NUMERIC; HIRED 36,8 date";
+        let layout: RecordLayout = text.parse().unwrap();
+        assert_eq!(layout.fields().len(), 3);
+        assert_eq!(layout.get("HIRED").unwrap().field_type, FieldType::Date);
+        assert_eq!(
+            layout.to_string(),
+            "LAYOUT LAST 0,8; SALARY 28,8 NUMERIC; HIRED 36,8 DATE"
+        );
+    }
+
+    #[test]
+    fn test_parse_layout_errors() {
+        let err = |text: &str| text.parse::<RecordLayout>().unwrap_err().to_string();
+        assert_eq!(
+            err("LAYOUT DEPT 18,10; CODE 20,4"),
+            "invalid layout: fields DEPT (18,10) and CODE (20,4) overlap"
+        );
+        assert_eq!(
+            err("LAYOUT 1ST 0,8"),
+            "invalid layout: invalid field name '1ST'"
+        );
+        assert!(err("LAYOUT DEPT 18").contains("'DEPT 18' should be NAME pos,len"));
+        assert!(err("LAYOUT DEPT 18,10 MONEY").contains("field DEPT has unknown type 'MONEY'"));
+        assert!(err("LAYOUT DEPT 75,10").contains("field DEPT (75,10) is outside"));
+        assert!(err("LAYOUT").contains("at least one field"));
+        assert!(err("LAYOUTX A 0,1").contains("expected LAYOUT"));
+    }
+
+    #[test]
+    fn test_layout_lrecl() {
+        let layout = RecordLayout::parse_lrecl("LAYOUT AMOUNT 120,12", 132).unwrap();
+        assert_eq!(layout.lrecl(), 132);
+        assert_eq!(layout.get("AMOUNT").map(|f| f.start), Some(120));
+        let err = "LAYOUT AMOUNT 120,12".parse::<RecordLayout>().unwrap_err();
+        assert!(err.to_string().contains("outside the 80-byte record"));
+    }
+}
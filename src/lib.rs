@@ -33,16 +33,102 @@
 //! assert_eq!(result.len(), 2);
 //! ```
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod cli;
+pub mod cms;
+pub mod condcode;
+pub mod condition;
+pub mod crossfoot;
+pub mod crypto;
+pub mod csv;
+pub mod dates;
+pub mod decimal;
+pub mod dfsort;
+pub mod diagnostic;
+pub mod dot;
 pub mod dsl;
+pub mod ebcdic;
 pub mod error;
+pub mod explain;
+pub mod format;
+pub mod golden;
+pub mod group;
+pub mod hostvars;
+pub mod infer;
+pub mod infiles;
+pub mod instrument;
+pub mod io;
+pub mod job;
+pub mod join;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod layout;
+pub mod lookup;
+pub mod metrics;
+pub mod outfiles;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod pipeline;
+pub mod plan;
+pub mod plugin;
+pub mod profile;
 pub mod record;
+pub mod reject;
+pub mod report;
+pub mod sink;
+pub mod specs;
+pub mod split;
 pub mod stage;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod testing;
+pub mod toolkit;
+pub mod trailer;
+pub mod xlate;
 
+pub use condcode::{ConditionCode, ConditionCodes};
+#[cfg(feature = "regex")]
+pub use condition::Pattern;
+pub use condition::{Condition, NumberFormat};
+pub use dates::{Date, DateFormat, ReformatDate};
+pub use decimal::Decimal;
 pub use dsl::{
-    Command, DebugCallbacks, DebugInfo, execute_pipeline, execute_pipeline_debug, parse_commands,
+    Command, CompareOp, DebugCallbacks, DebugInfo, PipelineRun, check_runtime, execute_pipeline,
+    execute_pipeline_debug, execute_pipeline_debug_with_vars, execute_pipeline_run,
+    execute_pipeline_with_vars, execute_plan, execute_plan_streaming, execute_plan_streaming_to,
+    execute_plan_with_inputs, input_file, parse_commands, parse_condition, pipeline_layout,
+    pipeline_lrecl,
 };
 pub use error::PipelineError;
-pub use pipeline::{Pipeline, from_lines, from_strings};
-pub use record::{RECORD_WIDTH, Record};
-pub use stage::{Filter, Inspect, Map, Reformat, Select, Stage};
+pub use group::{Aggregate, AggregateKind, GroupBy, Total};
+pub use hostvars::{HostVars, StampValue};
+pub use infiles::{InputFiles, SideReader};
+pub use join::{Join, JoinField, JoinKind};
+pub use layout::{FieldType, LayoutField, RecordLayout};
+pub use lookup::{Lookup, Unmatched};
+pub use metrics::{JobMetrics, MetricsRegistry, PipelineMetrics, StageMetrics};
+pub use outfiles::{OutputFiles, SideWriter};
+#[cfg(feature = "parallel")]
+pub use parallel::ParPipeline;
+pub use pipeline::{Grouped, Pipeline, TryPipeline, from_lines, from_lines_strict, from_strings};
+pub use plan::{PipelinePlan, Segment};
+pub use profile::ProfileStage;
+pub use record::{ColumnDiff, MAX_LRECL, POOL_LIMIT, RECORD_WIDTH, Record, RecordPool, RecordView};
+#[cfg(feature = "regex")]
+pub use regex::Regex;
+pub use reject::{ErrorPolicy, Reason, Rejects};
+pub use report::ControlBreak;
+pub use sink::{Sink, WriteSink};
+pub use specs::{Conversion, Placement, SpecInput, SpecItem, Specs};
+pub use split::{OutFile, Split, SplitOutputs};
+pub use stage::{
+    Between, Change, Console, Dedup, DedupKeep, DropLast, Dump, DumpFormat, Duplicate, Explode,
+    Filter, Inspect, KeyFormat, Literal, Locate, Map, Overlay, Pad, Recno, RecnoRange, Reformat,
+    RunningTotal, SORT_MEMORY, Sample, Select, Seqnum, Sort, SortKey, SortOrder, SortedRecords,
+    Squish, Stage, SumBy, SumField, SumFormat, TakeLast, ThrottleStage, Trunc,
+};
+#[cfg(feature = "async")]
+pub use stream::{AsyncStage, RecordStream};
+pub use trailer::Trailer;
+pub use xlate::{Xlate, XlateTable};
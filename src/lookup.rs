@@ -0,0 +1,246 @@
+//! Table lookup (translate) stage.
+//!
+//! A [`Lookup`] replaces a field of each record with a value from a
+//! reference table, such as a department code with the department name
+//! (like DFSORT `FIND`/`CHANGE` tables or a COBOL table `SEARCH`). The table
+//! is built in code or loaded from reference records, e.g. a file of
+//! `code name` lines.
+//!
+//! Keys are compared without trailing blanks, so the 10-byte field
+//! `"D01       "` matches the table key `"D01"`. What happens to a record
+//! whose key is not in the table is set by [`Unmatched`].
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Lookup, Record, Stage};
+//!
+//! let mut lookup = Lookup::new(8, 3).to(8, 10);
+//! lookup.insert("D01", "SALES");
+//! lookup.insert("D02", "ENGINEER");
+//!
+//! let record = lookup.process(Record::from_str("SMITH   D01")).unwrap();
+//! assert_eq!(record.as_str().trim_end(), "SMITH   SALES");
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::stage::Stage;
+
+/// What a [`Lookup`] does with a record whose key is not in the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Unmatched {
+    /// Pass the record on unchanged.
+    #[default]
+    PassThrough,
+    /// Drop the record.
+    Drop,
+    /// Fail; see [`Lookup::translate`].
+    Error,
+}
+
+/// Lookup stage - rewrites a field from a key → value table.
+///
+/// As a [`Stage`], a lookup with [`Unmatched::Error`] stops at the first
+/// unmatched key: that record and all later ones are dropped, and the
+/// error is kept for [`Lookup::error`]. `Pipeline::lookup` returns it.
+#[derive(Debug)]
+pub struct Lookup {
+    key: (usize, usize),
+    target: (usize, usize),
+    table: HashMap<String, String>,
+    unmatched: Unmatched,
+    error: Option<PipelineError>,
+}
+
+impl Lookup {
+    /// Creates a lookup keyed on the field at `start, len`, writing the
+    /// value back into the same field.
+    pub fn new(start: usize, len: usize) -> Self {
+        Self {
+            key: (start, len),
+            target: (start, len),
+            table: HashMap::new(),
+            unmatched: Unmatched::PassThrough,
+            error: None,
+        }
+    }
+
+    /// Writes the value to `start, len` instead of over the key field.
+    pub fn to(mut self, start: usize, len: usize) -> Self {
+        self.target = (start, len);
+        self
+    }
+
+    /// Sets what happens to records whose key is not in the table.
+    pub fn unmatched(mut self, unmatched: Unmatched) -> Self {
+        self.unmatched = unmatched;
+        self
+    }
+
+    /// Adds a table entry, replacing any earlier value for `key`.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.table
+            .insert(key.trim_end().to_string(), value.trim_end().to_string());
+    }
+
+    /// Adds an entry for each reference record, taking the key and value
+    /// from the given `(start, len)` fields.
+    pub fn load_records<I>(mut self, records: I, key: (usize, usize), value: (usize, usize)) -> Self
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        for record in records {
+            self.insert(record.field(key.0, key.1), record.field(value.0, value.1));
+        }
+        self
+    }
+
+    /// Adds an entry for each line of a reference file, taking the key and
+    /// value from the given `(start, len)` columns.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file cannot be read.
+    pub fn load_file(
+        self,
+        path: impl AsRef<Path>,
+        key: (usize, usize),
+        value: (usize, usize),
+    ) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(self.load_records(text.lines().map(Record::from_str), key, value))
+    }
+
+    /// Returns the number of table entries.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns true if the table has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Translates one record: `Ok(Some)` with the value written, or with
+    /// the record unchanged if it is unmatched and passed through;
+    /// `Ok(None)` if it is unmatched and dropped.
+    ///
+    /// # Errors
+    ///
+    /// Fails for an unmatched key with [`Unmatched::Error`].
+    pub fn translate(&self, mut record: Record) -> Result<Option<Record>> {
+        let key = record.field(self.key.0, self.key.1).trim_end();
+        match self.table.get(key) {
+            Some(value) => {
+                let value = value.clone();
+                record.set_field(self.target.0, self.target.1, &value);
+                Ok(Some(record))
+            }
+            None => match self.unmatched {
+                Unmatched::PassThrough => Ok(Some(record)),
+                Unmatched::Drop => Ok(None),
+                Unmatched::Error => Err(PipelineError::Stage(format!(
+                    "no lookup value for key '{key}'"
+                ))),
+            },
+        }
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.error.as_ref()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.error.take()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if self.error.is_some() {
+            return None;
+        }
+        self.translate(record).unwrap_or_else(|e| {
+            self.error = Some(e);
+            None
+        })
+    }
+}
+
+impl Stage for Lookup {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn departments() -> Lookup {
+        Lookup::new(18, 10).load_records(
+            [
+                Record::from_str("D01 SALES"),
+                Record::from_str("D02 ENGINEER"),
+            ],
+            (0, 3),
+            (4, 10),
+        )
+    }
+
+    fn employee(dept: &str) -> Record {
+        Record::from_str(&format!("SMITH   JOHN      {dept:<10}00050000"))
+    }
+
+    #[test]
+    fn test_lookup_rewrites_field() {
+        let mut lookup = departments();
+        assert_eq!(lookup.len(), 2);
+        let record = lookup.process(employee("D02")).unwrap();
+        assert_eq!(record.field(18, 18), "ENGINEER  00050000");
+    }
+
+    #[test]
+    fn test_unmatched_policies() {
+        let mut pass = departments();
+        assert_eq!(
+            pass.process(employee("D09")).unwrap().field(18, 10).trim(),
+            "D09"
+        );
+
+        let mut drop = departments().unmatched(Unmatched::Drop);
+        assert!(drop.process(employee("D09")).is_none());
+        assert!(drop.process(employee("D01")).is_some());
+
+        let mut error = departments().unmatched(Unmatched::Error);
+        assert!(error.process(employee("D01")).is_some());
+        assert!(error.process(employee("D09")).is_none());
+        assert!(error.process(employee("D01")).is_none());
+        let err = error.take_error().unwrap();
+        assert!(err.to_string().contains("'D09'"), "{err}");
+    }
+
+    #[test]
+    fn test_load_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("depts.txt");
+        std::fs::write(&path, "D01 SALES\nD02 ENGINEER\n").unwrap();
+
+        let lookup = Lookup::new(0, 3)
+            .to(4, 10)
+            .load_file(&path, (0, 3), (4, 10))
+            .unwrap();
+        let record = lookup.translate(Record::from_str("D01")).unwrap().unwrap();
+        assert_eq!(record.as_str().trim_end(), "D01 SALES");
+        assert!(
+            Lookup::new(0, 3)
+                .load_file(dir.path().join("missing"), (0, 3), (4, 10))
+                .is_err()
+        );
+    }
+}
@@ -4,7 +4,8 @@
 //! where data was processed as fixed-width 80-byte records (matching
 //! the width of punch cards).
 
-use pipelines_rs::{Pipeline, Record};
+use pipelines_rs::dates::{Date, DateFormat};
+use pipelines_rs::{Aggregate, Pipeline, Record, ReformatDate};
 
 fn main() {
     println!("=== Mainframe-Style Pipeline Processing Demo ===\n");
@@ -111,7 +112,7 @@ fn main() {
     println!("=== Pipeline 4: SUM SALARY ===");
     let total_salary: u64 =
         Pipeline::new(employee_records.clone().into_iter()).fold(0u64, |acc, r| {
-            let salary: u64 = r.field(36, 8).trim().parse().unwrap_or(0);
+            let salary: u64 = r.field_num(36, 8).expect("salary is numeric");
             acc + salary
         });
 
@@ -139,21 +140,46 @@ fn main() {
     }
     println!();
 
-    // Pipeline 6: Statistics
-    println!("=== Pipeline 6: DEPARTMENT STATISTICS ===");
-    let departments = ["SALES", "ENGINEER", "MARKETING"];
-
-    for dept in departments {
-        let (count, total): (usize, u64) = employee_records
-            .iter()
-            .filter(|r| r.field_eq(18, 10, dept))
-            .fold((0, 0), |(count, total), r| {
-                let salary: u64 = r.field(36, 8).trim().parse().unwrap_or(0);
-                (count + 1, total + salary)
-            });
-
-        let avg = if count > 0 { total / count as u64 } else { 0 };
-        println!("  {dept:<12} Count: {count}, Total: ${total}, Avg: ${avg}");
+    // Pipeline 6: Hire dates
+    println!("=== Pipeline 6: HIRED BEFORE 1990, DATES AS MMDDYY ===");
+    let cutoff = Date::new(1990, 1, 1).expect("a real date");
+    let veterans: Vec<_> = Pipeline::new(employee_records.clone().into_iter())
+        .filter(|r| {
+            r.field_date(44, 8, DateFormat::Yyyymmdd)
+                .is_ok_and(|hired| hired < cutoff)
+        })
+        .stage(ReformatDate::new(
+            44,
+            DateFormat::Yyyymmdd,
+            DateFormat::Mmddyy,
+        ))
+        .collect();
+
+    println!("Output ({} records):", veterans.len());
+    for record in &veterans {
+        println!(
+            "  {} {} - hired {}",
+            record.field(0, 8).trim(),
+            record.field(8, 10).trim(),
+            record.field(44, 6)
+        );
+    }
+    println!();
+
+    // Pipeline 7: Statistics
+    println!("=== Pipeline 7: DEPARTMENT STATISTICS ===");
+    let stats: Vec<_> = Pipeline::new(employee_records.into_iter())
+        .group_by(vec![(18, 10)])
+        .aggregate(vec![
+            Aggregate::count().at(11, 5),
+            Aggregate::sum(36, 8),
+            Aggregate::avg(36, 8),
+        ])
+        .collect();
+
+    println!("  DEPARTMENT COUNT TOTAL    AVG");
+    for record in &stats {
+        println!("  {}", record.as_str().trim_end());
     }
     println!();
 
@@ -0,0 +1,449 @@
+//! Job metrics in the Prometheus text exposition format.
+//!
+//! A [`JobMetrics`] describes one pipeline run: record counts, per-stage
+//! counts, duration, and whether it succeeded. A [`MetricsRegistry`]
+//! accumulates jobs into counters and renders them for scraping (by
+//! `pipe-serve`) or for the node_exporter textfile collector (by
+//! `pipe-run --metrics-file`). A [`PipelineMetrics`] holds the counts and
+//! wall time of each stage of a run, as printed by `pipe-run --stats`.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//! use pipelines_rs::metrics::{JobMetrics, MetricsRegistry, StageMetrics};
+//!
+//! let mut job = JobMetrics::new("filter-sales", "batch");
+//! job.records_in = 8;
+//! job.records_out = 3;
+//! job.duration = Duration::from_millis(2);
+//! job.stages.push(StageMetrics::new("FILTER", 8, 3));
+//!
+//! let mut registry = MetricsRegistry::new();
+//! registry.record(&job);
+//! let text = registry.render();
+//! assert!(text.contains("pipelines_records_in_total{pipeline=\"filter-sales\"} 8"));
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use crate::DebugInfo;
+
+/// Record counts and wall time for one stage of a job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageMetrics {
+    /// Stage name (e.g. `FILTER`).
+    pub name: String,
+    /// Records received by the stage.
+    pub records_in: usize,
+    /// Records emitted by the stage.
+    pub records_out: usize,
+    /// Wall-clock time the stage spent on its records (zero if not timed).
+    pub elapsed: Duration,
+}
+
+impl StageMetrics {
+    /// Creates stage metrics from a name and counts.
+    pub fn new(name: impl Into<String>, records_in: usize, records_out: usize) -> Self {
+        Self {
+            name: name.into(),
+            records_in,
+            records_out,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Sets the stage's wall time.
+    #[must_use]
+    pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed = elapsed;
+        self
+    }
+
+    /// Returns the records the stage removed: those received but not
+    /// emitted, or zero for a stage that emits more than it receives.
+    pub fn filtered(&self) -> usize {
+        self.records_in.saturating_sub(self.records_out)
+    }
+}
+
+/// Per-stage counts and wall time for one pipeline run, in stage order
+/// (the source stage first).
+///
+/// Its `Display` form is the table `pipe-run --stats` prints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipelineMetrics {
+    /// One entry per stage that ran.
+    pub stages: Vec<StageMetrics>,
+}
+
+impl PipelineMetrics {
+    /// Returns the wall time of all stages together.
+    pub fn elapsed(&self) -> Duration {
+        self.stages.iter().map(|s| s.elapsed).sum()
+    }
+
+    /// Returns the stage that took the longest, if any ran.
+    pub fn slowest(&self) -> Option<&StageMetrics> {
+        self.stages.iter().max_by_key(|s| s.elapsed)
+    }
+}
+
+impl fmt::Display for PipelineMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.elapsed().as_secs_f64();
+        writeln!(
+            f,
+            "{:>3}  {:<12} {:>10} {:>10} {:>10} {:>12} {:>6}",
+            "#", "Stage", "In", "Out", "Filtered", "Time (ms)", "Time%"
+        )?;
+        for (idx, s) in self.stages.iter().enumerate() {
+            let secs = s.elapsed.as_secs_f64();
+            let share = if total > 0.0 {
+                secs / total * 100.0
+            } else {
+                0.0
+            };
+            writeln!(
+                f,
+                "{idx:>3}  {:<12} {:>10} {:>10} {:>10} {:>12.3} {share:>5.1}%",
+                s.name,
+                s.records_in,
+                s.records_out,
+                s.filtered(),
+                secs * 1000.0
+            )?;
+        }
+        write!(f, "{:>3}  {:<12} {:>45.3}", "", "Total", total * 1000.0)
+    }
+}
+
+/// Metrics for a single pipeline run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobMetrics {
+    /// Pipeline name used as the `pipeline` label.
+    pub pipeline: String,
+    /// Executor used (`batch`, `rat`).
+    pub executor: String,
+    /// Records read by the source stage.
+    pub records_in: usize,
+    /// Records written by the last stage.
+    pub records_out: usize,
+    /// Wall-clock duration of the run.
+    pub duration: Duration,
+    /// Whether the run completed without error.
+    pub success: bool,
+    /// Per-stage counts, in pipeline order (may be empty).
+    pub stages: Vec<StageMetrics>,
+}
+
+impl JobMetrics {
+    /// Creates metrics for a successful, empty run.
+    pub fn new(pipeline: impl Into<String>, executor: impl Into<String>) -> Self {
+        Self {
+            pipeline: pipeline.into(),
+            executor: executor.into(),
+            records_in: 0,
+            records_out: 0,
+            duration: Duration::ZERO,
+            success: true,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Sets per-stage counts from the batch executor's debug info.
+    pub fn set_stages(&mut self, debug_info: &[DebugInfo]) {
+        self.stages = debug_info
+            .iter()
+            .map(|info| StageMetrics::new(&info.stage_name, info.input_count, info.output_count))
+            .collect();
+    }
+}
+
+/// Accumulated counters for one stage across jobs.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct StageTotals {
+    records_in: u64,
+    records_out: u64,
+}
+
+/// Accumulated counters for one pipeline across jobs.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PipelineTotals {
+    jobs_ok: u64,
+    jobs_failed: u64,
+    records_in: u64,
+    records_out: u64,
+    duration_seconds: f64,
+    /// Keyed by (stage index, stage name).
+    stages: BTreeMap<(usize, String), StageTotals>,
+}
+
+/// Accumulates job metrics and renders them in Prometheus text format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsRegistry {
+    pipelines: BTreeMap<String, PipelineTotals>,
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a finished job to the counters.
+    pub fn record(&mut self, job: &JobMetrics) {
+        let totals = self.pipelines.entry(job.pipeline.clone()).or_default();
+        if job.success {
+            totals.jobs_ok += 1;
+        } else {
+            totals.jobs_failed += 1;
+        }
+        totals.records_in += job.records_in as u64;
+        totals.records_out += job.records_out as u64;
+        totals.duration_seconds += job.duration.as_secs_f64();
+        for (idx, stage) in job.stages.iter().enumerate() {
+            let entry = totals.stages.entry((idx, stage.name.clone())).or_default();
+            entry.records_in += stage.records_in as u64;
+            entry.records_out += stage.records_out as u64;
+        }
+    }
+
+    /// Renders all counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "pipelines_jobs_total",
+            "counter",
+            "Pipeline runs by outcome.",
+        );
+        for (name, t) in &self.pipelines {
+            let pipe = escape(name);
+            let _ = writeln!(
+                out,
+                "pipelines_jobs_total{{pipeline=\"{pipe}\",status=\"ok\"}} {}",
+                t.jobs_ok
+            );
+            let _ = writeln!(
+                out,
+                "pipelines_jobs_total{{pipeline=\"{pipe}\",status=\"error\"}} {}",
+                t.jobs_failed
+            );
+        }
+
+        self.simple(
+            &mut out,
+            "pipelines_records_in_total",
+            "Records read by source stages.",
+            |t| t.records_in,
+        );
+        self.simple(
+            &mut out,
+            "pipelines_records_out_total",
+            "Records written by final stages.",
+            |t| t.records_out,
+        );
+        self.simple(
+            &mut out,
+            "pipelines_errors_total",
+            "Pipeline runs that failed.",
+            |t| t.jobs_failed,
+        );
+
+        header(
+            &mut out,
+            "pipelines_job_duration_seconds",
+            "summary",
+            "Wall-clock time of pipeline runs.",
+        );
+        for (name, t) in &self.pipelines {
+            let pipe = escape(name);
+            let _ = writeln!(
+                out,
+                "pipelines_job_duration_seconds_sum{{pipeline=\"{pipe}\"}} {}",
+                t.duration_seconds
+            );
+            let _ = writeln!(
+                out,
+                "pipelines_job_duration_seconds_count{{pipeline=\"{pipe}\"}} {}",
+                t.jobs_ok + t.jobs_failed
+            );
+        }
+
+        self.stage(
+            &mut out,
+            "pipelines_stage_records_in_total",
+            "Records received per stage.",
+            |s| s.records_in,
+        );
+        self.stage(
+            &mut out,
+            "pipelines_stage_records_out_total",
+            "Records emitted per stage.",
+            |s| s.records_out,
+        );
+
+        out
+    }
+
+    fn simple<F>(&self, out: &mut String, metric: &str, help: &str, value: F)
+    where
+        F: Fn(&PipelineTotals) -> u64,
+    {
+        header(out, metric, "counter", help);
+        for (name, t) in &self.pipelines {
+            let _ = writeln!(
+                out,
+                "{metric}{{pipeline=\"{}\"}} {}",
+                escape(name),
+                value(t)
+            );
+        }
+    }
+
+    fn stage<F>(&self, out: &mut String, metric: &str, help: &str, value: F)
+    where
+        F: Fn(&StageTotals) -> u64,
+    {
+        header(out, metric, "counter", help);
+        for (name, t) in &self.pipelines {
+            for ((idx, stage), s) in &t.stages {
+                let _ = writeln!(
+                    out,
+                    "{metric}{{pipeline=\"{}\",stage_index=\"{idx}\",stage=\"{}\"}} {}",
+                    escape(name),
+                    escape(stage),
+                    value(s)
+                );
+            }
+        }
+    }
+}
+
+/// Measures a stage's wall time; on `wasm32`, where std has no clock, it
+/// measures nothing and reports zero.
+pub(crate) struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.started.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        Duration::ZERO
+    }
+}
+
+fn header(out: &mut String, metric: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {metric} {help}");
+    let _ = writeln!(out, "# TYPE {metric} {kind}");
+}
+
+/// Escape a label value per the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(success: bool) -> JobMetrics {
+        let mut job = JobMetrics::new("sales", "batch");
+        job.records_in = 4;
+        job.records_out = 2;
+        job.duration = Duration::from_millis(500);
+        job.success = success;
+        job.stages = vec![
+            StageMetrics::new("CONSOLE", 0, 4),
+            StageMetrics::new("FILTER", 4, 2),
+        ];
+        job
+    }
+
+    #[test]
+    fn test_registry_accumulates() {
+        let mut registry = MetricsRegistry::new();
+        registry.record(&job(true));
+        registry.record(&job(false));
+        let text = registry.render();
+
+        assert!(text.contains("pipelines_jobs_total{pipeline=\"sales\",status=\"ok\"} 1"));
+        assert!(text.contains("pipelines_jobs_total{pipeline=\"sales\",status=\"error\"} 1"));
+        assert!(text.contains("pipelines_records_in_total{pipeline=\"sales\"} 8"));
+        assert!(text.contains("pipelines_errors_total{pipeline=\"sales\"} 1"));
+        assert!(text.contains("pipelines_job_duration_seconds_sum{pipeline=\"sales\"} 1"));
+        assert!(text.contains(
+            "pipelines_stage_records_out_total{pipeline=\"sales\",stage_index=\"1\",stage=\"FILTER\"} 4"
+        ));
+    }
+
+    #[test]
+    fn test_every_metric_has_help_and_type() {
+        let mut registry = MetricsRegistry::new();
+        registry.record(&job(true));
+        let text = registry.render();
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            let metric = line.split('{').next().unwrap();
+            let family = metric
+                .strip_suffix("_sum")
+                .or_else(|| metric.strip_suffix("_count"))
+                .unwrap_or(metric);
+            assert!(text.contains(&format!("# TYPE {family} ")), "{metric}");
+        }
+    }
+
+    #[test]
+    fn test_pipeline_metrics() {
+        let metrics = PipelineMetrics {
+            stages: vec![
+                StageMetrics::new("CONSOLE", 0, 4).with_elapsed(Duration::from_millis(1)),
+                StageMetrics::new("FILTER", 4, 1).with_elapsed(Duration::from_millis(3)),
+                StageMetrics::new("DUPLICATE", 1, 2),
+            ],
+        };
+        assert_eq!(metrics.elapsed(), Duration::from_millis(4));
+        assert_eq!(metrics.slowest().unwrap().name, "FILTER");
+        assert_eq!(metrics.stages[1].filtered(), 3);
+        assert_eq!(metrics.stages[2].filtered(), 0);
+
+        let table = metrics.to_string();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].starts_with("  1  FILTER"), "{table}");
+        assert!(lines[2].ends_with(" 3.000  75.0%"), "{table}");
+        assert!(lines[4].ends_with("4.000"), "{table}");
+        assert!(PipelineMetrics::default().slowest().is_none());
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_set_stages() {
+        let info = vec![DebugInfo::new("CONSOLE".to_string(), 0, 3)];
+        let mut job = JobMetrics::new("p", "batch");
+        job.set_stages(&info);
+        assert_eq!(job.stages, vec![StageMetrics::new("CONSOLE", 0, 3)]);
+    }
+}
@@ -0,0 +1,262 @@
+//! Files a run writes besides its main output, written as records pass.
+//!
+//! A [`SideWriter`] takes each record a `SPLIT` stage copies to a named
+//! output and each record a `>` or `>>` stage writes to a file, as the
+//! stage passes it on, so a run streaming a large input never holds them
+//! (see [`execute_plan_streaming_to`](crate::dsl::execute_plan_streaming_to)).
+//!
+//! [`OutputFiles`] is the writer of the command-line runners. Like a job
+//! step allocating its output datasets before it runs, it opens every file
+//! when the pipeline is set up: each SPLIT output's `-o NAME=PATH` and the
+//! file of each `>` and `>>` stage (its name, or the path `--out NAME=PATH`
+//! gives). Files ending in `.gz` or `.zst` are compressed as they are
+//! written.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::outfiles::{OutputFiles, SideWriter};
+//! use pipelines_rs::{PipelinePlan, Record};
+//!
+//! let dir = tempfile::tempdir().unwrap();
+//! let path = dir.path().join("sales.txt");
+//! let path = path.to_str().unwrap();
+//! let plan = PipelinePlan::parse("PIPE CONSOLE | > SALES | CONSOLE").unwrap();
+//!
+//! let outs = [("SALES".to_string(), path.to_string())];
+//! let mut files = OutputFiles::open(plan.commands(), [], &outs).unwrap();
+//! files.write_file("SALES", &Record::from_str("DOE")).unwrap();
+//! files.finish().unwrap();
+//!
+//! assert_eq!(std::fs::read_to_string(path).unwrap(), "DOE\n");
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::Command;
+use crate::Record;
+use crate::ebcdic::CodePage;
+use crate::io::{Compression, Encoder};
+
+/// Takes the records of a run's SPLIT outputs and `>` and `>>` stages as
+/// the stages pass them on.
+pub trait SideWriter {
+    /// Writes a record a `SPLIT name` stage copied.
+    fn write_output(&mut self, name: &str, record: &Record) -> Result<(), String>;
+
+    /// Writes a record a `> path` or `>> path` stage passed on.
+    fn write_file(&mut self, path: &str, record: &Record) -> Result<(), String>;
+}
+
+/// An open output file.
+struct OutputFile {
+    path: String,
+    encoder: Option<Encoder<BufWriter<File>>>,
+}
+
+impl OutputFile {
+    /// Creates the file at `path`, or opens it to append, creating its
+    /// directory.
+    fn open(path: &str, append: bool) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("cannot open output file '{path}': {e}");
+        if let Some(parent) = Path::new(path).parent()
+            && !parent.as_os_str().is_empty()
+        {
+            fs::create_dir_all(parent).map_err(|e| error(&e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| error(&e))?;
+        let encoder = Encoder::new(BufWriter::new(file), Compression::from_path(path))
+            .map_err(|e| error(&e))?;
+        Ok(Self {
+            path: path.to_string(),
+            encoder: Some(encoder),
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let encoder = self.encoder.as_mut().ok_or("output file is closed")?;
+        encoder
+            .write_all(bytes)
+            .map_err(|e| format!("cannot write output file '{}': {e}", self.path))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        match self.encoder.take() {
+            Some(encoder) => encoder
+                .finish()
+                .map(drop)
+                .map_err(|e| format!("cannot write output file '{}': {e}", self.path)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The files of a run's SPLIT outputs and `>` and `>>` stages, open for
+/// writing.
+///
+/// SPLIT outputs are written like the main output: text lines without
+/// trailing blanks, or EBCDIC records with [`with_ebcdic`]
+/// (Self::with_ebcdic). `>` and `>>` files are always text lines. A file
+/// several stages write is opened once, by the first.
+pub struct OutputFiles {
+    /// SPLIT outputs by name
+    outputs: Vec<(String, OutputFile)>,
+    /// `>` and `>>` files by the name the stages write
+    files: Vec<(String, OutputFile)>,
+    ebcdic: Option<(CodePage, usize)>,
+}
+
+impl OutputFiles {
+    /// Opens the file of each `(name, path)` SPLIT output in `splits`, and
+    /// of each `>` (creating it) and `>>` (appending) stage in `commands`,
+    /// at the path `outs` gives for its name, or at the name itself.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a file or its directory cannot be created.
+    pub fn open<'a>(
+        commands: &[Command],
+        splits: impl IntoIterator<Item = (&'a str, &'a str)>,
+        outs: &[(String, String)],
+    ) -> Result<Self, String> {
+        let outputs = splits
+            .into_iter()
+            .map(|(name, path)| Ok((name.to_string(), OutputFile::open(path, false)?)))
+            .collect::<Result<_, String>>()?;
+        let mut files: Vec<(String, OutputFile)> = Vec::new();
+        for cmd in commands {
+            if let Command::FileOut { path: name, append } = cmd
+                && !files.iter().any(|(n, _)| n == name)
+            {
+                let path = outs
+                    .iter()
+                    .rev()
+                    .find(|(n, _)| n == name)
+                    .map_or(name.as_str(), |(_, path)| path);
+                files.push((name.clone(), OutputFile::open(path, *append)?));
+            }
+        }
+        Ok(Self {
+            outputs,
+            files,
+            ebcdic: None,
+        })
+    }
+
+    /// Writes SPLIT outputs as `lrecl`-byte EBCDIC records in `code_page`,
+    /// for a pipeline ending with `CONVERT EBCDIC`.
+    #[must_use]
+    pub fn with_ebcdic(mut self, code_page: CodePage, lrecl: usize) -> Self {
+        self.ebcdic = Some((code_page, lrecl));
+        self
+    }
+
+    /// Flushes every file, ending any compressed stream.
+    ///
+    /// # Errors
+    ///
+    /// Fails with the first file that cannot be written.
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.outputs
+            .iter_mut()
+            .chain(&mut self.files)
+            .try_for_each(|(_, file)| file.finish())
+    }
+}
+
+impl SideWriter for OutputFiles {
+    fn write_output(&mut self, name: &str, record: &Record) -> Result<(), String> {
+        let Some((_, file)) = self.outputs.iter_mut().find(|(n, _)| n == name) else {
+            return Err(format!("SPLIT {name} has no output file"));
+        };
+        match self.ebcdic {
+            Some((code_page, lrecl)) => {
+                file.write(&Record::from_str_lrecl(record.as_str(), lrecl).to_ebcdic(code_page))
+            }
+            None => file.write(format!("{}\n", record.as_str().trim_end()).as_bytes()),
+        }
+    }
+
+    fn write_file(&mut self, path: &str, record: &Record) -> Result<(), String> {
+        let Some((_, file)) = self.files.iter_mut().find(|(n, _)| n == path) else {
+            return Err(format!("> {path} has no output file"));
+        };
+        file.write(format!("{}\n", record.as_str().trim_end()).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PipelinePlan;
+
+    #[test]
+    fn test_open_and_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let at = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        fs::write(at("log.txt"), "OLD\n").unwrap();
+        let plan = PipelinePlan::parse(
+            "PIPE CONSOLE | SPLIT HIGH | > out/all.txt | >> LOG | > out/all.txt | CONSOLE",
+        )
+        .unwrap();
+        let outs = [
+            ("LOG".to_string(), at("log.txt")),
+            ("out/all.txt".to_string(), at("out/all.txt")),
+        ];
+        let high = at("high.txt");
+        let mut files =
+            OutputFiles::open(plan.commands(), [("HIGH", high.as_str())], &outs).unwrap();
+
+        // Every file exists before a record is written
+        assert!(Path::new(&at("out/all.txt")).exists());
+        assert!(Path::new(&high).exists());
+        for text in ["ONE   ", "TWO"] {
+            let record = Record::from_str(text);
+            files.write_output("HIGH", &record).unwrap();
+            files.write_file("out/all.txt", &record).unwrap();
+            files.write_file("LOG", &record).unwrap();
+        }
+        assert!(files.write_file("other", &Record::from_str("X")).is_err());
+        files.finish().unwrap();
+
+        assert_eq!(fs::read_to_string(&high).unwrap(), "ONE\nTWO\n");
+        assert_eq!(fs::read_to_string(at("out/all.txt")).unwrap(), "ONE\nTWO\n");
+        assert_eq!(
+            fs::read_to_string(at("log.txt")).unwrap(),
+            "OLD\nONE\nTWO\n"
+        );
+    }
+
+    #[test]
+    fn test_ebcdic_split_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("high.dat");
+        let path = path.to_str().unwrap();
+        let mut files = OutputFiles::open(&[], [("HIGH", path)], &[])
+            .unwrap()
+            .with_ebcdic(CodePage::Cp037, 4);
+        files.write_output("HIGH", &Record::from_str("HI")).unwrap();
+        files.finish().unwrap();
+        assert_eq!(fs::read(path).unwrap(), [0xC8, 0xC9, 0x40, 0x40]);
+    }
+
+    #[test]
+    fn test_open_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let path = file.join("out.txt");
+        let err = OutputFiles::open(&[], [("HIGH", path.to_str().unwrap())], &[])
+            .err()
+            .unwrap();
+        assert!(err.starts_with("cannot open output file"), "{err}");
+    }
+}
@@ -0,0 +1,226 @@
+//! Parallel execution of stateless stages (requires the `parallel` feature;
+//! not for WebAssembly, which has no threads).
+//!
+//! [`Pipeline::par_bridge`](crate::Pipeline::par_bridge) hands a pipeline's
+//! records to a [`ParPipeline`], whose filter, select, and map stages run
+//! across rayon's thread pool. These stages look at one record at a time,
+//! so records can be processed in any order; stateful stages (sort, dedup,
+//! take, aggregation) stay in the sequential pipeline, before or after.
+//!
+//! Output order is kept by default: records are taken in chunks
+//! ([`PAR_CHUNK`] unless set with [`ParPipeline::chunk_size`]), each chunk
+//! is processed in parallel, and its records come out in input order before
+//! the next chunk is read, so memory stays bounded by the chunk.
+//! [`ParPipeline::for_each`] gives up the order and hands each record to
+//! its consumer on whichever thread produced it.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Pipeline, Record};
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//!     Record::from_str("DOE     JANE      SALES     00060000"),
+//! ];
+//!
+//! let sales: Vec<Record> = Pipeline::new(records.into_iter())
+//!     .par_bridge()
+//!     .filter(|r| r.field_eq(18, 10, "SALES"))
+//!     .select(vec![(0, 8, 0)])
+//!     .sequential()
+//!     .take(10)
+//!     .collect();
+//!
+//! assert_eq!(sales.len(), 2);
+//! assert_eq!(sales[1].field(0, 8).trim(), "DOE");
+//! ```
+
+use rayon::prelude::*;
+
+use crate::Record;
+use crate::pipeline::Pipeline;
+
+/// Records [`ParPipeline`] processes together by default.
+pub const PAR_CHUNK: usize = 8192;
+
+/// One stateless stage.
+enum Op {
+    Filter(Box<dyn Fn(&Record) -> bool + Send + Sync>),
+    Map(Box<dyn Fn(Record) -> Record + Send + Sync>),
+}
+
+/// Runs the stages on one record, or returns `None` if a filter drops it.
+fn apply(ops: &[Op], mut record: Record) -> Option<Record> {
+    for op in ops {
+        match op {
+            Op::Filter(predicate) => {
+                if !predicate(&record) {
+                    return None;
+                }
+            }
+            Op::Map(transform) => record = transform(record),
+        }
+    }
+    Some(record)
+}
+
+/// A pipeline whose stateless stages run in parallel, from
+/// [`Pipeline::par_bridge`](crate::Pipeline::par_bridge).
+pub struct ParPipeline<I> {
+    iter: I,
+    ops: Vec<Op>,
+    chunk: usize,
+}
+
+impl<I> ParPipeline<I>
+where
+    I: Iterator<Item = Record>,
+{
+    pub(crate) fn new(iter: I) -> Self {
+        Self {
+            iter,
+            ops: Vec::new(),
+            chunk: PAR_CHUNK,
+        }
+    }
+
+    /// Sets how many records are read and processed together when output
+    /// order is kept.
+    #[must_use]
+    pub fn chunk_size(mut self, records: usize) -> Self {
+        self.chunk = records.max(1);
+        self
+    }
+
+    /// Filters records using a predicate.
+    #[must_use]
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Record) -> bool + Send + Sync + 'static,
+    {
+        self.ops.push(Op::Filter(Box::new(predicate)));
+        self
+    }
+
+    /// Transforms each record.
+    #[must_use]
+    pub fn map<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(Record) -> Record + Send + Sync + 'static,
+    {
+        self.ops.push(Op::Map(Box::new(transform)));
+        self
+    }
+
+    /// Copies `(source_start, length, dest_start)` fields into a blank
+    /// record, as [`Pipeline::select`] does.
+    #[must_use]
+    pub fn select(self, fields: Vec<(usize, usize, usize)>) -> Self {
+        self.map(move |record| {
+            let mut output = Record::blank(record.lrecl());
+            for &(src_start, length, dest_start) in &fields {
+                output.set_field(dest_start, length, record.field(src_start, length));
+            }
+            output
+        })
+    }
+
+    /// Returns to a sequential pipeline whose records come out in input
+    /// order, processing each chunk in parallel as it is needed.
+    pub fn sequential(self) -> Pipeline<impl Iterator<Item = Record>> {
+        let Self {
+            mut iter,
+            ops,
+            chunk,
+        } = self;
+        let mut ready = Vec::new().into_iter();
+        Pipeline::new(std::iter::from_fn(move || {
+            loop {
+                if let Some(record) = ready.next() {
+                    return Some(record);
+                }
+                let batch: Vec<Record> = iter.by_ref().take(chunk).collect();
+                if batch.is_empty() {
+                    return None;
+                }
+                ready = batch
+                    .into_par_iter()
+                    .filter_map(|record| apply(&ops, record))
+                    .collect::<Vec<_>>()
+                    .into_iter();
+            }
+        }))
+    }
+
+    /// Collects the records in input order.
+    pub fn collect(self) -> Vec<Record> {
+        self.sequential().collect()
+    }
+
+    /// Passes each record to `consumer` on the thread that processed it, in
+    /// no particular order, without waiting for the rest of a chunk.
+    pub fn for_each<F>(self, consumer: F)
+    where
+        I: Send,
+        F: Fn(Record) + Send + Sync,
+    {
+        let ops = self.ops;
+        self.iter
+            .par_bridge()
+            .filter_map(|record| apply(&ops, record))
+            .for_each(consumer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn numbered(count: usize) -> Vec<Record> {
+        (0..count)
+            .map(|n| Record::from_str(&format!("{n:06}")))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_sequential_pipeline() {
+        let expected: Vec<Record> = Pipeline::new(numbered(1000).into_iter())
+            .filter(|r| r.field(5, 1) != "3")
+            .select(vec![(2, 4, 0)])
+            .map(|mut r| {
+                r.set_field(6, 2, "OK");
+                r
+            })
+            .collect();
+
+        let parallel = Pipeline::new(numbered(1000).into_iter())
+            .par_bridge()
+            .chunk_size(64)
+            .filter(|r| r.field(5, 1) != "3")
+            .select(vec![(2, 4, 0)])
+            .map(|mut r| {
+                r.set_field(6, 2, "OK");
+                r
+            })
+            .collect();
+        assert_eq!(parallel, expected);
+        assert_eq!(parallel.len(), 900);
+    }
+
+    #[test]
+    fn test_for_each_unordered() {
+        let seen = Mutex::new(Vec::new());
+        Pipeline::new(numbered(500).into_iter())
+            .par_bridge()
+            .filter(|r| r.field(5, 1) == "0")
+            .for_each(|r| seen.lock().unwrap().push(r));
+
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(seen.len(), 50);
+        assert_eq!(seen[49].field(0, 6), "000490");
+    }
+}
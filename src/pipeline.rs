@@ -23,6 +23,19 @@
 //! ```
 
 use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::group::{Aggregate, GroupBy};
+use crate::join::Join;
+use crate::lookup::Lookup;
+use crate::report::{BreakIter, ControlBreak};
+use crate::sink::Sink;
+use crate::specs::{SpecItem, Specs};
+use crate::split::{Split, SplitOutputs};
+use crate::stage::{
+    Between, Change, Dedup, DropLast, Duplicate, Literal, Locate, Overlay, Recno, RecnoRange,
+    RunningTotal, SORT_MEMORY, Sample, Seqnum, Sort, SortKey, Stage, SumBy, SumField, TakeLast,
+};
+use crate::trailer::Trailer;
 
 /// A pipeline for processing records.
 ///
@@ -83,6 +96,105 @@ where
         }
     }
 
+    /// Keeps records containing `pattern` anywhere (CMS Pipelines
+    /// `LOCATE`).
+    ///
+    /// See [`Locate`] to search a column range only.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ERROR disk full"),
+    ///     Record::from_str("INFO started"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .locate("disk")
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 1);
+    /// ```
+    pub fn locate(self, pattern: &str) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Locate::new(pattern))
+    }
+
+    /// Drops records containing `pattern` anywhere (CMS Pipelines
+    /// `NLOCATE`).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ERROR disk full"),
+    ///     Record::from_str("INFO started"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .nlocate("ERROR")
+    ///     .collect();
+    ///
+    /// assert!(result[0].as_str().starts_with("INFO"));
+    /// ```
+    pub fn nlocate(self, pattern: &str) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Locate::nlocate(pattern))
+    }
+
+    /// Keeps each section from a record containing `start` through the
+    /// next record containing `end` (CMS Pipelines `BETWEEN`).
+    ///
+    /// See [`Between`] to drop the markers or search a column range only.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("HEADER"),
+    ///     Record::from_str("BEGIN"),
+    ///     Record::from_str("DETAIL"),
+    ///     Record::from_str("END"),
+    ///     Record::from_str("FOOTER"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .between("BEGIN", "END")
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 3);
+    /// ```
+    pub fn between(self, start: &str, end: &str) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Between::new(start, end))
+    }
+
+    /// Replaces every occurrence of `old` with `new` (CMS Pipelines
+    /// `CHANGE`).
+    ///
+    /// See [`Change`] to limit the columns searched or the replacements per
+    /// record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("SMITH   SALES")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .change("SALES", "MKTG")
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].as_str().trim_end(), "SMITH   MKTG");
+    /// ```
+    pub fn change(self, old: &str, new: &str) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Change::new(old, new))
+    }
+
     /// Omits records matching a predicate.
     ///
     /// Records for which the predicate returns `true` are removed.
@@ -140,6 +252,142 @@ where
         }
     }
 
+    /// Transforms each record with a function that can fail, turning the
+    /// pipeline into a [`TryPipeline`].
+    ///
+    /// Nothing runs until the result is consumed; `try_collect` stops at
+    /// the first error, and `on_error` diverts errors and goes on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, PipelineError, Record};
+    ///
+    /// let records = vec![Record::from_str("00000042"), Record::from_str("BAD")];
+    ///
+    /// let result = Pipeline::new(records.into_iter())
+    ///     .try_map(|mut r| {
+    ///         let value = r.field_zoned(0, 8).ok_or_else(|| {
+    ///             PipelineError::Stage(format!("not a number: {}", r.as_str().trim()))
+    ///         })?;
+    ///         r.set_field_zoned(0, 8, value * 2)?;
+    ///         Ok(r)
+    ///     })
+    ///     .try_collect();
+    ///
+    /// assert_eq!(result.unwrap_err().to_string(), "stage error: not a number: BAD");
+    /// ```
+    pub fn try_map<F>(self, transform: F) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(Record) -> Result<Record>,
+    {
+        TryPipeline {
+            iter: self.iter.map(transform),
+        }
+    }
+
+    /// Filters records with a predicate that can fail, turning the
+    /// pipeline into a [`TryPipeline`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, PipelineError, Record};
+    ///
+    /// let records = vec![Record::from_str("00000042"), Record::from_str("????")];
+    /// let mut errors = Vec::new();
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .try_filter(|r| {
+    ///         let value = r.field_zoned(0, 8).ok_or_else(|| {
+    ///             PipelineError::Stage("bad amount".to_string())
+    ///         })?;
+    ///         Ok(value > 10)
+    ///     })
+    ///     .on_error(|e| errors.push(e))
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 1);
+    /// assert_eq!(errors.len(), 1);
+    /// ```
+    pub fn try_filter<F>(self, predicate: F) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(&Record) -> Result<bool>,
+    {
+        self.try_map(Ok).try_filter(predicate)
+    }
+
+    /// Turns each record into zero or more records, e.g. one detail record
+    /// per repeating group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("C001 A100B200")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .explode(|r| {
+    ///         vec![
+    ///             Record::from_str(&format!("{} {}", r.field(0, 4), r.field(5, 4))),
+    ///             Record::from_str(&format!("{} {}", r.field(0, 4), r.field(9, 4))),
+    ///         ]
+    ///     })
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[1].field_eq(5, 4, "B200"));
+    /// ```
+    pub fn explode<F>(self, transform: F) -> Pipeline<impl Iterator<Item = Record>>
+    where
+        F: FnMut(Record) -> Vec<Record>,
+    {
+        Pipeline {
+            iter: self.iter.flat_map(transform),
+        }
+    }
+
+    /// Writes each record followed by `copies` more copies of it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("A"), Record::from_str("B")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .duplicate(1)
+    ///     .collect();
+    ///
+    /// let names: Vec<_> = result.iter().map(|r| r.as_str().trim_end()).collect();
+    /// assert_eq!(names, ["A", "A", "B", "B"]);
+    /// ```
+    pub fn duplicate(self, copies: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Duplicate::new(copies))
+    }
+
+    /// Writes `record` ahead of the records, or alone if there are none.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("SMITH")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .literal(Record::from_str("NAME"))
+    ///     .collect();
+    ///
+    /// let names: Vec<_> = result.iter().map(|r| r.as_str().trim_end()).collect();
+    /// assert_eq!(names, ["NAME", "SMITH"]);
+    /// ```
+    pub fn literal(self, record: Record) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Literal::new(record))
+    }
+
     /// Transforms records with the option to filter.
     ///
     /// Records for which the transform returns `None` are removed.
@@ -179,7 +427,8 @@ where
 
     /// Selects specific fields from records.
     ///
-    /// Creates new records containing only the specified fields.
+    /// Creates new records, as long as the input records, containing only
+    /// the specified fields.
     /// Fields are specified as (source_start, length, dest_start) tuples.
     ///
     /// # Example
@@ -207,7 +456,7 @@ where
     ) -> Pipeline<impl Iterator<Item = Record>> {
         Pipeline {
             iter: self.iter.map(move |record| {
-                let mut output = Record::new();
+                let mut output = Record::blank(record.lrecl());
                 for &(src_start, length, dest_start) in &fields {
                     let value = record.field(src_start, length);
                     output.set_field(dest_start, length, value);
@@ -217,90 +466,810 @@ where
         }
     }
 
-    /// Reformats records by rearranging fields.
+    /// Copies fields into other columns of each record, keeping the rest
+    /// of the record (DFSORT `OVERLAY`).
+    ///
+    /// Fields are (source_start, length, dest_start) tuples, as for
+    /// `select`, and are read from the record as it arrived. Use
+    /// [`Overlay::literal`] with `stage` to write constant text as well.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .overlay(vec![(0, 8, 72)]) // Last name -> 72
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].field(0, 8).trim(), "SMITH");
+    /// assert_eq!(result[0].field(72, 8).trim(), "SMITH");
+    /// ```
+    pub fn overlay(
+        self,
+        fields: Vec<(usize, usize, usize)>,
+    ) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Overlay::new(fields))
+    }
+
+    /// Reformats records by rearranging fields.
+    ///
+    /// A convenience wrapper around `map` for field rearrangement.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     "),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .reformat(|r| {
+    ///         let mut out = Record::new();
+    ///         out.set_field(0, 10, r.field(8, 10));  // First name first
+    ///         out.set_field(10, 8, r.field(0, 8));   // Last name second
+    ///         out
+    ///     })
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].field(0, 10).trim(), "JOHN");
+    /// ```
+    pub fn reformat<F>(self, transform: F) -> Pipeline<impl Iterator<Item = Record>>
+    where
+        F: FnMut(&Record) -> Record,
+    {
+        let mut transform = transform;
+        Pipeline {
+            iter: self.iter.map(move |r| transform(&r)),
+        }
+    }
+
+    /// Builds each record from input fields, literals, and the record
+    /// number, like CMS Pipelines `SPECS`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SpecItem};
+    ///
+    /// let records = vec![Record::from_str("SMITH   JOHN      SALES     ")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .specs(vec![
+    ///         SpecItem::range(8, 4).at(0),
+    ///         SpecItem::range(0, 5).next_word(),
+    ///         SpecItem::recno().at(20).width(3),
+    ///     ])
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].as_str().trim_end(), "JOHN SMITH            1");
+    /// ```
+    pub fn specs(self, items: Vec<SpecItem>) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Specs::new(items))
+    }
+
+    /// Inspects each record without modifying it.
+    ///
+    /// Useful for debugging or logging.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("TEST")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .inspect(|r| println!("Processing: {}", r.field(0, 8).trim()))
+    ///     .collect();
+    /// ```
+    pub fn inspect<F>(self, callback: F) -> Pipeline<impl Iterator<Item = Record>>
+    where
+        F: FnMut(&Record),
+    {
+        Pipeline {
+            iter: self.iter.inspect(callback),
+        }
+    }
+
+    /// Copies every record into `sink` while the records continue
+    /// downstream; see [`crate::sink`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, WriteSink};
+    ///
+    /// let records = vec![Record::from_str("ONE"), Record::from_str("TWO")];
+    ///
+    /// let mut log = WriteSink::new(Vec::new());
+    /// let count = Pipeline::new(records.into_iter())
+    ///     .tee(&mut log)
+    ///     .take(1)
+    ///     .count();
+    ///
+    /// assert_eq!(count, 1);
+    /// assert_eq!(log.finish().unwrap(), b"ONE\n");
+    /// ```
+    pub fn tee<S: Sink>(self, mut sink: S) -> Pipeline<impl Iterator<Item = Record>> {
+        Pipeline {
+            iter: self.iter.inspect(move |r| sink.accept(r)),
+        }
+    }
+
+    /// Takes the first n records.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ONE"),
+    ///     Record::from_str("TWO"),
+    ///     Record::from_str("THREE"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .take(2)
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// ```
+    pub fn take(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        Pipeline {
+            iter: self.iter.take(n),
+        }
+    }
+
+    /// Takes the last n records (CMS Pipelines `TAKE LAST`).
+    ///
+    /// Holds only n records at a time; nothing passes until the input ends.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ONE"),
+    ///     Record::from_str("TWO"),
+    ///     Record::from_str("THREE"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .take_last(2)
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[0].as_str().starts_with("TWO"));
+    /// ```
+    pub fn take_last(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(TakeLast::new(n))
+    }
+
+    /// Drops the last n records (CMS Pipelines `DROP LAST`), such as a
+    /// trailer.
+    ///
+    /// Holds only n records at a time, so records stream through n behind
+    /// the input.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ONE"),
+    ///     Record::from_str("TWO"),
+    ///     Record::from_str("TRAILER"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .drop_last(1)
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[1].as_str().starts_with("TWO"));
+    /// ```
+    pub fn drop_last(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(DropLast::new(n))
+    }
+
+    /// Keeps the records at the positions `ranges` select, counting from 1
+    /// (CMS Pipelines-style record number selection).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, RecnoRange, Record};
+    ///
+    /// let records = (1..=10).map(|n| Record::from_str(&format!("REC{n}")));
+    ///
+    /// let result: Vec<_> = Pipeline::new(records)
+    ///     .recno(vec![RecnoRange::single(1), RecnoRange::new(6, 10).by(2)])
+    ///     .collect();
+    ///
+    /// let names: Vec<_> = result.iter().map(|r| r.as_str().trim()).collect();
+    /// assert_eq!(names, ["REC1", "REC6", "REC8", "REC10"]);
+    /// ```
+    pub fn recno(self, ranges: Vec<RecnoRange>) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Recno::new(ranges))
+    }
+
+    /// Keeps a random `fraction` of the records, the same ones on every run
+    /// with the same `seed`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records: Vec<_> = (0..1000).map(|n| Record::from_str(&format!("REC{n}"))).collect();
+    ///
+    /// let sample: Vec<_> = Pipeline::new(records.clone().into_iter())
+    ///     .sample(0.05, 1234)
+    ///     .collect();
+    /// let again: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .sample(0.05, 1234)
+    ///     .collect();
+    ///
+    /// assert_eq!(sample, again);
+    /// assert!(sample.len() < 100);
+    /// ```
+    pub fn sample(self, fraction: f64, seed: u64) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(Sample::new(fraction, seed))
+    }
+
+    /// Skips the first n records.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("ONE"),
+    ///     Record::from_str("TWO"),
+    ///     Record::from_str("THREE"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .skip(1)
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[0].as_str().starts_with("TWO"));
+    /// ```
+    pub fn skip(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        Pipeline {
+            iter: self.iter.skip(n),
+        }
+    }
+
+    /// Drops `n` header records from the front of a file.
+    ///
+    /// The same as [`Pipeline::skip`], named for files with a header.
+    pub fn skip_header(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
+        self.skip(n)
+    }
+
+    /// Removes the trailer record from the end of the records and checks
+    /// its control totals (record count, hash totals) against them.
+    ///
+    /// This reads all records, so that a bad trailer fails before any
+    /// detail record is used.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `PipelineError::Stage` if the trailer is missing or its
+    /// totals do not match; see [`Trailer::validate`].
+    pub fn with_trailer_validation(
+        self,
+        trailer: Trailer,
+    ) -> Result<Pipeline<impl Iterator<Item = Record>>> {
+        let mut records: Vec<Record> = self.iter.collect();
+        trailer.validate(&mut records)?;
+        Ok(Pipeline {
+            iter: records.into_iter(),
+        })
+    }
+
+    /// Hands the records to a [`ParPipeline`](crate::ParPipeline), whose
+    /// filter, select, and map stages run across a thread pool (requires
+    /// the `parallel` feature).
+    ///
+    /// Call [`sequential`](crate::ParPipeline::sequential) to come back to
+    /// a pipeline whose records are in input order.
+    #[cfg(feature = "parallel")]
+    pub fn par_bridge(self) -> crate::parallel::ParPipeline<I> {
+        crate::parallel::ParPipeline::new(self.iter)
+    }
+
+    /// Turns the pipeline into a [`RecordStream`](crate::RecordStream), to
+    /// continue with async stages (requires the `async` feature).
+    ///
+    /// The records are taken from the pipeline as the stream is polled.
+    #[cfg(feature = "async")]
+    pub fn into_stream<'a>(self) -> crate::stream::RecordStream<'a>
+    where
+        I: Send + 'a,
+    {
+        crate::stream::RecordStream::new(futures_util::stream::iter(self.iter))
+    }
+
+    /// Sorts records by one or more key fields.
+    ///
+    /// Earlier keys take precedence and records with equal keys keep their
+    /// input order. Similar to DFSORT's SORT FIELDS operation. The whole
+    /// input is read before the first record is returned; past
+    /// [`SORT_MEMORY`](crate::SORT_MEMORY) it is written to temporary files
+    /// in sorted runs, which are merged as the records are read (see
+    /// [`sort_external`](Pipeline::sort_external)).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a temporary file cannot be written or read back; use
+    /// [`sort_external`](Pipeline::sort_external) to handle those errors.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SortKey};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+    ///     Record::from_str("DOE     JANE      SALES     00060000"),
+    /// ];
+    ///
+    /// // Department ascending, then salary descending
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .sort(vec![
+    ///         SortKey::new(18, 10),
+    ///         SortKey::new(28, 8).numeric().descending(),
+    ///     ])
+    ///     .collect();
+    ///
+    /// assert!(result[0].field_eq(0, 8, "JONES"));
+    /// assert!(result[1].field_eq(0, 8, "DOE"));
+    /// assert!(result[2].field_eq(0, 8, "SMITH"));
+    /// ```
+    pub fn sort(self, keys: Vec<SortKey>) -> Pipeline<impl Iterator<Item = Record>> {
+        let sorted = self
+            .sort_external(keys, SORT_MEMORY)
+            .unwrap_or_else(|e| panic!("cannot sort: {e}"));
+        Pipeline {
+            iter: sorted
+                .iter
+                .map(|record| record.unwrap_or_else(|e| panic!("cannot sort: {e}"))),
+        }
+    }
+
+    /// Sorts records like [`sort`](Pipeline::sort), holding at most about
+    /// `memory` bytes of them: beyond that, sorted runs are written to
+    /// temporary files and merged as the records are read (see [`Sort`]).
+    ///
+    /// # Errors
+    ///
+    /// Fails if a temporary file cannot be written or read; an error while
+    /// merging is an item of the returned pipeline.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SortKey};
+    ///
+    /// let records = (0..1000).rev().map(|n| Record::from_str(&format!("{n:04}")));
+    ///
+    /// // At most 8000 bytes (100 records) in memory at a time
+    /// let sorted = Pipeline::new(records)
+    ///     .sort_external(vec![SortKey::new(0, 4)], 8000)
+    ///     .unwrap()
+    ///     .try_collect()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(sorted[0].field(0, 4), "0000");
+    /// assert_eq!(sorted[999].field(0, 4), "0999");
+    /// ```
+    pub fn sort_external(
+        self,
+        keys: Vec<SortKey>,
+        memory: usize,
+    ) -> Result<TryPipeline<impl Iterator<Item = Result<Record>>>> {
+        let mut sort = Sort::new(keys).with_memory(memory);
+        for record in self.iter {
+            sort.push(record)?;
+        }
+        Ok(TryPipeline {
+            iter: sort.sorted()?,
+        })
+    }
+
+    /// Writes a sequence number into the `length` columns at `position` of
+    /// each record, counting from `start` by `increment` (DFSORT `SEQNUM`).
+    ///
+    /// See [`Seqnum`] to restart numbering for each key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SortKey};
+    ///
+    /// let records = vec![Record::from_str("SMITH"), Record::from_str("DOE")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .sort(vec![SortKey::new(0, 8)])
+    ///     .seqnum(1, 1, 72, 8)
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].field(72, 8), "00000001");
+    /// assert!(result[0].field_eq(0, 8, "DOE"));
+    /// ```
+    pub fn seqnum(
+        self,
+        start: u64,
+        increment: u64,
+        position: usize,
+        length: usize,
+    ) -> Pipeline<impl Iterator<Item = Record>> {
+        let mut seqnum = Seqnum::new(start, increment, position, length);
+        Pipeline {
+            iter: self.iter.filter_map(move |r| seqnum.process(r)),
+        }
+    }
+
+    /// Runs records through a [`Stage`], including one written outside this
+    /// crate.
+    ///
+    /// Records stream through [`Stage::process_into`], so a stage may emit
+    /// several records for one input; when the input ends, the records
+    /// returned by [`Stage::flush`] follow.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, Stage};
+    ///
+    /// /// Holds records back and emits them in reverse order.
+    /// struct Reverse(Vec<Record>);
+    ///
+    /// impl Stage for Reverse {
+    ///     fn process_into(&mut self, record: Record, _: &mut Vec<Record>) {
+    ///         self.0.push(record);
+    ///     }
+    ///
+    ///     fn flush(&mut self) -> Vec<Record> {
+    ///         std::mem::take(&mut self.0).into_iter().rev().collect()
+    ///     }
+    /// }
+    ///
+    /// let records = vec![Record::from_str("ONE"), Record::from_str("TWO")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .stage(Reverse(Vec::new()))
+    ///     .collect();
+    ///
+    /// assert!(result[0].field_eq(0, 3, "TWO"));
+    /// assert!(result[1].field_eq(0, 3, "ONE"));
+    /// ```
+    pub fn stage<S: Stage>(self, mut stage: S) -> Pipeline<impl Iterator<Item = Record>> {
+        let mut iter = self.iter;
+        let mut pending = Vec::new().into_iter();
+        let mut flushed = false;
+        Pipeline {
+            iter: std::iter::from_fn(move || {
+                loop {
+                    if let Some(record) = pending.next() {
+                        return Some(record);
+                    }
+                    let mut output = Vec::new();
+                    match iter.next() {
+                        Some(record) => stage.process_into(record, &mut output),
+                        None if !flushed => {
+                            flushed = true;
+                            output = stage.flush();
+                        }
+                        None => return None,
+                    }
+                    pending = output.into_iter();
+                }
+            }),
+        }
+    }
+
+    /// Writes the running total of `field` into `dest` of each record, for
+    /// balance-forward processing.
+    ///
+    /// See [`RunningTotal`] to restart the total for each key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SumField};
+    ///
+    /// let records = vec![Record::from_str("00100"), Record::from_str("-0040")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .running_total(SumField::new(0, 5), SumField::new(6, 6))
+    ///     .collect();
+    ///
+    /// assert_eq!(result[1].field(6, 6), "000060");
+    /// ```
+    pub fn running_total(
+        self,
+        field: SumField,
+        dest: SumField,
+    ) -> Pipeline<impl Iterator<Item = Record>> {
+        let mut total = RunningTotal::new(field, dest);
+        Pipeline {
+            iter: self.iter.filter_map(move |r| total.process(r)),
+        }
+    }
+
+    /// Removes records whose `(start, len)` key fields repeat an earlier
+    /// record, keeping the first (DFSORT `SUM FIELDS=NONE`).
+    ///
+    /// The input need not be sorted. Records stream through; only the keys
+    /// seen so far are remembered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+    ///     Record::from_str("DOE     JANE      SALES     00060000"),
+    /// ];
+    ///
+    /// // The first employee of each department
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .dedup(vec![(18, 10)])
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[0].field_eq(0, 8, "SMITH"));
+    /// assert!(result[1].field_eq(0, 8, "JONES"));
+    /// ```
+    pub fn dedup(self, keys: Vec<(usize, usize)>) -> Pipeline<impl Iterator<Item = Record>> {
+        self.dedup_with(Dedup::new(keys))
+    }
+
+    /// Removes duplicates with a configured [`Dedup`] stage, e.g. keeping
+    /// the last record or streaming sorted input.
+    ///
+    /// Records held by the stage are emitted when the input ends.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Dedup, Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("A 1"),
+    ///     Record::from_str("A 2"),
+    ///     Record::from_str("B 1"),
+    /// ];
+    ///
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .dedup_with(Dedup::new(vec![(0, 1)]).sorted().keep_last())
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].as_str().trim_end(), "A 2");
+    /// assert_eq!(result[1].as_str().trim_end(), "B 1");
+    /// ```
+    pub fn dedup_with(self, dedup: Dedup) -> Pipeline<impl Iterator<Item = Record>> {
+        self.stage(dedup)
+    }
+
+    /// Collapses records with equal `(start, len)` key fields into one,
+    /// adding up their numeric `fields` (DFSORT `SUM FIELDS`).
+    ///
+    /// This collects all records; see [`SumBy`] for how totals are written
+    /// and what happens when a field is not a number or a total overflows.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Pipeline, Record, SumField};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+    ///     Record::from_str("DOE     JANE      SALES     00060000"),
+    /// ];
+    ///
+    /// // Salary total per department
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .sum_by(vec![(18, 10)], vec![SumField::new(28, 8)])
+    ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 2);
+    /// assert!(result[0].field_eq(28, 8, "00110000"));
+    /// assert!(result[1].field_eq(28, 8, "00075000"));
+    /// ```
+    pub fn sum_by(
+        self,
+        keys: Vec<(usize, usize)>,
+        fields: Vec<SumField>,
+    ) -> Pipeline<impl Iterator<Item = Record>> {
+        let records = SumBy::new(keys, fields).process_batch(self.iter.collect());
+        Pipeline {
+            iter: records.into_iter(),
+        }
+    }
+
+    /// Passes sorted records through with a subtotal record after each
+    /// group of equal keys and a grand-total record at the end; see
+    /// [`ControlBreak`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{ControlBreak, Pipeline, Record};
+    ///
+    /// let records = vec![Record::from_str("A"), Record::from_str("A"), Record::from_str("B")];
+    ///
+    /// let report: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .break_on(ControlBreak::new(vec![(0, 1)]).count(2, 3))
+    ///     .collect();
+    ///
+    /// let lines: Vec<_> = report.iter().map(|r| r.as_str().trim_end()).collect();
+    /// assert_eq!(lines, ["A", "A", "A 002", "B", "B 001", "  003"]);
+    /// ```
+    pub fn break_on(self, control: ControlBreak) -> Pipeline<impl Iterator<Item = Record>> {
+        Pipeline {
+            iter: BreakIter::new(self.iter, control),
+        }
+    }
+
+    /// Groups records by their `(start, len)` key fields; call
+    /// [`Grouped::aggregate`] to get one summary record per group.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Aggregate, Pipeline, Record};
+    ///
+    /// let records = vec![
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+    ///     Record::from_str("DOE     JANE      SALES     00060000"),
+    /// ];
+    ///
+    /// // Head count and average salary per department
+    /// let result: Vec<_> = Pipeline::new(records.into_iter())
+    ///     .group_by(vec![(18, 10)])
+    ///     .aggregate(vec![Aggregate::count().at(11, 3), Aggregate::avg(28, 8)])
+    ///     .collect();
+    ///
+    /// assert_eq!(result[0].as_str().trim_end(), "SALES      002 00055000");
+    /// assert_eq!(result[1].as_str().trim_end(), "ENGINEER   001 00075000");
+    /// ```
+    pub fn group_by(self, keys: Vec<(usize, usize)>) -> Grouped<I> {
+        Grouped {
+            iter: self.iter,
+            keys,
+        }
+    }
+
+    /// Joins with a second input on key fields (DFSORT `JOINKEYS`); see
+    /// [`Join`] for which records are written and how they are laid out.
     ///
-    /// A convenience wrapper around `map` for field rearrangement.
+    /// Records of this pipeline stream through; `right` is read into memory.
     ///
     /// # Example
     ///
     /// ```
-    /// use pipelines_rs::{Pipeline, Record};
+    /// use pipelines_rs::{Join, JoinField, Pipeline, Record};
     ///
-    /// let records = vec![
-    ///     Record::from_str("SMITH   JOHN      SALES     "),
+    /// let employees = vec![
+    ///     Record::from_str("SMITH   D01"),
+    ///     Record::from_str("JONES   D02"),
     /// ];
-    ///
-    /// let result: Vec<_> = Pipeline::new(records.into_iter())
-    ///     .reformat(|r| {
-    ///         let mut out = Record::new();
-    ///         out.set_field(0, 10, r.field(8, 10));  // First name first
-    ///         out.set_field(10, 8, r.field(0, 8));   // Last name second
-    ///         out
-    ///     })
+    /// let departments = vec![Record::from_str("D01 SALES")];
+    ///
+    /// let result: Vec<_> = Pipeline::new(employees.into_iter())
+    ///     .join(
+    ///         departments,
+    ///         Join::new(vec![(8, 3)], vec![(0, 3)])
+    ///             .left_outer()
+    ///             .layout(vec![JoinField::Left(0, 8), JoinField::Right(4, 5)]),
+    ///     )
     ///     .collect();
     ///
-    /// assert_eq!(result[0].field(0, 10).trim(), "JOHN");
+    /// assert_eq!(result[0].as_str().trim_end(), "SMITH   SALES");
+    /// assert_eq!(result[1].as_str().trim_end(), "JONES");
     /// ```
-    pub fn reformat<F>(self, transform: F) -> Pipeline<impl Iterator<Item = Record>>
+    pub fn join<J>(self, right: J, join: Join) -> Pipeline<impl Iterator<Item = Record>>
     where
-        F: FnMut(&Record) -> Record,
+        J: IntoIterator<Item = Record>,
     {
-        let mut transform = transform;
         Pipeline {
-            iter: self.iter.map(move |r| transform(&r)),
+            iter: join.apply(self.iter, right),
         }
     }
 
-    /// Inspects each record without modifying it.
+    /// Rewrites a field of each record from a [`Lookup`] table.
     ///
-    /// Useful for debugging or logging.
+    /// This reads all records, so that an unmatched key with
+    /// [`Unmatched::Error`](crate::Unmatched::Error) fails before any
+    /// output is produced.
+    ///
+    /// # Errors
+    ///
+    /// Fails at the first unmatched key if the lookup's policy is
+    /// `Unmatched::Error`.
     ///
     /// # Example
     ///
     /// ```
-    /// use pipelines_rs::{Pipeline, Record};
+    /// use pipelines_rs::{Lookup, Pipeline, Record, Unmatched};
     ///
-    /// let records = vec![Record::from_str("TEST")];
+    /// let mut depts = Lookup::new(8, 3).to(8, 10).unmatched(Unmatched::Drop);
+    /// depts.insert("D01", "SALES");
     ///
+    /// let records = vec![Record::from_str("SMITH   D01"), Record::from_str("JONES   D07")];
     /// let result: Vec<_> = Pipeline::new(records.into_iter())
-    ///     .inspect(|r| println!("Processing: {}", r.field(0, 8).trim()))
+    ///     .lookup(depts)
+    ///     .unwrap()
     ///     .collect();
+    ///
+    /// assert_eq!(result.len(), 1);
+    /// assert_eq!(result[0].as_str().trim_end(), "SMITH   SALES");
     /// ```
-    pub fn inspect<F>(self, callback: F) -> Pipeline<impl Iterator<Item = Record>>
-    where
-        F: FnMut(&Record),
-    {
-        Pipeline {
-            iter: self.iter.inspect(callback),
+    pub fn lookup(self, lookup: Lookup) -> Result<Pipeline<impl Iterator<Item = Record>>> {
+        let mut records = Vec::new();
+        for record in self.iter {
+            records.extend(lookup.translate(record)?);
         }
+        Ok(Pipeline {
+            iter: records.into_iter(),
+        })
     }
 
-    /// Takes the first n records.
+    /// Fans the records out to the named outputs of a [`Split`], consuming
+    /// the pipeline (DFSORT `OUTFIL`).
     ///
     /// # Example
     ///
     /// ```
-    /// use pipelines_rs::{Pipeline, Record};
+    /// use pipelines_rs::{OutFile, Pipeline, Record, Split};
     ///
     /// let records = vec![
-    ///     Record::from_str("ONE"),
-    ///     Record::from_str("TWO"),
-    ///     Record::from_str("THREE"),
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
     /// ];
     ///
-    /// let result: Vec<_> = Pipeline::new(records.into_iter())
-    ///     .take(2)
-    ///     .collect();
+    /// let outputs = Pipeline::new(records.into_iter()).split(
+    ///     Split::new()
+    ///         .output(OutFile::new("NAMES").build(|r: &Record| Record::from_str(r.field(0, 8))))
+    ///         .output(OutFile::new("SALES").include(|r: &Record| r.field_eq(18, 10, "SALES"))),
+    /// );
     ///
-    /// assert_eq!(result.len(), 2);
+    /// assert_eq!(outputs.get("NAMES").unwrap()[1].as_str().trim_end(), "JONES");
+    /// assert_eq!(outputs.get("SALES").unwrap().len(), 1);
     /// ```
-    pub fn take(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
-        Pipeline {
-            iter: self.iter.take(n),
+    pub fn split(self, mut split: Split) -> SplitOutputs {
+        for record in self.iter {
+            split.route(&record);
         }
+        split.finish()
     }
 
-    /// Skips the first n records.
+    /// Splits records into those matching `predicate` and those that do
+    /// not, each in order, so rejected records can be processed (e.g.
+    /// written to an exceptions file) instead of dropped.
+    ///
+    /// Consumes the input before either pipeline runs.
     ///
     /// # Example
     ///
@@ -308,22 +1277,33 @@ where
     /// use pipelines_rs::{Pipeline, Record};
     ///
     /// let records = vec![
-    ///     Record::from_str("ONE"),
-    ///     Record::from_str("TWO"),
-    ///     Record::from_str("THREE"),
+    ///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+    ///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+    ///     Record::from_str("DOE     JANE      SALES     00060000"),
     /// ];
     ///
-    /// let result: Vec<_> = Pipeline::new(records.into_iter())
-    ///     .skip(1)
-    ///     .collect();
+    /// let (sales, others) =
+    ///     Pipeline::new(records.into_iter()).partition(|r| r.field_eq(18, 10, "SALES"));
     ///
-    /// assert_eq!(result.len(), 2);
-    /// assert!(result[0].as_str().starts_with("TWO"));
+    /// assert_eq!(sales.count(), 2);
+    /// let names: Vec<_> = others.select(vec![(0, 8, 0)]).collect();
+    /// assert_eq!(names[0].as_str().trim_end(), "JONES");
     /// ```
-    pub fn skip(self, n: usize) -> Pipeline<impl Iterator<Item = Record>> {
-        Pipeline {
-            iter: self.iter.skip(n),
-        }
+    pub fn partition<F>(
+        self,
+        predicate: F,
+    ) -> (
+        Pipeline<std::vec::IntoIter<Record>>,
+        Pipeline<std::vec::IntoIter<Record>>,
+    )
+    where
+        F: FnMut(&Record) -> bool,
+    {
+        let (kept, rejected): (Vec<_>, Vec<_>) = self.iter.partition(predicate);
+        (
+            Pipeline::new(kept.into_iter()),
+            Pipeline::new(rejected.into_iter()),
+        )
     }
 
     /// Chains another iterator of records.
@@ -398,7 +1378,7 @@ where
     ///
     /// let total: u64 = Pipeline::new(records.into_iter())
     ///     .fold(0u64, |acc, r| {
-    ///         let salary: u64 = r.field(29, 8).trim().parse().unwrap_or(0);
+    ///         let salary: u64 = r.field_num(28, 8).unwrap();
     ///         acc + salary
     ///     });
     ///
@@ -470,9 +1450,153 @@ where
     Pipeline::new(lines.map(|s| Record::from_str(s.as_ref())))
 }
 
+/// Creates a pipeline from lines in strict mode: a line longer than a
+/// record or holding non-ASCII characters becomes an error instead of
+/// being truncated or altered (see [`Record::try_from_str`]).
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::pipeline::from_lines_strict;
+///
+/// let result = from_lines_strict(["SMITH", "JOSÉ"].into_iter()).try_collect();
+/// assert!(result.is_err());
+/// ```
+pub fn from_lines_strict<I, S>(lines: I) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+where
+    I: Iterator<Item = S>,
+    S: AsRef<str>,
+{
+    TryPipeline {
+        iter: lines.map(|s| Record::try_from_str(s.as_ref())),
+    }
+}
+
+/// A pipeline whose records are grouped by key, from [`Pipeline::group_by`].
+pub struct Grouped<I> {
+    iter: I,
+    keys: Vec<(usize, usize)>,
+}
+
+impl<I> Grouped<I>
+where
+    I: Iterator<Item = Record>,
+{
+    /// Emits one summary record per group holding the key fields and the
+    /// `aggregates`; see [`GroupBy`] for the layout.
+    ///
+    /// This collects all records.
+    pub fn aggregate(self, aggregates: Vec<Aggregate>) -> Pipeline<impl Iterator<Item = Record>> {
+        let records = GroupBy::new(self.keys, aggregates).process_batch(self.iter.collect());
+        Pipeline {
+            iter: records.into_iter(),
+        }
+    }
+}
+
+/// A pipeline whose stages can fail, from [`Pipeline::try_map`] or
+/// [`Pipeline::try_filter`].
+///
+/// Each item is a record or the error a stage returned for one. Later
+/// stages pass errors through untouched, so each error surfaces in input
+/// order where the record would have been.
+pub struct TryPipeline<I> {
+    iter: I,
+}
+
+impl<I> TryPipeline<I>
+where
+    I: Iterator<Item = Result<Record>>,
+{
+    /// Transforms each record with a function that can fail.
+    pub fn try_map<F>(self, mut transform: F) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(Record) -> Result<Record>,
+    {
+        TryPipeline {
+            iter: self.iter.map(move |item| item.and_then(&mut transform)),
+        }
+    }
+
+    /// Filters records with a predicate that can fail.
+    pub fn try_filter<F>(
+        self,
+        mut predicate: F,
+    ) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(&Record) -> Result<bool>,
+    {
+        TryPipeline {
+            iter: self.iter.filter_map(move |item| match item {
+                Ok(record) => match predicate(&record) {
+                    Ok(true) => Some(Ok(record)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                },
+                Err(e) => Some(Err(e)),
+            }),
+        }
+    }
+
+    /// Transforms each record.
+    pub fn map<F>(self, mut transform: F) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(Record) -> Record,
+    {
+        TryPipeline {
+            iter: self.iter.map(move |item| item.map(&mut transform)),
+        }
+    }
+
+    /// Filters records using a predicate.
+    pub fn filter<F>(self, mut predicate: F) -> TryPipeline<impl Iterator<Item = Result<Record>>>
+    where
+        F: FnMut(&Record) -> bool,
+    {
+        TryPipeline {
+            iter: self
+                .iter
+                .filter(move |item| item.as_ref().map_or(true, &mut predicate)),
+        }
+    }
+
+    /// Collects all records, or returns the first error.
+    ///
+    /// Stops reading at the first error, so no record after it is
+    /// processed.
+    pub fn try_collect(self) -> Result<Vec<Record>> {
+        self.iter.collect()
+    }
+
+    /// Passes each error to `handler` and continues with the remaining
+    /// records, e.g. to write failures to an error file.
+    pub fn on_error<F>(self, mut handler: F) -> Pipeline<impl Iterator<Item = Record>>
+    where
+        F: FnMut(PipelineError),
+    {
+        Pipeline {
+            iter: self
+                .iter
+                .filter_map(move |item| item.map_err(&mut handler).ok()),
+        }
+    }
+}
+
+impl<I> Iterator for TryPipeline<I>
+where
+    I: Iterator<Item = Result<Record>>,
+{
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::join::JoinField;
 
     fn sample_records() -> Vec<Record> {
         // Layout: Last(8) First(10) Dept(10) Salary(8)
@@ -493,6 +1617,19 @@ mod tests {
         assert_eq!(result.len(), 2);
     }
 
+    #[test]
+    fn test_partition() {
+        let (sales, others) =
+            Pipeline::new(sample_records().into_iter()).partition(|r| r.field_eq(18, 10, "SALES"));
+        let sales = sales.collect();
+        let others = others.collect();
+
+        assert_eq!(sales.len(), 2);
+        assert_eq!(others.len(), 2);
+        assert!(sales[1].field_eq(0, 8, "DOE"));
+        assert!(others[0].field_eq(0, 8, "JONES"));
+    }
+
     #[test]
     fn test_omit() {
         let result: Vec<_> = Pipeline::new(sample_records().into_iter())
@@ -522,6 +1659,86 @@ mod tests {
         assert_eq!(result[0].field(8, 8), "00050000");
     }
 
+    #[test]
+    fn test_overlay() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .overlay(vec![(28, 8, 72)])
+            .stage(Overlay::new(vec![]).literal(18, "DEPT"))
+            .collect();
+
+        assert_eq!(result[0].field(0, 8).trim(), "SMITH");
+        assert_eq!(result[0].field(18, 10), "DEPTS     ");
+        assert_eq!(result[0].field(28, 8), "00050000");
+        assert_eq!(result[0].field(72, 8), "00050000");
+    }
+
+    #[test]
+    fn test_try_collect_stops_at_first_error() {
+        let mut seen = 0;
+        let result = Pipeline::new(sample_records().into_iter())
+            .inspect(|_| seen += 1)
+            .try_map(|r| {
+                if r.field_eq(0, 8, "JONES") {
+                    Err(PipelineError::Stage("no JONES".to_string()))
+                } else {
+                    Ok(r)
+                }
+            })
+            .try_collect();
+
+        assert_eq!(result.unwrap_err().to_string(), "stage error: no JONES");
+        assert_eq!(seen, 2);
+
+        let ok = Pipeline::new(sample_records().into_iter())
+            .try_map(Ok)
+            .filter(|r| r.field_eq(18, 10, "SALES"))
+            .map(|r| r.with_lrecl(40))
+            .try_collect()
+            .unwrap();
+        assert_eq!(ok.len(), 2);
+        assert_eq!(ok[0].lrecl(), 40);
+    }
+
+    #[test]
+    fn test_try_filter_on_error() {
+        let mut errors = Vec::new();
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .try_filter(|r| Ok(r.field_num::<u32>(28, 8)? >= 55000))
+            .try_map(|r| {
+                if r.field_eq(0, 8, "WILSON") {
+                    Err(PipelineError::Stage("WILSON".to_string()))
+                } else {
+                    Ok(r)
+                }
+            })
+            .on_error(|e| errors.push(e.to_string()))
+            .collect();
+
+        // JONES and DOE pass; WILSON fails after the filter
+        assert_eq!(result.len(), 2);
+        assert_eq!(errors, ["stage error: WILSON"]);
+
+        let items: Vec<_> = Pipeline::new(vec![Record::from_str("X")].into_iter())
+            .try_filter(|_| Err(PipelineError::Stage("bad".to_string())))
+            .map(|r| r)
+            .collect();
+        assert!(items[0].is_err());
+    }
+
+    #[test]
+    fn test_from_lines_strict() {
+        let lines = ["SMITH", "JOS\u{00C9}", "DOE"];
+        let mut errors = Vec::new();
+        let records: Vec<_> = from_lines_strict(lines.iter())
+            .on_error(|e| errors.push(e.to_string()))
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(errors, ["non-ASCII byte 0xC3 at offset 3"]);
+
+        let records = from_lines_strict(["A", "B"].iter()).try_collect().unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
     #[test]
     fn test_chain() {
         let records1 = vec![Record::from_str("ONE")];
@@ -546,6 +1763,30 @@ mod tests {
         assert!(result[1].field_eq(0, 8, "DOE"));
     }
 
+    #[test]
+    fn test_take_last_drop_last() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .drop_last(1)
+            .take_last(2)
+            .collect();
+
+        assert_eq!(result.len(), 2);
+        assert!(result[0].field_eq(0, 8, "JONES"));
+        assert!(result[1].field_eq(0, 8, "DOE"));
+        assert_eq!(
+            Pipeline::new(sample_records().into_iter())
+                .take_last(0)
+                .count(),
+            0
+        );
+        assert_eq!(
+            Pipeline::new(sample_records().into_iter())
+                .drop_last(9)
+                .count(),
+            0
+        );
+    }
+
     #[test]
     fn test_count() {
         let count = Pipeline::new(sample_records().into_iter())
@@ -558,7 +1799,7 @@ mod tests {
     #[test]
     fn test_fold() {
         let total: u64 = Pipeline::new(sample_records().into_iter()).fold(0u64, |acc, r| {
-            let salary: u64 = r.field(28, 8).trim().parse().unwrap_or(0);
+            let salary: u64 = r.field_num(28, 8).unwrap();
             acc + salary
         });
 
@@ -576,6 +1817,21 @@ mod tests {
         assert!(!all_sales);
     }
 
+    #[test]
+    fn test_sort_external() {
+        let keys = vec![SortKey::new(18, 10), SortKey::new(28, 8).numeric()];
+        let expected: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .sort(keys.clone())
+            .collect();
+        // One record per run
+        let sorted = Pipeline::new(sample_records().into_iter())
+            .sort_external(keys, 1)
+            .unwrap()
+            .try_collect()
+            .unwrap();
+        assert_eq!(sorted, expected);
+    }
+
     #[test]
     fn test_first_last() {
         let first = Pipeline::new(sample_records().into_iter()).first();
@@ -616,4 +1872,158 @@ mod tests {
         let count = pipeline.count();
         assert_eq!(count, 3); // Remaining 3 records
     }
+
+    #[test]
+    fn test_sort() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .sort(vec![SortKey::new(28, 8).numeric().descending()])
+            .collect();
+
+        let names: Vec<_> = result.iter().map(|r| r.field(0, 8).trim()).collect();
+        assert_eq!(names, ["JONES", "DOE", "WILSON", "SMITH"]);
+    }
+
+    #[test]
+    fn test_sort_is_stable() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .sort(vec![SortKey::new(18, 10)])
+            .collect();
+
+        let names: Vec<_> = result.iter().map(|r| r.field(0, 8).trim()).collect();
+        assert_eq!(names, ["JONES", "WILSON", "SMITH", "DOE"]);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .dedup(vec![(18, 10)])
+            .collect();
+
+        assert_eq!(result.len(), 3);
+        assert!(result[0].field_eq(0, 8, "SMITH"));
+        assert!(result[1].field_eq(0, 8, "JONES"));
+        assert!(result[2].field_eq(0, 8, "WILSON"));
+    }
+
+    #[test]
+    fn test_dedup_with_keep_last() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .dedup_with(Dedup::new(vec![(18, 10)]).keep_last())
+            .collect();
+
+        let names: Vec<_> = result.iter().map(|r| r.field(0, 8).trim()).collect();
+        assert_eq!(names, ["JONES", "DOE", "WILSON"]);
+    }
+
+    #[test]
+    fn test_sum_by() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .sum_by(vec![(18, 10)], vec![SumField::new(28, 8)])
+            .collect();
+
+        let totals: Vec<_> = result.iter().map(|r| r.field(18, 18)).collect();
+        assert_eq!(
+            totals,
+            [
+                "SALES     00110000",
+                "ENGINEER  00075000",
+                "MARKETING 00055000"
+            ]
+        );
+        assert!(result[0].field_eq(0, 8, "SMITH"));
+    }
+
+    #[test]
+    fn test_group_by() {
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .group_by(vec![(18, 10)])
+            .aggregate(vec![
+                Aggregate::count().at(11, 1),
+                Aggregate::sum(28, 8),
+                Aggregate::max(28, 8),
+            ])
+            .collect();
+
+        let lines: Vec<_> = result.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(
+            lines,
+            [
+                "SALES      2 00110000 00060000",
+                "ENGINEER   1 00075000 00075000",
+                "MARKETING  1 00055000 00055000"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_join() {
+        let salaries = vec![
+            Record::from_str("DOE     00060000"),
+            Record::from_str("SMITH   00050000"),
+        ];
+        let result: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .join(
+                salaries,
+                Join::new(vec![(0, 8)], vec![(0, 8)])
+                    .layout(vec![JoinField::Left(0, 18), JoinField::Right(8, 8)]),
+            )
+            .collect();
+
+        let lines: Vec<_> = result.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(
+            lines,
+            ["SMITH   JOHN      00050000", "DOE     JANE      00060000"]
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut depts = Lookup::new(18, 10).unmatched(crate::Unmatched::Error);
+        depts.insert("SALES", "SLS");
+        depts.insert("ENGINEER", "ENG");
+
+        let err = Pipeline::new(sample_records().into_iter())
+            .lookup(depts)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("'MARKETING'"));
+    }
+
+    #[test]
+    fn test_tee() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut all = Vec::new();
+        let sales: Vec<_> = Pipeline::new(sample_records().into_iter())
+            .tee(&mut all)
+            .filter(|r| r.field_eq(18, 10, "SALES"))
+            .tee(tx)
+            .collect();
+
+        assert_eq!(all.len(), 4);
+        assert_eq!(rx.iter().collect::<Vec<_>>(), sales);
+    }
+
+    #[test]
+    fn test_header_and_trailer() {
+        let mut records = vec![Record::from_str("HEADER")];
+        records.extend(sample_records());
+        records.push(Record::from_str("TRAILER 000004 000240000"));
+        let trailer = || {
+            Trailer::new()
+                .count(8, 6)
+                .hash_total(SumField::new(28, 8), 15, 9)
+        };
+
+        let details: Vec<_> = Pipeline::new(records.clone().into_iter())
+            .skip_header(1)
+            .with_trailer_validation(trailer())
+            .unwrap()
+            .collect();
+        assert_eq!(details, sample_records());
+
+        let result = Pipeline::new(records.into_iter())
+            .skip_header(2)
+            .with_trailer_validation(trailer());
+        assert!(matches!(result, Err(crate::PipelineError::Stage(_))));
+    }
 }
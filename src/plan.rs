@@ -0,0 +1,520 @@
+//! Pipeline plans.
+//!
+//! A [`PipelinePlan`] is the parsed form of a pipeline: a source stage, the
+//! stages after it as typed [`Command`]s, and the record length. The DSL
+//! parser produces one, and both the batch executor
+//! ([`crate::dsl::execute_plan`]) and the record-at-a-time executor in
+//! `naive-pipe` run one, so a plan means the same thing to both.
+//!
+//! Plans can also be built in code and inspected or printed as DSL text.
+//!
+//! A stage may carry a label (`a: LOCATE /X/`). After the main pipeline, a
+//! [`Segment`] starting with `? a:` reads the records that stage drops, its
+//! secondary output in CMS Pipelines terms, so rejected records can be
+//! routed to stages of their own. A segment may end with a label alone
+//! (`| f:`) to feed its records into that stage's secondary input, where
+//! FANIN or FANINANY joins them back into the pipeline.
+//!
+//! A segment may instead start with a source of its own (`? < TRANS`),
+//! reading a second input the host supplies by name (see
+//! [`infiles`](crate::infiles)), so one pipeline
+//! can merge a master file with a transaction file.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Command, HostVars, PipelinePlan, execute_plan};
+//!
+//! let plan = PipelinePlan::new(Command::Console)
+//!     .stage(Command::Upper)
+//!     .stage(Command::Console);
+//!
+//! assert_eq!(plan.to_string(), "PIPE CONSOLE\n| UPPER\n| CONSOLE\n?");
+//! assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+//!
+//! let run = execute_plan(&plan, "smith", &None, &HostVars::new()).unwrap();
+//! assert_eq!(run.output, "SMITH");
+//! ```
+
+use std::fmt;
+
+use crate::condcode::ConditionCodes;
+use crate::dsl::{Command, parse_pipeline};
+use crate::{MAX_LRECL, RECORD_WIDTH};
+
+/// A parsed pipeline: a source stage, the stages after it, and the record
+/// length, plus any stage labels, secondary segments, and condition code
+/// thresholds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelinePlan {
+    commands: Vec<Command>,
+    labels: Vec<(usize, String)>,
+    segments: Vec<Segment>,
+    lrecl: usize,
+    condition_codes: ConditionCodes,
+}
+
+/// A pipeline segment after `?` that reads the records a labeled stage
+/// drops (its secondary output), or a named input, instead of the main
+/// pipeline's source.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Command, HostVars, PipelinePlan, Segment, execute_plan};
+///
+/// let plan = PipelinePlan::new(Command::Console)
+///     .stage(Command::Take { n: 1 })
+///     .label("rest")
+///     .stage(Command::Console)
+///     .segment(Segment::new("rest").stage(Command::Upper).stage(Command::Console));
+///
+/// assert_eq!(
+///     plan.to_string(),
+///     "PIPE CONSOLE\n| rest: TAKE 1\n| CONSOLE\n? rest:\n| UPPER\n| CONSOLE\n?"
+/// );
+/// let run = execute_plan(&plan, "a\nb\nc", &None, &HostVars::new()).unwrap();
+/// assert_eq!(run.output, "a\nB\nC");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    input: String,
+    file: Option<String>,
+    commands: Vec<Command>,
+    labels: Vec<(usize, String)>,
+    target: Option<String>,
+}
+
+impl Segment {
+    /// Starts a segment reading the secondary output of the stage labeled
+    /// `input`.
+    pub fn new(input: impl Into<String>) -> Self {
+        Self {
+            input: input.into(),
+            file: None,
+            commands: Vec::new(),
+            labels: Vec::new(),
+            target: None,
+        }
+    }
+
+    /// Starts a segment reading the input named `name` (`? < name`), which
+    /// the host opens with a [`SideReader`](crate::infiles::SideReader).
+    pub fn from_file(name: impl Into<String>) -> Self {
+        Self {
+            file: Some(name.into()),
+            ..Self::new(String::new())
+        }
+    }
+
+    /// Appends a stage.
+    pub fn stage(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Ends the segment at the stage labeled `target`, feeding its records
+    /// into that stage's secondary input (FANIN or FANINANY) instead of
+    /// the output.
+    pub fn to(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Labels the last stage added, so a later segment can read the
+    /// records it drops.
+    pub fn label(mut self, name: impl Into<String>) -> Self {
+        self.labels
+            .push((self.commands.len().saturating_sub(1), name.into()));
+        self
+    }
+
+    /// Returns the label of the stage this segment reads from, or an empty
+    /// string if it reads a named input.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns the name of the input this segment reads, if it starts with
+    /// `< name` instead of a label.
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    /// Returns the segment's stages.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Returns the label of the stage this segment feeds, if it ends in
+    /// one.
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Returns the label of the stage at `index`, if it has one.
+    pub fn label_of(&self, index: usize) -> Option<&str> {
+        label_of(&self.labels, index)
+    }
+
+    pub(crate) fn push(&mut self, command: Command, label: Option<String>) {
+        if let Some(label) = label {
+            self.labels.push((self.commands.len(), label));
+        }
+        self.commands.push(command);
+    }
+}
+
+impl PipelinePlan {
+    /// Starts a plan reading from `source` (CONSOLE, LITERAL, HOLE, or `<`), with
+    /// 80-byte records.
+    pub fn new(source: Command) -> Self {
+        Self {
+            commands: vec![source],
+            ..Self::empty()
+        }
+    }
+
+    /// A plan with no stages yet, for the parser to fill in.
+    pub(crate) fn empty() -> Self {
+        Self {
+            commands: Vec::new(),
+            labels: Vec::new(),
+            segments: Vec::new(),
+            lrecl: RECORD_WIDTH,
+            condition_codes: ConditionCodes::default(),
+        }
+    }
+
+    /// Appends a stage.
+    pub fn stage(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Labels the last stage added, so a [`Segment`] can read the records
+    /// it drops.
+    pub fn label(mut self, name: impl Into<String>) -> Self {
+        self.labels
+            .push((self.commands.len().saturating_sub(1), name.into()));
+        self
+    }
+
+    /// Appends a segment reading a labeled stage's secondary output.
+    pub fn segment(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Sets the record length.
+    pub fn with_lrecl(mut self, lrecl: usize) -> Self {
+        self.lrecl = lrecl;
+        self
+    }
+
+    /// Sets the thresholds for the run's condition code.
+    pub fn with_condition_codes(mut self, codes: ConditionCodes) -> Self {
+        self.condition_codes = codes;
+        self
+    }
+
+    /// Parses DSL text into a plan and validates it.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let plan = parse_pipeline(text)?;
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// Checks the plan's shape: a source stage first (and `<` nowhere
+    /// else), at least one stage after it, a record length from 1 to
+    /// 32760, and well-formed labels and segments.
+    ///
+    /// Any stage can be last; if it is not a sink, its output is discarded.
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_main()?;
+        self.validate_segments()
+    }
+
+    fn validate_main(&self) -> Result<(), String> {
+        let Some(first) = self.commands.first() else {
+            return Err("Pipeline is empty".to_string());
+        };
+        if self.commands.len() < 2 {
+            return Err("Pipeline must have at least 2 stages".to_string());
+        }
+        if !first.can_be_first() {
+            return Err(format!(
+                "{} cannot be the first stage (try CONSOLE, LITERAL, HOLE, or <)",
+                first.name()
+            ));
+        }
+        if let Some(cmd) = self
+            .stages()
+            .iter()
+            .find(|cmd| matches!(cmd, Command::FileIn { .. } | Command::FromCsv { .. }))
+        {
+            return Err(format!("{} can only be the first stage", cmd.name()));
+        }
+        if !(1..=MAX_LRECL).contains(&self.lrecl) {
+            return Err(format!(
+                "LRECL requires a record length from 1 to {MAX_LRECL}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks labels and segments: labels are unique names, and each
+    /// segment reads a named input or the unused secondary output of a
+    /// stage labeled before it, and either has stages or feeds a stage with
+    /// a secondary input.
+    fn validate_segments(&self) -> Result<(), String> {
+        let mut defined = Vec::new();
+        define_labels(&mut defined, &self.labels, &self.commands)?;
+
+        let mut connected: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            let input = segment.input.as_str();
+            let name = match &segment.file {
+                Some(file) => format!("< {file}"),
+                None => {
+                    let Some((_, command)) = defined.iter().find(|(n, _)| *n == input) else {
+                        return Err(format!("? {input}: names no labeled stage before it"));
+                    };
+                    if !command.has_secondary_output() {
+                        return Err(format!(
+                            "{input}: {} has no secondary output",
+                            command.name()
+                        ));
+                    }
+                    if connected.contains(&input) {
+                        return Err(format!("{input}: secondary output is already connected"));
+                    }
+                    connected.push(input);
+                    format!("{input}:")
+                }
+            };
+            if segment.commands.is_empty() && segment.target.is_none() {
+                return Err(format!("? {name} needs at least one stage"));
+            }
+            for command in &segment.commands {
+                match command {
+                    Command::FileIn { .. } | Command::FromCsv { .. } => {
+                        return Err(format!("{} can only be the first stage", command.name()));
+                    }
+                    Command::Convert { .. } => {
+                        return Err("CONVERT can only be in the main pipeline".to_string());
+                    }
+                    _ => {}
+                }
+            }
+            define_labels(&mut defined, &segment.labels, &segment.commands)?;
+        }
+
+        // A segment can feed a stage labeled anywhere, even after it
+        for target in self.segments.iter().filter_map(Segment::target) {
+            let Some((_, command)) = defined.iter().find(|(n, _)| *n == target) else {
+                return Err(format!("{target}: names no labeled stage"));
+            };
+            if !command.has_secondary_input() {
+                return Err(format!(
+                    "{target}: {} has no secondary input",
+                    command.name()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns every stage, source first.
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Returns the source stage.
+    pub fn source(&self) -> &Command {
+        &self.commands[0]
+    }
+
+    /// Returns the stages after the source.
+    pub fn stages(&self) -> &[Command] {
+        &self.commands[1..]
+    }
+
+    /// Returns the record length.
+    pub fn lrecl(&self) -> usize {
+        self.lrecl
+    }
+
+    /// Returns the thresholds for the run's condition code, from the `RC`
+    /// line (see [`crate::condcode`]).
+    pub fn condition_codes(&self) -> &ConditionCodes {
+        &self.condition_codes
+    }
+
+    /// Returns the label of the main pipeline's stage at `index` (the
+    /// source is 0), if it has one.
+    pub fn label_of(&self, index: usize) -> Option<&str> {
+        label_of(&self.labels, index)
+    }
+
+    /// Returns the segments after the main pipeline.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns every stage of the main pipeline and then of each segment.
+    pub fn all_commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands
+            .iter()
+            .chain(self.segments.iter().flat_map(|s| s.commands.iter()))
+    }
+
+    /// Adds a stage to the main pipeline, or to the last segment if there
+    /// is one.
+    pub(crate) fn push(&mut self, command: Command, label: Option<String>) {
+        match self.segments.last_mut() {
+            Some(segment) => segment.push(command, label),
+            None => {
+                if let Some(label) = label {
+                    self.labels.push((self.commands.len(), label));
+                }
+                self.commands.push(command);
+            }
+        }
+    }
+
+    /// Starts a segment reading the secondary output of `input`.
+    pub(crate) fn push_segment(&mut self, input: String) {
+        self.segments.push(Segment::new(input));
+    }
+
+    /// Starts a segment reading the input named `name`.
+    pub(crate) fn push_file_segment(&mut self, name: String) {
+        self.segments.push(Segment::from_file(name));
+    }
+
+    /// Ends the last segment at the stage labeled `target`; returns false
+    /// if there is no segment to end.
+    pub(crate) fn end_segment(&mut self, target: String) -> bool {
+        match self.segments.last_mut() {
+            Some(segment) => {
+                segment.target = Some(target);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true if the last segment already ends in a label.
+    pub(crate) fn segment_ended(&self) -> bool {
+        self.segments.last().is_some_and(|s| s.target.is_some())
+    }
+
+    /// Returns every stage, source first, consuming the plan.
+    pub fn into_commands(self) -> Vec<Command> {
+        self.commands
+    }
+}
+
+/// Prints the plan as DSL text that parses back to the same plan.
+impl fmt::Display for PipelinePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lrecl != RECORD_WIDTH {
+            writeln!(f, "LRECL {}", self.lrecl)?;
+        }
+        if self.condition_codes != ConditionCodes::default() {
+            writeln!(f, "{}", self.condition_codes)?;
+        }
+        for (i, command) in self.commands.iter().enumerate() {
+            let prefix = if i == 0 { "PIPE" } else { "|" };
+            write!(f, "{prefix} ")?;
+            if let Some(label) = self.label_of(i) {
+                write!(f, "{label}: ")?;
+            }
+            writeln!(f, "{command}")?;
+        }
+        for segment in &self.segments {
+            match &segment.file {
+                Some(file) => writeln!(f, "? < {file}")?,
+                None => writeln!(f, "? {}:", segment.input)?,
+            }
+            for (i, command) in segment.commands.iter().enumerate() {
+                write!(f, "| ")?;
+                if let Some(label) = segment.label_of(i) {
+                    write!(f, "{label}: ")?;
+                }
+                writeln!(f, "{command}")?;
+            }
+            if let Some(target) = &segment.target {
+                writeln!(f, "| {target}:")?;
+            }
+        }
+        write!(f, "?")
+    }
+}
+
+/// Returns true if `name` can be a stage label: letters, digits, and `_`,
+/// starting with a letter.
+pub(crate) fn is_label(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Adds `labels` on `commands` to the labels `defined` so far, checking
+/// that each is a valid, new name on a stage.
+fn define_labels<'a>(
+    defined: &mut Vec<(&'a str, &'a Command)>,
+    labels: &'a [(usize, String)],
+    commands: &'a [Command],
+) -> Result<(), String> {
+    for (index, name) in labels {
+        if !is_label(name) {
+            return Err(format!(
+                "Invalid label '{name}:' (use letters, digits, and _)"
+            ));
+        }
+        if defined.iter().any(|(n, _)| n == name) {
+            return Err(format!("Label '{name}:' is defined more than once"));
+        }
+        let command = commands
+            .get(*index)
+            .ok_or_else(|| format!("Label '{name}:' needs a stage"))?;
+        defined.push((name, command));
+    }
+    Ok(())
+}
+
+fn label_of(labels: &[(usize, String)], index: usize) -> Option<&str> {
+    labels
+        .iter()
+        .find(|(i, _)| *i == index)
+        .map(|(_, name)| name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plan() {
+        let plan = PipelinePlan::parse("LRECL 100\nPIPE CONSOLE | TAKE 2 | CONSOLE ?").unwrap();
+        assert_eq!(plan.lrecl(), 100);
+        assert_eq!(plan.source().name(), "CONSOLE");
+        assert_eq!(plan.stages().len(), 2);
+        assert_eq!(plan.stages()[0].name(), "TAKE");
+        assert_eq!(PipelinePlan::parse(&plan.to_string()).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_validate_plan() {
+        let error = |plan: PipelinePlan| plan.validate().unwrap_err();
+        assert!(error(PipelinePlan::new(Command::Console)).contains("at least 2"));
+        assert!(
+            error(PipelinePlan::new(Command::Upper).stage(Command::Console))
+                .contains("first stage")
+        );
+        let plan = PipelinePlan::new(Command::Hole).stage(Command::Console);
+        assert!(plan.validate().is_ok());
+        assert!(error(plan.with_lrecl(0)).contains("LRECL"));
+    }
+}
@@ -0,0 +1,246 @@
+//! Custom stages provided at runtime.
+//!
+//! Site-specific logic does not belong in the parser or the executors.
+//! Instead, code registers a named stage factory here and pipelines call it
+//! with `PLUGIN name [args]`. The factory receives the argument text and
+//! returns a [`Stage`], or an error message if the arguments are invalid.
+//!
+//! Stages can be registered directly with [`register`], or (with the
+//! `plugins` feature) loaded from a shared library with [`load_library`].
+//! A plugin library is an ordinary crate built as a `cdylib` that depends
+//! on `pipelines-rs` and exports its stages with [`declare_plugin!`]; see
+//! `examples/rot13_plugin.rs`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Record, Reformat, execute_pipeline, plugin};
+//!
+//! plugin::register("SHOUT", |args| {
+//!     let suffix = args.to_string();
+//!     Ok(Box::new(Reformat::new(move |r: &Record| {
+//!         Record::from_str(&format!("{}{suffix}", r.as_str().trim_end()))
+//!     })))
+//! });
+//!
+//! let (output, _, _) = execute_pipeline("hello", "PIPE CONSOLE | PLUGIN shout ! | CONSOLE").unwrap();
+//! assert_eq!(output, "hello!");
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::Command;
+use crate::error::{PipelineError, Result};
+use crate::stage::Stage;
+
+/// A stage created by a plugin factory.
+pub type PluginStage = Box<dyn Stage + Send>;
+
+/// Builds a stage from the `PLUGIN name` argument text.
+pub type StageFactory = dyn Fn(&str) -> std::result::Result<PluginStage, String> + Send + Sync;
+
+/// Plugin library interface version; a library built against a different
+/// version is refused by [`load_library`].
+pub const PLUGIN_API_VERSION: u32 = 1;
+
+fn registry() -> &'static Mutex<BTreeMap<String, Arc<StageFactory>>> {
+    static REGISTRY: OnceLock<Mutex<BTreeMap<String, Arc<StageFactory>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Registers a stage factory under `name` (case-insensitive), replacing
+/// any earlier registration of the same name.
+pub fn register<F>(name: &str, factory: F)
+where
+    F: Fn(&str) -> std::result::Result<PluginStage, String> + Send + Sync + 'static,
+{
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(name.to_uppercase(), Arc::new(factory));
+}
+
+/// Returns the names of all registered plugin stages, sorted.
+pub fn registered() -> Vec<String> {
+    registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .keys()
+        .cloned()
+        .collect()
+}
+
+/// Creates a stage from the plugin registered as `name`.
+pub fn create(name: &str, args: &str) -> Result<PluginStage> {
+    // Clone the factory out so it can run without holding the lock
+    let factory = registry()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&name.to_uppercase())
+        .cloned()
+        .ok_or_else(|| PipelineError::Plugin(format!("no plugin stage named {name}")))?;
+    factory(args).map_err(|e| PipelineError::Plugin(format!("{name}: {e}")))
+}
+
+/// Checks that every `PLUGIN` command names a registered stage and that
+/// its arguments are accepted.
+pub fn validate(commands: &[Command]) -> std::result::Result<(), String> {
+    for cmd in commands {
+        if let Command::Plugin { name, args } = cmd {
+            create(name, args).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Passed to a plugin library's registration function.
+///
+/// A library links its own copy of this crate, with its own registry, so
+/// it hands its factories back through the registrar instead of calling
+/// [`register`].
+#[derive(Default)]
+pub struct PluginRegistrar {
+    factories: Vec<(String, Arc<StageFactory>)>,
+}
+
+impl PluginRegistrar {
+    /// Adds a stage factory to be registered by the loading program.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&str) -> std::result::Result<PluginStage, String> + Send + Sync + 'static,
+    {
+        self.factories
+            .push((name.to_uppercase(), Arc::new(factory)));
+    }
+
+    /// Registers the collected factories, returning their names.
+    #[cfg(any(feature = "plugins", test))]
+    fn install(self) -> Vec<String> {
+        let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+        self.factories
+            .into_iter()
+            .map(|(name, factory)| {
+                registry.insert(name.clone(), factory);
+                name
+            })
+            .collect()
+    }
+}
+
+/// Exports a plugin library's registration function.
+///
+/// The plugin crate must be built as a `cdylib` with the same compiler and
+/// `pipelines-rs` version as the program that loads it.
+///
+/// ```ignore
+/// fn register(registrar: &mut PluginRegistrar) {
+///     registrar.register("ROT13", |_args| Ok(Box::new(Rot13)));
+/// }
+///
+/// pipelines_rs::declare_plugin!(register);
+/// ```
+#[macro_export]
+macro_rules! declare_plugin {
+    ($register:path) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn pipelines_plugin_api_version() -> u32 {
+            $crate::plugin::PLUGIN_API_VERSION
+        }
+
+        #[unsafe(no_mangle)]
+        pub fn pipelines_plugin_register(registrar: &mut $crate::plugin::PluginRegistrar) {
+            $register(registrar);
+        }
+    };
+}
+
+/// Loads a plugin library and registers its stages. Returns the names of
+/// the stages it registered.
+///
+/// The library stays loaded for the life of the process, since its stages
+/// may be in use anywhere.
+#[cfg(feature = "plugins")]
+pub fn load_library(path: impl AsRef<std::path::Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let error = |e: libloading::Error| PipelineError::Plugin(format!("{}: {e}", path.display()));
+
+    // SAFETY: loading runs the library's initializers; plugin libraries are
+    // trusted code, like any other dependency.
+    // (The loader's message already names the file.)
+    let library = unsafe { libloading::Library::new(path) }
+        .map_err(|e| PipelineError::Plugin(e.to_string()))?;
+
+    // SAFETY: the symbols are declared by `declare_plugin!` with these types.
+    let version = unsafe {
+        library
+            .get::<extern "C" fn() -> u32>(b"pipelines_plugin_api_version")
+            .map_err(error)?()
+    };
+    if version != PLUGIN_API_VERSION {
+        return Err(PipelineError::Plugin(format!(
+            "{}: plugin API version {version}, expected {PLUGIN_API_VERSION}",
+            path.display()
+        )));
+    }
+
+    let mut registrar = PluginRegistrar::default();
+    // SAFETY: as above.
+    unsafe {
+        library
+            .get::<fn(&mut PluginRegistrar)>(b"pipelines_plugin_register")
+            .map_err(error)?(&mut registrar);
+    }
+
+    // Registered factories point into the library's code
+    std::mem::forget(library);
+    Ok(registrar.install())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Filter, Record};
+
+    #[test]
+    fn test_register_and_create() {
+        register("keep-prefix", |args| {
+            if args.is_empty() {
+                return Err("requires a prefix".to_string());
+            }
+            let prefix = args.to_string();
+            Ok(Box::new(Filter::new(move |r: &Record| {
+                r.as_str().starts_with(&prefix)
+            })))
+        });
+        assert!(registered().contains(&"KEEP-PREFIX".to_string()));
+
+        let mut stage = create("Keep-Prefix", "AB").unwrap();
+        let records = vec![Record::from_str("ABC"), Record::from_str("XYZ")];
+        assert_eq!(stage.process_batch(records), [Record::from_str("ABC")]);
+
+        let err = create("keep-prefix", "").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "plugin error: keep-prefix: requires a prefix"
+        );
+    }
+
+    #[test]
+    fn test_unknown_plugin() {
+        let err = create("no-such-stage", "").err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("no plugin stage named no-such-stage")
+        );
+    }
+
+    #[test]
+    fn test_registrar_installs_factories() {
+        let mut registrar = PluginRegistrar::default();
+        registrar.register("tally", |_| Ok(Box::new(Filter::new(|_: &Record| true))));
+        assert!(create("TALLY", "").is_err());
+        assert_eq!(registrar.install(), ["TALLY"]);
+        assert!(create("TALLY", "").is_ok());
+    }
+}
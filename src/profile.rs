@@ -0,0 +1,288 @@
+//! Data-quality profiling.
+//!
+//! A [`ProfileStage`] consumes records and accumulates statistics for each
+//! field of a [`RecordLayout`]: how often it is blank, how many distinct
+//! values it holds, its minimum and maximum, and (for numeric fields) how
+//! many values are not numeric. At flush it emits a report, one line per
+//! field, so the data can be inspected before writing filters against it.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{FieldType, ProfileStage, Record, RecordLayout, Stage};
+//!
+//! let mut layout = RecordLayout::new();
+//! layout.add("DEPT", 18, 10, FieldType::Char).unwrap();
+//! layout.add("SALARY", 28, 8, FieldType::Numeric).unwrap();
+//!
+//! let mut profile = ProfileStage::new(layout);
+//! profile.process(Record::from_str("SMITH   JOHN      SALES     00050000"));
+//! profile.process(Record::from_str("JONES   MARY      ENGINEER  N/A"));
+//!
+//! let report = profile.flush();
+//! assert!(report[0].as_str().starts_with("PROFILE 2 RECORDS"));
+//! assert!(report[3].as_str().starts_with("SALARY"));
+//! assert!(report[3].as_str().trim_end().ends_with('1')); // one non-numeric value
+//! ```
+
+use std::collections::HashSet;
+
+use crate::infer::{infer_layout, is_numeric};
+use crate::layout::{FieldType, LayoutField, RecordLayout};
+use crate::stage::Stage;
+use crate::{RECORD_WIDTH, Record};
+
+/// Longest min/max value shown in the report.
+const VALUE_WIDTH: usize = 12;
+
+/// Statistics accumulated for one field.
+#[derive(Debug, Clone, Default)]
+struct FieldStats {
+    blank: usize,
+    distinct: HashSet<String>,
+    min: Option<String>,
+    max: Option<String>,
+    non_numeric: usize,
+}
+
+impl FieldStats {
+    fn add(&mut self, field: &LayoutField, value: &str) {
+        let value = value.trim();
+        if value.is_empty() {
+            self.blank += 1;
+            return;
+        }
+        let numeric = field.field_type == FieldType::Numeric;
+        if numeric && !is_numeric(value) {
+            self.non_numeric += 1;
+        }
+        let less = |a: &str, b: &str| match (numeric, a.parse::<f64>(), b.parse::<f64>()) {
+            (true, Ok(x), Ok(y)) => x < y,
+            _ => a < b,
+        };
+        if self.min.as_deref().is_none_or(|m| less(value, m)) {
+            self.min = Some(value.to_string());
+        }
+        if self.max.as_deref().is_none_or(|m| less(m, value)) {
+            self.max = Some(value.to_string());
+        }
+        if !self.distinct.contains(value) {
+            self.distinct.insert(value.to_string());
+        }
+    }
+}
+
+/// Profiles fields of the records passing through and reports at flush.
+///
+/// `process` consumes every record; `flush` returns the report as records.
+/// With [`ProfileStage::inferred`], records are buffered and the layout is
+/// inferred from all of them at flush.
+pub struct ProfileStage {
+    layout: Option<RecordLayout>,
+    stats: Vec<FieldStats>,
+    buffered: Vec<Record>,
+    count: usize,
+}
+
+impl ProfileStage {
+    /// Creates a profile of the fields in `layout`.
+    pub fn new(layout: RecordLayout) -> Self {
+        let stats = vec![FieldStats::default(); layout.fields().len()];
+        Self {
+            layout: Some(layout),
+            stats,
+            buffered: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Creates a profile whose layout is inferred from the input at flush.
+    pub fn inferred() -> Self {
+        Self {
+            layout: None,
+            stats: Vec::new(),
+            buffered: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Creates a profile of unnamed `(pos, len, type)` fields, named
+    /// `FIELD1`, `FIELD2`, ... in order. With no fields the layout is
+    /// inferred, as with [`ProfileStage::inferred`].
+    ///
+    /// Fields must lie within the record (the DSL checks this when parsing).
+    pub fn from_fields(fields: &[(usize, usize, FieldType)]) -> Self {
+        if fields.is_empty() {
+            return Self::inferred();
+        }
+        let lrecl = fields
+            .iter()
+            .map(|&(pos, len, _)| pos + len)
+            .fold(RECORD_WIDTH, usize::max);
+        let mut layout = RecordLayout::with_lrecl(lrecl);
+        for (idx, &(pos, len, field_type)) in fields.iter().enumerate() {
+            layout
+                .add(format!("FIELD{}", idx + 1), pos, len, field_type)
+                .expect("profile fields are unique and within the record");
+        }
+        Self::new(layout)
+    }
+
+    /// Emits the report and resets the accumulated statistics.
+    pub fn flush(&mut self) -> Vec<Record> {
+        let layout = match self.layout.clone() {
+            Some(layout) => layout,
+            None => {
+                let buffered = std::mem::take(&mut self.buffered);
+                let mut profile = ProfileStage::new(infer_layout(&buffered));
+                for record in buffered {
+                    profile.process(record);
+                }
+                return profile.flush();
+            }
+        };
+
+        let mut lines = vec![
+            format!("PROFILE {} RECORDS", self.count),
+            format!(
+                "{:<10} {:>3} {:>3} {:<7} {:>5} {:>6} {:<12} {:<12} {:>5}",
+                "FIELD", "POS", "LEN", "TYPE", "BLANK", "DIST", "MIN", "MAX", "NONUM"
+            ),
+        ];
+        for (field, stats) in layout.fields().iter().zip(&self.stats) {
+            let blank_pct = (stats.blank * 100).checked_div(self.count).unwrap_or(0);
+            let non_numeric = if field.field_type == FieldType::Numeric {
+                stats.non_numeric.to_string()
+            } else {
+                "-".to_string()
+            };
+            lines.push(format!(
+                "{:<10} {:>3} {:>3} {:<7} {:>4}% {:>6} {:<12} {:<12} {:>5}",
+                truncate(&field.name, 10),
+                field.start,
+                field.len,
+                field.field_type.name(),
+                blank_pct,
+                stats.distinct.len(),
+                truncate(stats.min.as_deref().unwrap_or("-"), VALUE_WIDTH),
+                truncate(stats.max.as_deref().unwrap_or("-"), VALUE_WIDTH),
+                non_numeric,
+            ));
+        }
+
+        self.stats = vec![FieldStats::default(); layout.fields().len()];
+        self.count = 0;
+        lines.iter().map(|line| Record::from_str(line)).collect()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        match &self.layout {
+            Some(layout) => {
+                self.count += 1;
+                for (field, stats) in layout.fields().iter().zip(&mut self.stats) {
+                    stats.add(field, record.field(field.start, field.len));
+                }
+            }
+            None => self.buffered.push(record),
+        }
+        None
+    }
+}
+
+impl Stage for ProfileStage {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        ProfileStage::flush(self)
+    }
+}
+
+fn truncate(value: &str, width: usize) -> &str {
+    &value[..value.len().min(width)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn employees() -> Vec<Record> {
+        [
+            "SMITH   JOHN      SALES     00050000",
+            "JONES   MARY      ENGINEER  00075000",
+            "DOE     JANE      SALES     00060000",
+            "BROWN             SALES     9000    ",
+        ]
+        .iter()
+        .map(|s| Record::from_str(s))
+        .collect()
+    }
+
+    #[test]
+    fn test_profile_with_layout() {
+        let mut layout = RecordLayout::new();
+        layout.add("FIRST", 8, 10, FieldType::Char).unwrap();
+        layout.add("DEPT", 18, 10, FieldType::Char).unwrap();
+        layout.add("SALARY", 28, 8, FieldType::Numeric).unwrap();
+
+        let mut profile = ProfileStage::new(layout);
+        for record in employees() {
+            assert!(profile.process(record).is_none());
+        }
+        let report: Vec<String> = profile
+            .flush()
+            .iter()
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect();
+
+        assert_eq!(report.len(), 5);
+        assert_eq!(report[0], "PROFILE 4 RECORDS");
+        let first: Vec<&str> = report[2].split_whitespace().collect();
+        assert_eq!(
+            first,
+            ["FIRST", "8", "10", "CHAR", "25%", "3", "JANE", "MARY", "-"]
+        );
+        let dept: Vec<&str> = report[3].split_whitespace().collect();
+        assert_eq!(dept[5], "2");
+        let salary: Vec<&str> = report[4].split_whitespace().collect();
+        assert_eq!(
+            salary,
+            [
+                "SALARY", "28", "8", "NUMERIC", "0%", "4", "9000", "00075000", "0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_profile_counts_non_numeric() {
+        let mut layout = RecordLayout::new();
+        layout.add("AMOUNT", 0, 6, FieldType::Numeric).unwrap();
+        let mut profile = ProfileStage::new(layout);
+        profile.process(Record::from_str("100"));
+        profile.process(Record::from_str("N/A"));
+        profile.process(Record::from_str("12X"));
+        let report = profile.flush();
+        assert!(report[2].as_str().trim_end().ends_with(" 2"));
+    }
+
+    #[test]
+    fn test_profile_inferred_layout() {
+        let mut profile = ProfileStage::inferred();
+        for record in employees() {
+            profile.process(record);
+        }
+        let report = profile.flush();
+        assert_eq!(report.len(), 6);
+        assert!(report[5].as_str().starts_with("FIELD4"));
+    }
+
+    #[test]
+    fn test_profile_empty_input() {
+        let mut profile = ProfileStage::inferred();
+        let report = profile.flush();
+        assert_eq!(report.len(), 2);
+        assert!(report[0].as_str().starts_with("PROFILE 0 RECORDS"));
+    }
+}
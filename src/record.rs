@@ -1,18 +1,32 @@
-//! Fixed-width 80-byte record type.
+//! Fixed-width record type.
 //!
-//! The 80-byte record width matches the historical punch card format used
-//! on mainframe systems. Each record is exactly 80 bytes, padded with spaces
-//! if the source data is shorter.
+//! The default 80-byte record width matches the historical punch card
+//! format used on mainframe systems. Other record lengths (LRECL), such as
+//! 132-byte print lines, are set when a record is created. Each record is
+//! exactly its LRECL in bytes, padded with spaces if the source data is
+//! shorter.
+//!
+//! A [`RecordView`] reads the same fields from a record borrowed out of an
+//! input buffer, for scans that only filter or count and never need an
+//! owned copy of each record.
 
+use std::borrow::Cow;
 use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{PipelineError, Result};
 
 /// The standard record width (punch card width).
 pub const RECORD_WIDTH: usize = 80;
 
-/// A fixed-width 80-byte record.
+/// The largest supported record length (the z/OS limit for fixed records).
+pub const MAX_LRECL: usize = 32760;
+
+/// A fixed-width record, 80 bytes unless created with another LRECL.
 ///
 /// This type represents a single record in mainframe-style batch processing.
-/// Records are always exactly 80 bytes, matching the width of punch cards.
+/// Records are always exactly their LRECL in bytes; the default matches the
+/// width of punch cards.
 ///
 /// # Field Access
 ///
@@ -35,9 +49,23 @@ pub const RECORD_WIDTH: usize = 80;
 /// - Columns 19-28: Department (positions 18-27)
 /// - Columns 29-36: Salary (positions 28-35)
 /// - etc.
+///
+/// # Record Length
+///
+/// The `_lrecl` constructors create records of another width. Field access
+/// works the same way, bounded by the record's own length:
+///
+/// ```
+/// use pipelines_rs::Record;
+///
+/// let mut line = Record::blank(132);
+/// line.set_field(120, 12, "PAGE 1");
+/// assert_eq!(line.lrecl(), 132);
+/// assert_eq!(line.field(120, 12).trim(), "PAGE 1");
+/// ```
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Record {
-    data: [u8; RECORD_WIDTH],
+    data: Box<[u8]>,
 }
 
 impl Record {
@@ -54,8 +82,24 @@ impl Record {
     /// ```
     #[must_use]
     pub fn new() -> Self {
+        Self::blank(RECORD_WIDTH)
+    }
+
+    /// Creates a record of `lrecl` bytes filled with spaces.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let record = Record::blank(132);
+    /// assert_eq!(record.as_str().len(), 132);
+    /// assert!(record.is_blank());
+    /// ```
+    #[must_use]
+    pub fn blank(lrecl: usize) -> Self {
         Self {
-            data: [b' '; RECORD_WIDTH],
+            data: vec![b' '; lrecl].into_boxed_slice(),
         }
     }
 
@@ -66,6 +110,7 @@ impl Record {
     ///
     /// Note: This method is named `from_str` for convenience but does not
     /// implement `std::str::FromStr` because record parsing never fails.
+    /// Use [`Record::try_from_str`] to reject data instead of altering it.
     ///
     /// # Example
     ///
@@ -79,16 +124,25 @@ impl Record {
     #[must_use]
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
-        let mut record = Self::new();
-        let bytes = s.as_bytes();
-        let len = bytes.len().min(RECORD_WIDTH);
-
-        for (i, &byte) in bytes.iter().take(len).enumerate() {
-            // Replace non-ASCII with '?' (simulating EBCDIC conversion issues)
-            record.data[i] = if byte.is_ascii() { byte } else { b'?' };
-        }
+        Self::from_str_lrecl(s, RECORD_WIDTH)
+    }
 
-        record
+    /// Creates an `lrecl`-byte record from a string slice, truncating or
+    /// padding it as `from_str` does.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let record = Record::from_str_lrecl("DETAIL", 256);
+    /// assert_eq!(record.as_str().len(), 256);
+    /// assert_eq!(Record::from_str_lrecl("ABCDEF", 3).as_str(), "ABC");
+    /// ```
+    #[must_use]
+    pub fn from_str_lrecl(s: &str, lrecl: usize) -> Self {
+        // Non-ASCII becomes '?' (simulating EBCDIC conversion issues)
+        Self::from_bytes_lrecl(s.as_bytes(), lrecl)
     }
 
     /// Creates a record from raw bytes.
@@ -106,14 +160,111 @@ impl Record {
     /// ```
     #[must_use]
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        let mut record = Self::new();
-        let len = bytes.len().min(RECORD_WIDTH);
+        Self::from_bytes_lrecl(bytes, RECORD_WIDTH)
+    }
+
+    /// Creates an `lrecl`-byte record from raw bytes, truncating or padding
+    /// them as `from_bytes` does.
+    #[must_use]
+    pub fn from_bytes_lrecl(bytes: &[u8], lrecl: usize) -> Self {
+        let mut record = Self::blank(lrecl);
+        record.fill(bytes);
+        record
+    }
 
-        for (i, &byte) in bytes.iter().take(len).enumerate() {
-            record.data[i] = if byte.is_ascii() { byte } else { b'?' };
+    /// Overwrites the record with `bytes`, replacing non-ASCII bytes and
+    /// padding with spaces as `from_bytes` does.
+    fn fill(&mut self, bytes: &[u8]) {
+        let copied = bytes.len().min(self.data.len());
+        for (out, &byte) in self.data.iter_mut().zip(bytes) {
+            *out = if byte.is_ascii() { byte } else { b'?' };
         }
+        self.data[copied..].fill(b' ');
+    }
 
-        record
+    /// Creates a record from a string slice, failing instead of truncating
+    /// long input or replacing non-ASCII characters.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::RecordTooLong`] for more than 80 bytes
+    /// and [`PipelineError::NonAscii`] for a non-ASCII character.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// assert!(Record::try_from_str("SMITH").is_ok());
+    /// assert!(Record::try_from_str("CAFÉ").is_err());
+    /// assert!(Record::try_from_str(&"X".repeat(81)).is_err());
+    /// ```
+    pub fn try_from_str(s: &str) -> Result<Self> {
+        Self::try_from_bytes_lrecl(s.as_bytes(), RECORD_WIDTH)
+    }
+
+    /// Creates an `lrecl`-byte record from a string slice, failing as
+    /// `try_from_str` does.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `s` is longer than `lrecl` bytes or is not ASCII.
+    pub fn try_from_str_lrecl(s: &str, lrecl: usize) -> Result<Self> {
+        Self::try_from_bytes_lrecl(s.as_bytes(), lrecl)
+    }
+
+    /// Creates a record from raw bytes, failing instead of truncating long
+    /// input or replacing non-ASCII bytes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `bytes` is longer than 80 or holds a non-ASCII byte.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::try_from_bytes_lrecl(bytes, RECORD_WIDTH)
+    }
+
+    /// Creates an `lrecl`-byte record from raw bytes, failing as
+    /// `try_from_bytes` does.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `bytes` is longer than `lrecl` or holds a non-ASCII byte.
+    pub fn try_from_bytes_lrecl(bytes: &[u8], lrecl: usize) -> Result<Self> {
+        if bytes.len() > lrecl {
+            return Err(PipelineError::RecordTooLong {
+                length: bytes.len(),
+                lrecl,
+            });
+        }
+        if let Some(offset) = bytes.iter().position(|b| !b.is_ascii()) {
+            return Err(PipelineError::NonAscii {
+                offset,
+                byte: bytes[offset],
+            });
+        }
+        Ok(Self::from_bytes_lrecl(bytes, lrecl))
+    }
+
+    /// Returns the record length in bytes.
+    #[must_use]
+    pub fn lrecl(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns a copy of the record truncated or padded to `lrecl` bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let wide = Record::from_str("SMITH").with_lrecl(132);
+    /// assert_eq!(wide.lrecl(), 132);
+    /// assert_eq!(wide.as_str().trim_end(), "SMITH");
+    /// ```
+    #[must_use]
+    pub fn with_lrecl(&self, lrecl: usize) -> Self {
+        Self::from_bytes_lrecl(&self.data, lrecl)
     }
 
     /// Returns the record data as a string slice.
@@ -123,14 +274,12 @@ impl Record {
     pub fn as_str(&self) -> &str {
         // SAFETY: We only store ASCII bytes, which are valid UTF-8
         // Fallback should never happen, but be safe
-        std::str::from_utf8(&self.data).unwrap_or(
-            "????????????????????????????????????????????????????????????????????????????????",
-        )
+        std::str::from_utf8(&self.data).unwrap_or("?")
     }
 
     /// Returns the raw bytes of the record.
     #[must_use]
-    pub fn as_bytes(&self) -> &[u8; RECORD_WIDTH] {
+    pub fn as_bytes(&self) -> &[u8] {
         &self.data
     }
 
@@ -150,14 +299,7 @@ impl Record {
     /// ```
     #[must_use]
     pub fn field(&self, start: usize, length: usize) -> &str {
-        let end = (start + length).min(RECORD_WIDTH);
-        let start = start.min(RECORD_WIDTH);
-
-        if start >= end {
-            return "";
-        }
-
-        std::str::from_utf8(&self.data[start..end]).unwrap_or("")
+        field_of(&self.data, start, length)
     }
 
     /// Sets a field in the record.
@@ -177,8 +319,8 @@ impl Record {
     /// assert_eq!(record.field(8, 10), "JOHN      ");
     /// ```
     pub fn set_field(&mut self, start: usize, length: usize, value: &str) {
-        let end = (start + length).min(RECORD_WIDTH);
-        let start = start.min(RECORD_WIDTH);
+        let end = start.saturating_add(length).min(self.lrecl());
+        let start = start.min(self.lrecl());
 
         if start >= end {
             return;
@@ -198,12 +340,180 @@ impl Record {
         }
     }
 
+    /// Copies `len` columns of `other` from `src_start` into this record at
+    /// `start`.
+    ///
+    /// Columns past the end of `other` copy as blanks, and columns past the
+    /// end of this record are dropped, as with `set_field`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let mut master = Record::from_str("SMITH   JOHN      SALES     ");
+    /// let update = Record::from_str("D01 ENGINEER");
+    /// master.patch(18, &update, 4, 10);
+    /// assert_eq!(master.field(0, 28), "SMITH   JOHN      ENGINEER  ");
+    /// ```
+    pub fn patch(&mut self, start: usize, other: &Record, src_start: usize, len: usize) {
+        self.set_field(start, len, other.field(src_start, len));
+    }
+
+    /// Copies the `(start, len)` column ranges of `other` into the same
+    /// columns of this record, e.g. to apply an update record to a master
+    /// record.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let mut master = Record::from_str("SMITH   JOHN      SALES     00050000");
+    /// let update = Record::from_str("SMITH   JON       MARKETING 00055000");
+    /// master.merge_from(&update, &[(18, 10), (28, 8)]);
+    /// assert_eq!(master.field(8, 28), "JOHN      MARKETING 00055000");
+    /// ```
+    pub fn merge_from(&mut self, other: &Record, ranges: &[(usize, usize)]) {
+        for &(start, len) in ranges {
+            self.patch(start, other, start, len);
+        }
+    }
+
+    /// Extracts a field, failing instead of truncating it if it extends
+    /// past the end of the record.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if `start + length`
+    /// exceeds the record length.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let record = Record::from_str("SMITH   JOHN");
+    /// assert_eq!(record.try_field(0, 8).unwrap(), "SMITH   ");
+    /// assert!(record.try_field(75, 10).is_err());
+    /// ```
+    pub fn try_field(&self, start: usize, length: usize) -> Result<&str> {
+        self.check_bounds(start, length)?;
+        Ok(self.field(start, length))
+    }
+
+    /// Sets a field, failing instead of truncating it if it extends past
+    /// the end of the record. The value is truncated or padded to the
+    /// field as with `set_field`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if `start + length`
+    /// exceeds the record length; the record is then unchanged.
+    pub fn try_set_field(&mut self, start: usize, length: usize, value: &str) -> Result<()> {
+        self.check_bounds(start, length)?;
+        self.set_field(start, length, value);
+        Ok(())
+    }
+
+    /// Parses a field as a number, ignoring surrounding blanks.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if the field extends
+    /// past the record, and [`PipelineError::NotNumeric`] if its text,
+    /// blank included, does not parse as a `T`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let record = Record::from_str("SMITH   JOHN      SALES     00050000");
+    /// assert_eq!(record.field_num::<u64>(28, 8).unwrap(), 50000);
+    /// assert!(record.field_num::<u64>(0, 8).is_err());
+    /// ```
+    pub fn field_num<T: FromStr>(&self, start: usize, length: usize) -> Result<T> {
+        let text = self.try_field(start, length)?;
+        text.trim().parse().map_err(|_| PipelineError::NotNumeric {
+            start,
+            length,
+            value: text.trim().to_string(),
+        })
+    }
+
+    /// Writes a number into a field, right-justified and zero-padded, with
+    /// a leading `-` for negatives.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::FieldOutOfBounds`] if the field extends
+    /// past the record, and [`PipelineError::ValueTooWide`] if the value
+    /// does not fit; the record is then unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let mut record = Record::new();
+    /// record.set_field_num(0, 8, 1250).unwrap();
+    /// record.set_field_num(8, 6, -42).unwrap();
+    /// assert_eq!(record.field(0, 14), "00001250-00042");
+    /// assert!(record.set_field_num(0, 3, 1250).is_err());
+    /// ```
+    pub fn set_field_num<T: fmt::Display>(
+        &mut self,
+        start: usize,
+        length: usize,
+        value: T,
+    ) -> Result<()> {
+        self.check_bounds(start, length)?;
+        let text = value.to_string();
+        if text.len() > length {
+            return Err(PipelineError::ValueTooWide {
+                value: text,
+                length,
+            });
+        }
+        let (sign, digits) = match text.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", text.as_str()),
+        };
+        let width = length - sign.len();
+        self.set_field(start, length, &format!("{sign}{digits:0>width$}"));
+        Ok(())
+    }
+
+    /// Checks that the field `start, length` lies within the record.
+    fn check_bounds(&self, start: usize, length: usize) -> Result<()> {
+        if start
+            .checked_add(length)
+            .is_none_or(|end| end > self.lrecl())
+        {
+            return Err(PipelineError::FieldOutOfBounds {
+                start,
+                length,
+                record_len: self.lrecl(),
+            });
+        }
+        Ok(())
+    }
+
     /// Returns true if the record is blank (all spaces).
     #[must_use]
     pub fn is_blank(&self) -> bool {
         self.data.iter().all(|&b| b == b' ')
     }
 
+    /// Returns a view of the record, to pass where views are read.
+    #[must_use]
+    pub fn view(&self) -> RecordView<'_> {
+        RecordView {
+            data: Cow::Borrowed(&self.data),
+        }
+    }
+
     /// Compares a field to a value.
     ///
     /// This is a convenience method for filtering operations.
@@ -239,6 +549,317 @@ impl Record {
     pub fn field_contains(&self, start: usize, length: usize, substring: &str) -> bool {
         self.field(start, length).contains(substring)
     }
+
+    /// Returns true if a field, without leading and trailing blanks,
+    /// matches a regular expression. Anchor the pattern with `^` and `$`
+    /// to match the whole field.
+    ///
+    /// Requires the `regex` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "regex")]
+    /// # {
+    /// use pipelines_rs::{Record, Regex};
+    ///
+    /// let record = Record::from_str("E01234  2026-02-04");
+    /// assert!(record.field_matches(0, 8, &Regex::new(r"^E\d{5}$").unwrap()));
+    /// assert!(!record.field_matches(8, 10, &Regex::new(r"^\d{2}/").unwrap()));
+    /// # }
+    /// ```
+    #[cfg(feature = "regex")]
+    #[must_use]
+    pub fn field_matches(&self, start: usize, length: usize, regex: &regex::Regex) -> bool {
+        regex.is_match(self.field(start, length).trim())
+    }
+
+    /// Reads a zoned decimal field, as written by COBOL `PIC S9(n)`.
+    ///
+    /// The field holds digits, with the sign overpunched on the last one:
+    /// `{` and `A`-`I` are +0 to +9, `}` and `J`-`R` are -0 to -9. A plain
+    /// last digit is unsigned (positive). Leading blanks are allowed.
+    /// Returns `None` if the field is blank, not zoned decimal, or too
+    /// large for an `i64`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let record = Record::from_str("0001234J0000500{00000042");
+    /// assert_eq!(record.field_zoned(0, 8), Some(-12341));
+    /// assert_eq!(record.field_zoned(8, 8), Some(5000));
+    /// assert_eq!(record.field_zoned(16, 8), Some(42));
+    /// ```
+    #[must_use]
+    pub fn field_zoned(&self, start: usize, length: usize) -> Option<i64> {
+        zoned_value(self.field(start, length).trim_start())
+    }
+
+    /// Writes `value` as a zoned decimal filling the field: zero-padded,
+    /// with the sign overpunched on the last digit (`{`/`A`-`I` for zero
+    /// and positive, `}`/`J`-`R` for negative).
+    ///
+    /// # Errors
+    ///
+    /// Fails if the field extends past the record or the value has more
+    /// digits than the field.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let mut record = Record::new();
+    /// record.set_field_zoned(0, 6, -1234).unwrap();
+    /// record.set_field_zoned(6, 6, 50).unwrap();
+    /// assert_eq!(record.field(0, 12), "00123M00005{");
+    /// assert!(record.set_field_zoned(0, 3, 1234).is_err());
+    /// ```
+    pub fn set_field_zoned(&mut self, start: usize, length: usize, value: i64) -> Result<()> {
+        if length == 0 || start + length > self.lrecl() {
+            return Err(PipelineError::FieldOutOfBounds {
+                start,
+                length,
+                record_len: self.lrecl(),
+            });
+        }
+        let digits = value.unsigned_abs().to_string();
+        if digits.len() > length {
+            return Err(PipelineError::ValueTooWide {
+                value: value.to_string(),
+                length,
+            });
+        }
+        let mut text = format!("{digits:0>length$}").into_bytes();
+        let last = text[length - 1] - b'0';
+        text[length - 1] = match (value < 0, last) {
+            (false, 0) => b'{',
+            (false, d) => b'A' + d - 1,
+            (true, 0) => b'}',
+            (true, d) => b'J' + d - 1,
+        };
+        self.data[start..start + length].copy_from_slice(&text);
+        Ok(())
+    }
+
+    /// Returns the runs of columns where this record differs from `other`.
+    /// Columns past the end of the shorter record count as differing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    /// use pipelines_rs::record::ColumnDiff;
+    ///
+    /// let expected = Record::from_str("SMITH   JOHN      SALES     00050000");
+    /// let actual = Record::from_str("SMITH   JON       SALES     00055000");
+    /// assert_eq!(
+    ///     expected.diff(&actual),
+    ///     vec![ColumnDiff { start: 10, len: 2 }, ColumnDiff { start: 32, len: 1 }]
+    /// );
+    /// ```
+    #[must_use]
+    pub fn diff(&self, other: &Record) -> Vec<ColumnDiff> {
+        let mut diffs: Vec<ColumnDiff> = Vec::new();
+        for col in 0..self.lrecl().max(other.lrecl()) {
+            if self.data.get(col) == other.data.get(col) {
+                continue;
+            }
+            match diffs.last_mut() {
+                Some(last) if last.start + last.len == col => last.len += 1,
+                _ => diffs.push(ColumnDiff { start: col, len: 1 }),
+            }
+        }
+        diffs
+    }
+
+    /// Returns a hex dump of the record: 16 bytes a line, each line the
+    /// offset of its first byte (0-based, in decimal like field positions),
+    /// the bytes in hex, and the bytes as text with `.` for unprintables.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let dump = Record::from_str_lrecl("SMITH\tJOHN", 20).to_hex_dump();
+    /// let lines: Vec<_> = dump.lines().collect();
+    /// assert_eq!(
+    ///     lines[0],
+    ///     "00000  53 4D 49 54 48 09 4A 4F  48 4E 20 20 20 20 20 20  |SMITH.JOHN      |"
+    /// );
+    /// assert!(lines[1].starts_with("00016  20 20 20 20   "));
+    /// assert!(lines[1].ends_with("|    |"));
+    /// assert_eq!(lines[1].len(), lines[0].len() - 12);
+    /// ```
+    #[must_use]
+    pub fn to_hex_dump(&self) -> String {
+        let lines: Vec<String> = self
+            .data
+            .chunks(16)
+            .enumerate()
+            .map(|(line, bytes)| {
+                let hex: Vec<String> = (0..16)
+                    .map(|i| {
+                        bytes
+                            .get(i)
+                            .map_or("  ".to_string(), |b| format!("{b:02X}"))
+                    })
+                    .collect();
+                let text: String = bytes
+                    .iter()
+                    .map(|&b| {
+                        if b.is_ascii_graphic() || b == b' ' {
+                            b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                format!(
+                    "{:05}  {}  {}  |{text}|",
+                    line * 16,
+                    hex[..8].join(" "),
+                    hex[8..].join(" ")
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Returns the record under a column ruler: a line marking every tenth
+    /// column (`0---------1---------2...`), a line of each column's last
+    /// digit, and the record itself.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::Record;
+    ///
+    /// let ruler = Record::from_str_lrecl("SMITH   JOHN", 12).to_ruler_string();
+    /// assert_eq!(ruler, "0---------1-\n012345678901\nSMITH   JOHN");
+    /// ```
+    #[must_use]
+    pub fn to_ruler_string(&self) -> String {
+        let tens: String = (0..self.lrecl())
+            .map(|col| match col % 10 {
+                0 => char::from(b'0' + (col / 10 % 10) as u8),
+                _ => '-',
+            })
+            .collect();
+        let units: String = (0..self.lrecl())
+            .map(|col| char::from(b'0' + (col % 10) as u8))
+            .collect();
+        format!("{tens}\n{units}\n{}", self.as_str())
+    }
+}
+
+/// A run of adjacent columns where two records differ, from [`Record::diff`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnDiff {
+    /// First differing column (0-based).
+    pub start: usize,
+    /// Number of differing columns.
+    pub len: usize,
+}
+
+/// Most spare buffers a [`RecordPool`] keeps by default.
+pub const POOL_LIMIT: usize = 1024;
+
+/// Spare record buffers, reused for new records instead of allocating one
+/// for each record and freeing it when the record is dropped.
+///
+/// A streaming executor hands each record it has finished with (written
+/// out, say) back to the pool, and builds the next input record from it.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, RecordPool};
+///
+/// let mut pool = RecordPool::new();
+/// let first = pool.from_str_lrecl("SMITH", 80);
+/// pool.recycle(first);
+/// assert_eq!(pool.len(), 1);
+///
+/// let second = pool.from_str_lrecl("JONES", 80);
+/// assert_eq!(second, Record::from_str("JONES"));
+/// assert!(pool.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct RecordPool {
+    free: Vec<Record>,
+    limit: usize,
+}
+
+impl RecordPool {
+    /// Creates an empty pool keeping up to [`POOL_LIMIT`] spare buffers.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_limit(POOL_LIMIT)
+    }
+
+    /// Creates an empty pool keeping up to `limit` spare buffers.
+    #[must_use]
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            free: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Returns an `lrecl`-byte record holding `bytes`, as
+    /// [`Record::from_bytes_lrecl`] does, in a spare buffer of that length
+    /// if the pool has one.
+    pub fn from_bytes_lrecl(&mut self, bytes: &[u8], lrecl: usize) -> Record {
+        match self.free.iter().rposition(|record| record.lrecl() == lrecl) {
+            Some(idx) => {
+                let mut record = self.free.swap_remove(idx);
+                record.fill(bytes);
+                record
+            }
+            None => Record::from_bytes_lrecl(bytes, lrecl),
+        }
+    }
+
+    /// Returns an `lrecl`-byte record holding `s`, as
+    /// [`Record::from_str_lrecl`] does, in a spare buffer if there is one.
+    pub fn from_str_lrecl(&mut self, s: &str, lrecl: usize) -> Record {
+        self.from_bytes_lrecl(s.as_bytes(), lrecl)
+    }
+
+    /// Returns a copy of `record` in a spare buffer if there is one.
+    pub fn copy(&mut self, record: &Record) -> Record {
+        self.from_bytes_lrecl(record.as_bytes(), record.lrecl())
+    }
+
+    /// Keeps the record's buffer for reuse, or drops it if the pool is
+    /// full.
+    pub fn recycle(&mut self, record: Record) {
+        if self.free.len() < self.limit {
+            self.free.push(record);
+        }
+    }
+
+    /// Returns the number of spare buffers.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Returns true if the pool has no spare buffers.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+}
+
+impl Default for RecordPool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Default for Record {
@@ -277,6 +898,188 @@ impl From<&[u8]> for Record {
     }
 }
 
+/// A fixed-width record borrowed from an input buffer.
+///
+/// A view has the same field accessors as [`Record`] but does not copy the
+/// record's bytes when they are already exactly LRECL ASCII bytes, as in
+/// fixed-block data; a short line is padded, and non-ASCII bytes replaced,
+/// in a copy of that one record. Filtering and counting views of a large
+/// buffer then touches only the fields compared. Call
+/// [`RecordView::to_record`] for the records that go on to be changed or
+/// kept.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::RecordView;
+///
+/// let data = b"SMITH   SALES     JONES   ENGINEER  DOE     SALES     ";
+/// let sales: Vec<_> = RecordView::chunks(data, 18)
+///     .filter(|r| r.field_eq(8, 10, "SALES"))
+///     .collect();
+/// assert_eq!(sales.len(), 2);
+/// assert!(sales.iter().all(|r| r.is_borrowed()));
+/// assert_eq!(sales[1].to_record().field(0, 8).trim(), "DOE");
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct RecordView<'a> {
+    data: Cow<'a, [u8]>,
+}
+
+impl<'a> RecordView<'a> {
+    /// Creates an `lrecl`-byte view of `bytes`, borrowing them if they are
+    /// exactly `lrecl` ASCII bytes and otherwise truncating or padding a
+    /// copy as [`Record::from_bytes_lrecl`] does.
+    #[must_use]
+    pub fn new(bytes: &'a [u8], lrecl: usize) -> Self {
+        let data = if bytes.len() == lrecl && bytes.is_ascii() {
+            Cow::Borrowed(bytes)
+        } else {
+            Cow::Owned(Record::from_bytes_lrecl(bytes, lrecl).data.into_vec())
+        };
+        Self { data }
+    }
+
+    /// Views fixed-block data, `lrecl` bytes per record with no line ends.
+    /// A short last record is padded.
+    pub fn chunks(data: &'a [u8], lrecl: usize) -> impl Iterator<Item = RecordView<'a>> {
+        data.chunks(lrecl.max(1))
+            .map(move |chunk| RecordView::new(chunk, lrecl))
+    }
+
+    /// Views text, one `lrecl`-byte record per non-empty line.
+    pub fn lines(text: &'a str, lrecl: usize) -> impl Iterator<Item = RecordView<'a>> {
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(move |line| RecordView::new(line.as_bytes(), lrecl))
+    }
+
+    /// Returns true if the view borrows its bytes rather than holding a
+    /// padded copy.
+    #[must_use]
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.data, Cow::Borrowed(_))
+    }
+
+    /// Returns the record length in bytes.
+    #[must_use]
+    pub fn lrecl(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns the record data as a string slice.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        // Views hold only ASCII bytes, as records do
+        std::str::from_utf8(&self.data).unwrap_or("?")
+    }
+
+    /// Returns the raw bytes of the record.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Extracts a field, as [`Record::field`] does.
+    #[must_use]
+    pub fn field(&self, start: usize, length: usize) -> &str {
+        field_of(&self.data, start, length)
+    }
+
+    /// Compares a field to a value, ignoring leading and trailing blanks.
+    #[must_use]
+    pub fn field_eq(&self, start: usize, length: usize, value: &str) -> bool {
+        self.field(start, length).trim() == value.trim()
+    }
+
+    /// Compares a field to a value with exact matching (including spaces).
+    #[must_use]
+    pub fn field_eq_exact(&self, start: usize, length: usize, value: &str) -> bool {
+        self.field(start, length) == value
+    }
+
+    /// Returns true if a field starts with the given prefix.
+    #[must_use]
+    pub fn field_starts_with(&self, start: usize, length: usize, prefix: &str) -> bool {
+        self.field(start, length).trim_start().starts_with(prefix)
+    }
+
+    /// Returns true if a field contains the given substring.
+    #[must_use]
+    pub fn field_contains(&self, start: usize, length: usize, substring: &str) -> bool {
+        self.field(start, length).contains(substring)
+    }
+
+    /// Reads a zoned decimal field, as [`Record::field_zoned`] does.
+    #[must_use]
+    pub fn field_zoned(&self, start: usize, length: usize) -> Option<i64> {
+        zoned_value(self.field(start, length).trim_start())
+    }
+
+    /// Returns true if the record is blank (all spaces).
+    #[must_use]
+    pub fn is_blank(&self) -> bool {
+        self.data.iter().all(|&b| b == b' ')
+    }
+
+    /// Copies the viewed record into an owned [`Record`].
+    #[must_use]
+    pub fn to_record(&self) -> Record {
+        Record {
+            data: self.data.to_vec().into_boxed_slice(),
+        }
+    }
+}
+
+impl fmt::Debug for RecordView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RecordView({:?})", self.as_str().trim_end())
+    }
+}
+
+impl From<RecordView<'_>> for Record {
+    fn from(view: RecordView<'_>) -> Self {
+        Record {
+            data: view.data.into_owned().into_boxed_slice(),
+        }
+    }
+}
+
+/// Extracts a field from record bytes, truncated at the end of the record.
+fn field_of(data: &[u8], start: usize, length: usize) -> &str {
+    let end = start.saturating_add(length).min(data.len());
+    let start = start.min(data.len());
+
+    if start >= end {
+        return "";
+    }
+
+    std::str::from_utf8(&data[start..end]).unwrap_or("")
+}
+
+/// Reads zoned decimal digits with an overpunched sign on the last one.
+pub(crate) fn zoned_value(text: &str) -> Option<i64> {
+    let (&last, leading) = text.as_bytes().split_last()?;
+    let (last_digit, negative) = match last {
+        b'0'..=b'9' => (last - b'0', false),
+        b'{' => (0, false),
+        b'A'..=b'I' => (last - b'A' + 1, false),
+        b'}' => (0, true),
+        b'J'..=b'R' => (last - b'J' + 1, true),
+        _ => return None,
+    };
+    let mut value: i64 = 0;
+    for &digit in leading.iter().chain(&[b'0' + last_digit]) {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        value = value
+            .checked_mul(10)?
+            .checked_add(i64::from(digit - b'0'))?;
+    }
+    Some(if negative { -value } else { value })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,6 +1158,77 @@ mod tests {
         assert!(record.as_str().contains('?'));
     }
 
+    #[test]
+    fn test_patch_and_merge() {
+        let mut master = Record::from_str("AAAAAAAAAA");
+        let short = Record::from_str_lrecl("XYZ", 3);
+        master.patch(2, &short, 1, 4);
+        assert_eq!(master.field(0, 10), "AAYZ  AAAA");
+
+        master.patch(78, &Record::from_str("123456"), 0, 6);
+        assert_eq!(master.field(76, 4), "  12");
+        assert_eq!(master.lrecl(), 80);
+
+        let mut master = Record::from_str("0123456789");
+        master.merge_from(&Record::from_str("abcdefghij"), &[(0, 2), (5, 1), (9, 5)]);
+        assert_eq!(master.field(0, 12), "ab234f678j  ");
+    }
+
+    #[test]
+    fn test_try_field() {
+        let mut record = Record::from_str("SMITH");
+        assert_eq!(record.try_field(0, 5).unwrap(), "SMITH");
+        assert_eq!(record.try_field(70, 10).unwrap().len(), 10);
+        let err = record.try_field(75, 10).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "field position 75:10 exceeds record length 80"
+        );
+        assert!(record.try_field(usize::MAX, 2).is_err());
+
+        record.try_set_field(6, 4, "JOHN").unwrap();
+        assert_eq!(record.field(0, 10), "SMITH JOHN");
+        assert!(record.try_set_field(78, 4, "XXXX").is_err());
+        assert_eq!(record.field(78, 2), "  ");
+    }
+
+    #[test]
+    fn test_field_num() {
+        let mut record = Record::from_str("  42    -7  X1  ");
+        assert_eq!(record.field_num::<u32>(0, 6).unwrap(), 42);
+        assert_eq!(record.field_num::<i64>(6, 4).unwrap(), -7);
+        assert_eq!(record.field_num::<f64>(0, 4).unwrap(), 42.0);
+
+        let err = record.field_num::<u32>(10, 4).unwrap_err();
+        assert_eq!(err.to_string(), "field 10:4 is not a number: 'X1'");
+        assert!(record.field_num::<u32>(20, 4).is_err()); // blank
+        assert!(record.field_num::<u32>(78, 4).is_err());
+
+        record.set_field_num(20, 5, 7u8).unwrap();
+        record.set_field_num(25, 5, -1234).unwrap();
+        assert_eq!(record.field(20, 10), "00007-1234");
+        assert_eq!(record.field_num::<i32>(25, 5).unwrap(), -1234);
+        assert!(record.set_field_num(25, 4, -1234).is_err());
+        assert!(record.set_field_num(78, 4, 1).is_err());
+        assert_eq!(record.field(25, 5), "-1234");
+    }
+
+    #[test]
+    fn test_try_from_str() {
+        let record = Record::try_from_str("SMITH").unwrap();
+        assert_eq!(record, Record::from_str("SMITH"));
+        assert_eq!(Record::try_from_str(&"X".repeat(80)).unwrap().lrecl(), 80);
+
+        let err = Record::try_from_str("Hello\u{00E9}").unwrap_err();
+        assert_eq!(err.to_string(), "non-ASCII byte 0xC3 at offset 5");
+        let err = Record::try_from_str(&"X".repeat(81)).unwrap_err();
+        assert_eq!(err.to_string(), "81 bytes do not fit in a 80-byte record");
+
+        assert!(Record::try_from_str_lrecl(&"X".repeat(81), 132).is_ok());
+        assert!(Record::try_from_bytes(b"OK\xFF").is_err());
+        assert!(Record::try_from_bytes_lrecl(b"TOOLONG", 3).is_err());
+    }
+
     #[test]
     fn test_field_out_of_bounds() {
         let record = Record::from_str("TEST");
@@ -393,4 +1267,111 @@ mod tests {
         // Debug should trim trailing spaces
         assert!(!debug.ends_with("   \")"));
     }
+
+    #[test]
+    fn test_diff() {
+        let a = Record::from_str("ABCDEF");
+        assert!(a.diff(&a.clone()).is_empty());
+        let b = Record::from_str("AXCDYZ");
+        assert_eq!(
+            a.diff(&b),
+            vec![
+                ColumnDiff { start: 1, len: 1 },
+                ColumnDiff { start: 4, len: 2 }
+            ]
+        );
+        let c = Record::from_str("ABCDEF  tail");
+        assert_eq!(a.diff(&c), vec![ColumnDiff { start: 8, len: 4 }]);
+    }
+
+    #[test]
+    fn test_field_zoned() {
+        let record = Record::from_str("12345{12345}    123D   }ABC 99999999999999999999");
+        assert_eq!(record.field_zoned(0, 6), Some(123450));
+        assert_eq!(record.field_zoned(6, 6), Some(-123450));
+        assert_eq!(record.field_zoned(12, 8), Some(1234));
+        assert_eq!(record.field_zoned(20, 4), Some(0));
+        assert_eq!(record.field_zoned(24, 3), None);
+        assert_eq!(record.field_zoned(28, 20), None);
+        assert_eq!(Record::new().field_zoned(0, 8), None);
+    }
+
+    #[test]
+    fn test_set_field_zoned_round_trip() {
+        let mut record = Record::new();
+        for value in [0, 7, -7, 10, -10, 99_999_999, -99_999_999] {
+            record.set_field_zoned(4, 8, value).unwrap();
+            assert_eq!(record.field_zoned(4, 8), Some(value));
+        }
+        assert!(record.set_field_zoned(75, 8, 1).is_err());
+        assert_eq!(
+            record.set_field_zoned(0, 2, -100).unwrap_err().to_string(),
+            "value -100 does not fit in a 2-byte field"
+        );
+    }
+
+    #[test]
+    fn test_lrecl() {
+        let mut record = Record::from_str_lrecl("SMITH", 132);
+        assert_eq!(record.lrecl(), 132);
+        assert_eq!(record.as_str().len(), 132);
+        record.set_field(124, 8, "00050000");
+        assert_eq!(record.field(124, 10), "00050000");
+        assert_eq!(record.field_zoned(124, 8), Some(50000));
+
+        let short = record.with_lrecl(80);
+        assert_eq!(short.as_str().trim_end(), "SMITH");
+        assert_ne!(short, Record::from_str("SMITH").with_lrecl(132));
+        assert_eq!(short, Record::from_str("SMITH"));
+        assert_eq!(short.diff(&record), vec![ColumnDiff { start: 80, len: 52 }]);
+    }
+
+    #[test]
+    fn test_record_view() {
+        let text = format!("{}\nSHORT\n\nCAFÉ", "A".repeat(80));
+        let views: Vec<RecordView> = RecordView::lines(&text, 80).collect();
+        assert_eq!(views.len(), 3);
+        assert!(views[0].is_borrowed());
+        assert!(!views[1].is_borrowed());
+        assert_eq!(views[1].lrecl(), 80);
+        assert_eq!(views[1].field(0, 8), "SHORT   ");
+        assert_eq!(views[2].as_str().trim_end(), "CAF??");
+
+        // A view reads the same fields as the record it copies into
+        let record = Record::from_str("0001234J  SALES");
+        let view = RecordView::new(record.as_bytes(), 80);
+        assert!(view.is_borrowed());
+        assert_eq!(view.field_zoned(0, 8), record.field_zoned(0, 8));
+        assert!(view.field_eq(10, 10, "SALES"));
+        assert!(view.field_starts_with(8, 10, "SA"));
+        assert_eq!(view.field(75, 10), record.field(75, 10));
+        assert_eq!(view.to_record(), record);
+        assert_eq!(record.view(), view);
+        assert_eq!(Record::from(view), record);
+
+        let fb: Vec<RecordView> = RecordView::chunks(b"ABCDEFG", 3).collect();
+        assert_eq!(fb.len(), 3);
+        assert_eq!(fb[2].as_str(), "G  ");
+    }
+
+    #[test]
+    fn test_record_pool() {
+        let mut pool = RecordPool::with_limit(2);
+        let long = pool.from_str_lrecl(&"X".repeat(80), 80);
+        let address = long.as_bytes().as_ptr();
+        pool.recycle(long);
+        pool.recycle(Record::blank(132));
+        pool.recycle(Record::new());
+        assert_eq!(pool.len(), 2);
+
+        // A reused buffer is overwritten and padded like a new record
+        let reused = pool.from_str_lrecl("CAFÉ", 80);
+        assert_eq!(reused.as_bytes().as_ptr(), address);
+        assert_eq!(reused, Record::from_str("CAFÉ"));
+        let wide = pool.copy(&Record::from_str_lrecl("WIDE", 132));
+        assert_eq!(wide.lrecl(), 132);
+        assert_eq!(wide.as_str().trim_end(), "WIDE");
+        assert!(pool.is_empty());
+        assert_eq!(pool.from_str_lrecl("NEW", 80).lrecl(), 80);
+    }
 }
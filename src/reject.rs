@@ -0,0 +1,224 @@
+//! Error records.
+//!
+//! By default a stage that reads numbers from records does what DFSORT
+//! does with a value that is not a number: [`SumBy`](crate::SumBy) leaves
+//! the record unsummed and [`RunningTotal`](crate::RunningTotal) leaves the
+//! total unchanged, so the record passes without anyone noticing. An
+//! [`ErrorPolicy`] makes the stage account for it instead:
+//!
+//! - [`ErrorPolicy::Reject`] sends the record, with a reason code appended,
+//!   to a reject [`Sink`]: a `Vec`, a file through a
+//!   [`WriteSink`](crate::WriteSink), or a channel feeding another pipeline.
+//!   The record leaves the main stream.
+//! - [`ErrorPolicy::Fail`] stops the stage at the first such record: that
+//!   record and all later ones are dropped, and the error is kept for the
+//!   stage's `error()`, as with a [`Lookup`](crate::Lookup).
+//!
+//! An error record is the input record followed by [`REASON_WIDTH`]
+//! columns holding the reason code and the field, e.g. `NOTNUM 28,8`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Pipeline, Record, RunningTotal, SumField};
+//!
+//! let records = vec![
+//!     Record::from_str("00100"),
+//!     Record::from_str("12X45"),
+//!     Record::from_str("00050"),
+//! ];
+//!
+//! let mut errors = Vec::new();
+//! let balance = RunningTotal::new(SumField::new(0, 5), SumField::new(6, 6)).rejects(&mut errors);
+//! let good: Vec<_> = Pipeline::new(records.into_iter()).stage(balance).collect();
+//!
+//! assert_eq!(good.len(), 2);
+//! assert_eq!(good[1].field(6, 6), "000150");
+//! assert_eq!(errors[0].field(0, 5), "12X45");
+//! assert_eq!(errors[0].field(80, 16).trim_end(), "NOTNUM 0,5");
+//! ```
+
+use std::fmt;
+
+use crate::Record;
+use crate::error::PipelineError;
+use crate::record::MAX_LRECL;
+use crate::sink::Sink;
+
+/// Columns appended to an error record for its reason.
+pub const REASON_WIDTH: usize = 16;
+
+/// Why a stage could not process a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// A numeric field holds something other than a number.
+    NotNumeric,
+    /// A result does not fit in its field.
+    Overflow,
+    /// A date field does not hold a real date.
+    InvalidDate,
+    /// A sealed field does not decode or authenticate under the key.
+    BadSeal,
+}
+
+impl Reason {
+    /// Returns the reason code written into error records.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Reason::NotNumeric => "NOTNUM",
+            Reason::Overflow => "OVERFLOW",
+            Reason::InvalidDate => "BADDATE",
+            Reason::BadSeal => "BADSEAL",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Returns `record` with `reason` and the `(start, len)` field appended.
+///
+/// The record grows by [`REASON_WIDTH`] columns; at [`MAX_LRECL`] the
+/// reason overwrites its last columns instead.
+pub fn error_record(record: &Record, reason: Reason, field: (usize, usize)) -> Record {
+    let lrecl = (record.lrecl() + REASON_WIDTH).min(MAX_LRECL);
+    let mut out = record.with_lrecl(lrecl);
+    let text = format!("{} {},{}", reason.code(), field.0, field.1);
+    out.set_field(lrecl - REASON_WIDTH, REASON_WIDTH, &text);
+    out
+}
+
+/// What a stage does with a record it cannot process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Use the record anyway, as the stage describes (DFSORT behavior).
+    #[default]
+    Ignore,
+    /// Send the record to the reject sink with its reason appended.
+    Reject,
+    /// Stop the stage, keeping the error.
+    Fail,
+}
+
+/// The error policy of a stage, with its reject sink and the error that
+/// stopped it.
+#[derive(Debug, Default)]
+pub struct Rejects<R = ()> {
+    policy: ErrorPolicy,
+    sink: R,
+    error: Option<PipelineError>,
+}
+
+impl Rejects {
+    /// Creates a policy whose rejected records are discarded.
+    pub fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            sink: (),
+            error: None,
+        }
+    }
+}
+
+impl<R: Sink> Rejects<R> {
+    /// Returns the policy.
+    pub fn policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    /// Sets the policy, keeping the sink.
+    pub fn with_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sends rejected records to `sink`, switching to [`ErrorPolicy::Reject`].
+    pub fn with_sink<S: Sink>(self, sink: S) -> Rejects<S> {
+        Rejects {
+            policy: ErrorPolicy::Reject,
+            sink,
+            error: self.error,
+        }
+    }
+
+    /// Handles a record the stage cannot process. Returns `true` if the
+    /// stage should use it anyway ([`ErrorPolicy::Ignore`]); otherwise
+    /// the record has been rejected or has stopped the stage.
+    pub fn reject(&mut self, record: &Record, reason: Reason, field: (usize, usize)) -> bool {
+        match self.policy {
+            ErrorPolicy::Ignore => true,
+            ErrorPolicy::Reject => {
+                self.sink.accept(&error_record(record, reason, field));
+                false
+            }
+            ErrorPolicy::Fail => {
+                self.error = Some(PipelineError::Stage(format!(
+                    "{reason} in field {},{} of '{}'",
+                    field.0,
+                    field.1,
+                    record.as_str().trim_end()
+                )));
+                false
+            }
+        }
+    }
+
+    /// Has a record stopped the stage?
+    pub fn stopped(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.error.as_ref()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.error.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_record() {
+        let record = Record::from_str("SMITH   ABC");
+        let out = error_record(&record, Reason::NotNumeric, (8, 3));
+        assert_eq!(out.lrecl(), 80 + REASON_WIDTH);
+        assert_eq!(out.field(0, 80), record.as_str());
+        assert_eq!(out.field(80, REASON_WIDTH).trim_end(), "NOTNUM 8,3");
+
+        let full = Record::blank(MAX_LRECL);
+        let out = error_record(&full, Reason::Overflow, (0, 5));
+        assert_eq!(out.lrecl(), MAX_LRECL);
+        assert!(out.as_str().ends_with("OVERFLOW 0,5    "));
+    }
+
+    #[test]
+    fn test_policies() {
+        let record = Record::from_str("X");
+        assert!(Rejects::new(ErrorPolicy::Ignore).reject(&record, Reason::NotNumeric, (0, 1)));
+
+        let mut sink = Vec::new();
+        let mut rejects = Rejects::new(ErrorPolicy::Ignore).with_sink(&mut sink);
+        assert_eq!(rejects.policy(), ErrorPolicy::Reject);
+        assert!(!rejects.reject(&record, Reason::Overflow, (0, 1)));
+        assert!(!rejects.stopped());
+        assert_eq!(sink.len(), 1);
+
+        let mut rejects = Rejects::new(ErrorPolicy::Fail);
+        assert!(!rejects.reject(&record, Reason::NotNumeric, (0, 1)));
+        assert!(rejects.stopped());
+        assert_eq!(
+            rejects.take_error().unwrap().to_string(),
+            "stage error: NOTNUM in field 0,1 of 'X'"
+        );
+        assert!(rejects.error().is_none());
+    }
+}
@@ -0,0 +1,291 @@
+//! Control-break reporting.
+//!
+//! A [`ControlBreak`] passes sorted detail records through and, whenever
+//! the key fields change, emits a subtotal record for the group that just
+//! ended. After the last record it emits a final subtotal and a grand-total
+//! trailer. This is the classic batch report loop: compare the key with the
+//! previous record's, print totals on a change, and add up as you go.
+//!
+//! Subtotal records hold the group's key fields in their own columns, so
+//! they line up under the detail lines, plus the configured totals. The
+//! input must be sorted (or at least grouped) by the key; a key that comes
+//! back later starts a new group.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{ControlBreak, Pipeline, Record, SumField};
+//!
+//! let records = vec![
+//!     Record::from_str("DOE     JANE      SALES     00060000"),
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//! ];
+//!
+//! let report: Vec<_> = Pipeline::new(records.into_iter())
+//!     .break_on(
+//!         ControlBreak::new(vec![(18, 10)])
+//!             .total(SumField::new(28, 8), 28, 9)
+//!             .subtotal_label(0, "SUBTOTAL")
+//!             .grand_total_label(0, "TOTAL"),
+//!     )
+//!     .collect();
+//!
+//! let lines: Vec<_> = report.iter().map(|r| r.as_str().trim_end()).collect();
+//! assert_eq!(lines, [
+//!     "DOE     JANE      SALES     00060000",
+//!     "SMITH   JOHN      SALES     00050000",
+//!     "SUBTOTAL          SALES     000110000",
+//!     "JONES   MARY      ENGINEER  00075000",
+//!     "SUBTOTAL          ENGINEER  000075000",
+//!     "TOTAL                       000185000",
+//! ]);
+//! ```
+
+use crate::group::write_total;
+use crate::stage::{SumField, key_of};
+use crate::{RECORD_WIDTH, Record};
+
+/// A field totalled by a [`ControlBreak`] and where its total is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BreakTotal {
+    field: SumField,
+    at: (usize, usize),
+}
+
+/// Control-break configuration and running totals.
+///
+/// [`ControlBreak::push`] and [`ControlBreak::finish`] drive it one record
+/// at a time; `Pipeline::break_on` runs it over a pipeline.
+#[derive(Debug, Clone)]
+pub struct ControlBreak {
+    keys: Vec<(usize, usize)>,
+    totals: Vec<BreakTotal>,
+    count_at: Option<(usize, usize)>,
+    subtotal_label: Option<(usize, String)>,
+    grand_total_label: Option<(usize, String)>,
+    /// Key and a copy of the first record of the open group.
+    group: Option<(String, Record)>,
+    group_sums: Vec<i128>,
+    group_count: usize,
+    grand_sums: Vec<i128>,
+    grand_count: usize,
+    lrecl: usize,
+}
+
+impl ControlBreak {
+    /// Creates a control break on the `(start, len)` key fields, with no
+    /// totals yet.
+    pub fn new(keys: Vec<(usize, usize)>) -> Self {
+        Self {
+            keys,
+            totals: Vec::new(),
+            count_at: None,
+            subtotal_label: None,
+            grand_total_label: None,
+            group: None,
+            group_sums: Vec::new(),
+            group_count: 0,
+            grand_sums: Vec::new(),
+            grand_count: 0,
+            lrecl: RECORD_WIDTH,
+        }
+    }
+
+    /// Totals `field`, writing the totals into `start, len` of the total
+    /// records. Blank fields count as zero and fields that are not numbers
+    /// are skipped; a total too wide for its columns is written as
+    /// asterisks.
+    pub fn total(mut self, field: SumField, start: usize, len: usize) -> Self {
+        self.totals.push(BreakTotal {
+            field,
+            at: (start, len),
+        });
+        self.group_sums.push(0);
+        self.grand_sums.push(0);
+        self
+    }
+
+    /// Writes the number of records into `start, len` of the total records.
+    pub fn count(mut self, start: usize, len: usize) -> Self {
+        self.count_at = Some((start, len));
+        self
+    }
+
+    /// Writes `text` at column `pos` of each subtotal record.
+    pub fn subtotal_label(mut self, pos: usize, text: &str) -> Self {
+        self.subtotal_label = Some((pos, text.to_string()));
+        self
+    }
+
+    /// Writes `text` at column `pos` of the grand-total record.
+    pub fn grand_total_label(mut self, pos: usize, text: &str) -> Self {
+        self.grand_total_label = Some((pos, text.to_string()));
+        self
+    }
+
+    /// Adds a detail record, returning the subtotal of the previous group
+    /// if the record starts a new one. The subtotal comes before `record`
+    /// in the report.
+    pub fn push(&mut self, record: &Record) -> Option<Record> {
+        let key = key_of(&self.keys, record);
+        let subtotal = match &self.group {
+            Some((open, _)) if *open != key => self.close_group(),
+            _ => None,
+        };
+        if self.group.is_none() {
+            self.group = Some((key, record.clone()));
+        }
+        self.lrecl = record.lrecl();
+        self.group_count += 1;
+        self.grand_count += 1;
+        for (i, total) in self.totals.iter().enumerate() {
+            if let Some(value) = total.field.read(record) {
+                self.group_sums[i] += i128::from(value);
+                self.grand_sums[i] += i128::from(value);
+            }
+        }
+        subtotal
+    }
+
+    /// Ends the report: returns the last group's subtotal, if any, and the
+    /// grand total, then resets for another report.
+    pub fn finish(&mut self) -> Vec<Record> {
+        let mut records: Vec<Record> = self.close_group().into_iter().collect();
+        let mut grand = Record::blank(self.lrecl);
+        if let Some((pos, text)) = &self.grand_total_label {
+            grand.set_field(*pos, text.len(), text);
+        }
+        let sums = std::mem::replace(&mut self.grand_sums, vec![0; self.totals.len()]);
+        self.write_totals(&mut grand, &sums, self.grand_count);
+        self.grand_count = 0;
+        records.push(grand);
+        records
+    }
+
+    /// Builds the subtotal record of the open group and resets it.
+    fn close_group(&mut self) -> Option<Record> {
+        let (_, first) = self.group.take()?;
+        let mut subtotal = Record::blank(first.lrecl());
+        if let Some((pos, text)) = &self.subtotal_label {
+            subtotal.set_field(*pos, text.len(), text);
+        }
+        for &(start, len) in &self.keys {
+            subtotal.set_field(start, len, first.field(start, len));
+        }
+        let sums = std::mem::replace(&mut self.group_sums, vec![0; self.totals.len()]);
+        self.write_totals(&mut subtotal, &sums, self.group_count);
+        self.group_count = 0;
+        Some(subtotal)
+    }
+
+    fn write_totals(&self, record: &mut Record, sums: &[i128], count: usize) {
+        for (total, &sum) in self.totals.iter().zip(sums) {
+            write_total(record, total.at.0, total.at.1, sum);
+        }
+        if let Some((start, len)) = self.count_at {
+            write_total(record, start, len, count as i128);
+        }
+    }
+}
+
+/// Iterator over a control-break report, from `Pipeline::break_on`.
+pub struct BreakIter<I> {
+    inner: I,
+    control: ControlBreak,
+    pending: Vec<Record>,
+    done: bool,
+}
+
+impl<I> BreakIter<I> {
+    pub(crate) fn new(inner: I, control: ControlBreak) -> Self {
+        Self {
+            inner,
+            control,
+            pending: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Record>> Iterator for BreakIter<I> {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        if !self.pending.is_empty() {
+            return Some(self.pending.remove(0));
+        }
+        if self.done {
+            return None;
+        }
+        match self.inner.next() {
+            Some(record) => match self.control.push(&record) {
+                Some(subtotal) => {
+                    self.pending.push(record);
+                    Some(subtotal)
+                }
+                None => Some(record),
+            },
+            None => {
+                self.done = true;
+                self.pending = self.control.finish();
+                self.next()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(control: ControlBreak, lines: &[&str]) -> Vec<String> {
+        BreakIter::new(lines.iter().map(|s| Record::from_str(s)), control)
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_control_break() {
+        let control = ControlBreak::new(vec![(0, 1)])
+            .total(SumField::new(2, 3), 6, 4)
+            .count(11, 2)
+            .grand_total_label(0, "*");
+        let lines = report(control, &["A 010", "A 005", "B 100", "C XXX", "C -20"]);
+        assert_eq!(
+            lines,
+            [
+                "A 010",
+                "A 005",
+                "A     0015 02",
+                "B 100",
+                "B     0100 01",
+                "C XXX",
+                "C -20",
+                "C     -020 02",
+                "*     0095 05",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_input_has_grand_total() {
+        let control = ControlBreak::new(vec![(0, 1)]).count(0, 3);
+        assert_eq!(report(control, &[]), ["000"]);
+    }
+
+    #[test]
+    fn test_push_and_finish() {
+        let mut control = ControlBreak::new(vec![(0, 1)]).total(SumField::new(2, 2), 2, 2);
+        assert!(control.push(&Record::from_str("A 99")).is_none());
+        let subtotal = control.push(&Record::from_str("B 01")).unwrap();
+        assert_eq!(subtotal.as_str().trim_end(), "A 99");
+        let end = control.finish();
+        assert_eq!(end.len(), 2);
+        assert_eq!(end[1].as_str().trim_end(), "  **");
+
+        // Totals start over for the next report
+        control.push(&Record::from_str("A 05"));
+        assert_eq!(control.finish()[1].as_str().trim_end(), "  05");
+    }
+}
@@ -0,0 +1,153 @@
+//! Side channels for copies of records.
+//!
+//! [`Pipeline::tee`](crate::Pipeline::tee) copies every record into a
+//! [`Sink`] while the main stream continues, e.g. to keep an audit copy of
+//! the records reaching a stage. A `Vec<Record>` collects them, an
+//! `mpsc` sender passes them to another thread, and a [`WriteSink`]
+//! writes them as text lines to any `io::Write`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Pipeline, Record};
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//! ];
+//!
+//! let mut audit = Vec::new();
+//! let sales: Vec<_> = Pipeline::new(records.into_iter())
+//!     .tee(&mut audit)
+//!     .filter(|r| r.field_eq(18, 10, "SALES"))
+//!     .collect();
+//!
+//! assert_eq!(audit.len(), 2);
+//! assert_eq!(sales.len(), 1);
+//! ```
+
+use std::io::{self, Write};
+use std::sync::mpsc::{Sender, SyncSender};
+
+use crate::Record;
+
+/// Receives copies of records from [`Pipeline::tee`](crate::Pipeline::tee).
+pub trait Sink {
+    /// Takes a copy of one record.
+    fn accept(&mut self, record: &Record);
+}
+
+/// Discards every record; the default reject output of a
+/// [`Filter`](crate::Filter).
+impl Sink for () {
+    fn accept(&mut self, _record: &Record) {}
+}
+
+impl Sink for Vec<Record> {
+    fn accept(&mut self, record: &Record) {
+        self.push(record.clone());
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn accept(&mut self, record: &Record) {
+        (**self).accept(record);
+    }
+}
+
+/// Sends each record; records sent after the receiver is dropped are lost.
+impl Sink for Sender<Record> {
+    fn accept(&mut self, record: &Record) {
+        let _ = self.send(record.clone());
+    }
+}
+
+/// Sends each record, blocking while the channel is full; records sent
+/// after the receiver is dropped are lost.
+impl Sink for SyncSender<Record> {
+    fn accept(&mut self, record: &Record) {
+        let _ = self.send(record.clone());
+    }
+}
+
+/// Writes records to an `io::Write` as lines without trailing blanks.
+///
+/// Writing stops at the first error, which [`WriteSink::finish`] returns.
+/// Pass the sink by `&mut` to call `finish` once the pipeline is done.
+pub struct WriteSink<W: Write> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> WriteSink<W> {
+    /// Creates a sink writing to `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner, error: None }
+    }
+
+    /// Flushes the writer and returns it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from writing or flushing.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Sink for WriteSink<W> {
+    fn accept(&mut self, record: &Record) {
+        if self.error.is_none()
+            && let Err(e) = writeln!(self.inner, "{}", record.as_str().trim_end())
+        {
+            self.error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_sink() {
+        let (mut tx, rx) = std::sync::mpsc::channel();
+        tx.accept(&Record::from_str("ONE"));
+        drop(tx);
+        assert_eq!(rx.iter().count(), 1);
+
+        let (mut tx, rx) = std::sync::mpsc::channel::<Record>();
+        drop(rx);
+        tx.accept(&Record::from_str("LOST"));
+    }
+
+    #[test]
+    fn test_write_sink() {
+        let mut sink = WriteSink::new(Vec::new());
+        sink.accept(&Record::from_str("ONE"));
+        sink.accept(&Record::from_str("TWO   "));
+        assert_eq!(sink.finish().unwrap(), b"ONE\nTWO\n");
+    }
+
+    #[test]
+    fn test_write_sink_keeps_first_error() {
+        struct Full;
+        impl Write for Full {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::StorageFull, "disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut sink = WriteSink::new(Full);
+        sink.accept(&Record::from_str("ONE"));
+        sink.accept(&Record::from_str("TWO"));
+        let err = sink.finish().err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+    }
+}
@@ -0,0 +1,384 @@
+//! Building records field by field (CMS Pipelines `SPECS`).
+//!
+//! A [`Specs`] stage builds each output record from a list of
+//! [`SpecItem`]s. Each item takes an input column range, a literal, or the
+//! record number, optionally converts it, and places it at a column, right
+//! after the previous item (`NEXT`), or one blank after it (`NEXTWORD`).
+//!
+//! Records are character data, so there is no CMS `C2D` or `D2C`, which
+//! convert between binary integers and decimal text. The number
+//! conversions work on numbers written as text instead: `ZEROPAD`
+//! right-aligns a number zero-padded to the output width (`"  42"` becomes
+//! `"0042"`), and `UNPAD` writes it plainly, without padding (`"0042"`
+//! becomes `"42"`). Blank fields count as zero. A value that is not a
+//! number, or does not fit, is written as asterisks.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Conversion, Record, SpecItem, Specs, Stage};
+//!
+//! let mut specs = Specs::new(vec![
+//!     SpecItem::range(8, 10).convert(Conversion::Strip).at(0),
+//!     SpecItem::range(0, 8).convert(Conversion::Strip).next_word(),
+//!     SpecItem::literal("earns").next_word(),
+//!     SpecItem::range(28, 8).convert(Conversion::Unpad).next_word(),
+//! ]);
+//!
+//! let record = specs
+//!     .process(Record::from_str("SMITH   JOHN      SALES     00050000"))
+//!     .unwrap();
+//! assert_eq!(record.as_str().trim_end(), "JOHN SMITH earns 50000");
+//! ```
+
+use crate::Record;
+use crate::stage::Stage;
+
+/// Width of a record number (`RECNO`).
+const RECNO_WIDTH: usize = 10;
+
+/// Where a [`SpecItem`] takes its value from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecInput {
+    /// The `(start, len)` input columns.
+    Range(usize, usize),
+    /// Literal text.
+    Literal(String),
+    /// The record number, counting from 1, right-aligned in the output
+    /// width (10 columns by default).
+    Recno,
+}
+
+/// How a [`SpecItem`] converts its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conversion {
+    /// Number to zero-padded decimal, right-aligned in the output width.
+    ZeroPad,
+    /// Zero-padded decimal to a plain number.
+    Unpad,
+    /// Uppercase.
+    Upper,
+    /// Lowercase.
+    Lower,
+    /// Remove leading and trailing blanks.
+    Strip,
+}
+
+impl Conversion {
+    /// Returns the DSL keyword.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Conversion::ZeroPad => "ZEROPAD",
+            Conversion::Unpad => "UNPAD",
+            Conversion::Upper => "UPPER",
+            Conversion::Lower => "LOWER",
+            Conversion::Strip => "STRIP",
+        }
+    }
+
+    /// Parses a DSL keyword (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Conversion::ZeroPad,
+            Conversion::Unpad,
+            Conversion::Upper,
+            Conversion::Lower,
+            Conversion::Strip,
+        ]
+        .into_iter()
+        .find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Returns the error for CMS `C2D` or `D2C` as a conversion `name`: they
+/// convert binary integers, which character records do not hold.
+pub(crate) fn binary_conversion_error(name: &str) -> Option<String> {
+    let hint = match name.to_ascii_uppercase().as_str() {
+        "C2D" => "ZEROPAD",
+        "D2C" => "UNPAD",
+        _ => return None,
+    };
+    Some(format!(
+        "SPECS {name} converts binary data, which records do not hold; \
+         use {hint} for numbers written as text"
+    ))
+}
+
+/// Where a [`SpecItem`] goes in the output record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// At a column.
+    Column(usize),
+    /// Right after the previous item.
+    Next,
+    /// One blank after the previous item (at column 0 if it is the first).
+    NextWord,
+}
+
+/// One field of a [`Specs`] output record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecItem {
+    /// Where the value comes from.
+    pub input: SpecInput,
+    /// How the value is converted.
+    pub conversion: Option<Conversion>,
+    /// Where the value goes.
+    pub placement: Placement,
+    /// Output width; the converted value's width if `None`.
+    pub width: Option<usize>,
+}
+
+impl SpecItem {
+    fn of(input: SpecInput) -> Self {
+        Self {
+            input,
+            conversion: None,
+            placement: Placement::Next,
+            width: None,
+        }
+    }
+
+    /// Copies the `(start, len)` input columns.
+    pub fn range(start: usize, len: usize) -> Self {
+        Self::of(SpecInput::Range(start, len))
+    }
+
+    /// Writes literal text.
+    pub fn literal(text: &str) -> Self {
+        Self::of(SpecInput::Literal(text.to_string()))
+    }
+
+    /// Writes the record number.
+    pub fn recno() -> Self {
+        Self::of(SpecInput::Recno)
+    }
+
+    /// Converts the value.
+    pub fn convert(mut self, conversion: Conversion) -> Self {
+        self.conversion = Some(conversion);
+        self
+    }
+
+    /// Places the value at column `col`.
+    pub fn at(mut self, col: usize) -> Self {
+        self.placement = Placement::Column(col);
+        self
+    }
+
+    /// Places the value right after the previous item (the default).
+    pub fn next(mut self) -> Self {
+        self.placement = Placement::Next;
+        self
+    }
+
+    /// Places the value one blank after the previous item.
+    pub fn next_word(mut self) -> Self {
+        self.placement = Placement::NextWord;
+        self
+    }
+
+    /// Pads or truncates the value to `width` columns.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Returns the output width if it does not depend on the data: the
+    /// given width, or else the input's unless a conversion changes it.
+    pub fn fixed_width(&self) -> Option<usize> {
+        if self.width.is_some() {
+            return self.width;
+        }
+        match (&self.input, self.conversion) {
+            (_, Some(Conversion::Unpad | Conversion::Strip)) => None,
+            (SpecInput::Range(_, len), _) => Some(*len),
+            (SpecInput::Literal(text), _) => Some(text.len()),
+            (SpecInput::Recno, _) => Some(RECNO_WIDTH),
+        }
+    }
+
+    /// Computes the value for `record`, the `recno`th record.
+    fn value(&self, record: &Record, recno: u64) -> String {
+        let raw = match &self.input {
+            SpecInput::Range(start, len) => record.field(*start, *len).to_string(),
+            SpecInput::Literal(text) => text.clone(),
+            SpecInput::Recno => {
+                let width = self.width.unwrap_or(RECNO_WIDTH);
+                format!("{recno:>width$}")
+            }
+        };
+        match self.conversion {
+            None => raw,
+            Some(Conversion::Upper) => raw.to_uppercase(),
+            Some(Conversion::Lower) => raw.to_lowercase(),
+            Some(Conversion::Strip) => raw.trim().to_string(),
+            Some(Conversion::ZeroPad) => {
+                let width = self.width.unwrap_or(raw.len());
+                match number(&raw) {
+                    Some(n) => {
+                        let sign = if n < 0 { "-" } else { "" };
+                        let digits = n.unsigned_abs().to_string();
+                        let pad = width.saturating_sub(sign.len());
+                        if sign.len() + digits.len() > width {
+                            "*".repeat(width)
+                        } else {
+                            format!("{sign}{digits:0>pad$}")
+                        }
+                    }
+                    None => "*".repeat(width),
+                }
+            }
+            Some(Conversion::Unpad) => match number(&raw) {
+                Some(n) => n.to_string(),
+                None => "*".repeat(raw.len()),
+            },
+        }
+    }
+}
+
+/// Reads a whole number with an optional sign; blank text is zero.
+fn number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Specs stage - builds each record from literals, input fields, and the
+/// record number (CMS Pipelines `SPECS`).
+///
+/// Output records have the input's length and start blank; values past the
+/// end are cut off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Specs {
+    items: Vec<SpecItem>,
+    records: u64,
+}
+
+impl Specs {
+    /// Creates a stage building records from `items`, in order.
+    pub fn new(items: Vec<SpecItem>) -> Self {
+        Self { items, records: 0 }
+    }
+
+    /// Returns the items.
+    pub fn items(&self) -> &[SpecItem] {
+        &self.items
+    }
+
+    /// Returns the number of records built so far.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Sets the number of records built so far, which numbers the next,
+    /// as when resuming a run from a checkpoint.
+    pub fn set_records(&mut self, records: u64) {
+        self.records = records;
+    }
+
+    /// Builds the output record for `record`, the `recno`th record.
+    pub fn build(&self, record: &Record, recno: u64) -> Record {
+        let mut output = Record::blank(record.lrecl());
+        let mut next = 0;
+        for item in &self.items {
+            let value = item.value(record, recno);
+            let width = item.width.unwrap_or(value.len());
+            let start = match item.placement {
+                Placement::Column(col) => col,
+                Placement::Next => next,
+                Placement::NextWord if next == 0 => 0,
+                Placement::NextWord => next + 1,
+            };
+            output.set_field(start, width, &value);
+            next = start + width;
+        }
+        output
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        self.records += 1;
+        Some(self.build(&record, self.records))
+    }
+}
+
+impl Stage for Specs {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(items: Vec<SpecItem>, text: &str) -> String {
+        Specs::new(items)
+            .process(Record::from_str(text))
+            .unwrap()
+            .as_str()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn test_specs_placement() {
+        let items = vec![
+            SpecItem::literal("ID:").at(2),
+            SpecItem::range(0, 3),
+            SpecItem::range(4, 3).next_word(),
+            SpecItem::range(0, 3).at(20).width(5),
+            SpecItem::literal("|").next(),
+        ];
+        assert_eq!(build(items, "ABC DEF"), "  ID:ABC DEF        ABC  |");
+    }
+
+    #[test]
+    fn test_specs_conversions() {
+        let items = vec![
+            SpecItem::range(0, 4).convert(Conversion::ZeroPad).at(0),
+            SpecItem::range(5, 4).convert(Conversion::Unpad).next_word(),
+            SpecItem::range(10, 3)
+                .convert(Conversion::Upper)
+                .next_word(),
+            SpecItem::range(10, 3)
+                .convert(Conversion::Lower)
+                .next_word(),
+            SpecItem::range(0, 4)
+                .convert(Conversion::ZeroPad)
+                .width(1)
+                .next_word(),
+            SpecItem::range(10, 3)
+                .convert(Conversion::ZeroPad)
+                .next_word(),
+        ];
+        assert_eq!(build(items, "  42 -007 aBc"), "0042 -7 ABC abc * ***");
+    }
+
+    #[test]
+    fn test_specs_recno() {
+        let mut specs = Specs::new(vec![
+            SpecItem::recno().at(0),
+            SpecItem::range(0, 1).next_word(),
+        ]);
+        specs.process(Record::from_str("A"));
+        let second = specs.process(Record::from_str("B")).unwrap();
+        assert_eq!(second.as_str().trim_end(), "         2 B");
+    }
+
+    #[test]
+    fn test_conversion_names() {
+        assert_eq!(Conversion::from_name("zeropad"), Some(Conversion::ZeroPad));
+        assert_eq!(Conversion::from_name("C2D"), None);
+        assert!(binary_conversion_error("d2c").unwrap().contains("UNPAD"));
+        assert_eq!(binary_conversion_error("STRIP"), None);
+        assert_eq!(Conversion::from_name("STRIP").unwrap().name(), "STRIP");
+        assert_eq!(Conversion::from_name("X2C"), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! Fanning one record stream out to several named outputs.
+//!
+//! A [`Split`] is a list of [`OutFile`]s, like DFSORT `OUTFIL` statements.
+//! Each output receives every record that meets its include condition,
+//! optionally rebuilt into a new layout; a record can go to any number of
+//! outputs. A [`OutFile::save`] output receives the records that no other
+//! output included, like `OUTFIL SAVE`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{OutFile, Pipeline, Record, Split};
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//!     Record::from_str("DOE     JANE      SALES     00060000"),
+//! ];
+//!
+//! let outputs = Pipeline::new(records.into_iter()).split(
+//!     Split::new()
+//!         .output(OutFile::new("SALES").include(|r: &Record| r.field_eq(18, 10, "SALES")))
+//!         .output(OutFile::new("OTHER").save()),
+//! );
+//!
+//! assert_eq!(outputs.get("SALES").unwrap().len(), 2);
+//! assert!(outputs.get("OTHER").unwrap()[0].field_eq(0, 8, "JONES"));
+//! ```
+
+use crate::Record;
+use crate::stage::Stage;
+
+type Predicate = Box<dyn Fn(&Record) -> bool>;
+type Transform = Box<dyn Fn(&Record) -> Record>;
+
+/// One named output of a [`Split`].
+pub struct OutFile {
+    name: String,
+    include: Option<Predicate>,
+    build: Option<Transform>,
+    save: bool,
+}
+
+impl OutFile {
+    /// Creates an output that receives every record.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            include: None,
+            build: None,
+            save: false,
+        }
+    }
+
+    /// Receives only records matching `predicate` (`INCLUDE=`).
+    pub fn include<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Record) -> bool + 'static,
+    {
+        self.include = Some(Box::new(predicate));
+        self
+    }
+
+    /// Rebuilds each record written to this output (`BUILD=`).
+    pub fn build<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&Record) -> Record + 'static,
+    {
+        self.build = Some(Box::new(transform));
+        self
+    }
+
+    /// Receives only records that no other non-`save` output included
+    /// (`SAVE`). Any include condition still applies.
+    pub fn save(mut self) -> Self {
+        self.save = true;
+        self
+    }
+
+    /// Returns the output name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn includes(&self, record: &Record) -> bool {
+        self.include.as_ref().is_none_or(|f| f(record))
+    }
+
+    fn write(&self, record: &Record) -> Record {
+        match &self.build {
+            Some(build) => build(record),
+            None => record.clone(),
+        }
+    }
+}
+
+/// Records written to each named output, in order of first use.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SplitOutputs {
+    outputs: Vec<(String, Vec<Record>)>,
+}
+
+impl SplitOutputs {
+    /// Creates an empty set of outputs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends records to the named output, creating it if needed.
+    pub fn append(&mut self, name: &str, records: impl IntoIterator<Item = Record>) {
+        match self.outputs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => existing.extend(records),
+            None => self
+                .outputs
+                .push((name.to_string(), records.into_iter().collect())),
+        }
+    }
+
+    /// Returns the records of the named output.
+    pub fn get(&self, name: &str) -> Option<&[Record]> {
+        self.outputs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, records)| records.as_slice())
+    }
+
+    /// Returns the output names.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns each output's name and records.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[Record])> {
+        self.outputs
+            .iter()
+            .map(|(name, records)| (name.as_str(), records.as_slice()))
+    }
+
+    /// Returns true if there are no outputs.
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+}
+
+/// Split stage - copies records to named outputs.
+///
+/// As a [`Stage`], every record also passes through unchanged, like the
+/// main `SORTOUT` output alongside `OUTFIL`s. [`Split::finish`] returns the
+/// named outputs.
+#[derive(Default)]
+pub struct Split {
+    outfiles: Vec<OutFile>,
+    outputs: SplitOutputs,
+}
+
+impl Split {
+    /// Creates a split with no outputs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an output.
+    pub fn output(mut self, outfile: OutFile) -> Self {
+        self.outputs.append(outfile.name(), []);
+        self.outfiles.push(outfile);
+        self
+    }
+
+    /// Copies `record` to each output that includes it.
+    pub fn route(&mut self, record: &Record) {
+        let mut included = false;
+        for outfile in self.outfiles.iter().filter(|o| !o.save) {
+            if outfile.includes(record) {
+                included = true;
+                self.outputs.append(&outfile.name, [outfile.write(record)]);
+            }
+        }
+        if !included {
+            for outfile in self.outfiles.iter().filter(|o| o.save) {
+                if outfile.includes(record) {
+                    self.outputs.append(&outfile.name, [outfile.write(record)]);
+                }
+            }
+        }
+    }
+
+    /// Returns the named outputs, leaving them empty.
+    pub fn finish(&mut self) -> SplitOutputs {
+        let mut empty = SplitOutputs::new();
+        for outfile in &self.outfiles {
+            empty.append(outfile.name(), []);
+        }
+        std::mem::replace(&mut self.outputs, empty)
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        self.route(&record);
+        Some(record)
+    }
+}
+
+impl Stage for Split {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(records: Option<&[Record]>) -> Vec<&str> {
+        records
+            .unwrap()
+            .iter()
+            .map(|r| r.field(0, 8).trim())
+            .collect()
+    }
+
+    fn split() -> Split {
+        Split::new()
+            .output(OutFile::new("SALES").include(|r: &Record| r.field_eq(18, 10, "SALES")))
+            .output(
+                OutFile::new("HIGH")
+                    .include(|r: &Record| r.field(28, 8) >= "00060000")
+                    .build(|r: &Record| Record::from_str(r.field(0, 8))),
+            )
+            .output(OutFile::new("REST").save())
+    }
+
+    #[test]
+    fn test_split_routes_records() {
+        let mut split = split();
+        for line in [
+            "SMITH   JOHN      SALES     00050000",
+            "JONES   MARY      ENGINEER  00075000",
+            "DOE     JANE      SALES     00060000",
+            "WILSON  BOB       MARKETING 00055000",
+        ] {
+            let record = Record::from_str(line);
+            assert_eq!(split.process(record.clone()), Some(record));
+        }
+
+        let outputs = split.finish();
+        assert_eq!(
+            outputs.names().collect::<Vec<_>>(),
+            ["SALES", "HIGH", "REST"]
+        );
+        assert_eq!(names(outputs.get("SALES")), ["SMITH", "DOE"]);
+        assert_eq!(names(outputs.get("HIGH")), ["JONES", "DOE"]);
+        assert_eq!(outputs.get("HIGH").unwrap()[0].as_str().trim_end(), "JONES");
+        assert_eq!(names(outputs.get("REST")), ["WILSON"]);
+        assert!(outputs.get("NONE").is_none());
+        assert!(split.finish().get("SALES").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_outputs_with_same_name_merge() {
+        let mut outputs = SplitOutputs::new();
+        outputs.append("A", [Record::from_str("1")]);
+        outputs.append("B", []);
+        outputs.append("A", [Record::from_str("2")]);
+        assert_eq!(outputs.iter().count(), 2);
+        assert_eq!(outputs.get("A").unwrap().len(), 2);
+    }
+}
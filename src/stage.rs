@@ -5,38 +5,115 @@
 //!
 //! ## Common Mainframe Operations
 //!
-//! - **Filter**: Select records matching criteria (like DFSORT INCLUDE/OMIT)
+//! - **Filter**: Select records matching criteria (like DFSORT INCLUDE/OMIT), optionally
+//!   sending the rest to a reject [`Sink`]
+//! - **Change**: Replace a string within records (like CMS Pipelines CHANGE)
+//! - **Locate**: Keep or drop records containing a string (like CMS Pipelines LOCATE/NLOCATE)
+//! - **Between**: Keep the sections from a start marker to an end marker (like CMS Pipelines
+//!   BETWEEN)
 //! - **Select**: Extract specific columns from records
+//! - **Overlay**: Copy fields or literals into columns of the existing record (like DFSORT
+//!   OVERLAY)
 //! - **Reformat**: Rearrange fields into a new layout (like DFSORT OUTREC)
+//! - **Sort**: Order records by key fields (like DFSORT SORT FIELDS)
+//! - **Dedup**: Drop records with duplicate keys (like DFSORT SUM FIELDS=NONE)
+//! - **SumBy**: Total numeric fields of records with equal keys (like DFSORT SUM FIELDS)
+//! - **Seqnum**: Number records in a column range (like DFSORT SEQNUM)
+//! - **RunningTotal**: Write the cumulative total of a numeric field into each record
+//! - **Literal**: Write a constant record ahead of the input (like CMS Pipelines LITERAL)
+//! - **Console**: Echo records to stderr as they pass (like CMS Pipelines CONSOLE)
+//! - **Dump**: Write records to stderr as hex dumps or under a column ruler as they pass
+//! - **Pad** / **Trunc** / **Squish**: Fill a record out to a length, blank it from a column,
+//!   or squeeze runs of blanks (like CMS Pipelines PAD, CHOP, and SQUISH)
+//! - **TakeLast** / **DropLast**: Keep or drop the last n records (like CMS Pipelines
+//!   TAKE LAST/DROP LAST)
+//! - **Recno**: Keep records by input position, in ranges with optional steps
+//! - **Sample**: Keep a reproducible random fraction of the records
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
 use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::reject::{ErrorPolicy, Reason, Rejects};
+use crate::sink::Sink;
 
 /// A pipeline stage that processes records.
 ///
-/// Stages can transform, filter, or otherwise modify records. The `process`
-/// method returns `Some(record)` to pass a record downstream, or `None` to
-/// filter it out.
+/// Stages can transform, filter, or otherwise modify records. The
+/// `process_into` method appends the records a stage writes for each one
+/// it reads: none to filter it out, one to pass it on, or several to
+/// explode it. Stages that write at most one record for each also have an
+/// inherent `process` method returning that record, which their
+/// `process_into` appends.
 pub trait Stage {
-    /// Process a single record.
+    /// Process a single record, appending zero, one, or many records to
+    /// `output`.
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>);
+
+    /// Ends the input, returning any records the stage has held back.
+    ///
+    /// Stateful stages (sorting, deduplication, aggregation) emit their
+    /// buffered records here. A flushed stage is empty and can take new
+    /// input. The default holds nothing and returns no records.
+    fn flush(&mut self) -> Vec<Record> {
+        Vec::new()
+    }
+
+    /// Process a block of records in place, replacing them with the
+    /// stage's output for them, in order.
     ///
-    /// Returns `Some(record)` to pass the record downstream, or `None` to
-    /// filter it out.
-    fn process(&mut self, record: Record) -> Option<Record>;
+    /// Unlike `process_batch`, this does not flush the stage, so one block
+    /// can follow another. The default calls `process_into` for each
+    /// record; filters override it to keep matching records where they
+    /// are, with one call per block instead of one per record.
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        let input = std::mem::replace(records, Vec::with_capacity(records.len()));
+        for record in input {
+            self.process_into(record, records);
+        }
+    }
 
     /// Process a batch of records.
     ///
-    /// Default implementation processes records one at a time.
+    /// Default implementation processes records one at a time with
+    /// `process_into`, then flushes the stage.
     fn process_batch(&mut self, records: Vec<Record>) -> Vec<Record> {
-        records
-            .into_iter()
-            .filter_map(|r| self.process(r))
-            .collect()
+        let mut output = Vec::with_capacity(records.len());
+        for record in records {
+            self.process_into(record, &mut output);
+        }
+        output.extend(self.flush());
+        output
+    }
+}
+
+/// A boxed stage, such as a `PLUGIN` stage or one chosen at run time, is a
+/// stage too.
+impl<S: Stage + ?Sized> Stage for Box<S> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        (**self).process_into(record, output);
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        (**self).flush()
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        (**self).process_block(records);
+    }
+
+    fn process_batch(&mut self, records: Vec<Record>) -> Vec<Record> {
+        (**self).process_batch(records)
     }
 }
 
 /// Filter stage - selects records matching a predicate.
 ///
-/// This is similar to DFSORT's INCLUDE/OMIT operations.
+/// This is similar to DFSORT's INCLUDE/OMIT operations. Records that fail
+/// the predicate are dropped, or go to the secondary output set with
+/// [`Filter::rejects`] (like a labeled CMS Pipelines selection stage).
 ///
 /// # Example
 ///
@@ -51,11 +128,12 @@ pub trait Stage {
 /// assert!(filter.process(sales).is_some());
 /// assert!(filter.process(eng).is_none());
 /// ```
-pub struct Filter<F>
+pub struct Filter<F, R = ()>
 where
     F: FnMut(&Record) -> bool,
 {
     predicate: F,
+    rejects: R,
 }
 
 impl<F> Filter<F>
@@ -64,23 +142,73 @@ where
 {
     /// Creates a new filter stage with the given predicate.
     pub fn new(predicate: F) -> Self {
-        Self { predicate }
+        Self {
+            predicate,
+            rejects: (),
+        }
+    }
+
+    /// Sends the records that fail the predicate to `sink` instead of
+    /// dropping them, e.g. to keep an exceptions file.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use pipelines_rs::{Filter, Record, Stage};
+    ///
+    /// let mut exceptions = Vec::new();
+    /// let mut filter =
+    ///     Filter::new(|r: &Record| r.field_eq(18, 10, "SALES")).rejects(&mut exceptions);
+    ///
+    /// assert!(filter.process(Record::from_str("SMITH   JOHN      SALES     ")).is_some());
+    /// assert!(filter.process(Record::from_str("JONES   MARY      ENGINEER  ")).is_none());
+    /// assert_eq!(exceptions[0].field(0, 8).trim(), "JONES");
+    /// ```
+    pub fn rejects<R: Sink>(self, sink: R) -> Filter<F, R> {
+        Filter {
+            predicate: self.predicate,
+            rejects: sink,
+        }
     }
 }
 
-impl<F> Stage for Filter<F>
+impl<F, R> Filter<F, R>
 where
     F: FnMut(&Record) -> bool,
+    R: Sink,
 {
-    fn process(&mut self, record: Record) -> Option<Record> {
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
         if (self.predicate)(&record) {
             Some(record)
         } else {
+            self.rejects.accept(&record);
             None
         }
     }
 }
 
+impl<F, R> Stage for Filter<F, R>
+where
+    F: FnMut(&Record) -> bool,
+    R: Sink,
+{
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn process_block(&mut self, records: &mut Vec<Record>) {
+        let Self { predicate, rejects } = self;
+        records.retain(|record| {
+            let keep = predicate(record);
+            if !keep {
+                rejects.accept(record);
+            }
+            keep
+        });
+    }
+}
+
 /// Select stage - extracts specific fields from records.
 ///
 /// Creates a new record containing only the selected fields.
@@ -117,11 +245,10 @@ impl Select {
     pub fn new(fields: Vec<(usize, usize, usize)>) -> Self {
         Self { fields }
     }
-}
 
-impl Stage for Select {
-    fn process(&mut self, record: Record) -> Option<Record> {
-        let mut output = Record::new();
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let mut output = Record::blank(record.lrecl());
 
         for &(src_start, length, dest_start) in &self.fields {
             let value = record.field(src_start, length);
@@ -132,6 +259,79 @@ impl Stage for Select {
     }
 }
 
+impl Stage for Select {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Overlay stage - writes fields and literals into columns of each record,
+/// like DFSORT `OVERLAY`.
+///
+/// Unlike [`Select`], the rest of the record is kept. Fields are
+/// (source_start, length, dest_start) tuples read from the record as it
+/// arrived, so two fields can be swapped; literals are written after them.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Overlay, Record, Stage};
+///
+/// // Copy the department to column 40 and stamp a batch id in 72-79
+/// let mut overlay = Overlay::new(vec![(18, 10, 40)]).literal(72, "BATCH001");
+///
+/// let output = overlay
+///     .process(Record::from_str("SMITH   JOHN      SALES     00050000"))
+///     .unwrap();
+/// assert_eq!(output.field(0, 36), "SMITH   JOHN      SALES     00050000");
+/// assert_eq!(output.field(40, 10).trim(), "SALES");
+/// assert_eq!(output.field(72, 8), "BATCH001");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlay {
+    /// Fields to copy: (source_start, length, dest_start)
+    fields: Vec<(usize, usize, usize)>,
+    /// Literals to write: (dest_start, text)
+    literals: Vec<(usize, String)>,
+}
+
+impl Overlay {
+    /// Creates an overlay stage copying `fields`, given as
+    /// (source_start, length, dest_start) tuples.
+    pub fn new(fields: Vec<(usize, usize, usize)>) -> Self {
+        Self {
+            fields,
+            literals: Vec::new(),
+        }
+    }
+
+    /// Also writes `text` at column `dest`.
+    pub fn literal(mut self, dest: usize, text: &str) -> Self {
+        self.literals.push((dest, text.to_string()));
+        self
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let mut output = record.clone();
+
+        for &(src_start, length, dest_start) in &self.fields {
+            output.patch(dest_start, &record, src_start, length);
+        }
+        for (dest_start, text) in &self.literals {
+            output.set_field(*dest_start, text.len(), text);
+        }
+
+        Some(output)
+    }
+}
+
+impl Stage for Overlay {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
 /// Reformat stage - transforms records using a custom function.
 ///
 /// This is the most flexible stage, allowing arbitrary record transformation.
@@ -170,14 +370,19 @@ where
     pub fn new(transform: F) -> Self {
         Self { transform }
     }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        Some((self.transform)(&record))
+    }
 }
 
 impl<F> Stage for Reformat<F>
 where
     F: FnMut(&Record) -> Record,
 {
-    fn process(&mut self, record: Record) -> Option<Record> {
-        Some((self.transform)(&record))
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
     }
 }
 
@@ -199,159 +404,2679 @@ where
     pub fn new(transform: F) -> Self {
         Self { transform }
     }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        (self.transform)(record)
+    }
 }
 
 impl<F> Stage for Map<F>
 where
     F: FnMut(Record) -> Option<Record>,
 {
-    fn process(&mut self, record: Record) -> Option<Record> {
-        (self.transform)(record)
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
     }
 }
 
-/// Inspect stage - observes records without modifying them.
+/// Locate stage - keeps records containing a string (CMS Pipelines
+/// `LOCATE`), or with [`Locate::nlocate`] drops them (`NLOCATE`).
 ///
-/// Useful for debugging or logging.
+/// The whole record is searched unless [`Locate::within`] restricts the
+/// search to a column range.
 ///
 /// # Example
 ///
 /// ```
-/// use pipelines_rs::{Record, Stage};
-/// use pipelines_rs::stage::Inspect;
+/// use pipelines_rs::{Locate, Record, Stage};
 ///
-/// let mut count = 0;
-/// let mut inspect = Inspect::new(|r: &Record| {
-///     println!("Processing: {}", r.field(0, 8).trim());
-/// });
+/// let mut locate = Locate::new("SALES").within(18, 10);
+///
+/// let sales = Record::from_str("SMITH   JOHN      SALES     ");
+/// let name = Record::from_str("SALES   MARY      ENGINEER  ");
+///
+/// assert!(locate.process(sales).is_some());
+/// assert!(locate.process(name).is_none());
 /// ```
-pub struct Inspect<F>
-where
-    F: FnMut(&Record),
-{
-    callback: F,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locate {
+    pattern: String,
+    field: Option<(usize, usize)>,
+    keep: bool,
 }
 
-impl<F> Inspect<F>
-where
-    F: FnMut(&Record),
-{
-    /// Creates a new inspect stage.
-    pub fn new(callback: F) -> Self {
-        Self { callback }
+impl Locate {
+    /// Creates a stage keeping records that contain `pattern`.
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            field: None,
+            keep: true,
+        }
+    }
+
+    /// Creates a stage dropping records that contain `pattern`.
+    pub fn nlocate(pattern: &str) -> Self {
+        Self {
+            keep: false,
+            ..Self::new(pattern)
+        }
+    }
+
+    /// Searches only the `(start, len)` columns.
+    pub fn within(mut self, start: usize, len: usize) -> Self {
+        self.field = Some((start, len));
+        self
+    }
+
+    /// Returns true if the record contains the pattern in the searched
+    /// columns.
+    pub fn matches(&self, record: &Record) -> bool {
+        match self.field {
+            Some((start, len)) => record.field_contains(start, len, &self.pattern),
+            None => record.as_str().contains(self.pattern.as_str()),
+        }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        (self.matches(&record) == self.keep).then_some(record)
     }
 }
 
-impl<F> Stage for Inspect<F>
-where
-    F: FnMut(&Record),
-{
-    fn process(&mut self, record: Record) -> Option<Record> {
-        (self.callback)(&record);
-        Some(record)
+impl Stage for Locate {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Between stage - keeps sections of records, each from a record containing
+/// a start string to the next record containing an end string (CMS
+/// Pipelines `BETWEEN`), e.g. to cut a section out of a report.
+///
+/// The markers are kept unless [`Between::exclusive`] drops them. After an
+/// end marker the next start marker begins another section; a section with
+/// no end marker runs to the end of the input. The end marker is looked for
+/// from the record after the start marker. [`Between::within`] restricts
+/// both searches to a column range.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Between, Record, Stage};
+///
+/// let lines = ["PAGE 1", "TOTALS", "SALES 10", "END", "PAGE 2"];
+/// let records = lines.iter().map(|l| Record::from_str(l)).collect();
+///
+/// let totals = Between::new("TOTALS", "END").process_batch(records);
+/// assert_eq!(totals.len(), 3);
+/// assert_eq!(totals[1].as_str().trim_end(), "SALES 10");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Between {
+    start: Locate,
+    end: Locate,
+    inclusive: bool,
+    inside: bool,
+}
 
-    fn sample_records() -> Vec<Record> {
-        vec![
-            Record::from_str("SMITH   JOHN      SALES     00050000"),
-            Record::from_str("JONES   MARY      ENGINEERING00075000"),
-            Record::from_str("DOE     JANE      SALES     00060000"),
-            Record::from_str("WILSON  BOB       MARKETING 00055000"),
-        ]
+impl Between {
+    /// Creates a stage keeping records from each one containing `start`
+    /// through the next one containing `end`.
+    pub fn new(start: &str, end: &str) -> Self {
+        Self {
+            start: Locate::new(start),
+            end: Locate::new(end),
+            inclusive: true,
+            inside: false,
+        }
     }
 
-    #[test]
-    fn test_filter_include() {
-        let mut filter = Filter::new(|r: &Record| r.field_eq(18, 10, "SALES"));
-        let records = sample_records();
-
-        let result: Vec<_> = records
-            .into_iter()
-            .filter_map(|r| filter.process(r))
-            .collect();
+    /// Drops the start and end marker records, keeping only the records
+    /// between them.
+    pub fn exclusive(mut self) -> Self {
+        self.inclusive = false;
+        self
+    }
 
-        assert_eq!(result.len(), 2);
-        assert!(result[0].field_eq(0, 8, "SMITH"));
-        assert!(result[1].field_eq(0, 8, "DOE"));
+    /// Searches for the markers only in the `(start, len)` columns.
+    pub fn within(mut self, start: usize, len: usize) -> Self {
+        self.start = self.start.within(start, len);
+        self.end = self.end.within(start, len);
+        self
     }
 
-    #[test]
-    fn test_filter_omit() {
-        // Omit SALES (keep non-SALES)
-        let mut filter = Filter::new(|r: &Record| !r.field_eq(18, 10, "SALES"));
-        let records = sample_records();
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let marker = if self.inside {
+            self.end.matches(&record)
+        } else {
+            self.start.matches(&record)
+        };
+        if marker {
+            self.inside = !self.inside;
+            return self.inclusive.then_some(record);
+        }
+        self.inside.then_some(record)
+    }
+}
 
-        let result: Vec<_> = records
-            .into_iter()
-            .filter_map(|r| filter.process(r))
-            .collect();
+impl Stage for Between {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
 
-        assert_eq!(result.len(), 2);
-        assert!(result[0].field_eq(0, 8, "JONES"));
-        assert!(result[1].field_eq(0, 8, "WILSON"));
+    fn flush(&mut self) -> Vec<Record> {
+        self.inside = false;
+        Vec::new()
     }
+}
 
-    #[test]
-    fn test_select_fields() {
-        let mut select = Select::new(vec![
-            (0, 8, 0),   // Last name
-            (18, 10, 8), // Department
-        ]);
+/// Change stage - replaces occurrences of a string within records (CMS
+/// Pipelines `CHANGE`).
+///
+/// Every occurrence is replaced unless [`Change::count`] limits the
+/// replacements per record. With [`Change::within`] only the column range
+/// is searched; a longer or shorter replacement shifts the rest of the
+/// record. An empty search string inserts the replacement once, at the
+/// start of the record or column range. Records keep their length: text pushed past the end is cut off
+/// and a shortened record is padded with blanks.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Change, Record, Stage};
+///
+/// let mut change = Change::new("SALES", "MKTG").within(18, 10);
+///
+/// let record = change
+///     .process(Record::from_str("SALES   JOHN      SALES     00050000"))
+///     .unwrap();
+/// assert_eq!(record.as_str().trim_end(), "SALES   JOHN      MKTG     00050000");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    old: String,
+    new: String,
+    field: Option<(usize, usize)>,
+    count: Option<usize>,
+}
 
-        let input = Record::from_str("SMITH   JOHN      SALES     00050000");
-        let output = select.process(input).unwrap();
+impl Change {
+    /// Creates a stage replacing every `old` with `new`.
+    pub fn new(old: &str, new: &str) -> Self {
+        Self {
+            old: old.to_string(),
+            new: new.to_string(),
+            field: None,
+            count: None,
+        }
+    }
 
-        assert_eq!(output.field(0, 8).trim(), "SMITH");
-        assert_eq!(output.field(8, 10).trim(), "SALES");
-        // Rest should be blank
-        assert!(output.field(18, 62).trim().is_empty());
+    /// Searches only the `(start, len)` columns.
+    pub fn within(mut self, start: usize, len: usize) -> Self {
+        self.field = Some((start, len));
+        self
     }
 
-    #[test]
-    fn test_reformat() {
-        let mut reformat = Reformat::new(|r: &Record| {
-            let mut output = Record::new();
-            // Swap first and last name positions
-            output.set_field(0, 10, r.field(8, 10)); // First name first
-            output.set_field(10, 8, r.field(0, 8)); // Last name second
-            output.set_field(18, 10, r.field(18, 10)); // Keep department
-            output
-        });
+    /// Replaces at most `n` occurrences per record, from the left.
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = Some(n);
+        self
+    }
 
-        let input = Record::from_str("SMITH   JOHN      SALES     ");
-        let output = reformat.process(input).unwrap();
+    /// Returns the record with the replacements made.
+    pub fn apply(&self, record: &Record) -> Record {
+        let lrecl = record.lrecl();
+        let (start, len) = self.field.unwrap_or((0, lrecl));
+        let end = start.saturating_add(len).min(lrecl);
+        let replace = |text: &str| match (self.old.is_empty(), self.count) {
+            (true, Some(0)) => text.to_string(),
+            (true, _) => format!("{}{text}", self.new),
+            (false, Some(n)) => text.replacen(&self.old, &self.new, n),
+            (false, None) => text.replace(&self.old, &self.new),
+        };
+        let content = format!(
+            "{}{}{}",
+            record.field(0, start),
+            replace(record.field(start, len)),
+            record.field(end, lrecl - end.min(lrecl))
+        );
+        Record::from_str_lrecl(&content, lrecl)
+    }
 
-        assert_eq!(output.field(0, 10).trim(), "JOHN");
-        assert_eq!(output.field(10, 8).trim(), "SMITH");
-        assert_eq!(output.field(18, 10).trim(), "SALES");
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        Some(self.apply(&record))
     }
+}
 
-    #[test]
-    fn test_inspect() {
-        let mut seen = Vec::new();
-        {
-            let mut inspect = Inspect::new(|r: &Record| {
-                seen.push(r.field(0, 8).trim().to_string());
-            });
+impl Stage for Change {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
 
-            for record in sample_records() {
-                inspect.process(record);
-            }
-        }
+/// Explode stage - turns each record into zero or more records.
+///
+/// Useful for splitting a record that carries several repeating groups
+/// into one detail record per group.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Explode, Record, Stage};
+///
+/// // One detail record per 4-column item after the customer id
+/// let mut explode = Explode::new(|r: Record| {
+///     (0..2)
+///         .map(|i| Record::from_str(&format!("{} {}", r.field(0, 4), r.field(5 + i * 4, 4))))
+///         .collect()
+/// });
+///
+/// let mut details = Vec::new();
+/// explode.process_into(Record::from_str("C001 A100B200"), &mut details);
+/// assert_eq!(details[0].as_str().trim_end(), "C001 A100");
+/// assert_eq!(details[1].as_str().trim_end(), "C001 B200");
+/// ```
+pub struct Explode<F>
+where
+    F: FnMut(Record) -> Vec<Record>,
+{
+    transform: F,
+}
 
-        assert_eq!(seen, vec!["SMITH", "JONES", "DOE", "WILSON"]);
+impl<F> Explode<F>
+where
+    F: FnMut(Record) -> Vec<Record>,
+{
+    /// Creates a new explode stage.
+    pub fn new(transform: F) -> Self {
+        Self { transform }
     }
+}
 
-    #[test]
-    fn test_process_batch() {
-        let mut filter = Filter::new(|r: &Record| r.field_eq(18, 10, "SALES"));
-        let result = filter.process_batch(sample_records());
+impl<F> Stage for Explode<F>
+where
+    F: FnMut(Record) -> Vec<Record>,
+{
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend((self.transform)(record));
+    }
+}
 
-        assert_eq!(result.len(), 2);
+/// Duplicate stage - writes each record and `n` more copies of it, like
+/// CMS Pipelines `DUPLICATE n`.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Duplicate, Record, Stage};
+///
+/// let copies = Duplicate::new(2).process_batch(vec![Record::from_str("A")]);
+/// assert_eq!(copies.len(), 3);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duplicate {
+    copies: usize,
+}
+
+impl Duplicate {
+    /// Creates a stage writing `copies` extra copies of each record.
+    pub fn new(copies: usize) -> Self {
+        Self { copies }
+    }
+
+    /// Returns the number of extra copies.
+    pub fn copies(&self) -> usize {
+        self.copies
+    }
+}
+
+impl Stage for Duplicate {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(std::iter::repeat_n(record, self.copies + 1));
+    }
+}
+
+/// Literal stage - writes a constant record ahead of the input, like CMS
+/// Pipelines `LITERAL`.
+///
+/// The literal comes before the first record, or alone at the end of an
+/// empty input.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Literal, Record, Stage};
+///
+/// let mut literal = Literal::new(Record::from_str("HEADER"));
+/// let records = literal.process_batch(vec![Record::from_str("A")]);
+/// assert_eq!(records, [Record::from_str("HEADER"), Record::from_str("A")]);
+/// assert_eq!(literal.process_batch(vec![]), [Record::from_str("HEADER")]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literal {
+    record: Record,
+    written: bool,
+}
+
+impl Literal {
+    /// Creates a stage writing `record` first.
+    pub fn new(record: Record) -> Self {
+        Self {
+            record,
+            written: false,
+        }
+    }
+
+    /// Returns the literal record.
+    pub fn record(&self) -> &Record {
+        &self.record
+    }
+
+    /// Returns whether the literal has been written.
+    pub fn is_written(&self) -> bool {
+        self.written
+    }
+
+    /// Sets whether the literal has been written, as when resuming a run
+    /// from a checkpoint.
+    pub fn set_written(&mut self, written: bool) {
+        self.written = written;
+    }
+}
+
+impl Stage for Literal {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        if !self.written {
+            self.written = true;
+            output.push(self.record.clone());
+        }
+        output.push(record);
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        let written = std::mem::take(&mut self.written);
+        if written {
+            vec![]
+        } else {
+            vec![self.record.clone()]
+        }
+    }
+}
+
+/// Console stage - echoes each record to stderr, without trailing blanks,
+/// and passes it on unchanged, like a CMS Pipelines `CONSOLE` in the middle
+/// of a pipeline.
+///
+/// Use [`crate::Pipeline::tee`] to copy records to another writer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Console;
+
+impl Console {
+    /// Creates a console stage.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        eprintln!("{}", record.as_str().trim_end());
+        Some(record)
+    }
+}
+
+impl Stage for Console {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// How a [`Dump`] stage shows each record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Offset, hex, and text, from [`Record::to_hex_dump`].
+    #[default]
+    Hex,
+    /// The record under a column ruler, from [`Record::to_ruler_string`].
+    Ruler,
+}
+
+/// Dump stage - writes each record to stderr as a hex dump or under a
+/// column ruler, numbered from 1, and passes it on unchanged. Like
+/// [`Console`], but for finding which column a field really starts in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dump {
+    format: DumpFormat,
+    count: usize,
+}
+
+impl Dump {
+    /// Creates a dump stage.
+    pub fn new(format: DumpFormat) -> Self {
+        Self { format, count: 0 }
+    }
+
+    /// Returns the text written for `record`, counting it.
+    pub fn render(&mut self, record: &Record) -> String {
+        self.count += 1;
+        let body = match self.format {
+            DumpFormat::Hex => record.to_hex_dump(),
+            DumpFormat::Ruler => record.to_ruler_string(),
+        };
+        format!("record {}:\n{body}", self.count)
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        eprintln!("{}", self.render(&record));
+        Some(record)
+    }
+}
+
+impl Stage for Dump {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.count = 0;
+        vec![]
+    }
+}
+
+/// Pad stage - fills each record with a character from the end of its text
+/// out to `len` columns, like CMS Pipelines `PAD`.
+///
+/// Trailing blanks count as the padding, so `Pad::new(10, '.')` turns
+/// `"ABC"` into `"ABC......."`; records already `len` long are unchanged.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Pad, Record, Stage};
+///
+/// let padded = Pad::new(6, '.').process(Record::from_str("ABC")).unwrap();
+/// assert_eq!(padded.as_str().trim_end(), "ABC...");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pad {
+    len: usize,
+    fill: char,
+}
+
+impl Pad {
+    /// Creates a stage padding records to `len` columns with `fill`.
+    pub fn new(len: usize, fill: char) -> Self {
+        Self { len, fill }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, mut record: Record) -> Option<Record> {
+        let text = record.as_str().trim_end_matches(' ').len();
+        if text < self.len {
+            let fill = self.fill.to_string().repeat(self.len - text);
+            record.set_field(text, self.len - text, &fill);
+        }
+        Some(record)
+    }
+}
+
+impl Stage for Pad {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Trunc stage - blanks each record from column `len` (0-based) on,
+/// keeping its first `len` characters, like CMS Pipelines `CHOP`.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Stage, Trunc};
+///
+/// let cut = Trunc::new(5).process(Record::from_str("SMITH   JOHN")).unwrap();
+/// assert_eq!(cut.as_str().trim_end(), "SMITH");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trunc {
+    len: usize,
+}
+
+impl Trunc {
+    /// Creates a stage keeping the first `len` characters.
+    pub fn new(len: usize) -> Self {
+        Self { len }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, mut record: Record) -> Option<Record> {
+        let lrecl = record.lrecl();
+        record.set_field(self.len, lrecl.saturating_sub(self.len), "");
+        Some(record)
+    }
+}
+
+impl Stage for Trunc {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Squish stage - squeezes each run of blanks in a record to a single
+/// blank, like CMS Pipelines `SQUISH`, e.g. to turn aligned columns into
+/// blank-separated words.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Squish, Stage};
+///
+/// let words = Squish::new().process(Record::from_str("SMITH   JOHN  SALES")).unwrap();
+/// assert_eq!(words.as_str().trim_end(), "SMITH JOHN SALES");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Squish;
+
+impl Squish {
+    /// Creates a squish stage.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let mut text = String::with_capacity(record.lrecl());
+        for c in record.as_str().chars() {
+            if !(c == ' ' && text.ends_with(' ')) {
+                text.push(c);
+            }
+        }
+        Some(Record::from_str_lrecl(&text, record.lrecl()))
+    }
+}
+
+impl Stage for Squish {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Take-last stage - keeps only the last `n` records, like CMS Pipelines
+/// `TAKE LAST`.
+///
+/// Holds at most `n` records and writes them when the input ends.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Stage, TakeLast};
+///
+/// let records = vec![Record::from_str("A"), Record::from_str("B"), Record::from_str("C")];
+/// let last = TakeLast::new(2).process_batch(records);
+/// assert_eq!(last, [Record::from_str("B"), Record::from_str("C")]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TakeLast {
+    n: usize,
+    held: VecDeque<Record>,
+}
+
+impl TakeLast {
+    /// Creates a stage keeping the last `n` records.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            held: VecDeque::with_capacity(n),
+        }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if self.n > 0 {
+            if self.held.len() == self.n {
+                self.held.pop_front();
+            }
+            self.held.push_back(record);
+        }
+        None
+    }
+}
+
+impl Stage for TakeLast {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.held.drain(..).collect()
+    }
+}
+
+/// Drop-last stage - passes all but the last `n` records, like CMS
+/// Pipelines `DROP LAST`, e.g. to trim trailer records.
+///
+/// Holds `n` records back, passing each on once `n` more have arrived.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{DropLast, Record, Stage};
+///
+/// let records = vec![Record::from_str("A"), Record::from_str("B"), Record::from_str("TRAILER")];
+/// let body = DropLast::new(1).process_batch(records);
+/// assert_eq!(body, [Record::from_str("A"), Record::from_str("B")]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DropLast {
+    n: usize,
+    held: VecDeque<Record>,
+}
+
+impl DropLast {
+    /// Creates a stage dropping the last `n` records.
+    pub fn new(n: usize) -> Self {
+        Self {
+            n,
+            held: VecDeque::with_capacity(n),
+        }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        self.held.push_back(record);
+        if self.held.len() > self.n {
+            self.held.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+impl Stage for DropLast {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.held.clear();
+        Vec::new()
+    }
+}
+
+/// A range of record numbers for [`Recno`]: `first` to `last`, counting
+/// from 1, inclusive, and every `step`th record from `first`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecnoRange {
+    /// First record number.
+    pub first: usize,
+    /// Last record number, or `None` for the end of the input.
+    pub last: Option<usize>,
+    /// Distance between selected records (1 selects all of them).
+    pub step: usize,
+}
+
+impl RecnoRange {
+    /// Creates the range `first` to `last`.
+    pub fn new(first: usize, last: usize) -> Self {
+        Self {
+            first,
+            last: Some(last),
+            step: 1,
+        }
+    }
+
+    /// Creates a range holding only record `n`.
+    pub fn single(n: usize) -> Self {
+        Self::new(n, n)
+    }
+
+    /// Creates the range from `first` to the end of the input.
+    pub fn starting(first: usize) -> Self {
+        Self {
+            first,
+            last: None,
+            step: 1,
+        }
+    }
+
+    /// Selects only every `step`th record of the range.
+    pub fn by(mut self, step: usize) -> Self {
+        self.step = step.max(1);
+        self
+    }
+
+    /// Does the range select record number `n`?
+    pub fn contains(&self, n: usize) -> bool {
+        n >= self.first
+            && self.last.is_none_or(|last| n <= last)
+            && (n - self.first).is_multiple_of(self.step)
+    }
+}
+
+/// Recno stage - keeps records by their position in the input, counting
+/// from 1, e.g. to sample a file or skip a known bad region.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Recno, RecnoRange, Record, Stage};
+///
+/// // Records 1-2 and every other record from 4 on
+/// let mut recno = Recno::new(vec![RecnoRange::new(1, 2), RecnoRange::starting(4).by(2)]);
+/// let records = (1..=7).map(|n| Record::from_str(&n.to_string())).collect();
+/// let kept: Vec<_> = recno.process_batch(records).iter().map(|r| r.as_str().trim().to_string()).collect();
+/// assert_eq!(kept, ["1", "2", "4", "6"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recno {
+    ranges: Vec<RecnoRange>,
+    seen: usize,
+}
+
+impl Recno {
+    /// Creates a stage keeping the records any of `ranges` selects.
+    pub fn new(ranges: Vec<RecnoRange>) -> Self {
+        Self { ranges, seen: 0 }
+    }
+
+    /// Returns the number of records counted so far.
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+
+    /// Sets the number of records counted so far, as when resuming a run
+    /// from a checkpoint.
+    pub fn set_seen(&mut self, seen: usize) {
+        self.seen = seen;
+    }
+
+    /// Counts `record` and reports whether it is selected.
+    pub fn matches(&mut self, _record: &Record) -> bool {
+        self.seen += 1;
+        self.ranges.iter().any(|range| range.contains(self.seen))
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        self.matches(&record).then_some(record)
+    }
+}
+
+impl Stage for Recno {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.seen = 0;
+        Vec::new()
+    }
+}
+
+/// Sample stage - keeps a random fraction of the records, the same
+/// records each run for the same seed, e.g. for test extracts of large
+/// files.
+///
+/// Each record is kept with probability `fraction` (clamped to 0..=1),
+/// drawn from a SplitMix64 generator seeded with `seed`.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Sample, Stage};
+///
+/// let records: Vec<_> = (0..1000).map(|n| Record::from_str(&n.to_string())).collect();
+/// let first = Sample::new(0.1, 42).process_batch(records.clone());
+/// let again = Sample::new(0.1, 42).process_batch(records);
+/// assert_eq!(first, again);
+/// assert!((50..150).contains(&first.len()));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    fraction: f64,
+    seed: u64,
+    state: u64,
+}
+
+impl Sample {
+    /// Creates a stage keeping about `fraction` of the records.
+    pub fn new(fraction: f64, seed: u64) -> Self {
+        Self {
+            fraction,
+            seed,
+            state: seed,
+        }
+    }
+
+    /// Draws the next number and reports whether the record is kept.
+    pub fn matches(&mut self, _record: &Record) -> bool {
+        // SplitMix64
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // The top 53 bits as a float in 0..1
+        let draw = (z >> 11) as f64 / (1u64 << 53) as f64;
+        draw < self.fraction
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        self.matches(&record).then_some(record)
+    }
+}
+
+impl Stage for Sample {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    fn flush(&mut self) -> Vec<Record> {
+        self.state = self.seed;
+        Vec::new()
+    }
+}
+
+/// Inspect stage - observes records without modifying them.
+///
+/// Useful for debugging or logging.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Stage};
+/// use pipelines_rs::stage::Inspect;
+///
+/// let mut count = 0;
+/// let mut inspect = Inspect::new(|r: &Record| {
+///     println!("Processing: {}", r.field(0, 8).trim());
+/// });
+/// ```
+pub struct Inspect<F>
+where
+    F: FnMut(&Record),
+{
+    callback: F,
+}
+
+impl<F> Inspect<F>
+where
+    F: FnMut(&Record),
+{
+    /// Creates a new inspect stage.
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        (self.callback)(&record);
+        Some(record)
+    }
+}
+
+impl<F> Stage for Inspect<F>
+where
+    F: FnMut(&Record),
+{
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Throttle stage - paces record flow by pausing before each record.
+///
+/// Useful for demonstrations: records visibly trickle through a pipeline
+/// instead of arriving all at once. On `wasm32` targets, where threads
+/// cannot sleep, records pass through without pausing.
+///
+/// # Example
+///
+/// ```
+/// use std::time::Duration;
+/// use pipelines_rs::{Record, Stage, ThrottleStage};
+///
+/// let mut throttle = ThrottleStage::new(Duration::from_millis(1));
+/// let record = Record::from_str("PACED");
+/// assert_eq!(throttle.process(record.clone()), Some(record));
+/// ```
+pub struct ThrottleStage {
+    delay: Duration,
+}
+
+impl ThrottleStage {
+    /// Creates a throttle that pauses for `delay` before each record.
+    pub fn new(delay: Duration) -> Self {
+        Self { delay }
+    }
+
+    /// Returns the per-record delay.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::sleep(self.delay);
+        Some(record)
+    }
+}
+
+impl Stage for ThrottleStage {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Direction of a sort key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// Smallest first (DFSORT `A`).
+    #[default]
+    Ascending,
+    /// Largest first (DFSORT `D`).
+    Descending,
+}
+
+/// How a sort key's bytes are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyFormat {
+    /// Byte-by-byte comparison (DFSORT `CH`).
+    #[default]
+    Character,
+    /// Signed whole number, ignoring surrounding blanks; a blank field is
+    /// zero. Values that are not numbers sort before all numbers.
+    Numeric,
+}
+
+/// One key of a multi-key sort: a field, its direction, and its format.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, SortKey};
+///
+/// let salary = SortKey::new(28, 8).numeric().descending();
+/// let high = Record::from_str("JONES   MARY      ENGINEER  75000");
+/// let low = Record::from_str("SMITH   JOHN      SALES     50000");
+///
+/// assert!(salary.compare(&high, &low).is_lt());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    /// Starting column (0-based).
+    pub start: usize,
+    /// Field length.
+    pub len: usize,
+    /// Sort direction.
+    pub order: SortOrder,
+    /// Comparison format.
+    pub format: KeyFormat,
+}
+
+impl SortKey {
+    /// Creates an ascending character key on `start, len`.
+    pub fn new(start: usize, len: usize) -> Self {
+        Self {
+            start,
+            len,
+            order: SortOrder::Ascending,
+            format: KeyFormat::Character,
+        }
+    }
+
+    /// Sorts this key largest first.
+    pub fn descending(mut self) -> Self {
+        self.order = SortOrder::Descending;
+        self
+    }
+
+    /// Compares this key as a number.
+    pub fn numeric(mut self) -> Self {
+        self.format = KeyFormat::Numeric;
+        self
+    }
+
+    /// Compares two records on this key, honouring its direction.
+    pub fn compare(&self, a: &Record, b: &Record) -> Ordering {
+        let (x, y) = (a.field(self.start, self.len), b.field(self.start, self.len));
+        let ordering = match self.format {
+            KeyFormat::Character => x.cmp(y),
+            KeyFormat::Numeric => match (whole_number(x), whole_number(y)) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                (None, Some(_)) => Ordering::Less,
+                (Some(_), None) => Ordering::Greater,
+                (None, None) => x.cmp(y),
+            },
+        };
+        match self.order {
+            SortOrder::Ascending => ordering,
+            SortOrder::Descending => ordering.reverse(),
+        }
+    }
+}
+
+/// Compares two records on each key in turn; the first unequal key decides.
+pub fn compare_keys(keys: &[SortKey], a: &Record, b: &Record) -> Ordering {
+    keys.iter()
+        .map(|key| key.compare(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Sorts records in place by `keys`. The sort is stable, so records with
+/// equal keys keep their input order (DFSORT `EQUALS`).
+pub fn sort_records(records: &mut [Record], keys: &[SortKey]) {
+    records.sort_by(|a, b| compare_keys(keys, a, b));
+}
+
+/// Parses a signed whole number; blank is zero.
+fn whole_number(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Some(0);
+    }
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+/// Memory [`Sort`] holds records in before it spills a sorted run to a
+/// temporary file: 64 MiB.
+pub const SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+/// Sort stage - orders records by one or more key fields.
+///
+/// Sorting needs every record before it can emit the first, so `process`
+/// holds each record and returns `None`; [`Stage::flush`] returns them in
+/// order.
+///
+/// Input far larger than memory is sorted in runs: once the held records
+/// pass a memory threshold ([`SORT_MEMORY`] unless set with
+/// [`Sort::with_memory`]), [`Sort::push`] sorts them and writes them to a
+/// temporary file as a run, like DFSORT's SORTWK work datasets, and
+/// [`Sort::sorted`] merges the runs as the records are read. Both return
+/// the I/O errors of the work files. The files are deleted when the
+/// records have been read. As a [`Stage`], which cannot fail, the sort
+/// spills the same way, and keeps the first work file error for
+/// [`Sort::error`].
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Sort, SortKey, Stage};
+///
+/// // Department ascending, then salary descending
+/// let mut sort = Sort::new(vec![
+///     SortKey::new(18, 10),
+///     SortKey::new(28, 8).numeric().descending(),
+/// ]);
+///
+/// let sorted = sort.process_batch(vec![
+///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+///     Record::from_str("DOE     JANE      SALES     00060000"),
+/// ]);
+///
+/// let names: Vec<_> = sorted.iter().map(|r| r.field(0, 8).trim()).collect();
+/// assert_eq!(names, ["JONES", "DOE", "SMITH"]);
+/// ```
+pub struct Sort {
+    keys: Vec<SortKey>,
+    held: Vec<Record>,
+    held_bytes: usize,
+    memory: usize,
+    runs: Vec<std::fs::File>,
+    error: Option<PipelineError>,
+}
+
+impl Sort {
+    /// Creates a sort stage; earlier keys take precedence.
+    pub fn new(keys: Vec<SortKey>) -> Self {
+        Self {
+            keys,
+            held: Vec::new(),
+            held_bytes: 0,
+            memory: SORT_MEMORY,
+            runs: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Spills a sorted run to a temporary file whenever the held records
+    /// reach `bytes`.
+    #[must_use]
+    pub fn with_memory(mut self, bytes: usize) -> Self {
+        self.memory = bytes.max(1);
+        self
+    }
+
+    /// Returns the sort keys.
+    pub fn keys(&self) -> &[SortKey] {
+        &self.keys
+    }
+
+    /// Returns the number of sorted runs spilled to temporary files.
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Holds a record, spilling the held records as a sorted run if they
+    /// have reached the memory threshold.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::Io`] if the run cannot be written; the
+    /// records are still held, and no more runs are spilled.
+    pub fn push(&mut self, record: Record) -> Result<()> {
+        self.held_bytes += record.lrecl();
+        self.held.push(record);
+        if self.held_bytes < self.memory {
+            return Ok(());
+        }
+        sort_records(&mut self.held, &self.keys);
+        match write_run(&self.held) {
+            Ok(run) => {
+                self.runs.push(run);
+                self.held.clear();
+                self.held_bytes = 0;
+                Ok(())
+            }
+            Err(e) => {
+                self.memory = usize::MAX;
+                Err(e)
+            }
+        }
+    }
+
+    /// Ends the input and returns the records in order, merging any
+    /// spilled runs as they are read. The stage is left empty.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::Io`] if a run cannot be read back; the
+    /// iterator's items fail the same way part way through.
+    pub fn sorted(&mut self) -> Result<SortedRecords> {
+        let mut held = std::mem::take(&mut self.held);
+        self.held_bytes = 0;
+        sort_records(&mut held, &self.keys);
+        let mut sources = std::mem::take(&mut self.runs)
+            .into_iter()
+            .map(RunReader::new)
+            .collect::<Result<Vec<_>>>()?;
+        // The records still held came last, so they lose ties with the runs
+        sources.push(RunReader::Memory(held.into_iter()));
+        let heads = sources
+            .iter_mut()
+            .map(RunReader::next_record)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SortedRecords {
+            keys: self.keys.clone(),
+            sources,
+            heads,
+        })
+    }
+
+    /// Holds a record like [`push`](Sort::push), keeping a work file
+    /// error for [`error`](Sort::error). Returns `None`: the records come
+    /// out of [`Stage::flush`].
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if let Err(e) = self.push(record) {
+            self.error.get_or_insert(e);
+        }
+        None
+    }
+
+    /// Returns the first work file error, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.error.as_ref()
+    }
+
+    /// Takes the first work file error, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.error.take()
+    }
+}
+
+impl Stage for Sort {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Returns the records in sorted order, merging the runs spilled to
+    /// work files. A run that cannot be read back ends the records there,
+    /// and its error is kept for [`Sort::error`].
+    fn flush(&mut self) -> Vec<Record> {
+        let sorted = match self.sorted() {
+            Ok(sorted) => sorted,
+            Err(e) => {
+                self.error.get_or_insert(e);
+                return Vec::new();
+            }
+        };
+        let mut records = Vec::new();
+        for record in sorted {
+            match record {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                    break;
+                }
+            }
+        }
+        records
+    }
+}
+
+/// Writes sorted records to a new temporary file, each as a 4-byte
+/// big-endian length and its bytes, and rewinds it.
+fn write_run(records: &[Record]) -> Result<std::fs::File> {
+    use std::io::{BufWriter, Seek, Write};
+
+    let mut writer = BufWriter::new(tempfile::tempfile()?);
+    for record in records {
+        let bytes = record.as_bytes();
+        let length = u32::try_from(bytes.len()).map_err(|_| {
+            PipelineError::Stage(format!(
+                "record of {} bytes is too long to sort",
+                bytes.len()
+            ))
+        })?;
+        writer.write_all(&length.to_be_bytes())?;
+        writer.write_all(bytes)?;
+    }
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// A sorted run being merged: a temporary file, or the records held last.
+enum RunReader {
+    File(std::io::BufReader<std::fs::File>),
+    Memory(std::vec::IntoIter<Record>),
+}
+
+impl RunReader {
+    fn new(file: std::fs::File) -> Result<Self> {
+        Ok(Self::File(std::io::BufReader::new(file)))
+    }
+
+    /// Reads the run's next record, or `None` at its end.
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        use std::io::Read;
+
+        let reader = match self {
+            Self::File(reader) => reader,
+            Self::Memory(records) => return Ok(records.next()),
+        };
+        let mut length = [0; 4];
+        match reader.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut bytes = vec![0; u32::from_be_bytes(length) as usize];
+        reader.read_exact(&mut bytes)?;
+        Ok(Some(Record::from_bytes_lrecl(&bytes, bytes.len())))
+    }
+}
+
+/// Records in sorted order from [`Sort::sorted`], merged from the sorted
+/// runs as they are read.
+pub struct SortedRecords {
+    keys: Vec<SortKey>,
+    sources: Vec<RunReader>,
+    /// The next record of each run
+    heads: Vec<Option<Record>>,
+}
+
+impl Iterator for SortedRecords {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The lowest head wins; on equal keys the earlier run, which holds
+        // earlier input, keeps the sort stable
+        let mut best: Option<usize> = None;
+        for (idx, head) in self.heads.iter().enumerate() {
+            let Some(record) = head else { continue };
+            let lower = best.is_none_or(|b| {
+                let current = self.heads[b].as_ref().expect("best run has a record");
+                compare_keys(&self.keys, record, current).is_lt()
+            });
+            if lower {
+                best = Some(idx);
+            }
+        }
+        let idx = best?;
+        let next = match self.sources[idx].next_record() {
+            Ok(next) => next,
+            Err(e) => {
+                self.heads.iter_mut().for_each(|head| *head = None);
+                return Some(Err(e));
+            }
+        };
+        std::mem::replace(&mut self.heads[idx], next).map(Ok)
+    }
+}
+
+/// Which record of a set of duplicates [`Dedup`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupKeep {
+    /// The first record with each key (DFSORT `SUM FIELDS=NONE` with
+    /// `EQUALS`).
+    #[default]
+    First,
+    /// The last record with each key.
+    Last,
+}
+
+/// Dedup stage - removes records whose key fields repeat an earlier record.
+///
+/// Keys are `(start, len)` fields compared byte for byte. By default the
+/// stage remembers every key it has seen, so the input need not be sorted.
+/// With [`sorted`](Dedup::sorted) it only compares each record with the one
+/// before, which streams in constant memory but only removes adjacent
+/// duplicates.
+///
+/// Keeping the first record streams: `process` returns each record with a
+/// new key. Keeping the last must wait for a later record with another key
+/// (sorted) or for the end of input (unsorted), so `process` holds records
+/// and [`Stage::flush`] returns any still held. Unsorted last-wins output
+/// is in the order of the kept records. `process_batch` returns both.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Dedup, Record, Stage};
+///
+/// // One record per department, the last one seen
+/// let mut dedup = Dedup::new(vec![(18, 10)]).keep_last();
+///
+/// let kept = dedup.process_batch(vec![
+///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+///     Record::from_str("DOE     JANE      SALES     00060000"),
+/// ]);
+///
+/// let names: Vec<_> = kept.iter().map(|r| r.field(0, 8).trim()).collect();
+/// assert_eq!(names, ["JONES", "DOE"]);
+/// ```
+pub struct Dedup {
+    keys: Vec<(usize, usize)>,
+    keep: DedupKeep,
+    sorted: bool,
+    /// Keys seen so far (unsorted, first-wins).
+    seen: HashSet<String>,
+    /// Key of the previous record (sorted).
+    previous: Option<String>,
+    /// Records waiting to be kept (last-wins).
+    held: Vec<Record>,
+}
+
+impl Dedup {
+    /// Creates a first-wins dedup of unsorted input on the given
+    /// `(start, len)` key fields.
+    pub fn new(keys: Vec<(usize, usize)>) -> Self {
+        Self {
+            keys,
+            keep: DedupKeep::First,
+            sorted: false,
+            seen: HashSet::new(),
+            previous: None,
+            held: Vec::new(),
+        }
+    }
+
+    /// Keeps the last record with each key instead of the first.
+    pub fn keep_last(mut self) -> Self {
+        self.keep = DedupKeep::Last;
+        self
+    }
+
+    /// Assumes the input is sorted on the keys, comparing each record only
+    /// with the one before.
+    pub fn sorted(mut self) -> Self {
+        self.sorted = true;
+        self
+    }
+
+    /// Returns the key fields.
+    pub fn keys(&self) -> &[(usize, usize)] {
+        &self.keys
+    }
+
+    /// Returns which duplicate is kept.
+    pub fn keep(&self) -> DedupKeep {
+        self.keep
+    }
+
+    /// The concatenated key fields of a record.
+    fn key(&self, record: &Record) -> String {
+        key_of(&self.keys, record)
+    }
+}
+
+/// Concatenates a record's `(start, len)` key fields.
+pub(crate) fn key_of(keys: &[(usize, usize)], record: &Record) -> String {
+    keys.iter()
+        .map(|&(start, len)| record.field(start, len))
+        .collect()
+}
+
+impl Dedup {
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        match (self.sorted, self.keep) {
+            (false, DedupKeep::First) => self.seen.insert(self.key(&record)).then_some(record),
+            (false, DedupKeep::Last) => {
+                self.held.push(record);
+                None
+            }
+            (true, DedupKeep::First) => {
+                let key = self.key(&record);
+                let new = self.previous.as_ref() != Some(&key);
+                self.previous = Some(key);
+                new.then_some(record)
+            }
+            (true, DedupKeep::Last) => {
+                let key = self.key(&record);
+                let new = self.previous.as_ref() != Some(&key);
+                self.previous = Some(key);
+                let done = std::mem::replace(&mut self.held, vec![record]);
+                if new { done.into_iter().next() } else { None }
+            }
+        }
+    }
+}
+
+impl Stage for Dedup {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Returns the held records that are kept.
+    fn flush(&mut self) -> Vec<Record> {
+        let held = std::mem::take(&mut self.held);
+        if self.sorted {
+            self.previous = None;
+            return held;
+        }
+        let last: HashMap<String, usize> = held
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| (self.key(record), idx))
+            .collect();
+        held.into_iter()
+            .enumerate()
+            .filter(|(idx, record)| last[&self.key(record)] == *idx)
+            .map(|(_, record)| record)
+            .collect()
+    }
+}
+
+/// How a [`SumField`] is read and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SumFormat {
+    /// Digits with an optional leading `+` or `-`, ignoring surrounding
+    /// blanks. Totals are written zero-padded, negatives with a leading `-`.
+    #[default]
+    Signed,
+    /// Zoned decimal with an overpunched sign (DFSORT `ZD`), see
+    /// [`Record::field_zoned`].
+    Zoned,
+}
+
+/// A numeric field totalled by [`SumBy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SumField {
+    /// Starting column (0-based).
+    pub start: usize,
+    /// Field length.
+    pub len: usize,
+    /// Number format.
+    pub format: SumFormat,
+}
+
+impl SumField {
+    /// Creates a signed whole number field on `start, len`.
+    pub fn new(start: usize, len: usize) -> Self {
+        Self {
+            start,
+            len,
+            format: SumFormat::Signed,
+        }
+    }
+
+    /// Reads and writes this field as zoned decimal.
+    pub fn zoned(mut self) -> Self {
+        self.format = SumFormat::Zoned;
+        self
+    }
+
+    /// Reads the field's value; a blank field is zero.
+    pub fn read(&self, record: &Record) -> Option<i64> {
+        if record.field(self.start, self.len).trim().is_empty() {
+            return Some(0);
+        }
+        match self.format {
+            SumFormat::Signed => whole_number(record.field(self.start, self.len)),
+            SumFormat::Zoned => record.field_zoned(self.start, self.len),
+        }
+    }
+
+    /// Writes `value` into the field, zero-padded.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the field extends past the record or the value does not
+    /// fit in it.
+    pub fn write(&self, record: &mut Record, value: i64) -> Result<()> {
+        if self.format == SumFormat::Zoned {
+            return record.set_field_zoned(self.start, self.len, value);
+        }
+        if self.len == 0 || self.start + self.len > record.lrecl() {
+            return Err(PipelineError::FieldOutOfBounds {
+                start: self.start,
+                length: self.len,
+                record_len: record.lrecl(),
+            });
+        }
+        let digits = value.unsigned_abs().to_string();
+        let width = if value < 0 { self.len - 1 } else { self.len };
+        if digits.len() > width {
+            return Err(PipelineError::ValueTooWide {
+                value: value.to_string(),
+                length: self.len,
+            });
+        }
+        let sign = if value < 0 { "-" } else { "" };
+        record.set_field(self.start, self.len, &format!("{sign}{digits:0>width$}"));
+        Ok(())
+    }
+}
+
+/// SumBy stage - collapses records with equal keys into one, adding up
+/// their numeric fields (DFSORT `SUM FIELDS`).
+///
+/// The first record with each key is kept, with each sum field replaced by
+/// the group's total. The input need not be sorted; output is in order of
+/// each key's first record. As with DFSORT on overflow, a record whose sum
+/// fields are not numbers, or whose total would not fit, is left unsummed
+/// and starts a new total for its key.
+///
+/// With [`SumBy::on_error`] or [`SumBy::rejects`], a record whose sum fields
+/// are not numbers is handled by an [`ErrorPolicy`] instead. A stage
+/// stopped by [`ErrorPolicy::Fail`] writes no totals.
+///
+/// Totals need every record, so `process` holds each record and returns
+/// `None`; [`Stage::flush`] returns the totals. `process_batch` returns
+/// them directly.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Stage, SumBy, SumField};
+///
+/// // Total salary per department
+/// let mut sum = SumBy::new(vec![(18, 10)], vec![SumField::new(28, 8)]);
+///
+/// let totals = sum.process_batch(vec![
+///     Record::from_str("SMITH   JOHN      SALES     00050000"),
+///     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+///     Record::from_str("DOE     JANE      SALES     00060000"),
+/// ]);
+///
+/// assert_eq!(totals.len(), 2);
+/// assert_eq!(totals[0].field(18, 18), "SALES     00110000");
+/// assert_eq!(totals[1].field(18, 18), "ENGINEER  00075000");
+/// ```
+pub struct SumBy<R = ()> {
+    keys: Vec<(usize, usize)>,
+    fields: Vec<SumField>,
+    /// Running totals, in order of each group's first record.
+    totals: Vec<Record>,
+    /// Index in `totals` of the open group for each key.
+    open: HashMap<String, usize>,
+    rejects: Rejects<R>,
+}
+
+impl SumBy {
+    /// Creates a stage totalling `fields` for each distinct value of the
+    /// `(start, len)` key fields.
+    pub fn new(keys: Vec<(usize, usize)>, fields: Vec<SumField>) -> Self {
+        Self {
+            keys,
+            fields,
+            totals: Vec::new(),
+            open: HashMap::new(),
+            rejects: Rejects::default(),
+        }
+    }
+
+    /// Sends records whose sum fields are not numbers to `sink`, each with
+    /// its reason appended (see [`crate::reject`]).
+    pub fn rejects<S: Sink>(self, sink: S) -> SumBy<S> {
+        SumBy {
+            keys: self.keys,
+            fields: self.fields,
+            totals: self.totals,
+            open: self.open,
+            rejects: self.rejects.with_sink(sink),
+        }
+    }
+}
+
+impl<R: Sink> SumBy<R> {
+    /// Sets what happens to a record whose sum fields are not numbers.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.rejects = self.rejects.with_policy(policy);
+        self
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.rejects.error()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.rejects.take_error()
+    }
+
+    /// Returns the key fields.
+    pub fn keys(&self) -> &[(usize, usize)] {
+        &self.keys
+    }
+
+    /// Returns the summed fields.
+    pub fn fields(&self) -> &[SumField] {
+        &self.fields
+    }
+
+    /// Adds `record` to `total`, or returns `None` if a field is not a
+    /// number or the sum does not fit.
+    fn add(&self, total: &Record, record: &Record) -> Option<Record> {
+        let mut sum = total.clone();
+        for field in &self.fields {
+            let value = field.read(total)?.checked_add(field.read(record)?)?;
+            field.write(&mut sum, value).ok()?;
+        }
+        Some(sum)
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        if self.rejects.stopped() {
+            return None;
+        }
+        if let Some(field) = self.fields.iter().find(|f| f.read(&record).is_none())
+            && !self
+                .rejects
+                .reject(&record, Reason::NotNumeric, (field.start, field.len))
+        {
+            return None;
+        }
+        let key = key_of(&self.keys, &record);
+        if let Some(&idx) = self.open.get(&key)
+            && let Some(sum) = self.add(&self.totals[idx], &record)
+        {
+            self.totals[idx] = sum;
+            return None;
+        }
+        if self.fields.iter().all(|f| f.read(&record).is_some()) {
+            self.open.insert(key, self.totals.len());
+        }
+        self.totals.push(record);
+        None
+    }
+}
+
+impl<R: Sink> Stage for SumBy<R> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+
+    /// Returns the totals.
+    fn flush(&mut self) -> Vec<Record> {
+        self.open.clear();
+        let totals = std::mem::take(&mut self.totals);
+        if self.rejects.stopped() {
+            return Vec::new();
+        }
+        totals
+    }
+}
+
+/// Seqnum stage - writes a sequence number into each record (DFSORT
+/// `SEQNUM` in `OUTREC`).
+///
+/// Numbers are zero-padded to the field length. A number too large for the
+/// field wraps around, keeping its low-order digits, as DFSORT does. With
+/// [`Seqnum::restart_on`] numbering starts over whenever the key field
+/// changes from the previous record (DFSORT `RESTART=`), which numbers the
+/// records within each group of sorted input.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, Seqnum, Stage};
+///
+/// // Number by 10 from 100 in columns 72-79
+/// let mut seqnum = Seqnum::new(100, 10, 72, 8);
+///
+/// let first = seqnum.process(Record::from_str("SMITH")).unwrap();
+/// let second = seqnum.process(Record::from_str("JONES")).unwrap();
+/// assert_eq!(first.field(72, 8), "00000100");
+/// assert_eq!(second.field(72, 8), "00000110");
+/// ```
+pub struct Seqnum {
+    start: u64,
+    increment: u64,
+    position: usize,
+    length: usize,
+    restart: Option<(usize, usize)>,
+    next: u64,
+    previous_key: Option<String>,
+}
+
+impl Seqnum {
+    /// Creates a stage numbering from `start` by `increment` into the
+    /// `length` columns at `position`.
+    pub fn new(start: u64, increment: u64, position: usize, length: usize) -> Self {
+        Self {
+            start,
+            increment,
+            position,
+            length,
+            restart: None,
+            next: start,
+            previous_key: None,
+        }
+    }
+
+    /// Starts numbering over at `start` when the `(start, len)` field
+    /// differs from the previous record's.
+    pub fn restart_on(mut self, start: usize, len: usize) -> Self {
+        self.restart = Some((start, len));
+        self
+    }
+
+    /// Formats `value` in `length` digits, keeping the low-order digits.
+    fn digits(&self, value: u64) -> String {
+        let text = format!("{value:0width$}", width = self.length);
+        text[text.len() - self.length..].to_string()
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, mut record: Record) -> Option<Record> {
+        if let Some((start, len)) = self.restart {
+            let key = record.field(start, len).to_string();
+            if self.previous_key.as_ref().is_some_and(|k| *k != key) {
+                self.next = self.start;
+            }
+            self.previous_key = Some(key);
+        }
+        let number = self.digits(self.next);
+        record.set_field(self.position, self.length, &number);
+        self.next = self.next.wrapping_add(self.increment);
+        Some(record)
+    }
+}
+
+impl Stage for Seqnum {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// RunningTotal stage - writes the cumulative total of a numeric field
+/// into each record, for balance-forward processing.
+///
+/// Blank fields count as zero; values that are not numbers leave the total
+/// unchanged. A total too wide for its destination is written as
+/// asterisks. With [`RunningTotal::restart_on`] the total starts over
+/// whenever the key field changes.
+///
+/// With [`RunningTotal::on_error`] or [`RunningTotal::rejects`], a value
+/// that is not a number, or a total too wide for its destination, is
+/// handled by an [`ErrorPolicy`] instead; a rejected record is not added
+/// to the total.
+///
+/// # Example
+///
+/// ```
+/// use pipelines_rs::{Record, RunningTotal, Stage, SumField};
+///
+/// let mut balance = RunningTotal::new(SumField::new(0, 5), SumField::new(6, 6));
+///
+/// let first = balance.process(Record::from_str("00100")).unwrap();
+/// let second = balance.process(Record::from_str("-0040")).unwrap();
+/// assert_eq!(first.field(6, 6), "000100");
+/// assert_eq!(second.field(6, 6), "000060");
+/// ```
+pub struct RunningTotal<R = ()> {
+    field: SumField,
+    dest: SumField,
+    restart: Option<(usize, usize)>,
+    total: i128,
+    previous_key: Option<String>,
+    rejects: Rejects<R>,
+}
+
+impl RunningTotal {
+    /// Creates a stage adding `field` of each record to the total and
+    /// writing the total into `dest`.
+    pub fn new(field: SumField, dest: SumField) -> Self {
+        Self {
+            field,
+            dest,
+            restart: None,
+            total: 0,
+            previous_key: None,
+            rejects: Rejects::default(),
+        }
+    }
+
+    /// Sends records with a value that is not a number, or a total that
+    /// does not fit, to `sink`, each with its reason appended (see
+    /// [`crate::reject`]).
+    pub fn rejects<S: Sink>(self, sink: S) -> RunningTotal<S> {
+        RunningTotal {
+            field: self.field,
+            dest: self.dest,
+            restart: self.restart,
+            total: self.total,
+            previous_key: self.previous_key,
+            rejects: self.rejects.with_sink(sink),
+        }
+    }
+}
+
+impl<R: Sink> RunningTotal<R> {
+    /// Sets what happens to a record with a value that is not a number or
+    /// a total that does not fit.
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.rejects = self.rejects.with_policy(policy);
+        self
+    }
+
+    /// Returns the error that stopped the stage, if any.
+    pub fn error(&self) -> Option<&PipelineError> {
+        self.rejects.error()
+    }
+
+    /// Takes the error that stopped the stage, if any.
+    pub fn take_error(&mut self) -> Option<PipelineError> {
+        self.rejects.take_error()
+    }
+
+    /// Starts the total over at zero when the `(start, len)` field differs
+    /// from the previous record's.
+    pub fn restart_on(mut self, start: usize, len: usize) -> Self {
+        self.restart = Some((start, len));
+        self
+    }
+
+    /// Returns the current total.
+    pub fn total(&self) -> i128 {
+        self.total
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, mut record: Record) -> Option<Record> {
+        if self.rejects.stopped() {
+            return None;
+        }
+        if let Some((start, len)) = self.restart {
+            let key = record.field(start, len).to_string();
+            if self.previous_key.as_ref().is_some_and(|k| *k != key) {
+                self.total = 0;
+            }
+            self.previous_key = Some(key);
+        }
+        let field = (self.field.start, self.field.len);
+        let value = match self.field.read(&record) {
+            Some(value) => i128::from(value),
+            None if self.rejects.reject(&record, Reason::NotNumeric, field) => 0,
+            None => return None,
+        };
+        let total = self.total + value;
+        // A failed write leaves the record as it was
+        let written = i64::try_from(total)
+            .ok()
+            .and_then(|v| self.dest.write(&mut record, v).ok());
+        if written.is_none() {
+            let dest = (self.dest.start, self.dest.len);
+            if !self.rejects.reject(&record, Reason::Overflow, dest) {
+                return None;
+            }
+            record.set_field(self.dest.start, self.dest.len, &"*".repeat(self.dest.len));
+        }
+        self.total = total;
+        Some(record)
+    }
+}
+
+impl<R: Sink> Stage for RunningTotal<R> {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_LRECL;
+
+    fn sample_records() -> Vec<Record> {
+        vec![
+            Record::from_str("SMITH   JOHN      SALES     00050000"),
+            Record::from_str("JONES   MARY      ENGINEERING00075000"),
+            Record::from_str("DOE     JANE      SALES     00060000"),
+            Record::from_str("WILSON  BOB       MARKETING 00055000"),
+        ]
+    }
+
+    #[test]
+    fn test_filter_include() {
+        let mut filter = Filter::new(|r: &Record| r.field_eq(18, 10, "SALES"));
+        let records = sample_records();
+
+        let result: Vec<_> = records
+            .into_iter()
+            .filter_map(|r| filter.process(r))
+            .collect();
+
+        assert_eq!(result.len(), 2);
+        assert!(result[0].field_eq(0, 8, "SMITH"));
+        assert!(result[1].field_eq(0, 8, "DOE"));
+    }
+
+    #[test]
+    fn test_filter_omit() {
+        // Omit SALES (keep non-SALES)
+        let mut filter = Filter::new(|r: &Record| !r.field_eq(18, 10, "SALES"));
+        let records = sample_records();
+
+        let result: Vec<_> = records
+            .into_iter()
+            .filter_map(|r| filter.process(r))
+            .collect();
+
+        assert_eq!(result.len(), 2);
+        assert!(result[0].field_eq(0, 8, "JONES"));
+        assert!(result[1].field_eq(0, 8, "WILSON"));
+    }
+
+    #[test]
+    fn test_filter_rejects() {
+        let mut rejected = Vec::new();
+        let mut filter =
+            Filter::new(|r: &Record| r.field_eq(18, 10, "SALES")).rejects(&mut rejected);
+        let kept = filter.process_batch(sample_records());
+
+        assert_eq!(kept.len(), 2);
+        assert_eq!(rejected.len(), 2);
+        assert!(rejected[0].field_eq(0, 8, "JONES"));
+        assert!(rejected[1].field_eq(0, 8, "WILSON"));
+    }
+
+    #[test]
+    fn test_process_block() {
+        let mut rejected = Vec::new();
+        let mut filter =
+            Filter::new(|r: &Record| r.field_eq(18, 10, "SALES")).rejects(&mut rejected);
+        let mut block = sample_records();
+        filter.process_block(&mut block);
+        assert_eq!(block.len(), 2);
+        assert!(block[1].field_eq(0, 8, "DOE"));
+        assert_eq!(rejected.len(), 2);
+
+        // The default runs process_into on each record, without flushing
+        let mut duplicate = Duplicate::new(1);
+        duplicate.process_block(&mut block);
+        assert_eq!(block.len(), 4);
+        assert!(block[1].field_eq(0, 8, "SMITH"));
+        let mut take_last = TakeLast::new(1);
+        take_last.process_block(&mut block);
+        assert!(block.is_empty());
+        assert_eq!(take_last.flush().len(), 1);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut overlay = Overlay::new(vec![(0, 5, 6), (6, 5, 0)]).literal(78, "OK");
+        let output = overlay
+            .process(Record::from_str("AAAAA BBBBB CCC"))
+            .unwrap();
+        assert_eq!(output.as_str().trim_end().len(), 80);
+        assert_eq!(output.field(0, 15), "BBBBB AAAAA CCC");
+        assert_eq!(output.field(78, 2), "OK");
+
+        // Past the end of the record, fields and literals are cut short
+        let mut overlay = Overlay::new(vec![(0, 3, 78)]).literal(79, "XYZ");
+        let output = overlay.process(Record::from_str("ABC")).unwrap();
+        assert_eq!(output.field(77, 3), " AX");
+    }
+
+    #[test]
+    fn test_select_fields() {
+        let mut select = Select::new(vec![
+            (0, 8, 0),   // Last name
+            (18, 10, 8), // Department
+        ]);
+
+        let input = Record::from_str("SMITH   JOHN      SALES     00050000");
+        let output = select.process(input).unwrap();
+
+        assert_eq!(output.field(0, 8).trim(), "SMITH");
+        assert_eq!(output.field(8, 10).trim(), "SALES");
+        // Rest should be blank
+        assert!(output.field(18, 62).trim().is_empty());
+    }
+
+    #[test]
+    fn test_reformat() {
+        let mut reformat = Reformat::new(|r: &Record| {
+            let mut output = Record::new();
+            // Swap first and last name positions
+            output.set_field(0, 10, r.field(8, 10)); // First name first
+            output.set_field(10, 8, r.field(0, 8)); // Last name second
+            output.set_field(18, 10, r.field(18, 10)); // Keep department
+            output
+        });
+
+        let input = Record::from_str("SMITH   JOHN      SALES     ");
+        let output = reformat.process(input).unwrap();
+
+        assert_eq!(output.field(0, 10).trim(), "JOHN");
+        assert_eq!(output.field(10, 8).trim(), "SMITH");
+        assert_eq!(output.field(18, 10).trim(), "SALES");
+    }
+
+    #[test]
+    fn test_inspect() {
+        let mut seen = Vec::new();
+        {
+            let mut inspect = Inspect::new(|r: &Record| {
+                seen.push(r.field(0, 8).trim().to_string());
+            });
+
+            for record in sample_records() {
+                inspect.process(record);
+            }
+        }
+
+        assert_eq!(seen, vec!["SMITH", "JONES", "DOE", "WILSON"]);
+    }
+
+    #[test]
+    fn test_literal_and_console() {
+        let mut literal = Literal::new(Record::from_str("HEADER"));
+        let mut output = Vec::new();
+        literal.process_into(Record::from_str("A"), &mut output);
+        literal.process_into(Record::from_str("B"), &mut output);
+        assert!(literal.flush().is_empty());
+        assert_eq!(output.len(), 3);
+        assert!(output[0].field_eq(0, 6, "HEADER"));
+        // A flushed literal is written again for the next input
+        assert_eq!(literal.process_batch(sample_records()).len(), 5);
+
+        let result = Console::new().process_batch(sample_records());
+        assert_eq!(result, sample_records());
+    }
+
+    #[test]
+    fn test_take_last_and_drop_last_hold_n_records() {
+        let mut take = TakeLast::new(2);
+        let mut drop = DropLast::new(2);
+        for (i, record) in sample_records().into_iter().enumerate() {
+            assert!(take.process(record.clone()).is_none());
+            assert!(take.held.len() <= 2);
+            assert_eq!(drop.process(record).is_some(), i >= 2);
+        }
+        let last = take.flush();
+        assert_eq!(last.len(), 2);
+        assert!(last[1].field_eq(0, 8, "WILSON"));
+        assert!(drop.flush().is_empty());
+        // Flushed stages start over
+        assert_eq!(take.process_batch(sample_records()).len(), 2);
+        assert_eq!(drop.process_batch(sample_records()).len(), 2);
+    }
+
+    #[test]
+    fn test_recno() {
+        let range = RecnoRange::new(10, 20).by(5);
+        assert!(range.contains(10) && range.contains(15) && range.contains(20));
+        assert!(!range.contains(5) && !range.contains(11) && !range.contains(25));
+        assert!(RecnoRange::starting(3).contains(1_000_000));
+        assert!(!RecnoRange::single(3).contains(4));
+
+        let mut recno = Recno::new(vec![RecnoRange::single(4), RecnoRange::new(1, 2)]);
+        let result = recno.process_batch(sample_records());
+        assert_eq!(result.len(), 3);
+        assert!(result[0].field_eq(0, 8, "SMITH"));
+        assert!(result[2].field_eq(0, 8, "WILSON"));
+        // Flushed stages count from 1 again
+        assert_eq!(recno.process_batch(sample_records()).len(), 3);
+    }
+
+    #[test]
+    fn test_sample() {
+        let records: Vec<Record> = (0..10_000)
+            .map(|n| Record::from_str(&n.to_string()))
+            .collect();
+        let mut sample = Sample::new(0.25, 7);
+        let kept = sample.process_batch(records.clone());
+        assert!((2300..2700).contains(&kept.len()), "{}", kept.len());
+        // Flushed stages start the same sequence again
+        assert_eq!(sample.process_batch(records.clone()), kept);
+        assert_ne!(Sample::new(0.25, 8).process_batch(records.clone()), kept);
+
+        assert!(
+            Sample::new(0.0, 1)
+                .process_batch(records.clone())
+                .is_empty()
+        );
+        assert_eq!(Sample::new(1.0, 1).process_batch(records).len(), 10_000);
+    }
+
+    #[test]
+    fn test_process_batch() {
+        let mut filter = Filter::new(|r: &Record| r.field_eq(18, 10, "SALES"));
+        let result = filter.process_batch(sample_records());
+
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_throttle_paces_records() {
+        let mut throttle = ThrottleStage::new(Duration::from_millis(5));
+        let start = std::time::Instant::now();
+        let result = throttle.process_batch(sample_records());
+
+        assert_eq!(result, sample_records());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_sort_multiple_keys() {
+        let mut sort = Sort::new(vec![
+            SortKey::new(18, 10).descending(),
+            SortKey::new(28, 8).numeric(),
+        ]);
+        let sorted = sort.process_batch(sample_records());
+        let names: Vec<_> = sorted.iter().map(|r| r.field(0, 8).trim()).collect();
+
+        assert_eq!(names, ["SMITH", "DOE", "WILSON", "JONES"]);
+    }
+
+    #[test]
+    fn test_sort_holds_records_until_flush() {
+        let mut sort = Sort::new(vec![SortKey::new(0, 8)]);
+        for record in sample_records() {
+            assert!(sort.process(record).is_none());
+        }
+        let names: Vec<_> = sort
+            .flush()
+            .iter()
+            .map(|r| r.field(0, 8).trim().to_string())
+            .collect();
+
+        assert_eq!(names, ["DOE", "JONES", "SMITH", "WILSON"]);
+        assert!(sort.flush().is_empty());
+    }
+
+    #[test]
+    fn test_sort_spills_runs() {
+        // Keys repeat across runs, with a sequence number to check stability
+        let records: Vec<Record> = (0..50)
+            .map(|n| Record::from_str(&format!("{:02}{n:03}", (n * 7) % 10)))
+            .collect();
+        let mut expected = records.clone();
+        sort_records(&mut expected, &[SortKey::new(0, 2)]);
+
+        // Eight 80-byte records per run
+        let mut sort = Sort::new(vec![SortKey::new(0, 2)]).with_memory(640);
+        for record in records.clone() {
+            sort.push(record).unwrap();
+        }
+        assert_eq!(sort.runs(), 6);
+        let sorted: Vec<Record> = sort.sorted().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(sorted, expected);
+        assert_eq!(sort.runs(), 0);
+
+        // As a stage it spills too, and flush merges the runs
+        let mut sort = Sort::new(vec![SortKey::new(0, 2)]).with_memory(640);
+        records
+            .into_iter()
+            .for_each(|r| assert!(sort.process(r).is_none()));
+        assert_eq!(sort.runs(), 6);
+        assert_eq!(sort.flush(), expected);
+        assert_eq!(sort.runs(), 0);
+        assert!(sort.error().is_none());
+    }
+
+    #[test]
+    fn test_sort_stage_past_sort_memory() {
+        // Records of the largest length, in reverse order, just past the
+        // default threshold
+        let count = SORT_MEMORY / MAX_LRECL + 2;
+        let records = (0..count)
+            .rev()
+            .map(|n| Record::from_str_lrecl(&format!("{n:05}"), MAX_LRECL));
+        let mut sort = Sort::new(vec![SortKey::new(0, 5)]);
+        for record in records {
+            assert!(sort.process(record).is_none());
+        }
+        assert_eq!(sort.runs(), 1);
+        assert!(sort.held.len() < count);
+
+        let sorted = sort.flush();
+        assert_eq!(sorted.len(), count);
+        assert!(
+            sorted
+                .iter()
+                .enumerate()
+                .all(|(n, r)| r.field(0, 5) == format!("{n:05}"))
+        );
+        assert!(sort.error().is_none());
+    }
+
+    #[test]
+    fn test_numeric_key() {
+        let key = SortKey::new(0, 6).numeric();
+        let mut records: Vec<_> = ["   +12", "  -3", "", "ABC", "100"]
+            .iter()
+            .map(|s| Record::from_str(s))
+            .collect();
+        records.sort_by(|a, b| key.compare(a, b));
+        let values: Vec<_> = records.iter().map(|r| r.field(0, 6).trim()).collect();
+
+        assert_eq!(values, ["ABC", "-3", "", "+12", "100"]);
+    }
+
+    fn dedup_names(dedup: Dedup, names: &[&str]) -> Vec<String> {
+        let mut dedup = dedup;
+        let records = names.iter().map(|s| Record::from_str(s)).collect();
+        dedup
+            .process_batch(records)
+            .iter()
+            .map(|r| r.as_str().trim_end().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_dedup_unsorted() {
+        let input = ["A 1", "B 1", "A 2", "C 1", "B 2"];
+        let key = vec![(0, 1)];
+        assert_eq!(
+            dedup_names(Dedup::new(key.clone()), &input),
+            ["A 1", "B 1", "C 1"]
+        );
+        assert_eq!(
+            dedup_names(Dedup::new(key).keep_last(), &input),
+            ["A 2", "C 1", "B 2"]
+        );
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        let input = ["A 1", "A 2", "B 1", "B 2", "B 3", "A 3"];
+        let key = vec![(0, 1)];
+        assert_eq!(
+            dedup_names(Dedup::new(key.clone()).sorted(), &input),
+            ["A 1", "B 1", "A 3"]
+        );
+        assert_eq!(
+            dedup_names(Dedup::new(key).sorted().keep_last(), &input),
+            ["A 2", "B 3", "A 3"]
+        );
+    }
+
+    #[test]
+    fn test_dedup_streaming() {
+        let mut dedup = Dedup::new(vec![(0, 1)]).sorted().keep_last();
+        assert!(dedup.process(Record::from_str("A 1")).is_none());
+        assert!(dedup.process(Record::from_str("A 2")).is_none());
+        let out = dedup.process(Record::from_str("B 1")).unwrap();
+        assert_eq!(out.as_str().trim_end(), "A 2");
+        assert_eq!(dedup.flush()[0].as_str().trim_end(), "B 1");
+        assert!(dedup.flush().is_empty());
+    }
+
+    #[test]
+    fn test_dedup_multiple_keys() {
+        let input = ["A X 1", "A Y 1", "A X 2"];
+        let dedup = Dedup::new(vec![(0, 1), (2, 1)]);
+        assert_eq!(dedup_names(dedup, &input), ["A X 1", "A Y 1"]);
+    }
+
+    #[test]
+    fn test_sum_by() {
+        let mut sum = SumBy::new(vec![(0, 1)], vec![SumField::new(2, 4), SumField::new(7, 3)]);
+        let totals = sum.process_batch(
+            ["A 0010 001", "B 0005 -01", "A   -3 002", "B 0001    "]
+                .iter()
+                .map(|s| Record::from_str(s))
+                .collect(),
+        );
+        let lines: Vec<_> = totals.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(lines, ["A 0007 003", "B 0006 -01"]);
+    }
+
+    #[test]
+    fn test_sum_by_zoned() {
+        let mut sum = SumBy::new(vec![(0, 1)], vec![SumField::new(2, 4).zoned()]);
+        let totals =
+            sum.process_batch(vec![Record::from_str("A 001E"), Record::from_str("A 002J")]);
+        assert_eq!(totals[0].field(2, 4), "000O");
+        assert_eq!(SumField::new(2, 4).zoned().read(&totals[0]), Some(-6));
+    }
+
+    #[test]
+    fn test_sum_by_overflow_left_unsummed() {
+        let mut sum = SumBy::new(vec![(0, 1)], vec![SumField::new(2, 2)]);
+        let totals = sum.process_batch(
+            ["A 60", "A 30", "A 20", "A XX", "A 01"]
+                .iter()
+                .map(|s| Record::from_str(s))
+                .collect(),
+        );
+        let lines: Vec<_> = totals.iter().map(|r| r.as_str().trim_end()).collect();
+        assert_eq!(lines, ["A 90", "A 21", "A XX"]);
+        assert!(sum.flush().is_empty());
+    }
+
+    #[test]
+    fn test_sum_by_error_policy() {
+        let input = || {
+            ["A 60", "A XX", "A 01"]
+                .iter()
+                .map(|s| Record::from_str(s))
+                .collect::<Vec<_>>()
+        };
+
+        let mut rejected = Vec::new();
+        let totals = SumBy::new(vec![(0, 1)], vec![SumField::new(2, 2)])
+            .rejects(&mut rejected)
+            .process_batch(input());
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].field(0, 4), "A 61");
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].field(0, 4), "A XX");
+        assert_eq!(rejected[0].field(80, 16).trim_end(), "NOTNUM 2,2");
+
+        let mut sum =
+            SumBy::new(vec![(0, 1)], vec![SumField::new(2, 2)]).on_error(ErrorPolicy::Fail);
+        assert!(sum.process_batch(input()).is_empty());
+        let err = sum.take_error().unwrap();
+        assert!(err.to_string().contains("'A XX'"), "{err}");
+    }
+
+    #[test]
+    fn test_seqnum() {
+        let mut seqnum = Seqnum::new(5, 5, 36, 4);
+        let records = seqnum.process_batch(sample_records());
+        let numbers: Vec<_> = records.iter().map(|r| r.field(36, 4)).collect();
+        assert_eq!(numbers, ["0005", "0010", "0015", "0020"]);
+        assert!(records[0].field_eq(0, 8, "SMITH"));
+    }
+
+    #[test]
+    fn test_seqnum_wraps_and_restarts() {
+        let mut seqnum = Seqnum::new(98, 1, 4, 2);
+        let numbers: Vec<_> = (0..3)
+            .map(|_| {
+                seqnum
+                    .process(Record::new())
+                    .unwrap()
+                    .field(4, 2)
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(numbers, ["98", "99", "00"]);
+
+        let mut seqnum = Seqnum::new(1, 1, 10, 2).restart_on(0, 1);
+        let numbers: Vec<_> = ["A", "A", "B", "A"]
+            .iter()
+            .map(|s| {
+                seqnum
+                    .process(Record::from_str(s))
+                    .unwrap()
+                    .field(10, 2)
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(numbers, ["01", "02", "01", "01"]);
+    }
+
+    #[test]
+    fn test_running_total() {
+        let mut total = RunningTotal::new(SumField::new(2, 3), SumField::new(6, 3));
+        let out: Vec<_> = ["A 500", "A XXX", "A    ", "A 499", "A 001", "A -99"]
+            .iter()
+            .map(|s| total.process(Record::from_str(s)).unwrap())
+            .map(|r| r.field(6, 3).to_string())
+            .collect();
+        assert_eq!(out, ["500", "500", "500", "999", "***", "901"]);
+        assert_eq!(total.total(), 901);
+    }
+
+    #[test]
+    fn test_running_total_rejects() {
+        let mut rejected = Vec::new();
+        let mut total =
+            RunningTotal::new(SumField::new(2, 3), SumField::new(6, 3)).rejects(&mut rejected);
+        let out: Vec<_> = ["A 500", "A XXX", "A 499", "A 001", "A -99"]
+            .iter()
+            .filter_map(|s| total.process(Record::from_str(s)))
+            .map(|r| r.field(6, 3).to_string())
+            .collect();
+        assert_eq!(out, ["500", "999", "900"]);
+        assert_eq!(total.total(), 900);
+        drop(total);
+        let reasons: Vec<_> = rejected
+            .iter()
+            .map(|r| r.field(80, 16).trim_end())
+            .collect();
+        assert_eq!(reasons, ["NOTNUM 2,3", "OVERFLOW 6,3"]);
+        assert_eq!(rejected[1].field(0, 5), "A 001");
+    }
+
+    #[test]
+    fn test_running_total_fail() {
+        let mut total =
+            RunningTotal::new(SumField::new(2, 3), SumField::new(6, 3)).on_error(ErrorPolicy::Fail);
+        assert!(total.process(Record::from_str("A 500")).is_some());
+        assert!(total.process(Record::from_str("A 5X0")).is_none());
+        assert!(total.process(Record::from_str("A 001")).is_none());
+        assert_eq!(total.total(), 500);
+        let err = total.error().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "stage error: NOTNUM in field 2,3 of 'A 5X0'"
+        );
+    }
+
+    #[test]
+    fn test_running_total_restart_and_zoned() {
+        let mut total = RunningTotal::new(SumField::new(2, 2).zoned(), SumField::new(5, 3).zoned())
+            .restart_on(0, 1);
+        let out: Vec<_> = ["A 10", "A 0J", "B 05"]
+            .iter()
+            .map(|s| total.process(Record::from_str(s)).unwrap())
+            .map(|r| r.field(5, 3).to_string())
+            .collect();
+        assert_eq!(out, ["01{", "00I", "00E"]);
+    }
+
+    #[test]
+    fn test_process_batch_flushes() {
+        struct HoldFirst(Option<Record>);
+
+        impl HoldFirst {
+            /// Processes one record, returning the record it passes on, if any.
+            pub fn process(&mut self, record: Record) -> Option<Record> {
+                if self.0.is_none() {
+                    self.0 = Some(record);
+                    return None;
+                }
+                Some(record)
+            }
+        }
+
+        impl Stage for HoldFirst {
+            fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+                output.extend(self.process(record));
+            }
+
+            fn flush(&mut self) -> Vec<Record> {
+                self.0.take().into_iter().collect()
+            }
+        }
+
+        let mut stage = HoldFirst(None);
+        let out = stage.process_batch(vec![Record::from_str("A"), Record::from_str("B")]);
+        assert!(out[0].field_eq(0, 1, "B"));
+        assert!(out[1].field_eq(0, 1, "A"));
+        assert!(stage.flush().is_empty());
+    }
+
+    #[test]
+    fn test_explode() {
+        let mut explode = Explode::new(|r: Record| {
+            r.as_str()
+                .split_whitespace()
+                .map(Record::from_str)
+                .collect()
+        });
+        let out = explode.process_batch(vec![Record::from_str("A B C"), Record::from_str("")]);
+        assert_eq!(out.len(), 3);
+        assert!(out[2].field_eq(0, 1, "C"));
+        let mut output = vec![Record::from_str("Z")];
+        explode.process_into(Record::from_str("D E"), &mut output);
+        assert_eq!(output.len(), 3);
+    }
+
+    #[test]
+    fn test_locate_and_nlocate() {
+        let records = || sample_records().into_iter();
+        let names = |records: Vec<Record>| -> Vec<String> {
+            records
+                .iter()
+                .map(|r| r.field(0, 8).trim().to_string())
+                .collect()
+        };
+
+        let mut locate = Locate::new("ING");
+        assert_eq!(
+            names(records().filter_map(|r| locate.process(r)).collect()),
+            ["JONES", "WILSON"]
+        );
+
+        let mut nlocate = Locate::nlocate("ON").within(0, 8);
+        assert_eq!(
+            names(records().filter_map(|r| nlocate.process(r)).collect()),
+            ["SMITH", "DOE"]
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut dump = Dump::new(DumpFormat::Ruler);
+        let text = dump.render(&Record::from_str("AB"));
+        assert!(text.starts_with("record 1:\n0---------1"));
+        assert!(
+            dump.render(&Record::from_str("CD"))
+                .starts_with("record 2:")
+        );
+
+        let mut dump = Dump::new(DumpFormat::Hex);
+        assert_eq!(
+            dump.process(Record::from_str("AB"))
+                .unwrap()
+                .as_str()
+                .trim(),
+            "AB"
+        );
+        dump.flush();
+        let text = dump.render(&Record::from_str("AB"));
+        assert!(text.starts_with("record 1:\n00000  41 42 20 20"));
+    }
+
+    #[test]
+    fn test_pad_trunc_squish() {
+        let record = Record::from_str_lrecl("  A   B ", 10);
+        let text = |stage: &mut dyn Stage| {
+            stage.process_batch(vec![record.clone()])[0]
+                .as_str()
+                .to_string()
+        };
+        assert_eq!(text(&mut Pad::new(10, '*')), "  A   B***");
+        assert_eq!(text(&mut Pad::new(4, '*')), "  A   B   ");
+        assert_eq!(text(&mut Pad::new(20, '*')), "  A   B***");
+        assert_eq!(text(&mut Trunc::new(3)), "  A       ");
+        assert_eq!(text(&mut Trunc::new(0)), "          ");
+        assert_eq!(text(&mut Trunc::new(99)), "  A   B   ");
+        assert_eq!(text(&mut Squish::new()), " A B      ");
+    }
+
+    #[test]
+    fn test_between() {
+        let records: Vec<_> = ["A", "START", "B", "END", "C", "START", "D"]
+            .into_iter()
+            .map(Record::from_str)
+            .collect();
+        let text = |records: Vec<Record>| -> Vec<String> {
+            records
+                .iter()
+                .map(|r| r.as_str().trim_end().to_string())
+                .collect()
+        };
+
+        let mut between = Between::new("START", "END");
+        assert_eq!(
+            text(between.process_batch(records.clone())),
+            ["START", "B", "END", "START", "D"]
+        );
+        let mut exclusive = Between::new("START", "END").exclusive();
+        assert_eq!(text(exclusive.process_batch(records.clone())), ["B", "D"]);
+        // The end marker is searched for after the start marker
+        let mut same = Between::new("S", "S").within(0, 1);
+        assert_eq!(
+            text(same.process_batch(records)),
+            ["START", "B", "END", "C", "START"]
+        );
+    }
+
+    #[test]
+    fn test_change() {
+        let apply = |change: Change, text: &str| {
+            change
+                .apply(&Record::from_str(text))
+                .as_str()
+                .trim_end()
+                .to_string()
+        };
+        assert_eq!(apply(Change::new("A", "XY"), "A-A-A"), "XY-XY-XY");
+        assert_eq!(apply(Change::new("A", "").count(2), "A-A-A"), "--A");
+        assert_eq!(apply(Change::new("A", "B").within(2, 2), "A-A-A"), "A-B-A");
+        assert_eq!(apply(Change::new("A", "").within(0, 2), "A-A-A"), "-A-A");
+        assert_eq!(apply(Change::new("A", "B").within(90, 5), "A"), "A");
+        assert_eq!(apply(Change::new("", "Z"), "ABC"), "ZABC");
+        assert_eq!(apply(Change::new("", "Z").within(1, 2), "ABC"), "AZBC");
+        assert_eq!(apply(Change::new("", "Z").count(0), "ABC"), "ABC");
+
+        let long = Change::new("X", "YYYY").apply(&Record::from_str_lrecl("XXX", 5));
+        assert_eq!(long.as_str(), "YYYYY");
     }
 }
@@ -0,0 +1,303 @@
+//! Async stages over record streams (requires the `async` feature).
+//!
+//! An [`AsyncStage`] is a stage whose `process` can wait, for an HTTP
+//! service or a database, without blocking the thread it runs on. A
+//! [`RecordStream`] is a `Stream<Item = Record>` (the trait tokio and
+//! `tokio-stream` use) with the pipeline operations: async stages,
+//! ordinary [`Stage`]s, filter, and map. Start one from any stream, such as
+//! a `tokio_stream::wrappers::ReceiverStream`, or from a sequential
+//! pipeline with [`Pipeline::into_stream`](crate::Pipeline::into_stream).
+//!
+//! Records go through an async stage one at a time, as they go through an
+//! ordinary stage; [`RecordStream::map_concurrent`] runs lookups for
+//! several records at once and keeps them in input order.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::stream::{AsyncStage, RecordStream};
+//! use pipelines_rs::{Pipeline, Record};
+//!
+//! /// Looks up each employee's region (a service call in practice).
+//! struct Region;
+//!
+//! impl AsyncStage for Region {
+//!     async fn process(&mut self, mut record: Record) -> Option<Record> {
+//!         let region = if record.field(18, 10).trim() == "SALES" { "WEST" } else { "EAST" };
+//!         record.set_field(36, 4, region);
+//!         Some(record)
+//!     }
+//! }
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//! ];
+//! let stream = Pipeline::new(records.into_iter())
+//!     .into_stream()
+//!     .stage(Region)
+//!     .filter(|r| r.field(36, 4) == "WEST");
+//!
+//! let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+//! let output = runtime.block_on(stream.collect());
+//! assert_eq!(output.len(), 1);
+//! assert_eq!(output[0].field(0, 8).trim(), "SMITH");
+//! ```
+
+use std::collections::VecDeque;
+use std::future::{Future, ready};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub use futures_util::Stream;
+use futures_util::StreamExt;
+use futures_util::stream;
+
+use crate::Record;
+use crate::stage::Stage;
+
+/// A pipeline stage whose processing can wait without blocking.
+///
+/// Implement `process` as an `async fn`; the future it returns must be
+/// `Send`, so the stage can run on a multi-threaded runtime.
+pub trait AsyncStage: Send {
+    /// Process a single record.
+    ///
+    /// Returns `Some(record)` to pass the record downstream, or `None` to
+    /// filter it out.
+    fn process(&mut self, record: Record) -> impl Future<Output = Option<Record>> + Send;
+
+    /// Ends the input, returning any records the stage has held back.
+    ///
+    /// The default holds nothing and returns no records.
+    fn flush(&mut self) -> impl Future<Output = Vec<Record>> + Send {
+        ready(Vec::new())
+    }
+}
+
+/// A stream of records with pipeline operations.
+pub struct RecordStream<'a> {
+    inner: Pin<Box<dyn Stream<Item = Record> + Send + 'a>>,
+}
+
+impl<'a> RecordStream<'a> {
+    /// Wraps a stream of records.
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Record> + Send + 'a,
+    {
+        Self {
+            inner: Box::pin(stream),
+        }
+    }
+
+    /// Passes each record through an async stage, flushing it when the
+    /// input ends.
+    #[must_use]
+    pub fn stage<A>(self, stage: A) -> Self
+    where
+        A: AsyncStage + 'a,
+    {
+        let state = Flow {
+            input: self.inner,
+            stage,
+            held: VecDeque::new(),
+            ended: false,
+        };
+        Self::new(stream::unfold(state, |mut flow| async move {
+            loop {
+                if let Some(record) = flow.held.pop_front() {
+                    return Some((record, flow));
+                }
+                if flow.ended {
+                    return None;
+                }
+                match flow.input.next().await {
+                    Some(record) => {
+                        if let Some(record) = flow.stage.process(record).await {
+                            return Some((record, flow));
+                        }
+                    }
+                    None => {
+                        flow.held.extend(flow.stage.flush().await);
+                        flow.ended = true;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Passes each record through an ordinary stage, which may emit any
+    /// number of records for it, flushing the stage when the input ends.
+    #[must_use]
+    pub fn sync_stage<S>(self, stage: S) -> Self
+    where
+        S: Stage + Send + 'a,
+    {
+        let state = Flow {
+            input: self.inner,
+            stage,
+            held: VecDeque::new(),
+            ended: false,
+        };
+        Self::new(stream::unfold(state, |mut flow| async move {
+            loop {
+                if let Some(record) = flow.held.pop_front() {
+                    return Some((record, flow));
+                }
+                if flow.ended {
+                    return None;
+                }
+                match flow.input.next().await {
+                    Some(record) => {
+                        let mut output = Vec::new();
+                        flow.stage.process_into(record, &mut output);
+                        flow.held.extend(output);
+                    }
+                    None => {
+                        flow.held.extend(flow.stage.flush());
+                        flow.ended = true;
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Filters records using a predicate.
+    #[must_use]
+    pub fn filter<F>(self, mut predicate: F) -> Self
+    where
+        F: FnMut(&Record) -> bool + Send + 'a,
+    {
+        Self::new(self.inner.filter(move |record| ready(predicate(record))))
+    }
+
+    /// Transforms each record.
+    #[must_use]
+    pub fn map<F>(self, transform: F) -> Self
+    where
+        F: FnMut(Record) -> Record + Send + 'a,
+    {
+        Self::new(self.inner.map(transform))
+    }
+
+    /// Runs `lookup` on up to `limit` records at once, passing its results
+    /// downstream in input order and dropping records it returns `None`
+    /// for.
+    #[must_use]
+    pub fn map_concurrent<F, Fut>(self, limit: usize, lookup: F) -> Self
+    where
+        F: FnMut(Record) -> Fut + Send + 'a,
+        Fut: Future<Output = Option<Record>> + Send + 'a,
+    {
+        Self::new(
+            self.inner
+                .map(lookup)
+                .buffered(limit.max(1))
+                .filter_map(ready),
+        )
+    }
+
+    /// Collects the records into a Vec.
+    pub async fn collect(self) -> Vec<Record> {
+        StreamExt::collect(self.inner).await
+    }
+}
+
+impl Stream for RecordStream<'_> {
+    type Item = Record;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Record>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// A stage's input and the records it emitted but has not passed on.
+struct Flow<'a, A> {
+    input: Pin<Box<dyn Stream<Item = Record> + Send + 'a>>,
+    stage: A,
+    held: VecDeque<Record>,
+    ended: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pipeline;
+    use crate::stage::{Duplicate, TakeLast};
+    use std::time::Duration;
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn numbered(count: usize) -> Vec<Record> {
+        (0..count)
+            .map(|n| Record::from_str(&format!("{n:04}")))
+            .collect()
+    }
+
+    /// Holds every record and emits a count when flushed.
+    struct Count(usize);
+
+    impl AsyncStage for Count {
+        async fn process(&mut self, _: Record) -> Option<Record> {
+            tokio::task::yield_now().await;
+            self.0 += 1;
+            None
+        }
+
+        async fn flush(&mut self) -> Vec<Record> {
+            vec![Record::from_str(&self.0.to_string())]
+        }
+    }
+
+    #[test]
+    fn test_async_stage_and_flush() {
+        let stream = Pipeline::new(numbered(100).into_iter())
+            .into_stream()
+            .filter(|r| r.field(3, 1) == "0")
+            .stage(Count(0));
+        let output = block_on(stream.collect());
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].as_str().trim(), "10");
+    }
+
+    #[test]
+    fn test_sync_stage_flushes() {
+        let stream = RecordStream::new(stream::iter(numbered(10)))
+            .map(|mut r| {
+                r.set_field(5, 2, "OK");
+                r
+            })
+            .sync_stage(TakeLast::new(2))
+            .sync_stage(Duplicate::new(1));
+        let output: Vec<String> = block_on(stream.collect())
+            .iter()
+            .map(|r| r.as_str().trim().to_string())
+            .collect();
+        assert_eq!(output, ["0008 OK", "0008 OK", "0009 OK", "0009 OK"]);
+    }
+
+    #[test]
+    fn test_map_concurrent_keeps_order() {
+        // Later records finish first, but come out in input order
+        let stream = RecordStream::new(stream::iter(numbered(20))).map_concurrent(8, |r| async {
+            let n: u64 = r.field(0, 4).parse().unwrap();
+            tokio::time::sleep(Duration::from_millis(20 - n)).await;
+            (!n.is_multiple_of(5)).then_some(r)
+        });
+        let output = block_on(stream.collect());
+        assert_eq!(output.len(), 16);
+        assert_eq!(output[0].field(0, 4), "0001");
+        assert_eq!(output[15].field(0, 4), "0019");
+    }
+}
@@ -0,0 +1,297 @@
+//! Generated records for property tests.
+//!
+//! A [`RecordGen`] makes records that conform to a [`RecordLayout`]: each
+//! field holds a value of its type, and the columns between fields are
+//! blank. Stage authors can then check an invariant, such as "SORT's output
+//! is a permutation of its input", over many generated inputs instead of a
+//! few hand-written fixtures:
+//!
+//! - `CHAR` fields hold upper-case letters, left-justified, and are
+//!   sometimes blank
+//! - `NUMERIC` fields hold zero-padded digits, valid for `SUM` and `FILTER
+//!   ... NUM`
+//! - `DATE` fields hold a valid date written for their width: `YYYYMMDD`
+//!   (8), `YYYY-MM-DD` (10), `MMDDYY` (6), or `YYDDD` (5)
+//! - [`RecordGen::duplicates`] repeats earlier records, or only some of
+//!   their fields, at a given rate, so duplicate keys are common rather
+//!   than vanishingly rare
+//!
+//! Generation is seeded and reproducible. [`RecordGen::records`] takes the
+//! seed directly; with the `proptest` feature, [`RecordGen::strategy`]
+//! gives a proptest `Strategy`, and with the `quickcheck` feature,
+//! [`RecordGen::arbitrary`] draws records from a quickcheck `Gen`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::RecordLayout;
+//! use pipelines_rs::testing::RecordGen;
+//!
+//! let layout: RecordLayout = "LAYOUT NAME 0,8; DEPT 8,10; SALARY 18,8 NUMERIC; HIRED 26,8 DATE"
+//!     .parse()
+//!     .unwrap();
+//! let generator = RecordGen::new(&layout).duplicates(0.5).on(&["DEPT"]);
+//! let records = generator.records(42, 100);
+//! assert_eq!(records.len(), 100);
+//! assert!(records.iter().all(|r| r.field(18, 8).bytes().all(|b| b.is_ascii_digit())));
+//! assert_eq!(records, generator.records(42, 100));
+//! ```
+
+use crate::dates::{Date, DateFormat};
+use crate::layout::{FieldType, LayoutField, RecordLayout};
+use crate::record::Record;
+
+/// A seeded source of random numbers (SplitMix64, as SAMPLE uses).
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator; the same seed gives the same numbers.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number below `n` (which must not be 0).
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Returns true with probability `p`.
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+/// Generates records that conform to a layout.
+#[derive(Debug, Clone)]
+pub struct RecordGen {
+    layout: RecordLayout,
+    /// Fraction of records that repeat an earlier record.
+    duplicates: f64,
+    /// The fields a repeat copies; all of them if empty.
+    keys: Vec<String>,
+}
+
+impl RecordGen {
+    /// Creates a generator for records of `layout`.
+    pub fn new(layout: &RecordLayout) -> Self {
+        Self {
+            layout: layout.clone(),
+            duplicates: 0.0,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Makes a fraction `rate` (0 to 1) of the records, after the first,
+    /// repeat an earlier record.
+    pub fn duplicates(mut self, rate: f64) -> Self {
+        self.duplicates = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Makes repeats copy only the named fields, generating the rest, so
+    /// that keys repeat while records differ.
+    pub fn on(mut self, fields: &[&str]) -> Self {
+        self.keys = fields.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Generates `count` records from `seed`.
+    pub fn records(&self, seed: u64, count: usize) -> Vec<Record> {
+        let mut rng = Rng::new(seed);
+        let mut records: Vec<Record> = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut record = self.record(&mut rng);
+            if !records.is_empty() && rng.chance(self.duplicates) {
+                let earlier = &records[rng.below(records.len() as u64) as usize];
+                record = self.repeat(earlier, &record);
+            }
+            records.push(record);
+        }
+        records
+    }
+
+    /// Generates one record.
+    pub fn record(&self, rng: &mut Rng) -> Record {
+        let mut record = Record::blank(self.layout.lrecl());
+        for field in self.layout.fields() {
+            record.set_field(field.start, field.len, &value(field, rng));
+        }
+        record
+    }
+
+    /// Copies the repeated fields of `earlier` into `record`.
+    fn repeat(&self, earlier: &Record, record: &Record) -> Record {
+        if self.keys.is_empty() {
+            return earlier.clone();
+        }
+        let mut record = record.clone();
+        for field in self.layout.fields() {
+            if self
+                .keys
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(&field.name))
+            {
+                record.set_field(
+                    field.start,
+                    field.len,
+                    earlier.field(field.start, field.len),
+                );
+            }
+        }
+        record
+    }
+
+    /// Returns a proptest strategy for up to `max` records. Failing cases
+    /// shrink toward fewer records.
+    #[cfg(feature = "proptest")]
+    pub fn strategy(&self, max: usize) -> impl proptest::strategy::Strategy<Value = Vec<Record>> {
+        use proptest::prelude::*;
+
+        let generator = self.clone();
+        (any::<u64>(), 0..=max).prop_map(move |(seed, count)| generator.records(seed, count))
+    }
+
+    /// Draws records from a quickcheck `Gen`: up to its size, from a seed
+    /// it chooses.
+    #[cfg(feature = "quickcheck")]
+    pub fn arbitrary(&self, g: &mut quickcheck::Gen) -> Vec<Record> {
+        use quickcheck::Arbitrary;
+
+        let seed = u64::arbitrary(g);
+        let count = usize::arbitrary(g) % (g.size() + 1);
+        self.records(seed, count)
+    }
+}
+
+/// Generates a value of the field's type that fills its width.
+fn value(field: &LayoutField, rng: &mut Rng) -> String {
+    let len = field.len;
+    match field.field_type {
+        FieldType::Char => {
+            let used = if rng.chance(0.1) {
+                0
+            } else {
+                1 + rng.below(len as u64) as usize
+            };
+            (0..used)
+                .map(|_| (b'A' + rng.below(26) as u8) as char)
+                .collect()
+        }
+        FieldType::Numeric => (0..len)
+            .map(|_| (b'0' + rng.below(10) as u8) as char)
+            .collect(),
+        FieldType::Date => {
+            let date = Date::from_ordinal(1950 + rng.below(100) as u32, 1 + rng.below(365) as u32)
+                .expect("every year has 365 days");
+            let text = match len {
+                5 => date.format(DateFormat::Julian),
+                6 => date.format(DateFormat::Mmddyy),
+                10 => format!("{:04}-{:02}-{:02}", date.year(), date.month(), date.day()),
+                _ => date.format(DateFormat::Yyyymmdd),
+            };
+            text.chars().take(len).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::execute_pipeline;
+
+    fn layout() -> RecordLayout {
+        "LAYOUT NAME 0,8; DEPT 8,3; SALARY 12,6 NUMERIC; HIRED 20,8 DATE; ISO 30,10 DATE; JUL 42,5 DATE"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_records_conform_to_layout() {
+        let records = RecordGen::new(&layout()).records(7, 200);
+        assert_eq!(records, RecordGen::new(&layout()).records(7, 200));
+        assert_ne!(records, RecordGen::new(&layout()).records(8, 200));
+        for record in &records {
+            assert!(
+                record
+                    .field(0, 8)
+                    .trim_end()
+                    .bytes()
+                    .all(|b| b.is_ascii_uppercase())
+            );
+            assert!(record.field(12, 6).bytes().all(|b| b.is_ascii_digit()));
+            assert!(Date::parse(record.field(20, 8), DateFormat::Yyyymmdd).is_ok());
+            assert_eq!(&record.field(30, 10)[4..5], "-");
+            assert!(Date::parse(record.field(42, 5), DateFormat::Julian).is_ok());
+            assert_eq!(record.field(8 + 3, 1), " ");
+        }
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let distinct = |records: &[Record], start, len| {
+            let mut values: Vec<&str> = records.iter().map(|r| r.field(start, len)).collect();
+            values.sort();
+            values.dedup();
+            values.len()
+        };
+        let layout = layout();
+        let none = RecordGen::new(&layout).records(1, 100);
+        assert_eq!(distinct(&none, 0, 80), 100);
+
+        let whole = RecordGen::new(&layout).duplicates(0.5).records(1, 100);
+        assert!(distinct(&whole, 0, 80) < 75);
+
+        let keys = RecordGen::new(&layout)
+            .duplicates(0.9)
+            .on(&["name"])
+            .records(1, 100);
+        assert!(distinct(&keys, 0, 8) < 30);
+        assert!(distinct(&keys, 0, 80) > 90);
+    }
+
+    #[test]
+    fn test_sort_is_permutation() {
+        let generator = RecordGen::new(&layout()).duplicates(0.3).on(&["DEPT"]);
+        for seed in 0..20 {
+            let records = generator.records(seed, 50);
+            let input: Vec<String> = records.iter().map(|r| r.as_str().to_string()).collect();
+            let (output, _, _) =
+                execute_pipeline(&input.join("\n"), "PIPE CONSOLE | SORT 8,3 | CONSOLE").unwrap();
+            let mut sorted: Vec<&str> = output.lines().collect();
+            let mut expected: Vec<&str> = input.iter().map(|r| r.trim_end()).collect();
+            assert!(sorted.is_sorted_by_key(|r| r.get(8..11).unwrap_or_default().to_string()));
+            sorted.sort();
+            expected.sort();
+            assert_eq!(sorted, expected);
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn test_strategy(records in RecordGen::new(&layout()).strategy(20)) {
+            proptest::prop_assert!(records.len() <= 20);
+            proptest::prop_assert!(records.iter().all(|r| r.lrecl() == 80));
+        }
+    }
+
+    #[cfg(feature = "quickcheck")]
+    #[test]
+    fn test_arbitrary() {
+        let mut g = quickcheck::Gen::new(10);
+        let records = RecordGen::new(&layout()).arbitrary(&mut g);
+        assert!(records.len() <= 10);
+    }
+}
@@ -0,0 +1,243 @@
+//! ICETOOL-style reports over a dataset.
+//!
+//! Before running a job, mainframe users check its input with ICETOOL:
+//! how often each value occurs, the range and total of an amount, which
+//! records hold bad numbers. These operators make the same passes over a
+//! set of records:
+//!
+//! | Operator | ICETOOL | Reports |
+//! |----------|---------|---------|
+//! | [`occur`] | `OCCUR` | each distinct value of a field and how many records hold it |
+//! | [`unique`] | `UNIQUE` | the number of distinct values of a field |
+//! | [`stats`] | `STATS` | the count, minimum, maximum, average, and total of a numeric field |
+//! | [`verify`] | `VERIFY` | the records whose numeric field is not a number |
+//!
+//! Numeric fields are read as by `SUM`: signed whole numbers, or zoned
+//! decimal (see [`SumField`]). A blank field is zero. `pipe-tool` runs the
+//! operators on a file.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Record, SumField};
+//! use pipelines_rs::toolkit::{occur, stats, verify};
+//!
+//! let records = vec![
+//!     Record::from_str("SMITH   JOHN      SALES     00050000"),
+//!     Record::from_str("JONES   MARY      ENGINEER  00075000"),
+//!     Record::from_str("DOE     JANE      SALES     0006O000"),
+//! ];
+//! let departments = occur(&records, 18, 10);
+//! assert_eq!(departments[1].value, "SALES");
+//! assert_eq!(departments[1].count, 2);
+//!
+//! let salary = SumField::new(28, 8);
+//! assert_eq!(stats(&records, salary).total, 125000);
+//! assert_eq!(verify(&records, salary)[0].record, 3);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::{Record, SumField};
+
+/// A distinct value of a field and the number of records holding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    /// The field's value, without trailing blanks.
+    pub value: String,
+    /// Records with this value.
+    pub count: usize,
+}
+
+/// Counts the records holding each value of the field at `start, len`,
+/// in order of value (ICETOOL `OCCUR`).
+pub fn occur(records: &[Record], start: usize, len: usize) -> Vec<Occurrence> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for record in records {
+        *counts
+            .entry(record.field(start, len).trim_end())
+            .or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(value, count)| Occurrence {
+            value: value.to_string(),
+            count,
+        })
+        .collect()
+}
+
+/// Counts the distinct values of the field at `start, len` (ICETOOL
+/// `UNIQUE`).
+pub fn unique(records: &[Record], start: usize, len: usize) -> usize {
+    records
+        .iter()
+        .map(|record| record.field(start, len).trim_end())
+        .collect::<BTreeSet<_>>()
+        .len()
+}
+
+/// Statistics of a numeric field (ICETOOL `STATS`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Records whose field is a number.
+    pub count: usize,
+    /// Records whose field is not a number, left out of the statistics.
+    pub invalid: usize,
+    /// The smallest value, if any record has one.
+    pub min: Option<i64>,
+    /// The largest value, if any record has one.
+    pub max: Option<i64>,
+    /// The sum of the values.
+    pub total: i128,
+}
+
+impl Stats {
+    /// The average value, truncated toward zero as ICETOOL does, if any
+    /// record has one.
+    pub fn average(&self) -> Option<i128> {
+        (self.count > 0).then(|| self.total / self.count as i128)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: Option<i128>| value.map_or("-".to_string(), |v| v.to_string());
+        write!(
+            f,
+            "COUNT {} MIN {} MAX {} AVG {} TOTAL {}",
+            self.count,
+            show(self.min.map(i128::from)),
+            show(self.max.map(i128::from)),
+            show(self.average()),
+            self.total
+        )?;
+        if self.invalid > 0 {
+            write!(f, " INVALID {}", self.invalid)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the statistics of a numeric field (ICETOOL `STATS`).
+pub fn stats(records: &[Record], field: SumField) -> Stats {
+    let mut stats = Stats::default();
+    for record in records {
+        match field.read(record) {
+            Some(value) => {
+                stats.count += 1;
+                stats.min = Some(stats.min.map_or(value, |min| min.min(value)));
+                stats.max = Some(stats.max.map_or(value, |max| max.max(value)));
+                stats.total += i128::from(value);
+            }
+            None => stats.invalid += 1,
+        }
+    }
+    stats
+}
+
+/// A record whose numeric field is not a number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invalid {
+    /// Record number, counting from 1.
+    pub record: usize,
+    /// The field's contents.
+    pub value: String,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record {}: '{}'", self.record, self.value)
+    }
+}
+
+/// Finds the records whose numeric field is not a number (ICETOOL
+/// `VERIFY`).
+pub fn verify(records: &[Record], field: SumField) -> Vec<Invalid> {
+    records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| field.read(record).is_none())
+        .map(|(index, record)| Invalid {
+            record: index + 1,
+            value: record.field(field.start, field.len).to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        [
+            "SMITH   JOHN      SALES     00050000",
+            "JONES   MARY      ENGINEER  00075000",
+            "DOE     JANE      SALES     0006000}",
+            "BROWN   BOB       SALES     0004X000",
+            "WHITE   ANN       MARKETING         ",
+        ]
+        .into_iter()
+        .map(Record::from_str)
+        .collect()
+    }
+
+    #[test]
+    fn test_occur_and_unique() {
+        let records = records();
+        let counts: Vec<(String, usize)> = occur(&records, 18, 10)
+            .into_iter()
+            .map(|o| (o.value, o.count))
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("ENGINEER".to_string(), 1),
+                ("MARKETING".to_string(), 1),
+                ("SALES".to_string(), 3)
+            ]
+        );
+        assert_eq!(unique(&records, 18, 10), 3);
+        assert_eq!(unique(&records, 0, 8), 5);
+        assert!(occur(&[], 0, 8).is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let records = records();
+        let signed = stats(&records, SumField::new(28, 8));
+        assert_eq!(signed.count, 3);
+        assert_eq!(signed.invalid, 2);
+        assert_eq!((signed.min, signed.max), (Some(0), Some(75000)));
+        assert_eq!(signed.total, 125000);
+        assert_eq!(signed.average(), Some(41666));
+
+        let zoned = stats(&records, SumField::new(28, 8).zoned());
+        // `}` is an overpunched minus zero: -60000
+        assert_eq!(zoned.total, 65000);
+        assert_eq!(
+            zoned.to_string(),
+            "COUNT 4 MIN -60000 MAX 75000 AVG 16250 TOTAL 65000 INVALID 1"
+        );
+        assert_eq!(
+            Stats::default().to_string(),
+            "COUNT 0 MIN - MAX - AVG - TOTAL 0"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let records = records();
+        let invalid = verify(&records, SumField::new(28, 8).zoned());
+        assert_eq!(
+            invalid,
+            [Invalid {
+                record: 4,
+                value: "0004X000".to_string()
+            }]
+        );
+        assert_eq!(invalid[0].to_string(), "record 4: '0004X000'");
+        assert_eq!(verify(&records, SumField::new(28, 8)).len(), 2);
+    }
+}
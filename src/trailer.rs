@@ -0,0 +1,189 @@
+//! Header and trailer records.
+//!
+//! Interchange files (bank transfers, clearing files) usually start with
+//! header records and end with a trailer record carrying control totals:
+//! the number of detail records and a hash total of an amount field. A
+//! [`Trailer`] describes those totals so a file can be checked before its
+//! detail records are used.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::{Pipeline, Record, SumField, Trailer};
+//!
+//! let records = vec![
+//!     Record::from_str("HDR 20250101"),
+//!     Record::from_str("DTL 00001500"),
+//!     Record::from_str("DTL 00002500"),
+//!     Record::from_str("TRL 000002 00004000"),
+//! ];
+//!
+//! let details: Vec<_> = Pipeline::new(records.into_iter())
+//!     .skip_header(1)
+//!     .with_trailer_validation(
+//!         Trailer::new()
+//!             .identified_by(0, "TRL")
+//!             .count(4, 6)
+//!             .hash_total(SumField::new(4, 8), 11, 8),
+//!     )
+//!     .unwrap()
+//!     .collect();
+//!
+//! assert_eq!(details.len(), 2);
+//! ```
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::stage::SumField;
+
+/// Control totals carried by a trailer record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trailer {
+    identifier: Option<(usize, String)>,
+    count_at: Option<(usize, usize)>,
+    hash_totals: Vec<(SumField, (usize, usize))>,
+}
+
+impl Trailer {
+    /// Creates a trailer check with no totals; it only removes the last
+    /// record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the trailer to hold `text` at column `pos`, e.g. a `TRL`
+    /// record type.
+    pub fn identified_by(mut self, pos: usize, text: &str) -> Self {
+        self.identifier = Some((pos, text.to_string()));
+        self
+    }
+
+    /// Checks that the trailer's `start, len` field holds the number of
+    /// detail records.
+    pub fn count(mut self, start: usize, len: usize) -> Self {
+        self.count_at = Some((start, len));
+        self
+    }
+
+    /// Checks that the trailer's `start, len` field holds the total of
+    /// `field` over the detail records. Blank detail fields count as zero.
+    pub fn hash_total(mut self, field: SumField, start: usize, len: usize) -> Self {
+        self.hash_totals.push((field, (start, len)));
+        self
+    }
+
+    /// Removes the trailer (the last record) from `records` and checks its
+    /// totals against the rest, returning the trailer.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`PipelineError::Stage`] if there is no trailer, it is
+    /// not identified as one, a field is not a number, or a total does not
+    /// match.
+    pub fn validate(&self, records: &mut Vec<Record>) -> Result<Record> {
+        let trailer = records
+            .pop()
+            .ok_or_else(|| stage_error("missing trailer record"))?;
+
+        if let Some((pos, text)) = &self.identifier
+            && trailer.field(*pos, text.len()) != text
+        {
+            return Err(stage_error(&format!(
+                "last record is not a trailer (expected '{text}' at column {pos})"
+            )));
+        }
+
+        if let Some((start, len)) = self.count_at {
+            let expected = trailer_number(&trailer, start, len, "count")?;
+            if expected != records.len() as i128 {
+                return Err(stage_error(&format!(
+                    "trailer count {expected} does not match {} detail records",
+                    records.len()
+                )));
+            }
+        }
+
+        for (field, (start, len)) in &self.hash_totals {
+            let expected = trailer_number(&trailer, *start, *len, "hash total")?;
+            let mut actual: i128 = 0;
+            for (i, record) in records.iter().enumerate() {
+                let value = field.read(record).ok_or_else(|| {
+                    stage_error(&format!(
+                        "detail record {} field {},{} is not a number",
+                        i + 1,
+                        field.start,
+                        field.len
+                    ))
+                })?;
+                actual += i128::from(value);
+            }
+            if expected != actual {
+                return Err(stage_error(&format!(
+                    "trailer hash total {expected} does not match {actual} for field {},{}",
+                    field.start, field.len
+                )));
+            }
+        }
+
+        Ok(trailer)
+    }
+}
+
+/// Reads a whole-number trailer field.
+fn trailer_number(trailer: &Record, start: usize, len: usize, what: &str) -> Result<i128> {
+    SumField::new(start, len)
+        .read(trailer)
+        .map(i128::from)
+        .ok_or_else(|| {
+            stage_error(&format!(
+                "trailer {what} '{}' is not a number",
+                trailer.field(start, len).trim()
+            ))
+        })
+}
+
+fn stage_error(message: &str) -> PipelineError {
+    PipelineError::Stage(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(lines: &[&str]) -> Vec<Record> {
+        lines.iter().map(|s| Record::from_str(s)).collect()
+    }
+
+    fn trailer() -> Trailer {
+        Trailer::new()
+            .identified_by(0, "TRL")
+            .count(4, 3)
+            .hash_total(SumField::new(4, 5), 8, 6)
+    }
+
+    #[test]
+    fn test_valid_trailer() {
+        let mut file = records(&["DTL 00100", "DTL -0040", "TRL 002 000060"]);
+        let found = trailer().validate(&mut file).unwrap();
+        assert_eq!(found.field(0, 3), "TRL");
+        assert_eq!(file.len(), 2);
+    }
+
+    #[test]
+    fn test_trailer_mismatches() {
+        let error = |lines: &[&str]| {
+            trailer()
+                .validate(&mut records(lines))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(error(&[]).contains("missing trailer"));
+        assert!(error(&["DTL 00100"]).contains("not a trailer"));
+        assert!(error(&["DTL 00100", "TRL 002 000100"]).contains("count 2 does not match 1"));
+        assert!(
+            error(&["DTL 00100", "TRL 001 000099"]).contains("hash total 99 does not match 100")
+        );
+        assert!(error(&["DTL 0010X", "TRL 001 000100"]).contains("detail record 1"));
+        assert!(error(&["DTL 00100", "TRL ABC 000100"]).contains("count 'ABC'"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Byte translation.
+//!
+//! `XLATE` maps each byte of a record, or of a column range, through a
+//! 256-entry translate table, like CMS Pipelines `xlate`. The table starts
+//! as UPPER, LOWER, the identity, or a 256-byte table read from a file,
+//! and single entries can then be changed with from/to pairs.
+//!
+//! Records hold ASCII only, so only the first 128 entries are ever looked
+//! up, and an entry mapping to a byte above 127 writes `?`.
+//!
+//! # Example
+//!
+//! ```
+//! use pipelines_rs::xlate::{Xlate, XlateTable};
+//! use pipelines_rs::{Record, Stage};
+//!
+//! // Uppercase columns 0-4 and turn '-' into '/' there
+//! let table = XlateTable::upper().map(b'-', b'/');
+//! let mut stage = Xlate::new(table).within(0, 5);
+//!
+//! let out = stage.process(Record::from_str("ab-cd ef-gh")).unwrap();
+//! assert_eq!(out.as_str().trim_end(), "AB/CD ef-gh");
+//! ```
+
+use std::path::Path;
+
+use crate::Record;
+use crate::error::{PipelineError, Result};
+use crate::stage::Stage;
+
+/// Size of a translate table: one entry per byte value.
+pub const TABLE_SIZE: usize = 256;
+
+/// A translate table: entry `b` is the byte written for byte `b`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XlateTable([u8; TABLE_SIZE]);
+
+impl XlateTable {
+    /// Creates a table that maps every byte to itself.
+    pub fn identity() -> Self {
+        let mut table = [0; TABLE_SIZE];
+        for (byte, entry) in table.iter_mut().enumerate() {
+            *entry = byte as u8;
+        }
+        Self(table)
+    }
+
+    /// Creates a table mapping `a`-`z` to `A`-`Z`.
+    pub fn upper() -> Self {
+        let mut table = Self::identity();
+        table.0.iter_mut().for_each(|b| *b = b.to_ascii_uppercase());
+        table
+    }
+
+    /// Creates a table mapping `A`-`Z` to `a`-`z`.
+    pub fn lower() -> Self {
+        let mut table = Self::identity();
+        table.0.iter_mut().for_each(|b| *b = b.to_ascii_lowercase());
+        table
+    }
+
+    /// Creates a table from exactly 256 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let table = bytes.try_into().map_err(|_| {
+            PipelineError::Xlate(format!(
+                "a translate table has {TABLE_SIZE} bytes, got {}",
+                bytes.len()
+            ))
+        })?;
+        Ok(Self(table))
+    }
+
+    /// Reads a table from a file holding exactly 256 bytes.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|e| PipelineError::Xlate(format!("{}: {e}", path.display())))?;
+        Self::from_bytes(&bytes).map_err(|_| {
+            PipelineError::Xlate(format!(
+                "{} holds {} bytes, not {TABLE_SIZE}",
+                path.display(),
+                bytes.len()
+            ))
+        })
+    }
+
+    /// Returns the table with `from` mapped to `to`.
+    pub fn map(mut self, from: u8, to: u8) -> Self {
+        self.0[from as usize] = to;
+        self
+    }
+
+    /// Returns the byte written for `byte`.
+    pub fn translate(&self, byte: u8) -> u8 {
+        self.0[byte as usize]
+    }
+}
+
+impl Default for XlateTable {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Where an `XLATE` table starts from.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TableSource {
+    /// Every byte maps to itself.
+    Identity,
+    /// `a`-`z` map to `A`-`Z`.
+    #[default]
+    Upper,
+    /// `A`-`Z` map to `a`-`z`.
+    Lower,
+    /// A 256-byte table read from a file.
+    File(String),
+}
+
+/// An `XLATE` specification.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct XlateSpec {
+    /// Columns translated, as (pos, len); the whole record if `None`.
+    pub field: Option<(usize, usize)>,
+    /// Table the pairs are applied to.
+    pub source: TableSource,
+    /// Entries changed, as (from, to) bytes, applied in order.
+    pub pairs: Vec<(u8, u8)>,
+}
+
+impl XlateSpec {
+    /// Builds the table, reading it from its file if it has one.
+    pub fn table(&self) -> Result<XlateTable> {
+        let table = match &self.source {
+            TableSource::Identity => XlateTable::identity(),
+            TableSource::Upper => XlateTable::upper(),
+            TableSource::Lower => XlateTable::lower(),
+            TableSource::File(path) => XlateTable::from_file(path)?,
+        };
+        Ok(self
+            .pairs
+            .iter()
+            .fold(table, |table, &(from, to)| table.map(from, to)))
+    }
+
+    /// Creates the stage this spec describes.
+    pub fn stage(&self) -> Result<Xlate> {
+        let stage = Xlate::new(self.table()?);
+        Ok(match self.field {
+            Some((pos, len)) => stage.within(pos, len),
+            None => stage,
+        })
+    }
+}
+
+/// Xlate stage - translates each byte of a record, or of a column range,
+/// through an [`XlateTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xlate {
+    table: XlateTable,
+    field: Option<(usize, usize)>,
+}
+
+impl Xlate {
+    /// Creates a stage translating whole records through `table`.
+    pub fn new(table: XlateTable) -> Self {
+        Self { table, field: None }
+    }
+
+    /// Translates only the `len` columns from `pos`.
+    pub fn within(mut self, pos: usize, len: usize) -> Self {
+        self.field = Some((pos, len));
+        self
+    }
+
+    /// Processes one record, returning the record it passes on, if any.
+    pub fn process(&mut self, record: Record) -> Option<Record> {
+        let lrecl = record.lrecl();
+        let (pos, len) = self.field.unwrap_or((0, lrecl));
+        let end = pos.saturating_add(len).min(lrecl);
+        let mut bytes = record.as_bytes().to_vec();
+        for byte in bytes.iter_mut().take(end).skip(pos) {
+            *byte = self.table.translate(*byte);
+        }
+        Some(Record::from_bytes_lrecl(&bytes, lrecl))
+    }
+}
+
+impl Stage for Xlate {
+    fn process_into(&mut self, record: Record, output: &mut Vec<Record>) {
+        output.extend(self.process(record));
+    }
+}
+
+/// Checks that the table of every `XLATE` command loads.
+pub fn validate(commands: &[crate::Command]) -> std::result::Result<(), String> {
+    for cmd in commands {
+        if let crate::Command::Xlate { spec } = cmd {
+            spec.table().map_err(|e| format!("{}: {e}", cmd.name()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables() {
+        assert_eq!(XlateTable::identity().translate(b'a'), b'a');
+        assert_eq!(XlateTable::upper().translate(b'a'), b'A');
+        assert_eq!(XlateTable::upper().translate(b'1'), b'1');
+        assert_eq!(XlateTable::lower().translate(b'Z'), b'z');
+        assert_eq!(XlateTable::identity().map(b'a', b'b').translate(b'a'), b'b');
+        assert_eq!(XlateTable::default(), XlateTable::identity());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let mut bytes: Vec<u8> = (0..=255).collect();
+        bytes.swap(b'0' as usize, b'1' as usize);
+        let table = XlateTable::from_bytes(&bytes).unwrap();
+        assert_eq!(table.translate(b'0'), b'1');
+        assert_eq!(table.translate(b'1'), b'0');
+
+        let err = XlateTable::from_bytes(&bytes[..10]).unwrap_err();
+        assert!(err.to_string().contains("got 10"));
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = std::env::temp_dir().join(format!("xlate-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rot.tbl");
+        let bytes: Vec<u8> = (0..=255u8)
+            .map(|b| match b {
+                b'A'..=b'Y' => b + 1,
+                b'Z' => b'A',
+                _ => b,
+            })
+            .collect();
+        std::fs::write(&path, &bytes).unwrap();
+
+        let spec = XlateSpec {
+            field: Some((0, 3)),
+            source: TableSource::File(path.display().to_string()),
+            pairs: vec![(b' ', b'_')],
+        };
+        let out = spec
+            .stage()
+            .unwrap()
+            .process(Record::from_str("HAL 9000"))
+            .unwrap();
+        assert_eq!(out.as_str().trim_end(), "IBM 9000");
+
+        std::fs::write(&path, b"short").unwrap();
+        assert!(
+            spec.table()
+                .unwrap_err()
+                .to_string()
+                .contains("holds 5 bytes")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(spec.table().is_err());
+    }
+
+    #[test]
+    fn test_xlate_stage() {
+        let mut stage = Xlate::new(XlateTable::lower());
+        let out = stage.process(Record::from_str("ABC def")).unwrap();
+        assert_eq!(out.as_str().trim_end(), "abc def");
+
+        // A range past the end stops at the record
+        let mut stage = Xlate::new(XlateTable::identity().map(b' ', b'.')).within(76, 10);
+        let out = stage.process(Record::from_str("X")).unwrap();
+        assert_eq!(&out.as_str()[74..], "  ....");
+
+        // Bytes above 127 cannot be stored
+        let mut stage = Xlate::new(XlateTable::identity().map(b'e', 0xE9));
+        let out = stage.process(Record::from_str("caf e")).unwrap();
+        assert_eq!(out.as_str().trim_end(), "caf ?");
+    }
+}
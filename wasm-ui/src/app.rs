@@ -10,7 +10,9 @@ use yew::prelude::*;
 
 use crate::components::{InputPanel, OutputPanel, PipelinePanel};
 use crate::debugger::{DebuggerPanel, DebuggerState};
-use crate::dsl::{execute_pipeline, execute_pipeline_debug, parse_pipeline_lines};
+use crate::dsl::{
+    execute_pipeline, execute_pipeline_debug, explain_pipeline, parse_pipeline_lines,
+};
 
 /// Render CSS-animated countdown with cycling dots.
 fn countdown_html(countdown: u32, prefix: &str, suffix: &str) -> Html {
@@ -178,10 +180,10 @@ const TUTORIALS: &[TutorialStep] = &[
     TutorialStep {
         name: "DUPLICATE",
         description: "DUPLICATE outputs each record multiple times.\n\n\
-            Syntax: DUPLICATE n\n\n\
-            Each input record is output n times.\n\
-            Useful for testing or data generation.",
-        example_pipeline: "# Duplicate: triple each record\nPIPE CONSOLE\n| TAKE 2\n| DUPLICATE 3\n| CONSOLE\n?",
+            Syntax: DUPLICATE [n]\n\n\
+            Each input record is output, followed by n more copies\n\
+            (1 if n is omitted). Useful for testing or data generation.",
+        example_pipeline: "# Duplicate: triple each record\nPIPE CONSOLE\n| TAKE 2\n| DUPLICATE 2\n| CONSOLE\n?",
     },
 ];
 
@@ -309,26 +311,26 @@ pub fn app() -> Html {
         Callback::from(move |e: web_sys::Event| {
             let state = state.clone();
             let input: HtmlInputElement = e.target_unchecked_into();
-            if let Some(files) = input.files() {
-                if let Some(file) = files.get(0) {
-                    let reader = web_sys::FileReader::new().unwrap();
-                    let reader_clone = reader.clone();
-
-                    let onload = Closure::wrap(Box::new(move |_: web_sys::Event| {
-                        if let Ok(result) = reader_clone.result() {
-                            if let Some(text) = result.as_string() {
-                                let mut new_state = (*state).clone();
-                                new_state.pipeline_text = text;
-                                state.set(new_state);
-                            }
-                        }
-                    }) as Box<dyn FnMut(_)>);
+            if let Some(files) = input.files()
+                && let Some(file) = files.get(0)
+            {
+                let reader = web_sys::FileReader::new().unwrap();
+                let reader_clone = reader.clone();
+
+                let onload = Closure::wrap(Box::new(move |_: web_sys::Event| {
+                    if let Ok(result) = reader_clone.result()
+                        && let Some(text) = result.as_string()
+                    {
+                        let mut new_state = (*state).clone();
+                        new_state.pipeline_text = text;
+                        state.set(new_state);
+                    }
+                }) as Box<dyn FnMut(_)>);
 
-                    reader.set_onload(Some(onload.as_ref().unchecked_ref()));
-                    onload.forget();
+                reader.set_onload(Some(onload.as_ref().unchecked_ref()));
+                onload.forget();
 
-                    let _ = reader.read_as_text(&file);
-                }
+                let _ = reader.read_as_text(&file);
             }
             // Clear the input so the same file can be loaded again
             input.set_value("");
@@ -405,10 +407,10 @@ pub fn app() -> Html {
         let state = state.clone();
         Callback::from(move |_: MouseEvent| {
             let mut new_state = (*state).clone();
-            if let Some(idx) = new_state.tutorial_step {
-                if let Some(tutorial) = TUTORIALS.get(idx) {
-                    new_state.pipeline_text = tutorial.example_pipeline.to_string();
-                }
+            if let Some(idx) = new_state.tutorial_step
+                && let Some(tutorial) = TUTORIALS.get(idx)
+            {
+                new_state.pipeline_text = tutorial.example_pipeline.to_string();
             }
             new_state.tutorial_phase = TutorialPhase::ShowingRunTooltip;
             if new_state.auto_mode {
@@ -470,6 +472,27 @@ pub fn app() -> Html {
         })
     };
 
+    // Plan button: explain the pipeline in the output panel
+    let on_plan = {
+        let state = state.clone();
+        Callback::from(move |_: ()| {
+            let mut new_state = (*state).clone();
+            match explain_pipeline(&new_state.pipeline_text) {
+                Ok((explanation, warnings)) => {
+                    new_state.output_text = explanation;
+                    new_state.error = None;
+                    new_state.stats = format!("Plan: {} warnings", warnings);
+                }
+                Err(e) => {
+                    new_state.output_text.clear();
+                    new_state.error = Some(e);
+                    new_state.stats.clear();
+                }
+            }
+            state.set(new_state);
+        })
+    };
+
     // Clear output button
     let on_clear = {
         let state = state.clone();
@@ -638,11 +661,11 @@ pub fn app() -> Html {
                             match current_phase {
                                 TutorialPhase::ShowingDialog => {
                                     // Load example and show Run tooltip
-                                    if let Some(idx) = tutorial_step {
-                                        if let Some(tutorial) = TUTORIALS.get(idx) {
-                                            new_state.pipeline_text =
-                                                tutorial.example_pipeline.to_string();
-                                        }
+                                    if let Some(idx) = tutorial_step
+                                        && let Some(tutorial) = TUTORIALS.get(idx)
+                                    {
+                                        new_state.pipeline_text =
+                                            tutorial.example_pipeline.to_string();
                                     }
                                     new_state.tutorial_phase = TutorialPhase::ShowingRunTooltip;
                                     new_state.countdown = tutorial_delay;
@@ -775,6 +798,7 @@ pub fn app() -> Html {
                                 on_run={on_run.clone()}
                                 on_load={on_load}
                                 on_save={on_save}
+                                on_plan={on_plan}
                                 show_run_tooltip={state.tutorial_phase == TutorialPhase::ShowingRunTooltip}
                                 on_tooltip_dismiss={on_tutorial_cancel.clone()}
                                 auto_mode={state.auto_mode}